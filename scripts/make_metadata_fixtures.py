@@ -0,0 +1,224 @@
+"""Write the tiny tagged audio files used by the Rust metadata tests.
+
+The audio payloads are silent, and as long as their headers claim so they decode to their
+tagged duration.
+"""
+import struct
+import zlib
+from pathlib import Path
+
+OUT = Path(__file__).resolve().parent.parent / "tests" / "fixtures"
+ARTIST = "Fixture Artist"
+ALBUM = "Fixture Album"
+# 1x1 transparent PNG.
+ARTWORK = bytes.fromhex(
+    "89504e470d0a1a0a0000000d4948445200000001000000010806000000"
+    "1f15c4890000000d49444154789c6360000002000001e221bc330000"
+    "000049454e44ae426082"
+)
+
+
+def synchsafe(n):
+    return bytes([(n >> 21) & 0x7F, (n >> 14) & 0x7F, (n >> 7) & 0x7F, n & 0x7F])
+
+
+def id3_frame(frame_id, body):
+    return frame_id.encode() + struct.pack(">I", len(body)) + b"\0\0" + body
+
+
+def id3_text(frame_id, text):
+    return id3_frame(frame_id, b"\x03" + text.encode())
+
+
+def mp3(title):
+    frames = id3_text("TIT2", title) + id3_text("TPE1", ARTIST) + id3_text("TALB", ALBUM)
+    frames += id3_text("TBPM", "124") + id3_text("TKEY", "Am")
+    frames += id3_frame("APIC", b"\x00image/png\x00\x03\x00" + ARTWORK)
+    tag = b"ID3\x04\x00\x00" + synchsafe(len(frames)) + frames
+    # MPEG-1 layer III, 128 kbit/s, 44.1 kHz, mono: 417-byte frames of 1152 samples.
+    header = bytes([0xFF, 0xFB, 0x90, 0xC0])
+    count = 40
+    xing = header + bytes(17) + b"Xing" + struct.pack(">II", 1, count)
+    audio = xing.ljust(417, b"\0") + (header + bytes(413)) * (count - 1)
+    return tag + audio
+
+
+def flac_block(kind, body, last=False):
+    return bytes([kind | (0x80 if last else 0)]) + struct.pack(">I", len(body))[1:] + body
+
+
+def vorbis_comments(fields):
+    vendor = b"deejay fixtures"
+    body = struct.pack("<I", len(vendor)) + vendor + struct.pack("<I", len(fields))
+    for field in fields:
+        body += struct.pack("<I", len(field.encode())) + field.encode()
+    return body
+
+
+def crc8(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x07) & 0xFF if crc & 0x80 else (crc << 1) & 0xFF
+    return crc
+
+
+def crc16(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte << 8
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x8005) & 0xFFFF if crc & 0x8000 else (crc << 1) & 0xFFFF
+    return crc
+
+
+def flac_frame(number, block=4096):
+    # Fixed 4096-sample blocks, rate and depth from STREAMINFO, two constant-zero subframes.
+    # A shorter last block carries its size, less one, in 16 bits after the frame number.
+    if block == 4096:
+        header = bytes([0xFF, 0xF8, 0xC0, 0x10, number])
+    else:
+        header = bytes([0xFF, 0xF8, 0x70, 0x10, number]) + struct.pack(">H", block - 1)
+    header += bytes([crc8(header)])
+    frame = header + bytes(6)
+    return frame + struct.pack(">H", crc16(frame))
+
+
+def flac():
+    rate, channels, bits, samples = 44_100, 2, 16, 88_200
+    packed = (rate << 44) | ((channels - 1) << 41) | ((bits - 1) << 36) | samples
+    streaminfo = struct.pack(">HH", 4096, 4096) + bytes(6) + packed.to_bytes(8, "big") + bytes(16)
+    comments = vorbis_comments(
+        [
+            "TITLE=Flac Title",
+            f"ARTIST={ARTIST}",
+            f"ALBUM={ALBUM}",
+            "BPM=128",
+            "INITIALKEY=Em",
+            "REPLAYGAIN_TRACK_GAIN=-6.50 dB",
+        ]
+    )
+    mime = b"image/png"
+    picture = struct.pack(">II", 3, len(mime)) + mime + struct.pack(">I", 0)
+    picture += struct.pack(">IIIII", 1, 1, 32, 0, len(ARTWORK)) + ARTWORK
+    return (
+        b"fLaC"
+        + flac_block(0, streaminfo)
+        + flac_block(4, comments)
+        + flac_block(6, picture, last=True)
+        + b"".join(
+            flac_frame(number, min(4096, samples - start))
+            for number, start in enumerate(range(0, samples, 4096))
+        )
+    )
+
+
+def ogg_crc(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte << 24
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x04C11DB7) if crc & 0x80000000 else crc << 1
+            crc &= 0xFFFFFFFF
+    return crc
+
+
+def ogg_page(packets, sequence, granule, flags):
+    segments = b""
+    for packet in packets:
+        segments += b"\xff" * (len(packet) // 255) + bytes([len(packet) % 255])
+    header = b"OggS\x00" + bytes([flags]) + struct.pack("<qIII", granule, 0x0D15EA5E, sequence, 0)
+    page = header + bytes([len(segments)]) + segments + b"".join(packets)
+    return page[:22] + struct.pack("<I", ogg_crc(page)) + page[26:]
+
+
+def ogg():
+    ident = b"\x01vorbis" + struct.pack("<IBIiii", 0, 2, 44_100, 0, 128_000, 0) + b"\xb8\x01"
+    comments = b"\x03vorbis" + vorbis_comments(
+        ["TITLE=Ogg Title", f"ARTIST={ARTIST}", f"ALBUM={ALBUM}"]
+    ) + b"\x01"
+    setup = b"\x05vorbis" + bytes(16)
+    return (
+        ogg_page([ident], 0, 0, 0x02)
+        + ogg_page([comments, setup], 1, 0, 0x00)
+        + ogg_page([b"\x00"], 2, 44_100, 0x04)
+    )
+
+
+def atom(kind, body):
+    return struct.pack(">I", 8 + len(body)) + kind + body
+
+
+def full_atom(kind, body, version=0, flags=0):
+    return atom(kind, struct.pack(">I", (version << 24) | flags) + body)
+
+
+def ilst_item(kind, payload, data_type):
+    return atom(kind, atom(b"data", struct.pack(">II", data_type, 0) + payload))
+
+
+def m4a():
+    rate, frames = 44_100, 44_100
+    # One silent AAC-LC stereo frame per 1024 samples, enough of them to fill `frames`.
+    silence = b"\x21\x00\x49\x90\x02\x19\x00\x23\x80"
+    packets = -(-frames // 1024)
+    matrix = struct.pack(">9I", 0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x40000000)
+    mvhd = full_atom(b"mvhd", struct.pack(">IIII", 0, 0, rate, frames) + struct.pack(">IH", 0x10000, 0x100) + bytes(10) + matrix + bytes(24) + struct.pack(">I", 2))
+    tkhd = full_atom(b"tkhd", struct.pack(">IIIII", 0, 0, 1, 0, frames) + bytes(8) + struct.pack(">HHHH", 0, 0, 0x100, 0) + matrix + struct.pack(">II", 0, 0), flags=7)
+    mdhd = full_atom(b"mdhd", struct.pack(">IIII", 0, 0, rate, frames) + struct.pack(">HH", 0x55C4, 0))
+    hdlr = full_atom(b"hdlr", struct.pack(">I", 0) + b"soun" + bytes(12) + b"\0")
+    smhd = full_atom(b"smhd", bytes(4))
+    dref = full_atom(b"dref", struct.pack(">I", 1) + full_atom(b"url ", b"", flags=1))
+    dinf = atom(b"dinf", dref)
+    # AAC-LC, 44.1 kHz, stereo.
+    asc = bytes([0x12, 0x10])
+    decoder_specific = b"\x05" + bytes([len(asc)]) + asc
+    decoder_config = b"\x04" + bytes([13 + len(decoder_specific)]) + b"\x40\x15" + bytes(3) + struct.pack(">II", 128_000, 128_000) + decoder_specific
+    sl_config = b"\x06\x01\x02"
+    es = b"\x03" + bytes([3 + len(decoder_config) + len(sl_config)]) + struct.pack(">HB", 1, 0) + decoder_config + sl_config
+    esds = full_atom(b"esds", es)
+    mp4a = atom(b"mp4a", bytes(6) + struct.pack(">H", 1) + bytes(8) + struct.pack(">HHHHI", 2, 16, 0, 0, rate << 16) + esds)
+    stsd = full_atom(b"stsd", struct.pack(">I", 1) + mp4a)
+    stts = full_atom(b"stts", struct.pack(">III", 1, packets, 1024))
+    stsc = full_atom(b"stsc", struct.pack(">IIII", 1, 1, packets, 1))
+    stsz = full_atom(b"stsz", struct.pack(">II", len(silence), packets))
+    stbl_without_stco = stsd + stts + stsc + stsz
+    minf_tail = smhd + dinf
+    ilst = atom(
+        b"ilst",
+        ilst_item(b"\xa9nam", b"M4a Title", 1)
+        + ilst_item(b"\xa9ART", ARTIST.encode(), 1)
+        + ilst_item(b"\xa9alb", ALBUM.encode(), 1)
+        + ilst_item(b"covr", ARTWORK, 14),
+    )
+    meta = full_atom(b"meta", full_atom(b"hdlr", struct.pack(">I", 0) + b"mdir" + b"appl" + bytes(9)) + ilst)
+    udta = atom(b"udta", meta)
+    ftyp = atom(b"ftyp", b"M4A " + struct.pack(">I", 0) + b"M4A mp42isom")
+
+    def moov(offset):
+        stco = full_atom(b"stco", struct.pack(">II", 1, offset))
+        stbl = atom(b"stbl", stbl_without_stco + stco)
+        minf = atom(b"minf", minf_tail + stbl)
+        mdia = atom(b"mdia", mdhd + hdlr + minf)
+        trak = atom(b"trak", tkhd + mdia)
+        return atom(b"moov", mvhd + trak + udta)
+
+    head = ftyp + moov(0)
+    return ftyp + moov(len(head) + 8) + atom(b"mdat", silence * packets)
+
+
+def main():
+    OUT.mkdir(parents=True, exist_ok=True)
+    (OUT / "tagged.mp3").write_bytes(mp3("Mp3 Title"))
+    (OUT / "tagged.flac").write_bytes(flac())
+    (OUT / "tagged.ogg").write_bytes(ogg())
+    (OUT / "tagged.m4a").write_bytes(m4a())
+    garbled = bytearray(mp3("Garbled"))
+    garbled[6:10] = b"\x7f\x7f\x7f\x7f"
+    garbled[10:30] = b"\xff" * 20
+    (OUT / "Some Artist - Some Title.mp3").write_bytes(bytes(garbled))
+
+
+if __name__ == "__main__":
+    main()
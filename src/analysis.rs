@@ -0,0 +1,60 @@
+pub mod bpm;
+pub mod gain;
+pub mod key;
+pub mod waveform;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Shared flag used to abandon a running analysis.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Analysis running on a worker thread that can be canceled from the caller.
+#[derive(Debug)]
+pub struct AnalysisJob<T> {
+    handle: JoinHandle<Option<T>>,
+    token: CancelToken,
+}
+
+impl<T: Send + 'static> AnalysisJob<T> {
+    /// Run `work` on a new thread. The closure should poll the token and
+    /// return `None` once it has been canceled.
+    pub fn spawn<F>(work: F) -> Self
+    where
+        F: FnOnce(&CancelToken) -> Option<T> + Send + 'static,
+    {
+        let token = CancelToken::new();
+        let worker_token = token.clone();
+        let handle = thread::spawn(move || work(&worker_token));
+        Self { handle, token }
+    }
+
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Wait for the result. Returns `None` if the job was canceled or panicked.
+    pub fn join(self) -> Option<T> {
+        self.handle.join().ok().flatten()
+    }
+}
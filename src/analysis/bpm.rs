@@ -0,0 +1,335 @@
+use std::ops::RangeInclusive;
+use std::thread::{self, JoinHandle};
+
+use serde::{Deserialize, Serialize};
+
+/// Slowest tempo ever reported.
+pub const MIN_BPM: f64 = 60.0;
+/// Fastest tempo ever reported.
+pub const MAX_BPM: f64 = 200.0;
+
+/// Target duration of one onset-envelope hop.
+const HOP_SECONDS: f64 = 0.003;
+
+/// Tuning for tempo estimation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BpmOptions {
+    /// Genre range used to resolve half/double tempo ambiguity.
+    pub preferred: RangeInclusive<f64>,
+}
+
+impl Default for BpmOptions {
+    fn default() -> Self {
+        Self {
+            preferred: 88.0..=176.0,
+        }
+    }
+}
+
+/// Result of tempo analysis over a whole track.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BpmEstimate {
+    pub bpm: f64,
+    /// Strength of the periodicity in [0, 1]; low values mean the tempo is a guess.
+    pub confidence: f32,
+    /// Offset of the first beat from the start of the track.
+    pub first_beat_secs: f64,
+}
+
+/// Estimate the tempo of mono `frames` sampled at `sample_rate`.
+///
+/// Returns `None` for silent or too-short material.
+pub fn estimate_bpm<I>(frames: I, sample_rate: u32, options: &BpmOptions) -> Option<BpmEstimate>
+where
+    I: IntoIterator<Item = f32>,
+{
+    let envelope = OnsetEnvelope::from_frames(frames, sample_rate);
+    let hop_secs = envelope.hop_secs;
+    let lag_of = |bpm: f64| 60.0 / (bpm * hop_secs);
+    let min_lag = lag_of(MAX_BPM).floor() as usize;
+    let max_lag = lag_of(MIN_BPM).ceil() as usize;
+    if envelope.values.len() < max_lag * 4 {
+        return None;
+    }
+
+    let acf = envelope.autocorrelation(max_lag * 2 + 1)?;
+    let score = |lag: f64| {
+        let centre = lag.round() as usize;
+        (centre.saturating_sub(1)..=centre + 1)
+            .filter_map(|l| acf.get(l).copied())
+            .fold(f32::MIN, f32::max)
+    };
+
+    let strongest = (min_lag..=max_lag)
+        .max_by(|a, b| acf[*a].total_cmp(&acf[*b]))
+        .map(|lag| refine_peak(&acf, lag))?;
+
+    let candidates: Vec<(f64, f32)> = [strongest, strongest * 2.0, strongest / 2.0]
+        .into_iter()
+        .filter(|lag| (MIN_BPM..=MAX_BPM).contains(&(60.0 / (lag * hop_secs))))
+        .map(|lag| (lag, score(lag)))
+        .collect();
+    let best_score = candidates.iter().map(|c| c.1).fold(f32::MIN, f32::max);
+    let pick = |preferred_only: bool| {
+        candidates
+            .iter()
+            .filter(|(lag, score)| {
+                !preferred_only
+                    || (options.preferred.contains(&(60.0 / (lag * hop_secs)))
+                        && *score >= best_score * 0.5)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .copied()
+    };
+    let (lag, confidence) = pick(true).or_else(|| pick(false))?;
+    let lag = refine_peak(&acf, lag.round() as usize);
+
+    let (period, first_beat) = envelope.track_beats(lag);
+    let bpm = (60.0 / (period * hop_secs)).clamp(MIN_BPM, MAX_BPM);
+    Some(BpmEstimate {
+        bpm,
+        confidence: confidence.clamp(0.0, 1.0),
+        first_beat_secs: first_beat * hop_secs,
+    })
+}
+
+/// Run [`estimate_bpm`] on a worker thread.
+pub fn spawn_estimate_bpm<I>(
+    frames: I,
+    sample_rate: u32,
+    options: BpmOptions,
+) -> JoinHandle<Option<BpmEstimate>>
+where
+    I: IntoIterator<Item = f32> + Send + 'static,
+{
+    thread::spawn(move || estimate_bpm(frames, sample_rate, &options))
+}
+
+/// Parabolic interpolation of a peak in `values` around `index`.
+fn refine_peak(values: &[f32], index: usize) -> f64 {
+    if index == 0 || index + 1 >= values.len() {
+        return index as f64;
+    }
+    let (a, b, c) = (values[index - 1], values[index], values[index + 1]);
+    let denom = a - 2.0 * b + c;
+    if denom.abs() < f32::EPSILON {
+        return index as f64;
+    }
+    index as f64 + (0.5 * (a - c) / denom).clamp(-0.5, 0.5) as f64
+}
+
+/// Half-wave rectified log-energy flux, one value per hop.
+struct OnsetEnvelope {
+    values: Vec<f32>,
+    hop_secs: f64,
+}
+
+impl OnsetEnvelope {
+    fn from_frames<I: IntoIterator<Item = f32>>(frames: I, sample_rate: u32) -> Self {
+        let hop = ((sample_rate as f64 * HOP_SECONDS).round() as usize).max(1);
+        let mut values = Vec::new();
+        let mut energy = 0.0f32;
+        let mut count = 0;
+        let mut previous = 0.0f32;
+
+        for sample in frames {
+            energy += sample * sample;
+            count += 1;
+            if count == hop {
+                let level = (1.0 + 1_000.0 * energy / hop as f32).ln();
+                values.push((level - previous).max(0.0));
+                previous = level;
+                energy = 0.0;
+                count = 0;
+            }
+        }
+
+        // Light smoothing so a peak split across two hops still correlates.
+        let smoothed = (0..values.len())
+            .map(|i| {
+                let prev = if i > 0 { values[i - 1] } else { 0.0 };
+                let next = values.get(i + 1).copied().unwrap_or(0.0);
+                0.25 * prev + 0.5 * values[i] + 0.25 * next
+            })
+            .collect();
+
+        Self {
+            values: smoothed,
+            hop_secs: hop as f64 / sample_rate as f64,
+        }
+    }
+
+    /// Normalized autocorrelation of the mean-removed envelope for lags `0..max_lag`.
+    fn autocorrelation(&self, max_lag: usize) -> Option<Vec<f32>> {
+        let n = self.values.len();
+        let mean = self.values.iter().sum::<f32>() / n as f32;
+        let centred: Vec<f32> = self.values.iter().map(|v| v - mean).collect();
+        let energy = centred.iter().map(|v| v * v).sum::<f32>() / n as f32;
+        if energy <= f32::EPSILON {
+            return None;
+        }
+
+        Some(
+            (0..max_lag.min(n))
+                .map(|lag| {
+                    let sum: f32 = centred[..n - lag]
+                        .iter()
+                        .zip(&centred[lag..])
+                        .map(|(a, b)| a * b)
+                        .sum();
+                    sum / (n - lag) as f32 / energy
+                })
+                .collect(),
+        )
+    }
+
+    /// Follow beats spaced roughly `period` hops apart and fit a line through
+    /// their onset times, returning the refined period and first-beat offset in hops.
+    fn track_beats(&self, period: f64) -> (f64, f64) {
+        let values = &self.values;
+        let at = |pos: f64| values.get(pos.round() as usize).copied().unwrap_or(0.0);
+
+        let phase = (0..period.ceil() as usize)
+            .map(|offset| {
+                let mut sum = 0.0;
+                let mut pos = offset as f64;
+                while (pos as usize) < values.len() {
+                    sum += at(pos);
+                    pos += period;
+                }
+                (offset, sum)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(0.0, |(offset, _)| offset as f64);
+
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let threshold = mean * 2.0;
+        let window = (period * 0.15).max(1.0);
+
+        let mut beats: Vec<(f64, f64)> = Vec::new();
+        let mut local_period = period;
+        let mut predicted = phase;
+        let mut index = 0.0;
+        while predicted + window < values.len() as f64 {
+            let lo = (predicted - window).max(0.0).round() as usize;
+            let hi = (predicted + window).round() as usize;
+            let peak = (lo..=hi)
+                .filter(|i| *i < values.len())
+                .max_by(|a, b| values[*a].total_cmp(&values[*b]));
+
+            match peak {
+                Some(peak) if values[peak] > threshold => {
+                    let time = refine_peak(values, peak);
+                    beats.push((index, time));
+                    if beats.len() >= 4 {
+                        local_period = fit_line(&beats).0;
+                    }
+                    predicted = time + local_period;
+                }
+                _ => predicted += local_period,
+            }
+            index += 1.0;
+        }
+
+        if beats.len() < 4 {
+            return (period, phase);
+        }
+        let (slope, intercept) = fit_line(&beats);
+        (slope, intercept.rem_euclid(slope))
+    }
+}
+
+/// Least-squares fit of `y = slope * x + intercept`.
+fn fit_line(points: &[(f64, f64)]) -> (f64, f64) {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let (cov, var) = points.iter().fold((0.0, 0.0), |(cov, var), (x, y)| {
+        (
+            cov + (x - mean_x) * (y - mean_y),
+            var + (x - mean_x) * (x - mean_x),
+        )
+    });
+    let slope = cov / var;
+    (slope, mean_y - slope * mean_x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44_100;
+
+    /// Click track whose tempo moves linearly from `start_bpm` to `end_bpm`.
+    fn click_track(start_bpm: f64, end_bpm: f64, seconds: f64) -> Vec<f32> {
+        let len = (seconds * SAMPLE_RATE as f64) as usize;
+        let mut samples = vec![0.0; len];
+        let click_len = (0.005 * SAMPLE_RATE as f64) as usize;
+        let mut t = 0.25;
+        while t < seconds {
+            let start = (t * SAMPLE_RATE as f64) as usize;
+            for i in 0..click_len.min(len.saturating_sub(start)) {
+                let phase = i as f32 / SAMPLE_RATE as f32 * 2_000.0 * std::f32::consts::TAU;
+                samples[start + i] = 0.8 * phase.sin() * (-(i as f32) / 40.0).exp();
+            }
+            let bpm = start_bpm + (end_bpm - start_bpm) * t / seconds;
+            t += 60.0 / bpm;
+        }
+        samples
+    }
+
+    fn noise(seconds: f64) -> Vec<f32> {
+        let mut state = 0x1234_5678u32;
+        (0..(seconds * SAMPLE_RATE as f64) as usize)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+            })
+            .collect()
+    }
+
+    fn assert_bpm(samples: Vec<f32>, options: &BpmOptions, expected: f64) -> BpmEstimate {
+        let estimate = estimate_bpm(samples, SAMPLE_RATE, options).unwrap();
+        assert!(
+            (estimate.bpm - expected).abs() <= 0.1,
+            "expected {expected}, got {estimate:?}"
+        );
+        estimate
+    }
+
+    #[test]
+    fn detects_click_track_tempos() {
+        let options = BpmOptions::default();
+        let estimate = assert_bpm(click_track(100.0, 100.0, 30.0), &options, 100.0);
+        assert!(
+            (estimate.first_beat_secs - 0.25).abs() < 0.01,
+            "{estimate:?}"
+        );
+        assert_bpm(click_track(128.0, 128.0, 30.0), &options, 128.0);
+
+        let drum_and_bass = BpmOptions {
+            preferred: 160.0..=190.0,
+        };
+        assert_bpm(click_track(174.0, 174.0, 30.0), &drum_and_bass, 174.0);
+    }
+
+    #[test]
+    fn follows_slight_tempo_drift() {
+        let handle = spawn_estimate_bpm(
+            click_track(127.8, 128.2, 30.0),
+            SAMPLE_RATE,
+            BpmOptions::default(),
+        );
+        let estimate = handle.join().unwrap().unwrap();
+        assert!((estimate.bpm - 128.0).abs() <= 0.1, "{estimate:?}");
+    }
+
+    #[test]
+    fn noise_has_low_confidence() {
+        let options = BpmOptions::default();
+        let clicks = estimate_bpm(click_track(128.0, 128.0, 20.0), SAMPLE_RATE, &options).unwrap();
+        let noise = estimate_bpm(noise(20.0), SAMPLE_RATE, &options).unwrap();
+        assert!(clicks.confidence > 0.5, "{clicks:?}");
+        assert!(noise.confidence < clicks.confidence * 0.5, "{noise:?}");
+    }
+}
@@ -0,0 +1,225 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+/// Loudness that suggested trims bring tracks to.
+pub const REFERENCE_LUFS: f64 = -18.0;
+/// Largest trim a deck applies automatically, either way.
+pub const MAX_AUTO_TRIM_DB: f32 = 12.0;
+
+/// Gating block length and hop (BS.1770: 400 ms blocks with 75% overlap).
+const BLOCK_SECONDS: f64 = 0.4;
+const HOP_SECONDS: f64 = 0.1;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Transposed direct form II biquad.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// BS.1770 K-weighting (high-shelf plus high-pass) for one channel.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    /// Coefficients derived for `sample_rate`, matching the 48 kHz reference filter.
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        let fs = sample_rate as f64;
+
+        let (f0, gain_db, q) = (
+            1_681.974_450_955_533,
+            3.999_843_853_973_347,
+            0.707_175_236_955_419_6,
+        );
+        let k = (PI * f0 / fs).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        let (f0, q) = (38.135_470_876_024_44, 0.500_327_037_323_877_3);
+        let k = (PI * f0 / fs).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        Self { shelf, high_pass }
+    }
+
+    pub(crate) fn process(&mut self, x: f32) -> f64 {
+        self.high_pass.process(self.shelf.process(x as f64))
+    }
+}
+
+/// Loudness in LUFS of a mean-square K-weighted power summed over channels.
+pub(crate) fn power_to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Integrated loudness over gating-block powers, with the absolute and relative gates.
+pub(crate) fn gated_loudness(blocks: &[f64]) -> Option<f64> {
+    let mean_above = |threshold: f64| {
+        let passed: Vec<f64> = blocks
+            .iter()
+            .copied()
+            .filter(|power| power_to_lufs(*power) > threshold)
+            .collect();
+        (!passed.is_empty()).then(|| passed.iter().sum::<f64>() / passed.len() as f64)
+    };
+    let ungated = mean_above(ABSOLUTE_GATE_LUFS)?;
+    let relative = power_to_lufs(ungated) + RELATIVE_GATE_LU;
+    mean_above(relative.max(ABSOLUTE_GATE_LUFS)).map(power_to_lufs)
+}
+
+/// Measured loudness of a track and the trim that brings it to [`REFERENCE_LUFS`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrackGain {
+    pub integrated_lufs: f32,
+    pub trim_db: f32,
+}
+
+/// Streaming EBU R128 integrated-loudness measurement.
+#[derive(Debug, Clone)]
+pub struct GainAnalyzer {
+    channels: usize,
+    filters: Vec<KWeighting>,
+    hop_frames: usize,
+    /// Sum of squares per 100 ms hop; four hops make one gating block.
+    hops: Vec<f64>,
+    pending: f64,
+    pending_frames: usize,
+}
+
+impl GainAnalyzer {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            channels,
+            filters: vec![KWeighting::new(sample_rate); channels],
+            hop_frames: ((sample_rate as f64 * HOP_SECONDS).round() as usize).max(1),
+            hops: Vec::new(),
+            pending: 0.0,
+            pending_frames: 0,
+        }
+    }
+
+    /// Feed interleaved samples. A trailing partial frame is ignored.
+    pub fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (filter, sample) in self.filters.iter_mut().zip(frame) {
+                let weighted = filter.process(*sample);
+                self.pending += weighted * weighted;
+            }
+            self.pending_frames += 1;
+            if self.pending_frames == self.hop_frames {
+                self.hops.push(self.pending);
+                self.pending = 0.0;
+                self.pending_frames = 0;
+            }
+        }
+    }
+
+    /// Integrated loudness and suggested trim, or `None` for silent or sub-block material.
+    pub fn finish(self) -> Option<TrackGain> {
+        let per_block = (BLOCK_SECONDS / HOP_SECONDS).round() as usize;
+        let block_frames = (self.hop_frames * per_block) as f64;
+        let blocks: Vec<f64> = self
+            .hops
+            .windows(per_block)
+            .map(|hops| hops.iter().sum::<f64>() / block_frames)
+            .collect();
+        let integrated = gated_loudness(&blocks)?;
+        Some(TrackGain {
+            integrated_lufs: integrated as f32,
+            trim_db: (REFERENCE_LUFS - integrated) as f32,
+        })
+    }
+}
+
+/// Measure interleaved `samples` in one call.
+pub fn analyze_gain<I>(samples: I, channels: u16, sample_rate: u32) -> Option<TrackGain>
+where
+    I: IntoIterator<Item = f32>,
+{
+    let mut analyzer = GainAnalyzer::new(channels, sample_rate);
+    let mut chunk = Vec::with_capacity(4_096);
+    for sample in samples {
+        chunk.push(sample);
+        if chunk.len() == chunk.capacity() {
+            analyzer.push(&chunk);
+            chunk.clear();
+        }
+    }
+    analyzer.push(&chunk);
+    analyzer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stereo 1 kHz sine whose peak sits at `dbfs`, which BS.1770 reads as `dbfs` LUFS.
+    fn sine(dbfs: f64, seconds: f64, sample_rate: u32) -> Vec<f32> {
+        let amplitude = 10f64.powf(dbfs / 20.0);
+        let frames = (seconds * sample_rate as f64) as usize;
+        (0..frames)
+            .flat_map(|i| {
+                let s =
+                    (amplitude * (2.0 * PI * 1_000.0 * i as f64 / sample_rate as f64).sin()) as f32;
+                [s, s]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reference_sines_measure_their_level() {
+        for rate in [44_100, 48_000] {
+            let quiet = analyze_gain(sine(-23.0, 20.0, rate), 2, rate).unwrap();
+            let loud = analyze_gain(sine(-13.0, 20.0, rate), 2, rate).unwrap();
+            assert!((quiet.integrated_lufs + 23.0).abs() < 0.1, "{quiet:?}");
+            assert!((loud.integrated_lufs + 13.0).abs() < 0.1, "{loud:?}");
+            assert!((quiet.trim_db - loud.trim_db - 10.0).abs() < 0.1);
+            assert!((quiet.trim_db - 5.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn gates_out_silence_and_quiet_passages() {
+        // Tech 3341 case 3: -36 / -23 / -36 dBFS for 10 / 60 / 10 s reads -23 LUFS.
+        let mut samples = sine(-36.0, 10.0, 48_000);
+        samples.extend(sine(-23.0, 60.0, 48_000));
+        samples.extend(sine(-36.0, 10.0, 48_000));
+        samples.extend(vec![0.0; 48_000 * 20]);
+        let gain = analyze_gain(samples, 2, 48_000).unwrap();
+        assert!((gain.integrated_lufs + 23.0).abs() < 0.1, "{gain:?}");
+
+        assert_eq!(analyze_gain(vec![0.0; 48_000 * 4], 2, 48_000), None);
+    }
+}
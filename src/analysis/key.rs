@@ -0,0 +1,425 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{AnalysisJob, CancelToken};
+
+/// Krumhansl-Kessler probe-tone profile for major keys, starting at the tonic.
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+/// Krumhansl-Kessler probe-tone profile for minor keys, starting at the tonic.
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+const PITCH_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+
+/// Rate the signal is decimated to before pitch analysis.
+const ANALYSIS_RATE: u32 = 11_025;
+const FRAME_LEN: usize = 4_096;
+const HOP_LEN: usize = 2_048;
+/// MIDI note range folded into the chroma profile (C2..B6).
+const LOWEST_NOTE: u8 = 36;
+const HIGHEST_NOTE: u8 = 95;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+/// Musical key as a tonic pitch class (0 = C) and mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Key {
+    pub tonic: u8,
+    pub mode: Mode,
+}
+
+impl Key {
+    pub fn new(tonic: u8, mode: Mode) -> Self {
+        Self {
+            tonic: tonic % 12,
+            mode,
+        }
+    }
+
+    /// All 24 major and minor keys.
+    pub fn all() -> impl Iterator<Item = Key> {
+        [Mode::Major, Mode::Minor]
+            .into_iter()
+            .flat_map(|mode| (0..12).map(move |tonic| Key::new(tonic, mode)))
+    }
+
+    pub fn camelot(&self) -> Camelot {
+        // Walking the circle of fifths moves one step around the wheel; C major is 8B.
+        let major_tonic = match self.mode {
+            Mode::Major => self.tonic,
+            Mode::Minor => (self.tonic + 3) % 12,
+        };
+        let number = (major_tonic as u32 * 7 + 7) % 12 + 1;
+        Camelot {
+            number: number as u8,
+            mode: self.mode,
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let suffix = match self.mode {
+            Mode::Major => "",
+            Mode::Minor => "m",
+        };
+        write!(f, "{}{}", PITCH_NAMES[self.tonic as usize], suffix)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unrecognised key {0:?}")]
+pub struct ParseKeyError(String);
+
+impl FromStr for Key {
+    type Err = ParseKeyError;
+
+    /// Parse a key as tagged by DJ software: "Am", "F#", "Ebmin", "C major" or Camelot "8A".
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = || ParseKeyError(text.to_string());
+        let trimmed = text.trim();
+        if let Some(number) = trimmed
+            .strip_suffix(['A', 'a', 'B', 'b'])
+            .and_then(|number| number.parse::<u8>().ok())
+        {
+            let mode = match trimmed.chars().last() {
+                Some('A' | 'a') => Mode::Minor,
+                _ => Mode::Major,
+            };
+            return Key::all()
+                .find(|key| key.camelot() == Camelot { number, mode })
+                .ok_or_else(error);
+        }
+
+        let mut chars = trimmed.chars();
+        let letter = chars.next().ok_or_else(error)?.to_ascii_uppercase();
+        let natural = PITCH_NAMES
+            .iter()
+            .position(|name| name.len() == 1 && name.starts_with(letter))
+            .ok_or_else(error)? as u8;
+        let mut tonic = natural;
+        match chars.clone().next() {
+            Some('#' | '♯') => tonic += 1,
+            Some('b' | '♭') => tonic += 11,
+            _ => {}
+        }
+        if tonic != natural {
+            chars.next();
+        }
+        let rest = chars.as_str();
+        let mode = match rest.trim().to_ascii_lowercase().as_str() {
+            "" | "maj" | "major" => Mode::Major,
+            "m" | "min" | "minor" => Mode::Minor,
+            _ => return Err(error()),
+        };
+        Ok(Key::new(tonic, mode))
+    }
+}
+
+/// Position on the Camelot wheel: 1-12 plus A (minor) or B (major).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Camelot {
+    pub number: u8,
+    pub mode: Mode,
+}
+
+impl fmt::Display for Camelot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letter = match self.mode {
+            Mode::Major => 'B',
+            Mode::Minor => 'A',
+        };
+        write!(f, "{}{}", self.number, letter)
+    }
+}
+
+/// How well a transition from one key into another will sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compatibility {
+    Same,
+    /// One step around the wheel, or the relative major/minor.
+    Adjacent,
+    /// Same mode, moving up a semitone or a whole tone.
+    EnergyBoost,
+    Clash,
+}
+
+/// Classify mixing from key `from` into key `to` using the Camelot wheel.
+pub fn key_compatibility(from: Key, to: Key) -> Compatibility {
+    let (a, b) = (from.camelot(), to.camelot());
+    // Steps clockwise from a to b, in 0..12.
+    let steps = (b.number as i32 - a.number as i32).rem_euclid(12);
+    match (a.mode == b.mode, steps) {
+        (true, 0) => Compatibility::Same,
+        (true, 1) | (true, 11) | (false, 0) => Compatibility::Adjacent,
+        // +7 on the wheel is a semitone up, +2 a whole tone up.
+        (true, 7) | (true, 2) => Compatibility::EnergyBoost,
+        _ => Compatibility::Clash,
+    }
+}
+
+/// Result of key analysis over a whole track.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KeyEstimate {
+    pub key: Key,
+    /// Correlation of the track's chroma with the winning key profile, in [0, 1].
+    pub confidence: f32,
+    pub camelot: Camelot,
+}
+
+/// Detect the key of mono `frames` sampled at `sample_rate`.
+///
+/// Returns `None` if the signal carries no pitched energy or `cancel` fires.
+pub fn detect_key<I>(frames: I, sample_rate: u32, cancel: &CancelToken) -> Option<KeyEstimate>
+where
+    I: IntoIterator<Item = f32>,
+{
+    let chroma = chroma_profile(frames, sample_rate, cancel)?;
+
+    let mut scores: Vec<(Key, f32)> = Key::all()
+        .map(|key| {
+            let profile = match key.mode {
+                Mode::Major => &MAJOR_PROFILE,
+                Mode::Minor => &MINOR_PROFILE,
+            };
+            let rotated: Vec<f32> = (0..12)
+                .map(|pc| profile[(pc + 12 - key.tonic as usize) % 12])
+                .collect();
+            (key, correlation(&chroma, &rotated))
+        })
+        .collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let (key, score) = scores[0];
+    Some(KeyEstimate {
+        key,
+        confidence: score.clamp(0.0, 1.0),
+        camelot: key.camelot(),
+    })
+}
+
+/// Run [`detect_key`] on a worker thread.
+pub fn spawn_detect_key<I>(frames: I, sample_rate: u32) -> AnalysisJob<KeyEstimate>
+where
+    I: IntoIterator<Item = f32> + Send + 'static,
+{
+    AnalysisJob::spawn(move |cancel| detect_key(frames, sample_rate, cancel))
+}
+
+/// Accumulate a normalized 12-bin pitch class profile over the whole signal.
+fn chroma_profile<I>(frames: I, sample_rate: u32, cancel: &CancelToken) -> Option<[f32; 12]>
+where
+    I: IntoIterator<Item = f32>,
+{
+    let factor = (sample_rate / ANALYSIS_RATE).max(1) as usize;
+    let rate = sample_rate as f32 / factor as f32;
+    let window: Vec<f32> = (0..FRAME_LEN)
+        .map(|i| {
+            let x = i as f32 / FRAME_LEN as f32;
+            0.5 - 0.5 * (std::f32::consts::TAU * x).cos()
+        })
+        .collect();
+    let bins: Vec<(usize, f32)> = (LOWEST_NOTE..=HIGHEST_NOTE)
+        .map(|note| {
+            let freq = 440.0 * 2f32.powf((note as f32 - 69.0) / 12.0);
+            let coeff = 2.0 * (std::f32::consts::TAU * freq / rate).cos();
+            (note as usize % 12, coeff)
+        })
+        .filter(|(_, coeff)| coeff.is_finite())
+        .collect();
+
+    let mut chroma = [0.0f32; 12];
+    let mut buffer = Vec::with_capacity(FRAME_LEN);
+    let mut acc = 0.0;
+    let mut count = 0;
+
+    for sample in frames {
+        acc += sample;
+        count += 1;
+        if count < factor {
+            continue;
+        }
+        buffer.push(acc / factor as f32);
+        acc = 0.0;
+        count = 0;
+
+        if buffer.len() == FRAME_LEN {
+            if cancel.is_canceled() {
+                return None;
+            }
+            let mut frame = [0.0f32; 12];
+            for (pitch_class, coeff) in &bins {
+                frame[*pitch_class] += goertzel_power(&buffer, &window, *coeff).sqrt();
+            }
+            let total: f32 = frame.iter().sum();
+            if total > 1e-6 {
+                for (acc, value) in chroma.iter_mut().zip(frame) {
+                    *acc += value / total;
+                }
+            }
+            buffer.drain(..HOP_LEN);
+        }
+    }
+
+    if cancel.is_canceled() || chroma.iter().sum::<f32>() <= 0.0 {
+        return None;
+    }
+    Some(chroma)
+}
+
+fn goertzel_power(samples: &[f32], window: &[f32], coeff: f32) -> f32 {
+    let (mut s1, mut s2) = (0.0f32, 0.0f32);
+    for (x, w) in samples.iter().zip(window) {
+        let s0 = x * w + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    s1 * s1 + s2 * s2 - coeff * s1 * s2
+}
+
+/// Pearson correlation of two equally sized profiles.
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len() as f32;
+    let mean_a = a.iter().sum::<f32>() / n;
+    let mean_b = b.iter().sum::<f32>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a) * (x - mean_a);
+        var_b += (y - mean_b) * (y - mean_b);
+    }
+    if var_a <= 0.0 || var_b <= 0.0 {
+        return 0.0;
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44_100;
+
+    fn midi_freq(note: u8) -> f32 {
+        440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+    }
+
+    /// Render each group of MIDI notes as a chord lasting `secs`.
+    fn render(chords: &[&[u8]], secs: f32) -> Vec<f32> {
+        let len = (secs * SAMPLE_RATE as f32) as usize;
+        chords
+            .iter()
+            .flat_map(|notes| {
+                (0..len).map(move |i| {
+                    let t = i as f32 / SAMPLE_RATE as f32;
+                    notes
+                        .iter()
+                        .map(|n| (std::f32::consts::TAU * midi_freq(*n) * t).sin())
+                        .sum::<f32>()
+                        * 0.2
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detects_c_major_arpeggio() {
+        let notes: Vec<[u8; 1]> = [60, 64, 67, 72, 67, 64]
+            .iter()
+            .cycle()
+            .take(24)
+            .map(|n| [*n])
+            .collect();
+        let chords: Vec<&[u8]> = notes.iter().map(|n| &n[..]).collect();
+        let job = spawn_detect_key(render(&chords, 0.25), SAMPLE_RATE);
+        let estimate = job.join().unwrap();
+        assert_eq!(estimate.key, Key::new(0, Mode::Major));
+        assert_eq!(estimate.camelot.to_string(), "8B");
+        assert!(estimate.confidence > 0.5, "{estimate:?}");
+    }
+
+    #[test]
+    fn detects_a_minor_progression() {
+        let progression: [&[u8]; 4] = [&[57, 60, 64], &[50, 53, 57], &[52, 56, 59], &[57, 60, 64]];
+        let samples = render(&progression, 1.0);
+        let estimate = detect_key(samples, SAMPLE_RATE, &CancelToken::new()).unwrap();
+        assert_eq!(estimate.key, Key::new(9, Mode::Minor));
+        assert_eq!(estimate.camelot.to_string(), "8A");
+    }
+
+    #[test]
+    fn cancel_abandons_analysis() {
+        let token = CancelToken::new();
+        token.cancel();
+        assert!(detect_key(render(&[&[60]], 2.0), SAMPLE_RATE, &token).is_none());
+    }
+
+    #[test]
+    fn camelot_codes_cover_the_wheel() {
+        assert_eq!(Key::new(7, Mode::Major).camelot().to_string(), "9B");
+        assert_eq!(Key::new(11, Mode::Major).camelot().to_string(), "1B");
+        assert_eq!(Key::new(0, Mode::Minor).camelot().to_string(), "5A");
+        let mut codes: Vec<String> = Key::all().map(|k| k.camelot().to_string()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), 24);
+    }
+
+    #[test]
+    fn compatibility_is_exhaustively_consistent() {
+        for a in Key::all() {
+            for b in Key::all() {
+                let (ca, cb) = (a.camelot(), b.camelot());
+                let up = (cb.number + 12 - ca.number) % 12;
+                let expected = if a == b {
+                    Compatibility::Same
+                } else if ca.mode == cb.mode && (up == 1 || up == 11) {
+                    Compatibility::Adjacent
+                } else if ca.mode != cb.mode && ca.number == cb.number {
+                    // Relative keys share every note.
+                    assert_eq!((a.tonic + 12 - b.tonic) % 12 % 3, 0);
+                    Compatibility::Adjacent
+                } else if ca.mode == cb.mode && [1, 2].contains(&((b.tonic + 12 - a.tonic) % 12)) {
+                    // Up a semitone or a whole tone.
+                    Compatibility::EnergyBoost
+                } else {
+                    Compatibility::Clash
+                };
+                assert_eq!(key_compatibility(a, b), expected, "{a} -> {b}");
+                if expected == Compatibility::Adjacent {
+                    assert_eq!(key_compatibility(b, a), Compatibility::Adjacent);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parses_tagged_key_names_and_camelot_codes() {
+        for (text, key) in [
+            ("Am", Key::new(9, Mode::Minor)),
+            ("F#", Key::new(6, Mode::Major)),
+            ("Ebmin", Key::new(3, Mode::Minor)),
+            ("bb minor", Key::new(10, Mode::Minor)),
+            ("C major", Key::new(0, Mode::Major)),
+            ("8A", Key::new(9, Mode::Minor)),
+            ("12b", Key::new(4, Mode::Major)),
+        ] {
+            assert_eq!(text.parse::<Key>(), Ok(key), "{text}");
+        }
+        for text in ["", "H", "13A", "Cdorian"] {
+            assert!(text.parse::<Key>().is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+
+/// Format version written into every overview; bump when the layout changes.
+pub const WAVEFORM_VERSION: u32 = 1;
+
+/// Crossover between the low and mid bands of the colored overview.
+const LOW_CROSSOVER_HZ: f32 = 200.0;
+/// Crossover between the mid and high bands of the colored overview.
+const HIGH_CROSSOVER_HZ: f32 = 2_000.0;
+
+/// How many frames are summarized by each bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketSize {
+    FramesPerBucket(u32),
+    /// Fit a track of known length into roughly `width` buckets.
+    TargetWidth {
+        width: u32,
+        total_frames: u64,
+    },
+}
+
+impl BucketSize {
+    fn frames_per_bucket(self) -> u32 {
+        match self {
+            BucketSize::FramesPerBucket(frames) => frames.max(1),
+            BucketSize::TargetWidth {
+                width,
+                total_frames,
+            } => total_frames.div_ceil(width.max(1) as u64).max(1) as u32,
+        }
+    }
+}
+
+/// Range and loudness of one channel within a bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChannelSummary {
+    pub min: f32,
+    pub max: f32,
+    pub rms: f32,
+}
+
+/// RMS energy of the low, mid and high bands within a bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BandEnergy {
+    pub low: f32,
+    pub mid: f32,
+    pub high: f32,
+}
+
+/// Downsampled summary of a track for drawing overview waveforms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaveformOverview {
+    pub version: u32,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub frames_per_bucket: u32,
+    /// Bucket-major summaries: `channels` entries per bucket.
+    pub summaries: Vec<ChannelSummary>,
+    /// Per-bucket band energies of the mono downmix, when requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bands: Option<Vec<BandEnergy>>,
+}
+
+impl WaveformOverview {
+    pub fn len(&self) -> usize {
+        self.summaries.len() / self.channels.max(1) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.summaries.is_empty()
+    }
+
+    /// Summary of `channel` in bucket `index`.
+    pub fn summary(&self, index: usize, channel: usize) -> Option<ChannelSummary> {
+        if channel >= self.channels as usize {
+            return None;
+        }
+        self.summaries
+            .get(index * self.channels as usize + channel)
+            .copied()
+    }
+}
+
+/// One-pole low-pass used for the cheap band split.
+#[derive(Debug, Clone, Copy)]
+struct OnePole {
+    coeff: f32,
+    state: f32,
+}
+
+impl OnePole {
+    fn new(cutoff_hz: f32, sample_rate: u32) -> Self {
+        let coeff = (-std::f32::consts::TAU * cutoff_hz / sample_rate as f32).exp();
+        Self { coeff, state: 0.0 }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        self.state = x + (self.state - x) * self.coeff;
+        self.state
+    }
+}
+
+/// Streaming overview generator; memory use is bounded by the number of buckets.
+#[derive(Debug, Clone)]
+pub struct WaveformBuilder {
+    overview: WaveformOverview,
+    pending: Vec<(ChannelSummary, f32)>,
+    pending_bands: [f32; 3],
+    pending_frames: u32,
+    filters: Option<(OnePole, OnePole)>,
+}
+
+impl WaveformBuilder {
+    pub fn new(channels: u16, sample_rate: u32, size: BucketSize, colored: bool) -> Self {
+        let channels = channels.max(1);
+        Self {
+            overview: WaveformOverview {
+                version: WAVEFORM_VERSION,
+                channels,
+                sample_rate,
+                frames_per_bucket: size.frames_per_bucket(),
+                summaries: Vec::new(),
+                bands: colored.then(Vec::new),
+            },
+            pending: vec![(empty_summary(), 0.0); channels as usize],
+            pending_bands: [0.0; 3],
+            pending_frames: 0,
+            filters: colored.then(|| {
+                (
+                    OnePole::new(LOW_CROSSOVER_HZ, sample_rate),
+                    OnePole::new(HIGH_CROSSOVER_HZ, sample_rate),
+                )
+            }),
+        }
+    }
+
+    /// Feed interleaved samples. A trailing partial frame is ignored.
+    pub fn push(&mut self, samples: &[f32]) {
+        let channels = self.overview.channels as usize;
+        for frame in samples.chunks_exact(channels) {
+            for ((summary, sum_sq), sample) in self.pending.iter_mut().zip(frame) {
+                summary.min = summary.min.min(*sample);
+                summary.max = summary.max.max(*sample);
+                *sum_sq += sample * sample;
+            }
+
+            if let Some((low_pass, high_pass)) = &mut self.filters {
+                let mono = frame.iter().sum::<f32>() / channels as f32;
+                let low = low_pass.process(mono);
+                let below_high = high_pass.process(mono);
+                let mid = below_high - low;
+                let high = mono - below_high;
+                self.pending_bands[0] += low * low;
+                self.pending_bands[1] += mid * mid;
+                self.pending_bands[2] += high * high;
+            }
+
+            self.pending_frames += 1;
+            if self.pending_frames == self.overview.frames_per_bucket {
+                self.flush_bucket();
+            }
+        }
+    }
+
+    /// Emit any partial bucket and return the finished overview.
+    pub fn finish(mut self) -> WaveformOverview {
+        if self.pending_frames > 0 {
+            self.flush_bucket();
+        }
+        self.overview
+    }
+
+    fn flush_bucket(&mut self) {
+        let frames = self.pending_frames as f32;
+        for (summary, sum_sq) in &mut self.pending {
+            summary.rms = (*sum_sq / frames).sqrt();
+            self.overview.summaries.push(*summary);
+            *summary = empty_summary();
+            *sum_sq = 0.0;
+        }
+        if let Some(bands) = &mut self.overview.bands {
+            let [low, mid, high] = self.pending_bands.map(|e| (e / frames).sqrt());
+            bands.push(BandEnergy { low, mid, high });
+            self.pending_bands = [0.0; 3];
+        }
+        self.pending_frames = 0;
+    }
+}
+
+fn empty_summary() -> ChannelSummary {
+    ChannelSummary {
+        min: f32::INFINITY,
+        max: f32::NEG_INFINITY,
+        rms: 0.0,
+    }
+}
+
+/// Build an overview from interleaved `samples` in one call.
+pub fn generate_waveform<I>(
+    samples: I,
+    channels: u16,
+    sample_rate: u32,
+    size: BucketSize,
+    colored: bool,
+) -> WaveformOverview
+where
+    I: IntoIterator<Item = f32>,
+{
+    let mut builder = WaveformBuilder::new(channels, sample_rate, size, colored);
+    let mut chunk = Vec::with_capacity(4_096);
+    for sample in samples {
+        chunk.push(sample);
+        if chunk.len() == chunk.capacity() {
+            builder.push(&chunk);
+            chunk.clear();
+        }
+    }
+    builder.push(&chunk);
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One second of silence followed by one second of a full-scale 50 Hz square wave.
+    fn silence_then_burst() -> Vec<f32> {
+        let silence = std::iter::repeat_n(0.0, 48_000 * 2);
+        let burst = (0..48_000).flat_map(|i| {
+            let value = if (i / 480) % 2 == 0 { 1.0 } else { -1.0 };
+            [value, value * 0.5]
+        });
+        silence.chain(burst).collect()
+    }
+
+    #[test]
+    fn summarizes_known_envelope() {
+        let overview = generate_waveform(
+            silence_then_burst(),
+            2,
+            48_000,
+            BucketSize::TargetWidth {
+                width: 20,
+                total_frames: 96_000,
+            },
+            true,
+        );
+
+        assert_eq!(overview.frames_per_bucket, 4_800);
+        assert_eq!(overview.len(), 20);
+        for bucket in 0..10 {
+            let left = overview.summary(bucket, 0).unwrap();
+            assert_eq!((left.min, left.max, left.rms), (0.0, 0.0, 0.0));
+            assert_eq!(overview.bands.as_ref().unwrap()[bucket].low, 0.0);
+        }
+        for bucket in 10..20 {
+            let left = overview.summary(bucket, 0).unwrap();
+            let right = overview.summary(bucket, 1).unwrap();
+            assert_eq!((left.min, left.max), (-1.0, 1.0));
+            assert!((left.rms - 1.0).abs() < 1e-6);
+            assert_eq!((right.min, right.max), (-0.5, 0.5));
+            assert!((right.rms - 0.5).abs() < 1e-6);
+            let bands = overview.bands.as_ref().unwrap()[bucket];
+            assert!(bands.low > bands.high, "{bands:?}");
+        }
+    }
+
+    #[test]
+    fn streams_in_arbitrary_chunks() {
+        let samples = silence_then_burst();
+        let size = BucketSize::FramesPerBucket(1_000);
+        let mut builder = WaveformBuilder::new(2, 48_000, size, false);
+        for chunk in samples.chunks(333 * 2) {
+            builder.push(chunk);
+        }
+        let streamed = builder.finish();
+        assert_eq!(streamed, generate_waveform(samples, 2, 48_000, size, false));
+        assert_eq!(streamed.len(), 96);
+        assert!(streamed.bands.is_none());
+    }
+
+    #[test]
+    fn round_trips_serialized_form() {
+        let overview = generate_waveform(
+            silence_then_burst(),
+            2,
+            48_000,
+            BucketSize::FramesPerBucket(4_800),
+            true,
+        );
+        let json = serde_json::to_string(&overview).unwrap();
+        let restored: WaveformOverview = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, overview);
+        assert_eq!(restored.version, WAVEFORM_VERSION);
+    }
+}
@@ -0,0 +1,682 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use crate::analysis::key::{key_compatibility, Compatibility};
+use crate::deck::{Deck, DeckCommand, DeckPosition, TransportState};
+use crate::library::{IndexEntry, LibraryIndex, Playlist};
+use crate::DeckId;
+
+/// Default time before the end of the playing track at which the mix starts.
+pub const DEFAULT_HANDOFF_SECONDS: f64 = 20.0;
+/// Loudness difference still counted as holding the energy level.
+const ENERGY_HOLD_LU: f32 = 2.0;
+
+/// How the loudness of consecutive tracks should move.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnergyProgression {
+    #[default]
+    Any,
+    /// Each track at least as loud as the last.
+    Build,
+    /// Each track within a couple of LU of the last.
+    Hold,
+    /// Each track no louder than the last.
+    Release,
+}
+
+/// Rules the next track has to pass. A rule is skipped for tracks missing the
+/// tempo, key or loudness it needs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelectionRules {
+    /// Largest tempo difference from the playing track, in BPM; `None` allows any.
+    pub bpm_tolerance: Option<f64>,
+    /// Only keys that mix harmonically out of the playing track's key.
+    pub harmonic: bool,
+    /// Tracks played this recently are not picked again.
+    pub no_repeat: usize,
+    pub energy: EnergyProgression,
+}
+
+impl Default for SelectionRules {
+    fn default() -> Self {
+        Self {
+            bpm_tolerance: Some(6.0),
+            harmonic: true,
+            no_repeat: 10,
+            energy: EnergyProgression::Any,
+        }
+    }
+}
+
+/// How far the rules were relaxed to find a track, in the order they give way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Relaxation {
+    /// Every rule held.
+    None,
+    /// The energy progression was ignored.
+    Energy,
+    /// The key was ignored too.
+    Key,
+    /// The tempo tolerance was doubled.
+    WideBpm,
+    /// Any tempo was allowed.
+    Bpm,
+    /// Recently played tracks were allowed back, except the one on air.
+    History,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoDjConfig {
+    pub rules: SelectionRules,
+    /// Remaining time on the playing track at which the transition to the next begins.
+    pub handoff_seconds: f64,
+}
+
+impl Default for AutoDjConfig {
+    fn default() -> Self {
+        Self {
+            rules: SelectionRules::default(),
+            handoff_seconds: DEFAULT_HANDOFF_SECONDS,
+        }
+    }
+}
+
+/// What auto-DJ needs to know about a deck; [`Deck`] implements it.
+pub trait DeckStatus {
+    fn transport(&self) -> TransportState;
+    fn position(&self) -> DeckPosition;
+}
+
+impl DeckStatus for Deck {
+    fn transport(&self) -> TransportState {
+        Deck::transport(self).clone()
+    }
+
+    fn position(&self) -> DeckPosition {
+        self.position_report()
+    }
+}
+
+/// Work for the caller to carry out on auto-DJ's behalf, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AutoDjAction {
+    /// Load `track` onto `deck`, e.g. with [`Deck::load_with`].
+    Load {
+        deck: DeckId,
+        track: IndexEntry,
+        relaxed: Relaxation,
+    },
+    Command {
+        deck: DeckId,
+        command: DeckCommand,
+    },
+    /// Start a transition between the decks on the transition engine.
+    Transition {
+        from: DeckId,
+        to: DeckId,
+    },
+    /// Nothing in the queue can follow the playing track, even with every rule relaxed.
+    QueueExhausted,
+}
+
+#[derive(Debug, Clone)]
+struct Cued {
+    deck: DeckId,
+    track: IndexEntry,
+    locked: bool,
+}
+
+/// Keeps the decks playing unattended, picking each next track from a queue.
+///
+/// Call [`tick`](Self::tick) regularly with both decks and carry out the returned actions.
+#[derive(Debug, Clone, Default)]
+pub struct AutoDj {
+    config: AutoDjConfig,
+    queue: Vec<IndexEntry>,
+    /// Paths played or passed over, most recent last.
+    history: VecDeque<PathBuf>,
+    live: Option<(DeckId, IndexEntry)>,
+    next: Option<Cued>,
+    paused: bool,
+    skip: bool,
+    exhausted: bool,
+}
+
+impl AutoDj {
+    pub fn new(config: AutoDjConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn config(&self) -> &AutoDjConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: AutoDjConfig) {
+        self.config = config;
+    }
+
+    /// Candidates in order of preference; the first one passing the rules is picked.
+    pub fn seed(&mut self, tracks: Vec<IndexEntry>) {
+        self.queue = tracks;
+        self.exhausted = false;
+    }
+
+    pub fn seed_from_index(&mut self, index: &LibraryIndex) {
+        self.seed(index.entries().cloned().collect());
+    }
+
+    /// Queue a playlist in its own order; entries missing from the index are skipped.
+    pub fn seed_from_playlist(&mut self, playlist: &Playlist, index: &LibraryIndex) {
+        let tracks = playlist
+            .entries
+            .iter()
+            .filter_map(|entry| index.get(&entry.path).cloned())
+            .collect();
+        self.seed(tracks);
+    }
+
+    pub fn queue(&self) -> &[IndexEntry] {
+        &self.queue
+    }
+
+    /// Track on air and the deck playing it.
+    pub fn now_playing(&self) -> Option<(DeckId, &IndexEntry)> {
+        self.live.as_ref().map(|(deck, track)| (*deck, track))
+    }
+
+    pub fn up_next(&self) -> Option<&IndexEntry> {
+        self.next.as_ref().map(|cued| &cued.track)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop making decisions; whatever is playing carries on.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Mix into the next track as soon as it is loaded.
+    pub fn skip(&mut self) {
+        self.skip = true;
+    }
+
+    /// Keep the cued track even through [`Self::skip_next`]; returns whether one is cued.
+    pub fn lock_next(&mut self, locked: bool) -> bool {
+        match &mut self.next {
+            Some(cued) => {
+                cued.locked = locked;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_next_locked(&self) -> bool {
+        self.next.as_ref().is_some_and(|cued| cued.locked)
+    }
+
+    /// Pick another next track unless the cued one is locked. The passed-over track
+    /// counts as played for the no-repeat window.
+    pub fn skip_next(&mut self) -> bool {
+        match &self.next {
+            Some(cued) if !cued.locked => {
+                let path = cued.track.path.clone();
+                self.remember(path);
+                self.next = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Decide what happens next given the decks as they are now.
+    pub fn tick<D: DeckStatus>(&mut self, decks: &[D; 2]) -> Vec<AutoDjAction> {
+        let mut actions = Vec::new();
+        if self.paused {
+            return actions;
+        }
+        if let Some(cued) = &self.next {
+            if matches!(
+                decks[cued.deck.index()].transport(),
+                TransportState::Error(_)
+            ) {
+                // Unreadable files are dropped from the queue for good.
+                let path = cued.track.path.clone();
+                self.queue.retain(|track| track.path != path);
+                self.next = None;
+            }
+        }
+
+        if self.live.is_none() {
+            self.start(decks, &mut actions);
+        }
+        let Some((live, _)) = self.live else {
+            return actions;
+        };
+        let idle = other(live);
+        if self.next.is_none() {
+            if decks[idle.index()].transport().can_load() {
+                self.cue(idle, &mut actions);
+            }
+            // A fresh load only shows on the deck once the caller has carried it out.
+            return actions;
+        }
+        let Some(cued) = &self.next else {
+            return actions;
+        };
+        let ready = decks[cued.deck.index()].transport() == TransportState::Loaded;
+        let due = self.skip
+            || decks[live.index()].position().remaining_seconds <= self.config.handoff_seconds;
+        if ready && due {
+            actions.push(AutoDjAction::Command {
+                deck: idle,
+                command: DeckCommand::Play,
+            });
+            actions.push(AutoDjAction::Transition {
+                from: live,
+                to: idle,
+            });
+            self.go_live();
+        }
+        actions
+    }
+
+    /// First track of the session, played straight in without a transition.
+    fn start<D: DeckStatus>(&mut self, decks: &[D; 2], actions: &mut Vec<AutoDjAction>) {
+        match &self.next {
+            None => {
+                let free = [DeckId::A, DeckId::B]
+                    .into_iter()
+                    .find(|deck| decks[deck.index()].transport().can_load());
+                if let Some(deck) = free {
+                    self.cue(deck, actions);
+                }
+            }
+            Some(cued) if decks[cued.deck.index()].transport() == TransportState::Loaded => {
+                actions.push(AutoDjAction::Command {
+                    deck: cued.deck,
+                    command: DeckCommand::Play,
+                });
+                self.go_live();
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn cue(&mut self, deck: DeckId, actions: &mut Vec<AutoDjAction>) {
+        let Some((track, relaxed)) = self.select() else {
+            if !self.exhausted {
+                self.exhausted = true;
+                actions.push(AutoDjAction::QueueExhausted);
+            }
+            return;
+        };
+        self.exhausted = false;
+        self.next = Some(Cued {
+            deck,
+            track: track.clone(),
+            locked: false,
+        });
+        actions.push(AutoDjAction::Load {
+            deck,
+            track,
+            relaxed,
+        });
+    }
+
+    fn go_live(&mut self) {
+        let Some(cued) = self.next.take() else {
+            return;
+        };
+        self.remember(cued.track.path.clone());
+        self.live = Some((cued.deck, cued.track));
+        self.skip = false;
+    }
+
+    fn remember(&mut self, path: PathBuf) {
+        self.history.push_back(path);
+        while self.history.len() > self.config.rules.no_repeat.max(1) {
+            self.history.pop_front();
+        }
+    }
+
+    /// First queued track passing the rules, relaxing them one at a time until one does.
+    fn select(&self) -> Option<(IndexEntry, Relaxation)> {
+        use Relaxation::*;
+        [None, Energy, Key, WideBpm, Bpm, History]
+            .into_iter()
+            .find_map(|relaxed| {
+                self.queue
+                    .iter()
+                    .find(|track| self.allows(track, relaxed))
+                    .map(|track| (track.clone(), relaxed))
+            })
+    }
+
+    fn allows(&self, track: &IndexEntry, relaxed: Relaxation) -> bool {
+        let rules = &self.config.rules;
+        let Some((_, current)) = &self.live else {
+            return relaxed == Relaxation::History || !self.history.contains(&track.path);
+        };
+        if track.path == current.path {
+            return false;
+        }
+        if relaxed < Relaxation::History && self.history.contains(&track.path) {
+            return false;
+        }
+        let tolerance = match relaxed {
+            Relaxation::WideBpm => rules.bpm_tolerance.map(|bpm| bpm * 2.0),
+            Relaxation::Bpm | Relaxation::History => None,
+            _ => rules.bpm_tolerance,
+        };
+        if let (Some(tolerance), Some(from), Some(to)) = (tolerance, current.bpm, track.bpm) {
+            if (to - from).abs() > tolerance {
+                return false;
+            }
+        }
+        if rules.harmonic && relaxed < Relaxation::Key {
+            if let (Some(from), Some(to)) = (current.key, track.key) {
+                if key_compatibility(from, to) == Compatibility::Clash {
+                    return false;
+                }
+            }
+        }
+        if relaxed < Relaxation::Energy {
+            if let (Some(from), Some(to)) = (current.gain, track.gain) {
+                let change = to.integrated_lufs - from.integrated_lufs;
+                let fits = match rules.energy {
+                    EnergyProgression::Any => true,
+                    EnergyProgression::Build => change >= 0.0,
+                    EnergyProgression::Hold => change.abs() <= ENERGY_HOLD_LU,
+                    EnergyProgression::Release => change <= 0.0,
+                };
+                if !fits {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+fn other(deck: DeckId) -> DeckId {
+    if deck == DeckId::A {
+        DeckId::B
+    } else {
+        DeckId::A
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::gain::TrackGain;
+
+    /// A deck that follows auto-DJ's actions instantly.
+    #[derive(Debug, Clone)]
+    struct FakeDeck {
+        transport: TransportState,
+        remaining_seconds: f64,
+    }
+
+    impl DeckStatus for FakeDeck {
+        fn transport(&self) -> TransportState {
+            self.transport.clone()
+        }
+
+        fn position(&self) -> DeckPosition {
+            DeckPosition {
+                remaining_seconds: self.remaining_seconds,
+                ..DeckPosition::default()
+            }
+        }
+    }
+
+    fn fake_decks() -> [FakeDeck; 2] {
+        [(), ()].map(|_| FakeDeck {
+            transport: TransportState::Empty,
+            remaining_seconds: 0.0,
+        })
+    }
+
+    fn carry_out(decks: &mut [FakeDeck; 2], actions: &[AutoDjAction]) {
+        for action in actions {
+            match action {
+                AutoDjAction::Load { deck, track, .. } => {
+                    decks[deck.index()] = FakeDeck {
+                        transport: TransportState::Loaded,
+                        remaining_seconds: track.duration_seconds.unwrap(),
+                    }
+                }
+                AutoDjAction::Command { deck, command } => {
+                    let deck = &mut decks[deck.index()];
+                    deck.transport = deck.transport.after(*command).unwrap();
+                }
+                AutoDjAction::Transition { from, .. } => {
+                    decks[from.index()].transport = TransportState::Paused;
+                }
+                AutoDjAction::QueueExhausted => {}
+            }
+        }
+    }
+
+    fn track(name: &str, bpm: f64, key: &str, lufs: f32) -> IndexEntry {
+        IndexEntry {
+            path: PathBuf::from(format!("/music/{name}.mp3")),
+            size: 0,
+            modified: None,
+            artist: None,
+            title: Some(name.to_string()),
+            album: None,
+            duration_seconds: Some(180.0),
+            bpm: Some(bpm),
+            key: Some(key.parse().unwrap()),
+            gain: Some(TrackGain {
+                integrated_lufs: lufs,
+                trim_db: -18.0 - lufs,
+            }),
+            has_artwork: false,
+        }
+    }
+
+    fn loaded(actions: &[AutoDjAction]) -> Vec<(String, Relaxation)> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                AutoDjAction::Load { track, relaxed, .. } => {
+                    Some((track.title.clone().unwrap(), *relaxed))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Start on the first queued track and return what is cued after it.
+    fn cue_after_opener(autodj: &mut AutoDj) -> Vec<(String, Relaxation)> {
+        let mut decks = fake_decks();
+        let actions = autodj.tick(&decks);
+        carry_out(&mut decks, &actions);
+        let actions = autodj.tick(&decks);
+        carry_out(&mut decks, &actions);
+        loaded(&actions)
+    }
+
+    #[test]
+    fn picks_the_first_track_passing_the_rules_and_relaxes_in_order() {
+        let opener = track("opener", 124.0, "Am", -10.0);
+        let config = AutoDjConfig {
+            rules: SelectionRules {
+                bpm_tolerance: Some(4.0),
+                energy: EnergyProgression::Build,
+                ..SelectionRules::default()
+            },
+            ..AutoDjConfig::default()
+        };
+        let mut autodj = AutoDj::new(config);
+        autodj.seed(vec![
+            opener.clone(),
+            track("too fast", 140.0, "Am", -8.0),
+            track("clashing", 125.0, "F#", -8.0),
+            track("quieter", 125.0, "Em", -12.0),
+            track("fits", 126.0, "C", -9.0),
+        ]);
+        assert_eq!(
+            cue_after_opener(&mut autodj),
+            [("fits".into(), Relaxation::None)]
+        );
+
+        // Each step drops the most recently satisfied rule first.
+        for (queue, expected) in [
+            (vec!["quieter"], ("quieter", Relaxation::Energy)),
+            (vec!["clashing", "quieter"], ("quieter", Relaxation::Energy)),
+            (vec!["clashing"], ("clashing", Relaxation::Key)),
+            (vec!["too fast"], ("too fast", Relaxation::Bpm)),
+        ] {
+            let mut autodj = AutoDj::new(config);
+            let mut tracks = vec![opener.clone()];
+            tracks.extend(
+                [
+                    track("too fast", 140.0, "Am", -8.0),
+                    track("clashing", 125.0, "F#", -8.0),
+                    track("quieter", 125.0, "Em", -12.0),
+                ]
+                .into_iter()
+                .filter(|track| queue.contains(&track.title.as_deref().unwrap())),
+            );
+            autodj.seed(tracks);
+            assert_eq!(
+                cue_after_opener(&mut autodj),
+                [(expected.0.to_string(), expected.1)],
+                "{queue:?}"
+            );
+        }
+
+        let mut autodj = AutoDj::new(config);
+        autodj.seed(vec![opener.clone(), track("near", 131.0, "Am", -8.0)]);
+        assert_eq!(
+            cue_after_opener(&mut autodj),
+            [("near".into(), Relaxation::WideBpm)]
+        );
+
+        let mut alone = AutoDj::new(config);
+        alone.seed(vec![opener]);
+        let mut decks = fake_decks();
+        let actions = alone.tick(&decks);
+        carry_out(&mut decks, &actions);
+        assert_eq!(alone.tick(&decks)[1..], [AutoDjAction::QueueExhausted]);
+        assert!(alone.tick(&decks).is_empty());
+    }
+
+    #[test]
+    fn no_repeat_window_cycles_through_the_queue() {
+        let mut autodj = AutoDj::new(AutoDjConfig {
+            rules: SelectionRules {
+                no_repeat: 3,
+                ..SelectionRules::default()
+            },
+            ..AutoDjConfig::default()
+        });
+        autodj.seed(
+            ["a", "b", "c", "d"]
+                .map(|name| track(name, 124.0, "Am", -10.0))
+                .to_vec(),
+        );
+        let mut decks = fake_decks();
+        let mut played = Vec::new();
+        for _ in 0..40 {
+            let actions = autodj.tick(&decks);
+            carry_out(&mut decks, &actions);
+            if let Some((deck, track)) = autodj.now_playing() {
+                if played.last() != track.title.as_ref() {
+                    played.push(track.title.clone().unwrap());
+                }
+                decks[deck.index()].remaining_seconds = 0.0;
+            }
+            assert!(loaded(&actions)
+                .iter()
+                .all(|(_, relaxed)| *relaxed == Relaxation::None));
+        }
+        assert!(played.len() > 8, "{played:?}");
+        for window in played.windows(4) {
+            let mut unique = window.to_vec();
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), 4, "{played:?}");
+        }
+    }
+
+    #[test]
+    fn hands_off_before_the_end_and_honours_controls() {
+        let mut autodj = AutoDj::new(AutoDjConfig::default());
+        autodj.seed(
+            ["one", "two", "three"]
+                .map(|name| track(name, 124.0, "Am", -10.0))
+                .to_vec(),
+        );
+        let mut decks = fake_decks();
+        let actions = autodj.tick(&decks);
+        assert_eq!(loaded(&actions), [("one".into(), Relaxation::None)]);
+        carry_out(&mut decks, &actions);
+        let actions = autodj.tick(&decks);
+        assert_eq!(
+            actions[0],
+            AutoDjAction::Command {
+                deck: DeckId::A,
+                command: DeckCommand::Play
+            }
+        );
+        assert_eq!(loaded(&actions), [("two".into(), Relaxation::None)]);
+        carry_out(&mut decks, &actions);
+
+        // Passing over "two" cues "three"; locking it survives another skip.
+        assert!(autodj.skip_next());
+        let actions = autodj.tick(&decks);
+        assert_eq!(loaded(&actions), [("three".into(), Relaxation::None)]);
+        carry_out(&mut decks, &actions);
+        assert!(autodj.lock_next(true));
+        assert!(!autodj.skip_next());
+        assert_eq!(autodj.up_next().unwrap().title.as_deref(), Some("three"));
+
+        decks[0].remaining_seconds = DEFAULT_HANDOFF_SECONDS + 0.1;
+        assert!(autodj.tick(&decks).is_empty());
+        decks[0].remaining_seconds = DEFAULT_HANDOFF_SECONDS;
+        autodj.set_paused(true);
+        assert!(autodj.tick(&decks).is_empty());
+        autodj.set_paused(false);
+        let actions = autodj.tick(&decks);
+        assert_eq!(
+            actions,
+            [
+                AutoDjAction::Command {
+                    deck: DeckId::B,
+                    command: DeckCommand::Play
+                },
+                AutoDjAction::Transition {
+                    from: DeckId::A,
+                    to: DeckId::B
+                },
+            ]
+        );
+        carry_out(&mut decks, &actions);
+        assert_eq!(autodj.now_playing().unwrap().0, DeckId::B);
+
+        // Everything has been heard, so the opener comes back; skipping mixes
+        // into it as soon as it is on the idle deck.
+        autodj.skip();
+        let actions = autodj.tick(&decks);
+        assert_eq!(loaded(&actions), [("one".into(), Relaxation::History)]);
+        carry_out(&mut decks, &actions);
+        assert_eq!(
+            autodj.tick(&decks)[1],
+            AutoDjAction::Transition {
+                from: DeckId::B,
+                to: DeckId::A
+            }
+        );
+    }
+}
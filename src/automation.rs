@@ -0,0 +1,222 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crossbeam_queue::ArrayQueue;
+
+#[cfg(feature = "native")]
+use crate::remote::ParamTarget;
+#[cfg(feature = "native")]
+use crate::render::{AutomationAction, AutomationPoint};
+use crate::DeckId;
+
+/// Default smallest change worth a new point, in each parameter's own units.
+pub const DEFAULT_EPSILON: f32 = 1e-3;
+const LANE_COUNT: usize = 7;
+
+/// A bus parameter the log follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    Crossfader,
+    MasterGain,
+    Gain(DeckId),
+    Filter(DeckId),
+    MicGain,
+}
+
+impl Lane {
+    fn index(self) -> usize {
+        match self {
+            Lane::Crossfader => 0,
+            Lane::MasterGain => 1,
+            Lane::Gain(deck) => 2 + deck.index(),
+            Lane::Filter(deck) => 4 + deck.index(),
+            Lane::MicGain => 6,
+        }
+    }
+
+    /// The remote target and deck the value is replayed through.
+    #[cfg(feature = "native")]
+    pub fn target(self) -> (ParamTarget, Option<DeckId>) {
+        match self {
+            Lane::Crossfader => (ParamTarget::Crossfader, None),
+            Lane::MasterGain => (ParamTarget::MasterGain, None),
+            Lane::Gain(deck) => (ParamTarget::Gain, Some(deck)),
+            Lane::Filter(deck) => (ParamTarget::Filter, Some(deck)),
+            Lane::MicGain => (ParamTarget::MicGain, None),
+        }
+    }
+}
+
+/// A value the bus applied, from `frame` onwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoggedValue {
+    /// Output frames mixed before the value took effect.
+    pub frame: u64,
+    pub lane: Lane,
+    pub value: f32,
+}
+
+/// Audio-side half of an automation log, fed by the [`SummingBus`](crate::SummingBus).
+#[derive(Debug)]
+pub struct AutomationTap {
+    queue: Arc<ArrayQueue<LoggedValue>>,
+    epsilon: f32,
+    /// Last value queued per lane; NaN until the first, so every lane starts with a point.
+    last: [f32; LANE_COUNT],
+    dropped: Arc<AtomicU64>,
+}
+
+impl AutomationTap {
+    /// Queue `value` if it moved more than the epsilon since the lane's last point.
+    pub fn observe(&mut self, frame: u64, lane: Lane, value: f32) {
+        let last = self.last[lane.index()];
+        if last.is_nan() || (value - last).abs() > self.epsilon {
+            self.push(frame, lane, value);
+        }
+    }
+
+    /// Queue `value` if it differs at all, e.g. where a ramp lands on its target.
+    pub fn settle(&mut self, frame: u64, lane: Lane, value: f32) {
+        if value != self.last[lane.index()] {
+            self.push(frame, lane, value);
+        }
+    }
+
+    fn push(&mut self, frame: u64, lane: Lane, value: f32) {
+        match self.queue.push(LoggedValue { frame, lane, value }) {
+            Ok(()) => self.last[lane.index()] = value,
+            // Left as it was, so the value is offered again on the next block.
+            Err(_) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Control-side half: collects what the bus actually applied, after clamping and ramps.
+#[derive(Debug)]
+pub struct AutomationLog {
+    queue: Arc<ArrayQueue<LoggedValue>>,
+    dropped: Arc<AtomicU64>,
+    values: Vec<LoggedValue>,
+}
+
+/// Create a log holding up to `capacity` undrained values, decimated to changes
+/// larger than `epsilon`.
+pub fn automation_log(capacity: usize, epsilon: f32) -> (AutomationTap, AutomationLog) {
+    let queue = Arc::new(ArrayQueue::new(capacity.max(1)));
+    let dropped = Arc::new(AtomicU64::new(0));
+    (
+        AutomationTap {
+            queue: queue.clone(),
+            epsilon: epsilon.max(0.0),
+            last: [f32::NAN; LANE_COUNT],
+            dropped: dropped.clone(),
+        },
+        AutomationLog {
+            queue,
+            dropped,
+            values: Vec::new(),
+        },
+    )
+}
+
+impl AutomationLog {
+    /// Move everything the bus has queued into the log. Returns how many values arrived.
+    pub fn drain(&mut self) -> usize {
+        let before = self.values.len();
+        self.values.extend(std::iter::from_fn(|| self.queue.pop()));
+        self.values.len() - before
+    }
+
+    /// Values drained so far, in the order they were applied.
+    pub fn values(&self) -> &[LoggedValue] {
+        &self.values
+    }
+
+    /// Values the bus could not queue because the log was not drained in time.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The drained values as automation points for a bus running at `sample_rate`,
+    /// ready for [`save_automation`](crate::render::save_automation) or
+    /// [`render_mix`](crate::render::render_mix).
+    #[cfg(feature = "native")]
+    pub fn to_automation(&self, sample_rate: u32) -> Vec<AutomationPoint> {
+        self.values
+            .iter()
+            .map(|logged| {
+                let (target, deck) = logged.lane.target();
+                AutomationPoint {
+                    seconds: logged.frame as f64 / sample_rate as f64,
+                    action: AutomationAction::SetParam {
+                        target,
+                        deck,
+                        value: logged.value,
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, ParameterUpdate, SummingBus};
+
+    #[test]
+    fn logs_clamped_values_and_decimated_ramps() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx).with_sample_rate(1_000);
+        let (tap, mut log) = automation_log(256, 0.05);
+        bus.set_automation_tap(Some(tap));
+        tx.send(ParameterUpdate::DeckGain {
+            deck: DeckId::B,
+            gain: -0.5,
+        })
+        .unwrap();
+        tx.send(ParameterUpdate::Crossfader(1.5)).unwrap();
+
+        let mut out = [0.0; 200];
+        bus.mix_stereo(&[0.0; 200], &[0.0; 200], &mut out);
+        tx.send(ParameterUpdate::CrossfaderRamp {
+            target: 0.0,
+            seconds: 0.05,
+        })
+        .unwrap();
+        tx.send(ParameterUpdate::MasterGain(1.0 + 1e-4)).unwrap();
+        for _ in 0..2 {
+            bus.mix_stereo(&[0.0; 200], &[0.0; 200], &mut out);
+        }
+        log.drain();
+        assert_eq!(log.dropped(), 0);
+
+        let lane = |lane: Lane| -> Vec<(u64, f32)> {
+            log.values()
+                .iter()
+                .filter(|logged| logged.lane == lane)
+                .map(|logged| (logged.frame, logged.value))
+                .collect()
+        };
+        assert_eq!(lane(Lane::Gain(DeckId::B)), [(0, 0.0)]);
+        assert_eq!(lane(Lane::Gain(DeckId::A)), [(0, 1.0)]);
+        // A change inside the epsilon is not worth a point.
+        assert_eq!(lane(Lane::MasterGain), [(0, 1.0)]);
+
+        let crossfader = lane(Lane::Crossfader);
+        assert_eq!(crossfader[0], (0, 1.0));
+        // The ramp lands exactly on its target, however small the last step.
+        let (end, value) = *crossfader.last().unwrap();
+        assert!((149..=150).contains(&end) && value == 0.0, "{end}: {value}");
+        for &(frame, value) in &crossfader[1..crossfader.len() - 1] {
+            // The ramp applies 1/50 per frame from frame 100.
+            let expected = 1.0 - (frame - 99) as f32 / 50.0;
+            assert!((value - expected).abs() < 1e-5, "{frame}: {value}");
+        }
+        for pair in crossfader[..crossfader.len() - 1].windows(2) {
+            assert!(pair[0].1 - pair[1].1 > 0.05);
+        }
+    }
+}
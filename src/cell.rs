@@ -0,0 +1,216 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::{DeckId, ParameterSource, ParameterUpdate};
+
+/// Bus-wide controls a cell carries, in slot order.
+const BUS_SLOTS: usize = 5;
+/// Controls a cell carries for each deck, in slot order after the bus ones.
+const DECK_SLOTS: usize = 3;
+
+/// Latest value of one control, and whether the bus has yet to see it.
+#[derive(Debug, Default)]
+struct Slot {
+    value: AtomicU32,
+    fresh: AtomicBool,
+}
+
+/// Slot and value `update` lands in, if a cell carries it.
+fn slot_of(update: &ParameterUpdate) -> Option<(usize, f32)> {
+    let deck = |deck: &DeckId, slot: usize| BUS_SLOTS + deck.index() * DECK_SLOTS + slot;
+    Some(match update {
+        ParameterUpdate::Crossfader(value) => (0, *value),
+        ParameterUpdate::MasterGain(gain) => (1, *gain),
+        ParameterUpdate::BoothGain(gain) => (2, *gain),
+        ParameterUpdate::CueMix(mix) => (3, *mix),
+        ParameterUpdate::StereoWidth(width) => (4, *width),
+        ParameterUpdate::DeckGain { deck: id, gain } => (deck(id, 0), *gain),
+        ParameterUpdate::DeckTrim { deck: id, gain } => (deck(id, 1), *gain),
+        ParameterUpdate::DeckFilter { deck: id, position } => (deck(id, 2), *position),
+        _ => return None,
+    })
+}
+
+/// The update that sets `slot` to `value`, the inverse of [`slot_of`].
+fn update_for(slot: usize, value: f32) -> ParameterUpdate {
+    match slot {
+        0 => ParameterUpdate::Crossfader(value),
+        1 => ParameterUpdate::MasterGain(value),
+        2 => ParameterUpdate::BoothGain(value),
+        3 => ParameterUpdate::CueMix(value),
+        4 => ParameterUpdate::StereoWidth(value),
+        _ => {
+            let deck = DeckId((slot - BUS_SLOTS) / DECK_SLOTS);
+            match (slot - BUS_SLOTS) % DECK_SLOTS {
+                0 => ParameterUpdate::DeckGain { deck, gain: value },
+                1 => ParameterUpdate::DeckTrim { deck, gain: value },
+                _ => ParameterUpdate::DeckFilter {
+                    deck,
+                    position: value,
+                },
+            }
+        }
+    }
+}
+
+/// Control-side handle to a parameter cell.
+#[derive(Debug, Clone)]
+pub struct ParamCellSender {
+    slots: Arc<[Slot]>,
+}
+
+impl ParamCellSender {
+    /// Set the control `update` carries, replacing any value the bus has not
+    /// picked up yet. Never blocks and never fills up; returns `update` if it
+    /// is not a continuous control a cell carries, or is for a deck past the
+    /// last, which then belongs on a [`parameter_channel`](crate::parameter_channel).
+    pub fn send(&self, update: ParameterUpdate) -> Result<(), ParameterUpdate> {
+        let Some((slot, value)) = slot_of(&update) else {
+            return Err(update);
+        };
+        let Some(slot) = self.slots.get(slot) else {
+            return Err(update);
+        };
+        slot.value.store(value.to_bits(), Ordering::Relaxed);
+        slot.fresh.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Audio-thread side of a parameter cell, handed to the bus as its
+/// [`ParameterSource`].
+#[derive(Debug)]
+pub struct ParamCellReceiver {
+    slots: Arc<[Slot]>,
+    /// Slot the next look starts from, so every control gets its turn under
+    /// the bus's per-block cap.
+    cursor: usize,
+}
+
+impl ParameterSource for ParamCellReceiver {
+    fn next_update(&mut self) -> Option<ParameterUpdate> {
+        for _ in 0..self.slots.len() {
+            let index = self.cursor;
+            self.cursor = (self.cursor + 1) % self.slots.len();
+            let slot = &self.slots[index];
+            // Cleared before the read, so a value stored meanwhile is picked
+            // up now or on the next look, never lost.
+            if slot.fresh.swap(false, Ordering::Acquire) {
+                let value = f32::from_bits(slot.value.load(Ordering::Relaxed));
+                return Some(update_for(index, value));
+            }
+        }
+        None
+    }
+
+    fn pending(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.fresh.load(Ordering::Relaxed))
+            .count()
+    }
+}
+
+/// Create a latest-value transport for the continuous controls of a bus with
+/// `decks` decks: the crossfader, master, booth and cue mix, stereo width, and
+/// each deck's fader, trim and filter.
+///
+/// Unlike a [`parameter_channel`](crate::parameter_channel), which keeps every
+/// update in order and refuses new ones once full, a cell keeps only the
+/// newest value of each control, so a control thread sending faster than the
+/// bus mixes moves it straight to where the knob is now. Reading takes no
+/// locks and allocates nothing. Pair it with a queue for everything else by
+/// handing the bus both as a `(ParameterReceiver, ParamCellReceiver)`.
+pub fn parameter_cell(decks: usize) -> (ParamCellSender, ParamCellReceiver) {
+    let slots: Arc<[Slot]> = (0..BUS_SLOTS + decks * DECK_SLOTS)
+        .map(|_| Slot::default())
+        .collect();
+    (
+        ParamCellSender {
+            slots: slots.clone(),
+        },
+        ParamCellReceiver { slots, cursor: 0 },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{parameter_channel, SummingBus};
+
+    #[test]
+    fn receiver_ends_at_the_last_value_sent() {
+        let (tx, rx) = parameter_cell(2);
+        let mut bus = SummingBus::new(rx);
+        let sender = thread::spawn(move || {
+            for step in 0..=10_000 {
+                tx.send(ParameterUpdate::Crossfader(step as f32 / 10_000.0))
+                    .unwrap();
+            }
+            tx.send(ParameterUpdate::DeckGain {
+                deck: DeckId::B,
+                gain: 0.25,
+            })
+            .unwrap();
+        });
+        let mut out = [0.0; 4];
+        let mut seen = Vec::new();
+        while !sender.is_finished() {
+            bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+            seen.push(bus.crossfader);
+        }
+        sender.join().unwrap();
+        bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+        assert_eq!(bus.crossfader, 1.0);
+        assert_eq!(bus.channels[1].gain, 0.25);
+        // Positions only ever move forward, skipping the ones overtaken.
+        assert!(seen.windows(2).all(|pair| pair[1] >= pair[0]));
+
+        // Nothing new, nothing to apply.
+        let mut rx = bus.params;
+        assert!(rx.next_update().is_none());
+        assert_eq!(rx.pending(), 0);
+    }
+
+    #[test]
+    fn only_continuous_controls_for_known_decks_fit() {
+        let (tx, mut rx) = parameter_cell(1);
+        assert!(tx.send(ParameterUpdate::MonoOutput(true)).is_err());
+        let for_b = ParameterUpdate::DeckTrim {
+            deck: DeckId::B,
+            gain: 1.0,
+        };
+        assert!(tx.send(for_b).is_err());
+
+        for value in [0.1, 0.2, 0.3] {
+            tx.send(ParameterUpdate::MasterGain(value)).unwrap();
+        }
+        tx.send(ParameterUpdate::DeckFilter {
+            deck: DeckId::A,
+            position: -0.5,
+        })
+        .unwrap();
+        assert_eq!(rx.pending(), 2);
+        assert!(matches!(
+            rx.next_update(),
+            Some(ParameterUpdate::MasterGain(gain)) if gain == 0.3
+        ));
+        assert!(matches!(
+            rx.next_update(),
+            Some(ParameterUpdate::DeckFilter { deck: DeckId::A, position }) if position == -0.5
+        ));
+        assert!(rx.next_update().is_none());
+
+        // Alongside a queue, the queue carries the rest.
+        let (queue_tx, queue_rx) = parameter_channel(4);
+        let (cell_tx, cell_rx) = parameter_cell(2);
+        let mut bus = SummingBus::new((queue_rx, cell_rx));
+        queue_tx.send(ParameterUpdate::MonoOutput(true)).unwrap();
+        cell_tx.send(ParameterUpdate::MasterGain(0.5)).unwrap();
+        bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut [0.0; 4]);
+        assert_eq!(bus.master_gain, 0.5);
+        assert_eq!(bus.mono.target(), 1.0);
+    }
+}
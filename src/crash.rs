@@ -1,9 +1,35 @@
+use std::collections::VecDeque;
 use std::fs::{self, OpenOptions};
-use std::io::Write;
-use std::path::Path;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 use chrono::Utc;
 
+/// Number of recent runtime events kept for the next crash report.
+const BREADCRUMB_CAPACITY: usize = 64;
+
+static BREADCRUMBS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Record a notable runtime event (e.g. an audio stream error) so it shows up in crash logs.
+pub fn record_breadcrumb(message: impl Into<String>) {
+    let entry = format!("{} {}", Utc::now().to_rfc3339(), message.into());
+    if let Ok(mut crumbs) = BREADCRUMBS.lock() {
+        if crumbs.len() == BREADCRUMB_CAPACITY {
+            crumbs.pop_front();
+        }
+        crumbs.push_back(entry);
+    }
+}
+
+/// Recorded breadcrumbs, oldest first.
+pub fn breadcrumbs() -> Vec<String> {
+    BREADCRUMBS
+        .lock()
+        .map(|crumbs| crumbs.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
 /// Install a panic hook that writes crash information to disk.
 pub fn install_panic_hook<P: AsRef<Path>>(log_path: P, version: &str) {
     let path = log_path.as_ref().to_path_buf();
@@ -14,17 +40,17 @@ pub fn install_panic_hook<P: AsRef<Path>>(log_path: P, version: &str) {
             let _ = fs::create_dir_all(parent);
         }
 
-        let mut file = match OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&path)
-        {
+        let mut file = match OpenOptions::new().create(true).append(true).open(&path) {
             Ok(file) => file,
             Err(_) => return,
         };
 
         let timestamp = Utc::now().to_rfc3339();
-        let _ = writeln!(file, "\n=== crash at {} (version {}) ===", timestamp, version);
+        let _ = writeln!(
+            file,
+            "\n=== crash at {} (version {}) ===",
+            timestamp, version
+        );
         if let Some(location) = panic_info.location() {
             let _ = writeln!(file, "location: {}:{}", location.file(), location.line());
         }
@@ -33,12 +59,49 @@ pub fn install_panic_hook<P: AsRef<Path>>(log_path: P, version: &str) {
         } else if let Some(s) = panic_info.payload().downcast_ref::<String>() {
             let _ = writeln!(file, "message: {}", s);
         }
+        // Never block inside the hook: the panic may have happened while the lock was held.
+        if let Ok(crumbs) = BREADCRUMBS.try_lock() {
+            for crumb in crumbs.iter() {
+                let _ = writeln!(file, "breadcrumb: {}", crumb);
+            }
+        }
     }));
 }
 
+/// File that exists while DeeJay runs; finding one at startup means the last run
+/// crashed or lost power.
+#[derive(Debug)]
+pub struct RunMarker {
+    path: PathBuf,
+}
+
+impl RunMarker {
+    pub fn default_path() -> PathBuf {
+        Path::new("deejay.running").to_path_buf()
+    }
+
+    /// Whether an earlier run left its marker behind.
+    pub fn left_behind(path: &Path) -> bool {
+        path.exists()
+    }
+
+    /// Mark this run as in progress until the marker is dropped on a clean exit.
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        fs::write(&path, std::process::id().to_string())?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for RunMarker {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::install_panic_hook;
+    use super::{install_panic_hook, record_breadcrumb, RunMarker};
     use std::panic;
     use tempfile::tempdir;
 
@@ -47,6 +110,7 @@ mod tests {
         let dir = tempdir().unwrap();
         let log_path = dir.path().join("crash.log");
         install_panic_hook(&log_path, "0.0.0-test");
+        record_breadcrumb("audio stream error: device unplugged");
 
         let result = panic::catch_unwind(|| panic!("boom"));
         assert!(result.is_err());
@@ -54,5 +118,17 @@ mod tests {
         let contents = std::fs::read_to_string(&log_path).unwrap();
         assert!(contents.contains("boom"));
         assert!(contents.contains("version"));
+        assert!(contents.contains("device unplugged"));
+    }
+
+    #[test]
+    fn run_marker_is_only_left_behind_by_unclean_exits() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("deejay.running");
+        drop(RunMarker::create(&path).unwrap());
+        assert!(!RunMarker::left_behind(&path));
+
+        std::mem::forget(RunMarker::create(&path).unwrap());
+        assert!(RunMarker::left_behind(&path));
     }
 }
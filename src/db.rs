@@ -0,0 +1,104 @@
+use std::f32::consts::LOG10_2;
+
+/// Exponent of the audio taper, which puts half travel at -10 dB.
+const AUDIO_TAPER_EXPONENT: f32 = 10.0 / (20.0 * LOG10_2);
+
+/// Linear gain for `db`; -inf dB is exactly silence and NaN stays NaN.
+pub fn db_to_linear(db: f32) -> f32 {
+    if db == f32::NEG_INFINITY {
+        0.0
+    } else {
+        10f32.powf(db / 20.0)
+    }
+}
+
+/// Level of `gain` in dB, whatever its sign; silence is -inf dB.
+pub fn linear_to_db(gain: f32) -> f32 {
+    if gain == 0.0 {
+        f32::NEG_INFINITY
+    } else {
+        20.0 * gain.abs().log10()
+    }
+}
+
+/// Gain for a channel fader at `position` in [0, 1] on an audio taper: silent
+/// at the bottom, -10 dB half way up and unity at the top.
+pub fn fader_to_linear(position: f32) -> f32 {
+    position.clamp(0.0, 1.0).powf(AUDIO_TAPER_EXPONENT)
+}
+
+/// Like [`fader_to_linear`], in dB.
+pub fn fader_to_db(position: f32) -> f32 {
+    linear_to_db(fader_to_linear(position))
+}
+
+/// Fader position that gives `db`, the inverse of [`fader_to_db`]; levels
+/// above 0 dB sit at the top.
+pub fn db_to_fader(db: f32) -> f32 {
+    db_to_linear(db.min(0.0)).powf(AUDIO_TAPER_EXPONENT.recip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, DeckId, ParameterUpdate, SummingBus};
+
+    #[test]
+    fn conversions_round_trip_and_handle_silence() {
+        for db in [-60.0, -23.5, -6.0, 0.0, 3.0, 12.0] {
+            assert!((linear_to_db(db_to_linear(db)) - db).abs() < 1e-4, "{db}");
+        }
+        for gain in [0.001, 0.5, 1.0, 2.0] {
+            assert!(
+                (db_to_linear(linear_to_db(gain)) - gain).abs() < 1e-6,
+                "{gain}"
+            );
+        }
+        assert_eq!(db_to_linear(f32::NEG_INFINITY), 0.0);
+        assert_eq!(linear_to_db(0.0), f32::NEG_INFINITY);
+        assert_eq!(linear_to_db(-0.0), f32::NEG_INFINITY);
+        assert!(db_to_linear(f32::NAN).is_nan());
+        assert!((linear_to_db(-0.5) - linear_to_db(0.5)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn audio_taper_pins_its_end_and_middle_points() {
+        assert_eq!(fader_to_linear(0.0), 0.0);
+        assert_eq!(fader_to_db(0.0), f32::NEG_INFINITY);
+        assert!((fader_to_db(0.5) + 10.0).abs() < 1e-4);
+        assert_eq!(fader_to_linear(1.0), 1.0);
+        assert_eq!(fader_to_linear(1.5), 1.0);
+        for position in [0.0, 0.1, 0.5, 0.9, 1.0] {
+            let back = db_to_fader(fader_to_db(position));
+            assert!((back - position).abs() < 1e-5, "{position}: {back}");
+        }
+        assert_eq!(db_to_fader(6.0), 1.0);
+    }
+
+    #[test]
+    fn db_updates_set_the_linear_gain() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        let send = |update: Option<ParameterUpdate>| tx.send(update.unwrap()).unwrap();
+        send(Some(ParameterUpdate::Crossfader(0.0)));
+        send(ParameterUpdate::deck_gain_db(DeckId::A, -6.0));
+        send(ParameterUpdate::master_gain_db(f32::NEG_INFINITY));
+        let mut out = [0.0; 2];
+        bus.mix_stereo(&[1.0; 2], &[0.0; 2], &mut out);
+        assert_eq!(out, [0.0; 2]);
+        send(ParameterUpdate::master_gain_db(0.0));
+        bus.mix_stereo(&[1.0; 2], &[0.0; 2], &mut out);
+        assert!((out[0] - db_to_linear(-6.0)).abs() < 1e-6, "{out:?}");
+
+        // Linear updates still land as they are.
+        send(Some(ParameterUpdate::DeckGain {
+            deck: DeckId::A,
+            gain: 0.25,
+        }));
+        bus.mix_stereo(&[1.0; 2], &[0.0; 2], &mut out);
+        assert!((out[0] - 0.25).abs() < 1e-6, "{out:?}");
+
+        assert!(ParameterUpdate::deck_gain_db(DeckId::B, f32::NAN).is_none());
+        assert!(ParameterUpdate::master_gain_db(f32::NAN).is_none());
+    }
+}
@@ -0,0 +1,1775 @@
+pub mod cues;
+pub mod grid;
+pub mod jog;
+pub mod position;
+pub mod quantize;
+mod stretch;
+pub mod sync;
+pub mod transport;
+
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam_queue::ArrayQueue;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::analysis::gain::{TrackGain, MAX_AUTO_TRIM_DB};
+
+pub use cues::{HotCues, HOT_CUE_COUNT};
+pub use grid::BeatGrid;
+pub use jog::{DEFAULT_SPIN_UP_SECONDS, MAX_SCRATCH_RATE};
+pub use position::{DeckPosition, DeckPositionReader, DEFAULT_END_WARNING_SECONDS};
+pub use quantize::{PendingTrigger, Quantize, QuantizedAction, DEFAULT_SNAP_BACK_SECONDS};
+pub use sync::{sync_assist, SyncAssist, SyncOptions, SyncSnapshot, SyncState};
+pub use transport::{DeckEvent, DeckEvents, TransportState, DECK_EVENT_CAPACITY};
+
+use jog::Jog;
+use position::PositionState;
+use stretch::Stretcher;
+
+/// Length of the crossfade applied whenever the playhead jumps.
+const DECLICK_SECONDS: f64 = 0.003;
+/// Length of the crossfade between the varispeed and keylock paths when keylock is toggled.
+const KEYLOCK_FADE_SECONDS: f64 = 0.01;
+
+/// Slowest varispeed ratio a deck accepts.
+pub const MIN_TEMPO_RATIO: f64 = 0.5;
+/// Fastest varispeed ratio a deck accepts.
+pub const MAX_TEMPO_RATIO: f64 = 2.0;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum DeckError {
+    #[error("no track loaded")]
+    NoTrack,
+    #[error("hot cue slot {0} does not exist")]
+    InvalidCueSlot(usize),
+    #[error("hot cue slot {0} is empty")]
+    EmptyCueSlot(usize),
+    #[error("invalid loop region {start}..{end}")]
+    InvalidLoop { start: u64, end: u64 },
+    #[error("deck has no beat grid")]
+    NoBeatGrid,
+}
+
+/// Per-track annotations that are persisted alongside the track's metadata.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackMarkers {
+    #[serde(default)]
+    pub hot_cues: HotCues,
+    #[serde(default)]
+    pub beat_grid: Option<BeatGrid>,
+    /// Loudness analysis, used for automatic trim on load.
+    #[serde(default)]
+    pub gain: Option<TrackGain>,
+}
+
+/// Descriptive tags shown to listeners, e.g. in stream "now playing" updates.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackInfo {
+    #[serde(default)]
+    pub artist: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl TrackInfo {
+    /// "Artist - Title", or whichever of the two is known.
+    pub fn display_name(&self) -> Option<String> {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => Some(format!("{artist} - {title}")),
+            (Some(name), None) | (None, Some(name)) => Some(name.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Decoded, interleaved stereo audio held in memory for playback on a deck.
+#[derive(Debug, Clone)]
+pub struct Track {
+    samples: Arc<[f32]>,
+    sample_rate: u32,
+    pub markers: TrackMarkers,
+    pub info: TrackInfo,
+}
+
+impl Track {
+    /// Wrap interleaved stereo samples. A trailing half frame is ignored.
+    pub fn from_interleaved(samples: impl Into<Arc<[f32]>>, sample_rate: u32) -> Self {
+        Self {
+            samples: samples.into(),
+            sample_rate,
+            markers: TrackMarkers::default(),
+            info: TrackInfo::default(),
+        }
+    }
+
+    /// Attach previously persisted markers to the track.
+    pub fn with_markers(mut self, markers: TrackMarkers) -> Self {
+        self.markers = markers;
+        self
+    }
+
+    pub fn with_info(mut self, info: TrackInfo) -> Self {
+        self.info = info;
+        self
+    }
+
+    pub fn frames(&self) -> u64 {
+        (self.samples.len() / 2) as u64
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Interleaved stereo samples.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    fn frame(&self, index: u64) -> [f32; 2] {
+        let idx = index as usize * 2;
+        match self.samples.get(idx..idx + 2) {
+            Some(frame) => [frame[0], frame[1]],
+            None => [0.0, 0.0],
+        }
+    }
+
+    /// Read a stereo frame at a fractional position using linear interpolation.
+    pub(crate) fn read(&self, position: f64) -> [f32; 2] {
+        if position < 0.0 {
+            return [0.0, 0.0];
+        }
+        let index = position.floor();
+        let frac = (position - index) as f32;
+        let a = self.frame(index as u64);
+        if frac == 0.0 {
+            return a;
+        }
+        let b = self.frame(index as u64 + 1);
+        [a[0] + (b[0] - a[0]) * frac, a[1] + (b[1] - a[1]) * frac]
+    }
+}
+
+/// Controller input applied to a deck.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeckCommand {
+    /// Hand on or off the jog platter; touching takes over the playhead for scratching.
+    JogTouch(bool),
+    /// Platter movement while touched, already scaled to track frames; negative is backwards.
+    JogTick {
+        delta_frames: f64,
+    },
+    /// Play backwards from the current playhead.
+    SetReverse(bool),
+    /// Keep the original pitch while the tempo ratio changes the speed.
+    SetKeylock(bool),
+    /// Jump this many beats of the grid in the playing direction; negative jumps back.
+    BeatJump {
+        beats: i32,
+    },
+    /// Set the varispeed ratio, as the pitch fader or sync does.
+    SetTempoRatio(f64),
+    /// Move the playhead by this many track frames, declicked; negative moves back.
+    Shift {
+        frames: f64,
+    },
+    Play,
+    Pause,
+    /// Stop and park the playhead at the start of the track.
+    Stop,
+    /// Unload the track.
+    Eject,
+}
+
+/// What reverse playback does on reaching the start of the track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReverseEnd {
+    /// Stop at the first frame.
+    #[default]
+    Stop,
+    /// Carry on forwards from the first frame.
+    Bounce,
+}
+
+/// Looping region in track frames; `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopRegion {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl LoopRegion {
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+}
+
+/// Crossfade from the pre-jump playhead (or silence) to the new one.
+#[derive(Debug, Clone, Copy)]
+struct Declick {
+    from: Option<f64>,
+    /// Advance of the fading-out playhead per frame.
+    rate: f64,
+    elapsed: u32,
+    length: u32,
+}
+
+/// Crossfade from the previous playback path into the one keylock now selects.
+#[derive(Debug, Clone, Copy)]
+struct KeylockFade {
+    elapsed: u32,
+    length: u32,
+}
+
+/// A single playback deck with a playhead, hot cues and an optional loop.
+#[derive(Debug)]
+pub struct Deck {
+    track: Option<Track>,
+    position: f64,
+    transport: TransportState,
+    events: Arc<ArrayQueue<DeckEvent>>,
+    /// Result of a load running on its own thread.
+    loading: Option<Receiver<Result<Track, String>>>,
+    tempo_ratio: f64,
+    keylock: bool,
+    stretcher: Stretcher,
+    keylock_fade: Option<KeylockFade>,
+    reverse: bool,
+    reverse_end: ReverseEnd,
+    /// Beat jumps carry an active loop along instead of leaving it.
+    loop_move: bool,
+    slip: bool,
+    /// Playhead that carries on at the base rate while a slip operation holds the audible one.
+    ghost: Option<f64>,
+    quantize: Quantize,
+    snap_back_seconds: f64,
+    pending: Option<PendingTrigger>,
+    end_warning_seconds: f64,
+    position_state: Arc<PositionState>,
+    active_loop: Option<LoopRegion>,
+    declick: Option<Declick>,
+    /// Linear trim applied to everything the deck renders.
+    trim: f32,
+    /// Take the trim from the track's loudness analysis on load.
+    auto_gain: bool,
+    jog: Jog,
+}
+
+impl Default for Deck {
+    fn default() -> Self {
+        Self {
+            track: None,
+            position: 0.0,
+            transport: TransportState::Empty,
+            events: Arc::new(ArrayQueue::new(DECK_EVENT_CAPACITY)),
+            loading: None,
+            tempo_ratio: 1.0,
+            keylock: false,
+            stretcher: Stretcher::default(),
+            keylock_fade: None,
+            reverse: false,
+            reverse_end: ReverseEnd::Stop,
+            loop_move: false,
+            slip: false,
+            ghost: None,
+            quantize: Quantize::Off,
+            snap_back_seconds: DEFAULT_SNAP_BACK_SECONDS,
+            pending: None,
+            end_warning_seconds: DEFAULT_END_WARNING_SECONDS,
+            position_state: Arc::default(),
+            active_loop: None,
+            declick: None,
+            trim: 1.0,
+            auto_gain: false,
+            jog: Jog::default(),
+        }
+    }
+}
+
+impl Deck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a track, replacing any existing one, and park the playhead at the start.
+    ///
+    /// With auto gain on, the trim is set from the track's analysis, or reset to
+    /// unity if it has none. Ignored, with [`DeckEvent::LoadRejected`], while playing.
+    pub fn load(&mut self, track: Track) {
+        if !self.transport.can_load() {
+            self.emit(DeckEvent::LoadRejected(self.transport.clone()));
+            return;
+        }
+        self.loading = None;
+        self.install(track);
+    }
+
+    /// Run `loader` on its own thread, showing [`TransportState::Loading`] until
+    /// [`Self::poll_load`] (or the next render) picks up the result.
+    pub fn load_with<F>(&mut self, loader: F)
+    where
+        F: FnOnce() -> Result<Track, String> + Send + 'static,
+    {
+        if !self.transport.can_load() {
+            self.emit(DeckEvent::LoadRejected(self.transport.clone()));
+            return;
+        }
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let spawned = thread::Builder::new()
+            .name("deck-load".into())
+            .spawn(move || {
+                let _ = sender.send(loader());
+            });
+        self.unload();
+        match spawned {
+            Ok(_) => {
+                self.loading = Some(receiver);
+                self.transport = TransportState::Loading;
+            }
+            Err(err) => self.fail_load(format!("failed to spawn loader: {err}")),
+        }
+    }
+
+    /// Install a finished background load; returns whether one completed.
+    pub fn poll_load(&mut self) -> bool {
+        let Some(receiver) = &self.loading else {
+            return false;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => Err("loader panicked".into()),
+        };
+        self.loading = None;
+        match result {
+            Ok(track) => self.install(track),
+            Err(reason) => self.fail_load(reason),
+        }
+        true
+    }
+
+    pub fn transport(&self) -> &TransportState {
+        &self.transport
+    }
+
+    /// Control-thread end of this deck's event queue.
+    pub fn events(&self) -> DeckEvents {
+        DeckEvents {
+            queue: self.events.clone(),
+        }
+    }
+
+    fn emit(&self, event: DeckEvent) {
+        let _ = self.events.push(event);
+    }
+
+    fn fail_load(&mut self, reason: String) {
+        self.transport = TransportState::Error(reason.clone());
+        self.emit(DeckEvent::LoadFailed(reason));
+    }
+
+    /// Drop the track and everything tied to its playhead.
+    fn unload(&mut self) {
+        self.track = None;
+        self.position = 0.0;
+        self.park();
+        self.transport = TransportState::Empty;
+    }
+
+    /// Forget playhead state that should not outlive a stop or track change.
+    fn park(&mut self) {
+        self.active_loop = None;
+        self.declick = None;
+        self.keylock_fade = None;
+        self.stretcher.stop();
+        self.jog.reset();
+        self.ghost = None;
+        self.pending = None;
+    }
+
+    fn install(&mut self, track: Track) {
+        if self.auto_gain {
+            let trim = track.markers.gain.map_or(0.0, |gain| {
+                gain.trim_db.clamp(-MAX_AUTO_TRIM_DB, MAX_AUTO_TRIM_DB)
+            });
+            self.set_trim_db(trim);
+        }
+        let duration_seconds = track.frames() as f64 / track.sample_rate as f64;
+        let bpm = track.markers.beat_grid.map(|grid| grid.bpm);
+        self.track = Some(track);
+        self.position = 0.0;
+        self.park();
+        self.transport = TransportState::Loaded;
+        self.emit(DeckEvent::TrackLoaded {
+            duration_seconds,
+            bpm,
+        });
+    }
+
+    pub fn track(&self) -> Option<&Track> {
+        self.track.as_ref()
+    }
+
+    /// Markers of the loaded track, for persisting between sessions.
+    pub fn markers(&self) -> Option<&TrackMarkers> {
+        self.track.as_ref().map(|track| &track.markers)
+    }
+
+    /// Hot cues of the loaded track, for UI display.
+    pub fn cues(&self) -> Option<&HotCues> {
+        self.markers().map(|markers| &markers.hot_cues)
+    }
+
+    /// Current playhead in track frames.
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// Playhead in track frames and wall-clock time at the current tempo.
+    pub fn position_report(&self) -> DeckPosition {
+        let Some(track) = &self.track else {
+            return DeckPosition::default();
+        };
+        let frames = track.frames() as f64;
+        let frames_per_second = track.sample_rate as f64 * self.tempo_ratio;
+        // The interpolating reader has no lookahead, so the playhead is what is heard.
+        let remaining_seconds = (frames - self.position).max(0.0) / frames_per_second;
+        DeckPosition {
+            frame: self.position,
+            seconds: self.position / frames_per_second,
+            remaining_seconds,
+            fraction: if frames > 0.0 {
+                self.position / frames
+            } else {
+                0.0
+            },
+            end_warning: remaining_seconds < self.end_warning_seconds,
+            pitch: self.effective_pitch(),
+            playing: self.is_playing(),
+        }
+    }
+
+    /// Lock-free view of [`Self::position_report`], refreshed after every rendered block.
+    pub fn position_reader(&self) -> DeckPositionReader {
+        DeckPositionReader {
+            state: self.position_state.clone(),
+        }
+    }
+
+    pub fn end_warning_seconds(&self) -> f64 {
+        self.end_warning_seconds
+    }
+
+    pub fn set_end_warning_seconds(&mut self, seconds: f64) {
+        self.end_warning_seconds = seconds.max(0.0);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.transport == TransportState::Playing
+    }
+
+    fn start_playing(&mut self) {
+        if !self.is_playing() {
+            self.transport = TransportState::Playing;
+            self.emit(DeckEvent::Started);
+        }
+    }
+
+    /// Varispeed ratio applied to playback (1.0 = original speed).
+    pub fn tempo_ratio(&self) -> f64 {
+        self.tempo_ratio
+    }
+
+    pub fn set_tempo_ratio(&mut self, ratio: f64) {
+        self.tempo_ratio = ratio.clamp(MIN_TEMPO_RATIO, MAX_TEMPO_RATIO);
+    }
+
+    /// Whether keylock holds the pitch while the tempo ratio changes the speed.
+    pub fn is_keylock(&self) -> bool {
+        self.keylock
+    }
+
+    /// Pitch of forward playback relative to the original (1.0 = unchanged).
+    pub fn effective_pitch(&self) -> f64 {
+        if self.keylock {
+            1.0
+        } else {
+            self.tempo_ratio
+        }
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        self.reverse
+    }
+
+    pub fn reverse_end(&self) -> ReverseEnd {
+        self.reverse_end
+    }
+
+    pub fn set_reverse_end(&mut self, end: ReverseEnd) {
+        self.reverse_end = end;
+    }
+
+    pub fn slip(&self) -> bool {
+        self.slip
+    }
+
+    /// In slip mode scratches, loops, reverse and cue jumps leave a ghost playhead running
+    /// that playback returns to afterwards. Turning slip off ends any held operation and
+    /// returns to the ghost.
+    pub fn set_slip(&mut self, slip: bool) {
+        self.slip = slip;
+        if !slip && self.ghost.is_some() {
+            self.jog.reset();
+            self.active_loop = None;
+            self.reverse = false;
+            self.end_slip();
+        }
+    }
+
+    /// Where the ghost playhead is while a slip operation is held, in track frames.
+    pub fn slip_position(&self) -> Option<f64> {
+        self.ghost
+    }
+
+    /// Start the ghost playhead if slip mode is on and it is not already running.
+    fn begin_slip(&mut self) {
+        if self.slip && self.ghost.is_none() {
+            self.ghost = Some(self.position);
+        }
+    }
+
+    /// Return to the ghost playhead once no slip operation is held any more.
+    fn end_slip(&mut self) {
+        if self.jog.is_touched() || self.active_loop.is_some() || self.reverse {
+            return;
+        }
+        if let Some(ghost) = self.ghost.take() {
+            self.jump_to(ghost);
+        }
+    }
+
+    pub fn quantize(&self) -> Quantize {
+        self.quantize
+    }
+
+    /// Hold cue and loop triggers back to the grid while playing; needs a beat grid.
+    pub fn set_quantize(&mut self, quantize: Quantize) {
+        self.quantize = quantize;
+    }
+
+    pub fn snap_back_seconds(&self) -> f64 {
+        self.snap_back_seconds
+    }
+
+    /// Window after a boundary in which a trigger fires at once instead of waiting.
+    pub fn set_snap_back_seconds(&mut self, seconds: f64) {
+        self.snap_back_seconds = seconds.max(0.0);
+    }
+
+    /// Quantized trigger waiting for its boundary.
+    pub fn pending_trigger(&self) -> Option<PendingTrigger> {
+        self.pending
+    }
+
+    /// Drop the waiting trigger; returns whether there was one.
+    pub fn cancel_pending(&mut self) -> bool {
+        self.pending.take().is_some()
+    }
+
+    /// Run `action` now, or schedule it for the next quantize boundary.
+    fn quantized(&mut self, action: QuantizedAction) {
+        let grid = self.beat_grid().copied();
+        let (Some(grid), Some(beats), Some(track)) = (grid, self.quantize.beats(), &self.track)
+        else {
+            return self.fire(action, 0.0);
+        };
+        if !self.is_playing() || self.reverse || self.jog.is_active() {
+            return self.fire(action, 0.0);
+        }
+        let boundary = grid.division_at_or_before(self.position, beats);
+        let late_by = self.position - boundary;
+        let snap_frames = self.snap_back_seconds * track.sample_rate as f64 * self.tempo_ratio;
+        if late_by <= snap_frames {
+            self.fire(action, late_by);
+        } else {
+            self.pending = Some(PendingTrigger {
+                action,
+                at_frame: boundary + grid.frames_per_beat() * beats,
+            });
+        }
+    }
+
+    /// Carry out `action` as if it had happened `late_by` frames ago.
+    fn fire(&mut self, action: QuantizedAction, late_by: f64) {
+        self.pending = None;
+        match action {
+            QuantizedAction::Cue { frame, .. } => {
+                // A slipped cue jump has no release; the ghost runs until another operation ends.
+                self.begin_slip();
+                self.active_loop = None;
+                self.jump_to(frame as f64 + late_by);
+                self.start_playing();
+            }
+            QuantizedAction::Loop(region) => {
+                self.begin_slip();
+                self.active_loop = Some(region);
+            }
+        }
+    }
+
+    /// Signed playhead advance per frame in normal playback.
+    fn playback_rate(&self) -> f64 {
+        if self.reverse {
+            -self.tempo_ratio
+        } else {
+            self.tempo_ratio
+        }
+    }
+
+    pub fn trim_db(&self) -> f32 {
+        20.0 * self.trim.log10()
+    }
+
+    pub fn set_trim_db(&mut self, db: f32) {
+        self.trim = 10f32.powf(db / 20.0);
+    }
+
+    pub fn auto_gain(&self) -> bool {
+        self.auto_gain
+    }
+
+    /// Apply each track's suggested trim as it is loaded.
+    pub fn set_auto_gain(&mut self, enabled: bool) {
+        self.auto_gain = enabled;
+    }
+
+    /// Time to get from standstill to normal speed after releasing the jog.
+    pub fn spin_up_seconds(&self) -> f64 {
+        self.jog.spin_up_seconds()
+    }
+
+    pub fn set_spin_up_seconds(&mut self, seconds: f64) {
+        self.jog.set_spin_up_seconds(seconds);
+    }
+
+    /// Whether the hand is on the jog platter.
+    pub fn is_scratching(&self) -> bool {
+        self.jog.is_touched()
+    }
+
+    /// Apply a controller command.
+    pub fn apply(&mut self, command: DeckCommand) {
+        match command {
+            DeckCommand::JogTouch(true) => {
+                let Some(track) = &self.track else {
+                    return;
+                };
+                let rate = if self.is_playing() {
+                    self.playback_rate()
+                } else {
+                    0.0
+                };
+                self.jog.touch(self.position, rate, track.sample_rate);
+                self.begin_slip();
+            }
+            DeckCommand::JogTouch(false) if self.ghost.is_some() => {
+                self.jog.reset();
+                self.end_slip();
+            }
+            DeckCommand::JogTouch(false) => self.jog.release(),
+            DeckCommand::JogTick { delta_frames } => self.jog.tick(delta_frames),
+            DeckCommand::Play | DeckCommand::Pause | DeckCommand::Stop | DeckCommand::Eject => {
+                self.transport_command(command);
+            }
+            DeckCommand::SetReverse(reverse) => {
+                if reverse != self.reverse {
+                    if reverse {
+                        self.begin_slip();
+                    }
+                    // Fade the old direction out from the flip point.
+                    let rate = self.playback_rate();
+                    self.reverse = reverse;
+                    if self.is_playing() {
+                        self.start_declick(rate);
+                    }
+                    if !reverse {
+                        self.end_slip();
+                    }
+                }
+            }
+            DeckCommand::SetKeylock(keylock) => {
+                if keylock != self.keylock {
+                    self.keylock = keylock;
+                    self.start_keylock_fade();
+                }
+            }
+            DeckCommand::BeatJump { beats } => {
+                // Without a track or grid there is nothing to jump by.
+                let _ = self.beat_jump(beats);
+            }
+            DeckCommand::SetTempoRatio(ratio) => self.set_tempo_ratio(ratio),
+            DeckCommand::Shift { frames } => self.jump_to(self.position + frames),
+        }
+    }
+
+    /// Crossfade between the varispeed and keylock paths; both follow the same playhead.
+    fn start_keylock_fade(&mut self) {
+        let Some(track) = &self.track else {
+            return;
+        };
+        if !self.is_playing() {
+            self.keylock_fade = None;
+            return;
+        }
+        let length = ((track.sample_rate as f64 * KEYLOCK_FADE_SECONDS).round() as u32).max(1);
+        self.keylock_fade = Some(KeylockFade { elapsed: 0, length });
+    }
+
+    fn transport_command(&mut self, command: DeckCommand) {
+        let Some(next) = self.transport.after(command) else {
+            self.emit(DeckEvent::Rejected {
+                command,
+                state: self.transport.clone(),
+            });
+            return;
+        };
+        match command {
+            DeckCommand::Play => self.start_playing(),
+            DeckCommand::Stop => {
+                self.position = 0.0;
+                self.park();
+            }
+            DeckCommand::Eject => {
+                self.loading = None;
+                self.unload();
+            }
+            _ => {}
+        }
+        self.transport = next;
+    }
+
+    pub fn beat_grid(&self) -> Option<&BeatGrid> {
+        self.markers()
+            .and_then(|markers| markers.beat_grid.as_ref())
+    }
+
+    pub fn set_beat_grid(&mut self, grid: BeatGrid) -> Result<(), DeckError> {
+        let track = self.track.as_mut().ok_or(DeckError::NoTrack)?;
+        track.markers.beat_grid = Some(grid);
+        Ok(())
+    }
+
+    /// Tempo heard at the current tempo ratio.
+    pub fn effective_bpm(&self) -> Option<f64> {
+        self.beat_grid().map(|grid| grid.bpm * self.tempo_ratio)
+    }
+
+    /// Match `other`'s effective tempo and move the playhead so the next beats line up.
+    pub fn sync_to(&mut self, other: &Deck) -> Result<(), DeckError> {
+        let grid = *self.beat_grid().ok_or(DeckError::NoBeatGrid)?;
+        let other_grid = other.beat_grid().ok_or(DeckError::NoBeatGrid)?;
+        let target_bpm = other_grid.bpm * other.tempo_ratio;
+        self.set_tempo_ratio(target_bpm / grid.bpm);
+
+        let mut offset = other_grid.phase_at(other.position) - grid.phase_at(self.position);
+        if offset >= 0.5 {
+            offset -= 1.0;
+        } else if offset < -0.5 {
+            offset += 1.0;
+        }
+        if offset != 0.0 {
+            self.jump_to(self.position + offset * grid.frames_per_beat());
+        }
+        Ok(())
+    }
+
+    pub fn play(&mut self) {
+        self.apply(DeckCommand::Play);
+    }
+
+    pub fn pause(&mut self) {
+        self.apply(DeckCommand::Pause);
+    }
+
+    /// Move the playhead, declicking the jump if the deck is audible.
+    pub fn seek(&mut self, frame: u64) {
+        self.pending = None;
+        self.jump_to(frame as f64);
+    }
+
+    pub fn set_cue(&mut self, slot: usize, frame: u64) -> Result<(), DeckError> {
+        let track = self.track.as_mut().ok_or(DeckError::NoTrack)?;
+        if track.markers.hot_cues.set(slot, frame) {
+            Ok(())
+        } else {
+            Err(DeckError::InvalidCueSlot(slot))
+        }
+    }
+
+    pub fn clear_cue(&mut self, slot: usize) -> Result<(), DeckError> {
+        let track = self.track.as_mut().ok_or(DeckError::NoTrack)?;
+        if track.markers.hot_cues.clear(slot) {
+            Ok(())
+        } else {
+            Err(DeckError::InvalidCueSlot(slot))
+        }
+    }
+
+    /// Jump to the cue in `slot`, starting playback if stopped and exiting any loop.
+    ///
+    /// While quantized, the jump waits for the next grid boundary.
+    pub fn trigger_cue(&mut self, slot: usize) -> Result<(), DeckError> {
+        let cues = self.cues().ok_or(DeckError::NoTrack)?;
+        if slot >= HOT_CUE_COUNT {
+            return Err(DeckError::InvalidCueSlot(slot));
+        }
+        let frame = cues.get(slot).ok_or(DeckError::EmptyCueSlot(slot))?;
+        self.quantized(QuantizedAction::Cue { slot, frame });
+        Ok(())
+    }
+
+    /// Loop `start..end`; while quantized, from the next grid boundary.
+    pub fn enable_loop(&mut self, start: u64, end: u64) -> Result<(), DeckError> {
+        let track = self.track.as_ref().ok_or(DeckError::NoTrack)?;
+        let region = LoopRegion { start, end };
+        if region.is_empty() || end > track.frames() {
+            return Err(DeckError::InvalidLoop { start, end });
+        }
+        self.quantized(QuantizedAction::Loop(region));
+        Ok(())
+    }
+
+    pub fn exit_loop(&mut self) {
+        self.active_loop = None;
+        self.end_slip();
+    }
+
+    pub fn active_loop(&self) -> Option<LoopRegion> {
+        self.active_loop
+    }
+
+    pub fn loop_move(&self) -> bool {
+        self.loop_move
+    }
+
+    /// Let beat jumps move an active loop with the playhead rather than exit it.
+    pub fn set_loop_move(&mut self, loop_move: bool) {
+        self.loop_move = loop_move;
+    }
+
+    /// Jump `beats` beats in the playing direction, landing on a grid line.
+    ///
+    /// The jump counts from the grid line nearest the playhead. It exits an active loop, or
+    /// moves it by the same distance in loop move mode. Jumping before the start lands on the
+    /// first frame; jumping past the end ends playback.
+    pub fn beat_jump(&mut self, beats: i32) -> Result<(), DeckError> {
+        let track = self.track.as_ref().ok_or(DeckError::NoTrack)?;
+        let grid = *self.beat_grid().ok_or(DeckError::NoBeatGrid)?;
+        let frames = track.frames();
+        let beats = if self.reverse { -beats } else { beats };
+        self.pending = None;
+
+        if let (true, Some(region)) = (self.loop_move, self.active_loop) {
+            let shift = (beats as f64 * grid.frames_per_beat()).round() as i64;
+            let shift = shift.clamp(-(region.start as i64), frames as i64 - region.end as i64);
+            self.active_loop = Some(LoopRegion {
+                start: region.start.saturating_add_signed(shift),
+                end: region.end.saturating_add_signed(shift),
+            });
+            self.jump_to(self.position + shift as f64);
+            return Ok(());
+        }
+
+        let nearest = grid.beat_at(self.position).round() as i64;
+        let target = grid.frame_of_beat(nearest + beats as i64);
+        self.begin_slip();
+        self.active_loop = None;
+        self.jump_to(target);
+        if target >= frames as f64 && self.is_playing() {
+            self.transport = TransportState::Ended;
+            self.emit(DeckEvent::Ended);
+        }
+        Ok(())
+    }
+
+    fn jump_to(&mut self, position: f64) {
+        let Some(track) = &self.track else {
+            return;
+        };
+        let frames = track.frames() as f64;
+        self.start_declick(self.playback_rate());
+        self.position = position.clamp(0.0, frames);
+        self.stretcher.stop();
+        self.jog.jump(self.position);
+    }
+
+    /// Crossfade out of the current playhead, which carries on at `rate`, or in from silence.
+    fn start_declick(&mut self, rate: f64) {
+        let Some(track) = &self.track else {
+            return;
+        };
+        let length = ((track.sample_rate as f64 * DECLICK_SECONDS).round() as u32).max(1);
+        self.declick = Some(Declick {
+            from: self.is_playing().then_some(self.position),
+            rate,
+            elapsed: 0,
+            length,
+        });
+    }
+
+    /// Render interleaved stereo frames into `output`, advancing the playhead.
+    ///
+    /// Anything but [`TransportState::Playing`] renders silence, unless the jog is held.
+    pub fn render(&mut self, output: &mut [f32]) {
+        self.poll_load();
+        if self.track.is_none() {
+            output.fill(0.0);
+            self.position_state.publish(&DeckPosition::default());
+            return;
+        }
+
+        for out in output.chunks_exact_mut(2) {
+            if let Some(pending) = self.pending {
+                let forward = self.is_playing() && !self.reverse && !self.jog.is_active();
+                if forward && self.position >= pending.at_frame {
+                    self.fire(pending.action, self.position - pending.at_frame);
+                }
+            }
+            let Some(track) = &self.track else {
+                break;
+            };
+            let frames = track.frames() as f64;
+
+            let playing = self.is_playing();
+            let scratching = self.jog.is_active();
+            if !playing && !scratching {
+                out.fill(0.0);
+                continue;
+            }
+            if !scratching && !self.reverse && self.position >= frames {
+                if playing {
+                    self.transport = TransportState::Ended;
+                    self.emit(DeckEvent::Ended);
+                }
+                self.declick = None;
+                out.fill(0.0);
+                continue;
+            }
+
+            if let Some(ghost) = &mut self.ghost {
+                if playing {
+                    *ghost = (*ghost + self.tempo_ratio).min(frames);
+                }
+            }
+
+            let varispeed = track.read(self.position);
+            // Keylock only applies to forward playback; scratching and reverse stay varispeed.
+            let stretching = playing && !scratching && !self.reverse;
+            let mut frame = if stretching && (self.keylock || self.keylock_fade.is_some()) {
+                if !self.stretcher.is_primed() {
+                    self.stretcher.prime(track, self.position);
+                }
+                let stretched = self.stretcher.next(track, self.position);
+                match &mut self.keylock_fade {
+                    Some(fade) => {
+                        fade.elapsed += 1;
+                        let gain = fade.elapsed as f32 / fade.length as f32;
+                        if fade.elapsed >= fade.length {
+                            self.keylock_fade = None;
+                        }
+                        let (from, to) = if self.keylock {
+                            (varispeed, stretched)
+                        } else {
+                            (stretched, varispeed)
+                        };
+                        [
+                            from[0] * (1.0 - gain) + to[0] * gain,
+                            from[1] * (1.0 - gain) + to[1] * gain,
+                        ]
+                    }
+                    None => stretched,
+                }
+            } else {
+                self.stretcher.stop();
+                self.keylock_fade = None;
+                varispeed
+            };
+            if let Some(declick) = &mut self.declick {
+                declick.elapsed += 1;
+                let gain = declick.elapsed as f32 / declick.length as f32;
+                let previous = match &mut declick.from {
+                    Some(from) => {
+                        let previous = track.read(*from);
+                        *from += declick.rate;
+                        previous
+                    }
+                    None => [0.0, 0.0],
+                };
+                frame[0] = previous[0] * (1.0 - gain) + frame[0] * gain;
+                frame[1] = previous[1] * (1.0 - gain) + frame[1] * gain;
+                if declick.elapsed >= declick.length {
+                    self.declick = None;
+                }
+            }
+            out[0] = frame[0] * self.trim;
+            out[1] = frame[1] * self.trim;
+
+            if scratching {
+                let base = if playing { self.playback_rate() } else { 0.0 };
+                let rate = self.jog.next_rate(self.position, base);
+                self.position = (self.position + rate).clamp(0.0, frames);
+                continue;
+            }
+            if !self.reverse {
+                self.position += self.tempo_ratio;
+                if let Some(region) = self.active_loop {
+                    if self.position >= region.end as f64 {
+                        self.position -= region.len() as f64;
+                    }
+                }
+                continue;
+            }
+
+            let previous = self.position;
+            self.position -= self.tempo_ratio;
+            if let Some(region) = self.active_loop {
+                // Wrap from the in-point back to the out-point, but only once inside the loop.
+                let start = region.start as f64;
+                if previous >= start && self.position < start {
+                    self.position += region.len() as f64;
+                }
+            }
+            if self.position < 0.0 {
+                match self.reverse_end {
+                    ReverseEnd::Stop => {
+                        self.position = 0.0;
+                        self.transport = TransportState::Paused;
+                        self.declick = None;
+                    }
+                    ReverseEnd::Bounce => {
+                        self.position = -self.position;
+                        self.reverse = false;
+                    }
+                }
+            }
+        }
+        self.position_state.publish(&self.position_report());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp_track(frames: usize) -> Track {
+        let samples: Vec<f32> = (0..frames).flat_map(|i| [i as f32, -(i as f32)]).collect();
+        Track::from_interleaved(samples, 48_000)
+    }
+
+    #[test]
+    fn trigger_jumps_with_declick_fade() {
+        let mut deck = Deck::new();
+        deck.load(ramp_track(4_000));
+        deck.set_cue(0, 2_000).unwrap();
+        deck.play();
+
+        let mut out = vec![0.0; 512];
+        deck.render(&mut out);
+        assert_eq!(deck.position(), 256.0);
+
+        deck.enable_loop(0, 1_000).unwrap();
+        deck.trigger_cue(0).unwrap();
+        assert_eq!(deck.active_loop(), None);
+        deck.render(&mut out);
+
+        let fade = 144;
+        for (i, frame) in out.chunks_exact(2).enumerate() {
+            let old = 256.0 + i as f32;
+            let new = 2_000.0 + i as f32;
+            let expected = if i < fade {
+                let gain = (i + 1) as f32 / fade as f32;
+                old * (1.0 - gain) + new * gain
+            } else {
+                new
+            };
+            assert!((frame[0] - expected).abs() < 1e-2, "frame {i}");
+            assert!((frame[1] + expected).abs() < 1e-2, "frame {i}");
+        }
+    }
+
+    #[test]
+    fn jog_scratches_forward_and_back_then_resumes() {
+        let mut deck = Deck::new();
+        deck.load(ramp_track(48_000));
+        deck.seek(10_000);
+        deck.play();
+        let mut out = vec![0.0; 960];
+        deck.render(&mut out);
+
+        // Grabbing the moving platter coasts one follow time further, then holds.
+        deck.apply(DeckCommand::JogTouch(true));
+        assert!(deck.is_scratching());
+        for _ in 0..10 {
+            deck.render(&mut out);
+        }
+        let held = deck.position();
+        assert!((held - 11_440.0).abs() < 1.0, "{held}");
+        assert!(out.iter().step_by(2).all(|s| (s - held as f32).abs() < 1.0));
+
+        // Forward at normal speed, stop, then backwards at double speed.
+        let mut previous = held;
+        for block in 0..40 {
+            let delta = match block {
+                0..=19 => 480.0,
+                20..=29 => 0.0,
+                _ => -960.0,
+            };
+            deck.apply(DeckCommand::JogTick {
+                delta_frames: delta,
+            });
+            deck.render(&mut out);
+            let position = deck.position();
+            match block {
+                0..=29 => assert!(position >= previous),
+                _ => assert!(position <= previous),
+            }
+            if block == 29 {
+                assert!((position - (held + 9_600.0)).abs() < 1.0);
+            }
+            let lowest = (held - 1.0) as f32;
+            let highest = (held + 9_601.0) as f32;
+            assert!(out.iter().step_by(2).all(|s| (lowest..highest).contains(s)));
+            previous = position;
+        }
+        for _ in 0..10 {
+            deck.render(&mut out);
+        }
+        assert!((deck.position() - held).abs() < 1.0);
+
+        // Letting go spins back up to the base rate and then plays normally.
+        deck.apply(DeckCommand::JogTouch(false));
+        assert!(!deck.is_scratching());
+        let mut spin_up = vec![0.0; 2 * 5_000];
+        deck.render(&mut spin_up);
+        let resumed = deck.position();
+        deck.render(&mut out);
+        assert!((deck.position() - resumed - 480.0).abs() < 1e-6);
+        for (i, frame) in out.chunks_exact(2).enumerate() {
+            assert!((frame[0] - (resumed as f32 + i as f32)).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn reverse_walks_back_from_the_flip_point_and_wraps_loops() {
+        let mut deck = Deck::new();
+        deck.load(ramp_track(4_000));
+        deck.seek(1_000);
+        deck.play();
+        let mut out = vec![0.0; 2 * 300];
+        deck.render(&mut out);
+
+        // Flip partway through what would be one device block.
+        deck.render(&mut out[..2 * 100]);
+        deck.apply(DeckCommand::SetReverse(true));
+        assert!(deck.is_reverse());
+        deck.render(&mut out[2 * 100..]);
+        let flip = 1_400.0;
+        let fade = 144;
+        for (i, frame) in out[2 * 100..].chunks_exact(2).enumerate() {
+            let forward = flip + i as f32;
+            let backward = flip - i as f32;
+            let expected = if i < fade {
+                let gain = (i + 1) as f32 / fade as f32;
+                forward * (1.0 - gain) + backward * gain
+            } else {
+                backward
+            };
+            assert!((frame[0] - expected).abs() < 1e-2, "frame {i}");
+        }
+
+        // Inside a loop, passing the in-point continues from the out-point.
+        deck.enable_loop(1_000, 1_100).unwrap();
+        deck.render(&mut out);
+        let walked: Vec<f32> = out.iter().step_by(2).copied().collect();
+        let expected: Vec<f32> = (0..300)
+            .map(|i| if i <= 200 { 1_200 - i } else { 1_099 - (i - 201) } as f32)
+            .collect();
+        assert_eq!(walked, expected);
+
+        // Without a loop the start of the track stops playback, or bounces.
+        deck.exit_loop();
+        deck.render(&mut vec![0.0; 2 * 2_000]);
+        assert!(!deck.is_playing());
+        assert_eq!(deck.position(), 0.0);
+        deck.set_reverse_end(ReverseEnd::Bounce);
+        deck.seek(10);
+        deck.play();
+        deck.render(&mut vec![0.0; 2 * 30]);
+        assert!(!deck.is_reverse());
+        assert_eq!(deck.position(), 20.0);
+    }
+
+    #[test]
+    fn slip_loop_returns_to_where_the_track_would_be() {
+        let mut deck = Deck::new();
+        deck.load(ramp_track(48_000));
+        deck.set_slip(true);
+        deck.seek(1_000);
+        deck.play();
+        let mut out = vec![0.0; 2 * 250];
+        deck.render(&mut out);
+        assert_eq!(deck.slip_position(), None);
+
+        let original = deck.position();
+        deck.enable_loop(1_200, 1_300).unwrap();
+        let looped = 1_000;
+        for _ in 0..looped / 250 {
+            deck.render(&mut out);
+        }
+        assert!(deck.position() < 1_300.0);
+        assert_eq!(deck.slip_position(), Some(original + looped as f64));
+
+        deck.exit_loop();
+        assert_eq!(deck.slip_position(), None);
+        assert_eq!(deck.position(), original + looped as f64);
+        deck.render(&mut out);
+        let resumed = (original + looped as f64) as f32;
+        for (i, frame) in out.chunks_exact(2).enumerate().skip(144) {
+            assert_eq!(frame[0], resumed + i as f32);
+        }
+
+        // Reverse slips the same way, and turning slip off returns as well.
+        deck.apply(DeckCommand::SetReverse(true));
+        deck.render(&mut out);
+        deck.set_slip(false);
+        assert!(!deck.is_reverse());
+        assert_eq!(deck.position(), resumed as f64 + 500.0);
+    }
+
+    #[test]
+    fn quantized_cue_jumps_exactly_on_the_next_beat() {
+        let mut deck = Deck::new();
+        deck.load(ramp_track(96_000));
+        deck.set_beat_grid(BeatGrid::new(120.0, 1_000.0, 48_000))
+            .unwrap();
+        deck.set_cue(0, 60_000).unwrap();
+        deck.set_quantize(Quantize::Beat);
+        deck.seek(10_000);
+        deck.play();
+        let mut out = vec![0.0; 2 * 999];
+        deck.render(&mut out);
+
+        deck.trigger_cue(0).unwrap();
+        let pending = deck.pending_trigger().unwrap();
+        assert_eq!(pending.at_frame, 25_000.0);
+        assert_eq!(
+            pending.action,
+            QuantizedAction::Cue {
+                slot: 0,
+                frame: 60_000
+            }
+        );
+        for _ in 0..14 {
+            deck.render(&mut out);
+        }
+        deck.render(&mut out[..2 * 15]);
+        assert_eq!(deck.position(), 25_000.0);
+        assert!(deck.pending_trigger().is_some());
+
+        let mut frame = [0.0; 2];
+        deck.render(&mut frame);
+        assert_eq!(deck.position(), 60_001.0);
+        assert!(deck.pending_trigger().is_none());
+        let gain = 1.0 / 144.0;
+        assert!((frame[0] - (25_000.0 * (1.0 - gain) + 60_000.0 * gain)).abs() < 1e-2);
+
+        // Just after a boundary fires at once, keeping the offset; later waits and can be canceled.
+        deck.seek(49_500);
+        deck.trigger_cue(0).unwrap();
+        assert_eq!(deck.position(), 60_500.0);
+        deck.seek(51_000);
+        deck.trigger_cue(0).unwrap();
+        assert!(deck.cancel_pending());
+        deck.render(&mut vec![0.0; 2 * 24_000]);
+        assert_eq!(deck.position(), 75_000.0);
+    }
+
+    #[test]
+    fn position_reports_wall_clock_time_at_tempo() {
+        for ratio in [1.0, 1.06] {
+            let mut deck = Deck::new();
+            deck.load(Track::from_interleaved(vec![0.0; 2 * 48_000 * 40], 48_000));
+            deck.set_tempo_ratio(ratio);
+            deck.play();
+            let reader = deck.position_reader();
+            // Fake wall clock advanced by 10 ms blocks.
+            let render = |deck: &mut Deck, blocks: usize| {
+                let mut out = [0.0; 2 * 480];
+                for _ in 0..blocks {
+                    deck.render(&mut out);
+                }
+                blocks as f64 * 0.01
+            };
+
+            let clock = render(&mut deck, 100);
+            let position = reader.read();
+            assert!((position.seconds - clock).abs() < 1e-9, "{ratio}");
+            assert!((position.remaining_seconds - (40.0 / ratio - clock)).abs() < 1e-9);
+            assert!((position.fraction - ratio / 40.0).abs() < 1e-9);
+            assert!(!position.end_warning);
+
+            // A seek shows up after one block.
+            deck.seek(48_000 * 20);
+            let clock = render(&mut deck, 1);
+            let position = reader.read();
+            assert!((position.seconds - (20.0 / ratio + clock)).abs() < 1e-9);
+            assert!(position.end_warning);
+
+            // Inside a loop the time keeps cycling over the loop.
+            deck.enable_loop(48_000 * 20, 48_000 * 21).unwrap();
+            for _ in 0..300 {
+                render(&mut deck, 1);
+                let seconds = reader.read().seconds;
+                assert!((20.0 / ratio..21.0 / ratio).contains(&seconds), "{seconds}");
+            }
+            assert_eq!(reader.read(), deck.position_report());
+        }
+    }
+
+    #[test]
+    fn transport_follows_the_transition_table() {
+        use TransportState::*;
+        let error = Error("bad file".into());
+        let commands = [
+            DeckCommand::Play,
+            DeckCommand::Pause,
+            DeckCommand::Stop,
+            DeckCommand::Eject,
+        ];
+        // Next state for Play, Pause, Stop and Eject from each state.
+        let table = [
+            (Empty, [None, None, None, None]),
+            (Loading, [None, None, None, Some(Empty)]),
+            (Loaded, [Some(Playing), None, None, Some(Empty)]),
+            (Playing, [None, Some(Paused), Some(Loaded), None]),
+            (Paused, [Some(Playing), None, Some(Loaded), Some(Empty)]),
+            (Ended, [None, None, Some(Loaded), Some(Empty)]),
+            (error.clone(), [None, None, None, Some(Empty)]),
+        ];
+        for (state, expected) in &table {
+            for (command, next) in commands.iter().zip(expected) {
+                assert_eq!(state.after(*command), *next, "{state:?} {command:?}");
+            }
+            assert_eq!(
+                state.after(DeckCommand::JogTouch(false)),
+                Some(state.clone())
+            );
+            assert_eq!(state.can_load(), *state != Playing);
+        }
+
+        // A deck runs through the same table and reports what it ignored.
+        let mut deck = Deck::new();
+        let events = deck.events();
+        deck.play();
+        assert_eq!(
+            events.poll(),
+            Some(DeckEvent::Rejected {
+                command: DeckCommand::Play,
+                state: Empty
+            })
+        );
+        deck.load(
+            Track::from_interleaved(vec![0.0; 2 * 4_800], 48_000).with_markers(TrackMarkers {
+                beat_grid: Some(BeatGrid::new(128.0, 0.0, 48_000)),
+                ..TrackMarkers::default()
+            }),
+        );
+        assert_eq!(
+            events.poll(),
+            Some(DeckEvent::TrackLoaded {
+                duration_seconds: 0.1,
+                bpm: Some(128.0)
+            })
+        );
+        deck.play();
+        assert_eq!(events.poll(), Some(DeckEvent::Started));
+        deck.load(Track::from_interleaved(vec![0.0; 2], 48_000));
+        assert_eq!(events.poll(), Some(DeckEvent::LoadRejected(Playing)));
+        deck.render(&mut vec![0.0; 2 * 5_000]);
+        assert_eq!(events.poll(), Some(DeckEvent::Ended));
+        assert_eq!(*deck.transport(), Ended);
+        deck.apply(DeckCommand::Stop);
+        assert_eq!((deck.transport(), deck.position()), (&Loaded, 0.0));
+        deck.apply(DeckCommand::Eject);
+        assert_eq!(*deck.transport(), Empty);
+        assert!(deck.track().is_none());
+        assert_eq!(events.poll(), None);
+    }
+
+    #[test]
+    fn background_load_reports_loading_then_loaded_or_failed() {
+        let mut deck = Deck::new();
+        let events = deck.events();
+        let (release, gate) = mpsc::channel::<()>();
+        deck.load_with(move || {
+            gate.recv().unwrap();
+            Ok(Track::from_interleaved(vec![0.5; 2 * 480], 48_000))
+        });
+        assert_eq!(*deck.transport(), TransportState::Loading);
+        assert!(!deck.poll_load());
+        release.send(()).unwrap();
+        while !deck.poll_load() {
+            thread::yield_now();
+        }
+        assert_eq!(*deck.transport(), TransportState::Loaded);
+        assert!(matches!(events.poll(), Some(DeckEvent::TrackLoaded { .. })));
+
+        deck.load_with(|| Err("unsupported format".into()));
+        assert!(deck.track().is_none());
+        while !deck.poll_load() {
+            thread::yield_now();
+        }
+        let reason = "unsupported format".to_string();
+        assert_eq!(*deck.transport(), TransportState::Error(reason.clone()));
+        assert_eq!(events.poll(), Some(DeckEvent::LoadFailed(reason)));
+    }
+
+    #[test]
+    fn only_playing_decks_make_sound() {
+        let tone = || Track::from_interleaved(vec![0.5; 2 * 48_000], 48_000);
+        let mut deck = Deck::new();
+        let mut out = vec![1.0; 2 * 256];
+        let mut assert_silent = |deck: &mut Deck| {
+            out.fill(1.0);
+            deck.render(&mut out);
+            assert!(out.iter().all(|s| *s == 0.0), "{:?}", deck.transport());
+        };
+
+        assert_silent(&mut deck);
+        let (_hold, gate) = mpsc::channel::<()>();
+        deck.load_with(move || {
+            let _ = gate.recv();
+            Ok(tone())
+        });
+        assert_silent(&mut deck);
+        deck.load(tone());
+        assert_silent(&mut deck);
+        deck.play();
+        let mut playing = vec![0.0; 2 * 256];
+        deck.render(&mut playing);
+        assert!(playing[2 * 200..].iter().all(|s| *s == 0.5));
+        deck.pause();
+        assert_silent(&mut deck);
+        deck.seek(47_900);
+        deck.play();
+        deck.render(&mut vec![0.0; 2 * 200]);
+        assert_eq!(*deck.transport(), TransportState::Ended);
+        assert_silent(&mut deck);
+        deck.load_with(|| Err("missing".into()));
+        while !deck.poll_load() {
+            thread::yield_now();
+        }
+        assert_silent(&mut deck);
+    }
+
+    #[test]
+    fn trigger_starts_stopped_deck_and_cues_survive_seek() {
+        let mut deck = Deck::new();
+        deck.load(ramp_track(1_000));
+        deck.set_cue(3, 500).unwrap();
+        deck.seek(100);
+        deck.pause();
+        assert_eq!(deck.cues().unwrap().get(3), Some(500));
+
+        deck.trigger_cue(3).unwrap();
+        assert!(deck.is_playing());
+        assert_eq!(deck.trigger_cue(4), Err(DeckError::EmptyCueSlot(4)));
+        assert_eq!(
+            deck.set_cue(HOT_CUE_COUNT, 0),
+            Err(DeckError::InvalidCueSlot(8))
+        );
+    }
+
+    #[test]
+    fn sync_aligns_beats_over_many_bars() {
+        let sample_rate = 44_100;
+        let silent = || Track::from_interleaved(vec![0.0; 44_100 * 2 * 90], sample_rate);
+
+        let mut master = Deck::new();
+        master.load(silent());
+        master
+            .set_beat_grid(BeatGrid::new(128.0, 1_234.0, sample_rate))
+            .unwrap();
+        master.set_tempo_ratio(1.02);
+        master.seek(50_000);
+        master.play();
+
+        let mut follower = Deck::new();
+        follower.load(silent());
+        follower
+            .set_beat_grid(BeatGrid::new(125.0, 7_000.0, sample_rate))
+            .unwrap();
+        follower.seek(80_000);
+        follower.play();
+
+        follower.sync_to(&master).unwrap();
+        assert!((follower.effective_bpm().unwrap() - 128.0 * 1.02).abs() < 1e-9);
+
+        // 2,500 blocks of 512 frames is about 16 bars at ~130 BPM.
+        let mut block = vec![0.0; 1_024];
+        for i in 0..2_500 {
+            master.render(&mut block);
+            follower.render(&mut block);
+            if i % 100 == 0 {
+                let master_grid = master.beat_grid().unwrap();
+                let follower_grid = follower.beat_grid().unwrap();
+                let mut diff = master_grid.phase_at(master.position())
+                    - follower_grid.phase_at(follower.position());
+                diff -= diff.round();
+                let frames = diff.abs() * follower_grid.frames_per_beat();
+                assert!(frames < 1.0, "block {i}: beats {frames} frames apart");
+            }
+        }
+    }
+
+    #[test]
+    fn cues_round_trip_through_serde() {
+        let mut deck = Deck::new();
+        deck.load(ramp_track(1_000));
+        deck.set_cue(0, 10).unwrap();
+        deck.set_cue(7, 900).unwrap();
+        deck.clear_cue(0).unwrap();
+
+        let json = serde_json::to_string(deck.markers().unwrap()).unwrap();
+        let markers: TrackMarkers = serde_json::from_str(&json).unwrap();
+
+        let mut restored = Deck::new();
+        restored.load(ramp_track(1_000).with_markers(markers));
+        assert_eq!(restored.cues(), deck.cues());
+        assert_eq!(restored.cues().unwrap().get(7), Some(900));
+        assert_eq!(restored.cues().unwrap().get(0), None);
+    }
+
+    #[test]
+    fn auto_gain_levels_tracks_through_the_mix() {
+        use crate::analysis::gain::analyze_gain;
+        use crate::{parameter_channel, ParameterUpdate, SummingBus};
+
+        let analysed = |dbfs: f32| {
+            let amplitude = 10f32.powf(dbfs / 20.0);
+            let samples: Vec<f32> = (0..48_000 * 5)
+                .flat_map(|i| {
+                    let s = amplitude * (i as f32 / 48.0 * std::f32::consts::TAU).sin();
+                    [s, s]
+                })
+                .collect();
+            let gain = analyze_gain(samples.iter().copied(), 2, 48_000);
+            Track::from_interleaved(samples, 48_000).with_markers(TrackMarkers {
+                gain,
+                ..TrackMarkers::default()
+            })
+        };
+
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let mut rms_through_bus = |deck: &mut Deck, crossfader: f32| {
+            tx.send(ParameterUpdate::Crossfader(crossfader)).unwrap();
+            let mut rendered = vec![0.0; 9_600];
+            deck.render(&mut rendered);
+            let (a, b) = if crossfader == 0.0 {
+                (rendered, vec![0.0; 9_600])
+            } else {
+                (vec![0.0; 9_600], rendered)
+            };
+            let mut out = vec![0.0; 9_600];
+            bus.mix_stereo(&a, &b, &mut out);
+            (out.iter().map(|s| s * s).sum::<f32>() / out.len() as f32).sqrt()
+        };
+
+        let mut quiet = Deck::new();
+        let mut loud = Deck::new();
+        quiet.set_auto_gain(true);
+        loud.set_auto_gain(true);
+        quiet.load(analysed(-23.0));
+        loud.load(analysed(-13.0));
+        assert!((quiet.trim_db() - loud.trim_db() - 10.0).abs() < 0.1);
+        quiet.play();
+        loud.play();
+        let ratio_db =
+            20.0 * (rms_through_bus(&mut quiet, 0.0) / rms_through_bus(&mut loud, 1.0)).log10();
+        assert!(ratio_db.abs() < 0.1, "{ratio_db} dB apart");
+
+        // Very quiet material is only lifted as far as the clamp allows.
+        quiet.pause();
+        quiet.load(analysed(-50.0));
+        assert!((quiet.trim_db() - MAX_AUTO_TRIM_DB).abs() < 1e-4);
+        quiet.set_auto_gain(false);
+        quiet.load(analysed(-13.0));
+        assert!((quiet.trim_db() - MAX_AUTO_TRIM_DB).abs() < 1e-4);
+    }
+
+    fn sine_track(hz: f32, seconds: f32) -> Track {
+        let frames = (48_000.0 * seconds) as usize;
+        let samples: Vec<f32> = (0..frames)
+            .flat_map(|i| {
+                let sample = 0.5 * (std::f32::consts::TAU * hz * i as f32 / 48_000.0).sin();
+                [sample, sample]
+            })
+            .collect();
+        Track::from_interleaved(samples, 48_000)
+    }
+
+    /// Frequency from the spacing of the first and last rising zero crossings.
+    fn frequency(left: &[f32]) -> f64 {
+        let crossings: Vec<f64> = left
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+            .map(|(i, pair)| i as f64 + (-pair[0] / (pair[1] - pair[0])) as f64)
+            .collect();
+        let span = crossings[crossings.len() - 1] - crossings[0];
+        (crossings.len() - 1) as f64 * 48_000.0 / span
+    }
+
+    #[test]
+    fn keylock_holds_pitch_while_tempo_changes_duration() {
+        for (keylock, expected_hz) in [(false, 440.0 * 1.05), (true, 440.0)] {
+            let mut deck = Deck::new();
+            deck.load(sine_track(440.0, 1.0));
+            deck.set_tempo_ratio(1.05);
+            deck.apply(DeckCommand::SetKeylock(keylock));
+            deck.play();
+            assert_eq!(
+                deck.position_report().pitch,
+                if keylock { 1.0 } else { 1.05 }
+            );
+
+            let mut left = Vec::new();
+            let mut out = [0.0; 2 * 480];
+            while deck.transport() == &TransportState::Playing {
+                deck.render(&mut out);
+                left.extend(out.iter().step_by(2));
+            }
+            // Duration follows the tempo either way: 48 000 frames at 1.05 is 45 715 frames.
+            let played = left.iter().rposition(|s| *s != 0.0).unwrap() + 1;
+            assert!((played as i64 - 45_715).abs() < 480, "{played}");
+
+            let cents = 1200.0 * (frequency(&left[4_800..40_000]) / expected_hz).log2();
+            assert!(cents.abs() < 3.0, "keylock {keylock}: {cents} cents");
+        }
+    }
+
+    #[test]
+    fn keylock_toggle_keeps_position_and_does_not_click() {
+        let mut reference = Deck::new();
+        let mut deck = Deck::new();
+        for deck in [&mut reference, &mut deck] {
+            deck.load(sine_track(220.0, 2.0));
+            deck.set_tempo_ratio(0.93);
+            deck.play();
+        }
+        let mut out = [0.0; 2 * 256];
+        let mut previous = 0.0;
+        // Largest step of a 0.5 amplitude 220 Hz sine is about 0.0144.
+        let mut largest_step: f32 = 0.0;
+        for block in 0..200 {
+            if block % 25 == 10 {
+                deck.apply(DeckCommand::SetKeylock(!deck.is_keylock()));
+            }
+            reference.render(&mut out);
+            deck.render(&mut out);
+            assert_eq!(deck.position(), reference.position(), "block {block}");
+            for sample in out.iter().step_by(2) {
+                largest_step = largest_step.max((sample - previous).abs());
+                previous = *sample;
+            }
+        }
+        assert!(largest_step < 0.03, "{largest_step}");
+    }
+
+    fn gridded_deck(ratio: f64) -> Deck {
+        let mut deck = Deck::new();
+        // 60 seconds at 120 BPM: 24 000 frames a beat, beat zero at frame 1 000.
+        deck.load(Track::from_interleaved(vec![0.0; 2 * 48_000 * 60], 48_000));
+        deck.set_beat_grid(BeatGrid::new(120.0, 1_000.0, 48_000))
+            .unwrap();
+        deck.set_tempo_ratio(ratio);
+        deck
+    }
+
+    #[test]
+    fn beat_jumps_land_on_the_grid() {
+        for ratio in [1.0, 1.12] {
+            let mut deck = gridded_deck(ratio);
+            let grid = *deck.beat_grid().unwrap();
+            deck.seek(grid.frame_of_beat(20) as u64 + 300);
+            deck.play();
+            let mut out = [0.0; 2 * 480];
+            for (beats, landed) in [(4, 24), (-16, 8), (16, 24), (-4, 20)] {
+                deck.render(&mut out);
+                deck.apply(DeckCommand::BeatJump { beats });
+                assert_eq!(
+                    deck.position(),
+                    grid.frame_of_beat(landed),
+                    "{ratio} {beats}"
+                );
+            }
+
+            // Reverse playback jumps the other way through the track.
+            deck.apply(DeckCommand::SetReverse(true));
+            deck.apply(DeckCommand::BeatJump { beats: 4 });
+            assert_eq!(deck.position(), grid.frame_of_beat(16), "{ratio}");
+        }
+    }
+
+    #[test]
+    fn beat_jumps_clamp_at_the_start_and_end_at_the_end() {
+        let mut deck = gridded_deck(1.0);
+        let grid = *deck.beat_grid().unwrap();
+        deck.seek(grid.frame_of_beat(2) as u64);
+        deck.play();
+        deck.apply(DeckCommand::BeatJump { beats: -16 });
+        assert_eq!(deck.position(), 0.0);
+        assert!(deck.is_playing());
+
+        deck.seek(grid.frame_of_beat(110) as u64);
+        deck.apply(DeckCommand::BeatJump { beats: 16 });
+        assert_eq!(deck.position(), (48_000 * 60) as f64);
+        assert_eq!(deck.transport(), &TransportState::Ended);
+    }
+
+    #[test]
+    fn beat_jumps_exit_loops_unless_loop_move_is_on() {
+        let mut deck = gridded_deck(1.0);
+        let grid = *deck.beat_grid().unwrap();
+        let (start, end) = (grid.frame_of_beat(20) as u64, grid.frame_of_beat(24) as u64);
+        deck.seek(start);
+        deck.play();
+        deck.enable_loop(start, end).unwrap();
+        deck.apply(DeckCommand::BeatJump { beats: 8 });
+        assert_eq!(deck.active_loop(), None);
+        assert_eq!(deck.position(), grid.frame_of_beat(28));
+
+        deck.seek(start + 1_000);
+        deck.enable_loop(start, end).unwrap();
+        deck.set_loop_move(true);
+        deck.apply(DeckCommand::BeatJump { beats: 4 });
+        assert_eq!(
+            deck.active_loop(),
+            Some(LoopRegion {
+                start: end,
+                end: grid.frame_of_beat(28) as u64,
+            })
+        );
+        assert_eq!(deck.position(), (end + 1_000) as f64);
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of hot cue slots available on every deck.
+pub const HOT_CUE_COUNT: usize = 8;
+
+/// Fixed bank of hot cue points, stored as frame offsets into the track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HotCues {
+    slots: [Option<u64>; HOT_CUE_COUNT],
+}
+
+impl HotCues {
+    /// Frame stored in `slot`, if any.
+    pub fn get(&self, slot: usize) -> Option<u64> {
+        self.slots.get(slot).copied().flatten()
+    }
+
+    /// Store `frame` in `slot`. Returns `false` if the slot does not exist.
+    pub fn set(&mut self, slot: usize, frame: u64) -> bool {
+        match self.slots.get_mut(slot) {
+            Some(entry) => {
+                *entry = Some(frame);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the cue in `slot`. Returns `false` if the slot does not exist.
+    pub fn clear(&mut self, slot: usize) -> bool {
+        match self.slots.get_mut(slot) {
+            Some(entry) => {
+                *entry = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Iterate over all slots in order, including empty ones.
+    pub fn iter(&self) -> impl Iterator<Item = Option<u64>> + '_ {
+        self.slots.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HotCues, HOT_CUE_COUNT};
+
+    #[test]
+    fn rejects_out_of_range_slots() {
+        let mut cues = HotCues::default();
+        assert!(cues.set(HOT_CUE_COUNT - 1, 10));
+        assert!(!cues.set(HOT_CUE_COUNT, 10));
+        assert!(!cues.clear(HOT_CUE_COUNT));
+        assert_eq!(cues.get(HOT_CUE_COUNT - 1), Some(10));
+        assert_eq!(cues.get(HOT_CUE_COUNT), None);
+    }
+}
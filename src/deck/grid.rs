@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::bpm::BpmEstimate;
+
+/// Constant-tempo beat grid anchored to a frame of the track.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BeatGrid {
+    /// Tempo of the track at its original speed.
+    pub bpm: f64,
+    /// Track frame of beat zero.
+    pub anchor_frame: f64,
+    pub sample_rate: u32,
+}
+
+impl BeatGrid {
+    pub fn new(bpm: f64, anchor_frame: f64, sample_rate: u32) -> Self {
+        Self {
+            bpm,
+            anchor_frame,
+            sample_rate,
+        }
+    }
+
+    /// Build a grid from tempo analysis of a track sampled at `sample_rate`.
+    pub fn from_estimate(estimate: &BpmEstimate, sample_rate: u32) -> Self {
+        Self::new(
+            estimate.bpm,
+            estimate.first_beat_secs * sample_rate as f64,
+            sample_rate,
+        )
+    }
+
+    /// Track frames between two beats.
+    pub fn frames_per_beat(&self) -> f64 {
+        60.0 * self.sample_rate as f64 / self.bpm
+    }
+
+    /// Fractional beat index at `frame`; negative before the anchor.
+    pub fn beat_at(&self, frame: f64) -> f64 {
+        (frame - self.anchor_frame) / self.frames_per_beat()
+    }
+
+    pub fn frame_of_beat(&self, beat: i64) -> f64 {
+        self.anchor_frame + beat as f64 * self.frames_per_beat()
+    }
+
+    /// Frame of the first beat strictly after `frame`.
+    pub fn next_beat_after(&self, frame: f64) -> f64 {
+        self.frame_of_beat(self.beat_at(frame).floor() as i64 + 1)
+    }
+
+    /// Frame of the last multiple of `beats` from the anchor at or before `frame`.
+    pub fn division_at_or_before(&self, frame: f64, beats: f64) -> f64 {
+        let length = self.frames_per_beat() * beats;
+        self.anchor_frame + ((frame - self.anchor_frame) / length).floor() * length
+    }
+
+    /// Position within the current beat in [0, 1).
+    pub fn phase_at(&self, frame: f64) -> f64 {
+        self.beat_at(frame).rem_euclid(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BeatGrid;
+
+    #[test]
+    fn looks_up_beats_around_anchor() {
+        let grid = BeatGrid::new(120.0, 1_000.0, 48_000);
+        assert_eq!(grid.frames_per_beat(), 24_000.0);
+        assert_eq!(grid.frame_of_beat(2), 49_000.0);
+        assert_eq!(grid.frame_of_beat(-1), -23_000.0);
+        assert_eq!(grid.beat_at(13_000.0), 0.5);
+        assert_eq!(grid.next_beat_after(1_000.0), 25_000.0);
+        assert_eq!(grid.next_beat_after(0.0), 1_000.0);
+    }
+}
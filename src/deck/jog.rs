@@ -0,0 +1,115 @@
+/// Time for the playhead to catch up with the platter while it is touched.
+const FOLLOW_SECONDS: f64 = 0.02;
+/// Fastest the hand can drive the playhead, in either direction.
+pub const MAX_SCRATCH_RATE: f64 = 8.0;
+/// Default time to get from standstill to normal speed after letting go.
+pub const DEFAULT_SPIN_UP_SECONDS: f64 = 0.1;
+
+/// Platter state that takes the playhead over from normal playback while scratching.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Jog {
+    touched: bool,
+    /// Still gliding back to the base rate after release.
+    spinning_up: bool,
+    /// Where the hand has moved the record to, in track frames.
+    hand: f64,
+    /// Playhead speed in frames per output frame; negative plays backwards.
+    rate: f64,
+    spin_up_seconds: f64,
+    sample_rate: f64,
+    follow_frames: f64,
+    /// Rate smoothing coefficient; a quarter of the follow time keeps the chase critically damped.
+    smoothing: f64,
+    spin_up_step: f64,
+}
+
+impl Default for Jog {
+    fn default() -> Self {
+        Self {
+            touched: false,
+            spinning_up: false,
+            hand: 0.0,
+            rate: 0.0,
+            spin_up_seconds: DEFAULT_SPIN_UP_SECONDS,
+            sample_rate: 48_000.0,
+            follow_frames: 1.0,
+            smoothing: 1.0,
+            spin_up_step: f64::INFINITY,
+        }
+    }
+}
+
+impl Jog {
+    /// Whether the jog rather than the deck's tempo drives the playhead.
+    pub(crate) fn is_active(&self) -> bool {
+        self.touched || self.spinning_up
+    }
+
+    pub(crate) fn is_touched(&self) -> bool {
+        self.touched
+    }
+
+    pub(crate) fn spin_up_seconds(&self) -> f64 {
+        self.spin_up_seconds
+    }
+
+    pub(crate) fn set_spin_up_seconds(&mut self, seconds: f64) {
+        self.spin_up_seconds = seconds.max(0.0);
+    }
+
+    /// Grab the platter at `position` while the playhead moves at `rate`.
+    pub(crate) fn touch(&mut self, position: f64, rate: f64, sample_rate: u32) {
+        self.sample_rate = sample_rate as f64;
+        self.follow_frames = (FOLLOW_SECONDS * self.sample_rate).max(1.0);
+        self.smoothing = 1.0 - (-4.0 / self.follow_frames).exp();
+        if !self.is_active() {
+            self.rate = rate;
+        }
+        self.touched = true;
+        self.spinning_up = false;
+        // Where a hand already moving at this rate would be, so grabbing a moving platter is seamless.
+        self.hand = position + self.rate * self.follow_frames;
+    }
+
+    pub(crate) fn release(&mut self) {
+        if self.touched {
+            self.touched = false;
+            self.spinning_up = true;
+            self.spin_up_step = 1.0 / (self.spin_up_seconds * self.sample_rate);
+        }
+    }
+
+    /// Let go without spinning up, e.g. when the track is replaced.
+    pub(crate) fn reset(&mut self) {
+        self.touched = false;
+        self.spinning_up = false;
+        self.rate = 0.0;
+    }
+
+    /// Move the record by `delta_frames` of track; ignored unless touched.
+    pub(crate) fn tick(&mut self, delta_frames: f64) {
+        if self.touched {
+            self.hand += delta_frames;
+        }
+    }
+
+    /// Keep the hand with the playhead when it jumps while held.
+    pub(crate) fn jump(&mut self, position: f64) {
+        self.hand = position;
+    }
+
+    /// Playhead advance for the next frame, `base` being the speed to return to on release.
+    pub(crate) fn next_rate(&mut self, position: f64, base: f64) -> f64 {
+        if self.touched {
+            let target = ((self.hand - position) / self.follow_frames)
+                .clamp(-MAX_SCRATCH_RATE, MAX_SCRATCH_RATE);
+            self.rate += (target - self.rate) * self.smoothing;
+        } else if (base - self.rate).abs() <= self.spin_up_step {
+            self.rate = base;
+            self.spinning_up = false;
+        } else {
+            self.rate += self.spin_up_step.copysign(base - self.rate);
+        }
+        self.rate
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Remaining time below which [`DeckPosition::end_warning`] is raised.
+pub const DEFAULT_END_WARNING_SECONDS: f64 = 30.0;
+
+/// Where a deck is in its track, as shown by a UI.
+///
+/// Times are wall-clock at the deck's current tempo ratio, not track time.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeckPosition {
+    /// Playhead in track frames.
+    pub frame: f64,
+    pub seconds: f64,
+    pub remaining_seconds: f64,
+    /// Playhead as a fraction of the track, in [0, 1].
+    pub fraction: f64,
+    /// Less than the end warning time is left; controllers blink on this.
+    pub end_warning: bool,
+    /// Pitch relative to the original recording; stays at 1.0 with keylock on.
+    pub pitch: f64,
+    pub playing: bool,
+}
+
+/// Values published by the deck after every rendered block.
+#[derive(Debug, Default)]
+pub(super) struct PositionState {
+    frame: AtomicU64,
+    seconds: AtomicU64,
+    remaining_seconds: AtomicU64,
+    fraction: AtomicU64,
+    end_warning: AtomicBool,
+    pitch: AtomicU64,
+    playing: AtomicBool,
+}
+
+impl PositionState {
+    pub(super) fn publish(&self, position: &DeckPosition) {
+        let store =
+            |target: &AtomicU64, value: f64| target.store(value.to_bits(), Ordering::Relaxed);
+        store(&self.frame, position.frame);
+        store(&self.seconds, position.seconds);
+        store(&self.remaining_seconds, position.remaining_seconds);
+        store(&self.fraction, position.fraction);
+        store(&self.pitch, position.pitch);
+        self.end_warning
+            .store(position.end_warning, Ordering::Relaxed);
+        self.playing.store(position.playing, Ordering::Relaxed);
+    }
+}
+
+/// Reads a deck's position from another thread without locking.
+#[derive(Debug, Clone)]
+pub struct DeckPositionReader {
+    pub(super) state: Arc<PositionState>,
+}
+
+impl DeckPositionReader {
+    /// Position as of the end of the deck's last rendered block.
+    pub fn read(&self) -> DeckPosition {
+        let load = |value: &AtomicU64| f64::from_bits(value.load(Ordering::Relaxed));
+        let state = &self.state;
+        DeckPosition {
+            frame: load(&state.frame),
+            seconds: load(&state.seconds),
+            remaining_seconds: load(&state.remaining_seconds),
+            fraction: load(&state.fraction),
+            end_warning: state.end_warning.load(Ordering::Relaxed),
+            pitch: load(&state.pitch),
+            playing: state.playing.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use super::LoopRegion;
+
+/// Triggers this soon after a boundary take effect at once, as if pressed on it.
+pub const DEFAULT_SNAP_BACK_SECONDS: f64 = 0.03;
+
+/// Grid boundary that quantized cue and loop triggers wait for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Quantize {
+    #[default]
+    Off,
+    Beat,
+    HalfBeat,
+    QuarterBeat,
+}
+
+impl Quantize {
+    /// Spacing of the boundaries in beats, or `None` when off.
+    pub fn beats(self) -> Option<f64> {
+        match self {
+            Quantize::Off => None,
+            Quantize::Beat => Some(1.0),
+            Quantize::HalfBeat => Some(0.5),
+            Quantize::QuarterBeat => Some(0.25),
+        }
+    }
+}
+
+/// Deck operation held back until a quantize boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantizedAction {
+    Cue { slot: usize, frame: u64 },
+    Loop(LoopRegion),
+}
+
+/// Action waiting for the playhead to reach `at_frame`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PendingTrigger {
+    pub action: QuantizedAction,
+    /// Track frame of the boundary it fires on.
+    pub at_frame: f64,
+}
@@ -0,0 +1,126 @@
+use super::Track;
+
+/// Length of one grain of the keylock time-stretcher.
+const GRAIN_SECONDS: f64 = 0.04;
+/// How far a grain may start from the playhead to line up with the grain it takes over from.
+const SEARCH_SECONDS: f64 = 0.006;
+/// Length of audio compared when lining grains up.
+const MATCH_SECONDS: f64 = 0.005;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Grain {
+    start: i64,
+    age: usize,
+}
+
+/// Pitch-preserving reader for keylock.
+///
+/// Two Hann-windowed grains overlap by half. Each plays the track at its original speed, so the
+/// pitch never changes, while new grains start at the playhead, which moves at the tempo ratio.
+/// A new grain is nudged within a few milliseconds of the playhead to where it best matches the
+/// grain fading out (WSOLA), so the splice stays in phase and the audible position never
+/// strays from the playhead.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Stretcher {
+    /// Older grain first.
+    grains: [Grain; 2],
+    hop: usize,
+    search: i64,
+    matched: usize,
+    primed: bool,
+}
+
+impl Stretcher {
+    pub(crate) fn is_primed(&self) -> bool {
+        self.primed
+    }
+
+    /// Drop the grains; the next [`Self::prime`] starts afresh.
+    pub(crate) fn stop(&mut self) {
+        self.primed = false;
+    }
+
+    /// Start with the audible position at `position`, at full level.
+    pub(crate) fn prime(&mut self, track: &Track, position: f64) {
+        let sample_rate = track.sample_rate as f64;
+        self.hop = ((GRAIN_SECONDS * sample_rate / 2.0).round() as usize).max(1);
+        self.search = (SEARCH_SECONDS * sample_rate).round() as i64;
+        self.matched = ((MATCH_SECONDS * sample_rate).round() as usize).max(1);
+        let at = position.round() as i64;
+        // Half way through the older grain, so the pair already sums to unity.
+        self.grains = [
+            Grain {
+                start: at - self.hop as i64,
+                age: self.hop,
+            },
+            Grain { start: at, age: 0 },
+        ];
+        self.primed = true;
+    }
+
+    /// Next output frame while the playhead is at `position`.
+    pub(crate) fn next(&mut self, track: &Track, position: f64) -> [f32; 2] {
+        if self.grains[1].age == self.hop {
+            let continuation = self.grains[1].start + self.hop as i64;
+            let start = self.best_start(track, position.round() as i64, continuation);
+            self.grains = [self.grains[1], Grain { start, age: 0 }];
+        }
+        let length = self.hop * 2;
+        let mut out = [0.0; 2];
+        for grain in &mut self.grains {
+            let phase = grain.age as f32 / length as f32;
+            let gain = 0.5 - 0.5 * (std::f32::consts::TAU * phase).cos();
+            let frame = frame_at(track, grain.start + grain.age as i64);
+            out[0] += frame[0] * gain;
+            out[1] += frame[1] * gain;
+            grain.age += 1;
+        }
+        out
+    }
+
+    /// Start near `nominal` whose audio best matches what follows `continuation`.
+    ///
+    /// Offsets are tried nearest first and only a clearly better match moves the grain, so a
+    /// periodic signal stays as close to the playhead as it can.
+    fn best_start(&self, track: &Track, nominal: i64, continuation: i64) -> i64 {
+        let mut best = nominal;
+        let mut best_score: f32 = 0.0;
+        for step in 0..=self.search * 2 {
+            let offset = if step % 2 == 0 {
+                step / 2
+            } else {
+                -(step + 1) / 2
+            };
+            let start = nominal + offset;
+            let mut correlation = 0.0;
+            let mut energy = 0.0;
+            for i in 0..self.matched as i64 {
+                let candidate = mono(track, start + i);
+                correlation += candidate * mono(track, continuation + i);
+                energy += candidate * candidate;
+            }
+            let score = if energy > f32::EPSILON {
+                correlation / energy.sqrt()
+            } else {
+                0.0
+            };
+            if step == 0 || score > best_score + best_score.abs() * 1e-4 {
+                best = start;
+                best_score = score;
+            }
+        }
+        best
+    }
+}
+
+fn frame_at(track: &Track, index: i64) -> [f32; 2] {
+    if index < 0 {
+        return [0.0, 0.0];
+    }
+    track.frame(index as u64)
+}
+
+fn mono(track: &Track, index: i64) -> f32 {
+    let frame = frame_at(track, index);
+    frame[0] + frame[1]
+}
@@ -0,0 +1,322 @@
+use super::{BeatGrid, Deck, DeckCommand};
+
+/// Phase error within which the decks count as aligned.
+pub const DEFAULT_SYNC_TOLERANCE_SECONDS: f64 = 0.003;
+
+/// What the assist needs to know about a deck, taken once per block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncSnapshot {
+    /// Playhead in track frames.
+    pub frame: f64,
+    pub tempo_ratio: f64,
+    pub grid: BeatGrid,
+}
+
+impl SyncSnapshot {
+    /// Snapshot of `deck`, or `None` without a beat grid to sync on.
+    pub fn of(deck: &Deck) -> Option<Self> {
+        Some(Self {
+            frame: deck.position(),
+            tempo_ratio: deck.tempo_ratio(),
+            grid: *deck.beat_grid()?,
+        })
+    }
+
+    /// Tempo heard at the snapshot's tempo ratio.
+    pub fn effective_bpm(&self) -> f64 {
+        self.grid.bpm * self.tempo_ratio
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncOptions {
+    /// Phase error below which the decks count as locked, in seconds.
+    pub tolerance_seconds: f64,
+    /// Largest rate nudge while pulling the slave into phase, as a fraction of its tempo.
+    pub max_nudge: f64,
+    /// Largest rate nudge once locked, enough to hold against drift.
+    pub lock_nudge: f64,
+    /// Rate nudge per second of phase error.
+    pub gain: f64,
+    /// Jump straight into phase instead of nudging there.
+    pub snap: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            tolerance_seconds: DEFAULT_SYNC_TOLERANCE_SECONDS,
+            max_nudge: 0.04,
+            lock_nudge: 0.005,
+            gain: 4.0,
+            snap: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+    /// Not holding the slave.
+    Idle,
+    /// Tempo matched, nudging into phase.
+    Aligning,
+    /// In phase, correcting drift with micro-nudges.
+    Locked,
+}
+
+/// Beats of the slave per beat of the master, picked from half, same or double
+/// time so the slave moves as little from its own tempo as possible.
+pub fn beat_multiple(slave_bpm: f64, master_bpm: f64) -> f64 {
+    [0.5, 1.0, 2.0]
+        .into_iter()
+        .min_by(|a, b| {
+            let distance = |multiple: f64| (master_bpm * multiple / slave_bpm).ln().abs();
+            distance(*a).total_cmp(&distance(*b))
+        })
+        .unwrap_or(1.0)
+}
+
+/// Tempo ratio that puts the slave's grid on the master's effective tempo, in
+/// half, same or double time.
+pub fn matched_ratio(slave_bpm: f64, master_bpm: f64) -> f64 {
+    master_bpm * beat_multiple(slave_bpm, master_bpm) / slave_bpm
+}
+
+/// Slave beats it has to move forward to line up with the master, in [-0.5, 0.5).
+pub fn phase_offset_beats(slave: &SyncSnapshot, master: &SyncSnapshot, multiple: f64) -> f64 {
+    let target = master.grid.beat_at(master.frame) * multiple;
+    let offset = (target - slave.grid.beat_at(slave.frame)).rem_euclid(1.0);
+    if offset >= 0.5 {
+        offset - 1.0
+    } else {
+        offset
+    }
+}
+
+/// Rate nudge that closes `error_seconds` of phase, bounded to `limit`.
+pub fn nudge(error_seconds: f64, gain: f64, limit: f64) -> f64 {
+    (error_seconds * gain).clamp(-limit, limit)
+}
+
+/// The sync button: holds a slave deck on the master's tempo and phase.
+///
+/// Feed it snapshots of both decks every block and send the commands it
+/// returns to the slave.
+#[derive(Debug, Clone)]
+pub struct SyncAssist {
+    options: SyncOptions,
+    state: SyncState,
+    /// Tempo ratio that matches the master, before any nudge.
+    base_ratio: f64,
+    /// Ratio last sent to the slave.
+    sent_ratio: f64,
+}
+
+/// Engage sync on `slave`: match tempo, then line up the beats by nudging, or
+/// at once if `options.snap` is set. Returns the assist and the commands for the slave.
+pub fn sync_assist(
+    slave: &SyncSnapshot,
+    master: &SyncSnapshot,
+    options: SyncOptions,
+) -> (SyncAssist, Vec<DeckCommand>) {
+    let base_ratio = matched_ratio(slave.grid.bpm, master.effective_bpm());
+    let mut assist = SyncAssist {
+        options,
+        state: SyncState::Aligning,
+        base_ratio,
+        sent_ratio: base_ratio,
+    };
+    let mut commands = vec![DeckCommand::SetTempoRatio(base_ratio)];
+    if options.snap {
+        let multiple = beat_multiple(slave.grid.bpm, master.effective_bpm());
+        let offset = phase_offset_beats(slave, master, multiple);
+        commands.push(DeckCommand::Shift {
+            frames: offset * slave.grid.frames_per_beat(),
+        });
+        assist.state = SyncState::Locked;
+    }
+    (assist, commands)
+}
+
+impl SyncAssist {
+    pub fn state(&self) -> SyncState {
+        self.state
+    }
+
+    pub fn is_engaged(&self) -> bool {
+        self.state != SyncState::Idle
+    }
+
+    /// Phase error of the slave in seconds, positive when it lags the master.
+    pub fn error_seconds(&self, slave: &SyncSnapshot, master: &SyncSnapshot) -> f64 {
+        let multiple = beat_multiple(slave.grid.bpm, master.effective_bpm());
+        let offset = phase_offset_beats(slave, master, multiple);
+        offset * 60.0 / (slave.grid.bpm * self.base_ratio)
+    }
+
+    /// Follow the master for one block. Returns a new tempo ratio for the slave
+    /// when the nudge changes.
+    pub fn update(&mut self, slave: &SyncSnapshot, master: &SyncSnapshot) -> Option<DeckCommand> {
+        if self.state == SyncState::Idle {
+            return None;
+        }
+        // Follows the master's pitch fader too.
+        self.base_ratio = matched_ratio(slave.grid.bpm, master.effective_bpm());
+        let error = self.error_seconds(slave, master);
+        let options = self.options;
+        self.state = match self.state {
+            SyncState::Aligning if error.abs() <= options.tolerance_seconds => SyncState::Locked,
+            // Knocked well out, e.g. by a beat jump: pull back in at full strength.
+            SyncState::Locked if error.abs() > 4.0 * options.tolerance_seconds => {
+                SyncState::Aligning
+            }
+            state => state,
+        };
+        let limit = match self.state {
+            SyncState::Locked => options.lock_nudge,
+            _ => options.max_nudge,
+        };
+        let ratio = self.base_ratio * (1.0 + nudge(error, options.gain, limit));
+        if (ratio - self.sent_ratio).abs() < 1e-7 {
+            return None;
+        }
+        self.sent_ratio = ratio;
+        Some(DeckCommand::SetTempoRatio(ratio))
+    }
+
+    /// Turn sync off, dropping any nudge so the slave sits on the matched tempo.
+    pub fn disengage(&mut self) -> Option<DeckCommand> {
+        if self.state == SyncState::Idle {
+            return None;
+        }
+        self.state = SyncState::Idle;
+        (self.sent_ratio != self.base_ratio).then_some(DeckCommand::SetTempoRatio(self.base_ratio))
+    }
+
+    /// The user moved the slave's pitch fader: let go without touching its tempo.
+    pub fn pitch_touched(&mut self) {
+        self.state = SyncState::Idle;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+    const BLOCK_FRAMES: f64 = 512.0;
+
+    fn snapshot(bpm: f64, frame: f64, tempo_ratio: f64) -> SyncSnapshot {
+        SyncSnapshot {
+            frame,
+            tempo_ratio,
+            grid: BeatGrid::new(bpm, 0.0, SAMPLE_RATE),
+        }
+    }
+
+    #[test]
+    fn picks_half_or_double_time_when_closer() {
+        assert_eq!(matched_ratio(125.0, 125.0), 1.0);
+        assert_eq!(matched_ratio(120.0, 126.0), 1.05);
+        assert_eq!(beat_multiple(87.0, 174.0), 0.5);
+        assert_eq!(matched_ratio(87.0, 174.0), 1.0);
+        assert_eq!(beat_multiple(170.0, 84.0), 2.0);
+
+        let master = snapshot(120.0, 6_000.0, 1.0);
+        let slave = snapshot(120.0, 0.0, 1.0);
+        assert_eq!(phase_offset_beats(&slave, &master, 1.0), 0.25);
+        assert_eq!(phase_offset_beats(&master, &slave, 1.0), -0.25);
+        assert_eq!(nudge(1.0, 4.0, 0.04), 0.04);
+    }
+
+    /// Run both decks for `blocks`, the slave's clock running `drift` fast, and
+    /// return the slave's phase error after each block.
+    fn simulate(
+        assist: &mut SyncAssist,
+        slave: &mut SyncSnapshot,
+        master: &mut SyncSnapshot,
+        drift: f64,
+        blocks: usize,
+    ) -> Vec<f64> {
+        (0..blocks)
+            .map(|_| {
+                master.frame += BLOCK_FRAMES * master.tempo_ratio;
+                slave.frame += BLOCK_FRAMES * slave.tempo_ratio * (1.0 + drift);
+                if let Some(DeckCommand::SetTempoRatio(ratio)) = assist.update(slave, master) {
+                    slave.tempo_ratio = ratio;
+                }
+                assist.error_seconds(slave, master)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nudges_into_phase_and_holds_against_drift() {
+        let mut master = snapshot(126.0, 10_000.0, 1.0);
+        let mut slave = snapshot(124.0, 0.0, 1.0);
+        let (mut assist, commands) = sync_assist(&slave, &master, SyncOptions::default());
+        assert_eq!(commands, [DeckCommand::SetTempoRatio(126.0 / 124.0)]);
+        slave.tempo_ratio = 126.0 / 124.0;
+        let start = assist.error_seconds(&slave, &master).abs();
+        assert!(start > 0.1, "{start}");
+
+        let blocks_per_second = SAMPLE_RATE as f64 / BLOCK_FRAMES;
+        let errors = simulate(&mut assist, &mut slave, &mut master, 2e-4, 3_000);
+        let converged = errors
+            .iter()
+            .position(|error| error.abs() <= DEFAULT_SYNC_TOLERANCE_SECONDS)
+            .unwrap();
+        assert!(converged as f64 / blocks_per_second < 8.0, "{converged}");
+        assert_eq!(assist.state(), SyncState::Locked);
+        // Steady state: well inside the tolerance, and no further than it ever after.
+        let settled = &errors[converged + blocks_per_second as usize..];
+        assert!(settled
+            .iter()
+            .all(|error| error.abs() < DEFAULT_SYNC_TOLERANCE_SECONDS));
+        let mean = settled.iter().map(|error| error.abs()).sum::<f64>() / settled.len() as f64;
+        assert!(mean < 1e-3, "{mean}");
+        // Once locked, nudges stay within the lock limit.
+        assert!((slave.tempo_ratio / (126.0 / 124.0) - 1.0).abs() <= 0.005 + 1e-9);
+    }
+
+    #[test]
+    fn snap_jumps_into_phase() {
+        let master = snapshot(128.0, 5_000.0, 1.0);
+        let mut slave = snapshot(128.0, 0.0, 1.0);
+        let options = SyncOptions {
+            snap: true,
+            ..SyncOptions::default()
+        };
+        let (assist, commands) = sync_assist(&slave, &master, options);
+        let [DeckCommand::SetTempoRatio(ratio), DeckCommand::Shift { frames }] = commands[..]
+        else {
+            panic!("{commands:?}");
+        };
+        assert_eq!(ratio, 1.0);
+        slave.frame += frames;
+        assert!(assist.error_seconds(&slave, &master).abs() < 1e-9);
+        assert_eq!(assist.state(), SyncState::Locked);
+    }
+
+    #[test]
+    fn touching_the_pitch_fader_lets_go() {
+        let mut master = snapshot(126.0, 10_000.0, 1.0);
+        let mut slave = snapshot(126.0, 0.0, 1.0);
+        let (mut assist, _) = sync_assist(&slave, &master, SyncOptions::default());
+        simulate(&mut assist, &mut slave, &mut master, 0.0, 20);
+        assert_ne!(slave.tempo_ratio, 1.0);
+
+        assist.pitch_touched();
+        assert!(!assist.is_engaged());
+        slave.tempo_ratio = 1.02;
+        simulate(&mut assist, &mut slave, &mut master, 0.0, 100);
+        assert_eq!(slave.tempo_ratio, 1.02);
+        assert_eq!(assist.disengage(), None);
+
+        // Switching sync off mid-nudge settles back on the matched tempo.
+        let (mut assist, _) = sync_assist(&slave, &master, SyncOptions::default());
+        simulate(&mut assist, &mut slave, &mut master, 0.0, 5);
+        assert_eq!(assist.disengage(), Some(DeckCommand::SetTempoRatio(1.0)));
+        assert_eq!(assist.update(&slave, &master), None);
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use crossbeam_queue::ArrayQueue;
+
+use super::DeckCommand;
+
+/// Events a deck can hold before the control thread collects them; later ones are dropped.
+pub const DECK_EVENT_CAPACITY: usize = 64;
+
+/// Where a deck is in its load/play lifecycle.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransportState {
+    Empty,
+    /// A track is being read off the audio thread.
+    Loading,
+    /// Track loaded and parked, at the start or after a stop.
+    Loaded,
+    Playing,
+    Paused,
+    /// Playback ran off the end of the track.
+    Ended,
+    /// The last load failed.
+    Error(String),
+}
+
+impl TransportState {
+    /// State after a transport command, or `None` if it is not allowed from here.
+    ///
+    /// Other commands leave the transport as it is.
+    pub fn after(&self, command: DeckCommand) -> Option<TransportState> {
+        use TransportState::*;
+        let next = match (self, command) {
+            (Loaded | Paused, DeckCommand::Play) => Playing,
+            (Playing, DeckCommand::Pause) => Paused,
+            (Playing | Paused | Ended, DeckCommand::Stop) => Loaded,
+            (Loading | Loaded | Paused | Ended | Error(_), DeckCommand::Eject) => Empty,
+            (
+                _,
+                DeckCommand::Play | DeckCommand::Pause | DeckCommand::Stop | DeckCommand::Eject,
+            ) => return None,
+            _ => self.clone(),
+        };
+        Some(next)
+    }
+
+    /// A new track may replace the current one unless it is on air.
+    pub fn can_load(&self) -> bool {
+        *self != TransportState::Playing
+    }
+
+    /// Lowercase name for remotes and controllers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransportState::Empty => "empty",
+            TransportState::Loading => "loading",
+            TransportState::Loaded => "loaded",
+            TransportState::Playing => "playing",
+            TransportState::Paused => "paused",
+            TransportState::Ended => "ended",
+            TransportState::Error(_) => "error",
+        }
+    }
+}
+
+/// Something that happened to a deck, for the control thread.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeckEvent {
+    TrackLoaded {
+        duration_seconds: f64,
+        bpm: Option<f64>,
+    },
+    Started,
+    Ended,
+    LoadFailed(String),
+    /// A transport command was ignored in `state`.
+    Rejected {
+        command: DeckCommand,
+        state: TransportState,
+    },
+    /// A load was ignored in `state`.
+    LoadRejected(TransportState),
+}
+
+/// Control-thread end of a deck's event queue.
+#[derive(Debug, Clone)]
+pub struct DeckEvents {
+    pub(super) queue: Arc<ArrayQueue<DeckEvent>>,
+}
+
+impl DeckEvents {
+    pub fn poll(&self) -> Option<DeckEvent> {
+        self.queue.pop()
+    }
+}
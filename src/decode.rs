@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use thiserror::Error;
+
+use crate::deck::Track;
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("failed to open track: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to decode track: {0}")]
+    Format(#[from] SymphoniaError),
+    #[error("track has no audio")]
+    NoAudio,
+}
+
+/// Decode the whole of `path` into memory as interleaved stereo.
+///
+/// Mono is copied to both sides and anything wider keeps its front left and
+/// right channels. Corrupt packets are skipped rather than failing the track.
+pub fn decode_track(path: &Path) -> Result<Track, DecodeError> {
+    let file = File::open(path)?;
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+    let track = format.default_track().ok_or(DecodeError::NoAudio)?;
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut samples = Vec::new();
+    // Conversion buffer and the frames it holds, regrown if a packet is larger.
+    let mut buffer: Option<(SampleBuffer<f32>, usize)> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let spec = *decoded.spec();
+        let channels = spec.channels.count();
+        if channels == 0 {
+            continue;
+        }
+        sample_rate.get_or_insert(spec.rate);
+        let frames = decoded.capacity();
+        if buffer
+            .as_ref()
+            .is_none_or(|(_, capacity)| *capacity < frames)
+        {
+            buffer = Some((SampleBuffer::new(frames as u64, spec), frames));
+        }
+        let (buffer, _) = buffer.as_mut().expect("buffer was just sized");
+        buffer.copy_interleaved_ref(decoded);
+        for frame in buffer.samples().chunks_exact(channels) {
+            let right = if channels == 1 { frame[0] } else { frame[1] };
+            samples.extend_from_slice(&[frame[0], right]);
+        }
+    }
+
+    let sample_rate = sample_rate.ok_or(DecodeError::NoAudio)?;
+    Ok(Track::from_interleaved(samples, sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::read_metadata;
+    use crate::record::{write_wav, RecorderOptions, WavFormat};
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn decodes_fixtures_to_their_tagged_duration() {
+        for file in ["tagged.mp3", "tagged.flac", "tagged.m4a"] {
+            let track = decode_track(&fixture(file)).unwrap();
+            let expected = read_metadata(&fixture(file))
+                .unwrap()
+                .duration_seconds
+                .unwrap();
+            let seconds = track.frames() as f64 / track.sample_rate() as f64;
+            assert!((seconds - expected).abs() < 0.1, "{file}: {seconds}");
+        }
+    }
+
+    #[test]
+    fn round_trips_a_float_wav_and_widens_mono() {
+        let dir = tempdir().unwrap();
+        let stereo: Vec<f32> = (0..960).map(|i| i as f32 / 1_000.0 - 0.48).collect();
+        let path = dir.path().join("stereo.wav");
+        let options = RecorderOptions {
+            format: WavFormat::Float32,
+            sample_rate: 44_100,
+            ..RecorderOptions::default()
+        };
+        write_wav(&path, &stereo, &options).unwrap();
+        let track = decode_track(&path).unwrap();
+        assert_eq!(track.sample_rate(), 44_100);
+        assert_eq!(track.samples(), &stereo[..]);
+
+        let path = dir.path().join("mono.wav");
+        let mono = RecorderOptions {
+            channels: 1,
+            ..options
+        };
+        write_wav(&path, &[0.25, -0.5], &mono).unwrap();
+        assert_eq!(
+            decode_track(&path).unwrap().samples(),
+            &[0.25, 0.25, -0.5, -0.5]
+        );
+
+        assert!(matches!(
+            decode_track(&fixture("missing.mp3")),
+            Err(DecodeError::Io(_))
+        ));
+    }
+}
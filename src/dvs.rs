@@ -0,0 +1,532 @@
+pub mod calibration;
+pub mod timecode;
+
+use std::f64::consts::TAU;
+
+use crate::deck::{Deck, DeckCommand};
+
+pub use calibration::Calibration;
+pub use timecode::{Rpm, TimecodeFormat, SERATO_2A};
+
+use timecode::CodeTable;
+
+/// Envelope below this fraction of the running level counts as lost signal.
+const LOSS_FRACTION: f32 = 0.2;
+/// Envelope above this multiple of the running level counts as a click, not signal.
+const SPIKE_FACTOR: f32 = 3.0;
+/// Envelope that counts as signal before a running level exists.
+const MIN_LEVEL: f32 = 0.01;
+/// Time constant of the running signal level, which is also the bit threshold.
+const LEVEL_SECONDS: f64 = 0.05;
+/// Consecutive agreeing code lookups before an absolute position is reported.
+const VALID_CYCLES: u32 = 16;
+/// How long a dropout holds the last velocity before the needle counts as lifted.
+pub const DEFAULT_HOLD_SECONDS: f64 = 0.05;
+/// In absolute mode a needle further than this from the playhead moves the playhead.
+pub const NEEDLE_DROP_SECONDS: f64 = 0.25;
+
+/// Whether the decoder is following the record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalState {
+    Locked,
+    /// Briefly lost, e.g. to dust; the last velocity is held.
+    Dropout,
+    /// No signal for longer than the hold time; the deck should stand still.
+    Lifted,
+}
+
+/// Decoder output for one block of input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DvsReading {
+    /// Playback rate, 1.0 being normal speed; negative while the record turns backwards.
+    pub velocity: f64,
+    /// Needle position in seconds from the start of the timecode, once the code is read.
+    pub position: Option<f64>,
+    pub signal: SignalState,
+    /// Fraction of the block with usable signal.
+    pub quality: f32,
+}
+
+impl DvsReading {
+    pub fn is_reverse(&self) -> bool {
+        self.velocity < 0.0
+    }
+}
+
+/// Follows a timecode record from its stereo input, one block at a time.
+#[derive(Debug, Clone)]
+pub struct TimecodeDecoder {
+    format: TimecodeFormat,
+    table: CodeTable,
+    rpm: Rpm,
+    sample_rate: f64,
+    calibration: Calibration,
+    hold_seconds: f64,
+    level_smoothing: f32,
+    /// Running envelope level; zero until signal has been seen.
+    level: f32,
+    /// Carrier phase of the last usable frame, in radians.
+    previous_phase: Option<f32>,
+    /// Carrier cycles travelled since the decoder started.
+    cycles: f64,
+    /// Recent bits, newest cycle's bit on top when moving forwards.
+    code: u32,
+    code_bits: u32,
+    /// Timecode cycle minus `cycles`, as suggested by the latest lookups.
+    offset: Option<i64>,
+    agreeing: u32,
+    /// Consecutive frames without usable signal.
+    missing: usize,
+    velocity: f64,
+}
+
+impl TimecodeDecoder {
+    pub fn new(format: TimecodeFormat, sample_rate: u32) -> Self {
+        let sample_rate = sample_rate as f64;
+        Self {
+            table: CodeTable::build(&format),
+            format,
+            rpm: Rpm::default(),
+            sample_rate,
+            calibration: Calibration::default(),
+            hold_seconds: DEFAULT_HOLD_SECONDS,
+            level_smoothing: (1.0 - (-1.0 / (LEVEL_SECONDS * sample_rate)).exp()) as f32,
+            level: 0.0,
+            previous_phase: None,
+            cycles: 0.0,
+            code: 0,
+            code_bits: 0,
+            offset: None,
+            agreeing: 0,
+            missing: usize::MAX,
+            velocity: 0.0,
+        }
+    }
+
+    pub fn format(&self) -> &TimecodeFormat {
+        &self.format
+    }
+
+    pub fn rpm(&self) -> Rpm {
+        self.rpm
+    }
+
+    pub fn set_rpm(&mut self, rpm: Rpm) {
+        self.rpm = rpm;
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// Measure `samples` of the record playing and correct for them from now on.
+    pub fn calibrate(&mut self, samples: &[f32]) -> Calibration {
+        self.calibration = Calibration::measure(samples);
+        self.level = 0.0;
+        self.calibration
+    }
+
+    pub fn hold_seconds(&self) -> f64 {
+        self.hold_seconds
+    }
+
+    pub fn set_hold_seconds(&mut self, seconds: f64) {
+        self.hold_seconds = seconds.max(0.0);
+    }
+
+    /// Carrier cycles per input frame at normal speed.
+    fn cycles_per_frame(&self) -> f64 {
+        self.format.carrier_hz * self.rpm.speed_factor() / self.sample_rate
+    }
+
+    /// Decode a block of interleaved stereo input.
+    pub fn decode(&mut self, input: &[f32]) -> DvsReading {
+        let frames = input.len() / 2;
+        let mut travelled = 0.0;
+        let mut steps = 0usize;
+        for frame in input.chunks_exact(2) {
+            let (left, right) = self.calibration.correct(frame[0], frame[1]);
+            let envelope = left.hypot(right);
+            let usable = if self.level > 0.0 {
+                envelope >= LOSS_FRACTION * self.level && envelope <= SPIKE_FACTOR * self.level
+            } else {
+                envelope >= MIN_LEVEL
+            };
+            if !usable {
+                self.missing = self.missing.saturating_add(1);
+                self.previous_phase = None;
+                continue;
+            }
+            self.level = if self.level > 0.0 {
+                self.level + (envelope - self.level) * self.level_smoothing
+            } else {
+                envelope
+            };
+
+            let phase = left.atan2(right);
+            let Some(previous) = self.previous_phase.replace(phase) else {
+                self.resume(phase);
+                continue;
+            };
+            let step = wrap(phase - previous) as f64 / TAU;
+            travelled += step;
+            steps += 1;
+            let before = self.cycles;
+            self.cycles += step;
+            self.read_bit(before, envelope);
+        }
+
+        let cycles_per_frame = self.cycles_per_frame();
+        let (signal, velocity) = if steps * 2 >= frames && steps > 0 {
+            (
+                SignalState::Locked,
+                travelled / steps as f64 / cycles_per_frame,
+            )
+        } else if (self.missing as f64) < self.hold_seconds * self.sample_rate {
+            (SignalState::Dropout, self.velocity)
+        } else {
+            self.lift();
+            (SignalState::Lifted, 0.0)
+        };
+        self.velocity = velocity;
+
+        let position = match (signal, self.offset) {
+            (SignalState::Lifted, _) => None,
+            (_, Some(offset)) if self.agreeing >= VALID_CYCLES => {
+                let mut cycles = self.cycles + offset as f64;
+                if self.previous_phase.is_none() {
+                    cycles += velocity * cycles_per_frame * self.missing as f64;
+                }
+                Some(cycles / (self.format.carrier_hz * self.rpm.speed_factor()))
+            }
+            _ => None,
+        };
+        DvsReading {
+            velocity,
+            position,
+            signal,
+            quality: steps as f32 / frames.max(1) as f32,
+        }
+    }
+
+    /// Pick the cycle count back up after a gap, assuming the record kept its speed.
+    fn resume(&mut self, phase: f32) {
+        if self.missing == usize::MAX {
+            self.cycles = phase as f64 / TAU;
+        } else {
+            let predicted =
+                self.cycles + self.velocity * self.cycles_per_frame() * self.missing as f64;
+            let fraction = phase as f64 / TAU;
+            self.cycles = (predicted - fraction).round() + fraction;
+        }
+        self.missing = 0;
+        self.code_bits = 0;
+    }
+
+    /// Forget the record after the needle has been lifted.
+    fn lift(&mut self) {
+        self.previous_phase = None;
+        self.missing = usize::MAX;
+        self.code_bits = 0;
+        self.offset = None;
+        self.agreeing = 0;
+        self.level = 0.0;
+    }
+
+    /// Read a cycle's bit as the phase passes its middle, then look the code up.
+    fn read_bit(&mut self, before: f64, envelope: f32) {
+        let (from, to) = ((before - 0.5).floor(), (self.cycles - 0.5).floor());
+        if from == to {
+            return;
+        }
+        let bit = u32::from(envelope > self.level);
+        let bits = self.format.bits;
+        // The code of the newest cycle in the register, whichever end it was read from.
+        let newest = if to > from {
+            self.code = (self.code >> 1) | (bit << (bits - 1));
+            to as i64
+        } else {
+            self.code = ((self.code << 1) & self.format.mask()) | bit;
+            from as i64 - 1 + bits as i64
+        };
+        self.code_bits = (self.code_bits + 1).min(bits);
+        if self.code_bits < bits {
+            return;
+        }
+        match self.table.cycle_of(self.code) {
+            Some(cycle) => {
+                let offset = cycle as i64 - newest;
+                if self.offset == Some(offset) {
+                    self.agreeing = self.agreeing.saturating_add(1);
+                } else {
+                    self.offset = Some(offset);
+                    self.agreeing = 1;
+                }
+            }
+            None => self.agreeing = 0,
+        }
+    }
+}
+
+/// Wrap a phase difference into (-π, π].
+fn wrap(delta: f32) -> f32 {
+    use std::f32::consts::{PI, TAU};
+    if delta > PI {
+        delta - TAU
+    } else if delta <= -PI {
+        delta + TAU
+    } else {
+        delta
+    }
+}
+
+/// How the record maps onto the track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DvsMode {
+    /// The needle's place on the record is the place in the track.
+    Absolute,
+    /// Only the record's movement counts; needle drops do not move the playhead.
+    #[default]
+    Relative,
+}
+
+/// Drives a deck's jog from timecode readings, holding the platter for as long as it runs.
+#[derive(Debug, Clone, Default)]
+pub struct DvsDriver {
+    mode: DvsMode,
+    engaged: bool,
+}
+
+impl DvsDriver {
+    pub fn new(mode: DvsMode) -> Self {
+        Self {
+            mode,
+            engaged: false,
+        }
+    }
+
+    pub fn mode(&self) -> DvsMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: DvsMode) {
+        self.mode = mode;
+    }
+
+    /// Move `deck` as the record moved during a block of `block_seconds`.
+    ///
+    /// A lifted needle leaves the platter held still, which pauses the deck.
+    pub fn drive(&mut self, reading: &DvsReading, block_seconds: f64, deck: &mut Deck) {
+        let Some(sample_rate) = deck.track().map(|track| track.sample_rate() as f64) else {
+            return;
+        };
+        if !self.engaged {
+            deck.apply(DeckCommand::JogTouch(true));
+            self.engaged = true;
+        }
+        if reading.signal == SignalState::Lifted {
+            return;
+        }
+        if let (DvsMode::Absolute, Some(seconds)) = (self.mode, reading.position) {
+            let needle = seconds * sample_rate;
+            if (needle - deck.position()).abs() > NEEDLE_DROP_SECONDS * sample_rate {
+                deck.seek(needle.max(0.0) as u64);
+            }
+        }
+        deck.apply(DeckCommand::JogTick {
+            delta_frames: reading.velocity * block_seconds * sample_rate,
+        });
+    }
+
+    /// Hand the deck back to normal playback.
+    pub fn release(&mut self, deck: &mut Deck) {
+        if self.engaged {
+            deck.apply(DeckCommand::JogTouch(false));
+            self.engaged = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+    const BLOCK: usize = 480;
+
+    /// Stereo timecode for the record turning at `velocity` from `start_cycle`.
+    fn timecode(rpm: Rpm, start_cycle: f64, velocity: f64, frames: usize) -> Vec<f32> {
+        let format = SERATO_2A;
+        let step = velocity * format.carrier_hz * rpm.speed_factor() / RATE as f64;
+        let end = start_cycle + step * frames as f64;
+        let last = start_cycle.max(end).ceil() as usize + 1;
+        let mut bits = Vec::with_capacity(last);
+        let mut code = format.seed;
+        for _ in 0..last {
+            bits.push(format.bit_of(code));
+            code = format.next_code(code);
+        }
+        (0..frames)
+            .flat_map(|i| {
+                let cycle = start_cycle + step * i as f64;
+                let amplitude = if bits[cycle.floor() as usize] {
+                    0.8
+                } else {
+                    0.8 * format.zero_level
+                };
+                let (sin, cos) = (cycle * TAU).sin_cos();
+                [
+                    (amplitude as f64 * sin) as f32,
+                    (amplitude as f64 * cos) as f32,
+                ]
+            })
+            .collect()
+    }
+
+    fn decode_all(decoder: &mut TimecodeDecoder, input: &[f32]) -> Vec<DvsReading> {
+        input
+            .chunks(BLOCK * 2)
+            .map(|block| decoder.decode(block))
+            .collect()
+    }
+
+    #[test]
+    fn follows_speed_and_direction_at_both_rpms() {
+        for (rpm, velocity) in [
+            (Rpm::ThirtyThree, 1.0),
+            (Rpm::ThirtyThree, 0.5),
+            (Rpm::ThirtyThree, -1.0),
+            (Rpm::FortyFive, 1.0),
+            (Rpm::FortyFive, -2.0),
+        ] {
+            let mut decoder = TimecodeDecoder::new(SERATO_2A, RATE);
+            decoder.set_rpm(rpm);
+            let start = 20_000.5;
+            let readings = decode_all(
+                &mut decoder,
+                &timecode(rpm, start, velocity, RATE as usize / 2),
+            );
+
+            let last = readings.last().unwrap();
+            assert_eq!(last.signal, SignalState::Locked);
+            assert_eq!(last.is_reverse(), velocity < 0.0);
+            for reading in &readings[1..] {
+                let error = (reading.velocity - velocity).abs() / velocity.abs();
+                assert!(error < 0.005, "{rpm:?} {velocity}: {}", reading.velocity);
+            }
+
+            // The needle's place on the record, in seconds at normal speed.
+            let cycles_per_second = SERATO_2A.carrier_hz * rpm.speed_factor();
+            let expected = (start + velocity * cycles_per_second * 0.5) / cycles_per_second;
+            let position = last.position.expect("code locked");
+            assert!(
+                (position - expected).abs() < 0.002,
+                "{rpm:?} {velocity}: {position}"
+            );
+        }
+    }
+
+    #[test]
+    fn dust_holds_velocity_and_a_lift_pauses() {
+        let mut decoder = TimecodeDecoder::new(SERATO_2A, RATE);
+        let mut input = timecode(Rpm::ThirtyThree, 5_000.5, 1.0, RATE as usize / 2);
+        // 10 ms of dust, then the needle lifts after 400 ms.
+        input[12_000 * 2..12_480 * 2].fill(0.0);
+        input[19_200 * 2..].fill(0.0);
+        let readings = decode_all(&mut decoder, &input);
+
+        let dust = readings[12_000 / BLOCK];
+        assert_eq!(dust.signal, SignalState::Dropout);
+        assert!((dust.velocity - 1.0).abs() < 0.005);
+        assert!(dust.position.is_some());
+
+        let after = readings[12_480 / BLOCK + 1];
+        assert_eq!(after.signal, SignalState::Locked);
+        let position = after.position.unwrap();
+        let expected = (5_000.5 + (12_480 + 2 * BLOCK) as f64 / 48.0) / 1_000.0;
+        assert!((position - expected).abs() < 0.002, "{position}");
+
+        let lifted = readings.last().unwrap();
+        assert_eq!(lifted.signal, SignalState::Lifted);
+        assert_eq!(lifted.velocity, 0.0);
+        assert_eq!(lifted.position, None);
+    }
+
+    #[test]
+    fn calibration_rebalances_a_skewed_cartridge() {
+        let clean = timecode(Rpm::ThirtyThree, 1_000.5, 1.0, RATE as usize / 4);
+        let error = 0.3f32;
+        let skewed: Vec<f32> = clean
+            .chunks_exact(2)
+            .flat_map(|frame| {
+                let (sin, cos) = (frame[0], frame[1]);
+                // Right lags by `error` and is half as loud.
+                [sin, 0.5 * (cos * error.cos() - sin * error.sin())]
+            })
+            .collect();
+
+        let ideal = Calibration::measure(&clean);
+        assert!(ideal.quality > 0.95, "{ideal:?}");
+
+        let mut decoder = TimecodeDecoder::new(SERATO_2A, RATE);
+        let calibration = decoder.calibrate(&skewed);
+        assert!(
+            (calibration.phase_error - error).abs() < 0.02,
+            "{calibration:?}"
+        );
+        assert!(calibration.quality < 0.5, "{calibration:?}");
+        assert!((calibration.right_gain / calibration.left_gain - 2.0).abs() < 0.05);
+
+        let readings = decode_all(&mut decoder, &skewed);
+        for reading in &readings[1..] {
+            assert!(
+                (reading.velocity - 1.0).abs() < 0.005,
+                "{}",
+                reading.velocity
+            );
+        }
+        assert!(readings.last().unwrap().position.is_some());
+    }
+
+    #[test]
+    fn driver_moves_the_deck_and_jumps_on_needle_drop() {
+        use crate::deck::Track;
+
+        let mut deck = Deck::new();
+        deck.load(Track::from_interleaved(vec![0.0; 48_000 * 2 * 20], RATE));
+        let mut driver = DvsDriver::new(DvsMode::Absolute);
+        let reading = DvsReading {
+            velocity: 1.0,
+            position: Some(10.0),
+            signal: SignalState::Locked,
+            quality: 1.0,
+        };
+        driver.drive(&reading, 0.01, &mut deck);
+        assert!(deck.is_scratching());
+        let mut out = vec![0.0; BLOCK * 2];
+        deck.render(&mut out);
+        // The playhead jumped to the needle and is chasing the record forwards.
+        let chased = deck.position();
+        assert!(chased > 480_000.0 && chased < 480_480.0, "{chased}");
+
+        // Relative mode ignores where the needle is.
+        driver.set_mode(DvsMode::Relative);
+        driver.drive(
+            &DvsReading {
+                position: Some(2.0),
+                ..reading
+            },
+            0.01,
+            &mut deck,
+        );
+        deck.render(&mut out);
+        assert!(deck.position() > chased && deck.position() < 481_000.0);
+
+        driver.release(&mut deck);
+        assert!(!deck.is_scratching());
+    }
+}
@@ -0,0 +1,64 @@
+use std::f32::consts::FRAC_PI_2;
+
+/// Carrier amplitude below which a channel is treated as silent.
+const MIN_LEVEL: f64 = 1e-3;
+
+/// Level and phase corrections for a timecode input, measured from a stretch of signal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    pub left_gain: f32,
+    pub right_gain: f32,
+    /// How far the right channel is from quadrature with the left, in radians.
+    pub phase_error: f32,
+    /// 1.0 for balanced channels in perfect quadrature, falling to 0.0 with no usable signal.
+    pub quality: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            left_gain: 1.0,
+            right_gain: 1.0,
+            phase_error: 0.0,
+            quality: 0.0,
+        }
+    }
+}
+
+impl Calibration {
+    /// Measure interleaved stereo timecode played at any steady speed.
+    pub fn measure(samples: &[f32]) -> Self {
+        let frames = (samples.len() / 2).max(1) as f64;
+        let (mut left, mut right, mut cross) = (0.0, 0.0, 0.0);
+        for frame in samples.chunks_exact(2) {
+            let (l, r) = (frame[0] as f64, frame[1] as f64);
+            left += l * l;
+            right += r * r;
+            cross += l * r;
+        }
+        // Peak amplitudes of the two sines, and their correlation from sin(a)cos(a + e).
+        let left = (2.0 * left / frames).sqrt();
+        let right = (2.0 * right / frames).sqrt();
+        if left < MIN_LEVEL || right < MIN_LEVEL {
+            return Self::default();
+        }
+        let phase_error = (-2.0 * cross / frames / (left * right))
+            .clamp(-1.0, 1.0)
+            .asin() as f32;
+        let balance = (left.min(right) / left.max(right)) as f32;
+        Self {
+            left_gain: (1.0 / left) as f32,
+            right_gain: (1.0 / right) as f32,
+            phase_error,
+            quality: (balance * (1.0 - phase_error.abs() / FRAC_PI_2)).clamp(0.0, 1.0),
+        }
+    }
+
+    /// Equalise the channels and rotate the right back into quadrature.
+    pub(crate) fn correct(&self, left: f32, right: f32) -> (f32, f32) {
+        let left = left * self.left_gain;
+        let right = right * self.right_gain;
+        let (sin, cos) = self.phase_error.sin_cos();
+        (left, (right + sin * left) / cos)
+    }
+}
@@ -0,0 +1,87 @@
+/// A timecode pressing: a quadrature carrier whose cycles each carry one bit of an LFSR
+/// sequence, a one at full amplitude and a zero at `zero_level`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimecodeFormat {
+    pub name: &'static str,
+    /// Carrier frequency at 33⅓ RPM and normal speed.
+    pub carrier_hz: f64,
+    /// Length of the LFSR, and so the number of cycles needed to find the position.
+    pub bits: u32,
+    pub seed: u32,
+    pub taps: u32,
+    /// Carrier cycles on the side.
+    pub length: u32,
+    /// Amplitude of a zero bit relative to a one.
+    pub zero_level: f32,
+}
+
+/// Serato-style 1 kHz side A, with the LFSR definition published by xwax.
+pub const SERATO_2A: TimecodeFormat = TimecodeFormat {
+    name: "serato_2a",
+    carrier_hz: 1_000.0,
+    bits: 20,
+    seed: 0x59017,
+    taps: 0x361e4,
+    length: 712_000,
+    zero_level: 0.6,
+};
+
+impl TimecodeFormat {
+    pub(crate) fn mask(&self) -> u32 {
+        (1 << self.bits) - 1
+    }
+
+    /// Code of the cycle after the one whose code is `code`.
+    pub(crate) fn next_code(&self, code: u32) -> u32 {
+        let feedback = (code & (self.taps | 1)).count_ones() & 1;
+        (code >> 1) | (feedback << (self.bits - 1))
+    }
+
+    /// Bit carried by the cycle whose code is `code`; older bits sit below it.
+    pub fn bit_of(&self, code: u32) -> bool {
+        code >> (self.bits - 1) & 1 == 1
+    }
+}
+
+/// Rotation speed the turntable is set to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rpm {
+    #[default]
+    ThirtyThree,
+    FortyFive,
+}
+
+impl Rpm {
+    /// Carrier speed-up relative to 33⅓ RPM.
+    pub fn speed_factor(self) -> f64 {
+        match self {
+            Rpm::ThirtyThree => 1.0,
+            Rpm::FortyFive => 1.35,
+        }
+    }
+}
+
+/// Reverse lookup from a code to the cycle it belongs to.
+#[derive(Debug, Clone)]
+pub(crate) struct CodeTable {
+    cycles: Vec<u32>,
+}
+
+impl CodeTable {
+    pub(crate) fn build(format: &TimecodeFormat) -> Self {
+        let mut cycles = vec![u32::MAX; 1 << format.bits];
+        let mut code = format.seed & format.mask();
+        for cycle in 0..format.length {
+            cycles[code as usize] = cycle;
+            code = format.next_code(code);
+        }
+        Self { cycles }
+    }
+
+    pub(crate) fn cycle_of(&self, code: u32) -> Option<u32> {
+        self.cycles
+            .get(code as usize)
+            .copied()
+            .filter(|cycle| *cycle != u32::MAX)
+    }
+}
@@ -0,0 +1,1509 @@
+#[cfg(feature = "audio")]
+mod cpal_backend;
+pub mod cue;
+pub mod exclusive;
+pub mod input;
+pub mod preview;
+pub mod recovery;
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crossbeam_queue::ArrayQueue;
+use thiserror::Error;
+
+use crate::crash::record_breadcrumb;
+use crate::fx::{FxChain, FxChainHandle};
+use crate::limiter::{Limiter, LimiterOptions};
+use crate::meter::{loudness_meter, LoudnessMeter, LoudnessReader, LoudnessReading};
+use crate::metrics::{Collect, DurationHistogram, MetricSet};
+use crate::record::RecordTap;
+use crate::ring::RingConsumer;
+use crate::sampler::{Sampler, SamplerHandle};
+use crate::settings::Settings;
+use crate::sidechain::GainReductionReader;
+use crate::{parameter_channel, DeckId, FaderReader, ParameterSender, SummingBus};
+use cue::{cue_link, cue_target_frames, CueFeed};
+use input::InputCounters;
+use recovery::Target;
+
+#[cfg(feature = "audio")]
+pub use cpal_backend::{list_output_devices, CpalBackend};
+pub use cue::{CueCallback, CueMonitor, CueStats};
+pub use exclusive::{plan_exclusive, ExclusiveCapabilities, ExclusiveFormat};
+pub use input::{CaptureBackend, ChannelMap, InputCapture, InputDeck};
+pub use preview::{PreviewBackend, PreviewCallback, PreviewPlayer};
+pub use recovery::{RecoveryOptions, Watchdog};
+
+/// Capacity of the control-to-audio parameter queue.
+const PARAMETER_QUEUE_CAPACITY: usize = 256;
+/// Pending tap attach/detach requests.
+const TAP_QUEUE_CAPACITY: usize = 4;
+/// Pending mic attach/detach requests.
+const MIC_QUEUE_CAPACITY: usize = 2;
+/// Mixers handed back by dropped output callbacks; only one ever exists.
+const MIXER_HOME_CAPACITY: usize = 1;
+/// Capacity of the engine event queue.
+const EVENT_QUEUE_CAPACITY: usize = 64;
+/// Largest block mixed in one pass; bigger host buffers are split.
+const MAX_BLOCK_FRAMES: usize = 4_096;
+/// Number of decks the summing bus mixes.
+const BUS_DECKS: usize = 2;
+
+#[derive(Debug, Error)]
+pub enum EngineError {
+    #[error("audio host {0} is not available")]
+    HostUnavailable(String),
+    #[error("audio device {0} was not found")]
+    DeviceNotFound(String),
+    #[error("no preview device is configured; set preview_device in the settings")]
+    NoPreviewDevice,
+    #[error("no cue device is configured; set cue_device in the settings")]
+    NoCueDevice,
+    #[error("device offers no f32 output configuration with at least two channels")]
+    NoSupportedConfig,
+    #[error("the mixer supports at most {BUS_DECKS} decks, got {0}")]
+    UnsupportedDeckCount(usize),
+    #[error("exclusive mode was denied: {0}; turn off exclusive_mode or close other applications using the device")]
+    ExclusiveModeDenied(String),
+    #[error("ASIO driver missing: {0}; install the interface's ASIO driver and build with the `asio` feature")]
+    AsioDriverMissing(String),
+    #[error("input runs at {input} Hz but the output runs at {output} Hz; pick matching rates for both devices")]
+    SampleRateMismatch { input: u32, output: u32 },
+    #[error("the cue device cannot run at the master's {master} Hz (nearest is {cue} Hz); pick a rate both devices support")]
+    CueSampleRateMismatch { cue: u32, master: u32 },
+    #[error("input channels {left} and {right} do not exist on a {channels}-channel device")]
+    InvalidChannelMap {
+        left: u16,
+        right: u16,
+        channels: u16,
+    },
+    #[error("audio backend error: {0}")]
+    Backend(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    F32,
+    I32,
+    I24,
+    I16,
+    U16,
+    Other,
+}
+
+/// One output configuration range offered by a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupportedConfig {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    /// Inclusive buffer size range in frames, if the host reports one.
+    pub buffer_frames: Option<(u32, u32)>,
+    pub sample_format: SampleFormat,
+}
+
+/// Stream parameters the engine settled on after negotiation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiatedConfig {
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// `None` when the host only offers its default buffer size.
+    pub buffer_frames: Option<u32>,
+    /// Device-side encoding; the mix is always rendered as f32.
+    pub sample_format: SampleFormat,
+    /// Whether the stream bypasses the system mixer (WASAPI exclusive mode).
+    pub exclusive: bool,
+}
+
+/// Output devices of one audio host, for `list-devices`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostDevices {
+    pub host: String,
+    pub devices: Vec<String>,
+    pub default_device: Option<String>,
+}
+
+/// Pick the output configuration closest to the requested rate and buffer size.
+///
+/// Only f32 layouts with at least two channels are considered; among those the
+/// requested rate wins if supported, otherwise the nearest supported rate, with
+/// ties broken towards fewer channels.
+pub fn negotiate(
+    sample_rate: u32,
+    buffer_frames: u32,
+    supported: &[SupportedConfig],
+) -> Result<NegotiatedConfig, EngineError> {
+    supported
+        .iter()
+        .filter(|config| config.sample_format == SampleFormat::F32 && config.channels >= 2)
+        .map(|config| {
+            let rate = sample_rate.clamp(config.min_sample_rate, config.max_sample_rate);
+            (rate.abs_diff(sample_rate), config, rate)
+        })
+        .min_by_key(|(distance, config, _)| (*distance, config.channels))
+        .map(|(_, config, rate)| NegotiatedConfig {
+            channels: config.channels,
+            sample_rate: rate,
+            buffer_frames: config
+                .buffer_frames
+                .map(|(min, max)| buffer_frames.clamp(min, max)),
+            sample_format: SampleFormat::F32,
+            exclusive: false,
+        })
+        .ok_or(EngineError::NoSupportedConfig)
+}
+
+/// Notifications from the audio side for the control thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineEvent {
+    StreamError(String),
+    /// Something degraded at startup, such as falling back from exclusive mode.
+    Warning(String),
+    /// The output device went away; the engine keeps trying to reopen it.
+    DeviceLost,
+    /// Mixing resumed on `device` after [`EngineEvent::DeviceLost`].
+    Recovered {
+        device: String,
+    },
+    /// The configured device stayed gone and the system default took over.
+    FallbackActive,
+}
+
+/// Counters shared between the audio callback and [`EngineHandle`].
+#[derive(Debug, Default)]
+struct EngineState {
+    callbacks: AtomicU64,
+    frames: AtomicU64,
+    deck_underruns: AtomicU64,
+    input_dropped_frames: AtomicU64,
+    input_overruns: AtomicU64,
+    stream_errors: AtomicU64,
+    /// Set by the stream when its device disappeared, cleared by the watchdog.
+    device_lost: AtomicBool,
+    /// Callbacks that took longer than the audio they rendered lasts.
+    xruns: AtomicU64,
+    last_callback_nanos: AtomicU64,
+    callback_durations: DurationHistogram,
+    peak_left: AtomicU32,
+    peak_right: AtomicU32,
+    /// Link session peers plus one, zero while Link is off.
+    link_peers: AtomicU64,
+}
+
+/// Point-in-time view of the engine's counters and meters.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EngineStats {
+    pub callbacks: u64,
+    pub frames_rendered: u64,
+    /// Total ring underruns across all decks.
+    pub deck_underruns: u64,
+    /// Captured frames discarded because an input deck's ring was full.
+    pub input_dropped_frames: u64,
+    /// Capture callbacks that had to drop frames.
+    pub input_overruns: u64,
+    pub stream_errors: u64,
+    /// Callbacks that overran their deadline.
+    pub xruns: u64,
+    pub last_callback_nanos: u64,
+    /// Absolute peak of the last callback's output, per channel.
+    pub peak: [f32; 2],
+    /// Other apps in the Link session, `None` while Link is off.
+    pub link_peers: Option<u64>,
+}
+
+/// Engine counters as scraped by the metrics endpoint.
+#[derive(Clone)]
+pub struct EngineMetrics {
+    state: Arc<EngineState>,
+    params: ParameterSender,
+}
+
+impl Collect for EngineMetrics {
+    fn collect(&self, metrics: &mut MetricSet) {
+        let state = &self.state;
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        metrics.counter(
+            "deejay_audio_callbacks_total",
+            "Output callbacks rendered.",
+            load(&state.callbacks),
+        );
+        metrics.summary(
+            "deejay_callback_duration_seconds",
+            "Time spent rendering each output callback.",
+            &state.callback_durations.snapshot(),
+        );
+        metrics.counter(
+            "deejay_xruns_total",
+            "Output callbacks that took longer than the audio they rendered.",
+            load(&state.xruns),
+        );
+        metrics.counter(
+            "deejay_audio_stream_errors_total",
+            "Errors reported by the output stream.",
+            load(&state.stream_errors),
+        );
+        metrics.counter(
+            "deejay_deck_underruns_total",
+            "Deck ring buffer underruns.",
+            load(&state.deck_underruns),
+        );
+        metrics.counter(
+            "deejay_input_dropped_frames_total",
+            "Captured frames dropped because an input deck's ring was full.",
+            load(&state.input_dropped_frames),
+        );
+        metrics.counter(
+            "deejay_parameter_queue_drops_total",
+            "Parameter updates refused because the queue to the audio thread was full.",
+            self.params.dropped(),
+        );
+    }
+}
+
+/// Lets the Link session publish its peer count into [`EngineStats`].
+#[derive(Clone)]
+pub struct LinkIndicator {
+    state: Arc<EngineState>,
+}
+
+impl LinkIndicator {
+    /// Show `peers`, or `None` once Link is disabled.
+    pub fn set(&self, peers: Option<u64>) {
+        let value = peers.map_or(0, |peers| peers.saturating_add(1));
+        self.state.link_peers.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Reports stream failures to the control thread and the crash breadcrumbs.
+#[derive(Clone)]
+pub struct ErrorSink {
+    events: Arc<ArrayQueue<EngineEvent>>,
+    state: Arc<EngineState>,
+}
+
+impl ErrorSink {
+    pub fn stream_error(&self, message: impl Into<String>) {
+        let message = message.into();
+        record_breadcrumb(format!("audio stream error: {message}"));
+        self.state.stream_errors.fetch_add(1, Ordering::Relaxed);
+        let _ = self.events.push(EngineEvent::StreamError(message));
+    }
+
+    /// Report an error that means the device is gone, so recovery need not wait
+    /// for the heartbeat to run out.
+    pub fn device_lost(&self, message: impl Into<String>) {
+        self.state.device_lost.store(true, Ordering::Release);
+        self.stream_error(message);
+    }
+}
+
+/// Audio-side end of a deck: the consumer half of its stereo ring.
+pub struct DeckHandle {
+    ring: RingConsumer,
+    /// Capture rate and counters when the deck is fed by a live input.
+    input: Option<(u32, Arc<InputCounters>)>,
+}
+
+impl DeckHandle {
+    pub fn new(ring: RingConsumer) -> Self {
+        debug_assert_eq!(ring.channels(), 2, "deck rings carry stereo frames");
+        Self { ring, input: None }
+    }
+}
+
+/// Consumers of the master mix that can be attached to a running engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapSlot {
+    Recording = 0,
+    Broadcast = 1,
+}
+
+impl TapSlot {
+    const COUNT: usize = 2;
+}
+
+/// Body of the output data callback: pulls deck rings, mixes and fans out to the device layout.
+///
+/// Dropping it, as a dead stream does, hands the mixer and its bus state back
+/// to the engine so a replacement stream carries on where it stopped.
+pub struct MixCallback {
+    mixer: Option<Box<Mixer>>,
+    home: Arc<ArrayQueue<Box<Mixer>>>,
+}
+
+impl MixCallback {
+    fn new(mixer: Box<Mixer>, home: Arc<ArrayQueue<Box<Mixer>>>) -> Self {
+        Self {
+            mixer: Some(mixer),
+            home,
+        }
+    }
+
+    /// Render interleaved output for a device with `channels` channels; extra channels stay silent.
+    pub fn process(&mut self, output: &mut [f32]) {
+        match &mut self.mixer {
+            Some(mixer) => mixer.process(output),
+            None => output.fill(0.0),
+        }
+    }
+}
+
+impl Drop for MixCallback {
+    fn drop(&mut self) {
+        if let Some(mixer) = self.mixer.take() {
+            // The home holds exactly one mixer, so there is always room.
+            let _ = self.home.push(mixer);
+        }
+    }
+}
+
+/// State of the mix that outlives any one output stream.
+struct Mixer {
+    bus: SummingBus,
+    decks: Vec<DeckHandle>,
+    deck_a: Vec<f32>,
+    deck_b: Vec<f32>,
+    mix: Vec<f32>,
+    channels: usize,
+    state: Arc<EngineState>,
+    /// Tees of the post-master mix, indexed by [`TapSlot`].
+    taps: [Option<RecordTap>; TapSlot::COUNT],
+    tap_requests: Arc<ArrayQueue<(TapSlot, Option<RecordTap>)>>,
+    /// Live mic summed into the master with talkover.
+    mic: Option<DeckHandle>,
+    mic_buffer: Vec<f32>,
+    mic_requests: Arc<ArrayQueue<Option<DeckHandle>>>,
+    /// Hand-over to the cue device's stream when the cue plays on a second card.
+    cue: Option<CueFeed>,
+    cue_buffer: Vec<f32>,
+    /// R128 meter on the post-master mix.
+    loudness: LoudnessMeter,
+    loudness_reader: LoudnessReader,
+    /// Frames left of the fade-in after a device swap, and its length.
+    fade: (u32, u32),
+}
+
+impl Mixer {
+    fn new(
+        bus: SummingBus,
+        decks: Vec<DeckHandle>,
+        channels: u16,
+        state: Arc<EngineState>,
+    ) -> Self {
+        let (loudness, loudness_reader) = loudness_meter(bus.sample_rate);
+        Self {
+            bus,
+            decks,
+            deck_a: vec![0.0; MAX_BLOCK_FRAMES * 2],
+            deck_b: vec![0.0; MAX_BLOCK_FRAMES * 2],
+            mix: vec![0.0; MAX_BLOCK_FRAMES * 2],
+            channels: channels as usize,
+            state,
+            taps: Default::default(),
+            tap_requests: Arc::new(ArrayQueue::new(TAP_QUEUE_CAPACITY)),
+            mic: None,
+            mic_buffer: vec![0.0; MAX_BLOCK_FRAMES * 2],
+            mic_requests: Arc::new(ArrayQueue::new(MIC_QUEUE_CAPACITY)),
+            cue: None,
+            cue_buffer: vec![0.0; MAX_BLOCK_FRAMES * 2],
+            loudness,
+            loudness_reader,
+            fade: (0, 1),
+        }
+    }
+
+    /// Ramp the master up from silence over the next `frames`.
+    fn fade_in(&mut self, frames: u32) {
+        self.fade = (frames, frames.max(1));
+    }
+
+    fn process(&mut self, output: &mut [f32]) {
+        let start = Instant::now();
+        let mut peak = [0.0f32; 2];
+        while let Some((slot, tap)) = self.tap_requests.pop() {
+            self.taps[slot as usize] = tap;
+        }
+        while let Some(mic) = self.mic_requests.pop() {
+            self.mic = mic;
+        }
+
+        for chunk in output.chunks_mut(MAX_BLOCK_FRAMES * self.channels) {
+            let frames = chunk.len() / self.channels;
+            let len = frames * 2;
+            for (index, buffer) in [&mut self.deck_a, &mut self.deck_b].into_iter().enumerate() {
+                match self.decks.get_mut(index) {
+                    Some(deck) => {
+                        deck.ring.read(&mut buffer[..len]);
+                    }
+                    None => buffer[..len].fill(0.0),
+                }
+            }
+
+            let mic = self.mic.as_mut().map(|mic| {
+                mic.ring.read(&mut self.mic_buffer[..len]);
+                &self.mic_buffer[..len]
+            });
+            let mix = &mut self.mix[..len];
+            let cue_mix = self.cue.is_some().then(|| &mut self.cue_buffer[..len]);
+            self.bus.process_with_cue(
+                &mut self.deck_a[..len],
+                &mut self.deck_b[..len],
+                mic,
+                mix,
+                cue_mix,
+            );
+            if self.fade.0 > 0 {
+                let (left, length) = &mut self.fade;
+                for frame in mix.chunks_exact_mut(2) {
+                    let gain = 1.0 - *left as f32 / *length as f32;
+                    frame[0] *= gain;
+                    frame[1] *= gain;
+                    *left = left.saturating_sub(1);
+                }
+            }
+            self.loudness.process(mix);
+            for tap in self.taps.iter_mut().flatten() {
+                tap.push(mix);
+            }
+            if let Some(cue) = &mut self.cue {
+                cue.push(&self.cue_buffer[..len]);
+            }
+
+            for (out, frame) in chunk
+                .chunks_exact_mut(self.channels)
+                .zip(mix.chunks_exact(2))
+            {
+                out[0] = frame[0];
+                out[1] = frame[1];
+                out[2..].fill(0.0);
+                peak[0] = peak[0].max(frame[0].abs());
+                peak[1] = peak[1].max(frame[1].abs());
+            }
+        }
+
+        let underruns = self.decks.iter().map(|deck| deck.ring.underruns()).sum();
+        let (dropped, overruns) = self
+            .decks
+            .iter()
+            .chain(&self.mic)
+            .filter_map(|deck| deck.input.as_ref())
+            .fold((0, 0), |(dropped, overruns), (_, counters)| {
+                (
+                    dropped + counters.dropped_frames.load(Ordering::Relaxed),
+                    overruns + counters.overruns.load(Ordering::Relaxed),
+                )
+            });
+        let state = &self.state;
+        state.input_dropped_frames.store(dropped, Ordering::Relaxed);
+        state.input_overruns.store(overruns, Ordering::Relaxed);
+        state.callbacks.fetch_add(1, Ordering::Relaxed);
+        state
+            .frames
+            .fetch_add((output.len() / self.channels) as u64, Ordering::Relaxed);
+        state.deck_underruns.store(underruns, Ordering::Relaxed);
+        state.peak_left.store(peak[0].to_bits(), Ordering::Relaxed);
+        state.peak_right.store(peak[1].to_bits(), Ordering::Relaxed);
+        let elapsed = start.elapsed();
+        let deadline = (output.len() / self.channels) as f64 / self.bus.sample_rate as f64;
+        if elapsed.as_secs_f64() > deadline {
+            state.xruns.fetch_add(1, Ordering::Relaxed);
+        }
+        state.callback_durations.record(elapsed);
+        state
+            .last_callback_nanos
+            .store(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// A running input or output stream.
+pub trait AudioStream {
+    fn play(&self) -> Result<(), EngineError>;
+    fn pause(&self) -> Result<(), EngineError>;
+}
+
+/// Device access used by [`AudioEngine`]; implemented with cpal behind the `audio` feature.
+pub trait StreamBackend {
+    /// Output configurations offered by the device selected in `settings`.
+    fn supported_configs(&self, settings: &Settings) -> Result<Vec<SupportedConfig>, EngineError>;
+
+    /// Exclusive-mode capabilities of the selected device. Backends that can
+    /// only open shared streams keep the default refusal.
+    fn exclusive_capabilities(
+        &self,
+        _settings: &Settings,
+    ) -> Result<ExclusiveCapabilities, EngineError> {
+        Err(EngineError::ExclusiveModeDenied(
+            "this audio backend only opens shared-mode streams".into(),
+        ))
+    }
+
+    /// Open (but do not start) an output stream driving `callback`.
+    fn build_output(
+        &self,
+        settings: &Settings,
+        config: &NegotiatedConfig,
+        callback: MixCallback,
+        errors: ErrorSink,
+    ) -> Result<Box<dyn AudioStream>, EngineError>;
+
+    /// Output configurations offered by `settings.cue_device`. Backends without
+    /// a second device keep the default refusal.
+    fn supported_cue_configs(
+        &self,
+        _settings: &Settings,
+    ) -> Result<Vec<SupportedConfig>, EngineError> {
+        Err(EngineError::Backend(
+            "this audio backend cannot open a cue device".into(),
+        ))
+    }
+
+    /// Open (but do not start) a stream on the cue device driving `callback`.
+    fn build_cue(
+        &self,
+        _settings: &Settings,
+        _config: &NegotiatedConfig,
+        _callback: CueCallback,
+    ) -> Result<Box<dyn AudioStream>, EngineError> {
+        Err(EngineError::Backend(
+            "this audio backend cannot open a cue device".into(),
+        ))
+    }
+}
+
+/// Owns the output stream that feeds decks through the [`SummingBus`].
+pub struct AudioEngine;
+
+impl AudioEngine {
+    /// Open the device from `settings` with cpal and start mixing `decks`.
+    #[cfg(feature = "audio")]
+    pub fn start(settings: &Settings, decks: Vec<DeckHandle>) -> Result<EngineHandle, EngineError> {
+        Self::start_with(&CpalBackend, settings, decks)
+    }
+
+    /// Negotiate a configuration with `backend`, wire up the bus and start the stream.
+    pub fn start_with<B: StreamBackend>(
+        backend: &B,
+        settings: &Settings,
+        decks: Vec<DeckHandle>,
+    ) -> Result<EngineHandle, EngineError> {
+        if decks.len() > BUS_DECKS {
+            return Err(EngineError::UnsupportedDeckCount(decks.len()));
+        }
+
+        let events = Arc::new(ArrayQueue::new(EVENT_QUEUE_CAPACITY));
+        let exclusive = if settings.exclusive_mode {
+            match backend.exclusive_capabilities(settings).and_then(|caps| {
+                plan_exclusive(settings.sample_rate, settings.buffer_frames, &caps)
+            }) {
+                Ok(config) => Some(config),
+                Err(EngineError::ExclusiveModeDenied(reason)) => {
+                    let warning =
+                        format!("exclusive mode unavailable, using shared mode: {reason}");
+                    record_breadcrumb(warning.clone());
+                    let _ = events.push(EngineEvent::Warning(warning));
+                    None
+                }
+                Err(err) => return Err(err),
+            }
+        } else {
+            None
+        };
+        let config = match exclusive {
+            Some(config) => config,
+            None => {
+                let supported = backend.supported_configs(settings)?;
+                negotiate(settings.sample_rate, settings.buffer_frames, &supported)?
+            }
+        };
+
+        if let Some(input) = decks
+            .iter()
+            .filter_map(|deck| deck.input.as_ref())
+            .find(|(rate, _)| *rate != config.sample_rate)
+        {
+            return Err(EngineError::SampleRateMismatch {
+                input: input.0,
+                output: config.sample_rate,
+            });
+        }
+
+        let (params, receiver) = parameter_channel(PARAMETER_QUEUE_CAPACITY);
+        let state = Arc::new(EngineState::default());
+        let mut bus = SummingBus::new(receiver).with_sample_rate(config.sample_rate);
+        bus.set_smoothing_ms(settings.smoothing_ms as f32);
+        let limiter = settings.master_limiter.then(|| {
+            let limiter = Limiter::new(config.sample_rate, LimiterOptions::default());
+            let reader = limiter.reader();
+            bus.set_limiter(Some(limiter));
+            reader
+        });
+        let deck_chains = [DeckId::A, DeckId::B].map(|deck| {
+            let (chain, handle) = FxChain::new(config.sample_rate, MAX_BLOCK_FRAMES);
+            bus.set_deck_fx(deck, Some(Box::new(chain)));
+            handle
+        });
+        let (sampler, sampler_handle) = Sampler::new(config.sample_rate);
+        bus.set_sampler(sampler);
+        let faders = bus.fader_reader();
+        let mut mixer = Box::new(Mixer::new(bus, decks, config.channels, state.clone()));
+        let cue = match settings.cue_device {
+            Some(_) => {
+                let supported = backend.supported_cue_configs(settings)?;
+                let cue_config = negotiate(config.sample_rate, settings.buffer_frames, &supported)?;
+                if cue_config.sample_rate != config.sample_rate {
+                    return Err(EngineError::CueSampleRateMismatch {
+                        cue: cue_config.sample_rate,
+                        master: config.sample_rate,
+                    });
+                }
+                let (feed, cue_callback, monitor) =
+                    cue_link(cue_target_frames(&config, &cue_config), cue_config.channels);
+                mixer.cue = Some(feed);
+                Some((
+                    backend.build_cue(settings, &cue_config, cue_callback)?,
+                    monitor,
+                ))
+            }
+            None => None,
+        };
+        let tap_requests = mixer.tap_requests.clone();
+        let mic_requests = mixer.mic_requests.clone();
+        let loudness = mixer.loudness_reader.clone();
+        let errors = ErrorSink {
+            events: events.clone(),
+            state: state.clone(),
+        };
+        let mixer_home = Arc::new(ArrayQueue::new(MIXER_HOME_CAPACITY));
+        let callback = MixCallback::new(mixer, mixer_home.clone());
+
+        let stream = backend.build_output(settings, &config, callback, errors.clone())?;
+        stream.play()?;
+        // The master is the clock the cue follows, so it starts first.
+        if let Some((cue_stream, _)) = &cue {
+            cue_stream.play()?;
+        }
+        Ok(EngineHandle {
+            stream: Some(stream),
+            mixer_home,
+            errors,
+            settings: settings.clone(),
+            watchdog: Watchdog::new(
+                RecoveryOptions::default().with_settings(settings),
+                Instant::now(),
+            ),
+            cue,
+            params,
+            state,
+            events,
+            tap_requests,
+            mic_requests,
+            deck_chains,
+            sampler: sampler_handle,
+            loudness,
+            limiter,
+            faders,
+            config,
+        })
+    }
+}
+
+/// Control-side handle to a running engine. Dropping it closes the stream.
+pub struct EngineHandle {
+    /// `None` while the output device is lost.
+    stream: Option<Box<dyn AudioStream>>,
+    /// Where the mixer waits between a lost stream and its replacement.
+    mixer_home: Arc<ArrayQueue<Box<Mixer>>>,
+    errors: ErrorSink,
+    settings: Settings,
+    watchdog: Watchdog,
+    /// Stream on the cue device, when the cue plays on a second card.
+    cue: Option<(Box<dyn AudioStream>, CueMonitor)>,
+    params: ParameterSender,
+    state: Arc<EngineState>,
+    events: Arc<ArrayQueue<EngineEvent>>,
+    tap_requests: Arc<ArrayQueue<(TapSlot, Option<RecordTap>)>>,
+    mic_requests: Arc<ArrayQueue<Option<DeckHandle>>>,
+    deck_chains: [FxChainHandle; BUS_DECKS],
+    sampler: SamplerHandle,
+    loudness: LoudnessReader,
+    limiter: Option<GainReductionReader>,
+    faders: FaderReader,
+    config: NegotiatedConfig,
+}
+
+impl EngineHandle {
+    /// Sender for mixer parameter updates.
+    pub fn parameters(&self) -> ParameterSender {
+        self.params.clone()
+    }
+
+    /// Effect chain inserted before `deck`'s fader.
+    pub fn deck_chain(&self, deck: DeckId) -> &FxChainHandle {
+        &self.deck_chains[deck.index()]
+    }
+
+    /// One-shot bank summed into the master after the crossfader.
+    pub fn sampler(&self) -> &SamplerHandle {
+        &self.sampler
+    }
+
+    /// Configuration actually obtained from the device.
+    pub fn config(&self) -> NegotiatedConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> EngineStats {
+        let state = &self.state;
+        EngineStats {
+            callbacks: state.callbacks.load(Ordering::Relaxed),
+            frames_rendered: state.frames.load(Ordering::Relaxed),
+            deck_underruns: state.deck_underruns.load(Ordering::Relaxed),
+            input_dropped_frames: state.input_dropped_frames.load(Ordering::Relaxed),
+            input_overruns: state.input_overruns.load(Ordering::Relaxed),
+            stream_errors: state.stream_errors.load(Ordering::Relaxed),
+            xruns: state.xruns.load(Ordering::Relaxed),
+            last_callback_nanos: state.last_callback_nanos.load(Ordering::Relaxed),
+            peak: [
+                f32::from_bits(state.peak_left.load(Ordering::Relaxed)),
+                f32::from_bits(state.peak_right.load(Ordering::Relaxed)),
+            ],
+            link_peers: state.link_peers.load(Ordering::Relaxed).checked_sub(1),
+        }
+    }
+
+    /// Collector for the engine's counters, for a metrics [`Registry`](crate::metrics::Registry).
+    pub fn metrics(&self) -> EngineMetrics {
+        EngineMetrics {
+            state: self.state.clone(),
+            params: self.params.clone(),
+        }
+    }
+
+    /// Writer for the Link peer count shown in [`EngineHandle::stats`].
+    pub fn link_indicator(&self) -> LinkIndicator {
+        LinkIndicator {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Latency, drift and dropouts of the cue device, when one is open.
+    pub fn cue_stats(&self) -> Option<CueStats> {
+        self.cue.as_ref().map(|(_, monitor)| monitor.stats())
+    }
+
+    /// EBU R128 loudness of the master mix.
+    pub fn loudness(&self) -> LoudnessReading {
+        self.loudness.reading()
+    }
+
+    /// Gain reduction of the master limiter over the last block, in dB, when
+    /// [`Settings::master_limiter`] is on.
+    pub fn limiter_reduction_db(&self) -> Option<f32> {
+        self.limiter.as_ref().map(GainReductionReader::reduction_db)
+    }
+
+    /// Gain each deck currently reaches the master with, e.g. to tell if it is audible.
+    pub fn fader_gains(&self) -> [f32; 2] {
+        self.faders.gains()
+    }
+
+    /// Restart integrated loudness, e.g. at the start of a set.
+    pub fn reset_loudness(&self) {
+        self.loudness.reset();
+    }
+
+    /// Start teeing the master mix into `tap`, replacing whatever fed `slot`.
+    /// Returns the tap if the request queue is full.
+    pub fn attach_tap(&self, slot: TapSlot, tap: RecordTap) -> Result<(), RecordTap> {
+        self.tap_requests
+            .push((slot, Some(tap)))
+            .map_err(|(_, tap)| tap.expect("attach requests carry a tap"))
+    }
+
+    /// Stop feeding `slot`; call before stopping the recorder or stream behind it.
+    pub fn detach_tap(&self, slot: TapSlot) -> bool {
+        self.tap_requests.push((slot, None)).is_ok()
+    }
+
+    /// Sum `mic` into the master with talkover ducking, replacing any previous mic.
+    pub fn attach_mic(&self, mic: DeckHandle) -> Result<(), EngineError> {
+        if let Some((rate, _)) = mic.input.as_ref() {
+            if *rate != self.config.sample_rate {
+                return Err(EngineError::SampleRateMismatch {
+                    input: *rate,
+                    output: self.config.sample_rate,
+                });
+            }
+        }
+        self.mic_requests
+            .push(Some(mic))
+            .map_err(|_| EngineError::Backend("mic request queue is full".into()))
+    }
+
+    pub fn detach_mic(&self) -> bool {
+        self.mic_requests.push(None).is_ok()
+    }
+
+    pub fn poll_event(&self) -> Option<EngineEvent> {
+        self.events.pop()
+    }
+
+    /// Replace the recovery timings, e.g. for a slower USB hub.
+    pub fn set_recovery(&mut self, options: RecoveryOptions) {
+        self.watchdog = Watchdog::new(options, Instant::now());
+    }
+
+    /// Whether the output device is gone and not yet reopened.
+    pub fn is_device_lost(&self) -> bool {
+        self.watchdog.is_lost()
+    }
+
+    /// [`supervise_with`](Self::supervise_with) reopening devices with cpal.
+    #[cfg(feature = "audio")]
+    pub fn supervise(&mut self, now: Instant) {
+        self.supervise_with(&CpalBackend, now)
+    }
+
+    /// Watch the output stream and bring it back when its device goes away.
+    /// Call regularly from the control thread, e.g. on the UI tick.
+    ///
+    /// A stream error naming the device gone, or callbacks stopping for the
+    /// heartbeat timeout, tears the stream down. The same device is then
+    /// reopened with exponential backoff, and with `fallback_to_default` the
+    /// system default is tried as well after a few failures. Bus parameters,
+    /// effects and taps live in the mixer, which moves to the new stream;
+    /// the decks keep filling their rings meanwhile, so the mix resumes
+    /// where it stopped behind a short fade-in.
+    pub fn supervise_with<B: StreamBackend>(&mut self, backend: &B, now: Instant) {
+        let gone = self.state.device_lost.swap(false, Ordering::AcqRel);
+        let callbacks = self.state.callbacks.load(Ordering::Relaxed);
+        if self.watchdog.check(callbacks, gone, now) {
+            if let Some(stream) = self.stream.take() {
+                // The device is gone either way; dropping the stream frees the mixer.
+                let _ = stream.pause();
+            }
+            self.notify(EngineEvent::DeviceLost, "output device lost".into());
+        }
+
+        let targets = self.watchdog.due(now);
+        if targets.is_empty() {
+            return;
+        }
+        for &target in targets {
+            if target == Target::Default && self.settings.device == "default" {
+                continue;
+            }
+            match self.reopen(backend, target) {
+                Ok(device) => {
+                    self.watchdog
+                        .recovered(self.state.callbacks.load(Ordering::Relaxed), now);
+                    self.notify(
+                        EngineEvent::Recovered {
+                            device: device.clone(),
+                        },
+                        format!("output resumed on {device}"),
+                    );
+                    if target == Target::Default {
+                        self.notify(
+                            EngineEvent::FallbackActive,
+                            format!(
+                                "{} is gone, playing on the default device",
+                                self.settings.device
+                            ),
+                        );
+                    }
+                    return;
+                }
+                Err(err) => record_breadcrumb(format!("reopening the output failed: {err}")),
+            }
+        }
+        self.watchdog.failed(now);
+    }
+
+    /// Build and start a stream on `target` for the mixer of the lost one.
+    fn reopen<B: StreamBackend>(
+        &mut self,
+        backend: &B,
+        target: Target,
+    ) -> Result<String, EngineError> {
+        let settings = match target {
+            Target::Configured => self.settings.clone(),
+            Target::Default => Settings {
+                device: "default".into(),
+                ..self.settings.clone()
+            },
+        };
+        let supported = backend.supported_configs(&settings)?;
+        let config = negotiate(self.config.sample_rate, settings.buffer_frames, &supported)?;
+        // The bus, effects and decks all run at the session rate.
+        if config.sample_rate != self.config.sample_rate {
+            return Err(EngineError::Backend(format!(
+                "{} cannot run at {} Hz",
+                settings.device, self.config.sample_rate
+            )));
+        }
+        let mut mixer = self.mixer_home.pop().ok_or_else(|| {
+            EngineError::Backend("the lost stream has not released the mixer yet".into())
+        })?;
+        mixer.channels = config.channels as usize;
+        let fade = self.watchdog.options().fade_in.as_secs_f64() * config.sample_rate as f64;
+        mixer.fade_in(fade as u32);
+        // On failure the callback is dropped and the mixer returns home for the next try.
+        let callback = MixCallback::new(mixer, self.mixer_home.clone());
+        let stream = backend.build_output(&settings, &config, callback, self.errors.clone())?;
+        stream.play()?;
+        self.stream = Some(stream);
+        self.config = config;
+        Ok(settings.device)
+    }
+
+    fn notify(&self, event: EngineEvent, breadcrumb: String) {
+        record_breadcrumb(breadcrumb);
+        let _ = self.events.push(event);
+    }
+
+    /// Pause and close the streams.
+    pub fn stop(self) -> Result<(), EngineError> {
+        if let Some((cue_stream, _)) = &self.cue {
+            cue_stream.pause()?;
+        }
+        match &self.stream {
+            Some(stream) => stream.pause(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crash::breadcrumbs;
+    use crate::fx::{ChainCommand, FilterFx};
+    use crate::record::{Recorder, RecorderOptions};
+    use crate::ring::AudioRing;
+    use crate::ParameterUpdate;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    fn f32_config(
+        channels: u16,
+        min: u32,
+        max: u32,
+        buffer: Option<(u32, u32)>,
+    ) -> SupportedConfig {
+        SupportedConfig {
+            channels,
+            min_sample_rate: min,
+            max_sample_rate: max,
+            buffer_frames: buffer,
+            sample_format: SampleFormat::F32,
+        }
+    }
+
+    #[test]
+    fn negotiates_nearest_supported_config() {
+        let supported = [
+            f32_config(8, 44_100, 192_000, Some((64, 2_048))),
+            f32_config(2, 44_100, 48_000, Some((128, 1_024))),
+            SupportedConfig {
+                sample_format: SampleFormat::I16,
+                ..f32_config(2, 8_000, 192_000, None)
+            },
+        ];
+
+        let exact = negotiate(48_000, 64, &supported).unwrap();
+        assert_eq!(
+            exact,
+            NegotiatedConfig {
+                channels: 2,
+                sample_rate: 48_000,
+                buffer_frames: Some(128),
+                sample_format: SampleFormat::F32,
+                exclusive: false,
+            }
+        );
+
+        let high = negotiate(96_000, 256, &supported).unwrap();
+        assert_eq!((high.channels, high.sample_rate), (8, 96_000));
+
+        let low = negotiate(22_050, 512, &supported[1..]).unwrap();
+        assert_eq!(low.sample_rate, 44_100);
+
+        let unknown_buffer =
+            negotiate(48_000, 512, &[f32_config(2, 48_000, 48_000, None)]).unwrap();
+        assert_eq!(unknown_buffer.buffer_frames, None);
+
+        assert!(matches!(
+            negotiate(48_000, 512, &supported[2..]),
+            Err(EngineError::NoSupportedConfig)
+        ));
+    }
+
+    struct FakeStream {
+        playing: Arc<AtomicBool>,
+    }
+
+    impl AudioStream for FakeStream {
+        fn play(&self) -> Result<(), EngineError> {
+            self.playing.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn pause(&self) -> Result<(), EngineError> {
+            self.playing.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Backend that hands the callback and error sink back to the test to drive by hand.
+    #[derive(Default)]
+    struct FakeBackend {
+        callback: Mutex<Option<MixCallback>>,
+        errors: Mutex<Option<ErrorSink>>,
+        playing: Arc<AtomicBool>,
+        cue_configs: Vec<SupportedConfig>,
+        cue: Mutex<Option<CueCallback>>,
+        /// Upcoming output builds that fail, whatever the device.
+        failures: AtomicUsize,
+        /// Device whose builds always fail.
+        dead_device: Mutex<Option<String>>,
+        /// Device of every output build attempted.
+        opened: Mutex<Vec<String>>,
+    }
+
+    impl StreamBackend for FakeBackend {
+        fn supported_configs(&self, _: &Settings) -> Result<Vec<SupportedConfig>, EngineError> {
+            Ok(vec![f32_config(4, 44_100, 48_000, Some((32, 4_096)))])
+        }
+
+        fn build_output(
+            &self,
+            settings: &Settings,
+            _: &NegotiatedConfig,
+            callback: MixCallback,
+            errors: ErrorSink,
+        ) -> Result<Box<dyn AudioStream>, EngineError> {
+            self.opened.lock().unwrap().push(settings.device.clone());
+            let scripted = self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if scripted || self.dead_device.lock().unwrap().as_ref() == Some(&settings.device) {
+                return Err(EngineError::DeviceNotFound(settings.device.clone()));
+            }
+            *self.callback.lock().unwrap() = Some(callback);
+            *self.errors.lock().unwrap() = Some(errors);
+            Ok(Box::new(FakeStream {
+                playing: self.playing.clone(),
+            }))
+        }
+
+        fn supported_cue_configs(&self, _: &Settings) -> Result<Vec<SupportedConfig>, EngineError> {
+            Ok(self.cue_configs.clone())
+        }
+
+        fn build_cue(
+            &self,
+            _: &Settings,
+            _: &NegotiatedConfig,
+            callback: CueCallback,
+        ) -> Result<Box<dyn AudioStream>, EngineError> {
+            *self.cue.lock().unwrap() = Some(callback);
+            Ok(Box::new(FakeStream {
+                playing: Arc::default(),
+            }))
+        }
+    }
+
+    #[test]
+    fn callback_mixes_deck_rings_into_device_layout() {
+        let (mut producer_a, consumer_a) = AudioRing::with_capacity_frames(1_024, 2).split();
+        let (mut producer_b, consumer_b) = AudioRing::with_capacity_frames(1_024, 2).split();
+        producer_a.write(&[0.5; 64]);
+        producer_b.write(&[0.25; 32]);
+
+        let backend = FakeBackend::default();
+        // Without the glide, so the new crossfader applies from the first frame.
+        let settings = Settings {
+            smoothing_ms: 0,
+            ..Settings::default()
+        };
+        let handle = AudioEngine::start_with(
+            &backend,
+            &settings,
+            vec![DeckHandle::new(consumer_a), DeckHandle::new(consumer_b)],
+        )
+        .unwrap();
+        assert!(backend.playing.load(Ordering::SeqCst));
+        assert_eq!(handle.config().channels, 4);
+        assert_eq!(handle.config().buffer_frames, Some(512));
+
+        handle
+            .parameters()
+            .send(ParameterUpdate::Crossfader(0.0))
+            .unwrap();
+        let mut callback = backend.callback.lock().unwrap().take().unwrap();
+        let mut output = vec![1.0; 32 * 4];
+        callback.process(&mut output);
+
+        for (i, frame) in output.chunks_exact(4).enumerate() {
+            assert_eq!(frame, [0.5, 0.5, 0.0, 0.0], "frame {i}");
+        }
+        let stats = handle.stats();
+        assert_eq!(stats.callbacks, 1);
+        assert_eq!(stats.frames_rendered, 32);
+        assert_eq!(stats.peak, [0.5, 0.5]);
+        // Deck B only had 16 frames buffered.
+        assert_eq!(stats.deck_underruns, 1);
+
+        handle.stop().unwrap();
+        assert!(!backend.playing.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn tees_master_mix_into_attached_recorder() {
+        let dir = tempfile::tempdir().unwrap();
+        let (recorder, tap) =
+            Recorder::start(dir.path().join("set.wav"), RecorderOptions::default()).unwrap();
+        let backend = FakeBackend::default();
+        let handle = AudioEngine::start_with(&backend, &Settings::default(), Vec::new()).unwrap();
+        let mut callback = backend.callback.lock().unwrap().take().unwrap();
+
+        let mut output = vec![0.0; 64 * 4];
+        callback.process(&mut output);
+        assert!(handle.attach_tap(TapSlot::Recording, tap).is_ok());
+        callback.process(&mut output);
+        callback.process(&mut output);
+        assert!(handle.detach_tap(TapSlot::Recording));
+        callback.process(&mut output);
+
+        assert_eq!(recorder.stop().unwrap().frames, 128);
+    }
+
+    #[test]
+    fn metrics_report_callbacks_and_parameter_drops() {
+        let backend = FakeBackend::default();
+        let handle = AudioEngine::start_with(&backend, &Settings::default(), Vec::new()).unwrap();
+        let mut callback = backend.callback.lock().unwrap().take().unwrap();
+        for _ in 0..3 {
+            callback.process(&mut vec![0.0; 64 * 4]);
+        }
+        let params = handle.parameters();
+        while params.send(ParameterUpdate::MasterGain(1.0)).is_ok() {}
+
+        let registry = crate::metrics::Registry::new();
+        registry.register("engine", handle.metrics());
+        let samples = crate::metrics::tests::parse_exposition(&registry.render());
+        let value = |name: &str| {
+            samples
+                .iter()
+                .find(|(sample, _, _)| sample == name)
+                .map(|(_, _, value)| *value)
+                .unwrap()
+        };
+        assert_eq!(value("deejay_audio_callbacks_total"), 3.0);
+        assert_eq!(value("deejay_callback_duration_seconds_count"), 3.0);
+        assert_eq!(value("deejay_parameter_queue_drops_total"), 1.0);
+        assert_eq!(value("deejay_xruns_total"), handle.stats().xruns as f64);
+    }
+
+    #[test]
+    fn link_peers_show_in_stats_while_enabled() {
+        let backend = FakeBackend::default();
+        let handle = AudioEngine::start_with(&backend, &Settings::default(), Vec::new()).unwrap();
+        assert_eq!(handle.stats().link_peers, None);
+
+        let indicator = handle.link_indicator();
+        indicator.set(Some(0));
+        assert_eq!(handle.stats().link_peers, Some(0));
+        indicator.set(Some(3));
+        assert_eq!(handle.stats().link_peers, Some(3));
+        indicator.set(None);
+        assert_eq!(handle.stats().link_peers, None);
+    }
+
+    #[test]
+    fn deck_chain_commands_reach_the_callback() {
+        let backend = FakeBackend::default();
+        let handle = AudioEngine::start_with(&backend, &Settings::default(), Vec::new()).unwrap();
+        let mut callback = backend.callback.lock().unwrap().take().unwrap();
+
+        let chain = handle.deck_chain(DeckId::B);
+        chain
+            .send(ChainCommand::Insert {
+                slot: 0,
+                fx: Box::new(FilterFx::new(48_000)),
+            })
+            .unwrap();
+        chain.send(ChainCommand::Remove { slot: 0 }).unwrap();
+        assert_eq!(chain.collect_retired(), 0);
+        callback.process(&mut vec![0.0; 64 * 4]);
+        assert_eq!(chain.collect_retired(), 1);
+    }
+
+    #[test]
+    fn attached_mic_is_summed_into_the_master() {
+        let (mut producer, consumer) = AudioRing::with_capacity_frames(1_024, 2).split();
+        producer.write(&[0.25; 128]);
+        let backend = FakeBackend::default();
+        let handle = AudioEngine::start_with(&backend, &Settings::default(), Vec::new()).unwrap();
+        handle
+            .parameters()
+            .send(ParameterUpdate::MicLowCutHz(0.0))
+            .unwrap();
+        assert!(handle.attach_mic(DeckHandle::new(consumer)).is_ok());
+        let mut callback = backend.callback.lock().unwrap().take().unwrap();
+
+        let mut output = vec![0.0; 64 * 4];
+        callback.process(&mut output);
+        assert!(output.chunks_exact(4).all(|f| f == [0.25, 0.25, 0.0, 0.0]));
+
+        assert!(handle.detach_mic());
+        callback.process(&mut output);
+        assert!(output.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn cue_device_plays_the_cued_decks_pre_fader() {
+        let (mut producer_a, consumer_a) = AudioRing::with_capacity_frames(8_192, 2).split();
+        let (mut producer_b, consumer_b) = AudioRing::with_capacity_frames(8_192, 2).split();
+        producer_a.write(&[0.5; 8_192]);
+        producer_b.write(&[0.25; 8_192]);
+        let backend = FakeBackend {
+            cue_configs: vec![f32_config(2, 48_000, 48_000, Some((256, 256)))],
+            ..FakeBackend::default()
+        };
+        let settings = Settings {
+            cue_device: Some("Headphones".into()),
+            smoothing_ms: 0,
+            ..Settings::default()
+        };
+        let handle = AudioEngine::start_with(
+            &backend,
+            &settings,
+            vec![DeckHandle::new(consumer_a), DeckHandle::new(consumer_b)],
+        )
+        .unwrap();
+        let params = handle.parameters();
+        params.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        params
+            .send(ParameterUpdate::DeckGain {
+                deck: DeckId::A,
+                gain: 0.0,
+            })
+            .unwrap();
+        for deck in [DeckId::A, DeckId::B] {
+            params
+                .send(ParameterUpdate::DeckCue { deck, on: true })
+                .unwrap();
+        }
+        let mut callback = backend.callback.lock().unwrap().take().unwrap();
+        let mut cue = backend.cue.lock().unwrap().take().unwrap();
+
+        let mut output = vec![1.0; 512 * 4];
+        for _ in 0..4 {
+            callback.process(&mut output);
+        }
+        assert!(output.iter().all(|s| *s == 0.0));
+        let mut headphones = vec![0.0; 256 * 2];
+        cue.process(&mut headphones);
+        // Past the short fade-in of the cue switches.
+        assert!(headphones.iter().all(|s| (s - 0.75).abs() < 1e-3));
+        let stats = handle.cue_stats().unwrap();
+        assert_eq!(stats.target_frames, 512 + 256 + 256);
+        assert_eq!((stats.frames, stats.underruns), (256, 0));
+
+        let backend = FakeBackend {
+            cue_configs: vec![f32_config(2, 44_100, 44_100, None)],
+            ..FakeBackend::default()
+        };
+        assert!(matches!(
+            AudioEngine::start_with(&backend, &settings, Vec::new()),
+            Err(EngineError::CueSampleRateMismatch {
+                cue: 44_100,
+                master: 48_000
+            })
+        ));
+        assert!(
+            AudioEngine::start_with(&FakeBackend::default(), &Settings::default(), Vec::new())
+                .unwrap()
+                .cue_stats()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn stream_errors_reach_events_and_breadcrumbs() {
+        let backend = FakeBackend::default();
+        let handle = AudioEngine::start_with(&backend, &Settings::default(), Vec::new()).unwrap();
+
+        let errors = backend.errors.lock().unwrap().take().unwrap();
+        errors.stream_error("device disconnected");
+
+        assert_eq!(
+            handle.poll_event(),
+            Some(EngineEvent::StreamError("device disconnected".into()))
+        );
+        assert_eq!(handle.stats().stream_errors, 1);
+        assert!(breadcrumbs()
+            .iter()
+            .any(|crumb| crumb.ends_with("audio stream error: device disconnected")));
+    }
+
+    /// Poll `handle` every 10 ms from `start` up to `until` ms, noting when each
+    /// output build was attempted. Nothing drives the callbacks, so keep `until`
+    /// within a heartbeat of the recovery.
+    fn supervise_until(
+        handle: &mut EngineHandle,
+        backend: &FakeBackend,
+        start: Instant,
+        until: u64,
+    ) -> Vec<u64> {
+        let mut attempts = Vec::new();
+        let mut opened = backend.opened.lock().unwrap().len();
+        for at in (0..=until).step_by(10) {
+            handle.supervise_with(backend, start + std::time::Duration::from_millis(at));
+            let now_opened = backend.opened.lock().unwrap().len();
+            attempts.extend(std::iter::repeat_n(at, now_opened - opened));
+            opened = now_opened;
+        }
+        attempts
+    }
+
+    fn drain_events(handle: &EngineHandle) -> Vec<EngineEvent> {
+        std::iter::from_fn(|| handle.poll_event()).collect()
+    }
+
+    #[test]
+    fn lost_device_is_reopened_with_backoff_and_keeps_the_mix() {
+        let (mut producer, consumer) = AudioRing::with_capacity_frames(16_384, 2).split();
+        producer.write(&[0.5; 16_384]);
+        let backend = FakeBackend::default();
+        let settings = Settings {
+            device: "USB DAC".into(),
+            smoothing_ms: 0,
+            ..Settings::default()
+        };
+        let mut handle =
+            AudioEngine::start_with(&backend, &settings, vec![DeckHandle::new(consumer)]).unwrap();
+        handle.set_recovery(RecoveryOptions::default());
+        let start = Instant::now();
+        let params = handle.parameters();
+        params.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        params.send(ParameterUpdate::MasterGain(0.5)).unwrap();
+        let mut callback = backend.callback.lock().unwrap().take().unwrap();
+        let mut output = vec![0.0; 64 * 4];
+        callback.process(&mut output);
+        assert_eq!(&output[..4], [0.25, 0.25, 0.0, 0.0]);
+
+        // The interface re-enumerates: the stream reports it and lets go of its callback.
+        let errors = backend.errors.lock().unwrap().take().unwrap();
+        errors.device_lost("device unplugged");
+        drop(callback);
+        backend.failures.store(2, Ordering::SeqCst);
+        let attempts = supervise_until(&mut handle, &backend, start, 2_000);
+
+        assert_eq!(attempts, [250, 750, 1_750]);
+        assert_eq!(*backend.opened.lock().unwrap(), ["USB DAC"; 4]);
+        assert!(!handle.is_device_lost());
+        assert_eq!(
+            drain_events(&handle),
+            [
+                EngineEvent::StreamError("device unplugged".into()),
+                EngineEvent::DeviceLost,
+                EngineEvent::Recovered {
+                    device: "USB DAC".into()
+                },
+            ]
+        );
+        assert!(breadcrumbs()
+            .iter()
+            .any(|crumb| crumb.ends_with("output resumed on USB DAC")));
+
+        // The new stream fades in, then plays on with the same crossfader and master gain.
+        let mut callback = backend.callback.lock().unwrap().take().unwrap();
+        let mut output = vec![0.0; 4_096 * 4];
+        callback.process(&mut output);
+        assert_eq!(output[0], 0.0);
+        assert!(output[4 * 1_200] > 0.1 && output[4 * 1_200] < 0.15);
+        assert_eq!(&output[4 * 4_000..4 * 4_000 + 4], [0.25, 0.25, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn silent_device_falls_back_to_the_default() {
+        let backend = FakeBackend::default();
+        let settings = Settings {
+            device: "USB DAC".into(),
+            fallback_to_default: true,
+            ..Settings::default()
+        };
+        let mut handle = AudioEngine::start_with(&backend, &settings, Vec::new()).unwrap();
+        handle.set_recovery(RecoveryOptions::default().with_settings(&settings));
+        let start = Instant::now();
+        *backend.dead_device.lock().unwrap() = Some("USB DAC".into());
+        // No error, the callbacks just stop.
+        drop(backend.callback.lock().unwrap().take());
+
+        let attempts = supervise_until(&mut handle, &backend, start, 4_500);
+        assert_eq!(attempts, [750, 1_250, 2_250, 4_250, 4_250]);
+        assert_eq!(
+            backend.opened.lock().unwrap()[1..],
+            ["USB DAC", "USB DAC", "USB DAC", "USB DAC", "default"]
+        );
+        assert_eq!(
+            drain_events(&handle),
+            [
+                EngineEvent::DeviceLost,
+                EngineEvent::Recovered {
+                    device: "default".into()
+                },
+                EngineEvent::FallbackActive,
+            ]
+        );
+        assert!(backend.callback.lock().unwrap().is_some());
+    }
+
+    #[test]
+    fn exclusive_refusal_falls_back_to_shared_with_warning() {
+        let settings = Settings {
+            exclusive_mode: true,
+            ..Settings::default()
+        };
+        let handle =
+            AudioEngine::start_with(&FakeBackend::default(), &settings, Vec::new()).unwrap();
+
+        assert!(!handle.config().exclusive);
+        assert_eq!(handle.config().sample_rate, 48_000);
+        assert!(
+            matches!(handle.poll_event(), Some(EngineEvent::Warning(w)) if w.contains("shared mode"))
+        );
+    }
+
+    #[test]
+    fn rejects_input_decks_at_another_rate() {
+        let deck = DeckHandle {
+            ring: AudioRing::with_capacity_frames(8, 2).split().1,
+            input: Some((44_100, Arc::default())),
+        };
+        assert!(matches!(
+            AudioEngine::start_with(&FakeBackend::default(), &Settings::default(), vec![deck]),
+            Err(EngineError::SampleRateMismatch {
+                input: 44_100,
+                output: 48_000
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_more_decks_than_the_bus_mixes() {
+        let decks = (0..3)
+            .map(|_| DeckHandle::new(AudioRing::with_capacity_frames(8, 2).split().1))
+            .collect();
+        assert!(matches!(
+            AudioEngine::start_with(&FakeBackend::default(), &Settings::default(), decks),
+            Err(EngineError::UnsupportedDeckCount(3))
+        ));
+    }
+}
@@ -0,0 +1,382 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use super::cue::CueCallback;
+use super::input::{CaptureBackend, InputCapture};
+use super::preview::{PreviewBackend, PreviewCallback};
+use super::{
+    AudioStream, EngineError, ErrorSink, ExclusiveCapabilities, HostDevices, MixCallback,
+    NegotiatedConfig, SampleFormat, StreamBackend, SupportedConfig,
+};
+use crate::crash::record_breadcrumb;
+use crate::settings::Settings;
+
+/// [`StreamBackend`] backed by the platform's cpal hosts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpalBackend;
+
+impl CpalBackend {
+    fn host(settings: &Settings) -> Result<cpal::Host, EngineError> {
+        let Some(name) = &settings.host else {
+            return Ok(cpal::default_host());
+        };
+        let unavailable = || {
+            if is_asio(name) {
+                EngineError::AsioDriverMissing(
+                    "the ASIO host is not available in this build or on this platform".into(),
+                )
+            } else {
+                EngineError::HostUnavailable(name.clone())
+            }
+        };
+        let id = cpal::available_hosts()
+            .into_iter()
+            .find(|id| id.name().eq_ignore_ascii_case(name))
+            .ok_or_else(unavailable)?;
+        cpal::host_from_id(id).map_err(|_| unavailable())
+    }
+
+    /// Resolve `settings.input_device` the same way as the output device.
+    fn input_device(settings: &Settings) -> Result<cpal::Device, EngineError> {
+        let host = Self::host(settings)?;
+        let name = settings.input_device.as_deref().unwrap_or("default");
+        if name == "default" {
+            return host
+                .default_input_device()
+                .ok_or_else(|| EngineError::DeviceNotFound(name.to_string()));
+        }
+        host.input_devices()
+            .map_err(backend_error)?
+            .find(|device| device.name().is_ok_and(|n| n.contains(name)))
+            .ok_or_else(|| EngineError::DeviceNotFound(name.to_string()))
+    }
+
+    /// Resolve `settings.device`: "default" or a substring of the device name.
+    fn device(settings: &Settings) -> Result<cpal::Device, EngineError> {
+        let host = Self::host(settings)?;
+        let name = &settings.device;
+        if is_asio(host.id().name())
+            && host
+                .output_devices()
+                .map_err(backend_error)?
+                .next()
+                .is_none()
+        {
+            return Err(EngineError::AsioDriverMissing(
+                "no ASIO drivers are installed".into(),
+            ));
+        }
+        output_device(&host, name)
+    }
+
+    /// Resolve `settings.preview_device` on the same host as the main output.
+    fn preview_device(settings: &Settings) -> Result<cpal::Device, EngineError> {
+        let name = settings
+            .preview_device
+            .as_deref()
+            .ok_or(EngineError::NoPreviewDevice)?;
+        output_device(&Self::host(settings)?, name)
+    }
+
+    /// Resolve `settings.cue_device` on the same host as the main output.
+    fn cue_device(settings: &Settings) -> Result<cpal::Device, EngineError> {
+        let name = settings
+            .cue_device
+            .as_deref()
+            .ok_or(EngineError::NoCueDevice)?;
+        output_device(&Self::host(settings)?, name)
+    }
+}
+
+/// "default" or the first output device whose name contains `name`.
+fn output_device(host: &cpal::Host, name: &str) -> Result<cpal::Device, EngineError> {
+    if name == "default" {
+        return host
+            .default_output_device()
+            .ok_or_else(|| EngineError::DeviceNotFound(name.to_string()));
+    }
+    host.output_devices()
+        .map_err(backend_error)?
+        .find(|device| device.name().is_ok_and(|n| n.contains(name)))
+        .ok_or_else(|| EngineError::DeviceNotFound(name.to_string()))
+}
+
+fn is_asio(host: &str) -> bool {
+    host.eq_ignore_ascii_case("asio")
+}
+
+/// Output devices of every host cpal can open on this machine.
+pub fn list_output_devices() -> Vec<HostDevices> {
+    cpal::available_hosts()
+        .into_iter()
+        .filter_map(|id| cpal::host_from_id(id).ok())
+        .map(|host| HostDevices {
+            host: host.id().name().to_string(),
+            devices: host
+                .output_devices()
+                .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+                .unwrap_or_default(),
+            default_device: host.default_output_device().and_then(|d| d.name().ok()),
+        })
+        .collect()
+}
+
+fn supported_config(config: cpal::SupportedStreamConfigRange) -> SupportedConfig {
+    SupportedConfig {
+        channels: config.channels(),
+        min_sample_rate: config.min_sample_rate().0,
+        max_sample_rate: config.max_sample_rate().0,
+        buffer_frames: match config.buffer_size() {
+            cpal::SupportedBufferSize::Range { min, max } => Some((*min, *max)),
+            cpal::SupportedBufferSize::Unknown => None,
+        },
+        sample_format: match config.sample_format() {
+            cpal::SampleFormat::F32 => SampleFormat::F32,
+            cpal::SampleFormat::I32 => SampleFormat::I32,
+            cpal::SampleFormat::I16 => SampleFormat::I16,
+            cpal::SampleFormat::U16 => SampleFormat::U16,
+            _ => SampleFormat::Other,
+        },
+    }
+}
+
+fn stream_config(config: &NegotiatedConfig) -> cpal::StreamConfig {
+    cpal::StreamConfig {
+        channels: config.channels,
+        sample_rate: cpal::SampleRate(config.sample_rate),
+        buffer_size: match config.buffer_frames {
+            Some(frames) => cpal::BufferSize::Fixed(frames),
+            None => cpal::BufferSize::Default,
+        },
+    }
+}
+
+fn backend_error(err: impl std::fmt::Display) -> EngineError {
+    EngineError::Backend(err.to_string())
+}
+
+impl StreamBackend for CpalBackend {
+    fn supported_configs(&self, settings: &Settings) -> Result<Vec<SupportedConfig>, EngineError> {
+        let device = Self::device(settings)?;
+        let configs = device.supported_output_configs().map_err(backend_error)?;
+        Ok(configs.map(supported_config).collect())
+    }
+
+    fn exclusive_capabilities(
+        &self,
+        settings: &Settings,
+    ) -> Result<ExclusiveCapabilities, EngineError> {
+        let host = Self::host(settings)?;
+        let reason = if host.id().name().eq_ignore_ascii_case("wasapi") {
+            "cpal's WASAPI host only opens shared-mode streams"
+        } else {
+            "exclusive mode is only available on the WASAPI host"
+        };
+        Err(EngineError::ExclusiveModeDenied(reason.into()))
+    }
+
+    fn build_output(
+        &self,
+        settings: &Settings,
+        config: &NegotiatedConfig,
+        mut callback: MixCallback,
+        errors: ErrorSink,
+    ) -> Result<Box<dyn AudioStream>, EngineError> {
+        if config.exclusive || config.sample_format != SampleFormat::F32 {
+            return Err(EngineError::ExclusiveModeDenied(
+                "cpal streams are opened shared with f32 samples".into(),
+            ));
+        }
+        let device = Self::device(settings)?;
+        let stream = device
+            .build_output_stream(
+                &stream_config(config),
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| callback.process(data),
+                move |err| match err {
+                    cpal::StreamError::DeviceNotAvailable => errors.device_lost(err.to_string()),
+                    err => errors.stream_error(err.to_string()),
+                },
+                None,
+            )
+            .map_err(backend_error)?;
+        Ok(Box::new(CpalStream(stream)))
+    }
+
+    fn supported_cue_configs(
+        &self,
+        settings: &Settings,
+    ) -> Result<Vec<SupportedConfig>, EngineError> {
+        let device = Self::cue_device(settings)?;
+        let configs = device.supported_output_configs().map_err(backend_error)?;
+        Ok(configs.map(supported_config).collect())
+    }
+
+    fn build_cue(
+        &self,
+        settings: &Settings,
+        config: &NegotiatedConfig,
+        mut callback: CueCallback,
+    ) -> Result<Box<dyn AudioStream>, EngineError> {
+        let device = Self::cue_device(settings)?;
+        let stream = device
+            .build_output_stream(
+                &stream_config(config),
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| callback.process(data),
+                // A failing cue device is left to itself; the master keeps playing.
+                move |err| record_breadcrumb(format!("cue stream error: {err}")),
+                None,
+            )
+            .map_err(backend_error)?;
+        Ok(Box::new(CpalStream(stream)))
+    }
+}
+
+impl CaptureBackend for CpalBackend {
+    fn supported_input_configs(
+        &self,
+        settings: &Settings,
+    ) -> Result<Vec<SupportedConfig>, EngineError> {
+        let device = Self::input_device(settings)?;
+        let configs = device.supported_input_configs().map_err(backend_error)?;
+        Ok(configs.map(supported_config).collect())
+    }
+
+    fn build_input(
+        &self,
+        settings: &Settings,
+        config: &NegotiatedConfig,
+        mut capture: InputCapture,
+    ) -> Result<Box<dyn AudioStream>, EngineError> {
+        let device = Self::input_device(settings)?;
+        let report = capture.error_reporter();
+        let stream = device
+            .build_input_stream(
+                &stream_config(config),
+                move |data: &[f32], _: &cpal::InputCallbackInfo| capture.push(data),
+                move |err| report(err.to_string()),
+                None,
+            )
+            .map_err(backend_error)?;
+        Ok(Box::new(CpalStream(stream)))
+    }
+}
+
+impl PreviewBackend for CpalBackend {
+    fn supported_preview_configs(
+        &self,
+        settings: &Settings,
+    ) -> Result<Vec<SupportedConfig>, EngineError> {
+        let device = Self::preview_device(settings)?;
+        let configs = device.supported_output_configs().map_err(backend_error)?;
+        Ok(configs.map(supported_config).collect())
+    }
+
+    fn build_preview(
+        &self,
+        settings: &Settings,
+        config: &NegotiatedConfig,
+        mut callback: PreviewCallback,
+    ) -> Result<Box<dyn AudioStream>, EngineError> {
+        let device = Self::preview_device(settings)?;
+        let stream = device
+            .build_output_stream(
+                &stream_config(config),
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| callback.process(data),
+                move |err| record_breadcrumb(format!("preview stream error: {err}")),
+                None,
+            )
+            .map_err(backend_error)?;
+        Ok(Box::new(CpalStream(stream)))
+    }
+}
+
+struct CpalStream(cpal::Stream);
+
+impl AudioStream for CpalStream {
+    fn play(&self) -> Result<(), EngineError> {
+        self.0.play().map_err(backend_error)
+    }
+
+    fn pause(&self) -> Result<(), EngineError> {
+        self.0.pause().map_err(backend_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{AudioEngine, ChannelMap, DeckHandle, InputDeck, PreviewPlayer};
+    use crate::deck::{Track, TransportState};
+    use crate::ring::AudioRing;
+    use crate::settings::Settings;
+    use std::time::Duration;
+
+    #[test]
+    #[ignore = "needs a real audio output device"]
+    fn plays_a_tone_on_the_default_device() {
+        let (mut producer, consumer) = AudioRing::with_capacity_frames(48_000, 2).split();
+        let tone: Vec<f32> = (0..48_000)
+            .flat_map(|i| {
+                let s = (i as f32 / 48_000.0 * 440.0 * std::f32::consts::TAU).sin() * 0.1;
+                [s, s]
+            })
+            .collect();
+        producer.write(&tone);
+
+        let handle =
+            AudioEngine::start(&Settings::default(), vec![DeckHandle::new(consumer)]).unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        let stats = handle.stats();
+        assert!(stats.callbacks > 0, "{stats:?}");
+        handle.stop().unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs a real audio input device"]
+    fn captures_from_the_default_input() {
+        let (input, _deck) = InputDeck::open(&Settings::default(), ChannelMap::default()).unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(input.captured_frames() > 0);
+        assert_eq!(input.stream_errors(), 0);
+        input.stop().unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs a second output device named in DEEJAY_CUE_DEVICE"]
+    fn plays_the_cue_on_a_second_device() {
+        let settings = Settings {
+            cue_device: std::env::var("DEEJAY_CUE_DEVICE").ok(),
+            ..Settings::default()
+        };
+        let handle = AudioEngine::start(&settings, Vec::new()).unwrap();
+        std::thread::sleep(Duration::from_secs(2));
+        let cue = handle.cue_stats().unwrap();
+        assert!(cue.frames > 0, "{cue:?}");
+        assert_eq!(cue.dropped_frames, 0);
+        handle.stop().unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs a second output device named in DEEJAY_PREVIEW_DEVICE"]
+    fn previews_on_a_second_device_alongside_the_engine() {
+        let settings = Settings {
+            preview_device: std::env::var("DEEJAY_PREVIEW_DEVICE").ok(),
+            ..Settings::default()
+        };
+        let handle = AudioEngine::start(&settings, Vec::new()).unwrap();
+        let mut preview = PreviewPlayer::open(&settings).unwrap();
+        let tone: Vec<f32> = (0..44_100)
+            .flat_map(|i| {
+                let s = (i as f32 / 44_100.0 * 660.0 * std::f32::consts::TAU).sin() * 0.1;
+                [s, s]
+            })
+            .collect();
+        assert!(preview.load(Track::from_interleaved(tone, 44_100)));
+        assert!(preview.play());
+        std::thread::sleep(Duration::from_millis(500));
+        preview.poll();
+        assert!(preview.position() > 0.0);
+        assert_eq!(preview.state(), &TransportState::Playing);
+        assert!(handle.stats().callbacks > 0);
+        preview.close().unwrap();
+        handle.stop().unwrap();
+    }
+}
@@ -0,0 +1,433 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::{NegotiatedConfig, MAX_BLOCK_FRAMES};
+use crate::ring::{AudioRing, RingConsumer, RingProducer};
+
+/// Extra frames buffered on top of both devices' blocks, to absorb callback jitter.
+const CUE_MARGIN_FRAMES: usize = 256;
+/// Block size assumed for a device that only offers its default.
+const DEFAULT_DEVICE_BLOCK: usize = 512;
+/// Weight of each callback's occupancy in the running average.
+const OCCUPANCY_SMOOTHING: f64 = 0.002;
+/// Averaged distance from the target, in frames, tolerated before correcting.
+const DRIFT_DEADBAND_FRAMES: f64 = 4.0;
+
+/// Frames of cue latency to hold for a master and cue stream with these configurations.
+pub fn cue_target_frames(master: &NegotiatedConfig, cue: &NegotiatedConfig) -> usize {
+    let block = |config: &NegotiatedConfig| {
+        config
+            .buffer_frames
+            .map_or(DEFAULT_DEVICE_BLOCK, |frames| frames as usize)
+    };
+    block(master) + block(cue) + CUE_MARGIN_FRAMES
+}
+
+/// Counters shared between the two halves of a cue link and [`CueMonitor`].
+#[derive(Debug, Default)]
+struct CueCounters {
+    /// Occupancy average seen by the cue callback, as f64 bits.
+    occupancy: AtomicU64,
+    /// Frames played on the cue device.
+    frames: AtomicU64,
+    /// Single-frame resample corrections applied.
+    corrections: AtomicU64,
+    /// Frames the cue clock consumed beyond what it was fed, net of corrections;
+    /// positive when the cue device runs fast.
+    drift_frames: AtomicI64,
+    /// Frames thrown away at once to catch up with a backlog.
+    skipped_frames: AtomicU64,
+    /// Frames the master could not hand over because the ring was full.
+    dropped_frames: AtomicU64,
+    underruns: AtomicU64,
+}
+
+/// Create the ring between the mixing callback and the cue stream, holding
+/// about `target_frames` of cue latency.
+pub fn cue_link(target_frames: usize, channels: u16) -> (CueFeed, CueCallback, CueMonitor) {
+    let target_frames = target_frames.max(1);
+    let capacity = (target_frames * 4).max(target_frames + 2 * MAX_BLOCK_FRAMES);
+    let (producer, consumer) = AudioRing::with_capacity_frames(capacity, 2).split();
+    let counters = Arc::new(CueCounters::default());
+    (
+        CueFeed {
+            ring: producer,
+            counters: counters.clone(),
+        },
+        CueCallback {
+            ring: consumer,
+            channels: channels as usize,
+            target: target_frames,
+            average: 0.0,
+            priming: true,
+            scratch: vec![0.0; (MAX_BLOCK_FRAMES + 1) * 2],
+            counters: counters.clone(),
+        },
+        CueMonitor {
+            counters,
+            target_frames,
+        },
+    )
+}
+
+/// Master-side end of the cue link, pushed to from the mixing callback.
+pub struct CueFeed {
+    ring: RingProducer,
+    counters: Arc<CueCounters>,
+}
+
+impl CueFeed {
+    /// Hand a block of interleaved stereo cue frames over. Never blocks: whatever
+    /// does not fit is dropped, so a stalled cue device cannot hold up the master.
+    pub fn push(&mut self, frames: &[f32]) {
+        let written = self.ring.write(frames);
+        if written < frames.len() {
+            self.counters
+                .dropped_frames
+                .fetch_add(((frames.len() - written) / 2) as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Body of the cue output callback.
+///
+/// The master stream is the clock: it feeds the ring at its own rate and this
+/// side drains it at the cue device's. The two crystals never agree exactly,
+/// so the averaged occupancy is held near the target by now and then stretching
+/// or squeezing a block by one frame with linear interpolation.
+pub struct CueCallback {
+    ring: RingConsumer,
+    channels: usize,
+    target: usize,
+    /// Smoothed occupancy, in frames.
+    average: f64,
+    /// Waiting for the ring to fill up to the target, at startup or after an underrun.
+    priming: bool,
+    scratch: Vec<f32>,
+    counters: Arc<CueCounters>,
+}
+
+impl CueCallback {
+    /// Render interleaved output for a device with `channels` channels; extra channels stay silent.
+    pub fn process(&mut self, output: &mut [f32]) {
+        for chunk in output.chunks_mut(MAX_BLOCK_FRAMES * self.channels) {
+            self.process_block(chunk);
+        }
+        self.counters
+            .frames
+            .fetch_add((output.len() / self.channels) as u64, Ordering::Relaxed);
+        self.counters
+            .occupancy
+            .store(self.average.to_bits(), Ordering::Relaxed);
+        self.counters
+            .underruns
+            .store(self.ring.underruns(), Ordering::Relaxed);
+    }
+
+    fn process_block(&mut self, output: &mut [f32]) {
+        let frames = output.len() / self.channels;
+        if frames == 0 {
+            return;
+        }
+        let mut occupancy = self.ring.occupancy();
+        if self.priming {
+            if occupancy < self.target {
+                output.fill(0.0);
+                return;
+            }
+            // Start out exactly on the target rather than wherever the last push left it.
+            self.discard(occupancy - self.target);
+            self.priming = false;
+            occupancy = self.target;
+            self.average = occupancy as f64;
+        }
+        self.average += (occupancy as f64 - self.average) * OCCUPANCY_SMOOTHING;
+
+        // A stall on either side leaves a backlog no single-frame correction could
+        // work off in reasonable time; drop it in one go.
+        if occupancy > self.target * 2 {
+            let excess = occupancy - self.target;
+            self.discard(excess);
+            self.counters
+                .skipped_frames
+                .fetch_add(excess as u64, Ordering::Relaxed);
+            self.average = self.target as f64;
+        }
+
+        let error = self.average - self.target as f64;
+        let correction: isize = if frames < 2 || error.abs() <= DRIFT_DEADBAND_FRAMES {
+            0
+        } else if error > 0.0 {
+            1
+        } else {
+            -1
+        };
+        if correction != 0 {
+            self.counters.corrections.fetch_add(1, Ordering::Relaxed);
+            self.counters
+                .drift_frames
+                .fetch_sub(correction as i64, Ordering::Relaxed);
+            // The occupancy moves by exactly the correction; tell the average
+            // so it does not keep asking for more while it catches up.
+            self.average -= correction as f64;
+        }
+
+        let wanted = (frames as isize + correction) as usize;
+        let input = &mut self.scratch[..wanted * 2];
+        if self.ring.read(input) < input.len() {
+            self.priming = true;
+        }
+        let step = if correction == 0 {
+            1.0
+        } else {
+            (wanted - 1) as f64 / (frames - 1) as f64
+        };
+        for (index, out) in output.chunks_exact_mut(self.channels).enumerate() {
+            let position = index as f64 * step;
+            let base = (position as usize).min(wanted - 1);
+            let next = (base + 1).min(wanted - 1);
+            let fraction = (position - base as f64) as f32;
+            for channel in 0..2 {
+                let a = input[base * 2 + channel];
+                let b = input[next * 2 + channel];
+                out[channel] = a + (b - a) * fraction;
+            }
+            out[2..].fill(0.0);
+        }
+    }
+
+    fn discard(&mut self, frames: usize) {
+        let mut left = frames;
+        while left > 0 {
+            let count = left.min(MAX_BLOCK_FRAMES);
+            self.ring.read(&mut self.scratch[..count * 2]);
+            left -= count;
+        }
+    }
+}
+
+/// Point-in-time view of the cue link.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CueStats {
+    /// Cue latency the link aims for, in frames.
+    pub target_frames: usize,
+    /// Averaged frames waiting in the ring.
+    pub occupancy_frames: f64,
+    /// Frames played on the cue device.
+    pub frames: u64,
+    /// Net frames the corrections made up for; positive when the cue clock runs fast.
+    pub drift_frames: i64,
+    pub corrections: u64,
+    /// Frames dropped at once to catch up after a stall.
+    pub skipped_frames: u64,
+    /// Frames the master could not hand over because the cue stream fell behind.
+    pub dropped_frames: u64,
+    /// Cue callbacks that found the ring short.
+    pub underruns: u64,
+}
+
+/// Control-side view of a cue link.
+#[derive(Debug, Clone)]
+pub struct CueMonitor {
+    counters: Arc<CueCounters>,
+    target_frames: usize,
+}
+
+impl CueStats {
+    /// How much faster the cue clock runs than the master's, estimated from the
+    /// corrections needed to hold the target so far.
+    pub fn drift_ppm(&self) -> f64 {
+        if self.frames == 0 {
+            0.0
+        } else {
+            self.drift_frames as f64 / self.frames as f64 * 1e6
+        }
+    }
+}
+
+impl CueMonitor {
+    pub fn stats(&self) -> CueStats {
+        let counters = &self.counters;
+        CueStats {
+            target_frames: self.target_frames,
+            occupancy_frames: f64::from_bits(counters.occupancy.load(Ordering::Relaxed)),
+            frames: counters.frames.load(Ordering::Relaxed),
+            drift_frames: counters.drift_frames.load(Ordering::Relaxed),
+            corrections: counters.corrections.load(Ordering::Relaxed),
+            skipped_frames: counters.skipped_frames.load(Ordering::Relaxed),
+            dropped_frames: counters.dropped_frames.load(Ordering::Relaxed),
+            underruns: counters.underruns.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+    const MASTER_BLOCK: usize = 256;
+    const CUE_BLOCK: usize = 480;
+    const TARGET: usize = MASTER_BLOCK + CUE_BLOCK + CUE_MARGIN_FRAMES;
+
+    /// Settling time excluded from the drift measurements.
+    const WARM_UP_SECONDS: f64 = 60.0;
+
+    struct Run {
+        /// Stats at the end of the warm-up and at the end of the run.
+        warm: CueStats,
+        stats: CueStats,
+        /// Lowest and highest occupancy seen by the cue callback after the warm-up.
+        occupancy: (usize, usize),
+        /// Largest jump between neighbouring output samples of the triangle.
+        max_step: f32,
+    }
+
+    impl Run {
+        fn corrections(&self) -> f64 {
+            (self.stats.corrections - self.warm.corrections) as f64
+        }
+
+        fn drift_ppm(&self) -> f64 {
+            (self.stats.drift_frames - self.warm.drift_frames) as f64
+                / (self.stats.frames - self.warm.frames) as f64
+                * 1e6
+        }
+    }
+
+    /// Feed a slow triangle wave from a master clock at 48 kHz to a cue clock off by
+    /// `ppm`, interleaving callbacks in the order the two devices would fire.
+    fn run(ppm: f64, seconds: f64) -> Run {
+        let (mut feed, mut callback, monitor) = cue_link(TARGET, 2);
+        let cue_rate = SAMPLE_RATE * (1.0 + ppm * 1e-6);
+        let master_period = MASTER_BLOCK as f64 / SAMPLE_RATE;
+        let cue_period = CUE_BLOCK as f64 / cue_rate;
+        let end = WARM_UP_SECONDS + seconds;
+        let (mut master_time, mut cue_time) = (0.0f64, cue_period / 3.0);
+        let mut next = 0u64;
+        let mut block = vec![0.0; MASTER_BLOCK * 2];
+        let mut output = vec![0.0; CUE_BLOCK * 2];
+        let mut warm = None;
+        let mut occupancy = (usize::MAX, 0);
+        let mut max_step = 0.0f32;
+        let mut last: Option<f32> = None;
+        while master_time.min(cue_time) < end {
+            if master_time <= cue_time {
+                for frame in block.chunks_exact_mut(2) {
+                    let phase = next % 96_000;
+                    let value = phase.min(96_000 - phase) as f32 / 48_000.0;
+                    frame.copy_from_slice(&[value, -value]);
+                    next += 1;
+                }
+                feed.push(&block);
+                master_time += master_period;
+                continue;
+            }
+            let before = callback.ring.occupancy();
+            callback.process(&mut output);
+            cue_time += cue_period;
+            if cue_time < WARM_UP_SECONDS {
+                continue;
+            }
+            warm.get_or_insert_with(|| monitor.stats());
+            occupancy = (occupancy.0.min(before), occupancy.1.max(before));
+            for frame in output.chunks_exact(2) {
+                if let Some(last) = last {
+                    max_step = max_step.max((frame[0] - last).abs());
+                }
+                last = Some(frame[0]);
+            }
+        }
+        Run {
+            warm: warm.unwrap(),
+            stats: monitor.stats(),
+            occupancy,
+            max_step,
+        }
+    }
+
+    #[test]
+    fn holds_latency_against_a_fast_cue_clock() {
+        let seconds = 300.0;
+        let run = run(50.0, seconds);
+        let stats = run.stats;
+        assert_eq!(stats.underruns, 0, "{stats:?}");
+        assert_eq!(stats.dropped_frames, 0);
+        assert_eq!(stats.skipped_frames, 0);
+        // Occupancy only swings by the master's block around the target.
+        let (low, high) = run.occupancy;
+        assert!(
+            low + MASTER_BLOCK >= TARGET && high <= TARGET + MASTER_BLOCK,
+            "{low}..{high}"
+        );
+
+        // 50 ppm of five minutes is 720 frames, one correction each.
+        let drift = 50e-6 * SAMPLE_RATE * seconds;
+        assert!(
+            (run.corrections() - drift).abs() < 0.05 * drift,
+            "{stats:?}"
+        );
+        assert!((run.drift_ppm() - 50.0).abs() < 2.5, "{}", run.drift_ppm());
+        // Squeezing a block by a frame does not click.
+        assert!(run.max_step < 2.0 / 48_000.0, "{}", run.max_step);
+    }
+
+    #[test]
+    fn holds_latency_against_a_slow_cue_clock() {
+        let seconds = 300.0;
+        let run = run(-50.0, seconds);
+        let stats = run.stats;
+        assert_eq!(stats.underruns, 0, "{stats:?}");
+        assert_eq!(stats.dropped_frames, 0);
+        assert_eq!(stats.skipped_frames, 0);
+        let (low, high) = run.occupancy;
+        assert!(
+            low + MASTER_BLOCK >= TARGET && high <= TARGET + MASTER_BLOCK,
+            "{low}..{high}"
+        );
+
+        let drift = 50e-6 * SAMPLE_RATE * seconds;
+        assert!(
+            (run.corrections() - drift).abs() < 0.05 * drift,
+            "{stats:?}"
+        );
+        assert!((run.drift_ppm() + 50.0).abs() < 2.5, "{}", run.drift_ppm());
+        assert!(run.max_step < 2.0 / 48_000.0, "{}", run.max_step);
+    }
+
+    #[test]
+    fn cue_stalls_and_underruns_stay_on_the_cue_side() {
+        let (mut feed, mut callback, monitor) = cue_link(TARGET, 4);
+        let block = vec![0.25; MASTER_BLOCK * 2];
+        let mut output = vec![1.0; CUE_BLOCK * 4];
+
+        // Silence while priming, then the fed frames on the first two channels.
+        callback.process(&mut output);
+        assert!(output.iter().all(|s| *s == 0.0));
+        for _ in 0..4 {
+            feed.push(&block);
+        }
+        callback.process(&mut output);
+        assert!(output.chunks_exact(4).all(|f| f == [0.25, 0.25, 0.0, 0.0]));
+
+        // A stalled cue device fills the ring; the master keeps pushing regardless.
+        for _ in 0..100 {
+            feed.push(&block);
+        }
+        let stats = monitor.stats();
+        assert!(stats.dropped_frames > 0);
+        callback.process(&mut output);
+        let stats = monitor.stats();
+        assert!(stats.skipped_frames > 0);
+        assert!(callback.ring.occupancy() <= TARGET);
+
+        // Running dry re-primes instead of crackling.
+        for _ in 0..4 {
+            callback.process(&mut output);
+        }
+        assert!(monitor.stats().underruns > 0);
+        assert!(callback.priming);
+        callback.process(&mut output);
+        assert!(output.iter().all(|s| *s == 0.0));
+    }
+}
@@ -0,0 +1,178 @@
+use super::{EngineError, NegotiatedConfig, SampleFormat};
+
+/// Encodings tried for an exclusive stream, most preferred first.
+///
+/// Exclusive streams bypass the system mixer, so many devices only accept
+/// their native integer formats.
+pub const EXCLUSIVE_FORMAT_LADDER: [SampleFormat; 4] = [
+    SampleFormat::F32,
+    SampleFormat::I32,
+    SampleFormat::I24,
+    SampleFormat::I16,
+];
+
+/// Rates tried after the requested one is refused.
+const FALLBACK_RATES: [u32; 2] = [48_000, 44_100];
+
+/// One exact format a device accepts in exclusive mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExclusiveFormat {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub sample_format: SampleFormat,
+}
+
+/// What a device reports when probed for exclusive access.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExclusiveCapabilities {
+    /// Whether the device's policy lets applications take exclusive control.
+    pub allowed: bool,
+    pub formats: Vec<ExclusiveFormat>,
+    /// Smallest device period in frames.
+    pub min_period_frames: u32,
+    /// Buffer sizes must be a multiple of this many frames.
+    pub period_alignment: u32,
+}
+
+/// The `(sample_rate, format)` pairs tried, in order, for an exclusive stream.
+pub fn exclusive_ladder(sample_rate: u32) -> Vec<(u32, SampleFormat)> {
+    let mut rates = vec![sample_rate];
+    rates.extend(FALLBACK_RATES.iter().filter(|rate| **rate != sample_rate));
+    rates
+        .into_iter()
+        .flat_map(|rate| EXCLUSIVE_FORMAT_LADDER.map(|format| (rate, format)))
+        .collect()
+}
+
+/// Walk [`exclusive_ladder`] against `capabilities` and return the first
+/// format the device accepts, with the buffer size rounded to its period.
+pub fn plan_exclusive(
+    sample_rate: u32,
+    buffer_frames: u32,
+    capabilities: &ExclusiveCapabilities,
+) -> Result<NegotiatedConfig, EngineError> {
+    if !capabilities.allowed {
+        return Err(EngineError::ExclusiveModeDenied(
+            "the device does not allow applications to take exclusive control".into(),
+        ));
+    }
+
+    let format = exclusive_ladder(sample_rate)
+        .into_iter()
+        .find_map(|(rate, sample_format)| {
+            capabilities
+                .formats
+                .iter()
+                .filter(|format| {
+                    format.sample_rate == rate
+                        && format.sample_format == sample_format
+                        && format.channels >= 2
+                })
+                .min_by_key(|format| format.channels)
+        })
+        .ok_or_else(|| {
+            EngineError::ExclusiveModeDenied(format!(
+                "no exclusive format near {sample_rate} Hz is accepted by the device"
+            ))
+        })?;
+
+    let alignment = capabilities.period_alignment.max(1);
+    let buffer = buffer_frames
+        .max(capabilities.min_period_frames)
+        .div_ceil(alignment)
+        * alignment;
+    Ok(NegotiatedConfig {
+        channels: format.channels,
+        sample_rate: format.sample_rate,
+        buffer_frames: Some(buffer),
+        sample_format: format.sample_format,
+        exclusive: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(channels: u16, sample_rate: u32, sample_format: SampleFormat) -> ExclusiveFormat {
+        ExclusiveFormat {
+            channels,
+            sample_rate,
+            sample_format,
+        }
+    }
+
+    fn device(formats: Vec<ExclusiveFormat>) -> ExclusiveCapabilities {
+        ExclusiveCapabilities {
+            allowed: true,
+            formats,
+            min_period_frames: 144,
+            period_alignment: 48,
+        }
+    }
+
+    #[test]
+    fn ladder_prefers_requested_rate_then_common_rates() {
+        let ladder = exclusive_ladder(96_000);
+        assert_eq!(ladder.len(), 12);
+        assert_eq!(ladder[0], (96_000, SampleFormat::F32));
+        assert_eq!(ladder[3], (96_000, SampleFormat::I16));
+        assert_eq!(ladder[4], (48_000, SampleFormat::F32));
+        assert_eq!(ladder[11], (44_100, SampleFormat::I16));
+        assert_eq!(exclusive_ladder(48_000).len(), 8);
+    }
+
+    #[test]
+    fn walks_formats_before_rates() {
+        let caps = device(vec![
+            format(2, 44_100, SampleFormat::F32),
+            format(2, 48_000, SampleFormat::I16),
+            format(8, 48_000, SampleFormat::I24),
+            format(2, 48_000, SampleFormat::I24),
+        ]);
+        let config = plan_exclusive(48_000, 256, &caps).unwrap();
+        assert_eq!(
+            config,
+            NegotiatedConfig {
+                channels: 2,
+                sample_rate: 48_000,
+                buffer_frames: Some(288),
+                sample_format: SampleFormat::I24,
+                exclusive: true,
+            }
+        );
+
+        let resampled = plan_exclusive(96_000, 64, &caps).unwrap();
+        assert_eq!(resampled.sample_rate, 48_000);
+        assert_eq!(resampled.buffer_frames, Some(144));
+    }
+
+    #[test]
+    fn refusals_are_exclusive_mode_denied() {
+        let mut caps = device(vec![format(2, 48_000, SampleFormat::F32)]);
+        assert!(plan_exclusive(48_000, 256, &caps).is_ok());
+
+        assert!(matches!(
+            plan_exclusive(
+                22_050,
+                256,
+                &device(vec![format(2, 22_050, SampleFormat::U16)])
+            ),
+            Err(EngineError::ExclusiveModeDenied(_))
+        ));
+        assert!(matches!(
+            plan_exclusive(
+                48_000,
+                256,
+                &device(vec![format(1, 48_000, SampleFormat::F32)])
+            ),
+            Err(EngineError::ExclusiveModeDenied(_))
+        ));
+
+        caps.allowed = false;
+        assert!(matches!(
+            plan_exclusive(48_000, 256, &caps),
+            Err(EngineError::ExclusiveModeDenied(_))
+        ));
+    }
+}
@@ -0,0 +1,425 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "audio")]
+use super::CpalBackend;
+use super::{
+    AudioStream, DeckHandle, EngineError, NegotiatedConfig, SampleFormat, SupportedConfig,
+};
+use crate::crash::record_breadcrumb;
+use crate::ring::{AudioRing, RingProducer};
+use crate::settings::Settings;
+
+/// Input ring capacity as a multiple of the capture buffer size.
+const RING_BUFFERS: usize = 4;
+/// Ring capacity when the host does not report a capture buffer size.
+const DEFAULT_RING_FRAMES: usize = 2_048;
+/// Frames mapped per pass; longer capture callbacks are processed in pieces.
+const MAX_CAPTURE_FRAMES: usize = 4_096;
+
+/// Hardware input channels (zero-based) feeding the deck's left and right sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelMap {
+    pub left: u16,
+    pub right: u16,
+}
+
+impl ChannelMap {
+    /// The stereo pair starting at `first`, e.g. `pair(2)` for inputs 3/4.
+    pub fn pair(first: u16) -> Self {
+        Self {
+            left: first,
+            right: first + 1,
+        }
+    }
+
+    /// Feed both sides of the deck from one input.
+    pub fn mono(channel: u16) -> Self {
+        Self {
+            left: channel,
+            right: channel,
+        }
+    }
+
+    fn channels_needed(self) -> u16 {
+        self.left.max(self.right) + 1
+    }
+}
+
+impl Default for ChannelMap {
+    fn default() -> Self {
+        Self::pair(0)
+    }
+}
+
+/// Counters shared between the capture callback, [`InputDeck`] and the engine stats.
+#[derive(Debug, Default)]
+pub(super) struct InputCounters {
+    pub(super) captured_frames: AtomicU64,
+    pub(super) dropped_frames: AtomicU64,
+    pub(super) overruns: AtomicU64,
+    pub(super) stream_errors: AtomicU64,
+    /// Ring occupancy after the last capture callback.
+    ring_frames: AtomicU64,
+}
+
+/// Capture-callback side of an input deck: picks the mapped channels and feeds the ring.
+pub struct InputCapture {
+    producer: RingProducer,
+    map: ChannelMap,
+    channels: usize,
+    scratch: Vec<f32>,
+    counters: Arc<InputCounters>,
+}
+
+impl InputCapture {
+    /// Push interleaved frames captured from a `channels`-wide device. Frames
+    /// that do not fit in the ring are dropped and counted as an overrun.
+    pub fn push(&mut self, data: &[f32]) {
+        let (left, right) = (self.map.left as usize, self.map.right as usize);
+        let mut captured = 0;
+        let mut dropped = 0;
+        for block in data.chunks(MAX_CAPTURE_FRAMES * self.channels) {
+            let frames = block.len() / self.channels;
+            for (out, frame) in self
+                .scratch
+                .chunks_exact_mut(2)
+                .zip(block.chunks_exact(self.channels))
+            {
+                out[0] = frame[left];
+                out[1] = frame[right];
+            }
+            let written = self.producer.write(&self.scratch[..frames * 2]) / 2;
+            captured += frames;
+            dropped += frames - written;
+        }
+
+        let counters = &self.counters;
+        counters
+            .captured_frames
+            .fetch_add(captured as u64, Ordering::Relaxed);
+        if dropped > 0 {
+            counters
+                .dropped_frames
+                .fetch_add(dropped as u64, Ordering::Relaxed);
+            counters.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+        counters
+            .ring_frames
+            .store(self.producer.occupancy() as u64, Ordering::Relaxed);
+    }
+
+    /// Callback for capture stream errors; records a breadcrumb and counts the error.
+    pub fn error_reporter(&self) -> impl Fn(String) + Send + 'static {
+        let counters = self.counters.clone();
+        move |message| {
+            record_breadcrumb(format!("audio input error: {message}"));
+            counters.stream_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Pick an f32 capture configuration running at exactly `sample_rate` with
+/// enough channels for `map`.
+pub fn negotiate_input(
+    sample_rate: u32,
+    buffer_frames: u32,
+    map: ChannelMap,
+    supported: &[SupportedConfig],
+) -> Result<NegotiatedConfig, EngineError> {
+    let usable: Vec<&SupportedConfig> = supported
+        .iter()
+        .filter(|config| config.sample_format == SampleFormat::F32)
+        .collect();
+    let widest = usable
+        .iter()
+        .map(|config| config.channels)
+        .max()
+        .ok_or(EngineError::NoSupportedConfig)?;
+    let wide_enough: Vec<&SupportedConfig> = usable
+        .into_iter()
+        .filter(|config| config.channels >= map.channels_needed())
+        .collect();
+    if wide_enough.is_empty() {
+        return Err(EngineError::InvalidChannelMap {
+            left: map.left,
+            right: map.right,
+            channels: widest,
+        });
+    }
+
+    if let Some(config) = wide_enough
+        .iter()
+        .filter(|config| (config.min_sample_rate..=config.max_sample_rate).contains(&sample_rate))
+        .min_by_key(|config| config.channels)
+    {
+        return Ok(NegotiatedConfig {
+            channels: config.channels,
+            sample_rate,
+            buffer_frames: config
+                .buffer_frames
+                .map(|(min, max)| buffer_frames.clamp(min, max)),
+            sample_format: SampleFormat::F32,
+            exclusive: false,
+        });
+    }
+
+    let nearest = wide_enough
+        .iter()
+        .map(|config| sample_rate.clamp(config.min_sample_rate, config.max_sample_rate))
+        .min_by_key(|rate| rate.abs_diff(sample_rate))
+        .unwrap_or(sample_rate);
+    Err(EngineError::SampleRateMismatch {
+        input: nearest,
+        output: sample_rate,
+    })
+}
+
+/// Capture device access used by [`InputDeck`].
+pub trait CaptureBackend {
+    /// Input configurations offered by the device selected in `settings`.
+    fn supported_input_configs(
+        &self,
+        settings: &Settings,
+    ) -> Result<Vec<SupportedConfig>, EngineError>;
+
+    /// Open (but do not start) a capture stream feeding `capture`.
+    fn build_input(
+        &self,
+        settings: &Settings,
+        config: &NegotiatedConfig,
+        capture: InputCapture,
+    ) -> Result<Box<dyn AudioStream>, EngineError>;
+}
+
+/// Live line-in/phono source mixed like a file deck.
+pub struct InputDeck {
+    stream: Box<dyn AudioStream>,
+    config: NegotiatedConfig,
+    counters: Arc<InputCounters>,
+}
+
+impl InputDeck {
+    /// Open `settings.input_device` with cpal at the session sample rate.
+    #[cfg(feature = "audio")]
+    pub fn open(settings: &Settings, map: ChannelMap) -> Result<(Self, DeckHandle), EngineError> {
+        Self::open_with(&CpalBackend, settings, map)
+    }
+
+    /// Start capturing and return the deck plus the handle to pass to the engine.
+    pub fn open_with<B: CaptureBackend>(
+        backend: &B,
+        settings: &Settings,
+        map: ChannelMap,
+    ) -> Result<(Self, DeckHandle), EngineError> {
+        let supported = backend.supported_input_configs(settings)?;
+        let config = negotiate_input(
+            settings.sample_rate,
+            settings.buffer_frames,
+            map,
+            &supported,
+        )?;
+
+        let ring_frames = config
+            .buffer_frames
+            .map_or(DEFAULT_RING_FRAMES, |frames| frames as usize * RING_BUFFERS);
+        let (producer, consumer) = AudioRing::with_capacity_frames(ring_frames, 2).split();
+        let counters = Arc::new(InputCounters::default());
+        let capture = InputCapture {
+            producer,
+            map,
+            channels: config.channels as usize,
+            scratch: vec![0.0; MAX_CAPTURE_FRAMES * 2],
+            counters: counters.clone(),
+        };
+
+        let stream = backend.build_input(settings, &config, capture)?;
+        stream.play()?;
+        let deck = DeckHandle {
+            ring: consumer,
+            input: Some((config.sample_rate, counters.clone())),
+        };
+        Ok((
+            Self {
+                stream,
+                config,
+                counters,
+            },
+            deck,
+        ))
+    }
+
+    pub fn config(&self) -> NegotiatedConfig {
+        self.config
+    }
+
+    /// Estimated input-to-output delay: the capture buffer, frames queued in
+    /// the ring and the output buffer.
+    pub fn latency_estimate(&self, output: &NegotiatedConfig) -> Duration {
+        let frames = self.config.buffer_frames.unwrap_or(0) as u64
+            + self.counters.ring_frames.load(Ordering::Relaxed)
+            + output.buffer_frames.unwrap_or(0) as u64;
+        Duration::from_secs_f64(frames as f64 / self.config.sample_rate as f64)
+    }
+
+    pub fn captured_frames(&self) -> u64 {
+        self.counters.captured_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped_frames(&self) -> u64 {
+        self.counters.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn overruns(&self) -> u64 {
+        self.counters.overruns.load(Ordering::Relaxed)
+    }
+
+    pub fn stream_errors(&self) -> u64 {
+        self.counters.stream_errors.load(Ordering::Relaxed)
+    }
+
+    /// Pause and close the capture stream.
+    pub fn stop(self) -> Result<(), EngineError> {
+        self.stream.pause()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{EngineState, Mixer};
+    use crate::{parameter_channel, ParameterUpdate, SummingBus};
+    use std::sync::Mutex;
+
+    struct NullStream;
+
+    impl AudioStream for NullStream {
+        fn play(&self) -> Result<(), EngineError> {
+            Ok(())
+        }
+
+        fn pause(&self) -> Result<(), EngineError> {
+            Ok(())
+        }
+    }
+
+    /// Four-input interface whose capture callback the test drives by hand.
+    #[derive(Default)]
+    struct FakeInterface {
+        capture: Mutex<Option<InputCapture>>,
+    }
+
+    impl CaptureBackend for FakeInterface {
+        fn supported_input_configs(
+            &self,
+            _: &Settings,
+        ) -> Result<Vec<SupportedConfig>, EngineError> {
+            Ok(vec![SupportedConfig {
+                channels: 4,
+                min_sample_rate: 44_100,
+                max_sample_rate: 48_000,
+                buffer_frames: Some((16, 1_024)),
+                sample_format: SampleFormat::F32,
+            }])
+        }
+
+        fn build_input(
+            &self,
+            _: &Settings,
+            _: &NegotiatedConfig,
+            capture: InputCapture,
+        ) -> Result<Box<dyn AudioStream>, EngineError> {
+            *self.capture.lock().unwrap() = Some(capture);
+            Ok(Box::new(NullStream))
+        }
+    }
+
+    /// Four-channel capture where input `n` of frame `f` carries `f * 10 + n`.
+    fn capture_block(frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|f| (0..4).map(move |n| (f * 10 + n) as f32))
+            .collect()
+    }
+
+    fn settings(buffer_frames: u32) -> Settings {
+        Settings {
+            buffer_frames,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn maps_selected_input_pair_into_the_deck() {
+        let interface = FakeInterface::default();
+        let (input, deck) =
+            InputDeck::open_with(&interface, &settings(256), ChannelMap::pair(2)).unwrap();
+        assert_eq!(input.config().channels, 4);
+        assert_eq!(input.config().buffer_frames, Some(256));
+
+        let mut capture = interface.capture.lock().unwrap().take().unwrap();
+        capture.push(&capture_block(100));
+
+        let (params, receiver) = parameter_channel(8);
+        params.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let state = Arc::new(EngineState::default());
+        let mut mixer = Mixer::new(SummingBus::new(receiver), vec![deck], 2, state);
+        let mut output = vec![0.0; 100 * 2];
+        mixer.process(&mut output);
+
+        for (f, frame) in output.chunks_exact(2).enumerate() {
+            assert_eq!(frame, [(f * 10 + 2) as f32, (f * 10 + 3) as f32]);
+        }
+        assert_eq!(input.captured_frames(), 100);
+        assert_eq!(input.dropped_frames(), 0);
+
+        let output_config = NegotiatedConfig {
+            buffer_frames: Some(256),
+            ..input.config()
+        };
+        capture.push(&capture_block(48));
+        let latency = input.latency_estimate(&output_config);
+        assert_eq!(
+            latency,
+            Duration::from_secs_f64((256 + 48 + 256) as f64 / 48_000.0)
+        );
+    }
+
+    #[test]
+    fn counts_dropped_frames_when_the_ring_is_full() {
+        let interface = FakeInterface::default();
+        let (input, deck) =
+            InputDeck::open_with(&interface, &settings(16), ChannelMap::mono(1)).unwrap();
+        let mut capture = interface.capture.lock().unwrap().take().unwrap();
+
+        capture.push(&capture_block(50));
+        capture.push(&capture_block(20));
+        assert_eq!(input.captured_frames(), 70);
+        assert_eq!(input.dropped_frames(), 70 - 64);
+        assert_eq!(input.overruns(), 1);
+
+        let mut ring = deck.ring;
+        let mut frames = vec![0.0; 64 * 2];
+        assert_eq!(ring.read(&mut frames), 128);
+        assert_eq!(&frames[..4], [1.0, 1.0, 11.0, 11.0]);
+    }
+
+    #[test]
+    fn negotiation_rejects_mismatched_rates_and_missing_inputs() {
+        let supported = FakeInterface::default()
+            .supported_input_configs(&Settings::default())
+            .unwrap();
+        assert!(matches!(
+            negotiate_input(96_000, 256, ChannelMap::default(), &supported),
+            Err(EngineError::SampleRateMismatch {
+                input: 48_000,
+                output: 96_000
+            })
+        ));
+        assert!(matches!(
+            negotiate_input(48_000, 256, ChannelMap::pair(3), &supported),
+            Err(EngineError::InvalidChannelMap { channels: 4, .. })
+        ));
+        assert!(negotiate_input(44_100, 256, ChannelMap::mono(3), &supported).is_ok());
+    }
+}
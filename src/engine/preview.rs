@@ -0,0 +1,599 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam_queue::ArrayQueue;
+
+#[cfg(feature = "audio")]
+use super::CpalBackend;
+use super::{negotiate, AudioStream, EngineError, NegotiatedConfig, SupportedConfig};
+use crate::deck::{DeckCommand, Track, TransportState};
+use crate::decode::decode_track;
+use crate::fx::Smoothed;
+use crate::settings::Settings;
+
+/// Pending control commands for the preview callback.
+const COMMAND_QUEUE_CAPACITY: usize = 16;
+/// Fade-in after a seek or a fresh start, so clicking the overview does not click.
+const DECLICK_SECONDS: f64 = 0.003;
+
+#[derive(Debug)]
+enum PreviewCommand {
+    Load(Box<Track>),
+    Play,
+    Pause,
+    /// Park at the start.
+    Stop,
+    Eject,
+    /// Jump to a fraction of the track.
+    Seek(f64),
+    Volume(f32),
+}
+
+/// State shared between the preview callback and [`PreviewPlayer`].
+#[derive(Debug, Default)]
+struct PreviewShared {
+    /// Playhead as a fraction of the track, as f64 bits.
+    position: AtomicU64,
+    /// Set by the callback when playback runs off the end.
+    ended: AtomicBool,
+}
+
+/// Body of the preview output callback: plays one track, resampled to the device rate.
+pub struct PreviewCallback {
+    track: Option<Track>,
+    /// Playhead in track frames.
+    position: f64,
+    playing: bool,
+    /// Track frames advanced per device frame.
+    step: f64,
+    sample_rate: u32,
+    channels: usize,
+    volume: Smoothed,
+    /// Frames left of the fade-in, and its length.
+    declick: (u32, u32),
+    commands: Arc<ArrayQueue<PreviewCommand>>,
+    /// Replaced tracks handed back so they are freed off the audio thread.
+    retired: Arc<ArrayQueue<Track>>,
+    shared: Arc<PreviewShared>,
+}
+
+impl PreviewCallback {
+    fn new(config: &NegotiatedConfig, shared: Arc<PreviewShared>) -> Self {
+        Self {
+            track: None,
+            position: 0.0,
+            playing: false,
+            step: 1.0,
+            sample_rate: config.sample_rate,
+            channels: config.channels as usize,
+            volume: Smoothed::new(1.0, config.sample_rate),
+            declick: (
+                0,
+                ((config.sample_rate as f64 * DECLICK_SECONDS) as u32).max(1),
+            ),
+            commands: Arc::new(ArrayQueue::new(COMMAND_QUEUE_CAPACITY)),
+            retired: Arc::new(ArrayQueue::new(COMMAND_QUEUE_CAPACITY)),
+            shared,
+        }
+    }
+
+    fn retire(&mut self) {
+        if let Some(track) = self.track.take() {
+            // Room for one per queued command; dropping here is only a last resort.
+            let _ = self.retired.push(track);
+        }
+    }
+
+    fn apply(&mut self, command: PreviewCommand) {
+        match command {
+            PreviewCommand::Load(track) => {
+                self.retire();
+                self.step = track.sample_rate() as f64 / self.sample_rate as f64;
+                self.track = Some(*track);
+                self.position = 0.0;
+                self.playing = false;
+            }
+            PreviewCommand::Play => {
+                self.playing = true;
+                self.declick.0 = self.declick.1;
+            }
+            PreviewCommand::Pause => self.playing = false,
+            PreviewCommand::Stop => {
+                self.playing = false;
+                self.position = 0.0;
+            }
+            PreviewCommand::Eject => {
+                self.playing = false;
+                self.retire();
+            }
+            PreviewCommand::Seek(fraction) => {
+                if let Some(track) = &self.track {
+                    self.position = fraction.clamp(0.0, 1.0) * track.frames() as f64;
+                    self.declick.0 = self.declick.1;
+                }
+            }
+            PreviewCommand::Volume(volume) => self.volume.set(volume.max(0.0)),
+        }
+    }
+
+    /// Render interleaved output for a device with `channels` channels; extra channels stay silent.
+    pub fn process(&mut self, output: &mut [f32]) {
+        while let Some(command) = self.commands.pop() {
+            self.apply(command);
+        }
+        let Some(track) = &self.track else {
+            output.fill(0.0);
+            return;
+        };
+        let frames = track.frames() as f64;
+        for out in output.chunks_exact_mut(self.channels) {
+            out.fill(0.0);
+            if !self.playing {
+                continue;
+            }
+            if self.position >= frames {
+                self.playing = false;
+                self.shared.ended.store(true, Ordering::Release);
+                continue;
+            }
+            let mut gain = self.volume.next();
+            let (left, length) = &mut self.declick;
+            if *left > 0 {
+                gain *= 1.0 - *left as f32 / *length as f32;
+                *left -= 1;
+            }
+            let frame = track.read(self.position);
+            out[0] = frame[0] * gain;
+            out[1] = frame[1] * gain;
+            self.position += self.step;
+        }
+        let fraction = if frames > 0.0 {
+            (self.position / frames).min(1.0)
+        } else {
+            0.0
+        };
+        self.shared
+            .position
+            .store(fraction.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Device access used by [`PreviewPlayer`].
+pub trait PreviewBackend {
+    /// Output configurations offered by `settings.preview_device`.
+    fn supported_preview_configs(
+        &self,
+        settings: &Settings,
+    ) -> Result<Vec<SupportedConfig>, EngineError>;
+
+    /// Open (but do not start) a stream on the preview device driving `callback`.
+    fn build_preview(
+        &self,
+        settings: &Settings,
+        config: &NegotiatedConfig,
+        callback: PreviewCallback,
+    ) -> Result<Box<dyn AudioStream>, EngineError>;
+}
+
+/// Prelisten player on its own output device, independent of the main mix.
+///
+/// Follows the deck transport states, except that a new track may be loaded at
+/// any time: previewing is never on air.
+pub struct PreviewPlayer {
+    stream: Box<dyn AudioStream>,
+    config: NegotiatedConfig,
+    state: TransportState,
+    /// Decode running on a worker thread.
+    loading: Option<Receiver<Result<Track, String>>>,
+    stop_on_deck_load: bool,
+    commands: Arc<ArrayQueue<PreviewCommand>>,
+    retired: Arc<ArrayQueue<Track>>,
+    shared: Arc<PreviewShared>,
+}
+
+impl PreviewPlayer {
+    /// Open `settings.preview_device` with cpal.
+    #[cfg(feature = "audio")]
+    pub fn open(settings: &Settings) -> Result<Self, EngineError> {
+        Self::open_with(&CpalBackend, settings)
+    }
+
+    /// Negotiate the preview device's own rate and buffer size and start its stream.
+    pub fn open_with<B: PreviewBackend>(
+        backend: &B,
+        settings: &Settings,
+    ) -> Result<Self, EngineError> {
+        if settings.preview_device.is_none() {
+            return Err(EngineError::NoPreviewDevice);
+        }
+        let supported = backend.supported_preview_configs(settings)?;
+        let config = negotiate(settings.sample_rate, settings.buffer_frames, &supported)?;
+        let shared = Arc::new(PreviewShared::default());
+        let callback = PreviewCallback::new(&config, shared.clone());
+        let commands = callback.commands.clone();
+        let retired = callback.retired.clone();
+
+        let stream = backend.build_preview(settings, &config, callback)?;
+        stream.play()?;
+        Ok(Self {
+            stream,
+            config,
+            state: TransportState::Empty,
+            loading: None,
+            stop_on_deck_load: settings.preview_stop_on_deck_load,
+            commands,
+            retired,
+            shared,
+        })
+    }
+
+    /// Configuration obtained from the preview device.
+    pub fn config(&self) -> NegotiatedConfig {
+        self.config
+    }
+
+    pub fn state(&self) -> &TransportState {
+        &self.state
+    }
+
+    /// Playhead as a fraction of the track, for drawing on the overview.
+    pub fn position(&self) -> f64 {
+        f64::from_bits(self.shared.position.load(Ordering::Relaxed))
+    }
+
+    /// Cue `track`, parked at the start, replacing whatever was previewing.
+    pub fn load(&mut self, track: Track) -> bool {
+        self.loading = None;
+        if self.send(PreviewCommand::Load(Box::new(track))) {
+            self.shared.ended.store(false, Ordering::Release);
+            self.state = TransportState::Loaded;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Decode `path` on a worker thread; [`poll`](Self::poll) cues it once ready.
+    pub fn load_file(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let spawned = thread::Builder::new()
+            .name("preview-load".into())
+            .spawn(move || {
+                let _ = sender.send(decode_track(&path).map_err(|err| err.to_string()));
+            });
+        self.send(PreviewCommand::Eject);
+        match spawned {
+            Ok(_) => {
+                self.loading = Some(receiver);
+                self.state = TransportState::Loading;
+            }
+            Err(err) => {
+                self.loading = None;
+                self.state = TransportState::Error(format!("failed to spawn loader: {err}"));
+            }
+        }
+    }
+
+    /// Pick up a finished decode and the end of playback, and free retired tracks.
+    /// Call regularly from the control thread.
+    pub fn poll(&mut self) {
+        while self.retired.pop().is_some() {}
+        if let Some(receiver) = &self.loading {
+            let result = match receiver.try_recv() {
+                Ok(result) => Some(result),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => Some(Err("loader panicked".into())),
+            };
+            match result {
+                Some(Ok(track)) => {
+                    self.load(track);
+                }
+                Some(Err(reason)) => {
+                    self.loading = None;
+                    self.state = TransportState::Error(reason);
+                }
+                None => {}
+            }
+        }
+        if self.shared.ended.swap(false, Ordering::Acquire) && self.state == TransportState::Playing
+        {
+            self.state = TransportState::Ended;
+        }
+    }
+
+    pub fn play(&mut self) -> bool {
+        self.transport(DeckCommand::Play, PreviewCommand::Play)
+    }
+
+    pub fn pause(&mut self) -> bool {
+        self.transport(DeckCommand::Pause, PreviewCommand::Pause)
+    }
+
+    /// Stop and park at the start of the track.
+    pub fn stop(&mut self) -> bool {
+        self.transport(DeckCommand::Stop, PreviewCommand::Stop)
+    }
+
+    pub fn eject(&mut self) -> bool {
+        self.loading = None;
+        self.transport(DeckCommand::Eject, PreviewCommand::Eject)
+    }
+
+    /// Jump to `fraction` of the track, e.g. where the overview was clicked.
+    /// Playing on from the end of a finished track resumes as paused.
+    pub fn seek(&mut self, fraction: f64) -> bool {
+        let sent = matches!(
+            self.state,
+            TransportState::Loaded
+                | TransportState::Playing
+                | TransportState::Paused
+                | TransportState::Ended
+        ) && self.send(PreviewCommand::Seek(fraction));
+        if sent && self.state == TransportState::Ended {
+            self.state = TransportState::Paused;
+        }
+        sent
+    }
+
+    /// Preview level, independent of the master; 1 is unity.
+    pub fn set_volume(&self, volume: f32) -> bool {
+        self.send(PreviewCommand::Volume(volume))
+    }
+
+    /// Whether loading a deck stops the preview.
+    pub fn stop_on_deck_load(&self) -> bool {
+        self.stop_on_deck_load
+    }
+
+    pub fn set_stop_on_deck_load(&mut self, stop: bool) {
+        self.stop_on_deck_load = stop;
+    }
+
+    /// Tell the preview a track went to a deck; stops it if so configured.
+    pub fn deck_loaded(&mut self) {
+        if self.stop_on_deck_load
+            && matches!(self.state, TransportState::Playing | TransportState::Paused)
+        {
+            self.stop();
+        }
+    }
+
+    /// Pause and close the preview stream.
+    pub fn close(self) -> Result<(), EngineError> {
+        self.stream.pause()
+    }
+
+    fn transport(&mut self, command: DeckCommand, preview: PreviewCommand) -> bool {
+        let Some(next) = self.state.after(command) else {
+            return false;
+        };
+        if !self.send(preview) {
+            return false;
+        }
+        self.state = next;
+        true
+    }
+
+    fn send(&self, command: PreviewCommand) -> bool {
+        self.commands.push(command).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::SampleFormat;
+    use crate::record::{write_wav, RecorderOptions, WavFormat};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    struct NullStream;
+
+    impl AudioStream for NullStream {
+        fn play(&self) -> Result<(), EngineError> {
+            Ok(())
+        }
+
+        fn pause(&self) -> Result<(), EngineError> {
+            Ok(())
+        }
+    }
+
+    /// Headphone interface at a fixed rate whose callback the test drives by hand.
+    struct FakeHeadphones {
+        sample_rate: u32,
+        callback: Mutex<Option<PreviewCallback>>,
+    }
+
+    impl FakeHeadphones {
+        fn new(sample_rate: u32) -> Self {
+            Self {
+                sample_rate,
+                callback: Mutex::new(None),
+            }
+        }
+
+        fn open(&self) -> (PreviewPlayer, PreviewCallback) {
+            let settings = Settings {
+                preview_device: Some("headphones".into()),
+                ..Settings::default()
+            };
+            let player = PreviewPlayer::open_with(self, &settings).unwrap();
+            (player, self.callback.lock().unwrap().take().unwrap())
+        }
+    }
+
+    impl PreviewBackend for FakeHeadphones {
+        fn supported_preview_configs(
+            &self,
+            _: &Settings,
+        ) -> Result<Vec<SupportedConfig>, EngineError> {
+            Ok(vec![SupportedConfig {
+                channels: 2,
+                min_sample_rate: self.sample_rate,
+                max_sample_rate: self.sample_rate,
+                buffer_frames: None,
+                sample_format: SampleFormat::F32,
+            }])
+        }
+
+        fn build_preview(
+            &self,
+            _: &Settings,
+            _: &NegotiatedConfig,
+            callback: PreviewCallback,
+        ) -> Result<Box<dyn AudioStream>, EngineError> {
+            *self.callback.lock().unwrap() = Some(callback);
+            Ok(Box::new(NullStream))
+        }
+    }
+
+    /// Stereo ramp whose left side counts frames.
+    fn ramp(frames: usize, sample_rate: u32) -> Track {
+        let samples: Vec<f32> = (0..frames).flat_map(|f| [f as f32, 0.5]).collect();
+        Track::from_interleaved(samples, sample_rate)
+    }
+
+    fn left(output: &[f32]) -> Vec<f32> {
+        output.iter().step_by(2).copied().collect()
+    }
+
+    #[test]
+    fn transport_follows_the_deck_state_machine() {
+        let headphones = FakeHeadphones::new(48_000);
+        let (mut player, mut callback) = headphones.open();
+        assert_eq!(player.state(), &TransportState::Empty);
+        assert!(!player.play());
+        assert!(!player.seek(0.5));
+
+        assert!(player.load(ramp(1_000, 48_000)));
+        assert!(!player.pause());
+        assert!(player.play());
+        assert_eq!(player.state(), &TransportState::Playing);
+        assert!(player.pause());
+        assert!(player.play());
+        let mut output = vec![0.0; 2_000 * 2];
+        callback.process(&mut output);
+        player.poll();
+        assert_eq!(player.state(), &TransportState::Ended);
+        assert_eq!(player.position(), 1.0);
+
+        // Seeking out of the end parks the player, ready to play on.
+        assert!(player.seek(0.25));
+        assert_eq!(player.state(), &TransportState::Paused);
+        assert!(player.play());
+        callback.process(&mut output[..400]);
+        assert_eq!(left(&output[..400])[150], 400.0);
+
+        // Loading a deck stops the preview only while that is configured.
+        player.set_stop_on_deck_load(false);
+        player.deck_loaded();
+        assert_eq!(player.state(), &TransportState::Playing);
+        player.set_stop_on_deck_load(true);
+        player.deck_loaded();
+        assert_eq!(player.state(), &TransportState::Loaded);
+        callback.process(&mut output);
+        assert!(output.iter().all(|s| *s == 0.0));
+
+        // A new track may replace the current one even while playing.
+        assert!(player.play());
+        assert!(player.load(ramp(10, 48_000)));
+        assert_eq!(player.state(), &TransportState::Loaded);
+        assert!(player.eject());
+        assert_eq!(player.state(), &TransportState::Empty);
+        callback.process(&mut output);
+        assert!(output.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn seeks_declick_and_scale_by_volume() {
+        let (mut player, mut callback) = FakeHeadphones::new(48_000).open();
+        player.load(ramp(48_000, 48_000));
+        player.seek(0.5);
+        player.play();
+        let mut output = vec![0.0; 1_000 * 2];
+        callback.process(&mut output);
+        // Fades in from silence rather than jumping straight to mid-track.
+        let left = left(&output);
+        assert_eq!(left[0], 0.0);
+        assert!(left[1] > 0.0 && left[1] < 24_001.0 * 0.05, "{}", left[1]);
+        assert_eq!(left[500], 24_500.0);
+        assert!(player.position() > 0.5);
+
+        player.set_volume(0.5);
+        for _ in 0..10 {
+            callback.process(&mut output);
+        }
+        let last = output.len() - 2;
+        assert!(
+            (output[last + 1] - 0.25).abs() < 1e-3,
+            "{}",
+            output[last + 1]
+        );
+    }
+
+    #[test]
+    fn resamples_the_track_to_the_device_rate() {
+        let (mut player, mut callback) = FakeHeadphones::new(48_000).open();
+        player.load(ramp(100, 24_000));
+        player.play();
+        let mut output = vec![0.0; 250 * 2];
+        callback.process(&mut output);
+        let left = left(&output);
+        // Two device frames per track frame, interpolated, then silence past the end.
+        assert_eq!(left[150], 75.0);
+        assert_eq!(left[151], 75.5);
+        assert!(left[200..].iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn decodes_files_off_the_control_thread() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("preview.wav");
+        let options = RecorderOptions {
+            format: WavFormat::Float32,
+            sample_rate: 44_100,
+            ..RecorderOptions::default()
+        };
+        write_wav(&path, &[0.25; 44_100 * 2], &options).unwrap();
+
+        let (mut player, mut callback) = FakeHeadphones::new(48_000).open();
+        player.load_file(&path);
+        assert_eq!(player.state(), &TransportState::Loading);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while player.state() == &TransportState::Loading {
+            assert!(Instant::now() < deadline, "decode never finished");
+            thread::sleep(Duration::from_millis(1));
+            player.poll();
+        }
+        assert_eq!(player.state(), &TransportState::Loaded);
+        player.seek(0.5);
+        player.play();
+        let mut output = vec![0.0; 512 * 2];
+        callback.process(&mut output);
+        assert_eq!(output[511 * 2], 0.25);
+        assert!((player.position() - (0.5 + 512.0 * 44_100.0 / 48_000.0 / 44_100.0)).abs() < 1e-6);
+
+        player.load_file(dir.path().join("missing.wav"));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while player.state() == &TransportState::Loading {
+            assert!(Instant::now() < deadline, "decode never failed");
+            thread::sleep(Duration::from_millis(1));
+            player.poll();
+        }
+        assert!(matches!(player.state(), TransportState::Error(_)));
+    }
+
+    #[test]
+    fn refuses_to_open_without_a_preview_device() {
+        let headphones = FakeHeadphones::new(48_000);
+        assert!(matches!(
+            PreviewPlayer::open_with(&headphones, &Settings::default()),
+            Err(EngineError::NoPreviewDevice)
+        ));
+    }
+}
@@ -0,0 +1,212 @@
+use std::time::{Duration, Instant};
+
+use crate::settings::Settings;
+
+/// When to give up on the output device and how to bring it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryOptions {
+    /// How long the output callback may stay silent before the device is presumed gone.
+    pub heartbeat_timeout: Duration,
+    /// Wait before the first reopen attempt; doubled after every failure.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Failed attempts on the configured device before the system default is tried too.
+    pub attempts_before_fallback: u32,
+    pub fallback_to_default: bool,
+    /// Fade-in on the master once a stream is back, so it does not restart with a click.
+    pub fade_in: Duration,
+}
+
+impl Default for RecoveryOptions {
+    fn default() -> Self {
+        Self {
+            heartbeat_timeout: Duration::from_millis(500),
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(4),
+            attempts_before_fallback: 3,
+            fallback_to_default: false,
+            fade_in: Duration::from_millis(50),
+        }
+    }
+}
+
+impl RecoveryOptions {
+    /// Take the fallback switch from `settings`.
+    pub fn with_settings(mut self, settings: &Settings) -> Self {
+        self.fallback_to_default = settings.fallback_to_default;
+        self
+    }
+}
+
+/// Device to try on a reopen attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Configured,
+    Default,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Running {
+        callbacks: u64,
+        progress: Instant,
+    },
+    Lost {
+        failures: u32,
+        next_attempt: Instant,
+    },
+}
+
+/// Decides when the output device is gone and when to try reopening it.
+///
+/// Pure bookkeeping: [`EngineHandle::supervise_with`](super::EngineHandle::supervise_with)
+/// feeds it the callback counter and carries out the attempts it asks for.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    options: RecoveryOptions,
+    phase: Phase,
+}
+
+impl Watchdog {
+    pub fn new(options: RecoveryOptions, now: Instant) -> Self {
+        Self {
+            options,
+            phase: Phase::Running {
+                callbacks: 0,
+                progress: now,
+            },
+        }
+    }
+
+    pub fn options(&self) -> &RecoveryOptions {
+        &self.options
+    }
+
+    pub fn is_lost(&self) -> bool {
+        matches!(self.phase, Phase::Lost { .. })
+    }
+
+    /// Follow the output callback counter; `device_gone` is set when the stream
+    /// itself reported the device missing. Returns `true` when this call
+    /// declares the device lost.
+    pub fn check(&mut self, callbacks: u64, device_gone: bool, now: Instant) -> bool {
+        let Phase::Running {
+            callbacks: seen,
+            progress,
+        } = &mut self.phase
+        else {
+            return false;
+        };
+        if callbacks != *seen {
+            *seen = callbacks;
+            *progress = now;
+        }
+        if device_gone || now.duration_since(*progress) >= self.options.heartbeat_timeout {
+            self.phase = Phase::Lost {
+                failures: 0,
+                next_attempt: now + self.backoff(0),
+            };
+            return true;
+        }
+        false
+    }
+
+    /// Wait before the next attempt after `failures` failed ones.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        self.options
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(self.options.max_backoff)
+    }
+
+    /// Devices to try, in order, if a reopen attempt is due at `now`.
+    pub fn due(&self, now: Instant) -> &'static [Target] {
+        match self.phase {
+            Phase::Lost {
+                failures,
+                next_attempt,
+            } if now >= next_attempt => {
+                if self.options.fallback_to_default
+                    && failures >= self.options.attempts_before_fallback
+                {
+                    &[Target::Configured, Target::Default]
+                } else {
+                    &[Target::Configured]
+                }
+            }
+            _ => &[],
+        }
+    }
+
+    /// Every device of the due attempt failed; back off before the next.
+    pub fn failed(&mut self, now: Instant) {
+        if let Phase::Lost { failures, .. } = self.phase {
+            self.phase = Phase::Lost {
+                failures: failures + 1,
+                next_attempt: now + self.backoff(failures + 1),
+            };
+        }
+    }
+
+    /// A stream is running again; watch its callbacks from `now`.
+    pub fn recovered(&mut self, callbacks: u64, now: Instant) {
+        self.phase = Phase::Running {
+            callbacks,
+            progress: now,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silent_callback_trips_the_heartbeat_and_attempts_back_off() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut watchdog = Watchdog::new(
+            RecoveryOptions {
+                fallback_to_default: true,
+                ..RecoveryOptions::default()
+            },
+            start,
+        );
+        // Callbacks arriving keep it happy, however late the first poll.
+        for (at, callbacks) in [(100, 10), (550, 50), (1_000, 90)] {
+            assert!(!watchdog.check(callbacks, false, ms(at)));
+        }
+        assert!(!watchdog.check(90, false, ms(1_499)));
+        assert!(watchdog.check(90, false, ms(1_500)));
+        assert!(watchdog.is_lost());
+        assert!(!watchdog.check(90, false, ms(1_600)));
+
+        let mut attempts = Vec::new();
+        for at in (1_500..20_000).step_by(10) {
+            let targets = watchdog.due(ms(at));
+            if !targets.is_empty() {
+                attempts.push((at - 1_500, targets.len()));
+                watchdog.failed(ms(at));
+            }
+        }
+        assert_eq!(
+            attempts,
+            [
+                (250, 1),
+                (750, 1),
+                (1_750, 1),
+                // Three failures in, the default device is tried as well.
+                (3_750, 2),
+                (7_750, 2),
+                (11_750, 2),
+                (15_750, 2),
+            ]
+        );
+
+        watchdog.recovered(90, ms(20_000));
+        assert!(!watchdog.is_lost());
+        assert!(watchdog.due(ms(20_000)).is_empty());
+        // A stream error does not wait for the heartbeat.
+        assert!(watchdog.check(91, true, ms(20_010)));
+    }
+}
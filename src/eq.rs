@@ -0,0 +1,331 @@
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::db_to_linear;
+use crate::fx::Smoothed;
+
+/// Crossover between the low and mid bands.
+const LOW_CROSSOVER_HZ: f32 = 250.0;
+/// Crossover between the mid and high bands.
+const HIGH_CROSSOVER_HZ: f32 = 2_500.0;
+/// Range of a band knob.
+pub const MIN_GAIN_DB: f32 = -26.0;
+pub const MAX_GAIN_DB: f32 = 6.0;
+
+/// Band of a deck's 3-band EQ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EqBand {
+    Low = 0,
+    Mid = 1,
+    High = 2,
+}
+
+impl EqBand {
+    pub const ALL: [EqBand; 3] = [EqBand::Low, EqBand::Mid, EqBand::High];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EqBand::Low => "low",
+            EqBand::Mid => "mid",
+            EqBand::High => "high",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    Low,
+    High,
+    All,
+}
+
+/// Biquad coefficients (RBJ cookbook), normalised so a0 is 1.
+#[derive(Debug, Clone, Copy, Default)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+}
+
+impl Biquad {
+    /// Butterworth low-pass, or high-pass, or the all-pass with the same poles.
+    fn new(pass: Pass, hz: f32, sample_rate: u32) -> Self {
+        let w = 2.0 * PI * hz.min(sample_rate as f32 * 0.45) / sample_rate as f32;
+        let (sin, cos) = w.sin_cos();
+        let alpha = sin / (2.0 * FRAC_1_SQRT_2);
+        let a0 = 1.0 + alpha;
+        let b = match pass {
+            Pass::Low => [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            Pass::High => [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            Pass::All => [1.0 - alpha, -2.0 * cos, 1.0 + alpha],
+        };
+        Self {
+            b: b.map(|b| b / a0),
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        }
+    }
+
+    /// Transposed direct form II.
+    fn tick(&self, state: &mut [f32; 2], x: f32) -> f32 {
+        let y = self.b[0] * x + state[0];
+        state[0] = self.b[1] * x - self.a[0] * y + state[1];
+        state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+
+    /// Two passes, making a 24 dB/octave Linkwitz-Riley section.
+    fn tick_twice(&self, state: &mut [[f32; 2]; 2], x: f32) -> f32 {
+        let y = self.tick(&mut state[0], x);
+        self.tick(&mut state[1], y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SplitState {
+    low: [[f32; 2]; 2],
+    /// Phase compensation of the low band for the high crossover.
+    low_all_pass: [f32; 2],
+    rest: [[f32; 2]; 2],
+    mid: [[f32; 2]; 2],
+    high: [[f32; 2]; 2],
+}
+
+/// Isolator-style 3-band EQ on one deck, ahead of its fader.
+///
+/// The signal is split by Linkwitz-Riley crossovers so the bands sum back flat,
+/// and each band's gain glides to its knob, or out entirely while its kill is
+/// on. While every knob sits at 0 dB the deck passes through untouched.
+#[derive(Debug, Clone)]
+pub struct DeckEq {
+    low_pass: Biquad,
+    high_pass: Biquad,
+    mid_pass: Biquad,
+    top_pass: Biquad,
+    all_pass: Biquad,
+    gains_db: [f32; 3],
+    kills: [bool; 3],
+    gains: [Smoothed; 3],
+    /// How far the bands are mixed in over the untouched deck.
+    engaged: Smoothed,
+    state: [SplitState; 2],
+}
+
+impl DeckEq {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut eq = Self {
+            low_pass: Biquad::default(),
+            high_pass: Biquad::default(),
+            mid_pass: Biquad::default(),
+            top_pass: Biquad::default(),
+            all_pass: Biquad::default(),
+            gains_db: [0.0; 3],
+            kills: [false; 3],
+            gains: [Smoothed::new(1.0, sample_rate); 3],
+            engaged: Smoothed::new(0.0, sample_rate),
+            state: [SplitState::default(); 2],
+        };
+        eq.prepare(sample_rate);
+        eq
+    }
+
+    /// Recompute the crossovers for `sample_rate` and clear the filter state,
+    /// keeping the knob settings.
+    pub fn prepare(&mut self, sample_rate: u32) {
+        self.low_pass = Biquad::new(Pass::Low, LOW_CROSSOVER_HZ, sample_rate);
+        self.high_pass = Biquad::new(Pass::High, LOW_CROSSOVER_HZ, sample_rate);
+        self.mid_pass = Biquad::new(Pass::Low, HIGH_CROSSOVER_HZ, sample_rate);
+        self.top_pass = Biquad::new(Pass::High, HIGH_CROSSOVER_HZ, sample_rate);
+        self.all_pass = Biquad::new(Pass::All, HIGH_CROSSOVER_HZ, sample_rate);
+        for band in EqBand::ALL {
+            self.gains[band as usize] = Smoothed::new(self.band_gain(band), sample_rate);
+        }
+        self.engaged = Smoothed::new(self.engaged.target(), sample_rate);
+        self.state = [SplitState::default(); 2];
+    }
+
+    pub fn gain_db(&self, band: EqBand) -> f32 {
+        self.gains_db[band as usize]
+    }
+
+    /// Turn `band` to `db`, clamped to the knob's range.
+    pub fn set_gain_db(&mut self, band: EqBand, db: f32) {
+        let db = if db.is_nan() {
+            0.0
+        } else {
+            db.clamp(MIN_GAIN_DB, MAX_GAIN_DB)
+        };
+        self.gains_db[band as usize] = db;
+        self.update_gain(band);
+    }
+
+    pub fn is_killed(&self, band: EqBand) -> bool {
+        self.kills[band as usize]
+    }
+
+    /// Remove `band` entirely, fading it out rather than cutting; releasing the
+    /// kill brings back the knob's gain.
+    pub fn set_kill(&mut self, band: EqBand, on: bool) {
+        self.kills[band as usize] = on;
+        self.update_gain(band);
+    }
+
+    fn band_gain(&self, band: EqBand) -> f32 {
+        if self.kills[band as usize] {
+            0.0
+        } else {
+            db_to_linear(self.gains_db[band as usize])
+        }
+    }
+
+    fn update_gain(&mut self, band: EqBand) {
+        self.gains[band as usize].set(self.band_gain(band));
+        let flat = self.gains.iter().all(|gain| gain.target() == 1.0);
+        self.engaged.set(if flat { 0.0 } else { 1.0 });
+    }
+
+    /// Whether every knob has settled at 0 dB, leaving the deck untouched.
+    #[cfg(feature = "simd")]
+    pub(crate) fn is_bypassed(&self) -> bool {
+        self.engaged.is_settled()
+            && self.engaged.current() == 0.0
+            && self.gains.iter().all(Smoothed::is_settled)
+    }
+
+    /// Stand in for a bypassed block without running the filters, clearing
+    /// them so they start from rest rather than stale state when engaged.
+    #[cfg(feature = "simd")]
+    pub(crate) fn rest(&mut self) {
+        self.state = [SplitState::default(); 2];
+    }
+
+    /// Equalise one frame.
+    pub fn tick(&mut self, frame: [f32; 2]) -> [f32; 2] {
+        let gains = self.gains.each_mut().map(|gain| gain.next());
+        let engaged = self.engaged.next();
+        let mut out = frame;
+        for (out, state) in out.iter_mut().zip(&mut self.state) {
+            let dry = *out;
+            // Filters keep running while bypassed, so engaging does not start them cold.
+            let low = self.low_pass.tick_twice(&mut state.low, dry);
+            let low = self.all_pass.tick(&mut state.low_all_pass, low);
+            let rest = self.high_pass.tick_twice(&mut state.rest, dry);
+            let mid = self.mid_pass.tick_twice(&mut state.mid, rest);
+            let high = self.top_pass.tick_twice(&mut state.high, rest);
+            if engaged > 0.0 {
+                let wet = low * gains[0] + mid * gains[1] + high * gains[2];
+                *out = dry + (wet - dry) * engaged;
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, DeckId, ParameterUpdate, SummingBus};
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn tone(freq: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let s = (i as f32 / SAMPLE_RATE as f32 * freq * std::f32::consts::TAU).sin();
+                [s, s]
+            })
+            .collect()
+    }
+
+    /// Level change in dB of a tone through deck A of a bus with `bands` set,
+    /// over the second half of a second.
+    fn band_response(freq: f32, bands: &[(EqBand, f32)]) -> f32 {
+        let updates: Vec<_> = bands
+            .iter()
+            .map(|&(band, gain_db)| ParameterUpdate::DeckEq {
+                deck: DeckId::A,
+                band,
+                gain_db,
+            })
+            .collect();
+        response(freq, &updates)
+    }
+
+    /// Level change in dB of a tone through deck A of a bus after `updates`.
+    fn response(freq: f32, updates: &[ParameterUpdate]) -> f32 {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        bus.prepare(SAMPLE_RATE);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        for update in updates {
+            tx.send(update.clone()).unwrap();
+        }
+        let input = tone(freq, SAMPLE_RATE as usize);
+        let mut out = vec![0.0; input.len()];
+        for (block, out) in input.chunks(512).zip(out.chunks_mut(512)) {
+            bus.mix_stereo(block, &vec![0.0; block.len()], out);
+        }
+        let rms = |samples: &[f32]| {
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+        let half = input.len() / 2;
+        20.0 * (rms(&out[half..]) / rms(&input[half..])).log10()
+    }
+
+    #[test]
+    fn bands_change_their_own_range() {
+        // Flat knobs leave the deck alone; the bands sum back flat once engaged.
+        assert_eq!(band_response(100.0, &[]), 0.0);
+        let engaged = [(EqBand::Mid, -1e-3)];
+        assert!(band_response(100.0, &engaged).abs() < 0.1);
+        assert!(band_response(5_000.0, &engaged).abs() < 0.1);
+
+        let low_cut = [(EqBand::Low, MIN_GAIN_DB)];
+        assert!(band_response(100.0, &low_cut) < -15.0);
+        assert!(band_response(5_000.0, &low_cut).abs() < 0.1);
+
+        let high_cut = [(EqBand::High, MIN_GAIN_DB)];
+        assert!(band_response(5_000.0, &high_cut) < -15.0);
+        assert!(band_response(100.0, &high_cut).abs() < 0.1);
+
+        // Knobs stop at the top of their range.
+        let high_boost = band_response(5_000.0, &[(EqBand::High, 12.0)]);
+        assert!((high_boost - MAX_GAIN_DB).abs() < 0.5, "{high_boost}");
+    }
+
+    #[test]
+    fn kill_removes_the_band_and_releases_to_the_knob() {
+        let kill = |band| ParameterUpdate::DeckEqKill {
+            deck: DeckId::A,
+            band,
+            on: true,
+        };
+        assert!(response(60.0, &[kill(EqBand::Low)]) < -40.0);
+        assert!(response(6_000.0, &[kill(EqBand::Low)]).abs() < 0.1);
+
+        let mut eq = DeckEq::new(SAMPLE_RATE);
+        eq.set_gain_db(EqBand::Low, -6.0);
+        eq.set_kill(EqBand::Low, true);
+        assert_eq!(eq.gain_db(EqBand::Low), -6.0);
+        eq.set_kill(EqBand::Low, false);
+        assert_eq!(eq.gains[0].target(), db_to_linear(-6.0));
+    }
+
+    #[test]
+    fn band_changes_glide_without_jumps() {
+        let mut eq = DeckEq::new(SAMPLE_RATE);
+        let input = tone(100.0, SAMPLE_RATE as usize);
+        let mut last = 0.0f32;
+        for (i, frame) in input.chunks_exact(2).enumerate() {
+            match i {
+                12_000 => eq.set_gain_db(EqBand::Low, MIN_GAIN_DB),
+                24_000 => eq.set_kill(EqBand::Low, true),
+                36_000 => eq.set_kill(EqBand::Low, false),
+                _ => {}
+            }
+            let [left, _] = eq.tick([frame[0], frame[1]]);
+            // A 100 Hz sine moves at most about 0.013 per sample.
+            assert!((left - last).abs() < 0.02, "{i}: {last} -> {left}");
+            last = left;
+        }
+    }
+}
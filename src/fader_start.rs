@@ -0,0 +1,317 @@
+use std::time::{Duration, Instant};
+
+use crate::deck::DeckCommand;
+use crate::settings::Settings;
+use crate::{DeckCommandSender, DeckId, ParameterUpdate, XfAssign};
+
+/// Thresholds and debounce for fader-start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaderStartOptions {
+    /// Openness a closed channel has to rise above to start its deck.
+    pub open_threshold: f32,
+    /// Openness an open channel has to fall to before its deck is cued.
+    pub close_threshold: f32,
+    /// How long a channel has to stay past a threshold before the deck follows.
+    pub debounce: Duration,
+}
+
+impl Default for FaderStartOptions {
+    fn default() -> Self {
+        Self {
+            open_threshold: 0.05,
+            close_threshold: 0.01,
+            debounce: Duration::from_millis(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    enabled: bool,
+    /// Debounced state the deck was last told about.
+    open: bool,
+    /// When the channel crossed the threshold towards the other state.
+    crossed_at: Option<Instant>,
+    /// Set by a manual pause while open; cleared when the channel next closes.
+    suppressed: bool,
+}
+
+/// Starts a deck when its channel opens and cues it back to the start when it
+/// closes, like the fader-start switch on a club mixer.
+///
+/// A channel's openness is its fader times how far the crossfader is away from
+/// the opposite side, so moving the crossfader off the far side starts the deck
+/// too; a deck assigned thru follows its fader alone. Feed it every update sent to the bus, as with
+/// [`MixerState::apply`](crate::session::MixerState::apply), and call
+/// [`poll`](Self::poll) regularly so debounced changes land without further
+/// movement.
+pub struct FaderStart {
+    decks: DeckCommandSender,
+    options: FaderStartOptions,
+    gains: [f32; 2],
+    crossfader: f32,
+    /// Hamster mode, with deck B on the crossfader's left.
+    crossfader_reverse: bool,
+    assign: [XfAssign; 2],
+    channels: [Channel; 2],
+}
+
+impl FaderStart {
+    /// Starts from the bus's power-on state, with every channel open and fader-start off.
+    pub fn new(decks: DeckCommandSender, options: FaderStartOptions) -> Self {
+        let mut fader_start = Self {
+            decks,
+            options,
+            gains: [1.0; 2],
+            crossfader: 0.5,
+            crossfader_reverse: false,
+            assign: [XfAssign::A, XfAssign::B],
+            channels: [Channel::default(); 2],
+        };
+        for deck in [DeckId::A, DeckId::B] {
+            fader_start.channels[deck.index()].open =
+                fader_start.openness(deck) > options.open_threshold;
+        }
+        fader_start
+    }
+
+    /// Take the per-deck enable flags from `settings`.
+    pub fn with_settings(mut self, settings: &Settings) -> Self {
+        for deck in [DeckId::A, DeckId::B] {
+            self.set_enabled(deck, settings.fader_start[deck.index()]);
+        }
+        self
+    }
+
+    pub fn is_enabled(&self, deck: DeckId) -> bool {
+        self.channels[deck.index()].enabled
+    }
+
+    /// Turn fader-start on or off for `deck`. The channel's position is still
+    /// followed while off, so turning it back on does not fire a command.
+    pub fn set_enabled(&mut self, deck: DeckId, enabled: bool) {
+        self.channels[deck.index()].enabled = enabled;
+    }
+
+    /// Whether a manual pause is holding `deck` until its channel closes.
+    pub fn is_suppressed(&self, deck: DeckId) -> bool {
+        self.channels[deck.index()].suppressed
+    }
+
+    /// Follow an update sent to the bus.
+    pub fn observe(&mut self, update: &ParameterUpdate, now: Instant) {
+        match *update {
+            // Only decks A and B have fader start.
+            ParameterUpdate::DeckGain { deck, gain } if deck.index() < 2 => {
+                self.gains[deck.index()] = gain;
+                self.track(deck, now);
+            }
+            ParameterUpdate::Crossfader(value)
+            | ParameterUpdate::CrossfaderRamp { target: value, .. }
+            | ParameterUpdate::CrossfadeTo { target: value, .. } => {
+                self.crossfader = value;
+                self.track(DeckId::A, now);
+                self.track(DeckId::B, now);
+            }
+            ParameterUpdate::CrossfaderReverse(reverse) => {
+                self.crossfader_reverse = reverse;
+                self.track(DeckId::A, now);
+                self.track(DeckId::B, now);
+            }
+            ParameterUpdate::CrossfaderAssign { deck, assign } if deck.index() < 2 => {
+                self.assign[deck.index()] = assign;
+                self.track(deck, now);
+            }
+            _ => {}
+        }
+    }
+
+    /// Tell fader-start about a transport command sent to `deck` by hand.
+    ///
+    /// Pausing or stopping an open channel keeps fader-start from restarting
+    /// the deck until the channel has fully closed once.
+    pub fn manual(&mut self, deck: DeckId, command: DeckCommand) {
+        let channel = &mut self.channels[deck.index()];
+        if matches!(command, DeckCommand::Pause | DeckCommand::Stop) && channel.open {
+            channel.suppressed = true;
+        }
+    }
+
+    /// Act on threshold crossings whose debounce has run out.
+    pub fn poll(&mut self, now: Instant) {
+        for deck in [DeckId::A, DeckId::B] {
+            self.settle(deck, now);
+        }
+    }
+
+    fn openness(&self, deck: DeckId) -> f32 {
+        // Side A sits on the left unless hamster mode swaps the sides.
+        let crossfader = match self.assign[deck.index()] {
+            XfAssign::Thru => 1.0,
+            side if (side == XfAssign::A) != self.crossfader_reverse => 1.0 - self.crossfader,
+            _ => self.crossfader,
+        };
+        self.gains[deck.index()] * crossfader.clamp(0.0, 1.0)
+    }
+
+    fn track(&mut self, deck: DeckId, now: Instant) {
+        let openness = self.openness(deck);
+        let channel = &mut self.channels[deck.index()];
+        // The gap between the thresholds keeps a wiggle near zero from flapping.
+        let crossed = if channel.open {
+            openness <= self.options.close_threshold
+        } else {
+            openness > self.options.open_threshold
+        };
+        if !crossed {
+            channel.crossed_at = None;
+        } else if channel.crossed_at.is_none() {
+            channel.crossed_at = Some(now);
+        }
+        self.settle(deck, now);
+    }
+
+    fn settle(&mut self, deck: DeckId, now: Instant) {
+        let channel = &mut self.channels[deck.index()];
+        let Some(crossed_at) = channel.crossed_at else {
+            return;
+        };
+        if now.duration_since(crossed_at) < self.options.debounce {
+            return;
+        }
+        channel.crossed_at = None;
+        channel.open = !channel.open;
+        let command = if channel.open {
+            DeckCommand::Play
+        } else if channel.suppressed {
+            channel.suppressed = false;
+            return;
+        } else {
+            // Stop parks the deck at the start of the track, its cue point.
+            DeckCommand::Stop
+        };
+        if channel.enabled {
+            // A full queue loses the command like any other controller input.
+            let _ = self.decks.send(deck, command);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deck_command_channel, DeckCommandReceiver};
+
+    const DEBOUNCE: Duration = Duration::from_millis(30);
+
+    fn fader_start() -> (FaderStart, DeckCommandReceiver) {
+        let (sender, receiver) = deck_command_channel(16);
+        let mut fader_start = FaderStart::new(
+            sender,
+            FaderStartOptions {
+                debounce: DEBOUNCE,
+                ..FaderStartOptions::default()
+            },
+        );
+        fader_start.set_enabled(DeckId::A, true);
+        fader_start.set_enabled(DeckId::B, true);
+        (fader_start, receiver)
+    }
+
+    fn drain(receiver: &DeckCommandReceiver) -> Vec<(DeckId, DeckCommand)> {
+        std::iter::from_fn(|| receiver.pop()).collect()
+    }
+
+    /// Move deck A's fader through `gains`, one step every 10 ms from `start`.
+    fn fade(fader_start: &mut FaderStart, start: Instant, gains: &[f32]) -> Instant {
+        let mut now = start;
+        for &gain in gains {
+            now += Duration::from_millis(10);
+            fader_start.observe(
+                &ParameterUpdate::DeckGain {
+                    deck: DeckId::A,
+                    gain,
+                },
+                now,
+            );
+        }
+        now
+    }
+
+    #[test]
+    fn fader_opens_and_closes_the_deck_after_the_debounce() {
+        let (mut fader_start, receiver) = fader_start();
+        let start = Instant::now();
+        let now = fade(&mut fader_start, start, &[0.5, 0.0]);
+        fader_start.poll(now + DEBOUNCE);
+        assert_eq!(drain(&receiver), [(DeckId::A, DeckCommand::Stop)]);
+
+        let now = fade(&mut fader_start, now + DEBOUNCE, &[0.2, 0.4, 0.6, 0.8]);
+        assert_eq!(drain(&receiver), [(DeckId::A, DeckCommand::Play)]);
+        fade(&mut fader_start, now, &[0.3, 0.0]);
+        fader_start.poll(now + Duration::from_millis(20) + DEBOUNCE);
+        assert_eq!(drain(&receiver), [(DeckId::A, DeckCommand::Stop)]);
+    }
+
+    #[test]
+    fn wiggles_near_zero_do_not_retrigger() {
+        let (mut fader_start, receiver) = fader_start();
+        let now = fade(&mut fader_start, Instant::now(), &[0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(drain(&receiver), [(DeckId::A, DeckCommand::Stop)]);
+
+        // Inside the hysteresis gap, then a blip shorter than the debounce.
+        let now = fade(&mut fader_start, now, &[0.03, 0.0, 0.04, 0.01, 0.2, 0.0]);
+        fader_start.poll(now + DEBOUNCE * 2);
+        assert!(drain(&receiver).is_empty());
+    }
+
+    #[test]
+    fn crossfader_starts_the_deck_it_moves_towards() {
+        let (mut fader_start, receiver) = fader_start();
+        let start = Instant::now();
+        fader_start.observe(&ParameterUpdate::Crossfader(1.0), start);
+        fader_start.poll(start + DEBOUNCE);
+        assert_eq!(drain(&receiver), [(DeckId::A, DeckCommand::Stop)]);
+
+        let now = start + DEBOUNCE * 2;
+        fader_start.observe(
+            &ParameterUpdate::CrossfaderRamp {
+                target: 0.0,
+                seconds: 1.0,
+            },
+            now,
+        );
+        fader_start.poll(now + DEBOUNCE);
+        assert_eq!(
+            drain(&receiver),
+            [
+                (DeckId::A, DeckCommand::Play),
+                (DeckId::B, DeckCommand::Stop)
+            ]
+        );
+    }
+
+    #[test]
+    fn manual_pause_holds_off_until_the_fader_closes() {
+        let (mut fader_start, receiver) = fader_start();
+        fader_start.set_enabled(DeckId::B, false);
+        fader_start.manual(DeckId::A, DeckCommand::Pause);
+        assert!(fader_start.is_suppressed(DeckId::A));
+
+        // Closing after a manual pause leaves the playhead where it was.
+        let now = fade(&mut fader_start, Instant::now(), &[0.0, 0.0, 0.0, 0.0]);
+        assert!(drain(&receiver).is_empty());
+        assert!(!fader_start.is_suppressed(DeckId::A));
+
+        let now = fade(&mut fader_start, now, &[1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(drain(&receiver), [(DeckId::A, DeckCommand::Play)]);
+
+        // Disabled decks are followed but never commanded.
+        fader_start.observe(&ParameterUpdate::Crossfader(0.0), now);
+        fader_start.poll(now + DEBOUNCE);
+        assert!(drain(&receiver).is_empty());
+        fader_start.set_enabled(DeckId::B, true);
+        fader_start.poll(now + DEBOUNCE * 2);
+        assert!(drain(&receiver).is_empty());
+    }
+}
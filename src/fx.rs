@@ -0,0 +1,215 @@
+//! Deck and send effects, run on the audio thread through [`Fx`].
+//!
+//! # Wet and dry
+//!
+//! Every effect's wet/dry parameter takes a balance in [0, 1], smoothed like
+//! its others: 0 passes the input through untouched, bit for bit, 1 leaves
+//! only the effect, and values between crossfade linearly from one to the
+//! other.
+
+pub mod chain;
+pub mod delay;
+pub mod filter;
+pub mod flanger;
+pub mod phaser;
+pub mod reverb;
+
+use std::fmt::Debug;
+
+pub use chain::{chain_param, ChainCommand, FxChain, FxChainHandle};
+pub use delay::{DelayFx, DelayTime};
+pub use filter::FilterFx;
+pub use flanger::{FlangerFx, FlangerRate};
+pub use phaser::PhaserFx;
+pub use reverb::ReverbFx;
+
+/// Time constant of the one-pole parameter smoothers.
+const SMOOTHING_SECONDS: f32 = 0.01;
+
+/// An effect processing interleaved stereo frames in place on the audio thread.
+///
+/// Implementations must not allocate or block in any of these methods.
+pub trait Fx: Send + Debug {
+    fn process(&mut self, frames: &mut [f32]);
+
+    /// Set parameter `id` (effect-specific) to `value`; unknown ids are ignored.
+    fn set_param(&mut self, id: u32, value: f32);
+
+    /// Clear internal state such as delay lines and settle smoothed parameters.
+    fn reset(&mut self);
+
+    /// Tempo of the deck the effect sits on, for beat-synced effects.
+    fn set_tempo(&mut self, _bpm: Option<f32>) {}
+}
+
+/// One-pole smoother that glides a parameter towards its target to avoid zipper noise.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Smoothed {
+    current: f32,
+    target: f32,
+    coeff: f32,
+}
+
+impl Smoothed {
+    pub(crate) fn new(value: f32, sample_rate: u32) -> Self {
+        Self::with_seconds(value, SMOOTHING_SECONDS, sample_rate)
+    }
+
+    /// Glide with a time constant of `seconds`; zero jumps straight to each target.
+    pub(crate) fn with_seconds(value: f32, seconds: f32, sample_rate: u32) -> Self {
+        let mut smoothed = Self {
+            current: value,
+            target: value,
+            coeff: 0.0,
+        };
+        smoothed.set_seconds(seconds, sample_rate);
+        smoothed
+    }
+
+    /// Change the time constant without disturbing a glide in progress.
+    pub(crate) fn set_seconds(&mut self, seconds: f32, sample_rate: u32) {
+        self.coeff = (-1.0 / (seconds.max(0.0) * sample_rate as f32)).exp();
+    }
+
+    pub(crate) fn set(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    pub(crate) fn target(&self) -> f32 {
+        self.target
+    }
+
+    pub(crate) fn current(&self) -> f32 {
+        self.current
+    }
+
+    /// Jump straight to the target.
+    pub(crate) fn settle(&mut self) {
+        self.current = self.target;
+    }
+
+    pub(crate) fn is_settled(&self) -> bool {
+        self.current == self.target
+    }
+
+    pub(crate) fn next(&mut self) -> f32 {
+        let next = self.target + (self.current - self.target) * self.coeff;
+        // Snap once close, or once rounding stalls the glide short of the target.
+        self.current = if (next - self.target).abs() < 1e-6 || next == self.current {
+            self.target
+        } else {
+            next
+        };
+        self.current
+    }
+}
+
+/// Crossfade `sample` towards the effect's `wet` output by a
+/// [wet/dry balance](self#wet-and-dry) of `mix`.
+pub(crate) fn mix_wet(sample: &mut f32, wet: f32, mix: f32) {
+    if mix == 1.0 {
+        *sample = wet;
+    } else if mix != 0.0 {
+        *sample += (wet - *sample) * mix;
+    }
+}
+
+/// Length of `division` of a whole note at `bpm` in 4/4, in milliseconds.
+///
+/// A division of 0.25 is one beat; 0.375 a dotted eighth.
+pub fn division_ms(division: f32, bpm: f32) -> f32 {
+    division * 4.0 * 60_000.0 / bpm
+}
+
+/// Raised-cosine LFO in [0, 1] whose phase runs on across blocks.
+///
+/// The rate is free-running in Hz, or locked to a beat division per cycle
+/// while a tempo is known.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Lfo {
+    phase: f32,
+    rate_hz: f32,
+    sync_division: Option<f32>,
+    bpm: Option<f32>,
+    sample_rate: f32,
+}
+
+impl Lfo {
+    pub(crate) fn new(rate_hz: f32, sample_rate: u32) -> Self {
+        Self {
+            phase: 0.0,
+            rate_hz,
+            sync_division: None,
+            bpm: None,
+            sample_rate: sample_rate as f32,
+        }
+    }
+
+    pub(crate) fn set_rate_hz(&mut self, rate_hz: f32) {
+        self.rate_hz = rate_hz.max(0.0);
+    }
+
+    /// Cycle length as a fraction of a whole note; zero or less returns to the free rate.
+    pub(crate) fn set_sync_division(&mut self, division: f32) {
+        self.sync_division = (division > 0.0).then_some(division);
+    }
+
+    pub(crate) fn set_tempo(&mut self, bpm: Option<f32>) {
+        self.bpm = bpm.filter(|bpm| *bpm > 0.0);
+    }
+
+    /// Run at `sample_rate` from here on, keeping the phase.
+    pub(crate) fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+    }
+
+    /// Restart the cycle from its low point.
+    pub(crate) fn retrigger(&mut self) {
+        self.phase = 0.0;
+    }
+
+    pub(crate) fn rate_hz(&self) -> f32 {
+        match (self.sync_division, self.bpm) {
+            (Some(division), Some(bpm)) => 1_000.0 / division_ms(division, bpm),
+            _ => self.rate_hz,
+        }
+    }
+
+    /// Value `offset` cycles ahead of where the LFO is, without moving it on.
+    pub(crate) fn peek(&self, offset: f32) -> f32 {
+        0.5 - 0.5 * (std::f32::consts::TAU * (self.phase + offset)).cos()
+    }
+
+    pub(crate) fn next(&mut self) -> f32 {
+        let value = self.peek(0.0);
+        self.phase += self.rate_hz() / self.sample_rate;
+        self.phase -= self.phase.floor();
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lfo_runs_on_across_calls_and_follows_tempo() {
+        let mut lfo = Lfo::new(1.0, 1_000);
+        let cycle: Vec<f32> = (0..1_000).map(|_| lfo.next()).collect();
+        assert_eq!(cycle[0], 0.0);
+        assert!((cycle[500] - 1.0).abs() < 1e-6);
+        assert!(lfo.next().abs() < 1e-6);
+
+        // Synced rates only apply once a tempo is known.
+        lfo.set_sync_division(1.0);
+        assert_eq!(lfo.rate_hz(), 1.0);
+        lfo.set_tempo(Some(120.0));
+        assert_eq!(lfo.rate_hz(), 0.5);
+        lfo.set_sync_division(0.0);
+        assert_eq!(lfo.rate_hz(), 1.0);
+
+        lfo.next();
+        lfo.retrigger();
+        assert_eq!(lfo.next(), 0.0);
+    }
+}
@@ -0,0 +1,332 @@
+use std::sync::Arc;
+
+use crossbeam_queue::ArrayQueue;
+
+use super::{Fx, Smoothed};
+
+/// Effects one chain can hold.
+pub const MAX_CHAIN_SLOTS: usize = 8;
+/// Parameter ids of a slot start at `slot * CHAIN_PARAM_STRIDE` when the chain
+/// is driven through [`Fx::set_param`].
+pub const CHAIN_PARAM_STRIDE: u32 = 256;
+/// Pending control commands per chain.
+const COMMAND_QUEUE_CAPACITY: usize = 32;
+
+/// Control-thread request applied by the chain between blocks.
+#[derive(Debug)]
+pub enum ChainCommand {
+    /// Put `fx` in `slot`, retiring whatever was there.
+    Insert {
+        slot: usize,
+        fx: Box<dyn Fx>,
+    },
+    Remove {
+        slot: usize,
+    },
+    Swap {
+        a: usize,
+        b: usize,
+    },
+    /// Enable or bypass a slot; the change is crossfaded.
+    SetEnabled {
+        slot: usize,
+        enabled: bool,
+    },
+    SetParam {
+        slot: usize,
+        id: u32,
+        value: f32,
+    },
+    /// Balance between the chain input and its output, in [0, 1].
+    Wet(f32),
+}
+
+/// Address `param` of the effect in `slot` through [`Fx::set_param`] on the chain.
+pub fn chain_param(slot: usize, param: u32) -> u32 {
+    slot as u32 * CHAIN_PARAM_STRIDE + param
+}
+
+/// Control-side handle to an [`FxChain`] running on the audio thread.
+#[derive(Debug, Clone)]
+pub struct FxChainHandle {
+    commands: Arc<ArrayQueue<ChainCommand>>,
+    retired: Arc<ArrayQueue<Box<dyn Fx>>>,
+}
+
+impl FxChainHandle {
+    /// Queue `command`; returns it back if the queue is full.
+    pub fn send(&self, command: ChainCommand) -> Result<(), ChainCommand> {
+        self.commands.push(command)
+    }
+
+    /// Drop effects the chain has handed back, so they are never freed on the audio thread.
+    /// Returns how many were collected.
+    pub fn collect_retired(&self) -> usize {
+        std::iter::from_fn(|| self.retired.pop()).count()
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    fx: Box<dyn Fx>,
+    /// Crossfade between bypass (0) and the processed signal (1).
+    mix: Smoothed,
+}
+
+impl Slot {
+    fn process(&mut self, block: &mut [f32], dry: &mut [f32]) {
+        if self.mix.is_settled() {
+            match self.mix.target() {
+                0.0 => return,
+                1.0 => return self.fx.process(block),
+                _ => {}
+            }
+        }
+        dry.copy_from_slice(block);
+        self.fx.process(block);
+        for (out, dry) in block.chunks_exact_mut(2).zip(dry.chunks_exact(2)) {
+            let mix = self.mix.next();
+            out[0] = dry[0] + (out[0] - dry[0]) * mix;
+            out[1] = dry[1] + (out[1] - dry[1]) * mix;
+        }
+    }
+}
+
+/// Ordered effect slots for one deck, reconfigured from the control thread
+/// through an [`FxChainHandle`] without allocating on the audio thread.
+#[derive(Debug)]
+pub struct FxChain {
+    sample_rate: u32,
+    slots: [Option<Slot>; MAX_CHAIN_SLOTS],
+    wet: Smoothed,
+    /// Chain input, kept for the wet/dry mix.
+    input: Vec<f32>,
+    /// Slot input, kept while a slot crossfades.
+    scratch: Vec<f32>,
+    commands: Arc<ArrayQueue<ChainCommand>>,
+    retired: Arc<ArrayQueue<Box<dyn Fx>>>,
+}
+
+impl FxChain {
+    /// Create an empty chain processing up to `max_block_frames` per pass, and its handle.
+    pub fn new(sample_rate: u32, max_block_frames: usize) -> (Self, FxChainHandle) {
+        let commands = Arc::new(ArrayQueue::new(COMMAND_QUEUE_CAPACITY));
+        // Room for every slot plus every queued insert, so retiring never has to drop.
+        let retired = Arc::new(ArrayQueue::new(MAX_CHAIN_SLOTS + COMMAND_QUEUE_CAPACITY));
+        let chain = Self {
+            sample_rate,
+            slots: Default::default(),
+            wet: Smoothed::new(1.0, sample_rate),
+            input: vec![0.0; max_block_frames.max(1) * 2],
+            scratch: vec![0.0; max_block_frames.max(1) * 2],
+            commands: commands.clone(),
+            retired: retired.clone(),
+        };
+        (chain, FxChainHandle { commands, retired })
+    }
+
+    fn retire(&mut self, slot: usize, fx: Option<Box<dyn Fx>>) {
+        let old = std::mem::replace(
+            &mut self.slots[slot],
+            fx.map(|fx| Slot {
+                fx,
+                mix: Smoothed::new(1.0, self.sample_rate),
+            }),
+        );
+        if let Some(old) = old {
+            // Only drops here if the control side stopped collecting.
+            let _ = self.retired.push(old.fx);
+        }
+    }
+
+    fn apply_commands(&mut self) {
+        while let Some(command) = self.commands.pop() {
+            match command {
+                ChainCommand::Insert { slot, fx } if slot < MAX_CHAIN_SLOTS => {
+                    self.retire(slot, Some(fx))
+                }
+                ChainCommand::Remove { slot } if slot < MAX_CHAIN_SLOTS => self.retire(slot, None),
+                ChainCommand::Swap { a, b } if a < MAX_CHAIN_SLOTS && b < MAX_CHAIN_SLOTS => {
+                    self.slots.swap(a, b)
+                }
+                ChainCommand::SetEnabled { slot, enabled } => {
+                    if let Some(Some(slot)) = self.slots.get_mut(slot) {
+                        if enabled && slot.mix.is_settled() && slot.mix.target() == 0.0 {
+                            // Don't replay a tail left over from before the bypass.
+                            slot.fx.reset();
+                        }
+                        slot.mix.set(if enabled { 1.0 } else { 0.0 });
+                    }
+                }
+                ChainCommand::SetParam { slot, id, value } => self.set_slot_param(slot, id, value),
+                ChainCommand::Wet(value) => self.wet.set(value.clamp(0.0, 1.0)),
+                ChainCommand::Insert { fx, .. } => {
+                    let _ = self.retired.push(fx);
+                }
+                ChainCommand::Remove { .. } | ChainCommand::Swap { .. } => {}
+            }
+        }
+    }
+
+    fn set_slot_param(&mut self, slot: usize, id: u32, value: f32) {
+        if let Some(Some(slot)) = self.slots.get_mut(slot) {
+            slot.fx.set_param(id, value);
+        }
+    }
+
+    fn process_block(&mut self, block: &mut [f32]) {
+        let len = block.len();
+        self.input[..len].copy_from_slice(block);
+        for slot in self.slots.iter_mut().flatten() {
+            slot.process(block, &mut self.scratch[..len]);
+        }
+        if self.wet.is_settled() && self.wet.target() == 1.0 {
+            return;
+        }
+        for (out, dry) in block.chunks_exact_mut(2).zip(self.input.chunks_exact(2)) {
+            let wet = self.wet.next();
+            out[0] = dry[0] + (out[0] - dry[0]) * wet;
+            out[1] = dry[1] + (out[1] - dry[1]) * wet;
+        }
+    }
+}
+
+impl Fx for FxChain {
+    fn process(&mut self, frames: &mut [f32]) {
+        self.apply_commands();
+        let capacity = self.input.len();
+        for block in frames.chunks_mut(capacity) {
+            self.process_block(block);
+        }
+    }
+
+    /// Forwards to the slot encoded in `id`; see [`chain_param`].
+    fn set_param(&mut self, id: u32, value: f32) {
+        let slot = (id / CHAIN_PARAM_STRIDE) as usize;
+        self.set_slot_param(slot, id % CHAIN_PARAM_STRIDE, value);
+    }
+
+    fn reset(&mut self) {
+        for slot in self.slots.iter_mut().flatten() {
+            slot.fx.reset();
+            slot.mix.settle();
+        }
+        self.wet.settle();
+    }
+
+    fn set_tempo(&mut self, bpm: Option<f32>) {
+        for slot in self.slots.iter_mut().flatten() {
+            slot.fx.set_tempo(bpm);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Offset(f32);
+
+    impl Fx for Offset {
+        fn process(&mut self, frames: &mut [f32]) {
+            frames.iter_mut().for_each(|s| *s += self.0);
+        }
+        fn set_param(&mut self, _id: u32, value: f32) {
+            self.0 = value;
+        }
+        fn reset(&mut self) {}
+    }
+
+    #[derive(Debug)]
+    struct Scale(f32);
+
+    impl Fx for Scale {
+        fn process(&mut self, frames: &mut [f32]) {
+            frames.iter_mut().for_each(|s| *s *= self.0);
+        }
+        fn set_param(&mut self, _id: u32, value: f32) {
+            self.0 = value;
+        }
+        fn reset(&mut self) {}
+    }
+
+    fn run(chain: &mut FxChain, value: f32) -> f32 {
+        let mut block = [value; 8];
+        chain.process(&mut block);
+        block[6]
+    }
+
+    #[test]
+    fn reorder_changes_processing_order() {
+        let (mut chain, handle) = FxChain::new(48_000, 4);
+        handle
+            .send(ChainCommand::Insert {
+                slot: 0,
+                fx: Box::new(Offset(1.0)),
+            })
+            .unwrap();
+        handle
+            .send(ChainCommand::Insert {
+                slot: 1,
+                fx: Box::new(Scale(2.0)),
+            })
+            .unwrap();
+        assert_eq!(run(&mut chain, 1.0), 4.0);
+
+        handle.send(ChainCommand::Swap { a: 0, b: 1 }).unwrap();
+        assert_eq!(run(&mut chain, 1.0), 3.0);
+
+        // Params follow the effect to its new slot.
+        chain.set_param(chain_param(0, 0), 3.0);
+        assert_eq!(run(&mut chain, 1.0), 4.0);
+
+        handle.send(ChainCommand::Remove { slot: 1 }).unwrap();
+        assert_eq!(run(&mut chain, 1.0), 3.0);
+        assert_eq!(handle.collect_retired(), 1);
+    }
+
+    #[test]
+    fn bypass_fades_out_to_exact_passthrough() {
+        let (mut chain, handle) = FxChain::new(1_000, 64);
+        handle
+            .send(ChainCommand::Insert {
+                slot: 3,
+                fx: Box::new(Scale(0.0)),
+            })
+            .unwrap();
+        assert_eq!(run(&mut chain, 0.5), 0.0);
+
+        handle
+            .send(ChainCommand::SetEnabled {
+                slot: 3,
+                enabled: false,
+            })
+            .unwrap();
+        let mut faded = vec![0.5; 400];
+        chain.process(&mut faded);
+        // A ramp, not a jump, back to the dry signal.
+        assert!(faded[0] < 0.1);
+        assert!(faded.windows(2).all(|w| w[1] >= w[0]));
+
+        let input: Vec<f32> = (0..256).map(|i| (i as f32 * 0.37).sin()).collect();
+        let mut output = input.clone();
+        chain.process(&mut output);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn wet_blends_chain_output_with_its_input() {
+        let (mut chain, handle) = FxChain::new(1_000, 64);
+        handle
+            .send(ChainCommand::Insert {
+                slot: 0,
+                fx: Box::new(Scale(0.0)),
+            })
+            .unwrap();
+        handle.send(ChainCommand::Wet(0.25)).unwrap();
+        let mut settle = vec![1.0; 400];
+        chain.process(&mut settle);
+        assert!((run(&mut chain, 1.0) - 0.75).abs() < 1e-6);
+    }
+}
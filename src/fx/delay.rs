@@ -0,0 +1,527 @@
+use super::{division_ms, mix_wet, Fx, Smoothed};
+use crate::ParameterUpdate;
+
+/// Longest echo the preallocated line can hold.
+pub const MAX_DELAY_SECONDS: f32 = 2.0;
+/// Feedback is capped below unity so repeats always die out.
+const MAX_FEEDBACK: f32 = 0.95;
+/// Length of the crossfade between read heads when the time changes.
+const TIME_CROSSFADE_SECONDS: f32 = 0.02;
+/// High-cut settings at or above this leave the feedback path unfiltered.
+const HIGH_CUT_OFF_HZ: f32 = 20_000.0;
+/// Level above which the safety limiter in the feedback path starts to bend;
+/// the line never holds more than twice it.
+const LIMIT_KNEE: f32 = 1.0;
+
+/// How long the echo of a [`DelayFx`] is, as carried by
+/// [`ParameterUpdate::FxDelayTime`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DelayTime {
+    Ms(f32),
+    /// Fraction of a whole note against the tempo, as
+    /// [`DelayFx::TIME_DIVISION`] takes it.
+    Division(f32),
+}
+
+/// Pass `sample` below the knee, and fold anything above it softly under
+/// twice the knee, so a loop fed hard at full feedback can't run away.
+fn limit(sample: f32) -> f32 {
+    let over = sample.abs() - LIMIT_KNEE;
+    if over <= 0.0 {
+        sample
+    } else {
+        (LIMIT_KNEE + over / (1.0 + over)).copysign(sample)
+    }
+}
+
+/// Stereo echo with a darkening high-cut in the feedback path.
+///
+/// Runs as an [`Fx`] in a chain, or as a deck insert through
+/// [`Processor`](crate::processor::Processor), where it follows the
+/// `FxDelay*` updates and the [`Tempo`](ParameterUpdate::Tempo) sent to its deck.
+#[derive(Debug)]
+pub struct DelayFx {
+    sample_rate: u32,
+    /// Interleaved stereo delay line.
+    line: Vec<f32>,
+    write: usize,
+    /// Delay in frames the main read head currently uses.
+    delay_frames: f32,
+    /// Delay the read head is crossfading towards, with fade progress in [0, 1].
+    fade: Option<(f32, f32)>,
+    target_frames: f32,
+    time_ms: f32,
+    /// Beat division overriding `time_ms` while a tempo is known.
+    division: Option<f32>,
+    bpm: Option<f32>,
+    feedback: Smoothed,
+    wet: Smoothed,
+    high_cut_hz: f32,
+    high_cut_coeff: f32,
+    high_cut_state: [f32; 2],
+}
+
+impl DelayFx {
+    /// Delay time in milliseconds.
+    pub const TIME_MS: u32 = 0;
+    /// Feedback amount, clamped to [0, 0.95].
+    pub const FEEDBACK: u32 = 1;
+    /// [Wet/dry balance](super#wet-and-dry).
+    pub const WET: u32 = 2;
+    /// Feedback high-cut frequency in Hz.
+    pub const HIGH_CUT_HZ: u32 = 3;
+    /// Delay as a fraction of a whole note (0.25 is one beat), tracking the tempo.
+    /// Zero returns to [`TIME_MS`](Self::TIME_MS).
+    pub const TIME_DIVISION: u32 = 4;
+
+    pub fn new(sample_rate: u32) -> Self {
+        let mut fx = Self {
+            sample_rate,
+            line: Vec::new(),
+            write: 0,
+            delay_frames: 0.0,
+            fade: None,
+            target_frames: 0.0,
+            time_ms: 375.0,
+            division: None,
+            bpm: None,
+            feedback: Smoothed::new(0.5, sample_rate),
+            wet: Smoothed::new(0.5, sample_rate),
+            high_cut_hz: 6_000.0,
+            high_cut_coeff: 1.0,
+            high_cut_state: [0.0; 2],
+        };
+        fx.allocate(sample_rate);
+        fx
+    }
+
+    /// Size the line for [`MAX_DELAY_SECONDS`] at `sample_rate` and retune
+    /// everything timed in frames, dropping any echoes still sounding.
+    fn allocate(&mut self, sample_rate: u32) {
+        let frames = (MAX_DELAY_SECONDS * sample_rate as f32).ceil() as usize + 2;
+        self.sample_rate = sample_rate;
+        self.line = vec![0.0; frames * 2];
+        self.write = 0;
+        self.feedback = Smoothed::new(self.feedback.target(), sample_rate);
+        self.wet = Smoothed::new(self.wet.target(), sample_rate);
+        self.set_param(Self::HIGH_CUT_HZ, self.high_cut_hz);
+        self.update_target();
+        self.reset();
+    }
+
+    /// Resolve the delay time against the current tempo.
+    fn update_target(&mut self) {
+        let ms = match (self.division, self.bpm) {
+            (Some(division), Some(bpm)) => division_ms(division, bpm),
+            _ => self.time_ms,
+        };
+        let max = self.frames() as f32 - 2.0;
+        self.target_frames = (ms * self.sample_rate as f32 / 1_000.0).clamp(1.0, max);
+    }
+
+    fn frames(&self) -> usize {
+        self.line.len() / 2
+    }
+
+    /// Linearly interpolated read `delay` frames behind the write head.
+    fn read(&self, delay: f32) -> [f32; 2] {
+        let frames = self.frames();
+        let position = self.write as f32 - delay;
+        let position = if position < 0.0 {
+            position + frames as f32
+        } else {
+            position
+        };
+        let index = position.floor() as usize % frames;
+        let next = (index + 1) % frames;
+        let frac = position.fract();
+        [0, 1].map(|ch| {
+            let a = self.line[index * 2 + ch];
+            let b = self.line[next * 2 + ch];
+            a + (b - a) * frac
+        })
+    }
+}
+
+impl Fx for DelayFx {
+    fn process(&mut self, frames: &mut [f32]) {
+        self.update_target();
+        let fade_step = 1.0 / (TIME_CROSSFADE_SECONDS * self.sample_rate as f32);
+        for frame in frames.chunks_exact_mut(2) {
+            if self.fade.is_none() && self.target_frames != self.delay_frames {
+                self.fade = Some((self.target_frames, 0.0));
+            }
+
+            let mut delayed = self.read(self.delay_frames);
+            if let Some((next, progress)) = self.fade {
+                let incoming = self.read(next);
+                for (out, new) in delayed.iter_mut().zip(incoming) {
+                    *out += (new - *out) * progress;
+                }
+                let progress = progress + fade_step;
+                self.fade = if progress >= 1.0 {
+                    self.delay_frames = next;
+                    None
+                } else {
+                    Some((next, progress))
+                };
+            }
+
+            let feedback = self.feedback.next();
+            let wet = self.wet.next();
+            for ch in 0..2 {
+                let state = &mut self.high_cut_state[ch];
+                *state += (delayed[ch] - *state) * self.high_cut_coeff;
+                self.line[self.write * 2 + ch] = limit(frame[ch] + *state * feedback);
+                mix_wet(&mut frame[ch], delayed[ch], wet);
+            }
+            self.write = (self.write + 1) % self.frames();
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        match id {
+            Self::TIME_MS => {
+                self.time_ms = value;
+                self.division = None;
+                self.update_target();
+            }
+            Self::TIME_DIVISION => {
+                self.division = (value > 0.0).then_some(value);
+                self.update_target();
+            }
+            Self::FEEDBACK => self.feedback.set(value.clamp(0.0, MAX_FEEDBACK)),
+            Self::WET => self.wet.set(value.clamp(0.0, 1.0)),
+            Self::HIGH_CUT_HZ => {
+                self.high_cut_hz = value;
+                self.high_cut_coeff = if value >= HIGH_CUT_OFF_HZ {
+                    1.0
+                } else {
+                    let cutoff = value.max(20.0) / self.sample_rate as f32;
+                    1.0 - (-std::f32::consts::TAU * cutoff).exp()
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.line.fill(0.0);
+        self.high_cut_state = [0.0; 2];
+        self.delay_frames = self.target_frames;
+        self.fade = None;
+        self.feedback.settle();
+        self.wet.settle();
+    }
+
+    fn set_tempo(&mut self, bpm: Option<f32>) {
+        self.bpm = bpm.filter(|bpm| *bpm > 0.0);
+        self.update_target();
+    }
+}
+
+impl crate::processor::Processor for DelayFx {
+    /// Reallocates the line for `sample_rate`.
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.allocate(sample_rate);
+    }
+
+    fn process(&mut self, buffer: &mut [f32]) {
+        Fx::process(self, buffer);
+    }
+
+    fn apply(&mut self, update: &ParameterUpdate) {
+        match *update {
+            ParameterUpdate::FxDelayTime { time, .. } => match time {
+                DelayTime::Ms(ms) => self.set_param(Self::TIME_MS, ms),
+                DelayTime::Division(division) => self.set_param(Self::TIME_DIVISION, division),
+            },
+            ParameterUpdate::FxDelayFeedback { feedback, .. } => {
+                self.set_param(Self::FEEDBACK, feedback)
+            }
+            ParameterUpdate::FxDelayMix { mix, .. } => self.set_param(Self::WET, mix),
+            ParameterUpdate::Tempo(bpm) => self.set_tempo(bpm),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn impulse_response(fx: &mut DelayFx, frames: usize) -> Vec<f32> {
+        let mut buffer = vec![0.0; frames * 2];
+        buffer[0] = 1.0;
+        buffer[1] = 1.0;
+        for block in buffer.chunks_mut(256) {
+            fx.process(block);
+        }
+        buffer.chunks_exact(2).map(|frame| frame[0]).collect()
+    }
+
+    #[test]
+    fn echoes_are_spaced_and_decay_by_feedback() {
+        let mut fx = DelayFx::new(SAMPLE_RATE);
+        fx.set_param(DelayFx::TIME_MS, 100.0);
+        fx.set_param(DelayFx::FEEDBACK, 0.5);
+        fx.set_param(DelayFx::WET, 1.0);
+        fx.set_param(DelayFx::HIGH_CUT_HZ, HIGH_CUT_OFF_HZ);
+        fx.reset();
+
+        let response = impulse_response(&mut fx, 20_000);
+        let peaks: Vec<(usize, f32)> = response
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.abs() > 1e-3)
+            .map(|(i, v)| (i, *v))
+            .collect();
+        assert_eq!(
+            peaks.iter().map(|p| p.0).collect::<Vec<_>>(),
+            [4_800, 9_600, 14_400, 19_200]
+        );
+        for pair in peaks.windows(2) {
+            assert!((pair[1].1 / pair[0].1 - 0.5).abs() < 1e-6, "{peaks:?}");
+        }
+    }
+
+    #[test]
+    fn high_cut_darkens_repeats() {
+        let mut fx = DelayFx::new(SAMPLE_RATE);
+        fx.set_param(DelayFx::TIME_MS, 50.0);
+        fx.set_param(DelayFx::FEEDBACK, 0.9);
+        fx.set_param(DelayFx::WET, 1.0);
+        fx.set_param(DelayFx::HIGH_CUT_HZ, 2_000.0);
+        fx.reset();
+
+        let response = impulse_response(&mut fx, 12_000);
+        // Each pass through the low-pass smears the echo over more samples.
+        let width = |start: usize| {
+            response[start..start + 2_400]
+                .iter()
+                .filter(|v| v.abs() > 1e-3)
+                .count()
+        };
+        assert_eq!(width(2_400), 1);
+        assert!(width(4_800) > 1);
+        assert!(width(9_600) > width(4_800));
+    }
+
+    #[test]
+    fn zero_wet_is_bit_transparent() {
+        let mut fx = DelayFx::new(SAMPLE_RATE);
+        fx.set_param(DelayFx::WET, 0.0);
+        fx.set_param(DelayFx::FEEDBACK, 0.95);
+        fx.reset();
+
+        let mut state = 0x2468_ace1u32;
+        let input: Vec<f32> = (0..48_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+            })
+            .collect();
+        let mut output = input.clone();
+        for block in output.chunks_mut(512) {
+            fx.process(block);
+        }
+        assert!(input
+            .iter()
+            .zip(&output)
+            .all(|(a, b)| a.to_bits() == b.to_bits()));
+    }
+
+    #[test]
+    fn time_changes_crossfade_without_jumps() {
+        let mut fx = DelayFx::new(SAMPLE_RATE);
+        fx.set_param(DelayFx::WET, 1.0);
+        fx.set_param(DelayFx::FEEDBACK, 0.0);
+        fx.reset();
+
+        // A slow sine through the delay, then a time change mid-stream.
+        let mut buffer: Vec<f32> = (0..96_000)
+            .flat_map(|i| {
+                let s = (i as f32 / SAMPLE_RATE as f32 * 50.0 * std::f32::consts::TAU).sin();
+                [s, s]
+            })
+            .collect();
+        let (first, second) = buffer.split_at_mut(96_000);
+        fx.process(first);
+        fx.set_param(DelayFx::TIME_MS, 130.0);
+        fx.process(second);
+
+        let max_step = buffer[96_000..]
+            .chunks_exact(2)
+            .map(|f| f[0])
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0, f32::max);
+        // A 50 Hz sine moves at most ~0.0066 per sample; a hard jump would be far larger.
+        assert!(max_step < 0.01, "max step {max_step}");
+    }
+
+    #[test]
+    fn beat_divisions_follow_tempo_changes() {
+        let mut fx = DelayFx::new(SAMPLE_RATE);
+        fx.set_param(DelayFx::TIME_DIVISION, 0.25);
+        fx.set_param(DelayFx::FEEDBACK, 0.0);
+        fx.set_param(DelayFx::WET, 1.0);
+        fx.set_param(DelayFx::HIGH_CUT_HZ, HIGH_CUT_OFF_HZ);
+        fx.set_tempo(Some(120.0));
+        fx.reset();
+        let first_echo = |fx: &mut DelayFx| {
+            impulse_response(fx, 40_000)
+                .iter()
+                .position(|v| v.abs() > 1e-3)
+                .unwrap()
+        };
+        // A quarter note at 120 BPM is 500 ms.
+        assert_eq!(first_echo(&mut fx), 24_000);
+
+        // Retempo while a sine plays through: the read head glides, it doesn't jump.
+        let mut buffer: Vec<f32> = (0..48_000)
+            .flat_map(|i| {
+                let s = (i as f32 / SAMPLE_RATE as f32 * 50.0 * std::f32::consts::TAU).sin();
+                [s, s]
+            })
+            .collect();
+        let (first, second) = buffer.split_at_mut(48_000);
+        fx.process(first);
+        fx.set_tempo(Some(100.0));
+        fx.process(second);
+        let max_step = buffer[48_000..]
+            .chunks_exact(2)
+            .map(|f| f[0])
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(max_step < 0.01, "max step {max_step}");
+
+        // Once settled the echoes sit 600 ms apart.
+        fx.reset();
+        assert_eq!(first_echo(&mut fx), 28_800);
+    }
+
+    #[test]
+    fn feedback_loop_is_held_by_the_limiter() {
+        let mut fx = DelayFx::new(SAMPLE_RATE);
+        fx.set_param(DelayFx::TIME_MS, 10.0);
+        fx.set_param(DelayFx::FEEDBACK, 5.0);
+        fx.set_param(DelayFx::WET, 1.0);
+        fx.reset();
+
+        // Full-scale DC would build to 20x in the line at 0.95 feedback.
+        let mut buffer = vec![1.0; 96_000];
+        for block in buffer.chunks_mut(512) {
+            fx.process(block);
+        }
+        let peak = buffer.iter().fold(0.0, |peak: f32, v| peak.max(v.abs()));
+        assert!(peak > 1.5 && peak < 2.0 * LIMIT_KNEE, "peak {peak}");
+        assert_eq!(limit(0.5), 0.5);
+        assert_eq!(limit(-LIMIT_KNEE), -LIMIT_KNEE);
+    }
+
+    #[test]
+    fn insert_follows_delay_updates_and_tempo() {
+        use crate::{parameter_channel, DeckId, SummingBus};
+
+        let (tx, rx) = parameter_channel(16);
+        // Prepared again at the bus's rate when inserted.
+        let mut bus = SummingBus::new(rx).with_sample_rate(44_100);
+        let mut delay = DelayFx::new(SAMPLE_RATE);
+        delay.set_param(DelayFx::HIGH_CUT_HZ, HIGH_CUT_OFF_HZ);
+        bus.set_deck_insert(DeckId::A, Box::new(delay));
+        for update in [
+            ParameterUpdate::Tempo(Some(120.0)),
+            ParameterUpdate::FxDelayTime {
+                deck: DeckId::A,
+                time: DelayTime::Division(0.125),
+            },
+            ParameterUpdate::FxDelayFeedback {
+                deck: DeckId::A,
+                feedback: 0.5,
+            },
+            ParameterUpdate::FxDelayMix {
+                deck: DeckId::A,
+                mix: 1.0,
+            },
+        ] {
+            tx.send(update).unwrap();
+        }
+        let mut mix = |frames: usize, impulse: bool| {
+            let mut deck_a = vec![0.0; frames * 2];
+            if impulse {
+                deck_a[..2].fill(1.0);
+            }
+            let mut out = vec![0.0; frames * 2];
+            for ((a, b), out) in deck_a
+                .chunks(512)
+                .zip(vec![0.0; frames * 2].chunks(512))
+                .zip(out.chunks_mut(512))
+            {
+                bus.mix_stereo(a, b, out);
+            }
+            out.iter().step_by(2).copied().collect::<Vec<f32>>()
+        };
+        // Let the updates glide home before the impulse.
+        mix(44_100, false);
+        let response = mix(40_000, true);
+        let peaks: Vec<(usize, f32)> = response
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.abs() > 1e-3)
+            .map(|(i, v)| (i, *v))
+            .collect();
+        // An eighth note at 120 BPM is 250 ms.
+        assert_eq!(
+            peaks.iter().map(|p| p.0).collect::<Vec<_>>(),
+            [11_025, 22_050, 33_075]
+        );
+        for pair in peaks.windows(2) {
+            assert!((pair[1].1 / pair[0].1 - 0.5).abs() < 1e-4, "{peaks:?}");
+        }
+
+        // A new tempo moves the echo along with it.
+        tx.send(ParameterUpdate::Tempo(Some(150.0))).unwrap();
+        tx.send(ParameterUpdate::FxDelayFeedback {
+            deck: DeckId::A,
+            feedback: 0.0,
+        })
+        .unwrap();
+        mix(88_200, false);
+        let echo = mix(20_000, true)
+            .iter()
+            .position(|v| v.abs() > 1e-3)
+            .unwrap();
+        assert_eq!(echo, 8_820);
+    }
+
+    #[test]
+    fn sanitized_insert_recovers_from_a_nan() {
+        use crate::meter::meter_channel;
+        use crate::{parameter_channel, DeckId, SummingBus};
+
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (meter, meters) = meter_channel(4);
+        bus.set_meter(Some(meter));
+        bus.set_sanitize_input(true);
+        bus.set_deck_insert(DeckId::A, Box::new(DelayFx::new(SAMPLE_RATE)));
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let mut deck_a = vec![0.25; 1_024];
+        deck_a[2] = f32::NAN;
+        let mut out = vec![0.0; 1_024];
+        bus.mix_stereo(&deck_a, &[0.0; 1_024], &mut out);
+        // Two seconds on, the echoes have come round several times.
+        for _ in 0..200 {
+            bus.mix_stereo(&[0.25; 1_024], &[0.0; 1_024], &mut out);
+            assert!(out.iter().all(|s| s.is_finite()));
+        }
+        assert!(out.iter().all(|s| s.abs() > 0.1), "{:?}", &out[..8]);
+        assert_eq!(meters.latest().unwrap().scrubbed, 1);
+    }
+}
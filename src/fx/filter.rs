@@ -0,0 +1,238 @@
+use std::f32::consts::PI;
+
+use super::{Fx, Smoothed};
+
+/// Knob positions within this distance of center bypass the filter.
+pub const DEAD_ZONE: f32 = 0.05;
+/// Low-pass cutoff with the knob just left of the dead zone, and fully left.
+const LOW_PASS_RANGE_HZ: (f32, f32) = (20_000.0, 100.0);
+/// High-pass cutoff with the knob just right of the dead zone, and fully right.
+const HIGH_PASS_RANGE_HZ: (f32, f32) = (20.0, 10_000.0);
+/// Damping at zero and full resonance; kept above zero so the filter stays stable.
+const DAMPING_RANGE: (f32, f32) = (1.414, 0.1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Bypass,
+    LowPass,
+    HighPass,
+}
+
+impl Mode {
+    /// Cutoff at which the mode is fully open and can switch to bypass inaudibly.
+    fn open_cutoff(self) -> f32 {
+        match self {
+            Mode::LowPass => LOW_PASS_RANGE_HZ.0,
+            Mode::HighPass | Mode::Bypass => HIGH_PASS_RANGE_HZ.0,
+        }
+    }
+}
+
+/// The DJ mixer's single-knob filter: low-pass left of center, high-pass right.
+#[derive(Debug)]
+pub struct FilterFx {
+    sample_rate: u32,
+    /// Mode the knob asks for, and its cutoff.
+    requested: (Mode, f32),
+    /// Mode currently running; it opens up fully before handing over.
+    active: Mode,
+    /// Smoothed cutoff in log2 Hz.
+    cutoff: Smoothed,
+    damping: f32,
+    /// Integrator states of the state-variable filter, per channel.
+    state: [[f32; 2]; 2],
+}
+
+impl FilterFx {
+    /// Knob position in [-1, 1].
+    pub const POSITION: u32 = 0;
+    /// Resonance in [0, 1].
+    pub const RESONANCE: u32 = 1;
+
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            requested: (Mode::Bypass, HIGH_PASS_RANGE_HZ.0),
+            active: Mode::Bypass,
+            cutoff: Smoothed::new(HIGH_PASS_RANGE_HZ.0.log2(), sample_rate),
+            damping: DAMPING_RANGE.0,
+            state: [[0.0; 2]; 2],
+        }
+    }
+
+    fn set_position(&mut self, position: f32) {
+        let position = position.clamp(-1.0, 1.0);
+        let amount = (position.abs() - DEAD_ZONE) / (1.0 - DEAD_ZONE);
+        let sweep = |(from, to): (f32, f32)| from * (to / from).powf(amount);
+        self.requested = if position.abs() <= DEAD_ZONE {
+            (Mode::Bypass, Mode::Bypass.open_cutoff())
+        } else if position < 0.0 {
+            (Mode::LowPass, sweep(LOW_PASS_RANGE_HZ))
+        } else {
+            (Mode::HighPass, sweep(HIGH_PASS_RANGE_HZ))
+        };
+    }
+
+    /// Advance the mode hand-over and return the cutoff target for this sample.
+    fn step_mode(&mut self) -> f32 {
+        let (mode, cutoff) = self.requested;
+        if mode != self.active {
+            if self.active != Mode::Bypass {
+                self.cutoff.set(self.active.open_cutoff().log2());
+                if !self.cutoff.is_settled() {
+                    return self.active.open_cutoff().log2();
+                }
+            }
+            self.active = mode;
+            self.state = [[0.0; 2]; 2];
+            self.cutoff = Smoothed::new(mode.open_cutoff().log2(), self.sample_rate);
+        }
+        cutoff.log2()
+    }
+}
+
+impl Fx for FilterFx {
+    fn process(&mut self, frames: &mut [f32]) {
+        let nyquist_guard = self.sample_rate as f32 * 0.49;
+        for frame in frames.chunks_exact_mut(2) {
+            let target = self.step_mode();
+            if self.active == Mode::Bypass {
+                continue;
+            }
+            self.cutoff.set(target);
+            let cutoff = self.cutoff.next().exp2().min(nyquist_guard);
+
+            // Topology-preserving state-variable filter (Simper).
+            let g = (PI * cutoff / self.sample_rate as f32).tan();
+            let k = self.damping;
+            let a1 = 1.0 / (1.0 + g * (g + k));
+            let a2 = g * a1;
+            let a3 = g * a2;
+            for (sample, [ic1, ic2]) in frame.iter_mut().zip(&mut self.state) {
+                let v0 = *sample;
+                let v3 = v0 - *ic2;
+                let v1 = a1 * *ic1 + a2 * v3;
+                let v2 = *ic2 + a2 * *ic1 + a3 * v3;
+                *ic1 = 2.0 * v1 - *ic1;
+                *ic2 = 2.0 * v2 - *ic2;
+                *sample = match self.active {
+                    Mode::LowPass => v2,
+                    _ => v0 - k * v1 - v2,
+                };
+            }
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        match id {
+            Self::POSITION => self.set_position(value),
+            Self::RESONANCE => {
+                let (open, peak) = DAMPING_RANGE;
+                self.damping = open + (peak - open) * value.clamp(0.0, 1.0);
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        let (mode, cutoff) = self.requested;
+        self.active = mode;
+        self.state = [[0.0; 2]; 2];
+        self.cutoff = Smoothed::new(cutoff.log2(), self.sample_rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn tone(freq: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let s = (i as f32 / SAMPLE_RATE as f32 * freq * std::f32::consts::TAU).sin();
+                [s, s]
+            })
+            .collect()
+    }
+
+    fn noise(frames: usize) -> Vec<f32> {
+        let mut state = 0x1357_9bdfu32;
+        (0..frames * 2)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    /// RMS of the left channel over the second half of a filtered tone.
+    fn filtered_rms(position: f32, freq: f32) -> f32 {
+        let mut fx = FilterFx::new(SAMPLE_RATE);
+        fx.set_param(FilterFx::POSITION, position);
+        fx.reset();
+        let mut buffer = tone(freq, 48_000);
+        for block in buffer.chunks_mut(512) {
+            fx.process(block);
+        }
+        let tail: Vec<f32> = buffer[48_000..].iter().step_by(2).copied().collect();
+        (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn each_side_attenuates_the_opposite_end() {
+        let full = std::f32::consts::FRAC_1_SQRT_2;
+        // Low-pass side: highs disappear, lows survive.
+        assert!(filtered_rms(-0.7, 5_000.0) < full * 0.05);
+        assert!(filtered_rms(-0.7, 60.0) > full * 0.9);
+        // High-pass side: lows disappear, highs survive.
+        assert!(filtered_rms(0.7, 60.0) < full * 0.05);
+        assert!(filtered_rms(0.7, 10_000.0) > full * 0.9);
+        // Turning further closes the filter further.
+        assert!(filtered_rms(-0.9, 1_000.0) < filtered_rms(-0.5, 1_000.0));
+        // Fully left keeps only the sub-bass; fully right only the air.
+        assert!(filtered_rms(-1.0, 1_000.0) < full * 0.02);
+        assert!(filtered_rms(-1.0, 40.0) > full * 0.9);
+        assert!(filtered_rms(1.0, 2_000.0) < full * 0.05);
+        assert!(filtered_rms(1.0, 18_000.0) > full * 0.85);
+    }
+
+    #[test]
+    fn dead_zone_is_exact_passthrough() {
+        let mut fx = FilterFx::new(SAMPLE_RATE);
+        fx.set_param(FilterFx::RESONANCE, 1.0);
+        let input = noise(4_800);
+        for position in [0.0, DEAD_ZONE * 0.5, -DEAD_ZONE] {
+            fx.set_param(FilterFx::POSITION, position);
+            let mut output = input.clone();
+            fx.process(&mut output);
+            assert!(input
+                .iter()
+                .zip(&output)
+                .all(|(a, b)| a.to_bits() == b.to_bits()));
+        }
+
+        // Returning to center from a closed filter ends in exact bypass too.
+        fx.set_param(FilterFx::POSITION, -1.0);
+        fx.process(&mut noise(4_800));
+        fx.set_param(FilterFx::POSITION, 0.0);
+        fx.process(&mut noise(48_000));
+        let mut output = input.clone();
+        fx.process(&mut output);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn full_resonance_stays_bounded_while_sweeping() {
+        let mut fx = FilterFx::new(SAMPLE_RATE);
+        fx.set_param(FilterFx::RESONANCE, 1.0);
+        let mut buffer = noise(96_000);
+        for (i, block) in buffer.chunks_mut(256).enumerate() {
+            let position = ((i as f32 * 0.05).sin() * 1.2).clamp(-1.0, 1.0);
+            fx.set_param(FilterFx::POSITION, position);
+            fx.process(block);
+            assert!(block.iter().all(|s| s.is_finite() && s.abs() < 20.0));
+        }
+    }
+}
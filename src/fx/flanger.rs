@@ -0,0 +1,386 @@
+use super::{mix_wet, Fx, Lfo, Smoothed};
+use crate::ParameterUpdate;
+
+/// Delay with the LFO at its low point.
+const MIN_DELAY_MS: f32 = 1.0;
+/// Delay with the LFO at its peak and full depth.
+const MAX_DELAY_MS: f32 = 10.0;
+/// Feedback is capped below unity so the comb never rings on forever.
+const MAX_FEEDBACK: f32 = 0.9;
+
+/// How fast a [`FlangerFx`] sweeps, as carried by
+/// [`ParameterUpdate::FxFlangerRate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlangerRate {
+    Hz(f32),
+    /// One cycle per this fraction of a whole note against the tempo, as
+    /// [`FlangerFx::SYNC_DIVISION`] takes it.
+    Division(f32),
+}
+
+/// Stereo flanger: a 1–10 ms delay swept by an LFO and mixed back with the dry signal.
+///
+/// At zero depth there is nothing to sweep, so the wet signal fades out entirely.
+///
+/// Runs as an [`Fx`] in a chain, or as a deck insert through
+/// [`Processor`](crate::processor::Processor), where it follows the
+/// `FxFlanger*` updates and the [`Tempo`](ParameterUpdate::Tempo) sent to its deck.
+#[derive(Debug)]
+pub struct FlangerFx {
+    sample_rate: u32,
+    /// Interleaved stereo delay line.
+    line: Vec<f32>,
+    write: usize,
+    lfo: Lfo,
+    /// How far the right channel's sweep runs ahead of the left, in cycles.
+    stereo_phase: f32,
+    depth: Smoothed,
+    feedback: Smoothed,
+    wet: Smoothed,
+    wet_setting: f32,
+}
+
+impl FlangerFx {
+    /// Free-running LFO rate in Hz.
+    pub const RATE_HZ: u32 = 0;
+    /// LFO cycle as a fraction of a whole note while a tempo is set; zero uses the free rate.
+    pub const SYNC_DIVISION: u32 = 1;
+    /// Sweep depth in [0, 1].
+    pub const DEPTH: u32 = 2;
+    /// Feedback in [-0.9, 0.9]; negative values flip the comb's polarity.
+    pub const FEEDBACK: u32 = 3;
+    /// [Wet/dry balance](super#wet-and-dry).
+    pub const WET: u32 = 4;
+    /// Any value restarts the LFO cycle.
+    pub const RETRIGGER: u32 = 5;
+    /// Lead of the right channel's sweep over the left in [0, 1] cycles; 0.5
+    /// sweeps them in opposite directions for the widest image.
+    pub const STEREO_PHASE: u32 = 6;
+
+    pub fn new(sample_rate: u32) -> Self {
+        let mut fx = Self {
+            sample_rate,
+            line: Vec::new(),
+            write: 0,
+            lfo: Lfo::new(0.25, sample_rate),
+            stereo_phase: 0.0,
+            depth: Smoothed::new(1.0, sample_rate),
+            feedback: Smoothed::new(0.5, sample_rate),
+            wet: Smoothed::new(0.5, sample_rate),
+            wet_setting: 0.5,
+        };
+        fx.allocate(sample_rate);
+        fx
+    }
+
+    /// Size the line for [`MAX_DELAY_MS`] at `sample_rate` and retime the
+    /// smoothers and LFO, which keeps its phase.
+    fn allocate(&mut self, sample_rate: u32) {
+        let frames = (MAX_DELAY_MS * sample_rate as f32 / 1_000.0).ceil() as usize + 2;
+        self.sample_rate = sample_rate;
+        self.line = vec![0.0; frames * 2];
+        self.write = 0;
+        self.lfo.set_sample_rate(sample_rate);
+        for smoothed in [&mut self.depth, &mut self.feedback, &mut self.wet] {
+            *smoothed = Smoothed::new(smoothed.target(), sample_rate);
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.line.len() / 2
+    }
+
+    /// Channel `ch` linearly interpolated `delay` frames behind the write head.
+    fn read(&self, ch: usize, delay: f32) -> f32 {
+        let frames = self.frames();
+        let position = self.write as f32 + frames as f32 - delay;
+        let index = position.floor() as usize % frames;
+        let next = (index + 1) % frames;
+        let frac = position.fract();
+        let a = self.line[index * 2 + ch];
+        let b = self.line[next * 2 + ch];
+        a + (b - a) * frac
+    }
+
+    fn update_wet(&mut self, depth: f32) {
+        self.wet
+            .set(if depth > 0.0 { self.wet_setting } else { 0.0 });
+    }
+}
+
+impl Fx for FlangerFx {
+    fn process(&mut self, frames: &mut [f32]) {
+        let ms = self.sample_rate as f32 / 1_000.0;
+        for frame in frames.chunks_exact_mut(2) {
+            let depth = self.depth.next();
+            let right = self.lfo.peek(self.stereo_phase);
+            let lfo = [self.lfo.next(), right];
+            let delayed: [f32; 2] = std::array::from_fn(|ch| {
+                let sweep = lfo[ch] * depth;
+                self.read(
+                    ch,
+                    (MIN_DELAY_MS + (MAX_DELAY_MS - MIN_DELAY_MS) * sweep) * ms,
+                )
+            });
+            let feedback = self.feedback.next();
+            let wet = self.wet.next();
+            for ch in 0..2 {
+                self.line[self.write * 2 + ch] = frame[ch] + delayed[ch] * feedback;
+                mix_wet(&mut frame[ch], delayed[ch], wet);
+            }
+            self.write = (self.write + 1) % self.frames();
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        match id {
+            Self::RATE_HZ => self.lfo.set_rate_hz(value),
+            Self::SYNC_DIVISION => self.lfo.set_sync_division(value),
+            Self::DEPTH => {
+                let depth = value.clamp(0.0, 1.0);
+                self.depth.set(depth);
+                self.update_wet(depth);
+            }
+            Self::FEEDBACK => self.feedback.set(value.clamp(-MAX_FEEDBACK, MAX_FEEDBACK)),
+            Self::WET => {
+                self.wet_setting = value.clamp(0.0, 1.0);
+                self.update_wet(self.depth.target());
+            }
+            Self::RETRIGGER => self.lfo.retrigger(),
+            Self::STEREO_PHASE => self.stereo_phase = value.clamp(0.0, 1.0),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.line.fill(0.0);
+        self.lfo.retrigger();
+        self.depth.settle();
+        self.feedback.settle();
+        self.wet.settle();
+    }
+
+    fn set_tempo(&mut self, bpm: Option<f32>) {
+        self.lfo.set_tempo(bpm);
+    }
+}
+
+impl crate::processor::Processor for FlangerFx {
+    /// Reallocates the line for `sample_rate`.
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.allocate(sample_rate);
+    }
+
+    fn process(&mut self, buffer: &mut [f32]) {
+        Fx::process(self, buffer);
+    }
+
+    fn apply(&mut self, update: &ParameterUpdate) {
+        match *update {
+            // The LFO runs on from its phase, so a new rate bends the sweep
+            // rather than jumping it.
+            ParameterUpdate::FxFlangerRate { rate, .. } => match rate {
+                FlangerRate::Hz(hz) => {
+                    self.set_param(Self::SYNC_DIVISION, 0.0);
+                    self.set_param(Self::RATE_HZ, hz);
+                }
+                FlangerRate::Division(division) => self.set_param(Self::SYNC_DIVISION, division),
+            },
+            ParameterUpdate::FxFlangerDepth { depth, .. } => self.set_param(Self::DEPTH, depth),
+            ParameterUpdate::FxFlangerFeedback { feedback, .. } => {
+                self.set_param(Self::FEEDBACK, feedback)
+            }
+            ParameterUpdate::FxFlangerStereoPhase { phase, .. } => {
+                self.set_param(Self::STEREO_PHASE, phase)
+            }
+            ParameterUpdate::Tempo(bpm) => self.set_tempo(bpm),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn noise(frames: usize) -> Vec<f32> {
+        let mut state = 0x1234_5678u32;
+        (0..frames)
+            .flat_map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let s = (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0;
+                [s, s]
+            })
+            .collect()
+    }
+
+    /// Magnitude of the left channel at `freq` over `frames` starting at `start` (Goertzel).
+    fn magnitude(samples: &[f32], start: usize, frames: usize, freq: f32) -> f32 {
+        channel_magnitude(samples, 0, start, frames, freq)
+    }
+
+    fn channel_magnitude(
+        samples: &[f32],
+        ch: usize,
+        start: usize,
+        frames: usize,
+        freq: f32,
+    ) -> f32 {
+        let coeff = 2.0 * (std::f32::consts::TAU * freq / SAMPLE_RATE as f32).cos();
+        let (mut s1, mut s2) = (0.0f32, 0.0f32);
+        for frame in samples[start * 2..(start + frames) * 2].chunks_exact(2) {
+            let s = frame[ch] + coeff * s1 - s2;
+            s2 = s1;
+            s1 = s;
+        }
+        (s1 * s1 + s2 * s2 - coeff * s1 * s2).sqrt()
+    }
+
+    #[test]
+    fn notches_move_with_the_sweep() {
+        let mut fx = FlangerFx::new(SAMPLE_RATE);
+        fx.set_param(FlangerFx::RATE_HZ, 0.1);
+        fx.set_param(FlangerFx::DEPTH, 1.0);
+        fx.set_param(FlangerFx::FEEDBACK, 0.0);
+        fx.set_param(FlangerFx::WET, 0.5);
+        fx.reset();
+
+        let input = noise(5 * SAMPLE_RATE as usize + 8_192);
+        let mut output = input.clone();
+        for block in output.chunks_mut(512) {
+            fx.process(block);
+        }
+        let response = |start: usize, freq: f32| {
+            magnitude(&output, start, 8_192, freq) / magnitude(&input, start, 8_192, freq)
+        };
+
+        // At 1 ms the first notch sits at 500 Hz with a peak at 1 kHz.
+        assert!(response(0, 500.0) < 0.3, "{}", response(0, 500.0));
+        assert!(response(0, 1_000.0) > 0.7);
+        // Half a cycle later the delay is 10 ms: 500 Hz is now a peak, 550 Hz a notch.
+        let later = 5 * SAMPLE_RATE as usize - 4_096;
+        assert!(response(later, 500.0) > 0.7, "{}", response(later, 500.0));
+        assert!(response(later, 550.0) < 0.3);
+    }
+
+    #[test]
+    fn zero_depth_or_wet_is_transparent() {
+        for (param, value) in [(FlangerFx::DEPTH, 0.0), (FlangerFx::WET, 0.0)] {
+            let mut fx = FlangerFx::new(SAMPLE_RATE);
+            fx.set_param(FlangerFx::FEEDBACK, MAX_FEEDBACK);
+            fx.set_param(param, value);
+            fx.reset();
+
+            let input = noise(48_000);
+            let mut output = input.clone();
+            for block in output.chunks_mut(500) {
+                fx.process(block);
+            }
+            assert!(input
+                .iter()
+                .zip(&output)
+                .all(|(a, b)| a.to_bits() == b.to_bits()));
+        }
+    }
+
+    #[test]
+    fn updates_sweep_the_channels_apart_and_over_time() {
+        use crate::processor::Processor;
+        use crate::DeckId;
+
+        let mut fx = FlangerFx::new(SAMPLE_RATE);
+        fx.prepare(SAMPLE_RATE, 512);
+        for update in [
+            ParameterUpdate::FxFlangerRate {
+                deck: DeckId::A,
+                rate: FlangerRate::Hz(0.1),
+            },
+            ParameterUpdate::FxFlangerDepth {
+                deck: DeckId::A,
+                depth: 1.0,
+            },
+            ParameterUpdate::FxFlangerFeedback {
+                deck: DeckId::A,
+                feedback: 0.0,
+            },
+            ParameterUpdate::FxFlangerStereoPhase {
+                deck: DeckId::A,
+                phase: 0.5,
+            },
+        ] {
+            fx.apply(&update);
+        }
+        fx.reset();
+
+        let input = noise(5 * SAMPLE_RATE as usize + 8_192);
+        let mut output = input.clone();
+        for block in output.chunks_mut(512) {
+            Processor::process(&mut fx, block);
+        }
+        // Response in dB across the notch region, for one channel at `start`.
+        let spectrum = |ch: usize, start: usize| -> Vec<f32> {
+            (4..=40)
+                .map(|step| {
+                    let freq = step as f32 * 50.0;
+                    let out = channel_magnitude(&output, ch, start, 8_192, freq);
+                    let inp = channel_magnitude(&input, ch, start, 8_192, freq);
+                    20.0 * (out / inp).max(1e-3).log10()
+                })
+                .collect()
+        };
+        // Mean squared difference between two responses, in dB squared.
+        let variance = |a: &[f32], b: &[f32]| {
+            a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum::<f32>() / a.len() as f32
+        };
+        let later = 5 * SAMPLE_RATE as usize - 4_096;
+        let (left_early, left_late) = (spectrum(0, 0), spectrum(0, later));
+        let (right_early, right_late) = (spectrum(1, 0), spectrum(1, later));
+        // Half a cycle on, the notches have moved well away...
+        assert!(
+            variance(&left_early, &left_late) > 50.0,
+            "{left_early:?} {left_late:?}"
+        );
+        // ...and with the channels half a cycle apart, the right ends where
+        // the left began.
+        assert!(variance(&left_early, &right_early) > 50.0);
+        assert!(variance(&left_early, &right_late) < 20.0);
+    }
+
+    #[test]
+    fn rate_changes_bend_the_sweep_without_jumping() {
+        use crate::processor::Processor;
+        use crate::DeckId;
+
+        let mut fx = FlangerFx::new(SAMPLE_RATE);
+        fx.prepare(SAMPLE_RATE, 512);
+        let mut block = noise(10_000);
+        Processor::process(&mut fx, &mut block);
+        let before = fx.lfo.peek(0.0);
+
+        fx.apply(&ParameterUpdate::FxFlangerRate {
+            deck: DeckId::A,
+            rate: FlangerRate::Hz(5.0),
+        });
+        assert_eq!(fx.lfo.peek(0.0), before);
+        fx.apply(&ParameterUpdate::Tempo(Some(120.0)));
+        fx.apply(&ParameterUpdate::FxFlangerRate {
+            deck: DeckId::A,
+            rate: FlangerRate::Division(0.25),
+        });
+        assert_eq!(fx.lfo.rate_hz(), 2.0);
+        assert_eq!(fx.lfo.peek(0.0), before);
+
+        // One frame on, the sweep has moved by one frame's worth of 2 Hz.
+        Processor::process(&mut fx, &mut [0.0; 2]);
+        assert!((fx.lfo.peek(0.0) - before).abs() < 1e-3);
+
+        // Back to a free rate, the tempo no longer applies.
+        fx.apply(&ParameterUpdate::FxFlangerRate {
+            deck: DeckId::A,
+            rate: FlangerRate::Hz(0.5),
+        });
+        assert_eq!(fx.lfo.rate_hz(), 0.5);
+    }
+}
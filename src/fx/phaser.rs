@@ -0,0 +1,192 @@
+use std::f32::consts::PI;
+
+use super::{mix_wet, Fx, Lfo, Smoothed};
+
+/// All-pass corner frequency with the LFO at its low point.
+const MIN_SWEEP_HZ: f32 = 200.0;
+/// All-pass corner frequency with the LFO at its peak and full depth.
+const MAX_SWEEP_HZ: f32 = 4_000.0;
+/// Stage counts the chain can be tapped at.
+const STAGE_RANGE: (usize, usize) = (4, 8);
+/// Feedback is capped below unity so the resonances stay stable.
+const MAX_FEEDBACK: f32 = 0.9;
+
+/// Stereo phaser: a chain of first-order all-passes swept by an LFO and mixed
+/// back with the dry signal.
+///
+/// All eight stages always run and the output tap glides between them, so
+/// stage changes are click-free. At zero depth the wet signal fades out entirely.
+#[derive(Debug)]
+pub struct PhaserFx {
+    sample_rate: u32,
+    /// All-pass states per stage, per channel.
+    states: [[f32; 2]; STAGE_RANGE.1],
+    /// Last wet output per channel, fed back into the chain.
+    last: [f32; 2],
+    lfo: Lfo,
+    stages: Smoothed,
+    depth: Smoothed,
+    feedback: Smoothed,
+    wet: Smoothed,
+    wet_setting: f32,
+}
+
+impl PhaserFx {
+    /// Free-running LFO rate in Hz.
+    pub const RATE_HZ: u32 = 0;
+    /// LFO cycle as a fraction of a whole note while a tempo is set; zero uses the free rate.
+    pub const SYNC_DIVISION: u32 = 1;
+    /// Sweep depth in [0, 1].
+    pub const DEPTH: u32 = 2;
+    /// Feedback in [-0.9, 0.9].
+    pub const FEEDBACK: u32 = 3;
+    /// [Wet/dry balance](super#wet-and-dry).
+    pub const WET: u32 = 4;
+    /// Any value restarts the LFO cycle.
+    pub const RETRIGGER: u32 = 5;
+    /// Number of all-pass stages, 4 to 8.
+    pub const STAGES: u32 = 6;
+
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            states: [[0.0; 2]; STAGE_RANGE.1],
+            last: [0.0; 2],
+            lfo: Lfo::new(0.5, sample_rate),
+            stages: Smoothed::new(STAGE_RANGE.0 as f32, sample_rate),
+            depth: Smoothed::new(1.0, sample_rate),
+            feedback: Smoothed::new(0.3, sample_rate),
+            wet: Smoothed::new(0.5, sample_rate),
+            wet_setting: 0.5,
+        }
+    }
+
+    fn update_wet(&mut self, depth: f32) {
+        self.wet
+            .set(if depth > 0.0 { self.wet_setting } else { 0.0 });
+    }
+}
+
+impl Fx for PhaserFx {
+    fn process(&mut self, frames: &mut [f32]) {
+        for frame in frames.chunks_exact_mut(2) {
+            let sweep = self.lfo.next() * self.depth.next();
+            let corner = MIN_SWEEP_HZ * (MAX_SWEEP_HZ / MIN_SWEEP_HZ).powf(sweep);
+            let t = (PI * corner / self.sample_rate as f32).tan();
+            let a = (t - 1.0) / (t + 1.0);
+
+            let stages = self.stages.next();
+            let lower = stages.floor() as usize;
+            let blend = stages - lower as f32;
+            let feedback = self.feedback.next();
+            let wet = self.wet.next();
+            for ch in 0..2 {
+                let mut x = frame[ch] + self.last[ch] * feedback;
+                let mut taps = [0.0; 2];
+                for (stage, state) in self.states.iter_mut().enumerate() {
+                    let y = a * x + state[ch];
+                    state[ch] = x - a * y;
+                    x = y;
+                    if stage + 1 == lower {
+                        taps[0] = y;
+                    } else if stage == lower {
+                        taps[1] = y;
+                    }
+                }
+                let out = taps[0] + (taps[1] - taps[0]) * blend;
+                self.last[ch] = out;
+                mix_wet(&mut frame[ch], out, wet);
+            }
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        match id {
+            Self::RATE_HZ => self.lfo.set_rate_hz(value),
+            Self::SYNC_DIVISION => self.lfo.set_sync_division(value),
+            Self::DEPTH => {
+                let depth = value.clamp(0.0, 1.0);
+                self.depth.set(depth);
+                self.update_wet(depth);
+            }
+            Self::FEEDBACK => self.feedback.set(value.clamp(-MAX_FEEDBACK, MAX_FEEDBACK)),
+            Self::WET => {
+                self.wet_setting = value.clamp(0.0, 1.0);
+                self.update_wet(self.depth.target());
+            }
+            Self::RETRIGGER => self.lfo.retrigger(),
+            Self::STAGES => self.stages.set(
+                value
+                    .round()
+                    .clamp(STAGE_RANGE.0 as f32, STAGE_RANGE.1 as f32),
+            ),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.states = [[0.0; 2]; STAGE_RANGE.1];
+        self.last = [0.0; 2];
+        self.lfo.retrigger();
+        self.stages.settle();
+        self.depth.settle();
+        self.feedback.settle();
+        self.wet.settle();
+    }
+
+    fn set_tempo(&mut self, bpm: Option<f32>) {
+        self.lfo.set_tempo(bpm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn noise(frames: usize) -> Vec<f32> {
+        let mut state = 0x0bad_cafeu32;
+        (0..frames)
+            .flat_map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let s = (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0;
+                [s, s]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn zero_depth_or_wet_is_transparent() {
+        for (param, value) in [(PhaserFx::DEPTH, 0.0), (PhaserFx::WET, 0.0)] {
+            let mut fx = PhaserFx::new(SAMPLE_RATE);
+            fx.set_param(PhaserFx::FEEDBACK, MAX_FEEDBACK);
+            fx.set_param(param, value);
+            fx.reset();
+
+            let input = noise(48_000);
+            let mut output = input.clone();
+            for block in output.chunks_mut(500) {
+                fx.process(block);
+            }
+            assert!(input
+                .iter()
+                .zip(&output)
+                .all(|(a, b)| a.to_bits() == b.to_bits()));
+        }
+    }
+
+    #[test]
+    fn stays_bounded_while_changing_stages_at_full_feedback() {
+        let mut fx = PhaserFx::new(SAMPLE_RATE);
+        fx.set_param(PhaserFx::RATE_HZ, 3.0);
+        fx.set_param(PhaserFx::FEEDBACK, MAX_FEEDBACK);
+        fx.set_param(PhaserFx::WET, 1.0);
+        let mut buffer = noise(96_000);
+        for (i, block) in buffer.chunks_mut(512).enumerate() {
+            fx.set_param(PhaserFx::STAGES, (4 + i % 5) as f32);
+            fx.process(block);
+            assert!(block.iter().all(|s| s.is_finite() && s.abs() < 30.0));
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use super::{mix_wet, Fx, Smoothed};
+use crate::ParameterUpdate;
+
+/// Freeverb comb lengths in frames at 44.1 kHz.
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// Freeverb all-pass lengths in frames at 44.1 kHz.
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+/// Extra length of the right channel's filters, decorrelating the channels.
+const STEREO_SPREAD: usize = 23;
+const TUNING_RATE: f32 = 44_100.0;
+/// Input attenuation keeping the eight summed combs near unity.
+const INPUT_GAIN: f32 = 0.015;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+/// Longest pre-delay the preallocated line can hold.
+pub const MAX_PRE_DELAY_MS: f32 = 250.0;
+/// Decay of the smallest and largest rooms, in seconds.
+const MIN_ROOM_DECAY_SECONDS: f32 = 0.2;
+const MAX_ROOM_DECAY_SECONDS: f32 = 10.0;
+/// Anything quieter than this in the loops is taken as silence, so a dying
+/// tail reaches zero instead of crawling through the denormal range.
+const FLUSH_BELOW: f32 = 1e-20;
+
+fn flush(sample: f32) -> f32 {
+    if sample.abs() < FLUSH_BELOW {
+        0.0
+    } else {
+        sample
+    }
+}
+
+#[derive(Debug, Default)]
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    feedback: f32,
+    filter_state: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.filter_state = flush(output + (self.filter_state - output) * damping);
+        self.buffer[self.index] = flush(input + self.filter_state * self.feedback);
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+#[derive(Debug, Default)]
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.index];
+        self.buffer[self.index] = flush(input + delayed * ALLPASS_FEEDBACK);
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+/// Freeverb-style stereo reverb. Output is wet only for the send bus until
+/// [`MIX`](Self::MIX) brings the dry signal back, as it does for a deck
+/// insert run through [`Processor`](crate::processor::Processor), where it
+/// follows the `FxReverb*` updates sent to its deck.
+#[derive(Debug)]
+pub struct ReverbFx {
+    sample_rate: u32,
+    pre_delay: Vec<f32>,
+    pre_delay_write: usize,
+    pre_delay_frames: usize,
+    /// Comb and all-pass banks for the left and right channels.
+    combs: [[Comb; 8]; 2],
+    allpasses: [[Allpass; 4]; 2],
+    decay_seconds: f32,
+    pre_delay_ms: f32,
+    damping: f32,
+    width: f32,
+    mix: Smoothed,
+}
+
+impl ReverbFx {
+    /// Time for the tail to fall by 60 dB, in seconds.
+    pub const DECAY_SECONDS: u32 = 0;
+    /// Pre-delay in milliseconds, up to [`MAX_PRE_DELAY_MS`].
+    pub const PRE_DELAY_MS: u32 = 1;
+    /// High-frequency damping in the tail, [0, 1).
+    pub const DAMPING: u32 = 2;
+    /// Stereo width in [0, 1]; 0 is mono.
+    pub const WIDTH: u32 = 3;
+    /// [Wet/dry balance](super#wet-and-dry).
+    pub const MIX: u32 = 4;
+    /// Room size in [0, 1], setting the decay from 0.2 to 10 seconds.
+    pub const ROOM_SIZE: u32 = 5;
+
+    pub fn new(sample_rate: u32) -> Self {
+        let mut fx = Self {
+            sample_rate,
+            pre_delay: Vec::new(),
+            pre_delay_write: 0,
+            pre_delay_frames: 0,
+            combs: Default::default(),
+            allpasses: Default::default(),
+            decay_seconds: 2.5,
+            pre_delay_ms: 20.0,
+            damping: 0.4,
+            width: 1.0,
+            mix: Smoothed::new(1.0, sample_rate),
+        };
+        fx.set_sample_rate(sample_rate);
+        fx
+    }
+
+    /// Resize every buffer for `sample_rate`. Allocates, so call it off the audio thread.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        let scale = sample_rate as f32 / TUNING_RATE;
+        let frames = |tuning: usize, ch: usize| {
+            (((tuning + ch * STEREO_SPREAD) as f32 * scale).round() as usize).max(1)
+        };
+        for ch in 0..2 {
+            for (comb, tuning) in self.combs[ch].iter_mut().zip(COMB_TUNING) {
+                comb.buffer = vec![0.0; frames(tuning, ch)];
+                comb.index = 0;
+            }
+            for (allpass, tuning) in self.allpasses[ch].iter_mut().zip(ALLPASS_TUNING) {
+                allpass.buffer = vec![0.0; frames(tuning, ch)];
+                allpass.index = 0;
+            }
+        }
+        self.pre_delay = vec![0.0; (MAX_PRE_DELAY_MS * sample_rate as f32 / 1_000.0) as usize + 1];
+        self.pre_delay_write = 0;
+        self.mix = Smoothed::new(self.mix.target(), sample_rate);
+        self.set_param(Self::DECAY_SECONDS, self.decay_seconds);
+        self.set_param(Self::PRE_DELAY_MS, self.pre_delay_ms);
+        self.clear();
+    }
+
+    /// Kill the tail immediately.
+    pub fn clear(&mut self) {
+        self.pre_delay.fill(0.0);
+        for comb in self.combs.iter_mut().flatten() {
+            comb.buffer.fill(0.0);
+            comb.filter_state = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut().flatten() {
+            allpass.buffer.fill(0.0);
+        }
+    }
+}
+
+impl Fx for ReverbFx {
+    fn process(&mut self, frames: &mut [f32]) {
+        let (main, cross) = ((1.0 + self.width) / 2.0, (1.0 - self.width) / 2.0);
+        let len = self.pre_delay.len();
+        for frame in frames.chunks_exact_mut(2) {
+            self.pre_delay[self.pre_delay_write] = (frame[0] + frame[1]) * INPUT_GAIN;
+            let input = self.pre_delay[(self.pre_delay_write + len - self.pre_delay_frames) % len];
+            self.pre_delay_write = (self.pre_delay_write + 1) % len;
+
+            let mut wet = [0.0; 2];
+            for (ch, out) in wet.iter_mut().enumerate() {
+                *out = self.combs[ch]
+                    .iter_mut()
+                    .map(|comb| comb.process(input, self.damping))
+                    .sum();
+                for allpass in &mut self.allpasses[ch] {
+                    *out = allpass.process(*out);
+                }
+            }
+            let mix = self.mix.next();
+            let out = [
+                wet[0] * main + wet[1] * cross,
+                wet[1] * main + wet[0] * cross,
+            ];
+            for (sample, out) in frame.iter_mut().zip(out) {
+                mix_wet(sample, out, mix);
+            }
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        match id {
+            Self::DECAY_SECONDS => {
+                self.decay_seconds = value.max(0.05);
+                let decay_frames = self.decay_seconds * self.sample_rate as f32;
+                for comb in self.combs.iter_mut().flatten() {
+                    // -60 dB after `decay_frames`, spread over the passes through this comb.
+                    comb.feedback = 10f32.powf(-3.0 * comb.buffer.len() as f32 / decay_frames);
+                }
+            }
+            Self::PRE_DELAY_MS => {
+                self.pre_delay_ms = value.clamp(0.0, MAX_PRE_DELAY_MS);
+                self.pre_delay_frames = ((self.pre_delay_ms * self.sample_rate as f32 / 1_000.0)
+                    as usize)
+                    .min(self.pre_delay.len() - 1);
+            }
+            Self::DAMPING => self.damping = value.clamp(0.0, 0.99),
+            Self::WIDTH => self.width = value.clamp(0.0, 1.0),
+            Self::MIX => self.mix.set(value.clamp(0.0, 1.0)),
+            Self::ROOM_SIZE => {
+                let size = value.clamp(0.0, 1.0);
+                let range = MAX_ROOM_DECAY_SECONDS / MIN_ROOM_DECAY_SECONDS;
+                self.set_param(
+                    Self::DECAY_SECONDS,
+                    MIN_ROOM_DECAY_SECONDS * range.powf(size),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.clear();
+        self.mix.settle();
+    }
+}
+
+impl crate::processor::Processor for ReverbFx {
+    /// Reallocates the combs and all-passes for `sample_rate`.
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn process(&mut self, buffer: &mut [f32]) {
+        Fx::process(self, buffer);
+    }
+
+    fn apply(&mut self, update: &ParameterUpdate) {
+        match *update {
+            ParameterUpdate::FxReverbRoomSize { size, .. } => self.set_param(Self::ROOM_SIZE, size),
+            ParameterUpdate::FxReverbDamping { damping, .. } => {
+                self.set_param(Self::DAMPING, damping)
+            }
+            ParameterUpdate::FxReverbMix { mix, .. } => self.set_param(Self::MIX, mix),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn impulse_response(fx: &mut ReverbFx, frames: usize) -> Vec<f32> {
+        let mut buffer = vec![0.0; frames * 2];
+        buffer[0] = 1.0;
+        buffer[1] = 1.0;
+        for block in buffer.chunks_mut(512) {
+            fx.process(block);
+        }
+        buffer
+    }
+
+    fn rms_db(samples: &[f32]) -> f32 {
+        let mean = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        10.0 * mean.log10()
+    }
+
+    #[test]
+    fn tail_decays_by_sixty_db_over_t60() {
+        let mut fx = ReverbFx::new(SAMPLE_RATE);
+        fx.set_param(ReverbFx::DECAY_SECONDS, 2.0);
+        fx.set_param(ReverbFx::PRE_DELAY_MS, 0.0);
+        fx.set_param(ReverbFx::DAMPING, 0.0);
+
+        let response = impulse_response(&mut fx, 2 * SAMPLE_RATE as usize);
+        let window = |seconds: f32| {
+            let start = (seconds * SAMPLE_RATE as f32) as usize * 2;
+            rms_db(&response[start..start + 9_600])
+        };
+        // Half of T60 apart, so the tail should have dropped by about 30 dB.
+        let drop = window(0.4) - window(1.4);
+        assert!((drop - 30.0).abs() < 5.0, "dropped {drop} dB");
+    }
+
+    #[test]
+    fn clear_silences_the_next_block() {
+        let mut fx = ReverbFx::new(SAMPLE_RATE);
+        fx.set_param(ReverbFx::DECAY_SECONDS, 10.0);
+        let mut loud = vec![0.5; 9_600];
+        fx.process(&mut loud);
+        assert!(loud.iter().any(|s| s.abs() > 1e-3));
+
+        fx.clear();
+        let mut block = [0.0; 512];
+        fx.process(&mut block);
+        assert!(block.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn channels_are_decorrelated() {
+        let mut fx = ReverbFx::new(SAMPLE_RATE);
+        fx.set_param(ReverbFx::WIDTH, 1.0);
+        let response = impulse_response(&mut fx, SAMPLE_RATE as usize);
+        let (mut lr, mut ll, mut rr) = (0.0f64, 0.0f64, 0.0f64);
+        for frame in response.chunks_exact(2) {
+            let (l, r) = (frame[0] as f64, frame[1] as f64);
+            lr += l * r;
+            ll += l * l;
+            rr += r * r;
+        }
+        let correlation = lr / (ll * rr).sqrt();
+        assert!(correlation.abs() < 0.3, "correlation {correlation}");
+
+        // Zero width folds both channels together.
+        fx.set_param(ReverbFx::WIDTH, 0.0);
+        fx.clear();
+        let mono = impulse_response(&mut fx, 4_800);
+        assert!(mono.chunks_exact(2).all(|f| f[0] == f[1]));
+    }
+
+    #[test]
+    fn tail_decays_steadily_to_silence_at_any_rate() {
+        use crate::processor::Processor;
+
+        for rate in [44_100, 96_000, 192_000] {
+            let mut fx = ReverbFx::new(SAMPLE_RATE);
+            fx.prepare(rate, 512);
+            fx.set_param(ReverbFx::ROOM_SIZE, 0.0);
+            fx.set_param(ReverbFx::PRE_DELAY_MS, 0.0);
+            fx.set_param(ReverbFx::DAMPING, 0.5);
+
+            let response = impulse_response(&mut fx, 3 * rate as usize);
+            // Past the early reflections, each 50 ms holds less than the last.
+            let window = rate as usize / 20 * 2;
+            let energy: Vec<f64> = response[window * 2..]
+                .chunks(window)
+                .map(|chunk| chunk.iter().map(|s| (*s as f64).powi(2)).sum())
+                .collect();
+            assert!(energy[0] > 0.0);
+            assert!(
+                energy
+                    .windows(2)
+                    .all(|pair| pair[1] < pair[0] || pair[1] == 0.0),
+                "{rate} Hz: {energy:?}"
+            );
+            // The tail ends in true silence, never crawling through denormals.
+            assert!(response.iter().all(|s| *s == 0.0 || s.is_normal()));
+            assert_eq!(*energy.last().unwrap(), 0.0, "{rate} Hz");
+        }
+    }
+
+    #[test]
+    fn zero_mix_passes_audio_unchanged() {
+        use crate::processor::Processor;
+        use crate::DeckId;
+
+        let mut fx = ReverbFx::new(SAMPLE_RATE);
+        fx.prepare(SAMPLE_RATE, 512);
+        fx.apply(&ParameterUpdate::FxReverbMix {
+            deck: DeckId::A,
+            mix: 0.0,
+        });
+        fx.reset();
+
+        let mut state = 0x1357_9bdfu32;
+        let input: Vec<f32> = (0..48_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+            })
+            .collect();
+        let mut output = input.clone();
+        for block in output.chunks_mut(512) {
+            Processor::process(&mut fx, block);
+        }
+        assert!(input
+            .iter()
+            .zip(&output)
+            .all(|(a, b)| a.to_bits() == b.to_bits()));
+
+        // The tail kept building underneath, ready as the mix comes up.
+        fx.apply(&ParameterUpdate::FxReverbMix {
+            deck: DeckId::A,
+            mix: 1.0,
+        });
+        let mut block = [0.0; 1_024];
+        Processor::process(&mut fx, &mut block);
+        assert!(block[512..].iter().any(|s| s.abs() > 1e-3));
+    }
+}
@@ -0,0 +1,388 @@
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::deck::TrackInfo;
+use crate::DeckId;
+
+/// Default audible play time before a track counts as played.
+pub const DEFAULT_MIN_PLAYED_SECONDS: f64 = 60.0;
+/// Post-fader gain below which a deck counts as silent (-60 dB).
+const AUDIBLE_GAIN: f32 = 0.001;
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("failed to access history log: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse history log line {line}: {source}")]
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("failed to encode history entry: {0}")]
+    Encode(#[source] serde_json::Error),
+}
+
+/// One track as it went out to the audience.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the track first became audible.
+    pub started_at: DateTime<Utc>,
+    /// Time spent audible, not counting paused or faded-out stretches.
+    pub played_seconds: f64,
+    pub deck: DeckId,
+    #[serde(flatten)]
+    pub info: TrackInfo,
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+impl HistoryEntry {
+    fn name(&self) -> String {
+        self.info
+            .display_name()
+            .or_else(|| {
+                let name = self.path.as_ref()?.file_stem()?;
+                Some(name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "Unknown track".to_string())
+    }
+}
+
+/// Append-only JSON-lines file of played tracks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryLog {
+    path: PathBuf,
+}
+
+impl HistoryLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn default_path() -> PathBuf {
+        Path::new("history.jsonl").to_path_buf()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write one entry and sync it to disk, so a crash only loses tracks still playing.
+    pub fn append(&self, entry: &HistoryEntry) -> Result<(), HistoryError> {
+        let mut line = serde_json::to_string(entry).map_err(HistoryError::Encode)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Every logged entry; a missing log is empty and a line cut short by a crash is skipped.
+    pub fn read(&self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let torn = !contents.is_empty() && !contents.ends_with('\n');
+        let lines: Vec<&str> = contents.lines().collect();
+        let complete = lines.len() - usize::from(torn);
+        lines[..complete]
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|source| HistoryError::Parse {
+                    line: index + 1,
+                    source,
+                })
+            })
+            .collect()
+    }
+}
+
+/// What the tracker needs from a deck each tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeckActivity {
+    pub playing: bool,
+    /// Gain the deck reaches the master with, from [`crate::FaderReader`].
+    pub post_fader_gain: f32,
+}
+
+impl DeckActivity {
+    fn audible(&self) -> bool {
+        self.playing && self.post_fader_gain >= AUDIBLE_GAIN
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    info: TrackInfo,
+    path: Option<PathBuf>,
+    started_at: Option<DateTime<Utc>>,
+    played_seconds: f64,
+}
+
+/// Follows the decks through a set and logs each track the audience actually heard.
+#[derive(Debug)]
+pub struct PlayTracker {
+    log: HistoryLog,
+    min_played_seconds: f64,
+    sessions: [Option<Session>; 2],
+}
+
+impl PlayTracker {
+    pub fn new(log: HistoryLog) -> Self {
+        Self {
+            log,
+            min_played_seconds: DEFAULT_MIN_PLAYED_SECONDS,
+            sessions: [None, None],
+        }
+    }
+
+    pub fn min_played_seconds(&self) -> f64 {
+        self.min_played_seconds
+    }
+
+    pub fn set_min_played_seconds(&mut self, seconds: f64) {
+        self.min_played_seconds = seconds.max(0.0);
+    }
+
+    /// A new track went onto `deck`; the one it replaces is logged if it played long enough.
+    pub fn track_loaded(
+        &mut self,
+        deck: DeckId,
+        info: TrackInfo,
+        path: Option<PathBuf>,
+    ) -> Result<Option<HistoryEntry>, HistoryError> {
+        let logged = self.close(deck)?;
+        self.sessions[deck.index()] = Some(Session {
+            info,
+            path,
+            started_at: None,
+            played_seconds: 0.0,
+        });
+        Ok(logged)
+    }
+
+    /// `deck` was ejected.
+    pub fn track_unloaded(&mut self, deck: DeckId) -> Result<Option<HistoryEntry>, HistoryError> {
+        self.close(deck)
+    }
+
+    /// Count `elapsed` towards every deck that was audible over it.
+    pub fn advance(&mut self, now: DateTime<Utc>, elapsed: Duration, decks: [DeckActivity; 2]) {
+        for (session, activity) in self.sessions.iter_mut().zip(decks) {
+            let Some(session) = session else {
+                continue;
+            };
+            if activity.audible() {
+                session.started_at.get_or_insert(now);
+                session.played_seconds += elapsed.as_secs_f64();
+            }
+        }
+    }
+
+    /// End of the set: log whatever is still loaded.
+    pub fn finish(&mut self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let mut logged = Vec::new();
+        for deck in [DeckId::A, DeckId::B] {
+            logged.extend(self.close(deck)?);
+        }
+        logged.sort_by_key(|entry| entry.started_at);
+        Ok(logged)
+    }
+
+    fn close(&mut self, deck: DeckId) -> Result<Option<HistoryEntry>, HistoryError> {
+        let Some(session) = self.sessions[deck.index()].take() else {
+            return Ok(None);
+        };
+        let Some(started_at) = session.started_at else {
+            return Ok(None);
+        };
+        if session.played_seconds < self.min_played_seconds {
+            return Ok(None);
+        }
+        let entry = HistoryEntry {
+            started_at,
+            played_seconds: session.played_seconds,
+            deck,
+            info: session.info,
+            path: session.path,
+        };
+        self.log.append(&entry)?;
+        Ok(Some(entry))
+    }
+}
+
+/// Entries as CSV with a header row, for rights bodies' reporting tools.
+pub fn export_csv(entries: &[HistoryEntry]) -> String {
+    let mut csv = String::from("started_at,played_seconds,deck,artist,title,path\n");
+    for entry in entries {
+        let path = entry
+            .path
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned());
+        let _ = writeln!(
+            csv,
+            "{},{:.1},{},{},{},{}",
+            entry.started_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            entry.played_seconds,
+            entry.deck,
+            csv_field(entry.info.artist.as_deref()),
+            csv_field(entry.info.title.as_deref()),
+            csv_field(path.as_deref()),
+        );
+    }
+    csv
+}
+
+/// Numbered tracklist with each track's start relative to the first, for posting after a set.
+pub fn export_tracklist(entries: &[HistoryEntry]) -> String {
+    let Some(first) = entries.first() else {
+        return String::new();
+    };
+    let mut list = String::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let offset = (entry.started_at - first.started_at).num_seconds().max(0);
+        let _ = writeln!(
+            list,
+            "{}. {:02}:{:02}:{:02} {}",
+            index + 1,
+            offset / 3_600,
+            offset / 60 % 60,
+            offset % 60,
+            entry.name()
+        );
+    }
+    list
+}
+
+/// Quote a field if it holds a separator, quote or line break.
+fn csv_field(text: Option<&str>) -> String {
+    let text = text.unwrap_or_default();
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn info(artist: &str, title: &str) -> TrackInfo {
+        TrackInfo {
+            artist: Some(artist.to_string()),
+            title: Some(title.to_string()),
+        }
+    }
+
+    #[test]
+    fn logs_only_tracks_heard_for_long_enough() {
+        let dir = tempdir().unwrap();
+        let log = HistoryLog::new(dir.path().join("history.jsonl"));
+        let mut tracker = PlayTracker::new(log.clone());
+        let start = Utc.with_ymd_and_hms(2026, 5, 1, 22, 0, 0).unwrap();
+        let mut clock = 0;
+        // Step the set forward one second at a time.
+        let mut run = |tracker: &mut PlayTracker, seconds: u32, decks: [DeckActivity; 2]| {
+            for _ in 0..seconds {
+                let now = start + chrono::Duration::seconds(clock);
+                tracker.advance(now, Duration::from_secs(1), decks);
+                clock += 1;
+            }
+        };
+        let on_air = DeckActivity {
+            playing: true,
+            post_fader_gain: 1.0,
+        };
+        let faded_out = DeckActivity {
+            playing: true,
+            post_fader_gain: 0.0,
+        };
+
+        tracker
+            .track_loaded(DeckId::A, info("Wrong", "Track"), None)
+            .unwrap();
+        run(&mut tracker, 10, [on_air, DeckActivity::default()]);
+        let misload = tracker
+            .track_loaded(
+                DeckId::A,
+                info("Right", "Track"),
+                Some("/music/right.flac".into()),
+            )
+            .unwrap();
+        assert_eq!(misload, None);
+
+        // Deck B is cued up in the headphones with its fader down the whole time.
+        tracker
+            .track_loaded(DeckId::B, info("Next", "Track"), None)
+            .unwrap();
+        run(&mut tracker, 90, [on_air, faded_out]);
+        run(&mut tracker, 30, [DeckActivity::default(), faded_out]);
+
+        let logged = tracker.finish().unwrap();
+        assert_eq!(logged.len(), 1);
+        let entry = &logged[0];
+        assert_eq!(entry.info, info("Right", "Track"));
+        assert_eq!(entry.started_at, start + chrono::Duration::seconds(10));
+        assert_eq!(entry.played_seconds, 90.0);
+        assert_eq!(entry.deck, DeckId::A);
+        assert_eq!(log.read().unwrap(), logged);
+    }
+
+    #[test]
+    fn exports_csv_and_tracklist() {
+        let start = Utc.with_ymd_and_hms(2026, 5, 1, 22, 0, 0).unwrap();
+        let entries = [
+            HistoryEntry {
+                started_at: start,
+                played_seconds: 245.3,
+                deck: DeckId::A,
+                info: info("Artist, The", "Say \"Hi\""),
+                path: Some("/music/a.mp3".into()),
+            },
+            HistoryEntry {
+                started_at: start + chrono::Duration::seconds(3_725),
+                played_seconds: 61.0,
+                deck: DeckId::B,
+                info: TrackInfo::default(),
+                path: Some("/music/Untagged Mix.flac".into()),
+            },
+        ];
+        assert_eq!(
+            export_csv(&entries),
+            "started_at,played_seconds,deck,artist,title,path\n\
+             2026-05-01T22:00:00Z,245.3,A,\"Artist, The\",\"Say \"\"Hi\"\"\",/music/a.mp3\n\
+             2026-05-01T23:02:05Z,61.0,B,,,/music/Untagged Mix.flac\n"
+        );
+        assert_eq!(
+            export_tracklist(&entries),
+            "1. 00:00:00 Artist, The - Say \"Hi\"\n2. 01:02:05 Untagged Mix\n"
+        );
+
+        let dir = tempdir().unwrap();
+        let log = HistoryLog::new(dir.path().join("history.jsonl"));
+        for entry in &entries {
+            log.append(entry).unwrap();
+        }
+        // A crash in the middle of a write leaves a partial last line.
+        let mut file = OpenOptions::new().append(true).open(log.path()).unwrap();
+        file.write_all(b"{\"started_at\":").unwrap();
+        assert_eq!(log.read().unwrap(), entries);
+    }
+}
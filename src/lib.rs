@@ -1,35 +1,767 @@
+pub mod analysis;
+#[cfg(feature = "native")]
+pub mod autodj;
+pub mod automation;
+#[cfg(feature = "native")]
+pub mod bundle;
+pub mod cell;
+#[cfg(feature = "native")]
+pub mod crash;
+pub mod db;
+pub mod deck;
+#[cfg(feature = "native")]
+pub mod decode;
+mod denormal;
+pub mod dvs;
+#[cfg(feature = "native")]
+pub mod engine;
+pub mod eq;
+#[cfg(feature = "native")]
+pub mod fader_start;
+pub mod fx;
+#[cfg(feature = "native")]
+pub mod history;
+#[cfg(feature = "native")]
+pub mod library;
+pub mod limiter;
+#[cfg(feature = "native")]
+pub mod link;
+#[cfg(feature = "native")]
+pub mod metadata;
+pub mod meter;
+#[cfg(feature = "native")]
+pub mod metrics;
+pub mod mic;
+#[cfg(feature = "native")]
+pub mod midi;
+#[cfg(feature = "native")]
+pub mod osc;
+pub mod processor;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "native")]
+pub mod record;
+#[cfg(feature = "native")]
+pub mod remote;
+#[cfg(feature = "native")]
+pub mod render;
+pub mod ring;
+pub mod sampler;
+pub mod schedule;
+#[cfg(feature = "native")]
+pub mod session;
+#[cfg(feature = "native")]
+pub mod settings;
+pub mod sidechain;
+#[cfg(feature = "simd")]
+mod simd;
+pub mod snapshot;
+pub mod softclip;
+pub mod spectrum;
+#[cfg(feature = "stream")]
+pub mod stream;
+pub mod tempo;
+pub mod transition;
+pub mod version;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
 use crossbeam_queue::ArrayQueue;
+use std::cell::Cell;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 
-/// Identifier for a deck feeding the summing bus.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum DeckId {
-    A = 0,
-    B = 1,
+use automation::{AutomationTap, Lane};
+use deck::DeckCommand;
+use denormal::DenormalGuard;
+use eq::{DeckEq, EqBand};
+use fx::{DelayTime, FilterFx, FlangerRate, Fx, Smoothed};
+use limiter::Limiter;
+use meter::{
+    loudness_meter, Correlation, Levels, LoudnessMeter, MeterFrame, MeterSender, PeakHold,
+    DEFAULT_PEAK_HOLD_FRAMES,
+};
+use mic::MicChannel;
+use processor::Processor;
+use ring::{RecordTap, RingConsumer};
+use sampler::Sampler;
+use schedule::{ScheduleReceiver, ScheduledUpdate};
+use serde::{Deserialize, Serialize};
+use sidechain::SidechainCompressor;
+use snapshot::{CrossfadeProgress, MixerSnapshot, SnapshotSender};
+use softclip::SoftClip;
+use spectrum::SpectrumTap;
+use thiserror::Error;
+
+/// Identifier for a deck feeding the summing bus, by its index; [`A`](Self::A)
+/// and [`B`](Self::B) are the two on the crossfader.
+///
+/// Written as the deck's letter, so two-deck sessions read as before.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DeckId(pub usize);
+
+impl DeckId {
+    pub const A: DeckId = DeckId(0);
+    pub const B: DeckId = DeckId(1);
+
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl std::fmt::Display for DeckId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match u8::try_from(self.0) {
+            Ok(index) if index < 26 => write!(f, "{}", (b'A' + index) as char),
+            _ => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl std::fmt::Debug for DeckId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+impl From<DeckId> for String {
+    fn from(deck: DeckId) -> Self {
+        deck.to_string()
+    }
+}
+
+impl TryFrom<String> for DeckId {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        match name.as_bytes() {
+            [letter @ b'A'..=b'Z'] => Ok(DeckId((letter - b'A') as usize)),
+            _ => name
+                .parse()
+                .map(DeckId)
+                .map_err(|_| format!("{name:?} is not a deck")),
+        }
+    }
+}
+
+/// Most a channel trim boosts, +12 dB.
+const MAX_TRIM: f32 = 3.981_072;
+
+/// Time constant of the fade when a deck is muted, soloed or released, or
+/// cued in or out of the headphones.
+const MUTE_FADE_SECONDS: f32 = 0.003;
+/// Corner of the DC blocker on the deck inputs.
+const DC_BLOCK_HZ: f32 = 5.0;
+/// Parameter updates applied per block unless
+/// [`set_updates_per_block`](SummingBus::set_updates_per_block) says otherwise.
+pub const DEFAULT_UPDATES_PER_BLOCK: usize = 64;
+/// Most updates one [`ParameterSender::send_batch`] carries.
+pub const MAX_BATCH_UPDATES: usize = 128;
+
+/// Which decks are heard: the soloed ones while any is soloed, otherwise the unmuted ones.
+pub fn audible_decks<const N: usize>(mutes: [bool; N], solos: [bool; N]) -> [bool; N] {
+    if solos.contains(&true) {
+        solos
+    } else {
+        mutes.map(|muted| !muted)
+    }
+}
+
+/// Crossfader travel over which [`CrossfaderCurve::SharpCut`], or any curve at
+/// full sharpness, brings a deck in.
+const SHARP_CUT_TRAVEL: f32 = 0.05;
+
+/// How the crossfader position maps to the gains of decks A and B.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrossfaderCurve {
+    /// Sine/cosine law: constant power through the middle, for blends.
+    #[default]
+    EqualPower,
+    /// Straight fade, dipping 3 dB in the middle.
+    Linear,
+    /// Both decks at full gain except the last few percent of travel, for scratching.
+    SharpCut,
+}
+
+/// How a channel fader's travel maps to its gain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FaderTaper {
+    /// Gain straight from the position.
+    Linear,
+    /// Silent at the bottom, -10 dB half way up and unity at the top, as on
+    /// a hardware mixer.
+    #[default]
+    Audio,
+}
+
+impl FaderTaper {
+    /// Gain for a fader at `position` in [0, 1].
+    pub fn gain(self, position: f32) -> f32 {
+        match self {
+            FaderTaper::Linear => position.clamp(0.0, 1.0),
+            FaderTaper::Audio => db::fader_to_linear(position),
+        }
+    }
+}
+
+/// Which side of the crossfader a deck is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum XfAssign {
+    A,
+    B,
+    /// Past the crossfader, heard through the channel fader alone.
+    Thru,
+}
+
+impl XfAssign {
+    pub const ALL: [XfAssign; 3] = [XfAssign::A, XfAssign::B, XfAssign::Thru];
+
+    /// Assignment a deck starts with: A and B on their sides, the rest thru.
+    pub fn default_for(deck: DeckId) -> Self {
+        match deck.index() {
+            0 => XfAssign::A,
+            1 => XfAssign::B,
+            _ => XfAssign::Thru,
+        }
+    }
+}
+
+/// Channels in each interleaved frame the bus mixes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelLayout {
+    #[default]
+    Stereo,
+    /// Front left and right, then rear left and right.
+    Quad,
+    /// Left, right, centre, LFE, then left and right surround.
+    Surround51,
+    /// Any other number of channels, mixed without a speaker order.
+    Discrete(usize),
+}
+
+impl ChannelLayout {
+    /// Samples in one frame.
+    pub fn channels(self) -> usize {
+        match self {
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Quad => 4,
+            ChannelLayout::Surround51 => 6,
+            ChannelLayout::Discrete(channels) => channels,
+        }
+    }
+}
+
+/// Buffers the bus refused to mix.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MixError {
+    #[error("buffer lengths differ: deck A {deck_a}, deck B {deck_b}, output {output}")]
+    LengthMismatch {
+        deck_a: usize,
+        deck_b: usize,
+        output: usize,
+    },
+    #[error("deck {deck} buffer holds {len} samples, the output {output}")]
+    DeckLength {
+        deck: DeckId,
+        len: usize,
+        output: usize,
+    },
+    #[error("{buffer} buffer holds {len} samples, the output {output}")]
+    BufferLength {
+        buffer: &'static str,
+        len: usize,
+        output: usize,
+    },
+    #[error("{0} samples is not a whole number of interleaved stereo frames")]
+    OddFrameCount(usize),
+    #[error("the bus mixes {expected} decks, {given} buffers were given")]
+    DeckCount { expected: usize, given: usize },
+    #[error("{len} samples is not a whole number of {channels}-channel frames")]
+    PartialFrame { len: usize, channels: usize },
+    #[error("a {channels}-channel bus only mixes through the general path")]
+    StereoOnly { channels: usize },
+    #[error("{buffer} channel {channel} holds {len} samples, the output {output}")]
+    ChannelLength {
+        buffer: &'static str,
+        channel: usize,
+        len: usize,
+        output: usize,
+    },
+}
+
+/// Batch the parameter queue refused.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum BatchError {
+    #[error("a batch holds at most {max} updates, {len} were given")]
+    TooLarge { len: usize, max: usize },
+    #[error("a batch cannot hold batch markers")]
+    Nested,
+    #[error("the queue has room for {free} updates, the batch needs {needed}")]
+    Full { needed: usize, free: usize },
+}
+
+/// Value of `table`, spread evenly over [0, 1], at `position`, interpolating
+/// between neighbouring entries.
+fn lookup(table: &[f32], position: f32) -> f32 {
+    let at = position.clamp(0.0, 1.0) * (table.len() - 1) as f32;
+    let index = (at as usize).min(table.len() - 2);
+    let frac = at - index as f32;
+    table[index] + (table[index + 1] - table[index]) * frac
+}
+
+/// Replace a NaN `sample` with silence and clamp an infinite one to full
+/// scale. Returns whether it was replaced.
+fn scrub(sample: &mut f32) -> bool {
+    if sample.is_finite() {
+        return false;
+    }
+    *sample = if sample.is_nan() {
+        0.0
+    } else {
+        sample.clamp(-1.0, 1.0)
+    };
+    true
+}
+
+/// Stereo frames the bus reads a deck from.
+trait StereoSource {
+    fn frame(&self, index: usize) -> [f32; 2];
+
+    /// The whole deck as interleaved samples, if it is stored that way.
+    #[cfg(feature = "simd")]
+    fn interleaved(&self) -> Option<&[f32]> {
+        None
+    }
+}
+
+/// Interleaved stereo, left then right.
+impl<T: AsRef<[f32]> + ?Sized> StereoSource for T {
+    fn frame(&self, index: usize) -> [f32; 2] {
+        let samples = &self.as_ref()[index * 2..index * 2 + 2];
+        [samples[0], samples[1]]
+    }
+
+    #[cfg(feature = "simd")]
+    fn interleaved(&self) -> Option<&[f32]> {
+        Some(self.as_ref())
+    }
+}
+
+/// Stereo frames the bus writes its master to.
+trait StereoSink {
+    fn frames(&self) -> usize;
+    fn write(&mut self, index: usize, frame: [f32; 2]);
+
+    /// The whole output as interleaved samples to overwrite, if it is one.
+    #[cfg(feature = "simd")]
+    fn interleaved_mut(&mut self) -> Option<&mut [f32]> {
+        None
+    }
+}
+
+impl StereoSink for [f32] {
+    fn frames(&self) -> usize {
+        self.len() / 2
+    }
+
+    fn write(&mut self, index: usize, frame: [f32; 2]) {
+        self[index * 2..index * 2 + 2].copy_from_slice(&frame);
+    }
+
+    #[cfg(feature = "simd")]
+    fn interleaved_mut(&mut self) -> Option<&mut [f32]> {
+        Some(self)
+    }
+}
+
+/// Interleaved stereo summed into rather than overwritten.
+struct Accumulate<'a>(&'a mut [f32]);
+
+impl StereoSink for Accumulate<'_> {
+    fn frames(&self) -> usize {
+        self.0.len() / 2
+    }
+
+    fn write(&mut self, index: usize, frame: [f32; 2]) {
+        for (sample, mixed) in self.0[index * 2..index * 2 + 2].iter_mut().zip(frame) {
+            *sample += mixed;
+        }
+    }
+}
+
+/// Interleaved stereo read and written through the same buffer, for mixing
+/// in place. Each frame is read before it is written.
+struct Cells<'a>(&'a [Cell<f32>]);
+
+impl StereoSource for Cells<'_> {
+    fn frame(&self, index: usize) -> [f32; 2] {
+        [self.0[index * 2].get(), self.0[index * 2 + 1].get()]
+    }
+}
+
+impl StereoSink for Cells<'_> {
+    fn frames(&self) -> usize {
+        self.0.len() / 2
+    }
+
+    fn write(&mut self, index: usize, frame: [f32; 2]) {
+        self.0[index * 2].set(frame[0]);
+        self.0[index * 2 + 1].set(frame[1]);
+    }
+}
+
+/// A deck of an in-place mix: the one the mix lands in, or the other.
+enum InPlace<'a> {
+    Mixed(Cells<'a>),
+    Read(&'a [f32]),
+}
+
+impl StereoSource for InPlace<'_> {
+    fn frame(&self, index: usize) -> [f32; 2] {
+        match self {
+            InPlace::Mixed(cells) => cells.frame(index),
+            InPlace::Read(samples) => samples.frame(index),
+        }
+    }
+}
+
+/// One buffer per channel, left then right.
+struct Planar<T>([T; 2]);
+
+impl StereoSource for Planar<&[f32]> {
+    fn frame(&self, index: usize) -> [f32; 2] {
+        [self.0[0][index], self.0[1][index]]
+    }
+}
+
+impl StereoSink for Planar<&mut [f32]> {
+    fn frames(&self) -> usize {
+        self.0[0].len()
+    }
+
+    fn write(&mut self, index: usize, frame: [f32; 2]) {
+        self.0[0][index] = frame[0];
+        self.0[1][index] = frame[1];
+    }
 }
 
 /// Updates that can be applied to the summing bus from a control thread.
 #[derive(Debug, Clone)]
 pub enum ParameterUpdate {
-    DeckGain { deck: DeckId, gain: f32 },
+    /// Channel fader of `deck`, as a linear gain in [0, 1].
+    DeckGain {
+        deck: DeckId,
+        gain: f32,
+    },
+    /// Channel fader of `deck` at `position` in [0, 1], turned into a gain
+    /// through the deck's [`FaderTaper`]; NaN is ignored.
+    DeckFader {
+        deck: DeckId,
+        position: f32,
+    },
+    /// Taper `deck`'s fader positions go through, re-applied to the last one.
+    DeckFaderTaper {
+        deck: DeckId,
+        taper: FaderTaper,
+    },
+    /// Silence `deck`, unless it is soloed.
+    DeckMute {
+        deck: DeckId,
+        on: bool,
+    },
+    /// Hear only the soloed decks; releasing the last solo brings back the unmuted ones.
+    DeckSolo {
+        deck: DeckId,
+        on: bool,
+    },
+    /// Send `deck` to the headphones, pre-fader and pre-crossfader.
+    DeckCue {
+        deck: DeckId,
+        on: bool,
+    },
+    /// Headphone blend in [0, 1]: 0 is the cued decks alone, 1 the master alone.
+    CueMix(f32),
+    /// Headphone level.
+    CueGain(f32),
+    /// Input trim of `deck` ahead of its EQ and fader, up to +12 dB; NaN is ignored.
+    DeckTrim {
+        deck: DeckId,
+        gain: f32,
+    },
     Crossfader(f32),
+    /// Glide the crossfader linearly to `target` over `seconds`; a NaN
+    /// target is ignored.
+    CrossfaderRamp {
+        target: f32,
+        seconds: f32,
+    },
+    /// Fade the crossfader linearly to `target`, landing on it exactly
+    /// `duration_frames` output frames on, for automated transitions. A
+    /// [`Crossfader`](Self::Crossfader) or another fade takes over from it;
+    /// a NaN target is ignored.
+    CrossfadeTo {
+        target: f32,
+        duration_frames: u64,
+    },
+    CrossfaderCurve(CrossfaderCurve),
+    /// Curve knob in [0, 1]: 0 fades over the whole travel, 1 cuts in the last few percent.
+    CrossfaderSharpness(f32),
+    /// Hamster mode: deck B on the left of the crossfader, A on the right.
+    CrossfaderReverse(bool),
+    /// Put `deck` on a side of the crossfader, or past it.
+    CrossfaderAssign {
+        deck: DeckId,
+        assign: XfAssign,
+    },
     MasterGain(f32),
+    /// Level of the booth monitor, independent of the master gain.
+    BoothGain(f32),
+    /// Master stereo width: 0 is mono, 1 unchanged and 2 the sides doubled.
+    StereoWidth(f32),
+    /// Fold the master to mono for a mono PA, the same on both outputs.
+    MonoOutput(bool),
+    /// Set a parameter of the effect inserted on `deck`.
+    DeckEffect {
+        deck: DeckId,
+        param: u32,
+        value: f32,
+    },
+    /// Set how long the echo of the [`DelayFx`](fx::DelayFx) inserted on
+    /// `deck` is; the read head crossfades to the new time.
+    FxDelayTime {
+        deck: DeckId,
+        time: DelayTime,
+    },
+    /// Feedback of the delay inserted on `deck`, clamped to [0, 0.95].
+    FxDelayFeedback {
+        deck: DeckId,
+        feedback: f32,
+    },
+    /// [Wet/dry balance](fx#wet-and-dry) of the delay inserted on `deck`.
+    FxDelayMix {
+        deck: DeckId,
+        mix: f32,
+    },
+    /// Room size in [0, 1] of the [`ReverbFx`](fx::ReverbFx) inserted on `deck`.
+    FxReverbRoomSize {
+        deck: DeckId,
+        size: f32,
+    },
+    /// High-frequency damping in the tail of the reverb inserted on `deck`, [0, 1).
+    FxReverbDamping {
+        deck: DeckId,
+        damping: f32,
+    },
+    /// [Wet/dry balance](fx#wet-and-dry) of the reverb inserted on `deck`.
+    FxReverbMix {
+        deck: DeckId,
+        mix: f32,
+    },
+    /// Sweep rate of the [`FlangerFx`](fx::FlangerFx) inserted on `deck`.
+    FxFlangerRate {
+        deck: DeckId,
+        rate: FlangerRate,
+    },
+    /// Sweep depth in [0, 1] of the flanger inserted on `deck`; 0 passes it through untouched.
+    FxFlangerDepth {
+        deck: DeckId,
+        depth: f32,
+    },
+    /// Feedback in [-0.9, 0.9] of the flanger inserted on `deck`.
+    FxFlangerFeedback {
+        deck: DeckId,
+        feedback: f32,
+    },
+    /// Lead of the right channel's sweep over the left, in [0, 1] cycles, of
+    /// the flanger inserted on `deck`.
+    FxFlangerStereoPhase {
+        deck: DeckId,
+        phase: f32,
+    },
+    /// Turn a band of `deck`'s EQ, in dB from -26 to +6.
+    DeckEq {
+        deck: DeckId,
+        band: EqBand,
+        gain_db: f32,
+    },
+    /// Kill a band of `deck`'s EQ outright, whatever its knob says.
+    DeckEqKill {
+        deck: DeckId,
+        band: EqBand,
+        on: bool,
+    },
+    /// Sweep `deck`'s filter knob: -1 is full low-pass, 0 bypass, 1 full high-pass.
+    DeckFilter {
+        deck: DeckId,
+        position: f32,
+    },
+    /// Resonance of `deck`'s filter in [0, 1].
+    DeckFilterResonance {
+        deck: DeckId,
+        resonance: f32,
+    },
+    /// Take any DC offset out of `deck`'s input.
+    DcBlock {
+        deck: DeckId,
+        on: bool,
+    },
+    /// Flip the polarity of either channel of `deck`'s input.
+    DeckPhaseInvert {
+        deck: DeckId,
+        left: bool,
+        right: bool,
+    },
+    /// Master tempo that beat-synced effects follow; `None` when unknown.
+    Tempo(Option<f32>),
+    MicGain(f32),
+    /// Hold talkover on, ducking the music while the mic is quiet too.
+    Talkover(bool),
+    /// Ceiling of the master limiter, in dBFS.
+    LimiterCeilingDb(f32),
+    /// Release of the master limiter, in ms.
+    LimiterReleaseMs(f32),
+    /// Round the master into full scale with the soft clipper after the limiter.
+    MasterSoftClip(bool),
+    /// Soft clipper drive in [0, 1]; more drive bends the master from lower down.
+    MasterSoftClipDrive(f32),
+    /// Mic low-cut corner in Hz; 0 disables it.
+    MicLowCutHz(f32),
+    /// Mic level above which the music ducks.
+    TalkoverThresholdDb(f32),
+    /// Music attenuation while the mic is open; 0 dB disables talkover.
+    TalkoverDepthDb(f32),
+    /// Zero the clip counts sent with each [`MeterFrame`].
+    ResetClipIndicators,
+    /// Restart the integrated loudness sent with each [`MeterFrame`].
+    ResetLoudness,
+    /// Start or stop copying the master into the bus's
+    /// [`set_record_tap`](SummingBus::set_record_tap) tap.
+    Record(bool),
+    /// Send the values the bus holds once every update ahead of this one is
+    /// applied, as a [`MixerSnapshot`], to the bus's
+    /// [`set_snapshots`](SummingBus::set_snapshots) queue.
+    RequestSnapshot,
+    /// Hold the updates after this one back until [`CommitBatch`](Self::CommitBatch),
+    /// then apply them together before the next block is mixed. Sent by
+    /// [`ParameterSender::send_batch`].
+    BeginBatch,
+    /// Close the batch a [`BeginBatch`](Self::BeginBatch) opened.
+    CommitBatch,
+}
+
+impl ParameterUpdate {
+    /// [`DeckGain`](Self::DeckGain) for a fader level in dB; `None` for NaN.
+    pub fn deck_gain_db(deck: DeckId, db: f32) -> Option<Self> {
+        (!db.is_nan()).then(|| ParameterUpdate::DeckGain {
+            deck,
+            gain: db::db_to_linear(db),
+        })
+    }
+
+    /// [`MasterGain`](Self::MasterGain) for a level in dB; `None` for NaN.
+    pub fn master_gain_db(db: f32) -> Option<Self> {
+        (!db.is_nan()).then(|| ParameterUpdate::MasterGain(db::db_to_linear(db)))
+    }
+
+    /// Deck the update is for, if it is for one.
+    pub fn deck(&self) -> Option<DeckId> {
+        match *self {
+            ParameterUpdate::DeckGain { deck, .. }
+            | ParameterUpdate::DeckFader { deck, .. }
+            | ParameterUpdate::DeckFaderTaper { deck, .. }
+            | ParameterUpdate::DeckMute { deck, .. }
+            | ParameterUpdate::DeckSolo { deck, .. }
+            | ParameterUpdate::DeckCue { deck, .. }
+            | ParameterUpdate::CrossfaderAssign { deck, .. }
+            | ParameterUpdate::DeckTrim { deck, .. }
+            | ParameterUpdate::DeckEffect { deck, .. }
+            | ParameterUpdate::FxDelayTime { deck, .. }
+            | ParameterUpdate::FxDelayFeedback { deck, .. }
+            | ParameterUpdate::FxDelayMix { deck, .. }
+            | ParameterUpdate::FxReverbRoomSize { deck, .. }
+            | ParameterUpdate::FxReverbDamping { deck, .. }
+            | ParameterUpdate::FxReverbMix { deck, .. }
+            | ParameterUpdate::FxFlangerRate { deck, .. }
+            | ParameterUpdate::FxFlangerDepth { deck, .. }
+            | ParameterUpdate::FxFlangerFeedback { deck, .. }
+            | ParameterUpdate::FxFlangerStereoPhase { deck, .. }
+            | ParameterUpdate::DeckEq { deck, .. }
+            | ParameterUpdate::DeckEqKill { deck, .. }
+            | ParameterUpdate::DeckFilter { deck, .. }
+            | ParameterUpdate::DeckFilterResonance { deck, .. }
+            | ParameterUpdate::DcBlock { deck, .. }
+            | ParameterUpdate::DeckPhaseInvert { deck, .. } => Some(deck),
+            _ => None,
+        }
+    }
 }
 
 /// Sender side of a lock-free parameter queue.
 #[derive(Clone)]
 pub struct ParameterSender {
     queue: Arc<ArrayQueue<ParameterUpdate>>,
+    /// Updates refused because the queue was full.
+    dropped: Arc<AtomicU64>,
 }
 
 impl ParameterSender {
     /// Enqueue a parameter update. Returns `Err` if the queue is full.
     pub fn send(&self, update: ParameterUpdate) -> Result<(), ParameterUpdate> {
-        self.queue.push(update)
+        self.queue.push(update).inspect_err(|_| {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    /// Enqueue all of `updates` or none of them, between a
+    /// [`BeginBatch`](ParameterUpdate::BeginBatch) and a
+    /// [`CommitBatch`](ParameterUpdate::CommitBatch), so the bus applies them
+    /// together and never mixes a block with only some of them. Refuses more
+    /// than [`MAX_BATCH_UPDATES`], batch markers among `updates`, and batches
+    /// the queue has no room for.
+    ///
+    /// Assumes this is the only sender pushing at the time.
+    pub fn send_batch(&self, updates: &[ParameterUpdate]) -> Result<(), BatchError> {
+        if updates.len() > MAX_BATCH_UPDATES {
+            return Err(BatchError::TooLarge {
+                len: updates.len(),
+                max: MAX_BATCH_UPDATES,
+            });
+        }
+        if updates.iter().any(|update| {
+            matches!(
+                update,
+                ParameterUpdate::BeginBatch | ParameterUpdate::CommitBatch
+            )
+        }) {
+            return Err(BatchError::Nested);
+        }
+        let (needed, free) = (updates.len() + 2, self.queue.capacity() - self.queue.len());
+        if free < needed {
+            self.dropped
+                .fetch_add(updates.len() as u64, Ordering::Relaxed);
+            return Err(BatchError::Full { needed, free });
+        }
+        let updates = updates.iter().cloned();
+        let batch = std::iter::once(ParameterUpdate::BeginBatch)
+            .chain(updates)
+            .chain(std::iter::once(ParameterUpdate::CommitBatch));
+        for update in batch {
+            // Room was checked above, and the audio thread only ever frees slots.
+            let _ = self.queue.push(update);
+        }
+        Ok(())
+    }
+
+    /// Updates refused so far because the queue was full, across all clones of this sender.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
     }
 }
 
 /// Receiver side of a lock-free parameter queue.
+#[derive(Debug)]
 pub struct ParameterReceiver {
     queue: Arc<ArrayQueue<ParameterUpdate>>,
 }
@@ -40,6 +772,40 @@ impl ParameterReceiver {
     }
 }
 
+/// Where a [`SummingBus`] takes its parameter updates from, at the start of
+/// each block on the audio thread; neither method may block or allocate.
+///
+/// The bus takes a [`ParameterReceiver`] queue, a
+/// [`ParamCellReceiver`](cell::ParamCellReceiver) cell, or the two together
+/// as a pair, the first drained ahead of the second.
+pub trait ParameterSource: Send + std::fmt::Debug {
+    /// Next update to apply, if any.
+    fn next_update(&mut self) -> Option<ParameterUpdate>;
+
+    /// Updates waiting to be applied.
+    fn pending(&self) -> usize;
+}
+
+impl ParameterSource for ParameterReceiver {
+    fn next_update(&mut self) -> Option<ParameterUpdate> {
+        self.pop()
+    }
+
+    fn pending(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+impl<A: ParameterSource, B: ParameterSource> ParameterSource for (A, B) {
+    fn next_update(&mut self) -> Option<ParameterUpdate> {
+        self.0.next_update().or_else(|| self.1.next_update())
+    }
+
+    fn pending(&self) -> usize {
+        self.0.pending() + self.1.pending()
+    }
+}
+
 /// Create a bounded, lock-free channel for parameter updates.
 ///
 /// The sender is intended to be owned by a control thread, while the receiver
@@ -49,127 +815,3224 @@ pub fn parameter_channel(capacity: usize) -> (ParameterSender, ParameterReceiver
     (
         ParameterSender {
             queue: queue.clone(),
+            dropped: Arc::new(AtomicU64::new(0)),
         },
         ParameterReceiver { queue },
     )
 }
 
-/// Summing bus that mixes two stereo decks with an equal-power crossfader and gain stages.
+/// Create a lock-free ring of stereo frames for recording the master, with
+/// room for `capacity_frames`, allocated up front: the tap goes to the bus
+/// through [`SummingBus::set_record_tap`] and the consumer to the thread
+/// writing the audio out. The counter tracks blocks dropped as overruns
+/// because the consumer fell behind.
+pub fn audio_ring_buffer(capacity_frames: usize) -> (RecordTap, RingConsumer, Arc<AtomicU64>) {
+    ring::tee(capacity_frames, 2)
+}
+
+/// Sender side of a lock-free queue of controller commands for the decks.
+#[derive(Clone)]
+pub struct DeckCommandSender {
+    queue: Arc<ArrayQueue<(DeckId, DeckCommand)>>,
+}
+
+impl DeckCommandSender {
+    /// Enqueue a command for `deck`. Returns `Err` if the queue is full.
+    pub fn send(&self, deck: DeckId, command: DeckCommand) -> Result<(), (DeckId, DeckCommand)> {
+        self.queue.push((deck, command))
+    }
+}
+
+/// Receiver side of a deck command queue, drained by whoever owns the decks.
 #[derive(Debug)]
-pub struct SummingBus {
-    deck_gains: [f32; 2],
-    crossfader: f32,
-    master_gain: f32,
-    params: ParameterReceiver,
+pub struct DeckCommandReceiver {
+    queue: Arc<ArrayQueue<(DeckId, DeckCommand)>>,
 }
 
-impl SummingBus {
-    /// Create a summing bus with unity gains and centered crossfader.
-    pub fn new(params: ParameterReceiver) -> Self {
+impl DeckCommandReceiver {
+    pub fn pop(&self) -> Option<(DeckId, DeckCommand)> {
+        self.queue.pop()
+    }
+}
+
+/// Create a bounded, lock-free channel for deck commands.
+pub fn deck_command_channel(capacity: usize) -> (DeckCommandSender, DeckCommandReceiver) {
+    let queue = Arc::new(ArrayQueue::new(capacity));
+    (
+        DeckCommandSender {
+            queue: queue.clone(),
+        },
+        DeckCommandReceiver { queue },
+    )
+}
+
+/// Reads the bus's post-fader deck gains from another thread without locking.
+#[derive(Debug, Clone)]
+pub struct FaderReader {
+    gains: Arc<[AtomicU32]>,
+}
+
+impl FaderReader {
+    fn new(decks: usize) -> Self {
         Self {
-            deck_gains: [1.0, 1.0],
-            crossfader: 0.5,
-            master_gain: 1.0,
-            params,
+            gains: (0..decks).map(|_| AtomicU32::new(0)).collect(),
         }
     }
 
-    /// Apply any pending parameter changes from the control thread.
-    fn drain_updates(&mut self) {
-        while let Some(update) = self.params.pop() {
-            match update {
-                ParameterUpdate::DeckGain { deck, gain } => {
-                    let idx = deck as usize;
-                    self.deck_gains[idx] = gain.max(0.0);
-                }
-                ParameterUpdate::Crossfader(value) => {
-                    self.crossfader = value.clamp(0.0, 1.0);
-                }
-                ParameterUpdate::MasterGain(value) => {
-                    self.master_gain = value.max(0.0);
+    /// Gain decks A and B reached the master with at the end of the last mixed block:
+    /// trim, channel fader, crossfader and master gain, without talkover ducking.
+    pub fn gains(&self) -> [f32; 2] {
+        [DeckId::A, DeckId::B].map(|deck| self.gain(deck).unwrap_or(0.0))
+    }
+
+    /// Like [`gains`](Self::gains), for any deck; `None` past the last one.
+    pub fn gain(&self, deck: DeckId) -> Option<f32> {
+        let slot = self.gains.get(deck.index())?;
+        Some(f32::from_bits(slot.load(Ordering::Relaxed)))
+    }
+
+    fn publish(&self, deck: usize, gain: f32) {
+        self.gains[deck].store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// One-pole high-pass taking DC offset out of a stereo input.
+#[derive(Debug, Clone, Copy)]
+struct DcBlocker {
+    coeff: f32,
+    /// Previous input and output, per channel.
+    state: [(f32, f32); 2],
+}
+
+impl DcBlocker {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            coeff: (-std::f32::consts::TAU * DC_BLOCK_HZ / sample_rate as f32).exp(),
+            state: [(0.0, 0.0); 2],
+        }
+    }
+
+    fn tick(&mut self, frame: [f32; 2]) -> [f32; 2] {
+        let mut out = [0.0; 2];
+        for ((out, x), (last_in, last_out)) in out.iter_mut().zip(frame).zip(&mut self.state) {
+            // y[n] = x[n] - x[n-1] + a * y[n-1]
+            *out = x - *last_in + self.coeff * *last_out;
+            *last_in = x;
+            *last_out = *out;
+        }
+        out
+    }
+}
+
+/// Where the bus is in a batch of updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchState {
+    Closed,
+    /// Holding updates until the commit.
+    Open,
+    /// Past [`MAX_BATCH_UPDATES`], dropping the rest until the commit.
+    Overflowed,
+}
+
+/// Frames an insert is handed at most, however long the block mixed.
+const INSERT_BLOCK_FRAMES: usize = 1_024;
+
+/// A [`Processor`] in a deck's path, with room to run the deck through it.
+struct Insert {
+    processor: Box<dyn Processor + Send>,
+    /// The stretch of the deck last run through the processor.
+    buffer: Vec<f32>,
+    /// Frames of the block in progress held in `buffer`.
+    stretch: Range<usize>,
+}
+
+impl Insert {
+    fn new(mut processor: Box<dyn Processor + Send>, sample_rate: u32) -> Self {
+        processor.prepare(sample_rate, INSERT_BLOCK_FRAMES);
+        Self {
+            processor,
+            buffer: vec![0.0; INSERT_BLOCK_FRAMES * 2],
+            stretch: 0..0,
+        }
+    }
+
+    /// Frame `index` of `input` after the processor. A block starts a new
+    /// stretch, as does any frame past the last one, running up to `end` or
+    /// [`INSERT_BLOCK_FRAMES`] on through the processor. While `scrubbed`
+    /// counts them, NaN and infinite samples are scrubbed on the way in, so
+    /// none lodge in the processor's state.
+    fn frame<D: StereoSource>(
+        &mut self,
+        input: &D,
+        index: usize,
+        end: usize,
+        scrubbed: Option<&mut u64>,
+    ) -> [f32; 2] {
+        if index == 0 || !self.stretch.contains(&index) {
+            let len = (end - index).min(INSERT_BLOCK_FRAMES);
+            let stretch = &mut self.buffer[..len * 2];
+            for (frame, out) in stretch.chunks_exact_mut(2).enumerate() {
+                out.copy_from_slice(&input.frame(index + frame));
+            }
+            if let Some(scrubbed) = scrubbed {
+                for sample in stretch.iter_mut() {
+                    *scrubbed += scrub(sample) as u64;
                 }
             }
+            self.processor.process(stretch);
+            self.stretch = index..index + len;
         }
+        let offset = (index - self.stretch.start) * 2;
+        [self.buffer[offset], self.buffer[offset + 1]]
     }
+}
 
-    /// Calculate equal-power crossfader gains for decks A and B.
-    fn crossfader_gains(&self) -> (f32, f32) {
-        // Map [0, 1] -> [0, PI/2] for equal-power sine/cosine curve.
-        let theta = self.crossfader * std::f32::consts::FRAC_PI_2;
-        (theta.cos(), theta.sin())
+impl std::fmt::Debug for Insert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Insert").finish_non_exhaustive()
     }
+}
 
-    /// Mix two interleaved stereo buffers into the provided output buffer.
-    ///
-    /// The method drains pending parameter updates, applies per-deck gains,
-    /// crossfader scaling, and a master gain to each frame. All buffers must
-    /// share the same length and contain interleaved stereo samples.
-    pub fn mix_stereo(&mut self, deck_a: &[f32], deck_b: &[f32], output: &mut [f32]) {
-        assert_eq!(
-            deck_a.len(),
-            deck_b.len(),
-            "Deck buffers must have equal length"
-        );
-        assert_eq!(
-            deck_a.len(),
-            output.len(),
-            "Output buffer must match deck length"
-        );
-        assert!(
-            deck_a.len() % 2 == 0,
-            "Buffers must contain interleaved stereo frames"
-        );
+/// Crossfader fade started by [`ParameterUpdate::CrossfadeTo`].
+#[derive(Debug, Clone, Copy)]
+struct Crossfade {
+    from: f32,
+    target: f32,
+    duration: u64,
+    /// Frames of the fade mixed so far.
+    elapsed: u64,
+}
 
-        self.drain_updates();
-        let (xf_a, xf_b) = self.crossfader_gains();
-        let deck_a_gain = self.deck_gains[0] * xf_a * self.master_gain;
-        let deck_b_gain = self.deck_gains[1] * xf_b * self.master_gain;
-
-        for (((out_l, out_r), a_frame), b_frame) in output
-            .chunks_exact_mut(2)
-            .zip(deck_a.chunks_exact(2))
-            .zip(deck_b.chunks_exact(2))
-        {
-            *out_l = a_frame[0] * deck_a_gain + b_frame[0] * deck_b_gain;
-            *out_r = a_frame[1] * deck_a_gain + b_frame[1] * deck_b_gain;
+impl Crossfade {
+    /// Position `elapsed` frames in.
+    fn position(&self) -> f32 {
+        if self.elapsed >= self.duration {
+            self.target
+        } else {
+            let done = self.elapsed as f64 / self.duration as f64;
+            self.from + (self.target - self.from) * done as f32
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::thread;
+/// Everything the bus keeps for one deck, from the effect insert to the fader.
+#[derive(Debug)]
+struct Channel {
+    trim: f32,
+    gain: f32,
+    taper: FaderTaper,
+    /// Fader position the gain was last set from, until a raw gain replaces it.
+    fader: Option<f32>,
+    mute: bool,
+    solo: bool,
+    cue: bool,
+    smoothed_trim: Smoothed,
+    smoothed_gain: Smoothed,
+    /// Fades the deck in and out as the mutes and solos change.
+    on: Smoothed,
+    /// Fades the deck in and out of the headphones.
+    cue_on: Smoothed,
+    assign: XfAssign,
+    /// How much of each side of [`XfAssign::ALL`] the deck follows, so a
+    /// reassignment fades across rather than jumping.
+    sides: [Smoothed; 3],
+    /// Custom processor the deck runs through first as it is mixed.
+    insert: Option<Insert>,
+    /// Pre-fader effect insert.
+    fx: Option<Box<dyn Fx>>,
+    /// 3-band EQ, ahead of the fader.
+    eq: DeckEq,
+    /// Sweepable filter, after the effect insert.
+    filter: FilterFx,
+    dc_block: bool,
+    dc_blocker: DcBlocker,
+    /// Fades between the raw and DC-blocked input as the blocker is switched.
+    dc_on: Smoothed,
+    /// Left and right inverted.
+    phase_invert: [bool; 2],
+    /// Sign applied to each input channel, sweeping through 0 on a flip.
+    polarity: [Smoothed; 2],
+    /// Knob position last applied to the filter.
+    filter_position: f32,
+    /// Trim and fader gain of the frame being mixed, from [`next_gains`](Self::next_gains).
+    gains: (f32, f32),
+    /// Gain the deck reached the master with on the last frame, before the master gain.
+    heard: f32,
+    /// Samples past full scale after the fader.
+    clips: u64,
+    /// Levels metered over the current block.
+    levels: Levels,
+    /// Metered peak, held for the bus's hold time.
+    peak_hold: PeakHold,
+}
 
-    fn approx_eq(a: f32, b: f32) {
-        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+impl Channel {
+    fn new(assign: XfAssign, sample_rate: u32) -> Self {
+        Self {
+            trim: 1.0,
+            gain: 1.0,
+            taper: FaderTaper::default(),
+            fader: None,
+            mute: false,
+            solo: false,
+            cue: false,
+            smoothed_trim: Smoothed::with_seconds(1.0, 0.0, sample_rate),
+            smoothed_gain: Smoothed::with_seconds(1.0, 0.0, sample_rate),
+            on: Smoothed::with_seconds(1.0, MUTE_FADE_SECONDS, sample_rate),
+            cue_on: Smoothed::with_seconds(0.0, MUTE_FADE_SECONDS, sample_rate),
+            assign,
+            sides: XfAssign::ALL.map(|side| {
+                let weight = if side == assign { 1.0 } else { 0.0 };
+                Smoothed::with_seconds(weight, MUTE_FADE_SECONDS, sample_rate)
+            }),
+            insert: None,
+            fx: None,
+            eq: DeckEq::new(sample_rate),
+            filter: FilterFx::new(sample_rate),
+            filter_position: 0.0,
+            dc_block: false,
+            dc_blocker: DcBlocker::new(sample_rate),
+            dc_on: Smoothed::with_seconds(0.0, MUTE_FADE_SECONDS, sample_rate),
+            phase_invert: [false; 2],
+            polarity: [(); 2].map(|_| Smoothed::with_seconds(1.0, MUTE_FADE_SECONDS, sample_rate)),
+            gains: (1.0, 1.0),
+            heard: 0.0,
+            clips: 0,
+            levels: Levels::default(),
+            peak_hold: PeakHold::default(),
+        }
     }
 
-    #[test]
-    fn equal_power_crossfader() {
-        let (_, rx) = parameter_channel(4);
-        let mut bus = SummingBus::new(rx);
+    /// Gain the crossfader gives the deck, from the side gains `(a, b)`.
+    fn crossfader_share(&self, (a, b): (f32, f32)) -> f32 {
+        let [on_a, on_b, thru] = self.sides.map(|side| side.current());
+        on_a * a + on_b * b + thru
+    }
 
-        bus.crossfader = 0.0;
-        let (a, b) = bus.crossfader_gains();
-        approx_eq(a, 1.0);
-        approx_eq(b, 0.0);
+    /// Like [`crossfader_share`](Self::crossfader_share), a frame further into
+    /// any reassignment fade.
+    fn next_crossfader_share(&mut self, (a, b): (f32, f32)) -> f32 {
+        let [on_a, on_b, thru] = self.sides.each_mut().map(|side| side.next());
+        on_a * a + on_b * b + thru
+    }
 
-        bus.crossfader = 0.5;
-        let (a, b) = bus.crossfader_gains();
-        approx_eq(a, std::f32::consts::FRAC_1_SQRT_2);
-        approx_eq(b, std::f32::consts::FRAC_1_SQRT_2);
+    /// Trim, and fader gain with mute, solo and crossfader, for the next frame.
+    fn next_gains(&mut self, crossfader: (f32, f32)) -> (f32, f32) {
+        let trim = self.smoothed_trim.next();
+        let gain =
+            self.smoothed_gain.next() * self.on.next() * self.next_crossfader_share(crossfader);
+        self.heard = gain * trim;
+        (trim, gain)
+    }
+}
 
-        bus.crossfader = 1.0;
-        let (a, b) = bus.crossfader_gains();
-        approx_eq(a, 0.0);
-        approx_eq(b, 1.0);
+/// Summing bus that mixes stereo decks with an equal-power crossfader and gain stages.
+///
+/// Decks are addressed by [`DeckId`]; by default the crossfader fades between
+/// the first two, and any further decks reach the master through their faders
+/// alone, until [`ParameterUpdate::CrossfaderAssign`] moves them.
+#[derive(Debug)]
+pub struct SummingBus {
+    channels: Vec<Channel>,
+    layout: ChannelLayout,
+    cue_mix: f32,
+    cue_gain: f32,
+    crossfader: f32,
+    /// Crossfader target and per-frame step while a ramp is running.
+    crossfader_ramp: Option<(f32, f32)>,
+    /// Automated crossfade under way.
+    crossfade: Option<Crossfade>,
+    crossfader_curve: CrossfaderCurve,
+    crossfader_sharpness: f32,
+    crossfader_reverse: bool,
+    /// Side gains last worked out, and the position they are for.
+    crossfader_cache: Option<(f32, (f32, f32))>,
+    /// Set when the curve or sharpness changes under the cache.
+    crossfader_dirty: bool,
+    /// Equal-power gain of deck B across the travel, looked up instead of
+    /// taking a sine per position.
+    crossfader_table: Option<Vec<f32>>,
+    /// Times the side gains were worked out afresh.
+    #[cfg(test)]
+    crossfader_recomputes: u64,
+    master_gain: f32,
+    booth_gain: f32,
+    /// Master stereo width, always gliding so a change never clicks.
+    width: Smoothed,
+    /// Fades the master between stereo and the mono fold.
+    mono: Smoothed,
+    /// Time constant the gains below glide towards the values above with; 0 is instant.
+    smoothing_seconds: f32,
+    smoothed_crossfader: Smoothed,
+    smoothed_master: Smoothed,
+    smoothed_booth: Smoothed,
+    sample_rate: u32,
+    /// Mic strip summed after the talkover duck.
+    mic: MicChannel,
+    /// Mic-keyed compressor on the summed decks, used instead of the fixed talkover duck.
+    sidechain: Option<SidechainCompressor>,
+    /// Brickwall limiter after the master gain.
+    limiter: Option<Limiter>,
+    /// Waveshaper after the limiter, applied while `soft_clip_on`.
+    soft_clip: SoftClip,
+    soft_clip_on: bool,
+    /// One-shots summed after the crossfader.
+    sampler: Option<Sampler>,
+    /// Where the master goes for a spectrum display.
+    spectrum: Option<SpectrumTap>,
+    faders: FaderReader,
+    /// Opt-in log of the values applied, stamped with the output frame.
+    automation: Option<AutomationTap>,
+    /// Where the levels of each mixed block go, if anywhere.
+    meter: Option<MeterSender>,
+    /// Where requested snapshots go, if anywhere.
+    snapshots: Option<SnapshotSender>,
+    /// Ring the master is copied into while `recording`.
+    record: Option<RecordTap>,
+    recording: bool,
+    peak_hold_frames: u64,
+    master_peak_hold: PeakHold,
+    /// Master samples past full scale ahead of the limiter.
+    master_clips: u64,
+    /// BS.1770 loudness of the master, run while metering.
+    loudness: LoudnessMeter,
+    /// Replace NaN and infinite deck samples as they are read.
+    sanitize_input: bool,
+    /// Deck samples replaced so far.
+    scrubbed: u64,
+    /// Flush denormals to zero while mixing.
+    denormal_protection: bool,
+    /// Most parameter updates applied per block; the rest wait for the next.
+    updates_per_block: usize,
+    /// Updates left queued behind the cap, summed over the blocks that hit it.
+    deferred_updates: u64,
+    /// Updates of the open batch, with room for the largest batch.
+    batch: Vec<ParameterUpdate>,
+    batch_state: BatchState,
+    /// Updates timed to the frame, applied mid-block as their frames come up.
+    schedule: Option<ScheduleReceiver>,
+    /// Next scheduled update, held until its frame.
+    scheduled: Option<ScheduledUpdate>,
+    /// Output frames mixed so far.
+    frame: u64,
+    params: Box<dyn ParameterSource>,
+}
+
+impl SummingBus {
+    /// Create a two-deck summing bus with unity gains and centered crossfader.
+    pub fn new(params: impl ParameterSource + 'static) -> Self {
+        Self::with_decks(2, params)
     }
 
-    #[test]
-    fn mixes_with_all_gain_stages() {
-        let (tx, rx) = parameter_channel(8);
-        let mut bus = SummingBus::new(rx);
+    /// Like [`new`](Self::new), with `decks` channels; updates for decks past
+    /// the last are ignored.
+    pub fn with_decks(decks: usize, params: impl ParameterSource + 'static) -> Self {
+        Self {
+            channels: (0..decks)
+                .map(|deck| Channel::new(XfAssign::default_for(DeckId(deck)), 48_000))
+                .collect(),
+            layout: ChannelLayout::Stereo,
+            cue_mix: 0.0,
+            cue_gain: 1.0,
+            crossfader: 0.5,
+            crossfader_ramp: None,
+            crossfade: None,
+            crossfader_curve: CrossfaderCurve::EqualPower,
+            crossfader_sharpness: 0.0,
+            crossfader_reverse: false,
+            crossfader_cache: None,
+            crossfader_dirty: true,
+            crossfader_table: None,
+            #[cfg(test)]
+            crossfader_recomputes: 0,
+            master_gain: 1.0,
+            booth_gain: 1.0,
+            width: Smoothed::with_seconds(1.0, MUTE_FADE_SECONDS, 48_000),
+            mono: Smoothed::with_seconds(0.0, MUTE_FADE_SECONDS, 48_000),
+            smoothing_seconds: 0.0,
+            smoothed_crossfader: Smoothed::with_seconds(0.5, 0.0, 48_000),
+            smoothed_master: Smoothed::with_seconds(1.0, 0.0, 48_000),
+            smoothed_booth: Smoothed::with_seconds(1.0, 0.0, 48_000),
+            sample_rate: 48_000,
+            mic: MicChannel::new(48_000),
+            sidechain: None,
+            limiter: None,
+            soft_clip: SoftClip::default(),
+            soft_clip_on: false,
+            sampler: None,
+            spectrum: None,
+            faders: FaderReader::new(decks),
+            automation: None,
+            meter: None,
+            snapshots: None,
+            record: None,
+            recording: false,
+            peak_hold_frames: DEFAULT_PEAK_HOLD_FRAMES,
+            master_peak_hold: PeakHold::default(),
+            master_clips: 0,
+            loudness: loudness_meter(48_000).0,
+            sanitize_input: false,
+            scrubbed: 0,
+            denormal_protection: true,
+            updates_per_block: DEFAULT_UPDATES_PER_BLOCK,
+            deferred_updates: 0,
+            batch: Vec::with_capacity(MAX_BATCH_UPDATES),
+            batch_state: BatchState::Closed,
+            schedule: None,
+            scheduled: None,
+            frame: 0,
+            params: Box::new(params),
+        }
+    }
+
+    /// Number of decks the bus mixes.
+    pub fn deck_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Mix frames of `layout` through [`mix`](Self::mix) instead of stereo ones.
+    ///
+    /// Gains, mute, solo, the crossfader, the master gain and the soft clipper
+    /// apply to every channel alike. The EQ, effects, inserts, width, mono
+    /// fold, mic, sampler, limiter, meters, cue and booth are stereo only, and beyond
+    /// stereo the other mixing methods refuse with [`MixError::StereoOnly`].
+    pub fn with_channel_layout(mut self, layout: ChannelLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn channel_layout(&self) -> ChannelLayout {
+        self.layout
+    }
+
+    /// Rebuild the per-deck filters and mic strip for the output `sample_rate`.
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.prepare(sample_rate);
+        self
+    }
+
+    /// Like [`with_sample_rate`](Self::with_sample_rate), in place; call before
+    /// mixing, never from the audio callback.
+    pub fn prepare(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        for channel in &mut self.channels {
+            channel.on.set_seconds(MUTE_FADE_SECONDS, sample_rate);
+            channel.cue_on.set_seconds(MUTE_FADE_SECONDS, sample_rate);
+            channel.dc_on.set_seconds(MUTE_FADE_SECONDS, sample_rate);
+            for polarity in &mut channel.polarity {
+                polarity.set_seconds(MUTE_FADE_SECONDS, sample_rate);
+            }
+            channel.dc_blocker = DcBlocker::new(sample_rate);
+            for side in &mut channel.sides {
+                side.set_seconds(MUTE_FADE_SECONDS, sample_rate);
+            }
+            channel.eq.prepare(sample_rate);
+            channel.filter = FilterFx::new(sample_rate);
+            if let Some(insert) = &mut channel.insert {
+                insert.processor.prepare(sample_rate, INSERT_BLOCK_FRAMES);
+            }
+        }
+        self.width.set_seconds(MUTE_FADE_SECONDS, sample_rate);
+        self.mono.set_seconds(MUTE_FADE_SECONDS, sample_rate);
+        self.mic = MicChannel::new(sample_rate);
+        if let Some(sidechain) = &mut self.sidechain {
+            sidechain.prepare(sample_rate);
+        }
+        if let Some(limiter) = &mut self.limiter {
+            limiter.prepare(sample_rate);
+        }
+        self.loudness.prepare(sample_rate);
+        self.set_smoothing_ms(self.smoothing_seconds * 1_000.0);
+    }
+
+    /// Start with `curve` on the crossfader instead of equal power.
+    pub fn with_crossfader_curve(mut self, curve: CrossfaderCurve) -> Self {
+        self.crossfader_curve = curve;
+        self.crossfader_dirty = true;
+        self
+    }
+
+    /// Start with the crossfader curve knob at `sharpness` in [0, 1].
+    pub fn with_crossfader_sharpness(mut self, sharpness: f32) -> Self {
+        self.crossfader_sharpness = sharpness.clamp(0.0, 1.0);
+        self.crossfader_dirty = true;
+        self
+    }
+
+    /// Look the equal-power curve up in a table of `entries` points across the
+    /// travel, interpolating between them, instead of taking a sine whenever the
+    /// position moves; cheaper through glides and ramps, within 0.001 of the
+    /// exact gain at 1024 entries. Fewer than 2 entries leaves the table out.
+    pub fn with_crossfader_table(mut self, entries: usize) -> Self {
+        self.crossfader_table = (entries >= 2).then(|| vec![0.0; entries]);
+        self.crossfader_dirty = true;
+        self
+    }
+
+    /// Glide deck trims and gains, crossfader, master and booth gain towards new values
+    /// with a time constant of `ms`, instead of stepping on the next frame. Off (0)
+    /// by default.
+    pub fn set_smoothing_ms(&mut self, ms: f32) {
+        self.smoothing_seconds = ms.max(0.0) / 1_000.0;
+        for smoothed in self
+            .channels
+            .iter_mut()
+            .flat_map(|channel| [&mut channel.smoothed_trim, &mut channel.smoothed_gain])
+            .chain([
+                &mut self.smoothed_crossfader,
+                &mut self.smoothed_master,
+                &mut self.smoothed_booth,
+            ])
+        {
+            smoothed.set_seconds(self.smoothing_seconds, self.sample_rate);
+        }
+    }
+
+    pub fn is_muted(&self, deck: DeckId) -> bool {
+        self.channel(deck).is_some_and(|channel| channel.mute)
+    }
+
+    pub fn is_soloed(&self, deck: DeckId) -> bool {
+        self.channel(deck).is_some_and(|channel| channel.solo)
+    }
+
+    pub fn is_cued(&self, deck: DeckId) -> bool {
+        self.channel(deck).is_some_and(|channel| channel.cue)
+    }
+
+    /// Whether `deck` is heard once any fade settles, given the mutes and solos.
+    pub fn is_audible(&self, deck: DeckId) -> bool {
+        let soloing = self.channels.iter().any(|channel| channel.solo);
+        self.channel(deck)
+            .is_some_and(|channel| if soloing { channel.solo } else { !channel.mute })
+    }
+
+    fn channel(&self, deck: DeckId) -> Option<&Channel> {
+        self.channels.get(deck.index())
+    }
+
+    /// Apply pending parameter changes from the control thread, up to the
+    /// per-block cap.
+    ///
+    /// A batch is applied whole once its commit arrives, however far that
+    /// carries past the cap, and held over to the next block while the commit
+    /// is still on its way.
+    fn drain_updates(&mut self) {
+        let mut applied = 0;
+        while applied < self.updates_per_block || self.batch_state != BatchState::Closed {
+            let Some(update) = self.params.next_update() else {
+                return;
+            };
+            match (update, self.batch_state) {
+                // A batch left open is abandoned for the one starting.
+                (ParameterUpdate::BeginBatch, _) => {
+                    self.batch.clear();
+                    self.batch_state = BatchState::Open;
+                }
+                (ParameterUpdate::CommitBatch, state) => {
+                    let mut batch = std::mem::take(&mut self.batch);
+                    if state == BatchState::Open {
+                        applied += batch.len();
+                        for update in batch.drain(..) {
+                            self.apply(update);
+                        }
+                    }
+                    batch.clear();
+                    self.batch = batch;
+                    self.batch_state = BatchState::Closed;
+                }
+                (update, BatchState::Open) => {
+                    if self.batch.len() < MAX_BATCH_UPDATES {
+                        self.batch.push(update);
+                    } else {
+                        self.batch_state = BatchState::Overflowed;
+                    }
+                }
+                (_, BatchState::Overflowed) => {}
+                (update, BatchState::Closed) => {
+                    self.apply(update);
+                    applied += 1;
+                }
+            }
+        }
+        self.deferred_updates += self.params.pending() as u64;
+    }
+
+    /// Apply one parameter change.
+    fn apply(&mut self, update: ParameterUpdate) {
+        match update {
+            ParameterUpdate::DeckGain { deck, gain } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.gain = gain.clamp(0.0, 1.0);
+                    channel.fader = None;
+                }
+            }
+            ParameterUpdate::DeckFader { deck, position } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    if !position.is_nan() {
+                        let position = position.clamp(0.0, 1.0);
+                        channel.gain = channel.taper.gain(position);
+                        channel.fader = Some(position);
+                    }
+                }
+            }
+            ParameterUpdate::DeckFaderTaper { deck, taper } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.taper = taper;
+                    if let Some(position) = channel.fader {
+                        channel.gain = taper.gain(position);
+                    }
+                }
+            }
+            ParameterUpdate::DeckMute { deck, on } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.mute = on;
+                }
+            }
+            ParameterUpdate::DeckSolo { deck, on } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.solo = on;
+                }
+            }
+            ParameterUpdate::DeckCue { deck, on } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.cue = on;
+                }
+            }
+            ParameterUpdate::CueMix(mix) => self.cue_mix = mix.clamp(0.0, 1.0),
+            ParameterUpdate::CueGain(gain) => self.cue_gain = gain.max(0.0),
+            ParameterUpdate::DeckTrim { deck, gain } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    if !gain.is_nan() {
+                        channel.trim = gain.clamp(0.0, MAX_TRIM);
+                    }
+                }
+            }
+            ParameterUpdate::Crossfader(value) => {
+                self.crossfader = value.clamp(0.0, 1.0);
+                self.crossfader_ramp = None;
+                self.crossfade = None;
+            }
+            ParameterUpdate::CrossfaderRamp { target, seconds } => {
+                if !target.is_nan() {
+                    let target = target.clamp(0.0, 1.0);
+                    let frames = (seconds * self.sample_rate as f32).max(1.0);
+                    self.crossfader_ramp = Some((target, (target - self.crossfader) / frames));
+                    self.crossfade = None;
+                }
+            }
+            ParameterUpdate::CrossfadeTo {
+                target,
+                duration_frames,
+            } => {
+                if !target.is_nan() {
+                    let target = target.clamp(0.0, 1.0);
+                    self.crossfader_ramp = None;
+                    self.crossfade = None;
+                    if duration_frames == 0 {
+                        self.crossfader = target;
+                    } else {
+                        self.crossfade = Some(Crossfade {
+                            from: self.crossfader,
+                            target,
+                            duration: duration_frames,
+                            elapsed: 0,
+                        });
+                    }
+                }
+            }
+            ParameterUpdate::CrossfaderCurve(curve) => {
+                self.crossfader_curve = curve;
+                self.crossfader_dirty = true;
+            }
+            ParameterUpdate::CrossfaderSharpness(sharpness) => {
+                self.crossfader_sharpness = sharpness.clamp(0.0, 1.0);
+                self.crossfader_dirty = true;
+            }
+            ParameterUpdate::CrossfaderReverse(reverse) => self.crossfader_reverse = reverse,
+            ParameterUpdate::CrossfaderAssign { deck, assign } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.assign = assign;
+                }
+            }
+            ParameterUpdate::MasterGain(value) => {
+                self.master_gain = value.max(0.0);
+            }
+            ParameterUpdate::BoothGain(value) => self.booth_gain = value.max(0.0),
+            ParameterUpdate::StereoWidth(width) => {
+                if !width.is_nan() {
+                    self.width.set(width.clamp(0.0, 2.0));
+                }
+            }
+            ParameterUpdate::MonoOutput(on) => self.mono.set(if on { 1.0 } else { 0.0 }),
+            ParameterUpdate::DeckEffect { deck, param, value } => {
+                if let Some(fx) = self
+                    .channels
+                    .get_mut(deck.index())
+                    .and_then(|channel| channel.fx.as_mut())
+                {
+                    fx.set_param(param, value);
+                }
+            }
+            ParameterUpdate::FxDelayTime { deck, .. }
+            | ParameterUpdate::FxDelayFeedback { deck, .. }
+            | ParameterUpdate::FxDelayMix { deck, .. }
+            | ParameterUpdate::FxReverbRoomSize { deck, .. }
+            | ParameterUpdate::FxReverbDamping { deck, .. }
+            | ParameterUpdate::FxReverbMix { deck, .. }
+            | ParameterUpdate::FxFlangerRate { deck, .. }
+            | ParameterUpdate::FxFlangerDepth { deck, .. }
+            | ParameterUpdate::FxFlangerFeedback { deck, .. }
+            | ParameterUpdate::FxFlangerStereoPhase { deck, .. } => {
+                if let Some(insert) = self
+                    .channels
+                    .get_mut(deck.index())
+                    .and_then(|channel| channel.insert.as_mut())
+                {
+                    insert.processor.apply(&update);
+                }
+            }
+            ParameterUpdate::DeckEq {
+                deck,
+                band,
+                gain_db,
+            } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.eq.set_gain_db(band, gain_db);
+                }
+            }
+            ParameterUpdate::DeckEqKill { deck, band, on } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.eq.set_kill(band, on);
+                }
+            }
+            ParameterUpdate::DeckFilter { deck, position } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    let position = position.clamp(-1.0, 1.0);
+                    channel.filter_position = position;
+                    channel.filter.set_param(FilterFx::POSITION, position);
+                }
+            }
+            ParameterUpdate::DeckFilterResonance { deck, resonance } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.filter.set_param(FilterFx::RESONANCE, resonance);
+                }
+            }
+            ParameterUpdate::DcBlock { deck, on } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    // Starting from silent state, the blocker's first output is its input.
+                    if on && channel.dc_on.current() == 0.0 {
+                        channel.dc_blocker.state = [(0.0, 0.0); 2];
+                    }
+                    channel.dc_block = on;
+                }
+            }
+            ParameterUpdate::DeckPhaseInvert { deck, left, right } => {
+                if let Some(channel) = self.channels.get_mut(deck.index()) {
+                    channel.phase_invert = [left, right];
+                }
+            }
+            ParameterUpdate::Tempo(bpm) => {
+                for fx in self
+                    .channels
+                    .iter_mut()
+                    .filter_map(|channel| channel.fx.as_mut())
+                {
+                    fx.set_tempo(bpm);
+                }
+                for insert in self
+                    .channels
+                    .iter_mut()
+                    .filter_map(|channel| channel.insert.as_mut())
+                {
+                    insert.processor.apply(&update);
+                }
+            }
+            ParameterUpdate::LimiterCeilingDb(db) => {
+                if let Some(limiter) = &mut self.limiter {
+                    limiter.set_ceiling_db(db);
+                }
+            }
+            ParameterUpdate::LimiterReleaseMs(ms) => {
+                if let Some(limiter) = &mut self.limiter {
+                    limiter.set_release_seconds(ms / 1_000.0, self.sample_rate);
+                }
+            }
+            ParameterUpdate::MasterSoftClip(on) => self.soft_clip_on = on,
+            ParameterUpdate::MasterSoftClipDrive(drive) => self.soft_clip.set_drive(drive),
+            ParameterUpdate::MicGain(gain) => self.mic.set_gain(gain),
+            ParameterUpdate::Talkover(on) => self.mic.set_talkover(on),
+            ParameterUpdate::MicLowCutHz(hz) => self.mic.set_low_cut_hz(hz),
+            ParameterUpdate::TalkoverThresholdDb(db) => self.mic.set_threshold_db(db),
+            ParameterUpdate::TalkoverDepthDb(db) => self.mic.set_depth_db(db),
+            ParameterUpdate::ResetClipIndicators => {
+                self.master_clips = 0;
+                for channel in &mut self.channels {
+                    channel.clips = 0;
+                }
+            }
+            ParameterUpdate::ResetLoudness => self.loudness.reset_integrated(),
+            ParameterUpdate::Record(on) => self.recording = on,
+            ParameterUpdate::RequestSnapshot => {
+                // Past four decks a snapshot allocates, so only build one with room for it.
+                if let Some(snapshots) = self.snapshots.as_ref().filter(|queue| !queue.is_full()) {
+                    snapshots.send(self.snapshot());
+                }
+            }
+            // Only the parameter queue batches.
+            ParameterUpdate::BeginBatch | ParameterUpdate::CommitBatch => {}
+        }
+    }
+
+    /// Insert `fx` before `deck`'s fader, returning the effect it replaces, or
+    /// `fx` itself if the bus has no such deck.
+    pub fn set_deck_fx(&mut self, deck: DeckId, fx: Option<Box<dyn Fx>>) -> Option<Box<dyn Fx>> {
+        match self.channels.get_mut(deck.index()) {
+            Some(channel) => std::mem::replace(&mut channel.fx, fx),
+            None => fx,
+        }
+    }
+
+    /// Run `deck` through `processor` as each stereo block is mixed, ahead of
+    /// its trim, EQ and fader, returning the processor it replaces, or
+    /// `processor` itself if the bus has no such deck.
+    ///
+    /// Prepares the processor and allocates its buffer, so call it off the
+    /// audio thread, never from the callback.
+    pub fn set_deck_insert(
+        &mut self,
+        deck: DeckId,
+        processor: Box<dyn Processor + Send>,
+    ) -> Option<Box<dyn Processor + Send>> {
+        let Some(channel) = self.channels.get_mut(deck.index()) else {
+            return Some(processor);
+        };
+        let insert = Insert::new(processor, self.sample_rate);
+        channel
+            .insert
+            .replace(insert)
+            .map(|insert| insert.processor)
+    }
+
+    /// Take `deck`'s processor out of its path. Frees its buffer, so like
+    /// [`set_deck_insert`](Self::set_deck_insert) keep it off the audio thread.
+    pub fn take_deck_insert(&mut self, deck: DeckId) -> Option<Box<dyn Processor + Send>> {
+        let channel = self.channels.get_mut(deck.index())?;
+        channel.insert.take().map(|insert| insert.processor)
+    }
+
+    /// Copy the master, as it leaves the bus, into `tap` for the
+    /// [`SpectrumAnalyzer`](spectrum::SpectrumAnalyzer) of a display,
+    /// returning the tap it replaces.
+    pub fn set_spectrum_tap(&mut self, tap: Option<SpectrumTap>) -> Option<SpectrumTap> {
+        std::mem::replace(&mut self.spectrum, tap)
+    }
+
+    /// Sum `sampler` into the master, unaffected by the crossfader and talkover.
+    pub fn set_sampler(&mut self, sampler: Sampler) {
+        self.sampler = Some(sampler);
+    }
+
+    /// Compress the summed decks keyed from the mic, in place of the fixed
+    /// talkover duck, returning the compressor it replaces.
+    pub fn set_sidechain(
+        &mut self,
+        sidechain: Option<SidechainCompressor>,
+    ) -> Option<SidechainCompressor> {
+        let sidechain = sidechain.map(|mut sidechain| {
+            sidechain.prepare(self.sample_rate);
+            sidechain
+        });
+        std::mem::replace(&mut self.sidechain, sidechain)
+    }
+
+    /// Limit the master after its gain, delaying it by the limiter's lookahead,
+    /// returning the limiter it replaces. Allocates, so call before mixing.
+    pub fn set_limiter(&mut self, limiter: Option<Limiter>) -> Option<Limiter> {
+        let limiter = limiter.map(|mut limiter| {
+            limiter.prepare(self.sample_rate);
+            limiter
+        });
+        std::mem::replace(&mut self.limiter, limiter)
+    }
+
+    /// Log every applied parameter value into `tap`, starting with the current ones.
+    pub fn set_automation_tap(&mut self, tap: Option<AutomationTap>) {
+        self.automation = tap;
+    }
+
+    /// Send the deck and master levels and the master loudness of every mixed
+    /// block to `meter`. A block the queue has no room for isn't sent; on a
+    /// bus of more than four decks, each frame that is sent allocates its
+    /// [`DeckValues`](meter::DeckValues).
+    pub fn set_meter(&mut self, meter: Option<MeterSender>) {
+        self.meter = meter;
+    }
+
+    /// Copy each stereo block of the master, as it leaves the bus, into `tap`
+    /// while [`ParameterUpdate::Record`] is on, returning the tap it replaces.
+    ///
+    /// The tap comes from [`audio_ring_buffer`], whose consumer a thread
+    /// drains to write the audio out. A block the ring has no room for is
+    /// dropped whole and counted as an overrun, never waited on.
+    pub fn set_record_tap(&mut self, tap: Option<RecordTap>) -> Option<RecordTap> {
+        std::mem::replace(&mut self.record, tap)
+    }
+
+    /// Answer every [`ParameterUpdate::RequestSnapshot`] on `snapshots`.
+    pub fn set_snapshots(&mut self, snapshots: Option<SnapshotSender>) {
+        self.snapshots = snapshots;
+    }
+
+    /// Values the bus is mixing with now, as clamped when they were applied.
+    /// From the audio thread, request one with
+    /// [`ParameterUpdate::RequestSnapshot`] instead.
+    pub fn snapshot(&self) -> MixerSnapshot {
+        let decks = self.channels.iter();
+        MixerSnapshot {
+            deck_trims: decks.clone().map(|channel| channel.trim).collect(),
+            deck_gains: decks.clone().map(|channel| channel.gain).collect(),
+            fader_tapers: decks.clone().map(|channel| channel.taper).collect(),
+            deck_mutes: decks.clone().map(|channel| channel.mute).collect(),
+            deck_solos: decks.clone().map(|channel| channel.solo).collect(),
+            deck_cues: decks.clone().map(|channel| channel.cue).collect(),
+            cue_mix: self.cue_mix,
+            cue_gain: self.cue_gain,
+            crossfader: self.crossfader,
+            crossfade: self.crossfade.map(|crossfade| CrossfadeProgress {
+                target: crossfade.target,
+                elapsed_frames: crossfade.elapsed,
+                duration_frames: crossfade.duration,
+            }),
+            crossfader_curve: self.crossfader_curve,
+            crossfader_sharpness: self.crossfader_sharpness,
+            crossfader_reverse: self.crossfader_reverse,
+            crossfader_assign: decks.clone().map(|channel| channel.assign).collect(),
+            master_gain: self.master_gain,
+            booth_gain: self.booth_gain,
+            stereo_width: self.width.target(),
+            mono_output: self.mono.target() == 1.0,
+            filter_positions: decks.map(|channel| channel.filter_position).collect(),
+            soft_clip: self.soft_clip_on,
+            recording: self.recording,
+            frame: self.frame,
+        }
+    }
+
+    /// Replace NaN deck samples with silence and infinite ones with full scale
+    /// before they reach the deck's insert or EQ, counting them in each
+    /// [`MeterFrame`]. Off by default.
+    pub fn set_sanitize_input(&mut self, on: bool) {
+        self.sanitize_input = on;
+    }
+
+    /// Flush denormal floats to zero on the calling thread while a block is
+    /// processed, so decaying tails cannot spike the CPU. On by default; a
+    /// no-op on targets other than x86-64 and aarch64.
+    pub fn set_denormal_protection(&mut self, on: bool) {
+        self.denormal_protection = on;
+    }
+
+    /// Take updates timed to the frame from `schedule`, splitting each block
+    /// at the frames they land on.
+    pub fn set_schedule(&mut self, schedule: Option<ScheduleReceiver>) {
+        self.schedule = schedule;
+        self.scheduled = None;
+    }
+
+    /// Output frames mixed so far, the clock scheduled updates are timed by.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Apply at most `updates` queued parameter updates per block, at least one,
+    /// leaving the rest for the blocks after, so a flooded queue cannot hold
+    /// up the audio callback. How many were held back is counted in each
+    /// [`MeterFrame`].
+    pub fn set_updates_per_block(&mut self, updates: usize) {
+        self.updates_per_block = updates.max(1);
+    }
+
+    /// Hold metered peaks for `frames` before they fall back to the live level.
+    pub fn set_peak_hold_frames(&mut self, frames: u64) {
+        self.peak_hold_frames = frames;
+    }
+
+    /// Offer the values in effect from the start of this block to the automation log.
+    fn log_applied(&mut self) {
+        let Some(tap) = &mut self.automation else {
+            return;
+        };
+        tap.observe(self.frame, Lane::Crossfader, self.crossfader);
+        tap.observe(self.frame, Lane::MasterGain, self.master_gain);
+        // The log has lanes for the crossfader decks only.
+        for (index, channel) in self.channels.iter().enumerate().take(2) {
+            let deck = DeckId(index);
+            tap.observe(self.frame, Lane::Gain(deck), channel.gain);
+            tap.observe(self.frame, Lane::Filter(deck), channel.filter_position);
+        }
+        tap.observe(self.frame, Lane::MicGain, self.mic.gain());
+    }
+
+    /// Lock-free view of the post-fader deck gains, refreshed after every mixed block.
+    pub fn fader_reader(&self) -> FaderReader {
+        self.faders.clone()
+    }
+
+    /// Crossfader position the curve is evaluated at, mirrored in hamster mode
+    /// so the flip glides like any other move.
+    fn heard_crossfader(&self) -> f32 {
+        if self.crossfader_reverse {
+            1.0 - self.crossfader
+        } else {
+            self.crossfader
+        }
+    }
+
+    /// Like [`crossfader_gains`](Self::crossfader_gains), reusing the last
+    /// gains while neither the position nor the curve has moved, and reading
+    /// the equal-power curve from the table when there is one.
+    fn cached_crossfader_gains(&mut self, position: f32) -> (f32, f32) {
+        if self.crossfader_dirty {
+            self.crossfader_dirty = false;
+            self.crossfader_cache = None;
+            if let Some(mut table) = self.crossfader_table.take() {
+                let last = (table.len() - 1) as f32;
+                for (index, gain) in table.iter_mut().enumerate() {
+                    *gain = self.crossfader_gains(index as f32 / last).1;
+                }
+                self.crossfader_table = Some(table);
+            }
+        }
+        if let Some((cached, gains)) = self.crossfader_cache {
+            if cached == position {
+                return gains;
+            }
+        }
+        #[cfg(test)]
+        {
+            self.crossfader_recomputes += 1;
+        }
+        let gains = match &self.crossfader_table {
+            // Deck A's side mirrors deck B's.
+            Some(table) if self.crossfader_curve == CrossfaderCurve::EqualPower => {
+                (lookup(table, 1.0 - position), lookup(table, position))
+            }
+            _ => self.crossfader_gains(position),
+        };
+        self.crossfader_cache = Some((position, gains));
+        gains
+    }
+
+    /// Calculate crossfader gains for decks A and B at `position` on the current curve.
+    ///
+    /// Sharpness narrows the travel each deck fades over, from the whole fader
+    /// at 0 down to the sharp cut's at 1; the curve shapes the fade within it.
+    fn crossfader_gains(&self, position: f32) -> (f32, f32) {
+        let mut travel = 1.0 - self.crossfader_sharpness * (1.0 - SHARP_CUT_TRAVEL);
+        if self.crossfader_curve == CrossfaderCurve::SharpCut {
+            travel = travel.min(SHARP_CUT_TRAVEL);
+        }
+        // How far each deck is faded in, 1 once its side of the fader is reached.
+        let a = ((1.0 - position) / travel).min(1.0);
+        let b = (position / travel).min(1.0);
+        match self.crossfader_curve {
+            CrossfaderCurve::EqualPower => {
+                // Map [0, 1] -> [0, PI/2] for equal-power sine/cosine curve.
+                let half_pi = std::f32::consts::FRAC_PI_2;
+                ((a * half_pi).sin(), (b * half_pi).sin())
+            }
+            CrossfaderCurve::Linear | CrossfaderCurve::SharpCut => (a, b),
+        }
+    }
+
+    /// Mix the interleaved stereo buffers of a two-deck bus into the provided output buffer.
+    ///
+    /// The method drains pending parameter updates, applies per-deck EQ and gains,
+    /// crossfader scaling, and a master gain to each frame, each gliding per
+    /// frame if [`set_smoothing_ms`](Self::set_smoothing_ms) is on, then the
+    /// limiter and soft clipper when they are in. All buffers
+    /// must share the same length and contain interleaved stereo samples.
+    ///
+    /// # Panics
+    ///
+    /// On buffers [`try_mix_stereo`](Self::try_mix_stereo) would refuse.
+    pub fn mix_stereo(&mut self, deck_a: &[f32], deck_b: &[f32], output: &mut [f32]) {
+        if let Err(error) = self.try_mix_stereo(deck_a, deck_b, output) {
+            panic!("{error}");
+        }
+    }
+
+    /// Like [`mix_stereo`](Self::mix_stereo), returning an error instead of
+    /// panicking on bad buffers, in which case nothing is mixed, no update is
+    /// applied and `output` is left as it was.
+    pub fn try_mix_stereo(
+        &mut self,
+        deck_a: &[f32],
+        deck_b: &[f32],
+        output: &mut [f32],
+    ) -> Result<(), MixError> {
+        self.check_pair(deck_a, deck_b, output.len())?;
+        self.try_mix(&[deck_a, deck_b], output)
+    }
+
+    /// Like [`mix_stereo`](Self::mix_stereo), adding the mix into `output`
+    /// instead of overwriting it, so several buses can sum into one buffer.
+    ///
+    /// # Panics
+    ///
+    /// On buffers [`try_mix_stereo_add`](Self::try_mix_stereo_add) would refuse.
+    pub fn mix_stereo_add(&mut self, deck_a: &[f32], deck_b: &[f32], output: &mut [f32]) {
+        if let Err(error) = self.try_mix_stereo_add(deck_a, deck_b, output) {
+            panic!("{error}");
+        }
+    }
+
+    /// Like [`mix_stereo_add`](Self::mix_stereo_add), returning an error instead
+    /// of panicking as [`try_mix_stereo`](Self::try_mix_stereo) does.
+    pub fn try_mix_stereo_add(
+        &mut self,
+        deck_a: &[f32],
+        deck_b: &[f32],
+        output: &mut [f32],
+    ) -> Result<(), MixError> {
+        self.check_pair(deck_a, deck_b, output.len())?;
+        self.check_buffers(&[deck_a, deck_b], None, output.len(), None, None)?;
+        self.render(&[deck_a, deck_b], None, &mut Accumulate(output), None, None);
+        Ok(())
+    }
+
+    /// Like [`mix_stereo`](Self::mix_stereo), writing the mix over `deck_a`
+    /// instead of a third buffer. Every frame of `deck_a` is read before it is
+    /// overwritten, so the result is the same as mixing out of place.
+    ///
+    /// # Panics
+    ///
+    /// On buffers [`try_mix_stereo_in_place`](Self::try_mix_stereo_in_place)
+    /// would refuse.
+    pub fn mix_stereo_in_place(&mut self, deck_a: &mut [f32], deck_b: &[f32]) {
+        if let Err(error) = self.try_mix_stereo_in_place(deck_a, deck_b) {
+            panic!("{error}");
+        }
+    }
+
+    /// Like [`mix_stereo_in_place`](Self::mix_stereo_in_place), returning an
+    /// error instead of panicking as [`try_mix_stereo`](Self::try_mix_stereo)
+    /// does, with `deck_a` standing in for the output.
+    pub fn try_mix_stereo_in_place(
+        &mut self,
+        deck_a: &mut [f32],
+        deck_b: &[f32],
+    ) -> Result<(), MixError> {
+        let output = deck_a.len();
+        self.check_pair(deck_a, deck_b, output)?;
+        self.check_buffers(&[&*deck_a, deck_b], None, output, None, None)?;
+        let cells = Cell::from_mut(deck_a).as_slice_of_cells();
+        let decks = [InPlace::Mixed(Cells(cells)), InPlace::Read(deck_b)];
+        self.render(&decks, None, &mut Cells(cells), None, None);
+        Ok(())
+    }
+
+    /// Refuse anything but a stereo bus, and decks unlike the output in length.
+    fn check_pair(&self, deck_a: &[f32], deck_b: &[f32], output: usize) -> Result<(), MixError> {
+        if self.layout != ChannelLayout::Stereo {
+            return Err(MixError::StereoOnly {
+                channels: self.layout.channels(),
+            });
+        }
+        if deck_a.len() != output || deck_b.len() != output {
+            return Err(MixError::LengthMismatch {
+                deck_a: deck_a.len(),
+                deck_b: deck_b.len(),
+                output,
+            });
+        }
+        Ok(())
+    }
+
+    /// Mix one interleaved buffer per deck of the bus into `output`, in frames
+    /// of its [channel layout](Self::with_channel_layout). All buffers must
+    /// share the same length, a whole number of frames.
+    ///
+    /// # Panics
+    ///
+    /// On buffers [`try_mix`](Self::try_mix) would refuse.
+    pub fn mix(&mut self, decks: &[&[f32]], output: &mut [f32]) {
+        if let Err(error) = self.try_mix(decks, output) {
+            panic!("{error}");
+        }
+    }
+
+    /// Like [`mix`](Self::mix), returning an error instead of panicking as
+    /// [`try_mix_stereo`](Self::try_mix_stereo) does.
+    pub fn try_mix(&mut self, decks: &[&[f32]], output: &mut [f32]) -> Result<(), MixError> {
+        match self.layout.channels() {
+            2 => self.try_mix_into(decks, None, output, None, None),
+            channels => {
+                self.check_frames(decks, output.len(), channels)?;
+                self.render_wide(decks, output, channels);
+                Ok(())
+            }
+        }
+    }
+
+    /// Like [`mix_stereo`](Self::mix_stereo), also rendering the headphones
+    /// into `cue_out`: the cued decks after their EQ but ahead of their faders
+    /// and the crossfader, blended with the master by the cue mix.
+    pub fn mix_stereo_with_cue(
+        &mut self,
+        deck_a: &[f32],
+        deck_b: &[f32],
+        main_out: &mut [f32],
+        cue_out: &mut [f32],
+    ) {
+        self.mix_into(&[deck_a, deck_b], None, main_out, None, Some(cue_out));
+    }
+
+    /// Like [`mix_stereo_with_cue`](Self::mix_stereo_with_cue), also rendering
+    /// the booth monitor into `booth_out` when given: the master program at the
+    /// booth gain instead of the master gain, taken ahead of the limiter and
+    /// soft clipper.
+    pub fn mix_stereo_multi(
+        &mut self,
+        deck_a: &[f32],
+        deck_b: &[f32],
+        main_out: &mut [f32],
+        booth_out: Option<&mut [f32]>,
+        cue_out: Option<&mut [f32]>,
+    ) {
+        self.mix_into(&[deck_a, deck_b], None, main_out, booth_out, cue_out);
+    }
+
+    /// Like [`mix_stereo`](Self::mix_stereo), also summing `mic`, mono or
+    /// interleaved stereo, into the master and ducking the decks while it is
+    /// open or talkover is held.
+    pub fn mix_stereo_with_mic(
+        &mut self,
+        deck_a: &[f32],
+        deck_b: &[f32],
+        mic: &[f32],
+        output: &mut [f32],
+    ) {
+        self.mix_into(&[deck_a, deck_b], Some(mic), output, None, None);
+    }
+
+    /// Like [`mix_stereo`](Self::mix_stereo), on planar buffers: one slice per
+    /// channel, left then right, each as long as the other.
+    ///
+    /// # Panics
+    ///
+    /// On buffers [`try_mix_planar`](Self::try_mix_planar) would refuse.
+    pub fn mix_planar(&mut self, deck_a: [&[f32]; 2], deck_b: [&[f32]; 2], out: [&mut [f32]; 2]) {
+        if let Err(error) = self.try_mix_planar(deck_a, deck_b, out) {
+            panic!("{error}");
+        }
+    }
+
+    /// Like [`mix_planar`](Self::mix_planar), returning an error instead of
+    /// panicking as [`try_mix_stereo`](Self::try_mix_stereo) does.
+    pub fn try_mix_planar(
+        &mut self,
+        deck_a: [&[f32]; 2],
+        deck_b: [&[f32]; 2],
+        out: [&mut [f32]; 2],
+    ) -> Result<(), MixError> {
+        if self.layout != ChannelLayout::Stereo {
+            return Err(MixError::StereoOnly {
+                channels: self.layout.channels(),
+            });
+        }
+        if self.channels.len() != 2 {
+            return Err(MixError::DeckCount {
+                expected: self.channels.len(),
+                given: 2,
+            });
+        }
+        let output = out[0].len();
+        let buffers = [
+            ("deck A", deck_a),
+            ("deck B", deck_b),
+            ("output", [&*out[0], &*out[1]]),
+        ];
+        for (buffer, channels) in buffers {
+            for (channel, samples) in channels.iter().enumerate() {
+                if samples.len() != output {
+                    return Err(MixError::ChannelLength {
+                        buffer,
+                        channel,
+                        len: samples.len(),
+                        output,
+                    });
+                }
+            }
+        }
+        self.render(
+            &[Planar(deck_a), Planar(deck_b)],
+            None,
+            &mut Planar(out),
+            None,
+            None,
+        );
+        Ok(())
+    }
+
+    /// Like [`try_mix_into`](Self::try_mix_into), panicking on bad buffers.
+    fn mix_into<D: AsRef<[f32]>>(
+        &mut self,
+        decks: &[D],
+        mic: Option<&[f32]>,
+        output: &mut [f32],
+        booth: Option<&mut [f32]>,
+        cue: Option<&mut [f32]>,
+    ) {
+        if let Err(error) = self.try_mix_into(decks, mic, output, booth, cue) {
+            panic!("{error}");
+        }
+    }
+
+    /// Check every deck buffer against the output before anything is touched.
+    fn check_decks<D: AsRef<[f32]>>(&self, decks: &[D], output: usize) -> Result<(), MixError> {
+        if decks.len() != self.channels.len() {
+            return Err(MixError::DeckCount {
+                expected: self.channels.len(),
+                given: decks.len(),
+            });
+        }
+        for (index, deck) in decks.iter().enumerate() {
+            let len = deck.as_ref().len();
+            if len != output {
+                return Err(MixError::DeckLength {
+                    deck: DeckId(index),
+                    len,
+                    output,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [`check_decks`](Self::check_decks), for frames of `channels`.
+    fn check_frames<D: AsRef<[f32]>>(
+        &self,
+        decks: &[D],
+        output: usize,
+        channels: usize,
+    ) -> Result<(), MixError> {
+        self.check_decks(decks, output)?;
+        if output.checked_rem(channels) != Some(0) {
+            return Err(MixError::PartialFrame {
+                len: output,
+                channels,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check every buffer of a stereo mix against the output.
+    fn check_buffers<D: AsRef<[f32]>>(
+        &self,
+        decks: &[D],
+        mic: Option<&[f32]>,
+        output: usize,
+        booth: Option<usize>,
+        cue: Option<usize>,
+    ) -> Result<(), MixError> {
+        if self.layout != ChannelLayout::Stereo {
+            return Err(MixError::StereoOnly {
+                channels: self.layout.channels(),
+            });
+        }
+        self.check_decks(decks, output)?;
+        if !output.is_multiple_of(2) {
+            return Err(MixError::OddFrameCount(output));
+        }
+        // Mono mics carry one sample a frame, stereo ones two.
+        let mic = mic
+            .map(<[f32]>::len)
+            .filter(|&len| len != output && len * 2 != output);
+        let booth = booth.filter(|&len| len != output);
+        let cue = cue.filter(|&len| len != output);
+        for (buffer, len) in [("mic", mic), ("booth", booth), ("cue", cue)] {
+            if let Some(len) = len {
+                return Err(MixError::BufferLength {
+                    buffer,
+                    len,
+                    output,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn try_mix_into<D: AsRef<[f32]>>(
+        &mut self,
+        decks: &[D],
+        mic: Option<&[f32]>,
+        output: &mut [f32],
+        booth: Option<&mut [f32]>,
+        cue: Option<&mut [f32]>,
+    ) -> Result<(), MixError> {
+        let booth_len = booth.as_deref().map(<[f32]>::len);
+        let cue_len = cue.as_deref().map(<[f32]>::len);
+        self.check_buffers(decks, mic, output.len(), booth_len, cue_len)?;
+        self.render(decks, mic, output, booth, cue);
+        Ok(())
+    }
+
+    /// The bus itself: every frame of `decks` through its strip, the
+    /// crossfader and the master, into `output`. Buffers are already checked.
+    fn render<D: StereoSource, O: StereoSink + ?Sized>(
+        &mut self,
+        decks: &[D],
+        mic: Option<&[f32]>,
+        output: &mut O,
+        booth: Option<&mut [f32]>,
+        cue: Option<&mut [f32]>,
+    ) {
+        self.drain_updates();
+        self.render_drained(decks, mic, output, booth, cue);
+    }
+
+    /// Like [`render`](Self::render), once the block's updates are drained.
+    fn render_drained<D: StereoSource, O: StereoSink + ?Sized>(
+        &mut self,
+        decks: &[D],
+        mic: Option<&[f32]>,
+        output: &mut O,
+        mut booth: Option<&mut [f32]>,
+        mut cue: Option<&mut [f32]>,
+    ) {
+        let frames = output.frames();
+        let _denormals = DenormalGuard::new(self.denormal_protection);
+        let (mut position, mut crossfader) = self.begin_block();
+        let mut master_gain = self.smoothed_master.current();
+        let recording =
+            self.recording && self.record.as_mut().is_some_and(|tap| tap.reserve(frames));
+
+        #[cfg(feature = "simd")]
+        if mic.is_none() && booth.is_none() && cue.is_none() && self.holds_still(frames) {
+            let plain = decks.iter().all(|deck| deck.interleaved().is_some());
+            if let Some(output) = output.interleaved_mut().filter(|_| plain) {
+                let master_gain = self.render_still(decks, output, crossfader);
+                if let Some(tap) = self.record.as_mut().filter(|_| recording) {
+                    tap.write(output);
+                }
+                if let Some(tap) = &mut self.spectrum {
+                    tap.push(output);
+                }
+                self.end_block(frames, master_gain);
+                return;
+            }
+        }
+
+        let mic_mono = mic.is_some_and(|mic| mic.len() == frames && frames > 0);
+        if let Some(sampler) = &mut self.sampler {
+            sampler.begin_block();
+        }
+        let metering = self.meter.is_some();
+        let sanitize = self.sanitize_input;
+        for channel in &mut self.channels {
+            channel.levels = Levels::default();
+        }
+        let mut master_levels = Levels::default();
+        let mut correlation = Correlation::default();
+
+        for index in 0..frames {
+            master_gain = self.next_frame(index, &mut position, &mut crossfader);
+            let booth_gain = self.smoothed_booth.next();
+            let (voice, talkover) = match mic {
+                Some(mic) if mic_mono => self.mic.tick([mic[index]; 2]),
+                Some(mic) => self.mic.tick([mic[index * 2], mic[index * 2 + 1]]),
+                // Without a mic, talkover held by hand still ducks and releases.
+                None if self.mic.talkover() || self.mic.duck_gain() < 1.0 => {
+                    self.mic.tick([0.0; 2])
+                }
+                None => ([0.0; 2], 1.0),
+            };
+            let duck = match &mut self.sidechain {
+                Some(sidechain) => sidechain.tick(voice),
+                None => talkover,
+            };
+            let shot = self
+                .sampler
+                .as_mut()
+                .map_or([0.0; 2], |sampler| sampler.next_frame());
+
+            let mut music = [0.0; 2];
+            let mut cued = [0.0; 2];
+            let stretch_end = self.stretch_end(index, frames);
+            for (channel, input) in self.channels.iter_mut().zip(decks) {
+                let mut input = match &mut channel.insert {
+                    Some(insert) => {
+                        let scrubbed = sanitize.then_some(&mut self.scrubbed);
+                        insert.frame(input, index, stretch_end, scrubbed)
+                    }
+                    None => {
+                        let mut input = input.frame(index);
+                        if sanitize {
+                            for sample in &mut input {
+                                self.scrubbed += scrub(sample) as u64;
+                            }
+                        }
+                        input
+                    }
+                };
+                if channel.dc_block || !channel.dc_on.is_settled() {
+                    let blocked = channel.dc_blocker.tick(input);
+                    let wet = channel.dc_on.next();
+                    for ch in 0..2 {
+                        input[ch] += (blocked[ch] - input[ch]) * wet;
+                    }
+                }
+                for (sample, polarity) in input.iter_mut().zip(&mut channel.polarity) {
+                    *sample *= polarity.next();
+                }
+                let (trim, gain) = channel.gains;
+                let frame = channel.eq.tick([input[0] * trim, input[1] * trim]);
+                if metering {
+                    channel.levels.add(frame);
+                }
+                if cue.is_some() {
+                    let cue_on = channel.cue_on.next();
+                    for ch in 0..2 {
+                        cued[ch] += frame[ch] * cue_on;
+                    }
+                }
+                for ch in 0..2 {
+                    let faded = frame[ch] * gain;
+                    channel.clips += (faded.abs() > 1.0) as u64;
+                    music[ch] += faded;
+                }
+            }
+            let mut out = [0.0; 2];
+            for ch in 0..2 {
+                let program = music[ch] * duck + voice[ch] + shot[ch];
+                out[ch] = program * master_gain;
+                if let Some(booth) = booth.as_deref_mut() {
+                    booth[index * 2 + ch] = program * booth_gain;
+                }
+            }
+            // Left untouched at unity, so the default width costs nothing.
+            if !(self.width.is_settled() && self.width.current() == 1.0) {
+                let width = self.width.next();
+                let mid = (out[0] + out[1]) * 0.5;
+                let side = (out[0] - out[1]) * 0.5 * width;
+                out = [mid + side, mid - side];
+            }
+            if metering {
+                correlation.add(out);
+            }
+            if !(self.mono.is_settled() && self.mono.current() == 0.0) {
+                let mono = self.mono.next();
+                // -3 dB pan law, so uncorrelated sides keep their power.
+                let sum = (out[0] + out[1]) * std::f32::consts::FRAC_1_SQRT_2;
+                for sample in out.iter_mut() {
+                    *sample += (sum - *sample) * mono;
+                }
+            }
+            for sample in out {
+                self.master_clips += (sample.abs() > 1.0) as u64;
+            }
+            if let Some(limiter) = &mut self.limiter {
+                out = limiter.tick(out);
+            }
+            if self.soft_clip_on {
+                out = out.map(|sample| self.soft_clip.shape(sample));
+            }
+            if metering {
+                master_levels.add(out);
+                self.loudness.tick(out);
+            }
+            if let Some(cue) = cue.as_deref_mut() {
+                for ch in 0..2 {
+                    cue[index * 2 + ch] =
+                        (cued[ch] * (1.0 - self.cue_mix) + out[ch] * self.cue_mix) * self.cue_gain;
+                }
+            }
+            if let Some(tap) = self.record.as_mut().filter(|_| recording) {
+                tap.write(&out);
+            }
+            if let Some(tap) = &mut self.spectrum {
+                tap.push_frame(out);
+            }
+            output.write(index, out);
+        }
+        if let Some(sidechain) = &mut self.sidechain {
+            sidechain.publish();
+        }
+        if let Some(limiter) = &mut self.limiter {
+            limiter.publish();
+        }
+        if let Some(meter) = &self.meter {
+            let (block, hold_frames) = (frames as u64, self.peak_hold_frames);
+            for channel in &mut self.channels {
+                channel
+                    .peak_hold
+                    .update(channel.levels.peak(), block, hold_frames);
+            }
+            let master_peak_hold =
+                self.master_peak_hold
+                    .update(master_levels.peak(), block, hold_frames);
+            // A frame for more decks than fit inline allocates, so don't build
+            // one only to free it here when the queue turns it away.
+            if !meter.is_full() {
+                meter.send(MeterFrame {
+                    deck_peaks: self.channels.iter().map(|c| c.levels.peak()).collect(),
+                    deck_rms: self.channels.iter().map(|c| c.levels.rms(frames)).collect(),
+                    master_peak: master_levels.peak(),
+                    master_rms: master_levels.rms(frames),
+                    deck_peak_holds: self.channels.iter().map(|c| c.peak_hold.held()).collect(),
+                    master_peak_hold,
+                    deck_clips: self.channels.iter().map(|c| c.clips).collect(),
+                    master_clips: self.master_clips,
+                    loudness: self.loudness.reading(),
+                    scrubbed: self.scrubbed,
+                    deferred_updates: self.deferred_updates,
+                    correlation: correlation.value(),
+                    frame: self.frame,
+                });
+            }
+        }
+        self.end_block(frames, master_gain);
+    }
+
+    /// Like [`render`](Self::render), for frames of `channels` wider than
+    /// stereo: only the gains, the crossfader and the soft clipper apply.
+    fn render_wide<D: AsRef<[f32]>>(&mut self, decks: &[D], output: &mut [f32], channels: usize) {
+        let frames = output.len() / channels;
+        let _denormals = DenormalGuard::new(self.denormal_protection);
+        self.drain_updates();
+        let (mut position, mut crossfader) = self.begin_block();
+        let mut master_gain = self.smoothed_master.current();
+        let sanitize = self.sanitize_input;
+
+        for (index, out) in output.chunks_exact_mut(channels).enumerate() {
+            master_gain = self.next_frame(index, &mut position, &mut crossfader);
+            out.fill(0.0);
+            for (channel, input) in self.channels.iter_mut().zip(decks) {
+                let (trim, gain) = channel.gains;
+                let input = &input.as_ref()[index * channels..(index + 1) * channels];
+                for (out, mut sample) in out.iter_mut().zip(input.iter().copied()) {
+                    if sanitize {
+                        self.scrubbed += scrub(&mut sample) as u64;
+                    }
+                    let faded = sample * trim * gain;
+                    channel.clips += (faded.abs() > 1.0) as u64;
+                    *out += faded;
+                }
+            }
+            for sample in out.iter_mut() {
+                *sample *= master_gain;
+                self.master_clips += (sample.abs() > 1.0) as u64;
+                if self.soft_clip_on {
+                    *sample = self.soft_clip.shape(*sample);
+                }
+            }
+        }
+        self.end_block(frames, master_gain);
+    }
+
+    /// Whether every stage but the gains is out, every gain has settled and
+    /// nothing is scheduled within the next `frames`, so a block reduces to one
+    /// fixed gain per deck and the master gain.
+    #[cfg(feature = "simd")]
+    fn holds_still(&self, frames: usize) -> bool {
+        let settled_at =
+            |smoothed: &Smoothed, value: f32| smoothed.is_settled() && smoothed.current() == value;
+        let decks_still = self.channels.iter().all(|channel| {
+            [&channel.smoothed_trim, &channel.smoothed_gain, &channel.on]
+                .into_iter()
+                .chain(&channel.sides)
+                .all(Smoothed::is_settled)
+                && !channel.dc_block
+                && settled_at(&channel.dc_on, 0.0)
+                && channel
+                    .polarity
+                    .iter()
+                    .all(|polarity| settled_at(polarity, 1.0))
+                && channel.eq.is_bypassed()
+                && channel.insert.is_none()
+        });
+        decks_still
+            && self.crossfader_ramp.is_none()
+            && self.crossfade.is_none()
+            && self.smoothed_crossfader.is_settled()
+            && self.smoothed_master.is_settled()
+            && self.smoothed_booth.is_settled()
+            && settled_at(&self.width, 1.0)
+            && settled_at(&self.mono, 0.0)
+            && !self.mic.talkover()
+            && self.mic.duck_gain() == 1.0
+            && self.sidechain.is_none()
+            && self.sampler.is_none()
+            && self.limiter.is_none()
+            && !self.soft_clip_on
+            && self.meter.is_none()
+            && !self.sanitize_input
+            && (frames == 0 || !self.scheduled_at(frames - 1))
+    }
+
+    /// Like [`render`](Self::render), for a block that
+    /// [holds still](Self::holds_still), a deck at a time through the vector
+    /// kernels. Returns the master gain.
+    #[cfg(feature = "simd")]
+    fn render_still<D: StereoSource>(
+        &mut self,
+        decks: &[D],
+        output: &mut [f32],
+        crossfader: (f32, f32),
+    ) -> f32 {
+        output.fill(0.0);
+        for (channel, input) in self.channels.iter_mut().zip(decks) {
+            let (trim, gain) = channel.next_gains(crossfader);
+            channel.eq.rest();
+            if let Some(input) = input.interleaved() {
+                channel.clips += simd::fade_add(output, input, trim, gain);
+            }
+        }
+        let master_gain = self.smoothed_master.next();
+        self.master_clips += simd::scale(output, master_gain);
+        master_gain
+    }
+
+    /// Apply the updates due at the block's start, drained already, and aim
+    /// every smoother at its new target. Returns the crossfader position and
+    /// side gains the block starts from.
+    fn begin_block(&mut self) -> (f32, (f32, f32)) {
+        self.apply_scheduled(self.frame);
+        self.log_applied();
+        self.aim()
+    }
+
+    /// Aim every smoother at the value now set. Returns the crossfader position
+    /// and side gains to carry on from.
+    fn aim(&mut self) -> (f32, (f32, f32)) {
+        let soloing = self.channels.iter().any(|channel| channel.solo);
+        for channel in &mut self.channels {
+            channel.smoothed_trim.set(channel.trim);
+            channel.smoothed_gain.set(channel.gain);
+            let audible = if soloing { channel.solo } else { !channel.mute };
+            channel.on.set(if audible { 1.0 } else { 0.0 });
+            channel.cue_on.set(if channel.cue { 1.0 } else { 0.0 });
+            channel.dc_on.set(if channel.dc_block { 1.0 } else { 0.0 });
+            for (polarity, invert) in channel.polarity.iter_mut().zip(channel.phase_invert) {
+                polarity.set(if invert { -1.0 } else { 1.0 });
+            }
+            for (side, assign) in channel.sides.iter_mut().zip(XfAssign::ALL) {
+                side.set(if assign == channel.assign { 1.0 } else { 0.0 });
+            }
+        }
+        self.smoothed_crossfader.set(self.heard_crossfader());
+        self.smoothed_master.set(self.master_gain);
+        self.smoothed_booth.set(self.booth_gain);
+        let position = self.smoothed_crossfader.current();
+        let crossfader = self.cached_crossfader_gains(position);
+        for channel in &mut self.channels {
+            channel.heard = channel.smoothed_gain.current()
+                * channel.on.current()
+                * channel.crossfader_share(crossfader)
+                * channel.smoothed_trim.current();
+        }
+        (position, crossfader)
+    }
+
+    /// Apply the scheduled updates due by output `frame`. Returns whether
+    /// there were any.
+    fn apply_scheduled(&mut self, frame: u64) -> bool {
+        let mut applied = false;
+        loop {
+            if self.scheduled.is_none() {
+                self.scheduled = self.schedule.as_ref().and_then(ScheduleReceiver::pop);
+            }
+            match self.scheduled.take() {
+                Some(due) if due.frame <= frame => {
+                    self.apply(due.update);
+                    applied = true;
+                }
+                held => {
+                    self.scheduled = held;
+                    return applied;
+                }
+            }
+        }
+    }
+
+    /// Whether a held scheduled update lands on frame `index` of the block.
+    fn scheduled_at(&self, index: usize) -> bool {
+        self.scheduled
+            .as_ref()
+            .is_some_and(|due| due.frame <= self.frame + index as u64)
+    }
+
+    /// Frame of the block that an insert stretch starting at `index` runs up
+    /// to: the next scheduled update's, so it reaches the processor on time,
+    /// or the block's end.
+    fn stretch_end(&self, index: usize, frames: usize) -> usize {
+        self.scheduled.as_ref().map_or(frames, |due| {
+            let due = due.frame.saturating_sub(self.frame);
+            due.clamp(index as u64 + 1, frames as u64) as usize
+        })
+    }
+
+    /// Step every parameter to frame `index` of the block, for the stereo and
+    /// wider layouts alike: the scheduled updates landing on it, the crossfader,
+    /// and each deck's [`gains`](Channel::gains). Returns the master gain.
+    fn next_frame(&mut self, index: usize, position: &mut f32, crossfader: &mut (f32, f32)) -> f32 {
+        if self.scheduled_at(index) {
+            self.apply_scheduled(self.frame + index as u64);
+            (*position, *crossfader) = self.aim();
+        }
+        self.next_crossfader(index, position, crossfader);
+        for channel in &mut self.channels {
+            channel.gains = channel.next_gains(*crossfader);
+        }
+        self.smoothed_master.next()
+    }
+
+    /// Step the crossfader ramp and glide to frame `index` of the block, updating
+    /// the side gains whenever the position moves.
+    fn next_crossfader(&mut self, index: usize, position: &mut f32, crossfader: &mut (f32, f32)) {
+        // Where a fade or ramp moves the crossfader, and whether it ends there.
+        let moved = if let Some(crossfade) = &mut self.crossfade {
+            crossfade.elapsed += 1;
+            Some((
+                crossfade.position(),
+                crossfade.elapsed >= crossfade.duration,
+            ))
+        } else if let Some((target, step)) = self.crossfader_ramp {
+            let next = self.crossfader + step;
+            if (step >= 0.0 && next >= target) || (step < 0.0 && next <= target) {
+                Some((target, true))
+            } else {
+                Some((next, false))
+            }
+        } else {
+            None
+        };
+        if let Some((next, done)) = moved {
+            let frame = self.frame + index as u64;
+            self.crossfader = next;
+            if done {
+                self.crossfader_ramp = None;
+                self.crossfade = None;
+            }
+            if let Some(tap) = &mut self.automation {
+                if done {
+                    tap.settle(frame, Lane::Crossfader, next);
+                } else {
+                    tap.observe(frame, Lane::Crossfader, next);
+                }
+            }
+            self.smoothed_crossfader.set(self.heard_crossfader());
+        }
+        // The curve follows the glide, so a smoothed jump stays equal-power.
+        let next = self.smoothed_crossfader.next();
+        if next != *position {
+            *position = next;
+            *crossfader = self.cached_crossfader_gains(next);
+        }
+    }
+
+    /// Count the block's frames and publish the gain each deck was heard at.
+    fn end_block(&mut self, frames: usize, master_gain: f32) {
+        self.frame += frames as u64;
+        for (deck, channel) in self.channels.iter().enumerate() {
+            self.faders.publish(deck, channel.heard * master_gain);
+        }
+    }
+
+    /// Run each deck through its effect insert and filter in place, then
+    /// [`mix_stereo`](Self::mix_stereo).
+    pub fn process(&mut self, deck_a: &mut [f32], deck_b: &mut [f32], output: &mut [f32]) {
+        self.process_with_mic(deck_a, deck_b, None, output);
+    }
+
+    /// Like [`process`](Self::process), also summing `mic` into the master and
+    /// ducking the decks while it is open, as [`mix_stereo_with_mic`](Self::mix_stereo_with_mic) does.
+    pub fn process_with_mic(
+        &mut self,
+        deck_a: &mut [f32],
+        deck_b: &mut [f32],
+        mic: Option<&[f32]>,
+        output: &mut [f32],
+    ) {
+        self.process_with_cue(deck_a, deck_b, mic, output, None);
+    }
+
+    /// Like [`process_with_mic`](Self::process_with_mic), also rendering the
+    /// headphones into `cue` as [`mix_stereo_with_cue`](Self::mix_stereo_with_cue) does.
+    pub fn process_with_cue(
+        &mut self,
+        deck_a: &mut [f32],
+        deck_b: &mut [f32],
+        mic: Option<&[f32]>,
+        output: &mut [f32],
+        cue: Option<&mut [f32]>,
+    ) {
+        self.process_decks(&mut [deck_a, deck_b], mic, output, cue);
+    }
+
+    /// Like [`process_with_cue`](Self::process_with_cue), with one buffer per
+    /// deck of the bus.
+    pub fn process_decks(
+        &mut self,
+        decks: &mut [&mut [f32]],
+        mic: Option<&[f32]>,
+        output: &mut [f32],
+        cue: Option<&mut [f32]>,
+    ) {
+        let cue_len = cue.as_deref().map(<[f32]>::len);
+        if let Err(error) = self.check_buffers(decks, mic, output.len(), None, cue_len) {
+            panic!("{error}");
+        }
+        let _denormals = DenormalGuard::new(self.denormal_protection);
+        // Drained once, so the effects run on the same updates as the mix.
+        self.drain_updates();
+        for (channel, deck) in self.channels.iter_mut().zip(decks.iter_mut()) {
+            if let Some(fx) = &mut channel.fx {
+                fx.process(deck);
+            }
+            channel.filter.process(deck);
+        }
+        self.render_drained(decks, mic, output, None, cue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn approx_eq(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    /// Two buses given the same `updates`, to mix one block two ways.
+    fn bus_pair(updates: &[ParameterUpdate]) -> [SummingBus; 2] {
+        [(); 2].map(|_| {
+            let (tx, rx) = parameter_channel(8);
+            for update in updates {
+                tx.send(update.clone()).unwrap();
+            }
+            SummingBus::new(rx)
+        })
+    }
+
+    /// `len` samples of white noise in [-1, 1), stepping the generator in `state`.
+    fn noise(state: &mut u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                *state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (*state >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn full_queue_counts_dropped_updates() {
+        let (tx, _rx) = parameter_channel(2);
+        let other = tx.clone();
+        tx.send(ParameterUpdate::MasterGain(1.0)).unwrap();
+        let batch = [
+            ParameterUpdate::Crossfader(0.0),
+            ParameterUpdate::Crossfader(1.0),
+        ];
+        assert!(other.send_batch(&batch).is_err());
+        other.send(ParameterUpdate::Crossfader(0.5)).unwrap();
+        assert!(tx.send(ParameterUpdate::MasterGain(0.5)).is_err());
+        assert_eq!(tx.dropped(), 3);
+        assert_eq!(other.dropped(), 3);
+    }
+
+    #[test]
+    fn process_drains_the_queue_once_a_block() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        bus.set_updates_per_block(1);
+        let (meter_tx, meter_rx) = meter::meter_channel(8);
+        bus.set_meter(Some(meter_tx));
+        tx.send(ParameterUpdate::MasterGain(0.5)).unwrap();
+        tx.send(ParameterUpdate::MasterGain(0.25)).unwrap();
+
+        let mut out = [0.0; 4];
+        bus.process(&mut [0.0; 4], &mut [0.0; 4], &mut out);
+        approx_eq(bus.master_gain, 0.5);
+        assert_eq!(meter_rx.latest().unwrap().deferred_updates, 1);
+        bus.process(&mut [0.0; 4], &mut [0.0; 4], &mut out);
+        approx_eq(bus.master_gain, 0.25);
+        assert_eq!(meter_rx.latest().unwrap().deferred_updates, 1);
+    }
+
+    #[test]
+    fn flooded_queue_is_applied_a_capped_slice_per_block() {
+        let (tx, rx) = parameter_channel(8_192);
+        let mut bus = SummingBus::new(rx);
+        bus.set_updates_per_block(100);
+        let (meter_tx, meter_rx) = meter::meter_channel(64);
+        bus.set_meter(Some(meter_tx));
+        for step in 1..=5_000 {
+            tx.send(ParameterUpdate::Crossfader(step as f32 / 5_000.0))
+                .unwrap();
+        }
+
+        let mut out = [0.0; 4];
+        bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+        approx_eq(bus.crossfader, 100.0 / 5_000.0);
+        assert_eq!(meter_rx.latest().unwrap().deferred_updates, 4_900);
+        bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+        approx_eq(bus.crossfader, 200.0 / 5_000.0);
+
+        let mut blocks = 2;
+        while bus.crossfader < 1.0 {
+            bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+            blocks += 1;
+        }
+        assert_eq!(blocks, 50);
+        let deferred = meter_rx.latest().unwrap().deferred_updates;
+        assert_eq!(
+            deferred,
+            (1..50).map(|block| 5_000 - block * 100).sum::<u64>()
+        );
+        bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+        assert_eq!(meter_rx.latest().unwrap().deferred_updates, deferred);
+    }
+
+    /// A scene setting the crossfader, both faders and the master all to `level`.
+    fn scene(level: f32) -> [ParameterUpdate; 4] {
+        [
+            ParameterUpdate::Crossfader(level),
+            ParameterUpdate::DeckGain {
+                deck: DeckId::A,
+                gain: level,
+            },
+            ParameterUpdate::DeckGain {
+                deck: DeckId::B,
+                gain: level,
+            },
+            ParameterUpdate::MasterGain(level),
+        ]
+    }
+
+    /// Values the last block was mixed with, in [`scene`] order.
+    fn mixed_scene(bus: &SummingBus) -> [f32; 4] {
+        [
+            bus.crossfader,
+            bus.channels[0].gain,
+            bus.channels[1].gain,
+            bus.master_gain,
+        ]
+    }
+
+    #[test]
+    fn batches_are_never_mixed_half_applied() {
+        let (tx, rx) = parameter_channel(64);
+        let mut bus = SummingBus::new(rx);
+        bus.set_updates_per_block(1);
+        let mut out = [0.0; 4];
+        tx.send_batch(&scene(0.0)).unwrap();
+        bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+        let sender = thread::spawn(move || {
+            for step in 1..=2_000 {
+                let scene = scene(step as f32 / 2_000.0);
+                while tx.send_batch(&scene).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+        while !sender.is_finished() || bus.master_gain < 1.0 {
+            bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+            let [crossfader, gains @ ..] = mixed_scene(&bus);
+            assert!(gains.iter().all(|&gain| gain == crossfader), "{gains:?}");
+        }
+        sender.join().unwrap();
+        assert_eq!(mixed_scene(&bus), [1.0; 4]);
+
+        // Half-sent, the batch waits for its commit, then lands in one block
+        // despite the cap.
+        let (tx, rx) = parameter_channel(16);
+        let mut bus = SummingBus::new(rx);
+        bus.set_updates_per_block(1);
+        tx.send(ParameterUpdate::BeginBatch).unwrap();
+        for update in &scene(0.25)[..2] {
+            tx.send(update.clone()).unwrap();
+        }
+        bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+        assert_eq!(mixed_scene(&bus), [0.5, 1.0, 1.0, 1.0]);
+        for update in &scene(0.25)[2..] {
+            tx.send(update.clone()).unwrap();
+        }
+        tx.send(ParameterUpdate::CommitBatch).unwrap();
+        bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut out);
+        assert_eq!(mixed_scene(&bus), [0.25; 4]);
+    }
+
+    #[test]
+    fn oversized_and_nested_batches_are_refused() {
+        let (tx, rx) = parameter_channel(512);
+        let too_many = vec![ParameterUpdate::MasterGain(0.5); MAX_BATCH_UPDATES + 1];
+        assert_eq!(
+            tx.send_batch(&too_many),
+            Err(BatchError::TooLarge {
+                len: MAX_BATCH_UPDATES + 1,
+                max: MAX_BATCH_UPDATES
+            })
+        );
+        let nested = [
+            ParameterUpdate::MasterGain(0.5),
+            ParameterUpdate::BeginBatch,
+            ParameterUpdate::CommitBatch,
+        ];
+        assert_eq!(tx.send_batch(&nested), Err(BatchError::Nested));
+        assert_eq!(tx.dropped(), 0);
+        let mut bus = SummingBus::new(rx);
+        bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut [0.0; 4]);
+        assert_eq!(bus.master_gain, 1.0);
+
+        // Too big for the queue: the markers need room too.
+        let (tx, _rx) = parameter_channel(4);
+        assert_eq!(
+            tx.send_batch(&scene(0.5)),
+            Err(BatchError::Full { needed: 6, free: 4 })
+        );
+        tx.send_batch(&scene(0.5)[..2]).unwrap();
+    }
+
+    #[test]
+    fn fader_tapers_pin_their_mapping_points() {
+        assert_eq!(FaderTaper::Audio.gain(0.0), 0.0);
+        assert!((db::linear_to_db(FaderTaper::Audio.gain(0.5)) + 10.0).abs() < 1e-4);
+        assert_eq!(FaderTaper::Audio.gain(1.0), 1.0);
+        for (position, gain) in [(0.0, 0.0), (0.5, 0.5), (1.0, 1.0), (-1.0, 0.0), (2.0, 1.0)] {
+            assert_eq!(FaderTaper::Linear.gain(position), gain);
+        }
+    }
+
+    #[test]
+    fn fader_positions_and_raw_gains_coexist() {
+        let (tx, rx) = parameter_channel(16);
+        let mut bus = SummingBus::new(rx);
+        let mut mix = |update: ParameterUpdate| {
+            tx.send(update).unwrap();
+            bus.mix_stereo(&[0.0; 4], &[0.0; 4], &mut [0.0; 4]);
+            bus.snapshot().deck_gains[0]
+        };
+        let fader = |position: f32| ParameterUpdate::DeckFader {
+            deck: DeckId::A,
+            position,
+        };
+        let taper = |taper: FaderTaper| ParameterUpdate::DeckFaderTaper {
+            deck: DeckId::A,
+            taper,
+        };
+
+        approx_eq(mix(fader(0.5)), db::db_to_linear(-10.0));
+        // A new taper moves a gain set from the fader with it.
+        approx_eq(mix(taper(FaderTaper::Linear)), 0.5);
+        // A raw gain is taken as it is, and no taper touches it.
+        approx_eq(
+            mix(ParameterUpdate::DeckGain {
+                deck: DeckId::A,
+                gain: 0.8,
+            }),
+            0.8,
+        );
+        approx_eq(mix(taper(FaderTaper::Audio)), 0.8);
+        approx_eq(mix(fader(f32::NAN)), 0.8);
+        approx_eq(mix(fader(1.0)), 1.0);
+        approx_eq(mix(fader(0.0)), 0.0);
+    }
+
+    #[test]
+    fn equal_power_crossfader() {
+        let (_, rx) = parameter_channel(4);
+        let bus = SummingBus::new(rx);
+
+        let (a, b) = bus.crossfader_gains(0.0);
+        approx_eq(a, 1.0);
+        approx_eq(b, 0.0);
+
+        let (a, b) = bus.crossfader_gains(0.5);
+        approx_eq(a, std::f32::consts::FRAC_1_SQRT_2);
+        approx_eq(b, std::f32::consts::FRAC_1_SQRT_2);
+
+        let (a, b) = bus.crossfader_gains(1.0);
+        approx_eq(a, 0.0);
+        approx_eq(b, 1.0);
+    }
+
+    #[test]
+    fn crossfader_curves_at_the_ends_and_middle() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        for (curve, middle) in [
+            (CrossfaderCurve::EqualPower, (half, half)),
+            (CrossfaderCurve::Linear, (0.5, 0.5)),
+            (CrossfaderCurve::SharpCut, (1.0, 1.0)),
+        ] {
+            let (tx, rx) = parameter_channel(4);
+            let mut bus = SummingBus::new(rx).with_crossfader_curve(curve);
+            for (position, (a, b)) in [(0.0, (1.0, 0.0)), (0.5, middle), (1.0, (0.0, 1.0))] {
+                tx.send(ParameterUpdate::Crossfader(position)).unwrap();
+                let mut out = [0.0; 2];
+                bus.mix_stereo(&[1.0, 0.0], &[0.0, 1.0], &mut out);
+                approx_eq(out[0], a);
+                approx_eq(out[1], b);
+            }
+        }
+
+        // Switched live, the cut brings a deck to full gain within 5% of travel.
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::CrossfaderCurve(CrossfaderCurve::SharpCut))
+            .unwrap();
+        tx.send(ParameterUpdate::Crossfader(0.05)).unwrap();
+        let mut out = [0.0; 2];
+        bus.mix_stereo(&[1.0, 0.0], &[0.0, 1.0], &mut out);
+        assert_eq!(out, [1.0, 1.0]);
+        tx.send(ParameterUpdate::Crossfader(0.025)).unwrap();
+        bus.mix_stereo(&[1.0, 0.0], &[0.0, 1.0], &mut out);
+        approx_eq(out[1], 0.5);
+    }
+
+    #[test]
+    fn sharpness_narrows_the_fade_without_overshoot() {
+        for curve in [
+            CrossfaderCurve::EqualPower,
+            CrossfaderCurve::Linear,
+            CrossfaderCurve::SharpCut,
+        ] {
+            for sharpness in [0.0, 0.25, 0.5, 0.9, 1.0] {
+                let (_, rx) = parameter_channel(4);
+                let bus = SummingBus::new(rx)
+                    .with_crossfader_curve(curve)
+                    .with_crossfader_sharpness(sharpness);
+                let gains: Vec<_> = (0..=1_000)
+                    .map(|step| bus.crossfader_gains(step as f32 / 1_000.0))
+                    .collect();
+                for pair in gains.windows(2) {
+                    assert!(pair[1].0 <= pair[0].0 && pair[1].1 >= pair[0].1);
+                }
+                assert!(gains
+                    .iter()
+                    .all(|&(a, b)| (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b)));
+                assert_eq!(gains[0], (1.0, 0.0));
+                assert_eq!(gains[1_000].1, 1.0);
+                approx_eq(gains[1_000].0, 0.0);
+            }
+        }
+
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        for step in 0..=10 {
+            let (a, b) = bus.crossfader_gains(step as f32 / 10.0);
+            let theta = step as f32 / 10.0 * std::f32::consts::FRAC_PI_2;
+            approx_eq(a, theta.cos());
+            approx_eq(b, theta.sin());
+        }
+        // Fully sharp, both decks play at full gain past 5% of travel.
+        tx.send(ParameterUpdate::CrossfaderSharpness(1.0)).unwrap();
+        bus.mix_stereo(&[], &[], &mut []);
+        assert_eq!(bus.crossfader_gains(0.05), (1.0, 1.0));
+        assert_eq!(bus.crossfader_gains(0.95), (1.0, 1.0));
+        assert!(bus.crossfader_gains(0.025).1 < 1.0);
+    }
+
+    #[test]
+    fn crossfader_gains_are_cached_until_something_moves() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        let mut out = [0.0; 8];
+        bus.mix_stereo(&[0.5; 8], &[0.5; 8], &mut out);
+        let computed = bus.crossfader_recomputes;
+        for _ in 0..10 {
+            bus.mix_stereo(&[0.5; 8], &[0.5; 8], &mut out);
+        }
+        assert_eq!(bus.crossfader_recomputes, computed);
+
+        let updates = [
+            ParameterUpdate::Crossfader(0.2),
+            ParameterUpdate::CrossfaderCurve(CrossfaderCurve::Linear),
+            ParameterUpdate::CrossfaderSharpness(0.6),
+            ParameterUpdate::CrossfaderReverse(true),
+            ParameterUpdate::CrossfaderCurve(CrossfaderCurve::SharpCut),
+            ParameterUpdate::CrossfaderRamp {
+                target: 0.9,
+                seconds: 0.001,
+            },
+        ];
+        for update in updates {
+            let before = bus.crossfader_recomputes;
+            tx.send(update).unwrap();
+            bus.mix_stereo(&[0.5; 8], &[0.5; 8], &mut out);
+            assert!(bus.crossfader_recomputes > before);
+            let (position, gains) = bus.crossfader_cache.unwrap();
+            assert_eq!(position, bus.smoothed_crossfader.current());
+            assert_eq!(gains, bus.crossfader_gains(position));
+        }
+    }
+
+    #[test]
+    fn crossfader_table_tracks_the_equal_power_curve() {
+        for sharpness in [0.0, 0.5, 1.0] {
+            let (_, rx) = parameter_channel(4);
+            let mut bus = SummingBus::new(rx)
+                .with_crossfader_sharpness(sharpness)
+                .with_crossfader_table(1024);
+            for step in 0..=10_000 {
+                let position = step as f32 / 10_000.0;
+                let (a, b) = bus.cached_crossfader_gains(position);
+                let (exact_a, exact_b) = bus.crossfader_gains(position);
+                assert!((a - exact_a).abs() < 0.001, "{sharpness} at {position}");
+                assert!((b - exact_b).abs() < 0.001, "{sharpness} at {position}");
+            }
+        }
+    }
+
+    #[test]
+    fn reversed_crossfader_swaps_the_decks() {
+        let deck_a = [1.0, 0.5, 0.25, 0.125];
+        let deck_b = [-0.5, 0.75, 0.0, -1.0];
+        let mix = |reverse: bool, position: f32| {
+            let (tx, rx) = parameter_channel(4);
+            let mut bus = SummingBus::new(rx).with_crossfader_sharpness(0.3);
+            tx.send(ParameterUpdate::CrossfaderReverse(reverse))
+                .unwrap();
+            tx.send(ParameterUpdate::Crossfader(position)).unwrap();
+            let mut out = [0.0; 4];
+            bus.mix_stereo(&deck_a, &deck_b, &mut out);
+            out
+        };
+        for position in [0.0, 0.2, 0.5, 1.0] {
+            let normal = mix(false, position);
+            let reversed = mix(true, position);
+            let (_, rx) = parameter_channel(1);
+            let (a, b) = SummingBus::new(rx)
+                .with_crossfader_sharpness(0.3)
+                .crossfader_gains(position);
+            for (ch, (&normal, &reversed)) in normal.iter().zip(&reversed).enumerate() {
+                approx_eq(normal, deck_a[ch] * a + deck_b[ch] * b);
+                approx_eq(reversed, deck_a[ch] * b + deck_b[ch] * a);
+            }
+        }
+        assert_eq!(mix(true, 0.0), [-0.5, 0.75, 0.0, -1.0]);
+
+        // With smoothing on, flipping glides rather than jumping across.
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        bus.set_smoothing_ms(10.0);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let mut out = [0.0; 96_000];
+        bus.mix_stereo(&[1.0; 96_000], &[0.0; 96_000], &mut out);
+        tx.send(ParameterUpdate::CrossfaderReverse(true)).unwrap();
+        bus.mix_stereo(&[1.0; 96_000], &[0.0; 96_000], &mut out);
+        assert!(out[0] > 0.99);
+        for pair in out.chunks_exact(2).collect::<Vec<_>>().windows(2) {
+            assert!(pair[1][0] <= pair[0][0] && pair[0][0] - pair[1][0] < 0.01);
+        }
+        assert_eq!(out[95_998], 0.0);
+    }
+
+    #[test]
+    fn trim_and_fader_multiply_independently() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        let mut mix = |updates: &[ParameterUpdate]| {
+            for update in updates {
+                tx.send(update.clone()).unwrap();
+            }
+            let mut out = [0.0; 2];
+            bus.mix_stereo(&[0.25, 0.25], &[0.0, 0.0], &mut out);
+            out[0]
+        };
+        let trim = |gain| ParameterUpdate::DeckTrim {
+            deck: DeckId::A,
+            gain,
+        };
+        let fader = |gain| ParameterUpdate::DeckGain {
+            deck: DeckId::A,
+            gain,
+        };
+
+        approx_eq(mix(&[ParameterUpdate::Crossfader(0.0), trim(2.0)]), 0.5);
+        approx_eq(mix(&[fader(0.5)]), 0.25);
+        // Moving one leaves the other where it was.
+        approx_eq(mix(&[trim(1.0)]), 0.125);
+        approx_eq(mix(&[fader(1.0)]), 0.25);
+        // Trim boosts up to +12 dB, the fader never above unity.
+        approx_eq(mix(&[trim(10.0), fader(3.0)]), 0.25 * MAX_TRIM);
+        // Negative trims close the channel and NaN ones are ignored.
+        approx_eq(mix(&[trim(-1.0)]), 0.0);
+        approx_eq(mix(&[trim(0.5), trim(f32::NAN)]), 0.125);
+    }
+
+    #[test]
+    fn mute_and_solo_fade_the_right_decks() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::Crossfader(0.5)).unwrap();
+        // Deck A plays 1.0 on the left, deck B 1.0 on the right.
+        let mut heard = |update: Option<ParameterUpdate>| {
+            if let Some(update) = update {
+                tx.send(update).unwrap();
+            }
+            let mut out = vec![0.0; 4_800];
+            bus.mix_stereo(
+                &[1.0, 0.0].repeat(2_400),
+                &[0.0, 1.0].repeat(2_400),
+                &mut out,
+            );
+            for pair in out.chunks_exact(2).collect::<Vec<_>>().windows(2) {
+                assert!((pair[1][0] - pair[0][0]).abs() < 0.005);
+                assert!((pair[1][1] - pair[0][1]).abs() < 0.005);
+            }
+            let end = &out[4_798..];
+            let level = std::f32::consts::FRAC_1_SQRT_2;
+            [end[0] > level - 1e-4, end[1] > level - 1e-4]
+        };
+        let mute = |deck, on| Some(ParameterUpdate::DeckMute { deck, on });
+        let solo = |deck, on| Some(ParameterUpdate::DeckSolo { deck, on });
+
+        assert_eq!(heard(None), [true, true]);
+        assert_eq!(heard(mute(DeckId::A, true)), [false, true]);
+        // Soloing a muted deck overrides its mute and silences the rest.
+        assert_eq!(heard(solo(DeckId::A, true)), [true, false]);
+        assert_eq!(heard(solo(DeckId::B, true)), [true, true]);
+        assert_eq!(heard(solo(DeckId::A, false)), [false, true]);
+        // With no solo left, the mutes apply again.
+        assert_eq!(heard(solo(DeckId::B, false)), [false, true]);
+        assert_eq!(heard(mute(DeckId::A, false)), [true, true]);
+    }
+
+    #[test]
+    fn extra_decks_mix_past_the_crossfader() {
+        for decks in [3, 4] {
+            let (tx, rx) = parameter_channel(8);
+            let mut bus = SummingBus::with_decks(decks, rx);
+            assert_eq!(bus.deck_count(), decks);
+            tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+            tx.send(ParameterUpdate::DeckGain {
+                deck: DeckId(2),
+                gain: 0.5,
+            })
+            .unwrap();
+            // Deck N plays 2^-N on the left.
+            let inputs: Vec<Vec<f32>> = (0..decks)
+                .map(|deck| [0.5f32.powi(deck as i32), 0.0].repeat(4))
+                .collect();
+            let inputs: Vec<&[f32]> = inputs.iter().map(Vec::as_slice).collect();
+            let mut out = [0.0; 8];
+            bus.mix(&inputs, &mut out);
+            // A is full on, B is faded out, C at half and D at unity.
+            let expected = 1.0 + 0.25 * 0.5 + if decks == 4 { 0.125 } else { 0.0 };
+            for frame in out.chunks_exact(2) {
+                approx_eq(frame[0], expected);
+                assert_eq!(frame[1], 0.0);
+            }
+            let faders = bus.fader_reader();
+            assert_eq!(faders.gain(DeckId(2)), Some(0.5));
+            assert_eq!(faders.gain(DeckId(decks)), None);
+        }
+    }
+
+    #[test]
+    fn updates_for_missing_decks_are_ignored() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::with_decks(3, rx);
+        for update in [
+            ParameterUpdate::DeckGain {
+                deck: DeckId(3),
+                gain: 0.0,
+            },
+            ParameterUpdate::DeckMute {
+                deck: DeckId(7),
+                on: true,
+            },
+            ParameterUpdate::DeckFilter {
+                deck: DeckId(usize::MAX),
+                position: -1.0,
+            },
+        ] {
+            tx.send(update).unwrap();
+        }
+        let mut out = [0.0; 2];
+        bus.mix(&[&[0.0; 2], &[0.0; 2], &[1.0, 1.0]], &mut out);
+        assert_eq!(out, [1.0, 1.0]);
+        assert!(!bus.is_muted(DeckId(7)) && !bus.is_audible(DeckId(7)));
+        assert!(bus.set_deck_fx(DeckId(3), None).is_none());
+    }
+
+    #[test]
+    fn thru_deck_ignores_the_crossfader() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::CrossfaderAssign {
+            deck: DeckId::A,
+            assign: XfAssign::Thru,
+        })
+        .unwrap();
+        tx.send(ParameterUpdate::DeckGain {
+            deck: DeckId::A,
+            gain: 0.5,
+        })
+        .unwrap();
+        // Long enough for the move off side A to settle.
+        let mut out = [0.0; 4_096];
+        bus.mix_stereo(&[1.0; 4_096], &[0.0; 4_096], &mut out);
+        for step in 0..=10 {
+            tx.send(ParameterUpdate::Crossfader(step as f32 / 10.0))
+                .unwrap();
+            bus.mix_stereo(&[1.0; 4_096], &[0.0; 4_096], &mut out);
+            assert!(out.iter().all(|s| *s == 0.5), "{step}");
+        }
+    }
+
+    #[test]
+    fn decks_on_one_side_share_its_gain() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::with_decks(3, rx);
+        tx.send(ParameterUpdate::CrossfaderAssign {
+            deck: DeckId(2),
+            assign: XfAssign::A,
+        })
+        .unwrap();
+        // Deck A on the left, deck C on the right.
+        let mut out = [0.0; 4_096];
+        let inputs: [&[f32]; 3] = [
+            &[1.0, 0.0].repeat(2_048),
+            &[0.0; 4_096],
+            &[0.0, 1.0].repeat(2_048),
+        ];
+        for position in [0.0, 0.3, 0.5, 0.8, 1.0] {
+            tx.send(ParameterUpdate::Crossfader(position)).unwrap();
+            bus.mix(&inputs, &mut out);
+            let frame = &out[out.len() - 2..];
+            assert_eq!(frame[0], frame[1], "{position}");
+        }
+        // Fully over to B, both are gone.
+        assert_eq!(out[out.len() - 1], 0.0);
+    }
+
+    #[test]
+    fn reassigning_a_deck_fades_instead_of_clicking() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let mut out = [0.0; 4_096];
+        bus.mix_stereo(&[1.0; 4_096], &[0.0; 4_096], &mut out);
+        assert_eq!(out[4_095], 1.0);
+
+        // Deck A moves to the closed side, then back.
+        for (assign, settled) in [(XfAssign::B, 0.0), (XfAssign::A, 1.0)] {
+            tx.send(ParameterUpdate::CrossfaderAssign {
+                deck: DeckId::A,
+                assign,
+            })
+            .unwrap();
+            let last = out[4_095];
+            bus.mix_stereo(&[1.0; 4_096], &[0.0; 4_096], &mut out);
+            let mut previous = last;
+            for frame in out.chunks_exact(2) {
+                assert!(
+                    (frame[0] - previous).abs() < 0.01,
+                    "{previous} -> {}",
+                    frame[0]
+                );
+                previous = frame[0];
+            }
+            assert_eq!(out[4_095], settled);
+        }
+    }
+
+    #[test]
+    fn bad_buffers_are_refused_without_touching_the_output() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::MasterGain(0.5)).unwrap();
+        let mut out = [7.0; 8];
+        assert_eq!(
+            bus.try_mix_stereo(&[1.0; 8], &[1.0; 6], &mut out),
+            Err(MixError::LengthMismatch {
+                deck_a: 8,
+                deck_b: 6,
+                output: 8,
+            })
+        );
+        assert_eq!(
+            bus.try_mix_stereo(&[1.0; 7], &[1.0; 7], &mut out[..7]),
+            Err(MixError::OddFrameCount(7))
+        );
+        assert_eq!(
+            bus.try_mix(&[&[1.0; 8]], &mut out),
+            Err(MixError::DeckCount {
+                expected: 2,
+                given: 1,
+            })
+        );
+        let error = bus.try_mix(&[&[1.0; 8], &[1.0; 4]], &mut out).unwrap_err();
+        assert_eq!(
+            error,
+            MixError::DeckLength {
+                deck: DeckId::B,
+                len: 4,
+                output: 8,
+            }
+        );
+        let error: &dyn std::error::Error = &error;
+        assert_eq!(
+            error.to_string(),
+            "deck B buffer holds 4 samples, the output 8"
+        );
+        assert_eq!(out, [7.0; 8]);
+
+        // A good block afterwards mixes as usual.
+        bus.try_mix_stereo(&[1.0; 8], &[0.0; 8], &mut out).unwrap();
+        approx_eq(out[0], 0.5 * std::f32::consts::FRAC_1_SQRT_2);
+    }
+
+    #[test]
+    fn planar_mix_matches_the_interleaved_one() {
+        let [mut interleaved, mut planar] = bus_pair(&[
+            ParameterUpdate::CrossfaderRamp {
+                target: 0.8,
+                seconds: 0.005,
+            },
+            ParameterUpdate::DeckGain {
+                deck: DeckId::A,
+                gain: 0.7,
+            },
+            ParameterUpdate::StereoWidth(1.5),
+            ParameterUpdate::MasterGain(0.9),
+        ]);
+
+        let frames = 512;
+        let deck = |freq: f32, ch: usize| -> Vec<f32> {
+            (0..frames)
+                .map(|i| (i as f32 * freq + ch as f32).sin() * 0.8)
+                .collect()
+        };
+        let (a, b) = (
+            [deck(0.01, 0), deck(0.03, 1)],
+            [deck(0.02, 2), deck(0.05, 3)],
+        );
+        let interleave = |planes: &[Vec<f32>; 2]| -> Vec<f32> {
+            (0..frames)
+                .flat_map(|i| [planes[0][i], planes[1][i]])
+                .collect()
+        };
+        let mut out = vec![0.0; frames * 2];
+        interleaved.mix_stereo(&interleave(&a), &interleave(&b), &mut out);
+        let (mut left, mut right) = (vec![0.0; frames], vec![0.0; frames]);
+        planar.mix_planar([&a[0], &a[1]], [&b[0], &b[1]], [&mut left, &mut right]);
+        assert_eq!(interleave(&[left.clone(), right.clone()]), out);
+
+        assert_eq!(
+            planar.try_mix_planar([&a[0], &a[1][..4]], [&b[0], &b[1]], [&mut left, &mut right]),
+            Err(MixError::ChannelLength {
+                buffer: "deck A",
+                channel: 1,
+                len: 4,
+                output: frames,
+            })
+        );
+    }
+
+    #[test]
+    fn quad_layout_mixes_every_channel_alike() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx).with_channel_layout(ChannelLayout::Quad);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::DeckGain {
+            deck: DeckId::A,
+            gain: 0.5,
+        })
+        .unwrap();
+        tx.send(ParameterUpdate::MasterGain(0.8)).unwrap();
+        let deck_a = [0.1, 0.2, 0.3, 0.4, -0.1, -0.2, -0.3, -0.4];
+        let mut out = [0.0; 8];
+        bus.mix(&[&deck_a, &[1.0; 8]], &mut out);
+        for (out, input) in out.iter().zip(deck_a) {
+            approx_eq(*out, input * 0.5 * 0.8);
+        }
+
+        // The crossfader takes every channel of a deck across together.
+        tx.send(ParameterUpdate::Crossfader(1.0)).unwrap();
+        bus.mix(&[&deck_a, &[1.0; 8]], &mut out);
+        for sample in out {
+            approx_eq(sample, 0.8);
+        }
+
+        assert_eq!(
+            bus.try_mix(&[&[0.0; 6], &[0.0; 6]], &mut [0.0; 6]),
+            Err(MixError::PartialFrame {
+                len: 6,
+                channels: 4,
+            })
+        );
+        assert_eq!(
+            bus.try_mix_stereo(&[0.0; 8], &[0.0; 8], &mut out),
+            Err(MixError::StereoOnly { channels: 4 })
+        );
+    }
+
+    #[test]
+    fn quad_layout_steps_parameters_as_stereo_does() {
+        let [mut stereo, quad] = bus_pair(&[
+            ParameterUpdate::Crossfader(0.0),
+            ParameterUpdate::CrossfaderRamp {
+                target: 1.0,
+                seconds: 0.002,
+            },
+            ParameterUpdate::MasterGain(0.8),
+        ]);
+        let mut quad = quad.with_channel_layout(ChannelLayout::Quad);
+        let _schedules = [&mut stereo, &mut quad].map(|bus| {
+            let (schedule, receiver) = schedule::schedule_channel(4);
+            bus.set_schedule(Some(receiver));
+            let gain = ParameterUpdate::DeckGain {
+                deck: DeckId::B,
+                gain: 0.5,
+            };
+            schedule.send(37, gain).unwrap();
+            schedule
+        });
+        let mut state = 0x5eed_f00du32;
+        let (deck_a, deck_b) = (noise(&mut state, 512), noise(&mut state, 512));
+        // Each stereo frame on the front and rear pairs alike.
+        let surround = |deck: &[f32]| -> Vec<f32> {
+            deck.chunks(2)
+                .flat_map(|frame| [frame, frame].concat())
+                .collect()
+        };
+        let mut out = vec![0.0; 512];
+        stereo.mix_stereo(&deck_a, &deck_b, &mut out);
+        let mut wide = vec![0.0; 1_024];
+        quad.mix(&[&surround(&deck_a), &surround(&deck_b)], &mut wide);
+        for (frame, wide) in out.chunks(2).zip(wide.chunks(4)) {
+            for (out, wide) in frame.iter().chain(frame).zip(wide) {
+                approx_eq(*wide, *out);
+            }
+        }
+        assert_eq!(quad.crossfader, stereo.crossfader);
+        assert_eq!(quad.channels[1].gain, 0.5);
+    }
+
+    #[test]
+    fn stereo_layout_mixes_as_mix_stereo() {
+        let [mut stereo, mut general] = bus_pair(&[
+            ParameterUpdate::Crossfader(0.3),
+            ParameterUpdate::DeckTrim {
+                deck: DeckId::B,
+                gain: 1.5,
+            },
+        ]);
+        assert_eq!(general.channel_layout(), ChannelLayout::Stereo);
+        let deck_a: Vec<f32> = (0..256).map(|i| (i as f32 * 0.1).sin()).collect();
+        let deck_b: Vec<f32> = (0..256).map(|i| (i as f32 * 0.07).cos()).collect();
+        let (mut a, mut b) = (vec![0.0; 256], vec![0.0; 256]);
+        stereo.mix_stereo(&deck_a, &deck_b, &mut a);
+        general.mix(&[&deck_a, &deck_b], &mut b);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn additive_mix_sums_into_the_output() {
+        let [mut overwrite, mut add] = bus_pair(&[
+            ParameterUpdate::Crossfader(0.25),
+            ParameterUpdate::MasterGain(0.6),
+        ]);
+        let deck_a: Vec<f32> = (0..128).map(|i| (i as f32 * 0.2).sin()).collect();
+        let deck_b: Vec<f32> = (0..128).map(|i| (i as f32 * 0.05).cos()).collect();
+        let prefill: Vec<f32> = (0..128).map(|i| i as f32 / 128.0 - 0.5).collect();
+
+        // The overwriting mix ignores whatever the output held.
+        let mut alone = vec![9.0; 128];
+        overwrite.mix_stereo(&deck_a, &deck_b, &mut alone);
+        let mut zeroed = vec![0.0; 128];
+        overwrite.mix_stereo(&deck_a, &deck_b, &mut zeroed);
+        assert_eq!(alone, zeroed);
+
+        let mut summed = prefill.clone();
+        add.mix_stereo_add(&deck_a, &deck_b, &mut summed);
+        for ((summed, prefill), alone) in summed.iter().zip(&prefill).zip(&alone) {
+            assert_eq!(*summed, prefill + alone);
+        }
+
+        assert_eq!(
+            add.try_mix_stereo_add(&deck_a, &deck_b[..64], &mut summed),
+            Err(MixError::LengthMismatch {
+                deck_a: 128,
+                deck_b: 64,
+                output: 128,
+            })
+        );
+    }
+
+    #[test]
+    fn in_place_mix_matches_the_out_of_place_one() {
+        let mut state = 0x1234_5678u32;
+        let settings = [
+            vec![ParameterUpdate::Crossfader(0.5)],
+            vec![
+                ParameterUpdate::Crossfader(0.1),
+                ParameterUpdate::DeckGain {
+                    deck: DeckId::A,
+                    gain: 0.3,
+                },
+            ],
+            vec![
+                ParameterUpdate::DeckEq {
+                    deck: DeckId::A,
+                    band: EqBand::Low,
+                    gain_db: 6.0,
+                },
+                ParameterUpdate::StereoWidth(0.4),
+                ParameterUpdate::MasterSoftClip(true),
+            ],
+        ];
+        for updates in settings {
+            let [mut out_of_place, mut in_place] = bus_pair(&updates);
+            for _ in 0..4 {
+                let (deck_a, deck_b) = (noise(&mut state, 256), noise(&mut state, 256));
+                let mut out = vec![0.0; 256];
+                out_of_place.mix_stereo(&deck_a, &deck_b, &mut out);
+                let mut mixed = deck_a.clone();
+                in_place.mix_stereo_in_place(&mut mixed, &deck_b);
+                assert_eq!(mixed, out, "{updates:?}");
+            }
+        }
+
+        let mut deck_a = [1.0; 6];
+        assert_eq!(
+            SummingBus::new(parameter_channel(1).1).try_mix_stereo_in_place(&mut deck_a, &[0.0; 4]),
+            Err(MixError::LengthMismatch {
+                deck_a: 6,
+                deck_b: 4,
+                output: 6,
+            })
+        );
+        assert_eq!(deck_a, [1.0; 6]);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn still_blocks_mix_as_the_per_frame_path_does() {
+        let mut state = 0x0bad_cafeu32;
+        // Hot enough to clip now and then.
+        let mut random = |len| -> Vec<f32> {
+            noise(&mut state, len)
+                .into_iter()
+                .map(|s| s * 1.2)
+                .collect()
+        };
+        let [mut vector, mut per_frame] = bus_pair(&[
+            ParameterUpdate::Crossfader(0.35),
+            ParameterUpdate::DeckTrim {
+                deck: DeckId::B,
+                gain: 1.4,
+            },
+            ParameterUpdate::MasterGain(1.1),
+        ]);
+        for frames in [64, 61, 1] {
+            let (deck_a, deck_b) = (random(frames * 2), random(frames * 2));
+            let mut out = vec![0.0; frames * 2];
+            vector.mix_stereo(&deck_a, &deck_b, &mut out);
+            // In place, the decks are read a frame at a time.
+            let mut mixed = deck_a.clone();
+            per_frame.mix_stereo_in_place(&mut mixed, &deck_b);
+            assert_eq!(out, mixed);
+        }
+        assert_eq!(vector.master_clips, per_frame.master_clips);
+        assert!(vector.master_clips > 0);
+        for (vector, per_frame) in vector.channels.iter().zip(&per_frame.channels) {
+            assert_eq!(vector.clips, per_frame.clips);
+            assert_eq!(vector.heard, per_frame.heard);
+        }
+    }
+
+    #[test]
+    fn sanitized_decks_scrub_nan_and_infinity() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (meter, meters) = meter::meter_channel(4);
+        bus.set_meter(Some(meter));
+        bus.set_sanitize_input(true);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let deck_a = [0.5, f32::NAN, f32::INFINITY, 0.5, f32::NEG_INFINITY, 0.25];
+        let mut out = [0.0; 6];
+        bus.mix_stereo(&deck_a, &[f32::NAN; 6], &mut out);
+        assert!(out.iter().all(|s| s.is_finite()), "{out:?}");
+        approx_eq(out[1], 0.0);
+        approx_eq(out[2], 1.0);
+        approx_eq(out[4], -1.0);
+        // Deck B is faded out, but its samples are scrubbed all the same.
+        assert_eq!(meters.latest().unwrap().scrubbed, 9);
+
+        // Off, a NaN goes straight through.
+        bus.set_sanitize_input(false);
+        bus.mix_stereo(&deck_a, &[0.0; 6], &mut out);
+        assert!(out[1].is_nan());
+        assert_eq!(meters.latest().unwrap().scrubbed, 9);
+    }
+
+    #[test]
+    fn dc_block_removes_offset_and_keeps_the_music() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        // 1 kHz at half scale, sitting on a 0.3 offset, for two seconds.
+        let sine = |i: usize| 0.5 * (i as f32 / 48.0 * std::f32::consts::TAU).sin();
+        let deck_a: Vec<f32> = (0..96_000).flat_map(|i| [sine(i) + 0.3; 2]).collect();
+        let mut out = vec![0.0; deck_a.len()];
+        let mut blocks = deck_a.chunks(512).zip(out.chunks_mut(512));
+        for (a, out) in blocks.by_ref().take(10) {
+            bus.mix_stereo(a, &[0.0; 512], out);
+        }
+        tx.send(ParameterUpdate::DcBlock {
+            deck: DeckId::A,
+            on: true,
+        })
+        .unwrap();
+        for (a, out) in blocks {
+            bus.mix_stereo(a, &[0.0; 512], out);
+        }
+
+        // No jump when the blocker comes in.
+        let left: Vec<f32> = out.iter().step_by(2).copied().collect();
+        let largest_step = left[..5_000]
+            .windows(2)
+            .fold(0.0f32, |m, pair| m.max((pair[1] - pair[0]).abs()));
+        assert!(largest_step < 0.07, "{largest_step}");
+
+        // The last half second is a whole number of cycles.
+        let tail = &left[left.len() - 24_000..];
+        let dc = tail.iter().sum::<f32>() / tail.len() as f32;
+        assert!(20.0 * (dc.abs() / 0.3).log10() < -40.0, "{dc}");
+        let rms = (tail.iter().map(|s| (s - dc).powi(2)).sum::<f32>() / tail.len() as f32).sqrt();
+        let level_db = 20.0 * (rms * std::f32::consts::SQRT_2 / 0.5).log10();
+        assert!(level_db.abs() < 0.1, "{level_db}");
+    }
+
+    #[test]
+    fn stereo_width_scales_only_the_sides() {
+        let render = |width: Option<f32>, deck_a: &[f32]| {
+            let (tx, rx) = parameter_channel(4);
+            let mut bus = SummingBus::new(rx);
+            tx.send(ParameterUpdate::Crossfader(0.3)).unwrap();
+            if let Some(width) = width {
+                tx.send(ParameterUpdate::StereoWidth(width)).unwrap();
+            }
+            let mut out = vec![0.0; deck_a.len()];
+            bus.mix_stereo(deck_a, &[0.25, -0.5].repeat(deck_a.len() / 2), &mut out);
+            // Past the glide.
+            out.split_off(deck_a.len() / 2)
+        };
+        let stereo = [0.8, 0.1].repeat(8_192);
+        assert_eq!(render(Some(1.0), &stereo), render(None, &stereo));
+        let mono = render(Some(0.0), &stereo);
+        assert!(mono.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+        let wide = render(Some(2.0), &stereo);
+        let plain = render(None, &stereo);
+        let side = |frame: &[f32]| frame[0] - frame[1];
+        approx_eq(side(&wide[..2]), 2.0 * side(&plain[..2]));
+
+        // A signal that is all mid passes any width untouched.
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let mut out = [0.0; 64];
+        for width in [0.0, 0.5, 1.0, 1.7, 2.0] {
+            tx.send(ParameterUpdate::StereoWidth(width)).unwrap();
+            bus.mix_stereo(&[0.6; 64], &[-0.2; 64], &mut out);
+            let centred = (0.6 - 0.2) * std::f32::consts::FRAC_1_SQRT_2;
+            assert!(out.iter().all(|s| (s - centred).abs() < 1e-6), "{width}");
+        }
+    }
+
+    #[test]
+    fn deck_inverted_against_itself_cancels() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx).with_crossfader_curve(CrossfaderCurve::Linear);
+        tx.send(ParameterUpdate::DeckPhaseInvert {
+            deck: DeckId::B,
+            left: true,
+            right: true,
+        })
+        .unwrap();
+        let music: Vec<f32> = (0..16_384)
+            .map(|i| (i as f32 * 0.013).sin() * 0.7)
+            .collect();
+        let mut out = vec![0.0; music.len()];
+        bus.mix_stereo(&music, &music, &mut out);
+        assert!(out[out.len() / 2..].iter().all(|s| *s == 0.0));
+
+        // One side only leaves the other summing.
+        tx.send(ParameterUpdate::DeckPhaseInvert {
+            deck: DeckId::B,
+            left: true,
+            right: false,
+        })
+        .unwrap();
+        bus.mix_stereo(&music, &music, &mut out);
+        let frame = &out[out.len() - 2..];
+        assert_eq!(frame[0], 0.0);
+        approx_eq(frame[1], music[music.len() - 1]);
+    }
+
+    #[test]
+    fn deck_ids_serialize_as_letters() {
+        assert_eq!(serde_json::to_string(&DeckId::B).unwrap(), "\"B\"");
+        assert_eq!(serde_json::to_string(&DeckId(3)).unwrap(), "\"D\"");
+        assert_eq!(serde_json::from_str::<DeckId>("\"C\"").unwrap(), DeckId(2));
+        assert_eq!(
+            serde_json::from_str::<DeckId>("\"30\"").unwrap(),
+            DeckId(30)
+        );
+        assert!(serde_json::from_str::<DeckId>("\"deck\"").is_err());
+    }
+
+    #[test]
+    fn mute_and_solo_state_is_queryable() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::DeckMute {
+            deck: DeckId::B,
+            on: true,
+        })
+        .unwrap();
+        tx.send(ParameterUpdate::DeckSolo {
+            deck: DeckId::B,
+            on: true,
+        })
+        .unwrap();
+        bus.mix_stereo(&[], &[], &mut []);
+        assert!(bus.is_muted(DeckId::B) && bus.is_soloed(DeckId::B));
+        assert!(!bus.is_audible(DeckId::A) && bus.is_audible(DeckId::B));
+        assert_eq!(audible_decks([true, false], [false; 2]), [false, true]);
+    }
+
+    #[test]
+    fn cued_deck_is_heard_with_its_fader_down() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        let mut mix = |updates: &[ParameterUpdate]| {
+            for update in updates {
+                tx.send(update.clone()).unwrap();
+            }
+            let (mut main, mut cue) = (vec![0.0; 4_800], vec![0.0; 4_800]);
+            bus.mix_stereo_with_cue(&[0.5; 4_800], &[0.25; 4_800], &mut main, &mut cue);
+            (main[4_798], cue[4_798])
+        };
+        let cue = |deck, on| ParameterUpdate::DeckCue { deck, on };
+
+        // Deck A's fader is down and the crossfader is over on B.
+        let (main, headphones) = mix(&[
+            ParameterUpdate::DeckGain {
+                deck: DeckId::A,
+                gain: 0.0,
+            },
+            ParameterUpdate::Crossfader(1.0),
+        ]);
+        approx_eq(main, 0.25);
+        assert_eq!(headphones, 0.0);
+        let (_, headphones) = mix(&[cue(DeckId::A, true)]);
+        approx_eq(headphones, 0.5);
+        let (_, headphones) = mix(&[cue(DeckId::B, true), ParameterUpdate::CueGain(0.5)]);
+        approx_eq(headphones, 0.375);
+        // Halfway between the cued decks and the master.
+        let (_, headphones) = mix(&[cue(DeckId::B, false), ParameterUpdate::CueMix(0.5)]);
+        approx_eq(headphones, (0.5 + 0.25) / 2.0 * 0.5);
+        let (main, headphones) =
+            mix(&[ParameterUpdate::CueMix(1.0), ParameterUpdate::CueGain(1.0)]);
+        approx_eq(headphones, main);
+    }
+
+    #[test]
+    fn booth_carries_the_program_at_its_own_level() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        bus.set_smoothing_ms(1.0);
+        tx.send(ParameterUpdate::MasterGain(0.5)).unwrap();
+        tx.send(ParameterUpdate::BoothGain(0.2)).unwrap();
+        let deck_a: Vec<f32> = (0..4_800).map(|i| (i as f32 * 0.01).sin()).collect();
+        let deck_b: Vec<f32> = (0..4_800).map(|i| (i as f32 * 0.003).cos()).collect();
+        let (mut main, mut booth) = (vec![0.0; 4_800], vec![0.0; 4_800]);
+        bus.mix_stereo_multi(&deck_a, &deck_b, &mut main, Some(&mut booth), None);
+        // Settled, the booth is the master scaled by the ratio of their gains.
+        for (main, booth) in main[2_400..].iter().zip(&booth[2_400..]) {
+            approx_eq(*booth, main * 0.4);
+        }
+
+        // Turning the booth up glides like any other gain, leaving the master alone.
+        tx.send(ParameterUpdate::BoothGain(1.0)).unwrap();
+        bus.mix_stereo_multi(&deck_a, &deck_b, &mut main, Some(&mut booth), None);
+        let ratios: Vec<f32> = main
+            .chunks_exact(2)
+            .zip(booth.chunks_exact(2))
+            .filter(|(main, _)| main[0].abs() > 0.1)
+            .map(|(main, booth)| booth[0] / main[0])
+            .collect();
+        assert!(ratios[0] < 1.0);
+        assert!(ratios.windows(2).all(|pair| pair[1] >= pair[0] - 1e-4));
+        approx_eq(ratios[ratios.len() - 1], 2.0);
+
+        // Taken ahead of the limiter, a hot booth is not pulled down with the master.
+        bus.set_limiter(Some(Limiter::new(48_000, Default::default())));
+        tx.send(ParameterUpdate::MasterGain(4.0)).unwrap();
+        bus.mix_stereo_multi(&deck_a, &deck_b, &mut main, Some(&mut booth), None);
+        assert!(main.iter().all(|s| s.abs() < 1.0));
+        assert!(booth.iter().any(|s| s.abs() > 1.2));
+    }
+
+    #[test]
+    fn smoothed_gains_ramp_across_the_block() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::DeckGain {
+            deck: DeckId::A,
+            gain: 0.0,
+        })
+        .unwrap();
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let mut out = [0.0; 960];
+        bus.mix_stereo(&[1.0; 960], &[0.0; 960], &mut out);
+        assert_eq!(out, [0.0; 960]);
+
+        // 10 ms at 48 kHz is 480 frames, the length of the block.
+        bus.set_smoothing_ms(10.0);
+        tx.send(ParameterUpdate::DeckGain {
+            deck: DeckId::A,
+            gain: 1.0,
+        })
+        .unwrap();
+        bus.mix_stereo(&[1.0; 960], &[0.0; 960], &mut out);
+        assert!(out[0] > 0.0 && out[0] < 0.01, "{}", out[0]);
+        for pair in out.chunks_exact(2).collect::<Vec<_>>().windows(2) {
+            assert!(pair[1][0] > pair[0][0]);
+        }
+        assert!(
+            (out[958] - (1.0 - (-1.0f32).exp())).abs() < 1e-3,
+            "{}",
+            out[958]
+        );
+
+        // The equal-power curve is evaluated on the gliding position.
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        bus.set_smoothing_ms(10.0);
+        tx.send(ParameterUpdate::Crossfader(1.0)).unwrap();
+        bus.mix_stereo(&[1.0; 960], &[0.0; 960], &mut out);
+        for (frame, gain) in out.chunks_exact(2).map(|frame| frame[0]).enumerate() {
+            let position = 1.0 - 0.5 * (-(frame as f32 + 1.0) / 480.0).exp();
+            let expected = (position * std::f32::consts::FRAC_PI_2).cos();
+            assert!(
+                (gain - expected).abs() < 1e-3,
+                "{frame}: {gain} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn mixes_with_all_gain_stages() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
 
         // Push parameter updates from a simulated control thread.
         thread::spawn(move || {
@@ -178,7 +4041,7 @@ mod tests {
                 gain: 0.5,
             })
             .unwrap();
-            tx.send(ParameterUpdate::DeckGain {
+            tx.send(ParameterUpdate::DeckTrim {
                 deck: DeckId::B,
                 gain: 1.5,
             })
@@ -207,4 +4070,303 @@ mod tests {
         approx_eq(out[2], expected_l);
         approx_eq(out[3], expected_r);
     }
+
+    #[test]
+    fn deck_fx_runs_pre_fader_and_takes_updates() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        let mut delay = fx::DelayFx::new(1_000);
+        delay.set_param(fx::DelayFx::TIME_MS, 2.0);
+        delay.set_param(fx::DelayFx::FEEDBACK, 0.0);
+        delay.set_param(fx::DelayFx::WET, 1.0);
+        delay.reset();
+        assert!(bus.set_deck_fx(DeckId::A, Some(Box::new(delay))).is_none());
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::DeckGain {
+            deck: DeckId::A,
+            gain: 0.5,
+        })
+        .unwrap();
+
+        let mut deck_a = [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let mut deck_b = [0.0; 8];
+        let mut out = [0.0; 8];
+        bus.process(&mut deck_a, &mut deck_b, &mut out);
+        assert_eq!(out, [0.0, 0.0, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0]);
+
+        tx.send(ParameterUpdate::DeckEffect {
+            deck: DeckId::A,
+            param: fx::DelayFx::WET,
+            value: 0.0,
+        })
+        .unwrap();
+        let mut deck_a = [0.25; 8];
+        bus.process(&mut deck_a, &mut [0.0; 8], &mut out);
+        assert!(out[6] < 0.125 + 1e-6 && out[6] > 0.0);
+    }
+
+    #[test]
+    fn crossfader_ramps_per_frame_and_stops_at_target() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx).with_sample_rate(1_000);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::CrossfaderRamp {
+            target: 1.0,
+            seconds: 0.1,
+        })
+        .unwrap();
+
+        let mut out = [0.0; 400];
+        bus.mix_stereo(&[0.0; 400], &[1.0; 400], &mut out);
+        let deck_b: Vec<f32> = out.iter().step_by(2).copied().collect();
+        assert!(deck_b[..100].windows(2).all(|w| w[1] > w[0]));
+        approx_eq(deck_b[49], (0.5 * std::f32::consts::FRAC_PI_2).sin());
+        assert!(deck_b[99..].iter().all(|s| *s == 1.0));
+        assert!(bus.crossfader_ramp.is_none());
+        // Published gains are where the ramp ended, not where the block started.
+        let [a, b] = bus.fader_reader().gains();
+        assert!(a.abs() < 1e-6, "{a}");
+        approx_eq(b, 1.0);
+    }
+
+    #[test]
+    fn nan_crossfader_ramp_target_is_ignored() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx).with_sample_rate(1_000);
+        tx.send(ParameterUpdate::Crossfader(0.25)).unwrap();
+        tx.send(ParameterUpdate::CrossfaderRamp {
+            target: f32::NAN,
+            seconds: 0.1,
+        })
+        .unwrap();
+        let mut out = [0.0; 400];
+        bus.mix_stereo(&[1.0; 400], &[1.0; 400], &mut out);
+        assert_eq!(bus.crossfader, 0.25);
+        assert!(bus.crossfader_ramp.is_none());
+        assert!(out.iter().all(|s| s.is_finite()));
+
+        // Nor does it end a ramp under way.
+        tx.send(ParameterUpdate::CrossfaderRamp {
+            target: 1.0,
+            seconds: 1.0,
+        })
+        .unwrap();
+        tx.send(ParameterUpdate::CrossfaderRamp {
+            target: f32::NAN,
+            seconds: 0.1,
+        })
+        .unwrap();
+        bus.mix_stereo(&[1.0; 400], &[1.0; 400], &mut out);
+        assert_eq!(bus.crossfader_ramp.map(|(target, _)| target), Some(1.0));
+        assert!(bus.crossfader > 0.25 && bus.crossfader < 1.0);
+    }
+
+    #[test]
+    fn record_tap_hands_the_master_to_another_thread() {
+        let (tx, rx) = parameter_channel(4);
+        let (tap, mut consumer, overruns) = audio_ring_buffer(4_096);
+        let mut bus = SummingBus::new(rx);
+        assert!(bus.set_record_tap(Some(tap)).is_none());
+        tx.send(ParameterUpdate::Record(true)).unwrap();
+
+        let mixer = thread::spawn(move || {
+            let mut mixed = Vec::new();
+            for block in 0..200 {
+                let deck_a: Vec<f32> = (0..512).map(|i| ((block * 512 + i) as f32).sin()).collect();
+                let mut out = [0.0; 512];
+                bus.mix_stereo(&deck_a, &[0.25; 512], &mut out);
+                mixed.extend_from_slice(&out);
+                // Give the writer time to keep up, as a real callback would.
+                thread::sleep(std::time::Duration::from_micros(200));
+            }
+            mixed
+        });
+        let mut recorded = Vec::new();
+        let mut chunk = [0.0; 256];
+        while !mixer.is_finished() || consumer.occupancy() > 0 {
+            let frames = consumer.occupancy().min(128);
+            let read = consumer.read(&mut chunk[..frames * 2]);
+            recorded.extend_from_slice(&chunk[..read]);
+        }
+        let mixed = mixer.join().unwrap();
+        assert_eq!(overruns.load(Ordering::Relaxed), 0);
+        assert_eq!(recorded, mixed);
+    }
+
+    #[test]
+    fn stalled_recorder_counts_overruns_without_blocking() {
+        let (tx, rx) = parameter_channel(4);
+        let (tap, mut consumer, overruns) = audio_ring_buffer(1_000);
+        let mut bus = SummingBus::new(rx);
+        bus.set_record_tap(Some(tap));
+        let mut out = [0.0; 512];
+        // Not recording yet, so nothing is queued.
+        bus.mix_stereo(&[0.5; 512], &[0.0; 512], &mut out);
+        assert_eq!(consumer.occupancy(), 0);
+
+        tx.send(ParameterUpdate::Record(true)).unwrap();
+        for _ in 0..5 {
+            bus.mix_stereo(&[0.5; 512], &[0.0; 512], &mut out);
+        }
+        // Three 256-frame blocks fit whole; the two after are dropped whole.
+        assert_eq!(consumer.occupancy(), 768);
+        assert_eq!(overruns.load(Ordering::Relaxed), 2);
+        assert!(bus.snapshot().recording);
+
+        // Once drained, recording picks up again.
+        let mut drained = vec![0.0; 768 * 2];
+        consumer.read(&mut drained);
+        assert!(drained.iter().all(|&s| s == out[0]));
+        bus.mix_stereo(&[0.5; 512], &[0.0; 512], &mut out);
+        assert_eq!(consumer.occupancy(), 256);
+        tx.send(ParameterUpdate::Record(false)).unwrap();
+        bus.mix_stereo(&[0.5; 512], &[0.0; 512], &mut out);
+        assert_eq!(consumer.occupancy(), 256);
+        assert_eq!(overruns.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn timed_crossfade_follows_its_line_to_the_frame() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::CrossfadeTo {
+            target: 1.0,
+            duration_frames: 1_000,
+        })
+        .unwrap();
+
+        let mut out = [0.0; 512];
+        let mut mixed = 0;
+        for _ in 0..3 {
+            bus.mix_stereo(&[0.0; 512], &[1.0; 512], &mut out);
+            mixed += 256;
+            approx_eq(bus.crossfader, mixed as f32 / 1_000.0);
+            let progress = bus.snapshot().crossfade.unwrap();
+            assert_eq!(progress.elapsed_frames, mixed);
+            assert_eq!(progress.duration_frames, 1_000);
+            approx_eq(progress.fraction(), bus.crossfader);
+        }
+        // Frame by frame, deck B rises along the equal-power curve.
+        let deck_b: Vec<f32> = out.iter().step_by(2).copied().collect();
+        approx_eq(deck_b[255], bus.crossfader_gains(0.768).1);
+        approx_eq(deck_b[99], bus.crossfader_gains(0.612).1);
+
+        bus.mix_stereo(&[0.0; 512], &[1.0; 512], &mut out);
+        assert_eq!(bus.crossfader, 1.0);
+        assert!(bus.snapshot().crossfade.is_none());
+        // Frame 999 of the fade is the first at the target.
+        let deck_b: Vec<f32> = out.iter().step_by(2).copied().collect();
+        assert!(deck_b[230] < 1.0);
+        assert!(deck_b[231..].iter().all(|&s| s == 1.0));
+
+        // Touching the crossfader by hand ends a fade where it is.
+        tx.send(ParameterUpdate::CrossfadeTo {
+            target: 0.0,
+            duration_frames: 48_000,
+        })
+        .unwrap();
+        bus.mix_stereo(&[0.0; 512], &[1.0; 512], &mut out);
+        assert!(bus.snapshot().crossfade.is_some());
+        tx.send(ParameterUpdate::Crossfader(0.75)).unwrap();
+        bus.mix_stereo(&[0.0; 512], &[1.0; 512], &mut out);
+        assert_eq!(bus.crossfader, 0.75);
+        assert!(bus.snapshot().crossfade.is_none());
+    }
+
+    #[test]
+    fn nan_crossfade_target_is_ignored() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::Crossfader(0.25)).unwrap();
+        tx.send(ParameterUpdate::CrossfadeTo {
+            target: f32::NAN,
+            duration_frames: 0,
+        })
+        .unwrap();
+        let mut out = [0.0; 512];
+        bus.mix_stereo(&[1.0; 512], &[1.0; 512], &mut out);
+        assert_eq!(bus.crossfader, 0.25);
+        assert!(out.iter().all(|s| s.is_finite()));
+
+        // Nor does it end a fade under way.
+        tx.send(ParameterUpdate::CrossfadeTo {
+            target: 1.0,
+            duration_frames: 48_000,
+        })
+        .unwrap();
+        tx.send(ParameterUpdate::CrossfadeTo {
+            target: f32::NAN,
+            duration_frames: 1_000,
+        })
+        .unwrap();
+        bus.mix_stereo(&[1.0; 512], &[1.0; 512], &mut out);
+        assert_eq!(bus.snapshot().crossfade.unwrap().elapsed_frames, 256);
+    }
+
+    #[test]
+    fn sampler_sums_after_the_crossfader() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx);
+        let (sampler, shots) = Sampler::new(48_000);
+        bus.set_sampler(sampler);
+        shots
+            .send(sampler::SamplerCommand::Load {
+                slot: 0,
+                track: Box::new(deck::Track::from_interleaved(vec![0.5; 2_000], 48_000)),
+                choke_group: None,
+            })
+            .unwrap();
+        shots.trigger(0);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::MasterGain(0.5)).unwrap();
+
+        // Deck B is faded out by the crossfader, the one-shot is not.
+        let mut out = [0.0; 400];
+        bus.process(&mut [0.0; 400], &mut [1.0; 400], &mut out);
+        assert_eq!(&out[300..], &[0.25; 100][..]);
+    }
+
+    #[test]
+    fn deck_filter_follows_updates() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx).with_sample_rate(48_000);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::DeckFilter {
+            deck: DeckId::A,
+            position: -1.0,
+        })
+        .unwrap();
+        tx.send(ParameterUpdate::DeckFilterResonance {
+            deck: DeckId::A,
+            resonance: 0.5,
+        })
+        .unwrap();
+
+        // A Nyquist-rate tone is all but gone once the low-pass has closed.
+        let mut out = [0.0; 1_024];
+        for _ in 0..20 {
+            let mut deck_a: Vec<f32> = (0..1_024).map(|i| [1.0, -1.0][i / 2 % 2]).collect();
+            bus.process(&mut deck_a, &mut [0.0; 1_024], &mut out);
+        }
+        assert!(out.iter().all(|s| s.abs() < 1e-3));
+    }
+
+    /// The core must keep building for the browser; needs the `wasm32-unknown-unknown` target.
+    #[test]
+    #[ignore = "runs a nested cargo build"]
+    fn core_builds_for_wasm32() {
+        let status = std::process::Command::new(env!("CARGO"))
+            .args(["check", "--lib", "--target", "wasm32-unknown-unknown"])
+            .args(["--no-default-features", "--features", "wasm"])
+            .arg("--manifest-path")
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+            .env(
+                "CARGO_TARGET_DIR",
+                concat!(env!("CARGO_MANIFEST_DIR"), "/target/wasm-check"),
+            )
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
 }
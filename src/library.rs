@@ -0,0 +1,5 @@
+pub mod index;
+pub mod playlist;
+
+pub use index::{IndexEntry, LibraryIndex, Query, ScanJob, SearchField, SortOrder};
+pub use playlist::{Crate, Playlist, PlaylistError, TrackRef};
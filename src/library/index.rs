@@ -0,0 +1,487 @@
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use walkdir::WalkDir;
+
+use crate::analysis::gain::TrackGain;
+use crate::analysis::key::{key_compatibility, Compatibility, Key};
+use crate::analysis::{AnalysisJob, CancelToken};
+use crate::metadata::read_metadata;
+
+/// File extensions picked up by a scan, compared case-insensitively.
+const AUDIO_EXTENSIONS: [&str; 9] = [
+    "mp3", "flac", "ogg", "m4a", "mp4", "aac", "wav", "aif", "aiff",
+];
+
+#[derive(Debug, Error)]
+pub enum IndexError {
+    #[error("failed to access library index: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse library index: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// One track in the library, with the file stamp it was read at.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch, if the platform reports one.
+    pub modified: Option<u64>,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub duration_seconds: Option<f64>,
+    pub bpm: Option<f64>,
+    pub key: Option<Key>,
+    pub gain: Option<TrackGain>,
+    pub has_artwork: bool,
+}
+
+/// Paths touched by a scan; everything else was left as indexed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanReport {
+    pub added: Vec<PathBuf>,
+    pub updated: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub unchanged: usize,
+}
+
+/// Scan progress shared with the thread doing the scan.
+#[derive(Debug, Clone, Default)]
+pub struct ScanProgress {
+    scanned: Arc<AtomicUsize>,
+    total: Arc<AtomicUsize>,
+}
+
+impl ScanProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Files checked so far.
+    pub fn scanned(&self) -> usize {
+        self.scanned.load(Ordering::Relaxed)
+    }
+
+    /// Audio files found under the roots; zero until the walk has finished.
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn fraction(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => self.scanned() as f64 / total as f64,
+        }
+    }
+}
+
+/// Library scan running on a worker thread.
+#[derive(Debug)]
+pub struct ScanJob {
+    job: AnalysisJob<(LibraryIndex, ScanReport)>,
+    progress: ScanProgress,
+}
+
+impl ScanJob {
+    pub fn progress(&self) -> &ScanProgress {
+        &self.progress
+    }
+
+    pub fn cancel(&self) {
+        self.job.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.job.is_finished()
+    }
+
+    /// The updated index and what changed, or `None` if the scan was canceled.
+    pub fn join(self) -> Option<(LibraryIndex, ScanReport)> {
+        self.job.join()
+    }
+}
+
+/// Field matched by a query's search text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchField {
+    /// Artist, title, album or file name.
+    #[default]
+    Any,
+    Artist,
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Artist,
+    Title,
+    /// Slowest first; tracks without a tempo go last.
+    Bpm,
+    /// Shortest first; tracks without a duration go last.
+    Duration,
+    Path,
+}
+
+/// Search over the index; the default query lists every track by artist.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    /// Case-insensitive substring; empty matches everything.
+    pub text: String,
+    pub field: SearchField,
+    /// Inclusive tempo range; tracks without a tempo are left out when set.
+    pub bpm_range: Option<(f64, f64)>,
+    /// Only tracks that mix harmonically out of this key.
+    pub compatible_with: Option<Key>,
+    pub sort: SortOrder,
+}
+
+impl Query {
+    fn matches(&self, entry: &IndexEntry, needle: &str) -> bool {
+        let contains =
+            |text: Option<&str>| text.is_some_and(|text| text.to_lowercase().contains(needle));
+        let text_matches = needle.is_empty()
+            || match self.field {
+                SearchField::Artist => contains(entry.artist.as_deref()),
+                SearchField::Title => contains(entry.title.as_deref()),
+                SearchField::Any => {
+                    contains(entry.artist.as_deref())
+                        || contains(entry.title.as_deref())
+                        || contains(entry.album.as_deref())
+                        || contains(entry.path.file_name().and_then(|name| name.to_str()))
+                }
+            };
+        let bpm_matches = match (self.bpm_range, entry.bpm) {
+            (None, _) => true,
+            (Some((low, high)), Some(bpm)) => (low..=high).contains(&bpm),
+            (Some(_), None) => false,
+        };
+        let key_matches = match (self.compatible_with, entry.key) {
+            (None, _) => true,
+            (Some(from), Some(to)) => key_compatibility(from, to) != Compatibility::Clash,
+            (Some(_), None) => false,
+        };
+        text_matches && bpm_matches && key_matches
+    }
+
+    fn compare(&self, a: &IndexEntry, b: &IndexEntry) -> CmpOrdering {
+        let by_text = |a: &Option<String>, b: &Option<String>| {
+            let lower = |text: &Option<String>| text.as_deref().map(str::to_lowercase);
+            match (lower(a), lower(b)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            }
+        };
+        let by_number = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (a, b) => a.is_none().cmp(&b.is_none()),
+        };
+        let order = match self.sort {
+            SortOrder::Artist => {
+                by_text(&a.artist, &b.artist).then_with(|| by_text(&a.title, &b.title))
+            }
+            SortOrder::Title => by_text(&a.title, &b.title),
+            SortOrder::Bpm => by_number(a.bpm, b.bpm),
+            SortOrder::Duration => by_number(a.duration_seconds, b.duration_seconds),
+            SortOrder::Path => CmpOrdering::Equal,
+        };
+        order.then_with(|| a.path.cmp(&b.path))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexFile {
+    entries: Vec<IndexEntry>,
+}
+
+/// Every track found under the library folders, keyed by path.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LibraryIndex {
+    entries: BTreeMap<PathBuf, IndexEntry>,
+}
+
+impl LibraryIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read an index saved by [`LibraryIndex::save`]; a missing file is an empty index.
+    pub fn load(path: &Path) -> Result<Self, IndexError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file: IndexFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let entries = file
+            .entries
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        Ok(Self { entries })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), IndexError> {
+        let file = IndexFile {
+            entries: self.entries.values().cloned().collect(),
+        };
+        fs::write(path, serde_json::to_string(&file)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&IndexEntry> {
+        self.entries.get(path)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Bring the index in line with the audio files under `roots`.
+    ///
+    /// Only files that are new or whose size or modification time changed are read
+    /// again; entries for files no longer found are dropped. Returns `None` if canceled,
+    /// leaving the entries checked so far updated.
+    pub fn scan(
+        &mut self,
+        roots: &[PathBuf],
+        progress: &ScanProgress,
+        cancel: &CancelToken,
+    ) -> Option<ScanReport> {
+        let mut files = Vec::new();
+        for root in roots {
+            for entry in WalkDir::new(root).into_iter().filter_map(Result::ok) {
+                if cancel.is_canceled() {
+                    return None;
+                }
+                if entry.file_type().is_file() && is_audio(entry.path()) {
+                    files.push(entry.into_path());
+                }
+            }
+        }
+        files.sort();
+        files.dedup();
+        progress.total.store(files.len(), Ordering::Relaxed);
+
+        let mut report = ScanReport::default();
+        for path in &files {
+            if cancel.is_canceled() {
+                return None;
+            }
+            progress.scanned.fetch_add(1, Ordering::Relaxed);
+            let Ok(stat) = fs::metadata(path) else {
+                continue;
+            };
+            let size = stat.len();
+            let modified = stat
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_nanos() as u64);
+            let previous = self.entries.get(path);
+            if previous.is_some_and(|entry| entry.size == size && entry.modified == modified) {
+                report.unchanged += 1;
+                continue;
+            }
+            let Ok(metadata) = read_metadata(path) else {
+                continue;
+            };
+            match previous {
+                Some(_) => report.updated.push(path.clone()),
+                None => report.added.push(path.clone()),
+            }
+            let entry = IndexEntry {
+                path: path.clone(),
+                size,
+                modified,
+                artist: metadata.artist,
+                title: metadata.title,
+                album: metadata.album,
+                duration_seconds: metadata.duration_seconds,
+                bpm: metadata.bpm,
+                key: metadata.key,
+                gain: metadata.gain,
+                has_artwork: metadata.artwork.is_some(),
+            };
+            self.entries.insert(path.clone(), entry);
+        }
+
+        self.entries.retain(|path, _| {
+            let keep = files.binary_search(path).is_ok();
+            if !keep {
+                report.removed.push(path.clone());
+            }
+            keep
+        });
+        Some(report)
+    }
+
+    /// Entries matching `query`, in its sort order.
+    pub fn search(&self, query: &Query) -> Vec<&IndexEntry> {
+        let needle = query.text.trim().to_lowercase();
+        let mut found: Vec<&IndexEntry> = self
+            .entries
+            .values()
+            .filter(|entry| query.matches(entry, &needle))
+            .collect();
+        found.sort_by(|a, b| query.compare(a, b));
+        found
+    }
+}
+
+/// Scan `roots` (normally [`Settings::library_paths`](crate::settings::Settings)) into
+/// `index` on a worker thread.
+pub fn spawn_scan(mut index: LibraryIndex, roots: Vec<PathBuf>) -> ScanJob {
+    let progress = ScanProgress::new();
+    let worker_progress = progress.clone();
+    let job = AnalysisJob::spawn(move |cancel| {
+        let report = index.scan(&roots, &worker_progress, cancel)?;
+        Some((index, report))
+    });
+    ScanJob { job, progress }
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            AUDIO_EXTENSIONS
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use tempfile::{tempdir, TempDir};
+
+    /// A small library: two house tracks with tempo and key tags, two without.
+    fn fixture_tree() -> TempDir {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let dir = tempdir().unwrap();
+        for (from, to) in [
+            ("tagged.mp3", "House/tagged.mp3"),
+            ("tagged.flac", "House/Deep/tagged.flac"),
+            ("tagged.ogg", "Ambient/tagged.ogg"),
+            (
+                "Some Artist - Some Title.mp3",
+                "Ambient/Some Artist - Some Title.mp3",
+            ),
+        ] {
+            let to = dir.path().join(to);
+            fs::create_dir_all(to.parent().unwrap()).unwrap();
+            fs::copy(fixtures.join(from), to).unwrap();
+        }
+        fs::write(dir.path().join("Ambient/notes.txt"), "not audio").unwrap();
+        dir
+    }
+
+    fn titles(found: &[&IndexEntry]) -> Vec<String> {
+        found
+            .iter()
+            .map(|entry| entry.title.clone().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn scans_in_the_background_and_queries_the_index() {
+        let dir = fixture_tree();
+        let job = spawn_scan(LibraryIndex::new(), vec![dir.path().to_path_buf()]);
+        let progress = job.progress().clone();
+        let (index, report) = job.join().unwrap();
+        assert_eq!(report.added.len(), 4);
+        assert_eq!((progress.scanned(), progress.total()), (4, 4));
+        assert_eq!(progress.fraction(), 1.0);
+
+        let flac = index
+            .get(&dir.path().join("House/Deep/tagged.flac"))
+            .unwrap();
+        assert_eq!(flac.bpm, Some(128.0));
+        assert!(flac.gain.is_some() && flac.has_artwork);
+
+        let by_title = Query {
+            sort: SortOrder::Title,
+            ..Query::default()
+        };
+        assert_eq!(
+            titles(&index.search(&by_title)),
+            ["Flac Title", "Mp3 Title", "Ogg Title", "Some Title"]
+        );
+        let artist = Query {
+            text: "some ART".into(),
+            field: SearchField::Artist,
+            ..Query::default()
+        };
+        assert_eq!(titles(&index.search(&artist)), ["Some Title"]);
+        let mixable = Query {
+            text: "title".into(),
+            bpm_range: Some((120.0, 130.0)),
+            sort: SortOrder::Bpm,
+            ..Query::default()
+        };
+        assert_eq!(titles(&index.search(&mixable)), ["Mp3 Title", "Flac Title"]);
+        let from_b_minor = Query {
+            compatible_with: Some("Bm".parse().unwrap()),
+            ..Query::default()
+        };
+        assert_eq!(titles(&index.search(&from_b_minor)), ["Flac Title"]);
+    }
+
+    #[test]
+    fn rescan_only_rereads_changed_files() {
+        let dir = fixture_tree();
+        let roots = vec![dir.path().to_path_buf()];
+        let mut index = LibraryIndex::new();
+        index
+            .scan(&roots, &ScanProgress::new(), &CancelToken::new())
+            .unwrap();
+        let saved = dir.path().join("index.json");
+        index.save(&saved).unwrap();
+        let mut index = LibraryIndex::load(&saved).unwrap();
+
+        let changed = dir.path().join("House/tagged.mp3");
+        let mut file = OpenOptions::new().append(true).open(&changed).unwrap();
+        file.write_all(&[0; 417]).unwrap();
+        drop(file);
+        let gone = dir.path().join("Ambient/tagged.ogg");
+        fs::remove_file(&gone).unwrap();
+
+        let report = index
+            .scan(&roots, &ScanProgress::new(), &CancelToken::new())
+            .unwrap();
+        assert_eq!(report.updated, std::slice::from_ref(&changed));
+        assert_eq!(report.removed, [gone]);
+        assert!(report.added.is_empty());
+        assert_eq!(report.unchanged, 2);
+        assert_eq!(index.len(), 3);
+        assert_eq!(
+            index.get(&changed).unwrap().key,
+            Some("Am".parse().unwrap())
+        );
+
+        let canceled = CancelToken::new();
+        canceled.cancel();
+        assert!(index
+            .scan(&roots, &ScanProgress::new(), &canceled)
+            .is_none());
+    }
+}
@@ -0,0 +1,411 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::deck::{HotCues, LoopRegion};
+
+#[derive(Debug, Error)]
+pub enum PlaylistError {
+    #[error("failed to access playlist file: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse playlist: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("playlist has no entry {0}")]
+    NoEntry(usize),
+}
+
+/// A track as listed in a playlist or crate, with the cues and loop saved for it there.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackRef {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub duration_seconds: Option<f64>,
+    #[serde(default)]
+    pub hot_cues: HotCues,
+    #[serde(default)]
+    pub saved_loop: Option<LoopRegion>,
+    /// The path did not resolve to a file when the list was last loaded or refreshed.
+    #[serde(skip)]
+    pub missing: bool,
+}
+
+impl TrackRef {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_duration(mut self, seconds: f64) -> Self {
+        self.duration_seconds = Some(seconds);
+        self
+    }
+}
+
+/// An ordered set list; the same track may appear more than once.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Playlist {
+    pub name: String,
+    pub entries: Vec<TrackRef>,
+}
+
+impl Playlist {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, track: TrackRef) {
+        self.entries.push(track);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<TrackRef, PlaylistError> {
+        if index >= self.entries.len() {
+            return Err(PlaylistError::NoEntry(index));
+        }
+        Ok(self.entries.remove(index))
+    }
+
+    /// Move the entry at `from` so that it ends up at index `to`.
+    pub fn move_entry(&mut self, from: usize, to: usize) -> Result<(), PlaylistError> {
+        let len = self.entries.len();
+        for index in [from, to] {
+            if index >= len {
+                return Err(PlaylistError::NoEntry(index));
+            }
+        }
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+        Ok(())
+    }
+
+    /// Indices of entries whose path already appeared earlier in the list.
+    pub fn duplicates(&self) -> Vec<usize> {
+        (0..self.entries.len())
+            .filter(|&index| {
+                let path = &self.entries[index].path;
+                self.entries[..index]
+                    .iter()
+                    .any(|entry| entry.path == *path)
+            })
+            .collect()
+    }
+
+    /// Re-check every entry's path, updating its missing marker.
+    pub fn refresh_missing(&mut self) {
+        refresh_missing(&mut self.entries);
+    }
+
+    /// Extended M3U with `#EXTINF` lines; paths under `base` are written relative to it.
+    pub fn to_m3u8(&self, base: &Path) -> String {
+        write_m3u8(&self.name, &self.entries, base)
+    }
+
+    /// Parse extended or plain M3U, resolving relative paths against `base`.
+    pub fn from_m3u8(text: &str, base: &Path) -> Self {
+        let (name, entries) = parse_m3u8(text, base);
+        Self { name, entries }
+    }
+
+    pub fn save_m3u8(&self, path: &Path) -> Result<(), PlaylistError> {
+        fs::write(path, self.to_m3u8(parent(path)))?;
+        Ok(())
+    }
+
+    /// Load an M3U8 file; it is named after the file unless it carries a `#PLAYLIST` line.
+    pub fn load_m3u8(path: &Path) -> Result<Self, PlaylistError> {
+        let mut playlist = Self::from_m3u8(&fs::read_to_string(path)?, parent(path));
+        if playlist.name.is_empty() {
+            playlist.name = file_stem(path);
+        }
+        Ok(playlist)
+    }
+
+    /// Native format, keeping each entry's cues and loop.
+    pub fn save_json(&self, path: &Path) -> Result<(), PlaylistError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load_json(path: &Path) -> Result<Self, PlaylistError> {
+        let mut playlist: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        resolve_all(&mut playlist.entries, parent(path));
+        Ok(playlist)
+    }
+}
+
+/// An unordered collection of tracks, each held at most once.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Crate {
+    pub name: String,
+    tracks: Vec<TrackRef>,
+}
+
+impl Crate {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tracks: Vec::new(),
+        }
+    }
+
+    /// Add `track` unless its path is already in the crate; returns whether it was added.
+    pub fn insert(&mut self, track: TrackRef) -> bool {
+        if self.contains(&track.path) {
+            return false;
+        }
+        self.tracks.push(track);
+        true
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Option<TrackRef> {
+        let index = self.tracks.iter().position(|track| track.path == path)?;
+        Some(self.tracks.swap_remove(index))
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.tracks.iter().any(|track| track.path == path)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&TrackRef> {
+        self.tracks.iter().find(|track| track.path == path)
+    }
+
+    pub fn tracks(&self) -> impl Iterator<Item = &TrackRef> {
+        self.tracks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    pub fn refresh_missing(&mut self) {
+        refresh_missing(&mut self.tracks);
+    }
+
+    pub fn to_m3u8(&self, base: &Path) -> String {
+        write_m3u8(&self.name, &self.tracks, base)
+    }
+
+    /// Parse M3U into a crate, dropping repeated paths.
+    pub fn from_m3u8(text: &str, base: &Path) -> Self {
+        let (name, entries) = parse_m3u8(text, base);
+        let mut collection = Self::new(name);
+        for entry in entries {
+            collection.insert(entry);
+        }
+        collection
+    }
+
+    pub fn save_m3u8(&self, path: &Path) -> Result<(), PlaylistError> {
+        fs::write(path, self.to_m3u8(parent(path)))?;
+        Ok(())
+    }
+
+    pub fn load_m3u8(path: &Path) -> Result<Self, PlaylistError> {
+        let mut collection = Self::from_m3u8(&fs::read_to_string(path)?, parent(path));
+        if collection.name.is_empty() {
+            collection.name = file_stem(path);
+        }
+        Ok(collection)
+    }
+
+    pub fn save_json(&self, path: &Path) -> Result<(), PlaylistError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load_json(path: &Path) -> Result<Self, PlaylistError> {
+        let mut collection: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        resolve_all(&mut collection.tracks, parent(path));
+        Ok(collection)
+    }
+}
+
+fn parent(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new(""))
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn refresh_missing(entries: &mut [TrackRef]) {
+    for entry in entries {
+        entry.missing = !entry.path.is_file();
+    }
+}
+
+/// Make relative entry paths absolute against `base` and mark the ones that are gone.
+fn resolve_all(entries: &mut [TrackRef], base: &Path) {
+    for entry in entries.iter_mut() {
+        if entry.path.is_relative() {
+            entry.path = base.join(&entry.path);
+        }
+    }
+    refresh_missing(entries);
+}
+
+fn write_m3u8(name: &str, entries: &[TrackRef], base: &Path) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    if !name.is_empty() {
+        text += &format!("#PLAYLIST:{name}\n");
+    }
+    for entry in entries {
+        if entry.title.is_some() || entry.duration_seconds.is_some() {
+            let seconds = entry
+                .duration_seconds
+                .map_or(-1, |seconds| seconds.round() as i64);
+            let title = entry.title.as_deref().unwrap_or_default();
+            text += &format!("#EXTINF:{seconds},{title}\n");
+        }
+        let path = entry.path.strip_prefix(base).unwrap_or(&entry.path);
+        text += &path.to_string_lossy();
+        text.push('\n');
+    }
+    text
+}
+
+fn parse_m3u8(text: &str, base: &Path) -> (String, Vec<TrackRef>) {
+    let mut name = String::new();
+    let mut entries = Vec::new();
+    let mut info: Option<(Option<f64>, Option<String>)> = None;
+    for line in text.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            let (seconds, title) = rest.split_once(',').unwrap_or((rest, ""));
+            let seconds = seconds
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| *seconds >= 0.0);
+            let title = (!title.trim().is_empty()).then(|| title.trim().to_string());
+            info = Some((seconds, title));
+        } else if let Some(rest) = line.strip_prefix("#PLAYLIST:") {
+            name = rest.trim().to_string();
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let (duration_seconds, title) = info.take().unwrap_or_default();
+            entries.push(TrackRef {
+                path: PathBuf::from(line),
+                title,
+                duration_seconds,
+                ..TrackRef::default()
+            });
+        }
+    }
+    resolve_all(&mut entries, base);
+    (name, entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn touch(path: &Path) -> PathBuf {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"").unwrap();
+        path.to_path_buf()
+    }
+
+    #[test]
+    fn edits_keep_order_and_report_duplicates() {
+        let mut playlist = Playlist::new("warmup");
+        for name in ["a.mp3", "b.mp3", "c.mp3", "a.mp3"] {
+            playlist.add(TrackRef::new(name));
+        }
+        assert_eq!(playlist.duplicates(), vec![3]);
+        playlist.move_entry(0, 2).unwrap();
+        playlist.remove(3).unwrap();
+        let order: Vec<_> = playlist.entries.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(order, ["b.mp3", "c.mp3", "a.mp3"].map(PathBuf::from));
+        assert!(matches!(
+            playlist.move_entry(0, 3),
+            Err(PlaylistError::NoEntry(3))
+        ));
+
+        let mut collection = Crate::new("techno");
+        assert!(collection.insert(TrackRef::new("a.mp3")));
+        assert!(!collection.insert(TrackRef::new("a.mp3").with_title("again")));
+        assert_eq!(collection.len(), 1);
+        assert!(collection.remove(Path::new("a.mp3")).is_some());
+        assert!(collection.is_empty());
+    }
+
+    #[test]
+    fn m3u8_round_trips_relative_absolute_and_non_ascii_paths() {
+        let library = tempdir().unwrap();
+        let elsewhere = tempdir().unwrap();
+        let inside = touch(&library.path().join("Ünïcødé/Björk - Jóga.flac"));
+        let outside = touch(&elsewhere.path().join("日本語.mp3"));
+
+        let mut playlist = Playlist::new("Sét");
+        playlist.add(
+            TrackRef::new(&inside)
+                .with_title("Björk - Jóga")
+                .with_duration(305.0),
+        );
+        playlist.add(TrackRef::new(&outside));
+        let file = library.path().join("set.m3u8");
+        playlist.save_m3u8(&file).unwrap();
+
+        let text = fs::read_to_string(&file).unwrap();
+        assert!(text.contains("#EXTINF:305,Björk - Jóga\nÜnïcødé"));
+        assert!(text.contains(&outside.to_string_lossy().into_owned()));
+        assert_eq!(Playlist::load_m3u8(&file).unwrap(), playlist);
+
+        // An entry whose file has gone is kept but marked.
+        fs::remove_file(&outside).unwrap();
+        let reloaded = Playlist::load_m3u8(&file).unwrap();
+        assert!(!reloaded.entries[0].missing);
+        assert!(reloaded.entries[1].missing);
+        assert_eq!(reloaded.entries[1].path, outside);
+    }
+
+    #[test]
+    fn json_round_trips_cues_and_loops() {
+        let dir = tempdir().unwrap();
+        let track = touch(&dir.path().join("Café del Mar.wav"));
+        let mut entry = TrackRef::new(&track).with_duration(412.5);
+        entry.hot_cues.set(2, 96_000);
+        entry.saved_loop = Some(LoopRegion {
+            start: 48_000,
+            end: 96_000,
+        });
+        let mut playlist = Playlist::new("closing");
+        playlist.add(entry.clone());
+        playlist.add(TrackRef::new(dir.path().join("gone.wav")));
+
+        let file = dir.path().join("closing.json");
+        playlist.save_json(&file).unwrap();
+        let loaded = Playlist::load_json(&file).unwrap();
+        assert_eq!(loaded.entries[0], entry);
+        assert!(loaded.entries[1].missing);
+
+        let mut collection = Crate::new("chill");
+        collection.insert(entry.clone());
+        let file = dir.path().join("chill.json");
+        collection.save_json(&file).unwrap();
+        let loaded = Crate::load_json(&file).unwrap();
+        assert_eq!(loaded.get(&track), Some(&entry));
+    }
+}
@@ -0,0 +1,232 @@
+use crate::db::db_to_linear;
+use crate::sidechain::GainReductionReader;
+
+fn coeff(seconds: f32, sample_rate: u32) -> f32 {
+    (-1.0 / (seconds.max(1e-6) * sample_rate as f32)).exp()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimiterOptions {
+    /// Highest sample value let through, in dBFS.
+    pub ceiling_db: f32,
+    /// How far ahead peaks are seen, which is also the time the gain takes to
+    /// come down and the latency the limiter adds.
+    pub lookahead_seconds: f32,
+    /// Time for the gain to recover 63% of the way once a peak has passed.
+    pub release_seconds: f32,
+}
+
+impl Default for LimiterOptions {
+    fn default() -> Self {
+        Self {
+            ceiling_db: -0.3,
+            lookahead_seconds: 0.003,
+            release_seconds: 0.1,
+        }
+    }
+}
+
+/// Lookahead brickwall limiter on the master.
+///
+/// The master is delayed by the lookahead so the gain can ramp down before a
+/// peak arrives: the lowest gain any sample in the window needs is held, lets go
+/// with the release, and is averaged over the window, which keeps every delayed
+/// sample at or under the ceiling without a step in the gain.
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    options: LimiterOptions,
+    ceiling: f32,
+    release: f32,
+    /// Delayed frames, gain each frame needs and held gain, over the window.
+    delay: Vec<[f32; 2]>,
+    required: Vec<f32>,
+    held: Vec<f32>,
+    /// Sum of `held`, for the window average.
+    held_sum: f64,
+    envelope: f32,
+    position: usize,
+    /// Lowest gain over the block, for the reader.
+    block_gain: f32,
+    reader: GainReductionReader,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: u32, options: LimiterOptions) -> Self {
+        let mut limiter = Self {
+            options,
+            ceiling: 1.0,
+            release: 0.0,
+            delay: Vec::new(),
+            required: Vec::new(),
+            held: Vec::new(),
+            held_sum: 0.0,
+            envelope: 1.0,
+            position: 0,
+            block_gain: 1.0,
+            reader: GainReductionReader::default(),
+        };
+        limiter.prepare(sample_rate);
+        limiter
+    }
+
+    /// Size the lookahead window for the session `sample_rate` and start over.
+    /// Allocates, so never call it from the audio callback.
+    pub fn prepare(&mut self, sample_rate: u32) {
+        let window = (self.options.lookahead_seconds * sample_rate as f32).round() as usize + 1;
+        self.delay = vec![[0.0; 2]; window.max(2)];
+        self.required = vec![1.0; self.delay.len()];
+        self.held = vec![1.0; self.delay.len()];
+        self.held_sum = self.held.len() as f64;
+        self.envelope = 1.0;
+        self.position = 0;
+        self.block_gain = 1.0;
+        self.set_ceiling_db(self.options.ceiling_db);
+        self.set_release_seconds(self.options.release_seconds, sample_rate);
+    }
+
+    pub fn options(&self) -> LimiterOptions {
+        self.options
+    }
+
+    /// Frames the limiter delays the master by.
+    pub fn latency_frames(&self) -> usize {
+        self.delay.len() - 1
+    }
+
+    pub fn set_ceiling_db(&mut self, db: f32) {
+        self.options.ceiling_db = db.min(0.0);
+        self.ceiling = db_to_linear(self.options.ceiling_db);
+    }
+
+    pub fn set_release_seconds(&mut self, seconds: f32, sample_rate: u32) {
+        self.options.release_seconds = seconds.max(0.0);
+        self.release = coeff(self.options.release_seconds, sample_rate);
+    }
+
+    /// Lock-free view of the gain reduction, refreshed by [`publish`](Self::publish).
+    pub fn reader(&self) -> GainReductionReader {
+        self.reader.clone()
+    }
+
+    /// Take one master frame and return the one from a lookahead ago, limited.
+    pub fn tick(&mut self, frame: [f32; 2]) -> [f32; 2] {
+        let window = self.delay.len();
+        let peak = frame[0].abs().max(frame[1].abs());
+        self.required[self.position] = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+        let lowest = self.required.iter().copied().fold(1.0, f32::min);
+        // Down at once, back up with the release.
+        self.envelope = if lowest < self.envelope {
+            lowest
+        } else {
+            lowest + (self.envelope - lowest) * self.release
+        };
+        self.held_sum += (self.envelope - self.held[self.position]) as f64;
+        self.held[self.position] = self.envelope;
+        let gain = ((self.held_sum / window as f64) as f32).min(1.0);
+
+        self.delay[self.position] = frame;
+        self.position = (self.position + 1) % window;
+        let delayed = self.delay[self.position];
+        self.block_gain = self.block_gain.min(gain);
+        // Rounding in the running average must not let a sample through.
+        delayed.map(|sample| (sample * gain).clamp(-self.ceiling, self.ceiling))
+    }
+
+    /// Hand the block's deepest reduction to the reader; call once per mixed block.
+    pub fn publish(&mut self) {
+        self.reader.publish(-20.0 * self.block_gain.log10());
+        self.block_gain = 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, ParameterUpdate, SummingBus};
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn sine(amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let s = (i as f32 / SAMPLE_RATE as f32 * 440.0 * std::f32::consts::TAU).sin();
+                [s * amplitude, s * amplitude]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hot_master_never_passes_the_ceiling() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let limiter = Limiter::new(SAMPLE_RATE, LimiterOptions::default());
+        let reader = limiter.reader();
+        bus.set_limiter(Some(limiter));
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+
+        // +6 dB over full scale.
+        let input = sine(2.0, SAMPLE_RATE as usize);
+        let mut out = vec![0.0; input.len()];
+        for (block, out) in input.chunks(512).zip(out.chunks_mut(512)) {
+            bus.mix_stereo(block, &vec![0.0; block.len()], out);
+        }
+        let ceiling = db_to_linear(-0.3);
+        assert!(out.iter().all(|s| s.abs() <= ceiling));
+        let peak = out[SAMPLE_RATE as usize..]
+            .iter()
+            .fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak > ceiling * 0.98, "{peak}");
+        let reduction = reader.reduction_db();
+        assert!((reduction - 6.3).abs() < 0.1, "{reduction}");
+
+        // A lower ceiling applies from the next block.
+        tx.send(ParameterUpdate::LimiterCeilingDb(-6.0)).unwrap();
+        bus.mix_stereo(&input[..1_024], &[0.0; 1_024], &mut out[..1_024]);
+        assert!(out[..1_024].iter().all(|s| s.abs() <= db_to_linear(-6.0)));
+    }
+
+    #[test]
+    fn quiet_material_passes_untouched_after_the_lookahead() {
+        let mut limiter = Limiter::new(SAMPLE_RATE, LimiterOptions::default());
+        let latency = limiter.latency_frames();
+        assert_eq!(latency, 144);
+        let input = sine(0.5, 4_800);
+        let out: Vec<f32> = input
+            .chunks_exact(2)
+            .flat_map(|frame| limiter.tick([frame[0], frame[1]]))
+            .collect();
+        assert!(out[..latency * 2].iter().all(|&s| s == 0.0));
+        assert_eq!(out[latency * 2..], input[..input.len() - latency * 2]);
+        limiter.publish();
+        assert_eq!(limiter.reader().reduction_db(), 0.0);
+    }
+
+    #[test]
+    fn gain_ramps_down_ahead_of_a_peak_and_releases() {
+        let mut limiter = Limiter::new(SAMPLE_RATE, LimiterOptions::default());
+        let latency = limiter.latency_frames();
+        let mut input = vec![0.5; 48_000];
+        input[4_800] = 4.0;
+        let out: Vec<f32> = input
+            .iter()
+            .flat_map(|&s| limiter.tick([s, s]))
+            .step_by(2)
+            .collect();
+        let gains: Vec<f32> = out[latency..]
+            .iter()
+            .zip(&input)
+            .map(|(out, input)| out / input)
+            .collect();
+        assert!((out[4_800 + latency] - db_to_linear(-0.3)).abs() < 1e-4);
+        // No step larger than the window's share of the reduction.
+        for pair in gains.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < 1.0 / latency as f32, "{pair:?}");
+        }
+        assert!(gains[4_800 + 2_400] < 0.9);
+        assert!(gains[gains.len() - 1] > 0.99);
+    }
+}
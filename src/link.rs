@@ -0,0 +1,327 @@
+#[cfg(feature = "link")]
+mod ableton;
+
+use crate::deck::{Deck, DeckError};
+use crate::engine::LinkIndicator;
+use crate::tempo::TempoSource;
+use crate::{ParameterSender, ParameterUpdate};
+
+#[cfg(feature = "link")]
+pub use ableton::AbletonLink;
+
+/// Beats per bar the master deck is phase-aligned over.
+pub const DEFAULT_QUANTUM: f64 = 4.0;
+/// Tempo differences below this are rounding, not a change worth passing on.
+const TEMPO_EPSILON: f64 = 1e-3;
+/// Tempo bend per beat of phase error while pulling the master deck into phase.
+const PHASE_GAIN: f64 = 0.2;
+/// Largest tempo bend used for phase correction, so it is never heard as a pitch wobble.
+const MAX_BEND: f64 = 0.04;
+
+/// The parts of an Ableton Link session the engine uses; times are on the session clock.
+pub trait LinkSession {
+    fn set_enabled(&mut self, enabled: bool);
+    fn is_enabled(&self) -> bool;
+    /// Other apps in the session.
+    fn peers(&self) -> u64;
+    fn now_micros(&self) -> i64;
+    fn tempo(&self) -> f64;
+    fn set_tempo(&mut self, bpm: f64, at_micros: i64);
+    fn beat_at(&self, at_micros: i64, quantum: f64) -> f64;
+    fn set_playing(&mut self, playing: bool, at_micros: i64);
+}
+
+/// Beats `deck_beat` has to move to sit at `link_beat`'s position in the bar, in [-quantum/2, quantum/2).
+pub fn phase_offset(link_beat: f64, deck_beat: f64, quantum: f64) -> f64 {
+    let half = quantum / 2.0;
+    (link_beat - deck_beat + half).rem_euclid(quantum) - half
+}
+
+/// Keeps the master tempo, transport and master deck in step with a Link session.
+pub struct LinkSync<S> {
+    session: S,
+    quantum: f64,
+    indicator: Option<LinkIndicator>,
+    /// Tempo last agreed with the session, to tell local changes from remote ones.
+    agreed: Option<f64>,
+    playing: Option<bool>,
+}
+
+impl<S: LinkSession> LinkSync<S> {
+    pub fn new(session: S) -> Self {
+        Self {
+            session,
+            quantum: DEFAULT_QUANTUM,
+            indicator: None,
+            agreed: None,
+            playing: None,
+        }
+    }
+
+    /// Show the session's peer count in the engine stats.
+    pub fn set_indicator(&mut self, indicator: LinkIndicator) {
+        indicator.set(self.is_enabled().then(|| self.session.peers()));
+        self.indicator = Some(indicator);
+    }
+
+    pub fn quantum(&self) -> f64 {
+        self.quantum
+    }
+
+    pub fn set_quantum(&mut self, beats: f64) {
+        if beats > 0.0 {
+            self.quantum = beats;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.session.is_enabled()
+    }
+
+    /// Join or leave the session; after rejoining, the tempo is negotiated afresh.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.session.set_enabled(enabled);
+        self.agreed = None;
+        self.playing = None;
+        if let Some(indicator) = &self.indicator {
+            indicator.set(enabled.then(|| self.session.peers()));
+        }
+    }
+
+    pub fn peers(&self) -> u64 {
+        self.session.peers()
+    }
+
+    /// Exchange tempo with the session, publishing to the audio thread whenever the session's tempo is taken.
+    ///
+    /// A remote change wins over a local one made since the last call. On joining, a session with
+    /// peers sets the tempo; joining alone offers ours.
+    pub fn update(
+        &mut self,
+        tempo: &mut TempoSource,
+        params: &ParameterSender,
+    ) -> Result<(), ParameterUpdate> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let peers = self.session.peers();
+        if let Some(indicator) = &self.indicator {
+            indicator.set(Some(peers));
+        }
+
+        let changed = |bpm: f64, agreed: Option<f64>| {
+            agreed.is_none_or(|agreed| (bpm - agreed).abs() > TEMPO_EPSILON)
+        };
+        let remote = self.session.tempo();
+        let local = tempo.bpm();
+        let adopt = match self.agreed {
+            None => peers > 0 || local.is_none(),
+            Some(_) => changed(remote, self.agreed),
+        };
+        if adopt {
+            self.agreed = Some(remote);
+            tempo.set_bpm(Some(remote));
+            return tempo.publish(params);
+        }
+        if let Some(local) = local.filter(|bpm| changed(*bpm, self.agreed)) {
+            let now = self.session.now_micros();
+            self.session.set_tempo(local, now);
+            self.agreed = Some(local);
+        }
+        Ok(())
+    }
+
+    /// Tell the session whether the master deck is playing.
+    pub fn sync_transport(&mut self, playing: bool) {
+        if self.is_enabled() && self.playing != Some(playing) {
+            let now = self.session.now_micros();
+            self.session.set_playing(playing, now);
+            self.playing = Some(playing);
+        }
+    }
+
+    /// Match `deck` to the session tempo, bending it slightly until its bar lines up with the session's.
+    ///
+    /// Call once per control tick while the session leads; returns the remaining offset in beats.
+    /// The bent deck should not also drive the [`TempoSource`], or the bend would be published.
+    pub fn align_deck(&self, deck: &mut Deck) -> Result<f64, DeckError> {
+        let grid = *deck.beat_grid().ok_or(DeckError::NoBeatGrid)?;
+        let now = self.session.now_micros();
+        let offset = phase_offset(
+            self.session.beat_at(now, self.quantum),
+            grid.beat_at(deck.position()),
+            self.quantum,
+        );
+        let bend = (offset * PHASE_GAIN).clamp(-MAX_BEND, MAX_BEND);
+        deck.set_tempo_ratio(self.session.tempo() / grid.bpm * (1.0 + bend));
+        Ok(offset)
+    }
+
+    pub fn session(&self) -> &S {
+        &self.session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::{BeatGrid, Track};
+
+    /// Session timeline of constant tempo, moved by hand.
+    struct FakeSession {
+        enabled: bool,
+        peers: u64,
+        now: i64,
+        bpm: f64,
+        /// Beat at `origin_micros`, kept continuous across tempo changes.
+        origin_beat: f64,
+        origin_micros: i64,
+        playing: Option<bool>,
+    }
+
+    impl FakeSession {
+        fn new(bpm: f64) -> Self {
+            Self {
+                enabled: false,
+                peers: 0,
+                now: 0,
+                bpm,
+                origin_beat: 0.0,
+                origin_micros: 0,
+                playing: None,
+            }
+        }
+    }
+
+    impl LinkSession for FakeSession {
+        fn set_enabled(&mut self, enabled: bool) {
+            self.enabled = enabled;
+        }
+
+        fn is_enabled(&self) -> bool {
+            self.enabled
+        }
+
+        fn peers(&self) -> u64 {
+            self.peers
+        }
+
+        fn now_micros(&self) -> i64 {
+            self.now
+        }
+
+        fn tempo(&self) -> f64 {
+            self.bpm
+        }
+
+        fn set_tempo(&mut self, bpm: f64, at_micros: i64) {
+            self.origin_beat = self.beat_at(at_micros, 1.0);
+            self.origin_micros = at_micros;
+            self.bpm = bpm;
+        }
+
+        fn beat_at(&self, at_micros: i64, _quantum: f64) -> f64 {
+            self.origin_beat + (at_micros - self.origin_micros) as f64 * self.bpm / 60e6
+        }
+
+        fn set_playing(&mut self, playing: bool, _at_micros: i64) {
+            self.playing = Some(playing);
+        }
+    }
+
+    #[test]
+    fn phase_offset_wraps_to_the_nearest_bar_position() {
+        assert!((phase_offset(5.25, 0.75, 4.0) - 0.5).abs() < 1e-12);
+        // Just past the downbeat versus just before it: a short step forwards, not most of a bar back.
+        assert!((phase_offset(8.1, 3.9, 4.0) - 0.2).abs() < 1e-12);
+        assert!((phase_offset(3.9, 8.1, 4.0) + 0.2).abs() < 1e-12);
+        assert!((phase_offset(2.0, 0.0, 4.0) + 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn takes_session_tempo_and_publishes_local_changes() {
+        let mut session = FakeSession::new(124.0);
+        session.peers = 2;
+        let mut link = LinkSync::new(session);
+        let (tx, rx) = crate::parameter_channel(4);
+        let mut tempo = TempoSource::new();
+        tempo.set_bpm(Some(120.0));
+
+        // Disabled, nothing moves either way.
+        link.update(&mut tempo, &tx).unwrap();
+        assert_eq!(tempo.bpm(), Some(120.0));
+        assert!(rx.pop().is_none());
+
+        // Joining a session with peers adopts its tempo.
+        link.set_enabled(true);
+        link.update(&mut tempo, &tx).unwrap();
+        assert_eq!(tempo.bpm(), Some(124.0));
+        assert!(matches!(rx.pop(), Some(ParameterUpdate::Tempo(Some(bpm))) if bpm == 124.0));
+
+        // A tap-tempo change here goes out to the session.
+        tempo.set_bpm(Some(126.0));
+        link.update(&mut tempo, &tx).unwrap();
+        assert_eq!(link.session().bpm, 126.0);
+        assert!(rx.pop().is_none());
+
+        // A peer changing tempo flows back in.
+        link.session.bpm = 128.0;
+        link.update(&mut tempo, &tx).unwrap();
+        assert_eq!(tempo.bpm(), Some(128.0));
+        assert!(matches!(rx.pop(), Some(ParameterUpdate::Tempo(Some(bpm))) if bpm == 128.0));
+        link.update(&mut tempo, &tx).unwrap();
+        assert!(rx.pop().is_none());
+    }
+
+    #[test]
+    fn joining_alone_offers_the_local_tempo_and_transport() {
+        let mut link = LinkSync::new(FakeSession::new(120.0));
+        let (tx, _rx) = crate::parameter_channel(4);
+        let mut tempo = TempoSource::new();
+        tempo.set_bpm(Some(132.0));
+
+        link.set_enabled(true);
+        link.update(&mut tempo, &tx).unwrap();
+        assert_eq!(link.session().bpm, 132.0);
+
+        link.sync_transport(true);
+        assert_eq!(link.session().playing, Some(true));
+        link.session.playing = None;
+        link.sync_transport(true);
+        assert_eq!(link.session().playing, None);
+    }
+
+    #[test]
+    fn pulls_the_master_deck_into_phase_without_jumping() {
+        let mut deck = Deck::new();
+        deck.load(Track::from_interleaved(vec![0.0; 48_000 * 2 * 30], 48_000));
+        deck.set_beat_grid(BeatGrid::new(120.0, 0.0, 48_000))
+            .unwrap();
+        // Half a beat behind the session's bar.
+        deck.seek(12_000);
+        deck.play();
+
+        let mut session = FakeSession::new(125.0);
+        session.origin_beat = 1.0;
+        let mut link = LinkSync::new(session);
+        link.set_enabled(true);
+
+        let first = link.align_deck(&mut deck).unwrap();
+        assert!((first - 0.5).abs() < 1e-9);
+        let ratio = deck.tempo_ratio();
+        assert!(ratio > 125.0 / 120.0 && ratio <= 125.0 / 120.0 * (1.0 + MAX_BEND));
+
+        let mut out = vec![0.0; 480 * 2];
+        let mut offset = first;
+        for _ in 0..1_500 {
+            let before = deck.position();
+            deck.render(&mut out);
+            // Only ever a gently bent playback rate, never a seek.
+            assert!(deck.position() - before < 480.0 * 1.1);
+            link.session.now += 10_000;
+            offset = link.align_deck(&mut deck).unwrap();
+        }
+        assert!(offset.abs() < 0.01, "{offset}");
+        assert!((deck.tempo_ratio() - 125.0 / 120.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,61 @@
+use rusty_link::{AblLink, SessionState};
+
+use super::LinkSession;
+
+/// [`LinkSession`] on the network through the Ableton Link SDK.
+pub struct AbletonLink {
+    link: AblLink,
+}
+
+impl AbletonLink {
+    /// Create a session offering `bpm` to peers; it stays offline until enabled.
+    pub fn new(bpm: f64) -> Self {
+        let link = AblLink::new(bpm);
+        link.enable_start_stop_sync(true);
+        Self { link }
+    }
+
+    fn capture(&self) -> SessionState {
+        let mut state = SessionState::new();
+        self.link.capture_app_session_state(&mut state);
+        state
+    }
+}
+
+impl LinkSession for AbletonLink {
+    fn set_enabled(&mut self, enabled: bool) {
+        self.link.enable(enabled);
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.link.is_enabled()
+    }
+
+    fn peers(&self) -> u64 {
+        self.link.num_peers()
+    }
+
+    fn now_micros(&self) -> i64 {
+        self.link.clock_micros()
+    }
+
+    fn tempo(&self) -> f64 {
+        self.capture().tempo()
+    }
+
+    fn set_tempo(&mut self, bpm: f64, at_micros: i64) {
+        let mut state = self.capture();
+        state.set_tempo(bpm, at_micros);
+        self.link.commit_app_session_state(&state);
+    }
+
+    fn beat_at(&self, at_micros: i64, quantum: f64) -> f64 {
+        self.capture().beat_at_time(at_micros, quantum)
+    }
+
+    fn set_playing(&mut self, playing: bool, at_micros: i64) {
+        let mut state = self.capture();
+        state.set_is_playing(playing, at_micros.max(0) as u64);
+        self.link.commit_app_session_state(&state);
+    }
+}
@@ -1,16 +1,16 @@
-mod bundle;
-mod crash;
-mod settings;
-mod version;
-
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
-use settings::Settings;
-
-use crate::bundle::{bundle_assets, BundlePlan};
-use crate::crash::install_panic_hook;
-use crate::version::current_version;
+use clap::{Parser, Subcommand, ValueEnum};
+use deejay::bundle::{bundle_assets, BundlePlan};
+use deejay::crash::{install_panic_hook, RunMarker};
+#[cfg(feature = "audio")]
+use deejay::engine::{list_output_devices, CpalBackend, StreamBackend};
+use deejay::history::{export_csv, export_tracklist, HistoryLog};
+use deejay::record::{write_wav, RecorderOptions, WavFormat};
+use deejay::render::{parse_automation, render_session};
+use deejay::session::Session;
+use deejay::settings::Settings;
+use deejay::version::current_version;
 
 #[derive(Debug, Parser)]
 #[command(author, version, about = "Cross-platform device/buffer configuration helper", long_about = None)]
@@ -30,6 +30,14 @@ struct Cli {
     #[arg(long)]
     sample_rate: Option<u32>,
 
+    /// Audio host to open devices on (e.g. WASAPI, ASIO, ALSA)
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Request an exclusive-mode output stream (WASAPI)
+    #[arg(long)]
+    exclusive_mode: Option<bool>,
+
     /// Persist any provided configuration overrides to settings.json
     #[arg(long)]
     save: bool,
@@ -53,6 +61,60 @@ enum Commands {
         #[arg(long, default_value = "target/release/deejay")]
         binary: String,
     },
+    /// Start a session, optionally picking up where a crashed one left off
+    Run {
+        /// Reload the decks, mixer and effects from the last session snapshot
+        #[arg(long)]
+        restore_session: bool,
+        /// Session snapshot to restore (defaults to session.json)
+        #[arg(long)]
+        session: Option<PathBuf>,
+    },
+    /// Render a saved session and its automation log offline to a WAV file
+    RenderSet {
+        /// Session snapshot with the decks, mixer and effects to start from
+        #[arg(long)]
+        session: PathBuf,
+        /// Automation exported from the performance
+        #[arg(long)]
+        automation: PathBuf,
+        /// WAV file to write
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Work with the log of played tracks
+    History {
+        #[command(subcommand)]
+        command: HistoryCommands,
+    },
+    /// List output devices on every available audio host
+    #[cfg(feature = "audio")]
+    ListDevices,
+    /// Show the output configurations the configured device supports
+    #[cfg(feature = "audio")]
+    Probe,
+}
+
+#[derive(Debug, Subcommand)]
+enum HistoryCommands {
+    /// Write the played tracks out as a tracklist
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Text)]
+        format: ExportFormat,
+        /// History log to read (defaults to history.jsonl)
+        #[arg(long)]
+        log: Option<PathBuf>,
+        /// File to write instead of standard output
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Text,
 }
 
 fn default_target() -> String {
@@ -69,29 +131,84 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
     let version = current_version().to_string();
 
-    let crash_log = cli
-        .crash_log
-        .unwrap_or_else(|| PathBuf::from("crash.log"));
+    let crash_log = cli.crash_log.unwrap_or_else(|| PathBuf::from("crash.log"));
     install_panic_hook(crash_log, &version);
 
-    if let Some(command) = cli.command {
-        match command {
-            Commands::Bundle {
-                target,
-                dist_dir,
-                binary,
-            } => {
-                let plan = BundlePlan::new(target, dist_dir);
-                bundle_assets(&plan, binary)?;
-                println!(
-                    "Bundled assets and runtime dependencies to {}",
-                    plan.output_dir().display()
-                );
-                return Ok(());
-            }
+    if let Some(Commands::Bundle {
+        target,
+        dist_dir,
+        binary,
+    }) = cli.command
+    {
+        let plan = BundlePlan::new(target, dist_dir);
+        bundle_assets(&plan, binary)?;
+        println!(
+            "Bundled assets and runtime dependencies to {}",
+            plan.output_dir().display()
+        );
+        return Ok(());
+    }
+
+    if let Some(Commands::History {
+        command:
+            HistoryCommands::Export {
+                format,
+                log,
+                output,
+            },
+    }) = &cli.command
+    {
+        let log = HistoryLog::new(log.clone().unwrap_or_else(HistoryLog::default_path));
+        let entries = log.read()?;
+        let exported = match format {
+            ExportFormat::Csv => export_csv(&entries),
+            ExportFormat::Text => export_tracklist(&entries),
+        };
+        match output {
+            Some(path) => std::fs::write(path, exported)?,
+            None => print!("{exported}"),
         }
+        return Ok(());
+    }
+
+    if let Some(Commands::RenderSet {
+        session,
+        automation,
+        out,
+    }) = &cli.command
+    {
+        let session = Session::load(session)?;
+        let automation = parse_automation(&std::fs::read_to_string(automation)?)?;
+        let mut reported = 0;
+        let (mix, summary) = render_session(&session, &automation, |seconds| {
+            let seconds = seconds as u64;
+            if seconds >= reported + 10 {
+                eprint!("\rrendered {}:{:02}", seconds / 60, seconds % 60);
+                reported = seconds;
+            }
+        })?;
+        eprintln!();
+        let options = RecorderOptions {
+            // Unclipped, so the set can be mastered afterwards.
+            format: WavFormat::Float32,
+            sample_rate: summary.sample_rate,
+            ..RecorderOptions::default()
+        };
+        write_wav(out, &mix, &options)?;
+        println!(
+            "Wrote {} ({:.1} s): peak {:.1} dBFS, integrated {:.1} LUFS",
+            out.display(),
+            summary.frames as f64 / summary.sample_rate as f64,
+            20.0 * summary.peak.log10(),
+            summary.loudness.integrated
+        );
+        return Ok(());
     }
 
+    let marker_path = RunMarker::default_path();
+    let unclean = RunMarker::left_behind(&marker_path);
+    let _marker = RunMarker::create(&marker_path)?;
+
     let mut settings = Settings::load()?;
 
     if let Some(device) = cli.device {
@@ -106,10 +223,72 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         settings.sample_rate = sample_rate;
     }
 
+    if let Some(host) = cli.host {
+        settings.host = Some(host);
+    }
+
+    if let Some(exclusive_mode) = cli.exclusive_mode {
+        settings.exclusive_mode = exclusive_mode;
+    }
+
     if cli.save {
         settings.save()?;
     }
 
+    #[cfg(feature = "audio")]
+    match cli.command {
+        Some(Commands::ListDevices) => {
+            for host in list_output_devices() {
+                println!("{}:", host.host);
+                if host.devices.is_empty() {
+                    println!("  (no output devices)");
+                }
+                for device in host.devices {
+                    let marker = if host.default_device.as_deref() == Some(device.as_str()) {
+                        " (default)"
+                    } else {
+                        ""
+                    };
+                    println!("  {device}{marker}");
+                }
+            }
+            return Ok(());
+        }
+        Some(Commands::Probe) => {
+            for config in CpalBackend.supported_configs(&settings)? {
+                let buffer = match config.buffer_frames {
+                    Some((min, max)) => format!("{min}..={max} frames"),
+                    None => "driver default".to_string(),
+                };
+                println!(
+                    "{} ch, {}..={} Hz, {:?}, buffer {}",
+                    config.channels,
+                    config.min_sample_rate,
+                    config.max_sample_rate,
+                    config.sample_format,
+                    buffer
+                );
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if let Some(Commands::Run {
+        restore_session,
+        session,
+    }) = &cli.command
+    {
+        let path = session.clone().unwrap_or_else(Session::default_path);
+        if *restore_session {
+            print_session(&Session::load(&path)?);
+        } else if unclean && path.exists() {
+            println!(
+                "The last run did not exit cleanly. Restore it with `deejay run --restore-session`."
+            );
+        }
+    }
+
     println!(
         "DeeJay v{}\ndevice: {}\nbuffer_frames: {}\nsample_rate: {}",
         version, settings.device, settings.buffer_frames, settings.sample_rate
@@ -117,3 +296,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+fn print_session(session: &Session) {
+    println!(
+        "Restoring session saved at {}",
+        session.saved_at.to_rfc3339()
+    );
+    for (name, deck) in ["A", "B"].iter().zip(&session.decks) {
+        match &deck.path {
+            Some(path) => {
+                let missing = if path.exists() { "" } else { " (missing)" };
+                println!(
+                    "  deck {name}: {} at frame {:.0}{}{missing}",
+                    path.display(),
+                    deck.position_frames,
+                    if deck.playing { ", playing" } else { "" }
+                );
+            }
+            None => println!("  deck {name}: empty"),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey};
+use symphonia::core::probe::Hint;
+use thiserror::Error;
+
+use crate::analysis::gain::{TrackGain, REFERENCE_LUFS};
+use crate::analysis::key::Key;
+use crate::deck::TrackInfo;
+
+#[derive(Debug, Error)]
+pub enum MetadataError {
+    #[error("failed to open track: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Picture embedded in a track's tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artwork {
+    /// MIME type as tagged, e.g. `image/jpeg`.
+    pub media_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Tags and duration of a track, for the library browser and now-playing display.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackMetadata {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub duration_seconds: Option<f64>,
+    /// Tempo written by other DJ software, if any.
+    pub bpm: Option<f64>,
+    pub key: Option<Key>,
+    /// ReplayGain track gain, re-expressed against [`REFERENCE_LUFS`].
+    pub gain: Option<TrackGain>,
+    pub artwork: Option<Artwork>,
+}
+
+impl TrackMetadata {
+    /// The tags shown to listeners.
+    pub fn info(&self) -> TrackInfo {
+        TrackInfo {
+            artist: self.artist.clone(),
+            title: self.title.clone(),
+        }
+    }
+
+    fn apply(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let Some(text) = clean(&tag.value.to_string()) else {
+                continue;
+            };
+            let field = match tag.std_key {
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Album) => &mut self.album,
+                Some(StandardTagKey::Bpm) => {
+                    if self.bpm.is_none() {
+                        self.bpm = text.parse().ok().filter(|bpm: &f64| *bpm > 0.0);
+                    }
+                    continue;
+                }
+                Some(StandardTagKey::ReplayGainTrackGain) => {
+                    if self.gain.is_none() {
+                        self.gain = parse_replay_gain(&text);
+                    }
+                    continue;
+                }
+                _ => {
+                    if self.key.is_none() && is_key_tag(&tag.key) {
+                        self.key = text.parse().ok();
+                    }
+                    continue;
+                }
+            };
+            field.get_or_insert(text);
+        }
+        let visuals = revision.visuals();
+        let cover = visuals
+            .iter()
+            .find(|visual| visual.usage == Some(StandardVisualKey::FrontCover))
+            .or(visuals.first());
+        if let (None, Some(visual)) = (&self.artwork, cover) {
+            self.artwork = Some(Artwork {
+                media_type: visual.media_type.clone(),
+                data: visual.data.to_vec(),
+            });
+        }
+    }
+}
+
+/// Read tags, artwork and duration from the container headers without decoding audio.
+///
+/// Missing or unreadable tags are not an error: the title and artist then come from
+/// an "Artist - Title" file name.
+pub fn read_metadata(path: &Path) -> Result<TrackMetadata, MetadataError> {
+    let file = File::open(path)?;
+    let mut metadata = TrackMetadata::default();
+
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    );
+    if let Ok(mut probed) = probed {
+        // Tags in front of the container (ID3v2) come first, then the container's own.
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            metadata.apply(revision);
+        }
+        let mut format = probed.format;
+        if let Some(revision) = format.metadata().current() {
+            metadata.apply(revision);
+        }
+        metadata.duration_seconds = format.default_track().and_then(|track| {
+            let params = &track.codec_params;
+            let frames = params.n_frames?;
+            match params.time_base {
+                Some(base) => {
+                    let time = base.calc_time(frames);
+                    Some(time.seconds as f64 + time.frac)
+                }
+                None => Some(frames as f64 / params.sample_rate? as f64),
+            }
+        });
+    }
+
+    if metadata.title.is_none() {
+        let (artist, title) = split_file_name(path);
+        metadata.title = title;
+        if metadata.artist.is_none() {
+            metadata.artist = artist;
+        }
+    }
+    Ok(metadata)
+}
+
+/// Artist and title from a file named "Artist - Title.ext", or just the title.
+fn split_file_name(path: &Path) -> (Option<String>, Option<String>) {
+    let Some(stem) = path.file_stem().map(|stem| stem.to_string_lossy()) else {
+        return (None, None);
+    };
+    match stem.split_once(" - ") {
+        Some((artist, title)) => (clean(artist), clean(title)),
+        None => (None, clean(&stem)),
+    }
+}
+
+/// Initial-key tags have no standard key: ID3 uses TKEY, Vorbis comments INITIALKEY or KEY.
+fn is_key_tag(key: &str) -> bool {
+    ["TKEY", "INITIALKEY", "KEY"]
+        .iter()
+        .any(|name| key.eq_ignore_ascii_case(name))
+}
+
+/// Gain from a ReplayGain value such as "-6.50 dB".
+fn parse_replay_gain(text: &str) -> Option<TrackGain> {
+    let number = text.trim_end_matches(|c: char| c.is_alphabetic() || c.is_whitespace());
+    let trim_db: f64 = number.trim().parse().ok()?;
+    Some(TrackGain {
+        integrated_lufs: (REFERENCE_LUFS - trim_db) as f32,
+        trim_db: trim_db as f32,
+    })
+}
+
+/// Trimmed text without stray NULs, or `None` if nothing is left.
+fn clean(text: &str) -> Option<String> {
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn reads_tags_and_artwork_from_each_container() {
+        let png_signature = b"\x89PNG\r\n\x1a\n";
+        for (file, title, duration, has_artwork) in [
+            (
+                "tagged.mp3",
+                "Mp3 Title",
+                Some(40.0 * 1_152.0 / 44_100.0),
+                true,
+            ),
+            ("tagged.flac", "Flac Title", Some(2.0), true),
+            ("tagged.ogg", "Ogg Title", None, false),
+            ("tagged.m4a", "M4a Title", Some(1.0), true),
+        ] {
+            let metadata = read_metadata(&fixture(file)).unwrap();
+            assert_eq!(metadata.title.as_deref(), Some(title), "{file}");
+            assert_eq!(metadata.artist.as_deref(), Some("Fixture Artist"), "{file}");
+            assert_eq!(metadata.album.as_deref(), Some("Fixture Album"), "{file}");
+            if let Some(duration) = duration {
+                let read = metadata.duration_seconds.unwrap();
+                assert!((read - duration).abs() < 1e-6, "{file}: {read}");
+            }
+            let artwork = metadata.artwork.as_ref();
+            assert_eq!(artwork.is_some(), has_artwork, "{file}");
+            if let Some(artwork) = artwork {
+                assert_eq!(artwork.media_type, "image/png", "{file}");
+                assert!(artwork.data.starts_with(png_signature), "{file}");
+            }
+        }
+    }
+
+    #[test]
+    fn reads_dj_tags_when_present() {
+        let mp3 = read_metadata(&fixture("tagged.mp3")).unwrap();
+        assert_eq!(mp3.bpm, Some(124.0));
+        assert_eq!(mp3.key, Some("Am".parse().unwrap()));
+        assert_eq!(mp3.gain, None);
+
+        let flac = read_metadata(&fixture("tagged.flac")).unwrap();
+        assert_eq!(flac.bpm, Some(128.0));
+        assert_eq!(flac.key, Some("Em".parse().unwrap()));
+        let gain = flac.gain.unwrap();
+        assert_eq!(gain.trim_db, -6.5);
+        assert_eq!(gain.integrated_lufs as f64, REFERENCE_LUFS + 6.5);
+
+        let ogg = read_metadata(&fixture("tagged.ogg")).unwrap();
+        assert_eq!((ogg.bpm, ogg.key, ogg.gain), (None, None, None));
+    }
+
+    #[test]
+    fn garbled_tags_fall_back_to_the_file_name() {
+        let metadata = read_metadata(&fixture("Some Artist - Some Title.mp3")).unwrap();
+        assert_eq!(metadata.artist.as_deref(), Some("Some Artist"));
+        assert_eq!(metadata.title.as_deref(), Some("Some Title"));
+        assert!(matches!(
+            read_metadata(&fixture("missing.mp3")),
+            Err(MetadataError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,710 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crossbeam_queue::ArrayQueue;
+use smallvec::SmallVec;
+
+use crate::analysis::gain::{power_to_lufs, KWeighting};
+
+/// Readings are refreshed every 100 ms hop.
+const HOP_SECONDS: f32 = 0.1;
+/// Frames a peak is held for unless the bus is told otherwise: 2 s at 48 kHz.
+pub const DEFAULT_PEAK_HOLD_FRAMES: u64 = 96_000;
+/// Hops in the 400 ms momentary and 3 s short-term windows.
+const MOMENTARY_HOPS: usize = 4;
+const SHORT_TERM_HOPS: usize = 30;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+/// Gating-block histogram covering -70..+10 LUFS in 0.1 LU bins.
+const HISTOGRAM_BINS: usize = 800;
+const HISTOGRAM_FLOOR_LUFS: f64 = -70.0;
+const HISTOGRAM_BIN_LU: f64 = 0.1;
+
+/// EBU R128 loudness of the master; each value is `NEG_INFINITY` while silent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessReading {
+    /// Last 400 ms, in LUFS.
+    pub momentary: f32,
+    /// Last 3 s, in LUFS.
+    pub short_term: f32,
+    /// Gated loudness since the last reset, in LUFS.
+    pub integrated: f32,
+}
+
+impl Default for LoudnessReading {
+    /// Silence.
+    fn default() -> Self {
+        Self {
+            momentary: f32::NEG_INFINITY,
+            short_term: f32::NEG_INFINITY,
+            integrated: f32::NEG_INFINITY,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    momentary: AtomicU32,
+    short_term: AtomicU32,
+    integrated: AtomicU32,
+    reset_requested: AtomicBool,
+}
+
+/// Control-side view of a [`LoudnessMeter`].
+#[derive(Debug, Clone)]
+pub struct LoudnessReader {
+    shared: Arc<Shared>,
+}
+
+impl Shared {
+    fn reading(&self) -> LoudnessReading {
+        let load = |value: &AtomicU32| f32::from_bits(value.load(Ordering::Relaxed));
+        LoudnessReading {
+            momentary: load(&self.momentary),
+            short_term: load(&self.short_term),
+            integrated: load(&self.integrated),
+        }
+    }
+}
+
+impl LoudnessReader {
+    pub fn reading(&self) -> LoudnessReading {
+        self.shared.reading()
+    }
+
+    /// Restart integrated loudness; takes effect on the meter's next block.
+    pub fn reset(&self) {
+        self.shared.reset_requested.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Real-time EBU R128 meter for interleaved stereo, run on the audio thread.
+#[derive(Debug)]
+pub struct LoudnessMeter {
+    filters: [KWeighting; 2],
+    hop_frames: usize,
+    pending: f64,
+    pending_frames: usize,
+    /// Mean-square power of the most recent hops, oldest first once full.
+    hops: [f64; SHORT_TERM_HOPS],
+    hop_index: usize,
+    hops_seen: usize,
+    /// Count and summed power of gating blocks per loudness bin.
+    histogram: Vec<(u64, f64)>,
+    shared: Arc<Shared>,
+}
+
+/// Create a meter for `sample_rate` and the reader that publishes its values.
+pub fn loudness_meter(sample_rate: u32) -> (LoudnessMeter, LoudnessReader) {
+    let silent = f32::NEG_INFINITY.to_bits();
+    let shared = Arc::new(Shared {
+        momentary: AtomicU32::new(silent),
+        short_term: AtomicU32::new(silent),
+        integrated: AtomicU32::new(silent),
+        reset_requested: AtomicBool::new(false),
+    });
+    let mut meter = LoudnessMeter {
+        filters: [KWeighting::new(sample_rate); 2],
+        hop_frames: 1,
+        pending: 0.0,
+        pending_frames: 0,
+        hops: [0.0; SHORT_TERM_HOPS],
+        hop_index: 0,
+        hops_seen: 0,
+        histogram: vec![(0, 0.0); HISTOGRAM_BINS],
+        shared: shared.clone(),
+    };
+    meter.prepare(sample_rate);
+    (meter, LoudnessReader { shared })
+}
+
+impl LoudnessMeter {
+    /// Recompute the K-weighting for the session `sample_rate` and start over.
+    pub fn prepare(&mut self, sample_rate: u32) {
+        self.filters = [KWeighting::new(sample_rate); 2];
+        self.hop_frames = ((sample_rate as f32 * HOP_SECONDS).round() as usize).max(1);
+        self.pending = 0.0;
+        self.pending_frames = 0;
+        self.hops = [0.0; SHORT_TERM_HOPS];
+        self.hops_seen = 0;
+        self.reset_integrated();
+    }
+
+    /// Restart integrated loudness from the audio thread.
+    pub fn reset_integrated(&mut self) {
+        self.histogram.fill((0, 0.0));
+        let silent = f32::NEG_INFINITY.to_bits();
+        self.shared.integrated.store(silent, Ordering::Relaxed);
+    }
+
+    pub fn process(&mut self, frames: &[f32]) {
+        if self.shared.reset_requested.swap(false, Ordering::Relaxed) {
+            self.reset_integrated();
+        }
+        for frame in frames.chunks_exact(2) {
+            self.tick([frame[0], frame[1]]);
+        }
+    }
+
+    /// Measure one frame, for callers already looping over theirs.
+    pub fn tick(&mut self, frame: [f32; 2]) {
+        for (filter, sample) in self.filters.iter_mut().zip(frame) {
+            let weighted = filter.process(sample);
+            self.pending += weighted * weighted;
+        }
+        self.pending_frames += 1;
+        if self.pending_frames == self.hop_frames {
+            self.finish_hop();
+        }
+    }
+
+    /// Readings as of the last finished hop, without going through the reader.
+    pub fn reading(&self) -> LoudnessReading {
+        self.shared.reading()
+    }
+
+    /// Mean power over the last `count` hops, once that many have been seen.
+    fn window_power(&self, count: usize) -> Option<f64> {
+        if self.hops_seen < count {
+            return None;
+        }
+        let sum: f64 = (1..=count)
+            .map(|back| self.hops[(self.hop_index + SHORT_TERM_HOPS - back) % SHORT_TERM_HOPS])
+            .sum();
+        Some(sum / count as f64)
+    }
+
+    fn finish_hop(&mut self) {
+        self.hops[self.hop_index] = self.pending / self.hop_frames as f64;
+        self.hop_index = (self.hop_index + 1) % SHORT_TERM_HOPS;
+        self.hops_seen = (self.hops_seen + 1).min(SHORT_TERM_HOPS);
+        self.pending = 0.0;
+        self.pending_frames = 0;
+
+        let lufs = |power: Option<f64>| power.map_or(f64::NEG_INFINITY, power_to_lufs) as f32;
+        let momentary = self.window_power(MOMENTARY_HOPS);
+        self.store(&self.shared.momentary, lufs(momentary));
+        self.store(
+            &self.shared.short_term,
+            lufs(self.window_power(SHORT_TERM_HOPS)),
+        );
+
+        // Each momentary window is one 400 ms gating block with 75% overlap.
+        if let Some(power) = momentary {
+            let loudness = power_to_lufs(power);
+            if loudness > ABSOLUTE_GATE_LUFS {
+                let bin = ((loudness - HISTOGRAM_FLOOR_LUFS) / HISTOGRAM_BIN_LU) as usize;
+                let (count, sum) = &mut self.histogram[bin.min(HISTOGRAM_BINS - 1)];
+                *count += 1;
+                *sum += power;
+                let integrated = self.integrated();
+                self.store(&self.shared.integrated, lufs(integrated));
+            }
+        }
+    }
+
+    /// Gated mean over the histogram: absolute gate first, then relative.
+    fn integrated(&self) -> Option<f64> {
+        let mean_from = |first_bin: usize| {
+            let (count, sum) = self.histogram[first_bin..]
+                .iter()
+                .fold((0, 0.0), |(c, s), (count, sum)| (c + count, s + sum));
+            (count > 0).then(|| sum / count as f64)
+        };
+        let relative = power_to_lufs(mean_from(0)?) + RELATIVE_GATE_LU;
+        let first = ((relative - HISTOGRAM_FLOOR_LUFS) / HISTOGRAM_BIN_LU).ceil();
+        mean_from((first.max(0.0) as usize).min(HISTOGRAM_BINS - 1))
+    }
+
+    fn store(&self, target: &AtomicU32, value: f32) {
+        target.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// One value per deck of the bus, in deck order. Up to four are held inline;
+/// a frame for a wider bus allocates the rest on the audio thread.
+pub type DeckValues<T> = SmallVec<[T; 4]>;
+
+/// Peak and RMS levels over one mixed block, left and right.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeterFrame {
+    /// Every deck after trim and EQ, ahead of its fader.
+    pub deck_peaks: DeckValues<[f32; 2]>,
+    pub deck_rms: DeckValues<[f32; 2]>,
+    /// The master as it leaves the bus.
+    pub master_peak: [f32; 2],
+    pub master_rms: [f32; 2],
+    /// Highest recent peaks, held for the bus's hold time before falling
+    /// back to the live ones.
+    pub deck_peak_holds: DeckValues<[f32; 2]>,
+    pub master_peak_hold: [f32; 2],
+    /// Samples past full scale since the last
+    /// [`ResetClipIndicators`](crate::ParameterUpdate::ResetClipIndicators):
+    /// every deck after its fader, and the master ahead of its limiter and
+    /// soft clipper.
+    pub deck_clips: DeckValues<u64>,
+    pub master_clips: u64,
+    /// Loudness of the master as it leaves the bus, since the last
+    /// [`ResetLoudness`](crate::ParameterUpdate::ResetLoudness).
+    pub loudness: LoudnessReading,
+    /// NaN and infinite deck samples replaced since the bus started, while
+    /// [`set_sanitize_input`](crate::SummingBus::set_sanitize_input) is on.
+    pub scrubbed: u64,
+    /// Parameter updates held back by
+    /// [`set_updates_per_block`](crate::SummingBus::set_updates_per_block),
+    /// summed over every block that left some queued: a steady climb means
+    /// the control side is sending faster than the bus applies.
+    pub deferred_updates: u64,
+    /// Correlation of the master's left and right ahead of any mono fold,
+    /// from +1 for identical channels through 0 to -1 for inverted ones; 0
+    /// while either is silent.
+    pub correlation: f32,
+    /// Output frame the block started at.
+    pub frame: u64,
+}
+
+/// Audio-thread side of a meter queue, handed to
+/// [`SummingBus::set_meter`](crate::SummingBus::set_meter).
+#[derive(Debug)]
+pub struct MeterSender {
+    queue: Arc<ArrayQueue<MeterFrame>>,
+}
+
+impl MeterSender {
+    /// Enqueue `frame`, dropping it if the control side has fallen behind.
+    pub fn send(&self, frame: MeterFrame) {
+        let _ = self.queue.push(frame);
+    }
+
+    /// Whether the next [`send`](Self::send) would be dropped.
+    pub(crate) fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+}
+
+/// Control-side end of a meter queue.
+#[derive(Debug)]
+pub struct MeterReceiver {
+    queue: Arc<ArrayQueue<MeterFrame>>,
+}
+
+impl MeterReceiver {
+    pub fn pop(&self) -> Option<MeterFrame> {
+        self.queue.pop()
+    }
+
+    /// Drain the queue, keeping only the most recent frame.
+    pub fn latest(&self) -> Option<MeterFrame> {
+        std::iter::from_fn(|| self.queue.pop()).last()
+    }
+}
+
+/// Create a bounded, lock-free queue of meter frames, one per mixed block.
+pub fn meter_channel(capacity: usize) -> (MeterSender, MeterReceiver) {
+    let queue = Arc::new(ArrayQueue::new(capacity));
+    (
+        MeterSender {
+            queue: queue.clone(),
+        },
+        MeterReceiver { queue },
+    )
+}
+
+/// Running peak and sum of squares over a block, for a [`MeterFrame`].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Levels {
+    peak: [f32; 2],
+    squares: [f64; 2],
+}
+
+impl Levels {
+    pub(crate) fn add(&mut self, frame: [f32; 2]) {
+        for ((peak, squares), sample) in self.peak.iter_mut().zip(&mut self.squares).zip(frame) {
+            *peak = peak.max(sample.abs());
+            *squares += (sample as f64).powi(2);
+        }
+    }
+
+    pub(crate) fn peak(&self) -> [f32; 2] {
+        self.peak
+    }
+
+    pub(crate) fn rms(&self, frames: usize) -> [f32; 2] {
+        self.squares
+            .map(|squares| (squares / frames.max(1) as f64).sqrt() as f32)
+    }
+}
+
+/// Running sums for the correlation of left and right over a block.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Correlation {
+    product: f64,
+    squares: [f64; 2],
+}
+
+impl Correlation {
+    pub(crate) fn add(&mut self, [left, right]: [f32; 2]) {
+        let (left, right) = (left as f64, right as f64);
+        self.product += left * right;
+        self.squares[0] += left * left;
+        self.squares[1] += right * right;
+    }
+
+    pub(crate) fn value(&self) -> f32 {
+        let norm = (self.squares[0] * self.squares[1]).sqrt();
+        if norm > 0.0 {
+            (self.product / norm) as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Peak held for a number of frames, then let fall to the live peak.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PeakHold {
+    held: [f32; 2],
+    /// Frames since each side's held peak was set.
+    age: [u64; 2],
+}
+
+impl PeakHold {
+    /// Follow a block of `frames` that peaked at `peak`, returning the held peak.
+    pub(crate) fn update(&mut self, peak: [f32; 2], frames: u64, hold_frames: u64) -> [f32; 2] {
+        for ((held, age), peak) in self.held.iter_mut().zip(&mut self.age).zip(peak) {
+            *age += frames;
+            if peak >= *held || *age > hold_frames {
+                *held = peak;
+                *age = 0;
+            }
+        }
+        self.held
+    }
+
+    pub(crate) fn held(&self) -> [f32; 2] {
+        self.held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limiter::{Limiter, LimiterOptions};
+    use crate::{parameter_channel, DeckId, ParameterUpdate, SummingBus};
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn sine(dbfs: f32, seconds: f32) -> Vec<f32> {
+        let amplitude = 10f32.powf(dbfs / 20.0);
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .flat_map(|i| {
+                let s = amplitude * (i as f32 / 48.0 * std::f32::consts::TAU).sin();
+                [s, s]
+            })
+            .collect()
+    }
+
+    fn feed(meter: &mut LoudnessMeter, samples: &[f32]) {
+        for block in samples.chunks(1_024) {
+            meter.process(block);
+        }
+    }
+
+    #[test]
+    fn reference_sine_reads_minus_23_on_every_scale() {
+        let (mut meter, reader) = loudness_meter(44_100);
+        meter.prepare(SAMPLE_RATE);
+        assert_eq!(reader.reading().momentary, f32::NEG_INFINITY);
+
+        feed(&mut meter, &sine(-23.0, 20.0));
+        let reading = reader.reading();
+        for value in [reading.momentary, reading.short_term, reading.integrated] {
+            assert!((value + 23.0).abs() < 0.1, "{reading:?}");
+        }
+    }
+
+    #[test]
+    fn gate_excludes_silence_and_quiet_passages() {
+        let (mut meter, reader) = loudness_meter(SAMPLE_RATE);
+        // Tech 3341 case 3 followed by silence: integrated stays at -23 LUFS.
+        feed(&mut meter, &sine(-36.0, 10.0));
+        feed(&mut meter, &sine(-23.0, 60.0));
+        feed(&mut meter, &sine(-36.0, 10.0));
+        feed(&mut meter, &vec![0.0; SAMPLE_RATE as usize * 2 * 10]);
+        let reading = reader.reading();
+        assert!((reading.integrated + 23.0).abs() < 0.1, "{reading:?}");
+        assert_eq!(reading.momentary, f32::NEG_INFINITY);
+
+        reader.reset();
+        feed(&mut meter, &vec![0.0; 9_600]);
+        assert_eq!(reader.reading().integrated, f32::NEG_INFINITY);
+        feed(&mut meter, &sine(-18.0, 10.0));
+        assert!((reader.reading().integrated + 18.0).abs() < 0.1);
+    }
+
+    /// 1 kHz at 48 kHz, a whole number of cycles in every 480-frame block.
+    fn tone(left: f32, right: f32) -> Vec<f32> {
+        (0..4_800)
+            .flat_map(|i| {
+                let s = (i as f32 / 48.0 * std::f32::consts::TAU).sin();
+                [s * left, s * right]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bus_meters_decks_and_master_per_block() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (meter, meters) = meter_channel(16);
+        bus.set_meter(Some(meter));
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let deck_a = tone(0.5, 0.5);
+        let deck_b = tone(0.25, 0.0);
+        let mut out = vec![0.0; deck_a.len()];
+        for ((a, b), out) in deck_a
+            .chunks(960)
+            .zip(deck_b.chunks(960))
+            .zip(out.chunks_mut(960))
+        {
+            bus.mix_stereo(a, b, out);
+        }
+
+        let frames: Vec<MeterFrame> = std::iter::from_fn(|| meters.pop()).collect();
+        assert_eq!(frames.len(), 10);
+        assert!(frames
+            .iter()
+            .map(|f| f.frame)
+            .eq((0..10).map(|block| block * 480)));
+        let close = |actual: [f32; 2], expected: [f32; 2]| {
+            for (actual, expected) in actual.into_iter().zip(expected) {
+                assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}");
+            }
+        };
+        let rms = std::f32::consts::FRAC_1_SQRT_2;
+        for frame in &frames {
+            close(frame.deck_peaks[0], [0.5; 2]);
+            close(frame.deck_rms[0], [0.5 * rms; 2]);
+            // Faded out by the crossfader, but metered ahead of it.
+            close(frame.deck_peaks[1], [0.25, 0.0]);
+            close(frame.deck_rms[1], [0.25 * rms, 0.0]);
+            close(frame.master_peak, [0.5; 2]);
+            close(frame.master_rms, [0.5 * rms; 2]);
+        }
+        assert_eq!(meters.latest(), None);
+    }
+
+    #[test]
+    fn every_deck_of_a_wider_bus_is_metered() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::with_decks(4, rx);
+        let (meter, meters) = meter_channel(16);
+        bus.set_meter(Some(meter));
+        tx.send(ParameterUpdate::DeckGain {
+            deck: DeckId(3),
+            gain: 0.0,
+        })
+        .unwrap();
+        let decks = [
+            tone(0.1, 0.1),
+            tone(0.2, 0.2),
+            tone(0.3, 0.0),
+            tone(1.5, 0.4),
+        ];
+        let decks: Vec<&[f32]> = decks.iter().map(|deck| &deck[..960]).collect();
+        let mut out = vec![0.0; 960];
+        bus.mix(&decks, &mut out);
+
+        let frame = meters.latest().unwrap();
+        let peaks: Vec<[f32; 2]> = frame.deck_peaks.to_vec();
+        let expected = [[0.1, 0.1], [0.2, 0.2], [0.3, 0.0], [1.5, 0.4]];
+        for (actual, expected) in peaks.iter().flatten().zip(expected.iter().flatten()) {
+            assert!((actual - expected).abs() < 1e-4, "{peaks:?}");
+        }
+        assert_eq!(frame.deck_peak_holds, frame.deck_peaks);
+        assert_eq!(frame.deck_rms.len(), 4);
+        // Deck 4 is hot but faded out, so only its metered peak shows it.
+        assert_eq!(frame.deck_clips[..], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decks_past_the_inline_four_are_metered() {
+        let (_tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::with_decks(6, rx);
+        let (meter, meters) = meter_channel(1);
+        bus.set_meter(Some(meter));
+        let decks: Vec<Vec<f32>> = (1..=6).map(|deck| tone(deck as f32 / 10.0, 0.0)).collect();
+        let mut out = vec![0.0; 960];
+        for block in 0..3 {
+            let decks: Vec<&[f32]> = decks
+                .iter()
+                .map(|deck| &deck[block * 960..][..960])
+                .collect();
+            bus.mix(&decks, &mut out);
+        }
+
+        // The queue only had room for the first block.
+        let frame = meters.pop().unwrap();
+        assert!(meters.pop().is_none());
+        assert_eq!(frame.frame, 0);
+        assert!(frame.deck_peaks.spilled());
+        for (deck, [left, right]) in frame.deck_peaks.iter().enumerate() {
+            assert!(
+                (left - (deck + 1) as f32 / 10.0).abs() < 1e-4,
+                "{deck}: {left}"
+            );
+            assert_eq!(*right, 0.0);
+        }
+        assert_eq!(frame.deck_peak_holds, frame.deck_peaks);
+        assert_eq!(frame.deck_rms.len(), 6);
+        assert_eq!(frame.deck_clips[..], [0; 6]);
+    }
+
+    #[test]
+    fn full_meter_queue_leaves_the_mix_alone() {
+        let render = |meter: Option<MeterSender>| {
+            let (tx, rx) = parameter_channel(4);
+            let mut bus = SummingBus::new(rx);
+            bus.set_meter(meter);
+            tx.send(ParameterUpdate::Crossfader(0.3)).unwrap();
+            let deck_a = tone(0.5, 0.4);
+            let deck_b = tone(0.2, 0.3);
+            let mut out = vec![0.0; deck_a.len()];
+            for ((a, b), out) in deck_a
+                .chunks(512)
+                .zip(deck_b.chunks(512))
+                .zip(out.chunks_mut(512))
+            {
+                bus.mix_stereo(a, b, out);
+            }
+            out
+        };
+        let (meter, meters) = meter_channel(1);
+        assert_eq!(render(Some(meter)), render(None));
+        // Only the first block fit; the rest were dropped.
+        assert_eq!(meters.latest().map(|frame| frame.frame), Some(0));
+    }
+
+    #[test]
+    fn clips_are_counted_until_reset() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        bus.set_limiter(Some(Limiter::new(48_000, LimiterOptions::default())));
+        let (meter, meters) = meter_channel(16);
+        bus.set_meter(Some(meter));
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let mut deck_a = vec![0.5; 9_600];
+        let offending = [10, 11, 501, 3_001, 9_599];
+        for (n, &sample) in offending.iter().enumerate() {
+            deck_a[sample] = if n % 2 == 0 { 1.5 } else { -1.2 };
+        }
+        // Deck B is hot, but faded out by the crossfader.
+        let deck_b = vec![2.0; 9_600];
+        let mut out = vec![0.0; 9_600];
+        for ((a, b), out) in deck_a
+            .chunks(960)
+            .zip(deck_b.chunks(960))
+            .zip(out.chunks_mut(960))
+        {
+            bus.mix_stereo(a, b, out);
+        }
+        assert!(out.iter().all(|s| s.abs() < 1.0));
+        let frame = meters.latest().unwrap();
+        assert_eq!(frame.deck_clips[..], [5, 0]);
+        // Counted ahead of the limiter, which kept them all out of the output.
+        assert_eq!(frame.master_clips, 5);
+
+        tx.send(ParameterUpdate::ResetClipIndicators).unwrap();
+        bus.mix_stereo(&[0.5; 960], &[2.0; 960], &mut out[..960]);
+        let frame = meters.latest().unwrap();
+        assert_eq!(frame.deck_clips[..], [0, 0]);
+        assert_eq!(frame.master_clips, 0);
+    }
+
+    #[test]
+    fn peaks_are_held_for_the_hold_time() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (meter, meters) = meter_channel(16);
+        bus.set_meter(Some(meter));
+        bus.set_peak_hold_frames(960);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let mut out = [0.0; 960];
+        let mut held = Vec::new();
+        for level in [0.8, 0.2, 0.2, 0.2, 0.2, 0.9, 0.4] {
+            bus.mix_stereo(&[level; 960], &[0.0; 960], &mut out);
+            let frame = meters.pop().unwrap();
+            assert_eq!(frame.deck_peak_holds[0], frame.master_peak_hold);
+            held.push(frame.master_peak_hold[0]);
+        }
+        // Two blocks of 480 frames after the peak it is still held, then falls.
+        let expected = [0.8, 0.8, 0.8, 0.2, 0.2, 0.9, 0.9];
+        for (held, expected) in held.iter().zip(expected) {
+            assert!((held - expected).abs() < 1e-6, "{held:?}");
+        }
+    }
+
+    #[test]
+    fn bus_sends_master_loudness_with_its_meters() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (meter, meters) = meter_channel(4);
+        bus.set_meter(Some(meter));
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let mut play = |samples: &[f32]| {
+            let mut out = vec![0.0; 1_024];
+            for block in samples.chunks(1_024) {
+                let out = &mut out[..block.len()];
+                bus.mix_stereo(block, &vec![0.0; block.len()], out);
+                meters.latest();
+            }
+            bus.mix_stereo(&[0.0; 2], &[0.0; 2], &mut out[..2]);
+            meters.latest().unwrap().loudness
+        };
+
+        let reading = play(&sine(-23.0, 20.0));
+        for value in [reading.momentary, reading.short_term, reading.integrated] {
+            assert!((value + 23.0).abs() < 0.5, "{reading:?}");
+        }
+
+        // Once the momentary window has gone quiet, a reset clears the integrated value.
+        play(&[0.0; 96_000]);
+        tx.send(ParameterUpdate::ResetLoudness).unwrap();
+        assert_eq!(play(&[0.0; 9_600]).integrated, f32::NEG_INFINITY);
+        let reading = play(&sine(-18.0, 10.0));
+        assert!((reading.integrated + 18.0).abs() < 0.5, "{reading:?}");
+    }
+
+    #[test]
+    fn mono_output_folds_both_sides_and_correlation_reads_the_phase() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (meter, meters) = meter_channel(4);
+        bus.set_meter(Some(meter));
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let mut play = |left: f32, right: f32| {
+            let deck_a = tone(left, right);
+            let mut out = vec![0.0; deck_a.len()];
+            bus.mix_stereo(&deck_a, &vec![0.0; deck_a.len()], &mut out);
+            (meters.latest().unwrap().correlation, out)
+        };
+        let (identical, _) = play(0.5, 0.5);
+        assert!((identical - 1.0).abs() < 1e-4, "{identical}");
+        let (inverted, _) = play(0.5, -0.5);
+        assert!((inverted + 1.0).abs() < 1e-4, "{inverted}");
+        let (silent, _) = play(0.5, 0.0);
+        assert_eq!(silent, 0.0);
+
+        // Hard left comes out of both sides at -3 dB once folded.
+        tx.send(ParameterUpdate::MonoOutput(true)).unwrap();
+        let (before_fold, out) = play(0.5, 0.0);
+        assert_eq!(before_fold, 0.0);
+        let settled = &out[out.len() / 2..];
+        assert!(settled.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+        let peak = settled.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(
+            (peak - 0.5 * std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-4,
+            "{peak}"
+        );
+    }
+}
@@ -0,0 +1,383 @@
+#[cfg(feature = "metrics")]
+mod http;
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::version::current_version;
+
+#[cfg(feature = "metrics")]
+pub use http::{MetricsError, MetricsServer};
+
+/// Upper bounds of the duration histogram buckets, in microseconds.
+const DURATION_BOUNDS_MICROS: [u64; 10] = [
+    50, 100, 200, 500, 1_000, 2_000, 5_000, 10_000, 20_000, 50_000,
+];
+/// Quantiles reported for duration summaries.
+const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+/// Lock-free histogram of durations, cheap enough to record from the audio callback.
+#[derive(Debug, Default)]
+pub struct DurationHistogram {
+    /// Per-bucket counts; the last one takes everything above the largest bound.
+    buckets: [AtomicU64; DURATION_BOUNDS_MICROS.len() + 1],
+    sum_nanos: AtomicU64,
+}
+
+impl DurationHistogram {
+    pub fn record(&self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        let bucket = DURATION_BOUNDS_MICROS
+            .iter()
+            .position(|bound| nanos <= bound * 1_000)
+            .unwrap_or(DURATION_BOUNDS_MICROS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> DurationSnapshot {
+        DurationSnapshot {
+            counts: std::array::from_fn(|bucket| self.buckets[bucket].load(Ordering::Relaxed)),
+            sum_seconds: self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9,
+        }
+    }
+}
+
+/// Point-in-time copy of a [`DurationHistogram`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DurationSnapshot {
+    counts: [u64; DURATION_BOUNDS_MICROS.len() + 1],
+    pub sum_seconds: f64,
+}
+
+impl DurationSnapshot {
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Upper bound in seconds of the bucket holding quantile `q`: NaN when empty, infinite when
+    /// it lies beyond the largest bucket.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let count = self.count();
+        if count == 0 {
+            return f64::NAN;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, bucket_count) in self.counts.iter().enumerate() {
+            seen += bucket_count;
+            if seen >= rank {
+                return DURATION_BOUNDS_MICROS
+                    .get(bucket)
+                    .map_or(f64::INFINITY, |bound| *bound as f64 / 1e6);
+            }
+        }
+        f64::INFINITY
+    }
+}
+
+/// One scrape's worth of samples, written in the Prometheus text exposition format.
+#[derive(Debug, Default)]
+pub struct MetricSet {
+    text: String,
+}
+
+impl MetricSet {
+    fn header(&mut self, name: &str, help: &str, kind: &str) {
+        let help = help.replace('\\', "\\\\").replace('\n', "\\n");
+        let _ = writeln!(self.text, "# HELP {name} {help}");
+        let _ = writeln!(self.text, "# TYPE {name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            self.text.push('{');
+            for (index, (label, value)) in labels.iter().enumerate() {
+                if index > 0 {
+                    self.text.push(',');
+                }
+                let value = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                let _ = write!(self.text, "{label}=\"{value}\"");
+            }
+            self.text.push('}');
+        }
+        let _ = writeln!(self.text, " {}", format_value(value));
+    }
+
+    /// A monotonically increasing total; by convention `name` ends in `_total`.
+    pub fn counter(&mut self, name: &str, help: &str, value: u64) {
+        self.header(name, help, "counter");
+        self.sample(name, &[], value as f64);
+    }
+
+    pub fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.gauge_with(name, help, &[], value);
+    }
+
+    pub fn gauge_with(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        self.header(name, help, "gauge");
+        self.sample(name, labels, value);
+    }
+
+    /// Durations in seconds as a summary with the median, 90th and 99th percentiles.
+    pub fn summary(&mut self, name: &str, help: &str, durations: &DurationSnapshot) {
+        self.header(name, help, "summary");
+        for q in QUANTILES {
+            let quantile = q.to_string();
+            self.sample(name, &[("quantile", &quantile)], durations.quantile(q));
+        }
+        self.sample(&format!("{name}_sum"), &[], durations.sum_seconds);
+        self.sample(&format!("{name}_count"), &[], durations.count() as f64);
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".into()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.into()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Something that reports metrics when scraped, typically a reader over a module's counters.
+pub trait Collect: Send + Sync {
+    fn collect(&self, metrics: &mut MetricSet);
+}
+
+impl<F: Fn(&mut MetricSet) + Send + Sync> Collect for F {
+    fn collect(&self, metrics: &mut MetricSet) {
+        self(metrics)
+    }
+}
+
+/// Collectors by the key they were registered under, in registration order.
+type Collectors = Vec<(String, Box<dyn Collect>)>;
+
+/// Collectors scraped together; modules register readers here without knowing how they are served.
+#[derive(Clone, Default)]
+pub struct Registry {
+    collectors: Arc<Mutex<Collectors>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `collector` under `key`, replacing the one registered before, e.g. by the previous recording.
+    pub fn register(&self, key: impl Into<String>, collector: impl Collect + 'static) {
+        let key = key.into();
+        let mut collectors = self.collectors.lock().unwrap();
+        match collectors.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, slot)) => *slot = Box::new(collector),
+            None => collectors.push((key, Box::new(collector))),
+        }
+    }
+
+    pub fn unregister(&self, key: &str) -> bool {
+        let mut collectors = self.collectors.lock().unwrap();
+        let before = collectors.len();
+        collectors.retain(|(existing, _)| existing != key);
+        collectors.len() != before
+    }
+
+    /// Scrape every collector, after a build info sample labelled with the app and version.
+    pub fn render(&self) -> String {
+        let mut metrics = MetricSet::default();
+        metrics.gauge_with(
+            "deejay_build_info",
+            "Always 1; labels identify the running build.",
+            &[("app", "deejay"), ("version", current_version())],
+            1.0,
+        );
+        for (_, collector) in self.collectors.lock().unwrap().iter() {
+            collector.collect(&mut metrics);
+        }
+        metrics.into_text()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Check `text` against the exposition format: every sample follows its family's TYPE line,
+    /// names are valid and label values are quoted. Returns `(name, labels, value)` per sample.
+    pub(crate) fn parse_exposition(text: &str) -> Vec<(String, String, f64)> {
+        let valid_name = |name: &str| {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+        };
+        let mut family: Option<String> = None;
+        let mut samples = Vec::new();
+        assert!(text.ends_with('\n'), "exposition must end with a newline");
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# HELP ") {
+                let name = rest.split(' ').next().unwrap();
+                assert!(valid_name(name), "bad name in {line:?}");
+            } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert!(valid_name(name), "bad name in {line:?}");
+                assert!(
+                    ["counter", "gauge", "summary", "histogram", "untyped"].contains(&kind),
+                    "bad type in {line:?}"
+                );
+                family = Some(name.to_string());
+            } else {
+                let (series, value) = line.rsplit_once(' ').unwrap();
+                let (name, labels) = match series.split_once('{') {
+                    Some((name, labels)) => {
+                        let labels = labels.strip_suffix('}').expect("unclosed labels");
+                        for pair in split_labels(labels) {
+                            let (label, value) = pair.split_once('=').unwrap();
+                            assert!(valid_name(label), "bad label in {line:?}");
+                            assert!(
+                                value.len() >= 2 && value.starts_with('"') && value.ends_with('"'),
+                                "unquoted label value in {line:?}"
+                            );
+                        }
+                        (name, labels)
+                    }
+                    None => (series, ""),
+                };
+                let family = family.as_deref().expect("sample before TYPE");
+                assert!(
+                    name == family
+                        || name == format!("{family}_sum")
+                        || name == format!("{family}_count"),
+                    "{name} outside its family {family}"
+                );
+                let value = match value {
+                    "NaN" => f64::NAN,
+                    "+Inf" => f64::INFINITY,
+                    "-Inf" => f64::NEG_INFINITY,
+                    value => value.parse().expect("bad value"),
+                };
+                samples.push((name.to_string(), labels.to_string(), value));
+            }
+        }
+        samples
+    }
+
+    /// Split `a="x",b="y"` on the commas outside quotes.
+    fn split_labels(labels: &str) -> Vec<&str> {
+        let mut pairs = Vec::new();
+        let (mut start, mut quoted, mut escaped) = (0, false, false);
+        for (index, c) in labels.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    pairs.push(&labels[start..index]);
+                    start = index + 1;
+                }
+                _ => {}
+            }
+        }
+        pairs.push(&labels[start..]);
+        pairs
+    }
+
+    #[test]
+    fn quantiles_come_from_bucket_bounds() {
+        let histogram = DurationHistogram::default();
+        assert!(histogram.snapshot().quantile(0.5).is_nan());
+        for _ in 0..90 {
+            histogram.record(Duration::from_micros(80));
+        }
+        for _ in 0..9 {
+            histogram.record(Duration::from_micros(1_500));
+        }
+        histogram.record(Duration::from_millis(80));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count(), 100);
+        assert_eq!(snapshot.quantile(0.5), 100e-6);
+        assert_eq!(snapshot.quantile(0.9), 100e-6);
+        assert_eq!(snapshot.quantile(0.99), 2e-3);
+        assert_eq!(snapshot.quantile(1.0), f64::INFINITY);
+        assert!((snapshot.sum_seconds - (90.0 * 80e-6 + 9.0 * 1.5e-3 + 0.08)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn renders_valid_exposition_with_escaped_labels() {
+        let registry = Registry::new();
+        let durations = DurationHistogram::default();
+        durations.record(Duration::from_micros(300));
+        let snapshot = durations.snapshot();
+        registry.register("fake", move |metrics: &mut MetricSet| {
+            metrics.counter("deejay_fake_total", "Things that\nhappened.", 7);
+            metrics.gauge_with(
+                "deejay_fake_state",
+                "Labelled gauge.",
+                &[("name", "say \"hi\"\\"), ("kind", "a,b")],
+                0.5,
+            );
+            metrics.summary("deejay_fake_seconds", "Durations.", &snapshot);
+        });
+
+        let text = registry.render();
+        assert!(text.contains("# HELP deejay_fake_total Things that\\nhappened.\n"));
+        let samples = parse_exposition(&text);
+        let find = |name: &str| samples.iter().find(|(n, _, _)| n == name).unwrap();
+
+        let (_, labels, value) = find("deejay_build_info");
+        assert_eq!(
+            *labels,
+            format!("app=\"deejay\",version=\"{}\"", current_version())
+        );
+        assert_eq!(*value, 1.0);
+        assert_eq!(find("deejay_fake_total").2, 7.0);
+        assert_eq!(
+            find("deejay_fake_state").1,
+            r#"name="say \"hi\"\\",kind="a,b""#
+        );
+        let quantiles: Vec<_> = samples
+            .iter()
+            .filter(|(name, _, _)| name == "deejay_fake_seconds")
+            .map(|(_, labels, value)| (labels.as_str(), *value))
+            .collect();
+        assert_eq!(
+            quantiles,
+            [
+                ("quantile=\"0.5\"", 500e-6),
+                ("quantile=\"0.9\"", 500e-6),
+                ("quantile=\"0.99\"", 500e-6)
+            ]
+        );
+        assert_eq!(find("deejay_fake_seconds_count").2, 1.0);
+    }
+
+    #[test]
+    fn registering_a_key_again_replaces_its_collector() {
+        let registry = Registry::new();
+        registry.register("recording", |metrics: &mut MetricSet| {
+            metrics.counter("deejay_recording_dropped_blocks_total", "Dropped.", 3)
+        });
+        registry.register("recording", |metrics: &mut MetricSet| {
+            metrics.counter("deejay_recording_dropped_blocks_total", "Dropped.", 0)
+        });
+        let samples = parse_exposition(&registry.render());
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].2, 0.0);
+
+        assert!(registry.unregister("recording"));
+        assert_eq!(parse_exposition(&registry.render()).len(), 1);
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use super::Registry;
+use crate::crash::record_breadcrumb;
+
+/// How often the idle server checks whether it should stop.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+/// A scraper that stalls mid-request longer than this is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Debug, Error)]
+pub enum MetricsError {
+    #[error("failed to bind metrics endpoint on {address}: {source}")]
+    Bind { address: String, source: io::Error },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Serves `GET /metrics` from a [`Registry`] on a background thread.
+pub struct MetricsServer {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    worker: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    /// Listen on `address`, e.g. `0.0.0.0:9898`; port 0 picks a free one.
+    pub fn start(address: &str, registry: Registry) -> Result<Self, MetricsError> {
+        let listener = TcpListener::bind(address).map_err(|source| MetricsError::Bind {
+            address: address.to_string(),
+            source,
+        })?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker_stop = stop.clone();
+        let worker = thread::Builder::new()
+            .name("metrics".into())
+            .spawn(move || serve(listener, registry, worker_stop))?;
+        Ok(Self {
+            address,
+            stop,
+            worker,
+        })
+    }
+
+    /// Address actually bound.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Release);
+        let _ = self.worker.join();
+    }
+}
+
+fn serve(listener: TcpListener, registry: Registry, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Acquire) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(err) = respond(stream, &registry) {
+                    record_breadcrumb(format!("metrics request failed: {err}"));
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+            Err(err) => {
+                record_breadcrumb(format!("metrics accept failed: {err}"));
+                thread::sleep(ACCEPT_INTERVAL);
+            }
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, registry: &Registry) -> io::Result<()> {
+    // Accepted sockets inherit non-blocking mode on some platforms.
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", registry.render()),
+        ("GET", _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::tests::parse_exposition;
+    use crate::metrics::MetricSet;
+    use std::io::Read;
+
+    fn get(address: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_the_registry_over_http() {
+        let registry = Registry::new();
+        registry.register("fake", |metrics: &mut MetricSet| {
+            metrics.counter(
+                "deejay_xruns_total",
+                "Callbacks that missed their deadline.",
+                4,
+            );
+            metrics.gauge("deejay_stream_connected", "Whether the stream is up.", 1.0);
+        });
+        let server = MetricsServer::start("127.0.0.1:0", registry).unwrap();
+
+        let response = get(
+            server.local_addr(),
+            "GET /metrics HTTP/1.1\r\nHost: localhost\r\nAccept: text/plain\r\n\r\n",
+        );
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{head}");
+        assert!(head.contains(&format!("Content-Type: {CONTENT_TYPE}")));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+
+        let samples = parse_exposition(body);
+        let names: Vec<_> = samples.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "deejay_build_info",
+                "deejay_xruns_total",
+                "deejay_stream_connected"
+            ]
+        );
+        assert_eq!(samples[1].2, 4.0);
+
+        let missing = get(server.local_addr(), "GET /other HTTP/1.1\r\n\r\n");
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let post = get(server.local_addr(), "POST /metrics HTTP/1.1\r\n\r\n");
+        assert!(post.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        server.stop();
+    }
+
+    #[test]
+    fn reports_the_address_it_could_not_bind() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = taken.local_addr().unwrap().to_string();
+        match MetricsServer::start(&address, Registry::new()) {
+            Err(MetricsError::Bind {
+                address: failed, ..
+            }) => assert_eq!(failed, address),
+            other => panic!("unexpected {:?}", other.map(|server| server.local_addr())),
+        }
+    }
+}
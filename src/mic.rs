@@ -0,0 +1,263 @@
+use std::f32::consts::TAU;
+
+use crate::db::db_to_linear;
+
+/// Envelope follower attack and release on the mic level.
+const ENVELOPE_ATTACK_SECONDS: f32 = 0.001;
+const ENVELOPE_RELEASE_SECONDS: f32 = 0.15;
+/// How quickly the music ducks once the mic opens, and recovers once it closes.
+const DUCK_ATTACK_SECONDS: f32 = 0.01;
+const DUCK_RELEASE_SECONDS: f32 = 0.4;
+/// Low-cut settings at or below this leave the mic unfiltered.
+const LOW_CUT_OFF_HZ: f32 = 10.0;
+
+fn coeff(seconds: f32, sample_rate: u32) -> f32 {
+    (-1.0 / (seconds * sample_rate as f32)).exp()
+}
+
+/// Microphone strip summed into the master, with talkover ducking of the music.
+#[derive(Debug, Clone)]
+pub struct MicChannel {
+    sample_rate: u32,
+    gain: f32,
+    low_cut_coeff: f32,
+    /// Previous input and output of the low-cut, per channel.
+    low_cut_state: [(f32, f32); 2],
+    threshold: f32,
+    depth: f32,
+    /// Talkover held on by hand, whatever the mic level.
+    talkover: bool,
+    envelope: f32,
+    duck: f32,
+}
+
+impl MicChannel {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut mic = Self {
+            sample_rate,
+            gain: 1.0,
+            low_cut_coeff: 1.0,
+            low_cut_state: [(0.0, 0.0); 2],
+            threshold: db_to_linear(-30.0),
+            depth: db_to_linear(-12.0),
+            talkover: false,
+            envelope: 0.0,
+            duck: 1.0,
+        };
+        mic.set_low_cut_hz(100.0);
+        mic
+    }
+
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain.max(0.0);
+    }
+
+    /// High-pass corner removing handling noise and rumble; 0 disables it.
+    pub fn set_low_cut_hz(&mut self, hz: f32) {
+        self.low_cut_coeff = if hz <= LOW_CUT_OFF_HZ {
+            1.0
+        } else {
+            (-TAU * hz / self.sample_rate as f32).exp()
+        };
+    }
+
+    /// Mic level above which the music is ducked.
+    pub fn set_threshold_db(&mut self, db: f32) {
+        self.threshold = db_to_linear(db);
+    }
+
+    /// How far the music is pulled down while the mic is open; 0 dB disables talkover.
+    pub fn set_depth_db(&mut self, db: f32) {
+        self.depth = db_to_linear(db.min(0.0));
+    }
+
+    pub fn talkover(&self) -> bool {
+        self.talkover
+    }
+
+    /// Duck the music while `on`, as if the mic were over the threshold.
+    pub fn set_talkover(&mut self, on: bool) {
+        self.talkover = on;
+    }
+
+    /// Gain currently applied to the music.
+    pub fn duck_gain(&self) -> f32 {
+        self.duck
+    }
+
+    /// Filter one mic frame and advance the ducker. Returns the processed frame
+    /// and the gain to apply to the music for this frame.
+    pub fn tick(&mut self, frame: [f32; 2]) -> ([f32; 2], f32) {
+        let mut out = [0.0; 2];
+        for ((out, input), (last_in, last_out)) in
+            out.iter_mut().zip(frame).zip(&mut self.low_cut_state)
+        {
+            let x = input * self.gain;
+            *out = if self.low_cut_coeff < 1.0 {
+                // One-pole high-pass: y[n] = a * (y[n-1] + x[n] - x[n-1]).
+                self.low_cut_coeff * (*last_out + x - *last_in)
+            } else {
+                x
+            };
+            *last_in = x;
+            *last_out = *out;
+        }
+
+        let level = out[0].abs().max(out[1].abs());
+        let env_coeff = if level > self.envelope {
+            coeff(ENVELOPE_ATTACK_SECONDS, self.sample_rate)
+        } else {
+            coeff(ENVELOPE_RELEASE_SECONDS, self.sample_rate)
+        };
+        self.envelope = level + (self.envelope - level) * env_coeff;
+
+        let target = if self.talkover || self.envelope > self.threshold {
+            self.depth
+        } else {
+            1.0
+        };
+        let duck_coeff = if target < self.duck {
+            coeff(DUCK_ATTACK_SECONDS, self.sample_rate)
+        } else {
+            coeff(DUCK_RELEASE_SECONDS, self.sample_rate)
+        };
+        let next = target + (self.duck - target) * duck_coeff;
+        // Snap once close, or once rounding stalls the glide short of the target.
+        self.duck = if (next - target).abs() < 1e-6 || next == self.duck {
+            target
+        } else {
+            next
+        };
+        (out, self.duck)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, ParameterUpdate, SummingBus};
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn noise(frames: usize, level: f32) -> Vec<f32> {
+        let mut state = 0x5eed_1234u32;
+        (0..frames)
+            .flat_map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let s = ((state >> 8) as f32 / (1u32 << 23) as f32 - 1.0) * level;
+                [s, s]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn speech_burst_ducks_the_music_and_recovers() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx).with_sample_rate(SAMPLE_RATE);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::MasterGain(0.5)).unwrap();
+        tx.send(ParameterUpdate::MicLowCutHz(0.0)).unwrap();
+        tx.send(ParameterUpdate::TalkoverDepthDb(-12.0)).unwrap();
+
+        // Half a second of quiet, one second of speech, then five seconds of quiet.
+        let frames = 6 * SAMPLE_RATE as usize + SAMPLE_RATE as usize / 2;
+        let burst = SAMPLE_RATE as usize / 2..3 * SAMPLE_RATE as usize / 2;
+        let speech = noise(burst.len(), 0.3);
+        let mut mic = vec![0.0; frames * 2];
+        mic[burst.start * 2..burst.end * 2].copy_from_slice(&speech);
+        let mut deck_a = vec![0.8; frames * 2];
+        let mut out = vec![0.0; frames * 2];
+        for ((a, m), o) in deck_a
+            .chunks_mut(512)
+            .zip(mic.chunks(512))
+            .zip(out.chunks_mut(512))
+        {
+            bus.process_with_mic(a, &mut [0.0; 512][..a.len()], Some(m), o);
+        }
+
+        // Music level per frame, with the mic's contribution removed.
+        let music: Vec<f32> = out
+            .chunks_exact(2)
+            .zip(mic.chunks_exact(2))
+            .map(|(o, m)| (o[0] - m[0] * 0.5) / 0.4)
+            .collect();
+        let depth = db_to_linear(-12.0);
+        assert!((music[burst.start - 1] - 1.0).abs() < 1e-6);
+        // Fast attack: fully ducked within 100 ms.
+        assert!((music[burst.start + 4_800] - depth).abs() < 1e-3);
+        assert!((music[burst.end - 1] - depth).abs() < 1e-3);
+        // Slow release: still well down 100 ms after the speech stops...
+        let after = burst.end + ((ENVELOPE_RELEASE_SECONDS + 0.1) * SAMPLE_RATE as f32) as usize;
+        assert!(music[after] < 0.6, "{}", music[after]);
+        assert!(music[burst.end..].windows(2).all(|w| w[1] >= w[0] - 1e-6));
+        // ...and fully recovered by the end.
+        assert!((music[frames - 1] - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn held_talkover_ducks_by_the_depth_and_releases_on_time() {
+        let (tx, rx) = parameter_channel(8);
+        let mut bus = SummingBus::new(rx).with_sample_rate(SAMPLE_RATE);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::MicLowCutHz(0.0)).unwrap();
+        tx.send(ParameterUpdate::TalkoverDepthDb(-9.0)).unwrap();
+        tx.send(ParameterUpdate::Talkover(true)).unwrap();
+        let mut music = |talkover: Option<bool>, mic: f32| {
+            if let Some(on) = talkover {
+                tx.send(ParameterUpdate::Talkover(on)).unwrap();
+            }
+            // A mono mic: one sample a frame, heard on both sides.
+            let frames = SAMPLE_RATE as usize;
+            let mut out = vec![0.0; frames * 2];
+            bus.mix_stereo_with_mic(
+                &vec![1.0; frames * 2],
+                &vec![0.0; frames * 2],
+                &vec![mic; frames],
+                &mut out,
+            );
+            assert!(out.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+            out.iter().step_by(2).map(|s| s - mic).collect::<Vec<_>>()
+        };
+
+        // Held with the mic silent, the music sits at the depth.
+        let depth = db_to_linear(-9.0);
+        let held = music(None, 0.0);
+        assert!((held[held.len() - 1] - depth).abs() < 1e-4);
+        let released = music(Some(false), 0.0);
+        let at_release = released[(DUCK_RELEASE_SECONDS * SAMPLE_RATE as f32) as usize];
+        let expected = 1.0 - (1.0 - depth) / std::f32::consts::E;
+        assert!((at_release - expected).abs() < 1e-3, "{at_release}");
+        assert!(released.windows(2).all(|w| w[1] >= w[0] - 1e-6));
+        music(None, 0.0);
+        let recovered = music(None, 0.0);
+        assert!((recovered[recovered.len() - 1] - 1.0).abs() < 1e-3);
+
+        // Released, a mic over the threshold still ducks on its own.
+        let speaking = music(None, 0.1);
+        assert!((speaking[speaking.len() - 1] - depth).abs() < 1e-4);
+    }
+
+    #[test]
+    fn quiet_mic_never_ducks_and_low_cut_removes_dc() {
+        let mut mic = MicChannel::new(SAMPLE_RATE);
+        mic.set_threshold_db(-20.0);
+        let mut last = [0.0; 2];
+        for frame in noise(SAMPLE_RATE as usize, 0.05).chunks_exact(2) {
+            let (_, duck) = mic.tick([frame[0], frame[1]]);
+            assert_eq!(duck, 1.0);
+        }
+        for _ in 0..SAMPLE_RATE {
+            last = mic.tick([0.05, 0.05]).0;
+        }
+        assert!(last[0].abs() < 1e-4);
+
+        // Zero depth disables talkover entirely.
+        mic.set_depth_db(0.0);
+        let (_, duck) = mic.tick([1.0, 1.0]);
+        assert_eq!(duck, 1.0);
+    }
+}
@@ -0,0 +1,283 @@
+pub mod clock;
+pub mod input;
+#[cfg(feature = "midi")]
+mod midir_backend;
+pub mod output;
+
+use std::collections::HashMap;
+
+use crate::deck::DeckCommand;
+use crate::{DeckId, ParameterUpdate};
+
+pub use clock::{ClockCommand, ClockScheduler, MidiClock};
+pub use input::{ControlSinks, ControllerInput, MidiBackend, MidiInputs, PortStats};
+#[cfg(feature = "midi")]
+pub use midir_backend::MidirBackend;
+pub use output::{
+    FeedbackBinding, FeedbackMapper, FeedbackSource, FeedbackState, FeedbackTarget, LedMode,
+    MidiFeedback,
+};
+
+/// Which kind of control a message comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControlKind {
+    /// Pads and buttons; a release arrives as value 0.
+    Note,
+    ControlChange,
+}
+
+/// A channel message from a controller, normalised to press/release and 7-bit values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiMessage {
+    pub kind: ControlKind,
+    /// Zero-based MIDI channel.
+    pub channel: u8,
+    /// Note or CC number.
+    pub control: u8,
+    pub value: u8,
+}
+
+impl MidiMessage {
+    /// Parse a raw note on/off or control change; anything else is ignored.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let [status, control, value] = *bytes else {
+            return None;
+        };
+        let channel = status & 0x0F;
+        let (kind, value) = match status & 0xF0 {
+            0x80 => (ControlKind::Note, 0),
+            0x90 => (ControlKind::Note, value),
+            0xB0 => (ControlKind::ControlChange, value),
+            _ => return None,
+        };
+        Some(Self {
+            kind,
+            channel,
+            control: control & 0x7F,
+            value: value & 0x7F,
+        })
+    }
+
+    fn is_press(&self) -> bool {
+        self.value > 0
+    }
+
+    /// Value as a fader position in [0, 1].
+    fn fader(&self) -> f32 {
+        self.value as f32 / 127.0
+    }
+
+    /// Value as a centre-detented knob in [-1, 1], 64 being the centre.
+    fn knob(&self) -> f32 {
+        let offset = self.value as f32 - 64.0;
+        if offset >= 0.0 {
+            offset / 63.0
+        } else {
+            offset / 64.0
+        }
+    }
+
+    /// Value as a relative encoder step: 1..63 forwards, 65..127 backwards.
+    fn relative(&self) -> i32 {
+        if self.value < 64 {
+            self.value as i32
+        } else {
+            self.value as i32 - 128
+        }
+    }
+}
+
+/// What a mapped control does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiAction {
+    Play(DeckId),
+    Pause(DeckId),
+    Stop(DeckId),
+    /// Plays backwards while held.
+    Reverse(DeckId),
+    BeatJump {
+        deck: DeckId,
+        beats: i32,
+    },
+    /// Touch-sensitive platter top.
+    JogTouch(DeckId),
+    /// Relative platter encoder, each step moving the record `frames_per_step` track frames.
+    Jog {
+        deck: DeckId,
+        frames_per_step: f64,
+    },
+    DeckGain(DeckId),
+    Filter(DeckId),
+    Crossfader,
+    /// Crossfader curve knob.
+    CrossfaderSharpness,
+    MasterGain,
+}
+
+/// Where a translated message goes.
+#[derive(Debug, Clone)]
+pub enum ControlOutput {
+    Parameter(ParameterUpdate),
+    Deck { deck: DeckId, command: DeckCommand },
+}
+
+impl MidiAction {
+    /// Turn a message on this action's control into output; releases of momentary buttons give none.
+    pub fn apply(self, message: &MidiMessage) -> Option<ControlOutput> {
+        let deck = |deck, command| Some(ControlOutput::Deck { deck, command });
+        let parameter = |update| Some(ControlOutput::Parameter(update));
+        match self {
+            MidiAction::Play(id) if message.is_press() => deck(id, DeckCommand::Play),
+            MidiAction::Pause(id) if message.is_press() => deck(id, DeckCommand::Pause),
+            MidiAction::Stop(id) if message.is_press() => deck(id, DeckCommand::Stop),
+            MidiAction::BeatJump { deck: id, beats } if message.is_press() => {
+                deck(id, DeckCommand::BeatJump { beats })
+            }
+            MidiAction::Play(_)
+            | MidiAction::Pause(_)
+            | MidiAction::Stop(_)
+            | MidiAction::BeatJump { .. } => None,
+            MidiAction::Reverse(id) => deck(id, DeckCommand::SetReverse(message.is_press())),
+            MidiAction::JogTouch(id) => deck(id, DeckCommand::JogTouch(message.is_press())),
+            MidiAction::Jog {
+                deck: id,
+                frames_per_step,
+            } => deck(
+                id,
+                DeckCommand::JogTick {
+                    delta_frames: message.relative() as f64 * frames_per_step,
+                },
+            ),
+            MidiAction::DeckGain(id) => parameter(ParameterUpdate::DeckGain {
+                deck: id,
+                gain: message.fader(),
+            }),
+            MidiAction::Filter(id) => parameter(ParameterUpdate::DeckFilter {
+                deck: id,
+                position: message.knob(),
+            }),
+            MidiAction::Crossfader => parameter(ParameterUpdate::Crossfader(message.fader())),
+            MidiAction::CrossfaderSharpness => {
+                parameter(ParameterUpdate::CrossfaderSharpness(message.fader()))
+            }
+            MidiAction::MasterGain => parameter(ParameterUpdate::MasterGain(message.fader())),
+        }
+    }
+}
+
+/// Controller layout: which action each note or CC on each channel triggers.
+#[derive(Debug, Clone, Default)]
+pub struct MidiMapping {
+    bindings: HashMap<(ControlKind, u8, u8), MidiAction>,
+}
+
+impl MidiMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind a control, replacing whatever it did before.
+    pub fn bind(&mut self, kind: ControlKind, channel: u8, control: u8, action: MidiAction) {
+        self.bindings.insert((kind, channel, control), action);
+    }
+
+    pub fn unbind(&mut self, kind: ControlKind, channel: u8, control: u8) -> Option<MidiAction> {
+        self.bindings.remove(&(kind, channel, control))
+    }
+
+    pub fn action_for(&self, message: &MidiMessage) -> Option<MidiAction> {
+        self.bindings
+            .get(&(message.kind, message.channel, message.control))
+            .copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bindings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_notes_and_control_changes() {
+        let press = MidiMessage::parse(&[0x91, 24, 100]).unwrap();
+        assert_eq!(press.kind, ControlKind::Note);
+        assert_eq!((press.channel, press.control, press.value), (1, 24, 100));
+        // Note-on at velocity 0 and note-off are both releases.
+        assert_eq!(MidiMessage::parse(&[0x91, 24, 0]).unwrap().value, 0);
+        assert_eq!(MidiMessage::parse(&[0x81, 24, 64]).unwrap().value, 0);
+        let cc = MidiMessage::parse(&[0xB0, 7, 127]).unwrap();
+        assert_eq!(cc.kind, ControlKind::ControlChange);
+        assert_eq!(MidiMessage::parse(&[0xF8]), None);
+        assert_eq!(MidiMessage::parse(&[0xE0, 0, 64]), None);
+    }
+
+    #[test]
+    fn translates_buttons_faders_and_encoders() {
+        let mut mapping = MidiMapping::new();
+        mapping.bind(ControlKind::Note, 0, 11, MidiAction::Play(DeckId::A));
+        mapping.bind(ControlKind::Note, 0, 12, MidiAction::JogTouch(DeckId::B));
+        mapping.bind(ControlKind::ControlChange, 0, 8, MidiAction::Crossfader);
+        mapping.bind(
+            ControlKind::ControlChange,
+            0,
+            9,
+            MidiAction::Filter(DeckId::A),
+        );
+        mapping.bind(
+            ControlKind::ControlChange,
+            0,
+            10,
+            MidiAction::Jog {
+                deck: DeckId::B,
+                frames_per_step: 100.0,
+            },
+        );
+        let translate = |bytes: &[u8]| {
+            let message = MidiMessage::parse(bytes).unwrap();
+            mapping.action_for(&message)?.apply(&message)
+        };
+
+        assert!(matches!(
+            translate(&[0x90, 11, 127]),
+            Some(ControlOutput::Deck {
+                deck: DeckId::A,
+                command: DeckCommand::Play
+            })
+        ));
+        assert!(translate(&[0x80, 11, 0]).is_none());
+        assert!(matches!(
+            translate(&[0x80, 12, 0]),
+            Some(ControlOutput::Deck {
+                deck: DeckId::B,
+                command: DeckCommand::JogTouch(false)
+            })
+        ));
+        assert!(matches!(
+            translate(&[0xB0, 8, 127]),
+            Some(ControlOutput::Parameter(ParameterUpdate::Crossfader(x))) if x == 1.0
+        ));
+        for (value, expected) in [(0, -1.0), (64, 0.0), (127, 1.0)] {
+            assert!(matches!(
+                translate(&[0xB0, 9, value]),
+                Some(ControlOutput::Parameter(ParameterUpdate::DeckFilter { position, .. }))
+                    if position == expected
+            ));
+        }
+        assert!(matches!(
+            translate(&[0xB0, 10, 126]),
+            Some(ControlOutput::Deck {
+                deck: DeckId::B,
+                command: DeckCommand::JogTick { delta_frames },
+            }) if delta_frames == -200.0
+        ));
+        // Other channels are not bound.
+        assert!(translate(&[0x91, 11, 127]).is_none());
+    }
+}
@@ -0,0 +1,450 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossbeam_queue::ArrayQueue;
+
+use super::input::{MidiError, POLL_INTERVAL};
+use super::output::{MidiOutputBackend, MidiSink};
+use crate::crash::record_breadcrumb;
+use crate::settings::Settings;
+use crate::tempo::TempoSource;
+
+/// Clock pulses per quarter note.
+pub const PPQN: u32 = 24;
+const TIMING_CLOCK: u8 = 0xF8;
+const START: u8 = 0xFA;
+const CONTINUE: u8 = 0xFB;
+const STOP: u8 = 0xFC;
+/// Sleeping ends this far before a tick; the rest is spun away for sub-millisecond accuracy.
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+/// Longest the clock thread waits before looking at its commands again.
+const IDLE_WAIT: Duration = Duration::from_millis(10);
+const COMMAND_CAPACITY: usize = 16;
+
+/// Control of a running clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockCommand {
+    /// Send Start and count from the first beat.
+    Start,
+    Stop,
+    /// Carry on from where Stop left the receiver.
+    Continue,
+    /// Master tempo to follow; ticks pause while it is unknown.
+    Tempo(Option<f64>),
+}
+
+/// Decides when clock ticks and transport messages are due.
+#[derive(Debug, Clone)]
+pub struct ClockScheduler {
+    bpm: Option<f64>,
+    running: bool,
+    send_transport: bool,
+    /// Transport messages waiting to go out ahead of the next tick.
+    transport: Vec<u8>,
+    last_tick: Option<Instant>,
+    next_tick: Option<Instant>,
+    /// Ticks sent since the last Start.
+    ticks: u64,
+}
+
+impl ClockScheduler {
+    /// A stopped clock; with `send_transport` off only ticks are sent.
+    pub fn new(send_transport: bool) -> Self {
+        Self {
+            bpm: None,
+            running: false,
+            send_transport,
+            transport: Vec::new(),
+            last_tick: None,
+            next_tick: None,
+            ticks: 0,
+        }
+    }
+
+    pub fn bpm(&self) -> Option<f64> {
+        self.bpm
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    fn interval(&self) -> Option<Duration> {
+        self.bpm
+            .map(|bpm| Duration::from_secs_f64(60.0 / (bpm * PPQN as f64)))
+    }
+
+    pub fn apply(&mut self, command: ClockCommand, now: Instant) {
+        match command {
+            ClockCommand::Start => {
+                self.queue_transport(START);
+                self.running = true;
+                self.ticks = 0;
+                self.last_tick = None;
+                self.next_tick = Some(now);
+            }
+            ClockCommand::Stop if self.running => {
+                self.queue_transport(STOP);
+                self.running = false;
+                self.last_tick = None;
+                self.next_tick = None;
+            }
+            ClockCommand::Continue if !self.running => {
+                self.queue_transport(CONTINUE);
+                self.running = true;
+                self.next_tick = Some(now);
+            }
+            ClockCommand::Stop | ClockCommand::Continue => {}
+            ClockCommand::Tempo(bpm) => self.set_bpm(bpm),
+        }
+    }
+
+    /// Change the tempo, spacing the next tick from the last one so the pulse bends without a jump.
+    pub fn set_bpm(&mut self, bpm: Option<f64>) {
+        self.bpm = bpm.filter(|bpm| bpm.is_finite() && *bpm > 0.0);
+        if let (Some(last), Some(interval)) = (self.last_tick, self.interval()) {
+            self.next_tick = Some(last + interval);
+        }
+    }
+
+    fn queue_transport(&mut self, message: u8) {
+        if self.send_transport {
+            self.transport.push(message);
+        }
+    }
+
+    /// When the next tick is due, if the clock is running at a known tempo.
+    ///
+    /// Transport messages do not wait for it; they go out with the next poll.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.interval().and(self.next_tick).filter(|_| self.running)
+    }
+
+    /// Messages due by `now`: pending transport first, then every tick that has come due.
+    pub fn poll(&mut self, now: Instant) -> Vec<u8> {
+        let mut due = std::mem::take(&mut self.transport);
+        let Some(interval) = self.interval().filter(|_| self.running) else {
+            return due;
+        };
+        let next = self.next_tick.get_or_insert(now);
+        while *next <= now {
+            due.push(TIMING_CLOCK);
+            self.last_tick = Some(*next);
+            *next += interval;
+            self.ticks += 1;
+        }
+        due
+    }
+}
+
+/// Time source for the clock thread.
+pub trait ClockTimer {
+    fn now(&self) -> Instant;
+    fn sleep_until(&self, deadline: Instant);
+}
+
+/// Sleeps most of the way to a deadline and spins the last stretch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreciseTimer;
+
+impl ClockTimer for PreciseTimer {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        let now = Instant::now();
+        if deadline > now + SPIN_MARGIN {
+            thread::sleep(deadline - now - SPIN_MARGIN);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// MIDI clock sent to `settings.midi_clock_output` from a dedicated thread.
+pub struct MidiClock {
+    commands: Arc<ArrayQueue<ClockCommand>>,
+    stop: Arc<AtomicBool>,
+    sender: JoinHandle<()>,
+}
+
+impl MidiClock {
+    /// Run the clock through midir; `None` when no clock output is configured.
+    #[cfg(feature = "midi")]
+    pub fn start(settings: &Settings) -> Result<Option<Self>, MidiError> {
+        Self::start_with(super::MidirBackend, PreciseTimer, settings)
+    }
+
+    /// Run the clock on `backend`, reconnecting the port whenever it reappears.
+    ///
+    /// The clock keeps counting while the port is away, so the receiver picks up in phase.
+    pub fn start_with<B, T>(
+        backend: B,
+        timer: T,
+        settings: &Settings,
+    ) -> Result<Option<Self>, MidiError>
+    where
+        B: MidiOutputBackend + Send + 'static,
+        T: ClockTimer + Send + 'static,
+    {
+        let Some(pattern) = settings.midi_clock_output.clone() else {
+            return Ok(None);
+        };
+        let mut scheduler = ClockScheduler::new(settings.midi_clock_transport);
+        let commands = Arc::new(ArrayQueue::new(COMMAND_CAPACITY));
+        let queued = commands.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = stop.clone();
+        let sender = thread::Builder::new()
+            .name("midi-clock".into())
+            .spawn(move || {
+                let mut port: Option<Box<dyn MidiSink>> = None;
+                let mut last_check: Option<Instant> = None;
+                while !stopping.load(Ordering::Acquire) {
+                    let now = timer.now();
+                    while let Some(command) = queued.pop() {
+                        scheduler.apply(command, now);
+                    }
+                    if port.is_none() && last_check.is_none_or(|last| now - last >= POLL_INTERVAL) {
+                        last_check = Some(now);
+                        port = connect(&backend, &pattern);
+                    }
+
+                    let due = scheduler.poll(now);
+                    if let Some(sink) = &mut port {
+                        if let Err(err) = due.iter().try_for_each(|byte| sink.send(&[*byte])) {
+                            record_breadcrumb(err.to_string());
+                            port = None;
+                        }
+                    }
+                    let idle = now + IDLE_WAIT;
+                    let wake = scheduler
+                        .next_deadline()
+                        .map_or(idle, |tick| tick.min(idle));
+                    timer.sleep_until(wake);
+                }
+            })?;
+        Ok(Some(Self {
+            commands,
+            stop,
+            sender,
+        }))
+    }
+
+    /// Queue a command for the clock thread. Returns `Err` if the queue is full.
+    pub fn send(&self, command: ClockCommand) -> Result<(), ClockCommand> {
+        self.commands.push(command)
+    }
+
+    /// Take the master tempo from `tempo`.
+    pub fn follow(&self, tempo: &TempoSource) -> Result<(), ClockCommand> {
+        self.send(ClockCommand::Tempo(tempo.bpm()))
+    }
+
+    /// Stop the thread and close the port, without sending Stop.
+    pub fn close(self) {
+        self.stop.store(true, Ordering::Release);
+        self.sender.thread().unpark();
+        let _ = self.sender.join();
+    }
+}
+
+fn connect<B: MidiOutputBackend>(backend: &B, pattern: &str) -> Option<Box<dyn MidiSink>> {
+    let ports = backend
+        .output_ports()
+        .map_err(|err| record_breadcrumb(err.to_string()))
+        .ok()?;
+    let name = ports.iter().find(|name| name.contains(pattern))?;
+    backend
+        .connect_output(name)
+        .map_err(|err| record_breadcrumb(err.to_string()))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Run `scheduler` from deadline to deadline until `end`, as the clock thread would.
+    fn run(scheduler: &mut ClockScheduler, end: Instant) -> Vec<(Instant, u8)> {
+        let mut sent = Vec::new();
+        while let Some(deadline) = scheduler.next_deadline().filter(|at| *at <= end) {
+            sent.extend(
+                scheduler
+                    .poll(deadline)
+                    .into_iter()
+                    .map(|byte| (deadline, byte)),
+            );
+        }
+        sent
+    }
+
+    fn tick_spacing(sent: &[(Instant, u8)]) -> Vec<f64> {
+        let ticks: Vec<Instant> = sent
+            .iter()
+            .filter(|(_, byte)| *byte == TIMING_CLOCK)
+            .map(|(at, _)| *at)
+            .collect();
+        ticks
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).as_secs_f64() * 1_000.0)
+            .collect()
+    }
+
+    #[test]
+    fn ticks_24_per_beat_at_each_tempo() {
+        for bpm in [120.0, 174.0] {
+            let mut scheduler = ClockScheduler::new(true);
+            let start = Instant::now();
+            scheduler.apply(ClockCommand::Tempo(Some(bpm)), start);
+            scheduler.apply(ClockCommand::Start, start);
+
+            let sent = run(&mut scheduler, start + Duration::from_secs(2));
+            assert_eq!(sent[0], (start, START));
+            assert_eq!(sent[1], (start, TIMING_CLOCK));
+            let expected = 60_000.0 / (bpm * 24.0);
+            for spacing in tick_spacing(&sent) {
+                assert!((spacing - expected).abs() < 1e-3, "{bpm}: {spacing}");
+            }
+            // Two seconds hold this many beats, plus the tick on the downbeat.
+            assert_eq!(scheduler.ticks(), (bpm / 30.0 * 24.0) as u64 + 1);
+        }
+    }
+
+    #[test]
+    fn counts_ticks_between_start_and_stop() {
+        let mut scheduler = ClockScheduler::new(true);
+        let start = Instant::now();
+        scheduler.apply(ClockCommand::Tempo(Some(120.0)), start);
+        scheduler.apply(ClockCommand::Start, start);
+        // Four beats at 120 BPM, stopped just before the fifth downbeat.
+        let mut sent = run(&mut scheduler, start + Duration::from_millis(1_990));
+        let stop = start + Duration::from_millis(1_995);
+        scheduler.apply(ClockCommand::Stop, stop);
+        sent.extend(scheduler.poll(stop).into_iter().map(|byte| (stop, byte)));
+        assert!(run(&mut scheduler, start + Duration::from_secs(4)).is_empty());
+
+        let bytes: Vec<u8> = sent.iter().map(|(_, byte)| *byte).collect();
+        assert_eq!(bytes.first(), Some(&START));
+        assert_eq!(bytes.last(), Some(&STOP));
+        let ticks = bytes.iter().filter(|byte| **byte == TIMING_CLOCK).count();
+        assert_eq!(ticks, 4 * PPQN as usize);
+
+        // Continue resumes the count where Stop left it.
+        let resume = start + Duration::from_secs(5);
+        scheduler.apply(ClockCommand::Continue, resume);
+        assert_eq!(scheduler.poll(resume), vec![CONTINUE, TIMING_CLOCK]);
+        assert_eq!(scheduler.ticks(), 4 * PPQN as u64 + 1);
+    }
+
+    #[test]
+    fn tempo_jump_bends_from_the_last_tick() {
+        let mut scheduler = ClockScheduler::new(false);
+        let start = Instant::now();
+        scheduler.apply(ClockCommand::Tempo(Some(120.0)), start);
+        scheduler.apply(ClockCommand::Start, start);
+        let before = run(&mut scheduler, start + Duration::from_millis(500));
+        let last = before.last().unwrap().0;
+        assert_eq!(before.len(), 25);
+
+        // Sync moves to a 174 BPM deck between ticks.
+        scheduler.apply(
+            ClockCommand::Tempo(Some(174.0)),
+            start + Duration::from_millis(505),
+        );
+        let after = run(&mut scheduler, start + Duration::from_millis(1_000));
+        let spacing = 60_000.0 / (174.0 * 24.0);
+        let first = (after[0].0 - last).as_secs_f64() * 1_000.0;
+        assert!((first - spacing).abs() < 1e-3, "{first}");
+        for gap in tick_spacing(&after) {
+            assert!((gap - spacing).abs() < 1e-3, "{gap}");
+        }
+
+        // Without a tempo the clock holds its place rather than guessing.
+        scheduler.apply(ClockCommand::Tempo(None), start + Duration::from_secs(1));
+        assert_eq!(scheduler.next_deadline(), None);
+    }
+
+    struct FakeTimer {
+        now: Mutex<Instant>,
+    }
+
+    impl ClockTimer for Arc<FakeTimer> {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep_until(&self, deadline: Instant) {
+            let mut now = self.now.lock().unwrap();
+            *now = (*now).max(deadline);
+            thread::yield_now();
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Capture {
+        sent: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl MidiSink for Capture {
+        fn send(&mut self, message: &[u8]) -> Result<(), MidiError> {
+            self.sent.lock().unwrap().extend_from_slice(message);
+            Ok(())
+        }
+    }
+
+    impl MidiOutputBackend for Capture {
+        fn output_ports(&self) -> Result<Vec<String>, MidiError> {
+            Ok(vec!["TR-8S".into()])
+        }
+
+        fn connect_output(&self, _name: &str) -> Result<Box<dyn MidiSink>, MidiError> {
+            Ok(Box::new(self.clone()))
+        }
+    }
+
+    #[test]
+    fn clock_thread_follows_the_tempo_source() {
+        let capture = Capture::default();
+        let timer = Arc::new(FakeTimer {
+            now: Mutex::new(Instant::now()),
+        });
+        let settings = Settings {
+            midi_clock_output: Some("TR-8".into()),
+            ..Settings::default()
+        };
+        let clock = MidiClock::start_with(capture.clone(), timer, &settings)
+            .unwrap()
+            .unwrap();
+        let mut tempo = TempoSource::new();
+        tempo.set_bpm(Some(128.0));
+        clock.follow(&tempo).unwrap();
+        clock.send(ClockCommand::Start).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while capture.sent.lock().unwrap().len() < 100 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        clock.send(ClockCommand::Stop).unwrap();
+        while capture.sent.lock().unwrap().last() != Some(&STOP) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        clock.close();
+
+        let sent = capture.sent.lock().unwrap();
+        assert_eq!(sent[0], START);
+        assert_eq!(sent.last(), Some(&STOP));
+        assert!(sent[1..sent.len() - 1]
+            .iter()
+            .all(|byte| *byte == TIMING_CLOCK));
+    }
+}
@@ -0,0 +1,449 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use super::{ControlOutput, MidiMapping, MidiMessage};
+use crate::crash::record_breadcrumb;
+use crate::settings::Settings;
+use crate::{DeckCommandSender, ParameterSender};
+
+/// How often missing ports are looked for and present ones checked for removal.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Error)]
+pub enum MidiError {
+    #[error("MIDI is unavailable: {0}")]
+    Unavailable(String),
+    #[error("failed to connect to MIDI port {port}: {message}")]
+    Connect { port: String, message: String },
+    #[error("failed to send MIDI: {0}")]
+    Send(String),
+    #[error("failed to start the MIDI thread: {0}")]
+    Thread(#[from] io::Error),
+}
+
+/// Channels translated controller input is pushed into.
+#[derive(Clone)]
+pub struct ControlSinks {
+    pub parameters: ParameterSender,
+    pub decks: DeckCommandSender,
+}
+
+/// Counters shared between a port's [`ControllerInput`] and [`MidiInputs`].
+#[derive(Debug, Default)]
+struct PortCounters {
+    messages: AtomicU64,
+    unmapped: AtomicU64,
+    dropped: AtomicU64,
+    connections: AtomicU64,
+    connected: AtomicBool,
+    /// Message rate over the last poll interval, as `f64` bits.
+    rate: AtomicU64,
+}
+
+/// Activity of one controller port.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PortStats {
+    pub connected: bool,
+    /// Times the port has been (re)connected.
+    pub connections: u64,
+    pub messages: u64,
+    /// Messages with no binding in the mapping, including ones that are not notes or CCs.
+    pub unmapped: u64,
+    /// Translated messages lost because the engine's queue was full.
+    pub dropped: u64,
+    pub messages_per_second: f64,
+}
+
+impl PortCounters {
+    fn stats(&self) -> PortStats {
+        PortStats {
+            connected: self.connected.load(Ordering::Relaxed),
+            connections: self.connections.load(Ordering::Relaxed),
+            messages: self.messages.load(Ordering::Relaxed),
+            unmapped: self.unmapped.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            messages_per_second: f64::from_bits(self.rate.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Port-callback side of a controller: raw bytes through the mapping into the engine's channels.
+pub struct ControllerInput {
+    mapping: Arc<MidiMapping>,
+    sinks: ControlSinks,
+    counters: Arc<PortCounters>,
+}
+
+impl ControllerInput {
+    pub fn new(mapping: Arc<MidiMapping>, sinks: ControlSinks) -> Self {
+        Self {
+            mapping,
+            sinks,
+            counters: Arc::default(),
+        }
+    }
+
+    /// Handle one raw message as received from the port.
+    pub fn handle(&mut self, bytes: &[u8]) {
+        let counters = &self.counters;
+        counters.messages.fetch_add(1, Ordering::Relaxed);
+        let Some((message, action)) = MidiMessage::parse(bytes)
+            .and_then(|message| Some((message, self.mapping.action_for(&message)?)))
+        else {
+            counters.unmapped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        let sent = match action.apply(&message) {
+            None => true,
+            Some(ControlOutput::Parameter(update)) => self.sinks.parameters.send(update).is_ok(),
+            Some(ControlOutput::Deck { deck, command }) => {
+                self.sinks.decks.send(deck, command).is_ok()
+            }
+        };
+        if !sent {
+            counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> PortStats {
+        self.counters.stats()
+    }
+}
+
+/// An open port; dropping it closes the port.
+pub trait MidiConnection {}
+
+/// MIDI device access used by [`MidiInputs`].
+pub trait MidiBackend {
+    /// Names of the input ports present right now.
+    fn input_ports(&self) -> Result<Vec<String>, MidiError>;
+
+    /// Open the port called `name` and feed everything it receives to `input`.
+    fn connect(
+        &self,
+        name: &str,
+        input: ControllerInput,
+    ) -> Result<Box<dyn MidiConnection>, MidiError>;
+}
+
+/// One configured controller, reconnected whenever a matching port appears.
+struct PortWatch {
+    pattern: String,
+    counters: Arc<PortCounters>,
+    connection: Option<(String, Box<dyn MidiConnection>)>,
+    last_messages: u64,
+    last_poll: Option<Instant>,
+}
+
+impl PortWatch {
+    fn new(pattern: String, counters: Arc<PortCounters>) -> Self {
+        Self {
+            pattern,
+            counters,
+            connection: None,
+            last_messages: 0,
+            last_poll: None,
+        }
+    }
+
+    /// Drop a vanished port, open a newly present one and update the message rate.
+    fn poll<B: MidiBackend>(
+        &mut self,
+        backend: &B,
+        ports: &[String],
+        mapping: &Arc<MidiMapping>,
+        sinks: &ControlSinks,
+        now: Instant,
+    ) {
+        if let Some((name, _)) = &self.connection {
+            if !ports.contains(name) {
+                record_breadcrumb(format!("MIDI port disconnected: {name}"));
+                self.connection = None;
+            }
+        }
+        if self.connection.is_none() {
+            if let Some(name) = ports.iter().find(|name| name.contains(&self.pattern)) {
+                let input = ControllerInput {
+                    mapping: mapping.clone(),
+                    sinks: sinks.clone(),
+                    counters: self.counters.clone(),
+                };
+                match backend.connect(name, input) {
+                    Ok(connection) => {
+                        self.connection = Some((name.clone(), connection));
+                        self.counters.connections.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(err) => record_breadcrumb(err.to_string()),
+                }
+            }
+        }
+        self.counters
+            .connected
+            .store(self.connection.is_some(), Ordering::Relaxed);
+
+        let messages = self.counters.messages.load(Ordering::Relaxed);
+        if let Some(last) = self.last_poll {
+            let seconds = now.duration_since(last).as_secs_f64();
+            if seconds > 0.0 {
+                let rate = (messages - self.last_messages) as f64 / seconds;
+                self.counters.rate.store(rate.to_bits(), Ordering::Relaxed);
+            }
+        }
+        self.last_messages = messages;
+        self.last_poll = Some(now);
+    }
+}
+
+/// Controllers named in the settings, kept connected from a background thread.
+pub struct MidiInputs {
+    ports: Vec<(String, Arc<PortCounters>)>,
+    stop: Arc<AtomicBool>,
+    poller: JoinHandle<()>,
+}
+
+impl MidiInputs {
+    /// Open every port in `settings.midi_inputs` with midir.
+    #[cfg(feature = "midi")]
+    pub fn start(
+        settings: &Settings,
+        mapping: MidiMapping,
+        sinks: ControlSinks,
+    ) -> Result<Self, MidiError> {
+        Self::start_with(super::MidirBackend, settings, mapping, sinks)
+    }
+
+    /// Watch for the configured ports on `backend`, connecting each one as it shows up.
+    pub fn start_with<B: MidiBackend + Send + 'static>(
+        backend: B,
+        settings: &Settings,
+        mapping: MidiMapping,
+        sinks: ControlSinks,
+    ) -> Result<Self, MidiError> {
+        let ports: Vec<(String, Arc<PortCounters>)> = settings
+            .midi_inputs
+            .iter()
+            .map(|pattern| (pattern.clone(), Arc::default()))
+            .collect();
+        let watched = ports.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = stop.clone();
+        let mapping = Arc::new(mapping);
+        let poller = thread::Builder::new()
+            .name("midi-input".into())
+            .spawn(move || {
+                // Connections need not be `Send`, so the watches live on this thread only.
+                let mut watches: Vec<PortWatch> = watched
+                    .into_iter()
+                    .map(|(pattern, counters)| PortWatch::new(pattern, counters))
+                    .collect();
+                while !stopping.load(Ordering::Acquire) {
+                    match backend.input_ports() {
+                        Ok(present) => {
+                            for watch in &mut watches {
+                                watch.poll(&backend, &present, &mapping, &sinks, Instant::now());
+                            }
+                        }
+                        Err(err) => record_breadcrumb(err.to_string()),
+                    }
+                    thread::park_timeout(POLL_INTERVAL);
+                }
+            })?;
+        Ok(Self {
+            ports,
+            stop,
+            poller,
+        })
+    }
+
+    /// Statistics for each configured port, by the name it is matched with.
+    pub fn stats(&self) -> Vec<(String, PortStats)> {
+        self.ports
+            .iter()
+            .map(|(pattern, counters)| (pattern.clone(), counters.stats()))
+            .collect()
+    }
+
+    /// Close every port and stop watching for them.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Release);
+        self.poller.thread().unpark();
+        let _ = self.poller.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::DeckCommand;
+    use crate::midi::{ControlKind, MidiAction};
+    use crate::{deck_command_channel, parameter_channel, DeckId, ParameterUpdate};
+    use std::sync::Mutex;
+
+    fn mapping() -> MidiMapping {
+        let mut mapping = MidiMapping::new();
+        mapping.bind(ControlKind::Note, 0, 1, MidiAction::Play(DeckId::B));
+        mapping.bind(ControlKind::ControlChange, 0, 2, MidiAction::Crossfader);
+        mapping
+    }
+
+    #[test]
+    fn routes_messages_into_the_engine_channels() {
+        let (parameters, parameter_rx) = parameter_channel(1);
+        let (decks, deck_rx) = deck_command_channel(4);
+        let mut input =
+            ControllerInput::new(Arc::new(mapping()), ControlSinks { parameters, decks });
+
+        input.handle(&[0x90, 1, 127]);
+        input.handle(&[0x80, 1, 0]);
+        input.handle(&[0xB0, 2, 0]);
+        input.handle(&[0xB0, 2, 127]);
+        input.handle(&[0xB0, 3, 127]);
+        input.handle(&[0xF8]);
+
+        assert!(matches!(
+            deck_rx.pop(),
+            Some((DeckId::B, DeckCommand::Play))
+        ));
+        assert!(deck_rx.pop().is_none());
+        assert!(matches!(
+            parameter_rx.pop(),
+            Some(ParameterUpdate::Crossfader(x)) if x == 0.0
+        ));
+        let stats = input.stats();
+        assert_eq!(stats.messages, 6);
+        assert_eq!(stats.unmapped, 2);
+        // The second crossfader move did not fit in the one-slot queue.
+        assert_eq!(stats.dropped, 1);
+    }
+
+    #[derive(Default)]
+    struct FakeMidi {
+        ports: Mutex<Vec<String>>,
+        inputs: Mutex<Vec<(String, ControllerInput)>>,
+    }
+
+    struct FakeConnection;
+
+    impl MidiConnection for FakeConnection {}
+
+    impl MidiBackend for Arc<FakeMidi> {
+        fn input_ports(&self) -> Result<Vec<String>, MidiError> {
+            Ok(self.ports.lock().unwrap().clone())
+        }
+
+        fn connect(
+            &self,
+            name: &str,
+            input: ControllerInput,
+        ) -> Result<Box<dyn MidiConnection>, MidiError> {
+            self.inputs.lock().unwrap().push((name.to_string(), input));
+            Ok(Box::new(FakeConnection))
+        }
+    }
+
+    #[test]
+    fn reconnects_ports_as_they_come_and_go() {
+        let backend = Arc::new(FakeMidi::default());
+        let (parameters, _parameter_rx) = parameter_channel(8);
+        let (decks, _deck_rx) = deck_command_channel(8);
+        let sinks = ControlSinks { parameters, decks };
+        let mapping = Arc::new(mapping());
+        let mut watch = PortWatch::new("DDJ".into(), Arc::default());
+        let start = Instant::now();
+
+        watch.poll(&backend, &[], &mapping, &sinks, start);
+        assert!(!watch.counters.stats().connected);
+
+        let ports = vec![
+            "Launchpad".to_string(),
+            "Pioneer DDJ-400 MIDI 1".to_string(),
+        ];
+        watch.poll(&backend, &ports, &mapping, &sinks, start + POLL_INTERVAL);
+        let stats = watch.counters.stats();
+        assert!(stats.connected);
+        assert_eq!(stats.connections, 1);
+        {
+            let mut inputs = backend.inputs.lock().unwrap();
+            assert_eq!(inputs[0].0, "Pioneer DDJ-400 MIDI 1");
+            for _ in 0..10 {
+                inputs[0].1.handle(&[0xB0, 2, 64]);
+            }
+        }
+        watch.poll(
+            &backend,
+            &ports,
+            &mapping,
+            &sinks,
+            start + 2 * POLL_INTERVAL,
+        );
+        assert_eq!(watch.counters.stats().messages_per_second, 20.0);
+        assert_eq!(backend.inputs.lock().unwrap().len(), 1);
+
+        // Unplugged, then back under the same name.
+        watch.poll(
+            &backend,
+            &ports[..1],
+            &mapping,
+            &sinks,
+            start + 3 * POLL_INTERVAL,
+        );
+        assert!(!watch.counters.stats().connected);
+        watch.poll(
+            &backend,
+            &ports,
+            &mapping,
+            &sinks,
+            start + 4 * POLL_INTERVAL,
+        );
+        let stats = watch.counters.stats();
+        assert!(stats.connected);
+        assert_eq!(stats.connections, 2);
+        assert_eq!(stats.messages, 10);
+    }
+
+    #[test]
+    fn opens_each_configured_controller() {
+        let backend = Arc::new(FakeMidi::default());
+        *backend.ports.lock().unwrap() = vec!["DDJ-400".into(), "Launchpad Mini".into()];
+        let settings = Settings {
+            midi_inputs: vec!["DDJ".into(), "Launchpad".into(), "Missing".into()],
+            ..Settings::default()
+        };
+        let (parameters, _parameter_rx) = parameter_channel(8);
+        let (decks, deck_rx) = deck_command_channel(8);
+        let inputs = MidiInputs::start_with(
+            backend.clone(),
+            &settings,
+            mapping(),
+            ControlSinks { parameters, decks },
+        )
+        .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let connected = |inputs: &MidiInputs| {
+            inputs
+                .stats()
+                .iter()
+                .filter(|(_, stats)| stats.connected)
+                .count()
+        };
+        while connected(&inputs) < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        for (_, input) in backend.inputs.lock().unwrap().iter_mut() {
+            input.handle(&[0x90, 1, 100]);
+        }
+        assert!(deck_rx.pop().is_some() && deck_rx.pop().is_some());
+
+        let stats = inputs.stats();
+        assert_eq!(stats.len(), 3);
+        assert!(stats[0].1.connected && stats[1].1.connected && !stats[2].1.connected);
+        assert_eq!(stats[1].1.messages, 1);
+        inputs.stop();
+    }
+}
@@ -0,0 +1,99 @@
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+
+use super::input::{ControllerInput, MidiBackend, MidiConnection, MidiError};
+use super::output::{MidiOutputBackend, MidiSink};
+
+const CLIENT_NAME: &str = "deejay";
+
+/// [`MidiBackend`] backed by the platform's MIDI API through midir.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MidirBackend;
+
+impl MidiConnection for MidiInputConnection<()> {}
+
+impl MidiSink for MidiOutputConnection {
+    fn send(&mut self, message: &[u8]) -> Result<(), MidiError> {
+        MidiOutputConnection::send(self, message).map_err(|err| MidiError::Send(err.to_string()))
+    }
+}
+
+fn client() -> Result<MidiInput, MidiError> {
+    MidiInput::new(CLIENT_NAME).map_err(|err| MidiError::Unavailable(err.to_string()))
+}
+
+fn output_client() -> Result<MidiOutput, MidiError> {
+    MidiOutput::new(CLIENT_NAME).map_err(|err| MidiError::Unavailable(err.to_string()))
+}
+
+impl MidiBackend for MidirBackend {
+    fn input_ports(&self) -> Result<Vec<String>, MidiError> {
+        let client = client()?;
+        Ok(client
+            .ports()
+            .iter()
+            .filter_map(|port| client.port_name(port).ok())
+            .collect())
+    }
+
+    fn connect(
+        &self,
+        name: &str,
+        mut input: ControllerInput,
+    ) -> Result<Box<dyn MidiConnection>, MidiError> {
+        let client = client()?;
+        let failed = |message: String| MidiError::Connect {
+            port: name.to_string(),
+            message,
+        };
+        let port = client
+            .ports()
+            .into_iter()
+            .find(|port| {
+                client
+                    .port_name(port)
+                    .is_ok_and(|port_name| port_name == name)
+            })
+            .ok_or_else(|| failed("port is no longer present".into()))?;
+        let connection = client
+            .connect(
+                &port,
+                CLIENT_NAME,
+                move |_, bytes, _| input.handle(bytes),
+                (),
+            )
+            .map_err(|err| failed(err.to_string()))?;
+        Ok(Box::new(connection))
+    }
+}
+
+impl MidiOutputBackend for MidirBackend {
+    fn output_ports(&self) -> Result<Vec<String>, MidiError> {
+        let client = output_client()?;
+        Ok(client
+            .ports()
+            .iter()
+            .filter_map(|port| client.port_name(port).ok())
+            .collect())
+    }
+
+    fn connect_output(&self, name: &str) -> Result<Box<dyn MidiSink>, MidiError> {
+        let client = output_client()?;
+        let failed = |message: String| MidiError::Connect {
+            port: name.to_string(),
+            message,
+        };
+        let port = client
+            .ports()
+            .into_iter()
+            .find(|port| {
+                client
+                    .port_name(port)
+                    .is_ok_and(|port_name| port_name == name)
+            })
+            .ok_or_else(|| failed("port is no longer present".into()))?;
+        let connection = client
+            .connect(&port, CLIENT_NAME)
+            .map_err(|err| failed(err.to_string()))?;
+        Ok(Box::new(connection))
+    }
+}
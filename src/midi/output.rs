@@ -0,0 +1,595 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::input::{MidiError, POLL_INTERVAL};
+use crate::crash::record_breadcrumb;
+use crate::deck::{DeckEvent, DeckPosition};
+use crate::settings::Settings;
+use crate::DeckId;
+
+/// Default ceiling on how often changed values are sent.
+pub const DEFAULT_MAX_RATE_HZ: f64 = 30.0;
+/// Default smallest change, as a fraction of full scale, worth sending.
+pub const DEFAULT_THRESHOLD: f32 = 0.01;
+/// Loudness shown as an empty meter; 0 LUFS is full.
+const METER_FLOOR_LUFS: f32 = -60.0;
+
+/// Mixer state the controller reflects, sampled by the feedback thread.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeedbackState {
+    pub decks: [DeckPosition; 2],
+    /// Post-fader gain of each deck, as read from the bus.
+    pub fader_gains: [f32; 2],
+    /// Momentary loudness of the master in LUFS.
+    pub loudness: f32,
+    /// The last load on each deck failed; cleared by the next successful load.
+    pub load_failed: [bool; 2],
+}
+
+impl Default for FeedbackState {
+    fn default() -> Self {
+        Self {
+            decks: [DeckPosition::default(); 2],
+            fader_gains: [0.0; 2],
+            loudness: f32::NEG_INFINITY,
+            load_failed: [false; 2],
+        }
+    }
+}
+
+impl FeedbackState {
+    /// Latch what a deck event says about the deck.
+    pub fn observe(&mut self, deck: DeckId, event: &DeckEvent) {
+        match event {
+            DeckEvent::LoadFailed(_) => self.load_failed[deck.index()] = true,
+            DeckEvent::TrackLoaded { .. } => self.load_failed[deck.index()] = false,
+            _ => {}
+        }
+    }
+
+    /// `source` as a fraction of full scale.
+    pub fn value(&self, source: FeedbackSource) -> f32 {
+        let flag = |on: bool| if on { 1.0 } else { 0.0 };
+        match source {
+            FeedbackSource::Playing(deck) => flag(self.decks[deck.index()].playing),
+            FeedbackSource::Position(deck) => self.decks[deck.index()].fraction as f32,
+            FeedbackSource::EndWarning(deck) => flag(self.decks[deck.index()].end_warning),
+            FeedbackSource::LoadFailed(deck) => flag(self.load_failed[deck.index()]),
+            FeedbackSource::FaderGain(deck) => self.fader_gains[deck.index()],
+            FeedbackSource::Loudness => {
+                1.0 - self.loudness.max(METER_FLOOR_LUFS) / METER_FLOOR_LUFS
+            }
+        }
+        .clamp(0.0, 1.0)
+    }
+}
+
+/// A piece of state an LED can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackSource {
+    Playing(DeckId),
+    /// Playhead through the track.
+    Position(DeckId),
+    EndWarning(DeckId),
+    LoadFailed(DeckId),
+    FaderGain(DeckId),
+    /// Master loudness meter.
+    Loudness,
+}
+
+/// Where a value is sent on the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackTarget {
+    Note { channel: u8, note: u8 },
+    ControlChange { channel: u8, control: u8 },
+}
+
+impl FeedbackTarget {
+    fn message(self, value: u8) -> [u8; 3] {
+        match self {
+            FeedbackTarget::Note { channel, note } => [0x90 | (channel & 0x0F), note & 0x7F, value],
+            FeedbackTarget::ControlChange { channel, control } => {
+                [0xB0 | (channel & 0x0F), control & 0x7F, value]
+            }
+        }
+    }
+}
+
+/// How a value is drawn by the LEDs behind a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedMode {
+    /// Fully on from half scale, otherwise off.
+    Switch,
+    /// Straight through, e.g. for a VU strip that does its own drawing.
+    Linear,
+    /// Ring of `segments` LEDs lit from the start up to the value.
+    RingFill { segments: u8 },
+    /// Ring with just the segment nearest the value lit.
+    RingDot { segments: u8 },
+}
+
+impl LedMode {
+    fn level(self, value: f32) -> f32 {
+        match self {
+            LedMode::Switch => {
+                if value >= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            LedMode::Linear => value,
+            LedMode::RingFill { segments } => {
+                let segments = segments.max(1) as f32;
+                (value * segments).ceil() / segments
+            }
+            LedMode::RingDot { segments } => {
+                let steps = segments.saturating_sub(1).max(1) as f32;
+                (value * steps).round() / steps
+            }
+        }
+    }
+}
+
+/// One row of the feedback table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeedbackBinding {
+    pub source: FeedbackSource,
+    pub target: FeedbackTarget,
+    pub mode: LedMode,
+    /// Values sent for empty and full; reversed ranges are allowed.
+    pub min: u8,
+    pub max: u8,
+}
+
+impl FeedbackBinding {
+    pub fn new(source: FeedbackSource, target: FeedbackTarget, mode: LedMode) -> Self {
+        Self {
+            source,
+            target,
+            mode,
+            min: 0,
+            max: 127,
+        }
+    }
+
+    /// Level in [0, 1] and the value sent for it.
+    fn render(&self, state: &FeedbackState) -> (f32, u8) {
+        let level = self.mode.level(state.value(self.source));
+        let (min, max) = (self.min.min(127) as f32, self.max.min(127) as f32);
+        (level, (min + (max - min) * level).round() as u8)
+    }
+}
+
+/// Turns state snapshots into rate-limited controller messages through a feedback table.
+#[derive(Debug, Clone)]
+pub struct FeedbackMapper {
+    bindings: Vec<FeedbackBinding>,
+    /// Level and value last sent per binding.
+    sent: Vec<Option<(f32, u8)>>,
+    min_interval: Duration,
+    threshold: f32,
+    last_update: Option<Instant>,
+}
+
+impl FeedbackMapper {
+    pub fn new(bindings: Vec<FeedbackBinding>) -> Self {
+        Self {
+            sent: vec![None; bindings.len()],
+            bindings,
+            min_interval: Duration::from_secs_f64(1.0 / DEFAULT_MAX_RATE_HZ),
+            threshold: DEFAULT_THRESHOLD,
+            last_update: None,
+        }
+    }
+
+    pub fn bindings(&self) -> &[FeedbackBinding] {
+        &self.bindings
+    }
+
+    pub fn set_max_rate(&mut self, hz: f64) {
+        self.min_interval = Duration::from_secs_f64(1.0 / hz.max(f64::MIN_POSITIVE));
+    }
+
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold.max(0.0);
+    }
+
+    /// Every binding's current value, for a controller that has just been connected.
+    pub fn refresh(&mut self, state: &FeedbackState, now: Instant) -> Vec<[u8; 3]> {
+        self.last_update = Some(now);
+        self.bindings
+            .iter()
+            .zip(&mut self.sent)
+            .map(|(binding, sent)| {
+                let (level, value) = binding.render(state);
+                *sent = Some((level, value));
+                binding.target.message(value)
+            })
+            .collect()
+    }
+
+    /// Bindings that moved by at least the threshold since they were last sent.
+    ///
+    /// Nothing is sent more often than the maximum rate; changes in between are
+    /// picked up by the next update that is allowed through.
+    pub fn update(&mut self, state: &FeedbackState, now: Instant) -> Vec<[u8; 3]> {
+        if self
+            .last_update
+            .is_some_and(|last| now.duration_since(last) < self.min_interval)
+        {
+            return Vec::new();
+        }
+        self.last_update = Some(now);
+        let threshold = self.threshold;
+        self.bindings
+            .iter()
+            .zip(&mut self.sent)
+            .filter_map(|(binding, sent)| {
+                let (level, value) = binding.render(state);
+                let changed = match *sent {
+                    None => true,
+                    Some((last_level, last_value)) => {
+                        // Always let the ends through so a meter can reach empty and full.
+                        let end = level == 0.0 || level == 1.0;
+                        value != last_value && ((level - last_level).abs() >= threshold || end)
+                    }
+                };
+                changed.then(|| {
+                    *sent = Some((level, value));
+                    binding.target.message(value)
+                })
+            })
+            .collect()
+    }
+}
+
+/// An open output port.
+pub trait MidiSink {
+    fn send(&mut self, message: &[u8]) -> Result<(), MidiError>;
+}
+
+/// MIDI output access used by [`MidiFeedback`].
+pub trait MidiOutputBackend {
+    /// Names of the output ports present right now.
+    fn output_ports(&self) -> Result<Vec<String>, MidiError>;
+
+    fn connect_output(&self, name: &str) -> Result<Box<dyn MidiSink>, MidiError>;
+}
+
+/// The controller's output port, reconnected and refreshed whenever it reappears.
+struct FeedbackPort {
+    pattern: String,
+    mapper: FeedbackMapper,
+    connection: Option<(String, Box<dyn MidiSink>)>,
+}
+
+impl FeedbackPort {
+    /// Connect if a matching port is present, then send the refresh or the changes.
+    ///
+    /// `ports` is the current port list when it has been checked this time round.
+    fn poll<B: MidiOutputBackend>(
+        &mut self,
+        backend: &B,
+        ports: Option<&[String]>,
+        state: &FeedbackState,
+        now: Instant,
+    ) {
+        if let (Some(ports), Some((name, _))) = (ports, &self.connection) {
+            if !ports.contains(name) {
+                record_breadcrumb(format!("MIDI output disconnected: {name}"));
+                self.connection = None;
+            }
+        }
+        let messages = match &self.connection {
+            Some(_) => self.mapper.update(state, now),
+            None => {
+                let Some(name) = ports
+                    .unwrap_or_default()
+                    .iter()
+                    .find(|name| name.contains(&self.pattern))
+                else {
+                    return;
+                };
+                match backend.connect_output(name) {
+                    Ok(sink) => self.connection = Some((name.clone(), sink)),
+                    Err(err) => {
+                        record_breadcrumb(err.to_string());
+                        return;
+                    }
+                }
+                self.mapper.refresh(state, now)
+            }
+        };
+        let Some((_, sink)) = &mut self.connection else {
+            return;
+        };
+        for message in messages {
+            if let Err(err) = sink.send(&message) {
+                record_breadcrumb(err.to_string());
+                self.connection = None;
+                return;
+            }
+        }
+    }
+}
+
+/// Sends controller feedback from a background thread.
+pub struct MidiFeedback {
+    stop: Arc<AtomicBool>,
+    sender: JoinHandle<()>,
+}
+
+impl MidiFeedback {
+    /// Drive `settings.midi_output` through midir; `None` when no output is configured.
+    #[cfg(feature = "midi")]
+    pub fn start<F>(
+        settings: &Settings,
+        mapper: FeedbackMapper,
+        state: F,
+    ) -> Result<Option<Self>, MidiError>
+    where
+        F: FnMut() -> FeedbackState + Send + 'static,
+    {
+        Self::start_with(super::MidirBackend, settings, mapper, state)
+    }
+
+    /// Sample `state` at the mapper's rate and send what changed to the configured output.
+    pub fn start_with<B, F>(
+        backend: B,
+        settings: &Settings,
+        mapper: FeedbackMapper,
+        mut state: F,
+    ) -> Result<Option<Self>, MidiError>
+    where
+        B: MidiOutputBackend + Send + 'static,
+        F: FnMut() -> FeedbackState + Send + 'static,
+    {
+        let Some(pattern) = settings.midi_output.clone() else {
+            return Ok(None);
+        };
+        let tick = mapper.min_interval;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = stop.clone();
+        let sender = thread::Builder::new()
+            .name("midi-feedback".into())
+            .spawn(move || {
+                let mut port = FeedbackPort {
+                    pattern,
+                    mapper,
+                    connection: None,
+                };
+                let mut last_check: Option<Instant> = None;
+                while !stopping.load(Ordering::Acquire) {
+                    let now = Instant::now();
+                    let ports = if last_check.is_none_or(|last| now - last >= POLL_INTERVAL) {
+                        last_check = Some(now);
+                        backend
+                            .output_ports()
+                            .map_err(|err| record_breadcrumb(err.to_string()))
+                            .ok()
+                    } else {
+                        None
+                    };
+                    port.poll(&backend, ports.as_deref(), &state(), now);
+                    thread::park_timeout(tick);
+                }
+            })?;
+        Ok(Some(Self { stop, sender }))
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Release);
+        self.sender.thread().unpark();
+        let _ = self.sender.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn table() -> Vec<FeedbackBinding> {
+        vec![
+            FeedbackBinding::new(
+                FeedbackSource::Playing(DeckId::A),
+                FeedbackTarget::Note {
+                    channel: 0,
+                    note: 0x0B,
+                },
+                LedMode::Switch,
+            ),
+            FeedbackBinding::new(
+                FeedbackSource::Position(DeckId::B),
+                FeedbackTarget::ControlChange {
+                    channel: 1,
+                    control: 0x20,
+                },
+                LedMode::RingFill { segments: 8 },
+            ),
+            FeedbackBinding {
+                min: 0,
+                max: 100,
+                ..FeedbackBinding::new(
+                    FeedbackSource::Loudness,
+                    FeedbackTarget::ControlChange {
+                        channel: 0,
+                        control: 0x40,
+                    },
+                    LedMode::Linear,
+                )
+            },
+        ]
+    }
+
+    fn at(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn refresh_sends_every_binding_then_only_changes() {
+        let mut mapper = FeedbackMapper::new(table());
+        let mut state = FeedbackState::default();
+        let start = Instant::now();
+
+        assert_eq!(
+            mapper.refresh(&state, start),
+            vec![[0x90, 0x0B, 0], [0xB1, 0x20, 0], [0xB0, 0x40, 0]]
+        );
+        assert!(mapper.update(&state, at(start, 100)).is_empty());
+
+        state.decks[0].playing = true;
+        // Just under a third of the way through lights three of the eight segments.
+        state.decks[1].fraction = 0.3;
+        state.loudness = -30.0;
+        assert_eq!(
+            mapper.update(&state, at(start, 200)),
+            vec![[0x90, 0x0B, 127], [0xB1, 0x20, 48], [0xB0, 0x40, 50]]
+        );
+
+        // Within the same ring segment nothing is resent.
+        state.decks[1].fraction = 0.33;
+        assert!(mapper.update(&state, at(start, 300)).is_empty());
+    }
+
+    #[test]
+    fn updates_are_rate_limited_and_thresholded() {
+        let mut mapper = FeedbackMapper::new(table());
+        mapper.set_threshold(0.05);
+        let mut state = FeedbackState::default();
+        let start = Instant::now();
+        mapper.refresh(&state, start);
+
+        state.decks[0].playing = true;
+        // 10 ms after the refresh is inside the 30 Hz interval.
+        assert!(mapper.update(&state, at(start, 10)).is_empty());
+        assert_eq!(
+            mapper.update(&state, at(start, 40)),
+            vec![[0x90, 0x0B, 127]]
+        );
+
+        // A 1 dB wobble on the meter is below the threshold; 6 dB is not.
+        state.loudness = -20.0;
+        assert_eq!(mapper.update(&state, at(start, 80)).len(), 1);
+        state.loudness = -21.0;
+        assert!(mapper.update(&state, at(start, 120)).is_empty());
+        state.loudness = -26.0;
+        assert_eq!(
+            mapper.update(&state, at(start, 160)),
+            vec![[0xB0, 0x40, 57]]
+        );
+        // Silence always gets through so the meter empties.
+        state.loudness = f32::NEG_INFINITY;
+        assert_eq!(mapper.update(&state, at(start, 200)), vec![[0xB0, 0x40, 0]]);
+    }
+
+    #[test]
+    fn load_failures_latch_until_the_next_load() {
+        let mut state = FeedbackState::default();
+        state.observe(DeckId::B, &DeckEvent::LoadFailed("corrupt".into()));
+        assert_eq!(state.value(FeedbackSource::LoadFailed(DeckId::B)), 1.0);
+        state.observe(DeckId::B, &DeckEvent::Started);
+        assert_eq!(state.value(FeedbackSource::LoadFailed(DeckId::B)), 1.0);
+        state.observe(
+            DeckId::B,
+            &DeckEvent::TrackLoaded {
+                duration_seconds: 1.0,
+                bpm: None,
+            },
+        );
+        assert_eq!(state.value(FeedbackSource::LoadFailed(DeckId::B)), 0.0);
+    }
+
+    #[derive(Clone, Default)]
+    struct FakeOutput {
+        sent: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl MidiSink for FakeOutput {
+        fn send(&mut self, message: &[u8]) -> Result<(), MidiError> {
+            self.sent.lock().unwrap().push(message.to_vec());
+            Ok(())
+        }
+    }
+
+    impl MidiOutputBackend for FakeOutput {
+        fn output_ports(&self) -> Result<Vec<String>, MidiError> {
+            Ok(vec!["DDJ-400 MIDI 1".into()])
+        }
+
+        fn connect_output(&self, _name: &str) -> Result<Box<dyn MidiSink>, MidiError> {
+            Ok(Box::new(self.clone()))
+        }
+    }
+
+    #[test]
+    fn reconnecting_pushes_a_full_refresh() {
+        let backend = FakeOutput::default();
+        let mut port = FeedbackPort {
+            pattern: "DDJ".into(),
+            mapper: FeedbackMapper::new(table()),
+            connection: None,
+        };
+        let ports = backend.output_ports().unwrap();
+        let mut state = FeedbackState::default();
+        let start = Instant::now();
+
+        port.poll(&backend, Some(&[]), &state, start);
+        assert!(backend.sent.lock().unwrap().is_empty());
+        port.poll(&backend, Some(&ports), &state, at(start, 50));
+        assert_eq!(backend.sent.lock().unwrap().len(), 3);
+
+        state.decks[0].playing = true;
+        port.poll(&backend, None, &state, at(start, 100));
+        assert_eq!(backend.sent.lock().unwrap()[3], vec![0x90, 0x0B, 127]);
+
+        // Unplugged and back: everything is sent again, not just what changed.
+        port.poll(&backend, Some(&[]), &state, at(start, 150));
+        port.poll(&backend, Some(&ports), &state, at(start, 200));
+        let sent = backend.sent.lock().unwrap();
+        assert_eq!(sent.len(), 7);
+        assert_eq!(
+            sent[4..],
+            [
+                vec![0x90, 0x0B, 127],
+                vec![0xB1, 0x20, 0],
+                vec![0xB0, 0x40, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn feedback_thread_sends_the_refresh_on_connect() {
+        let backend = FakeOutput::default();
+        let settings = Settings {
+            midi_output: Some("DDJ".into()),
+            ..Settings::default()
+        };
+        let feedback = MidiFeedback::start_with(
+            backend.clone(),
+            &settings,
+            FeedbackMapper::new(table()),
+            FeedbackState::default,
+        )
+        .unwrap()
+        .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while backend.sent.lock().unwrap().len() < 3 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        feedback.stop();
+        assert_eq!(backend.sent.lock().unwrap().len(), 3);
+
+        let unconfigured = MidiFeedback::start_with(
+            backend,
+            &Settings::default(),
+            FeedbackMapper::new(table()),
+            FeedbackState::default,
+        )
+        .unwrap();
+        assert!(unconfigured.is_none());
+    }
+}
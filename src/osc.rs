@@ -0,0 +1,218 @@
+pub mod feedback;
+
+use thiserror::Error;
+
+pub use feedback::{OscFeedback, OscPublisher, OscState};
+
+/// Marks a packet as a bundle rather than a single message.
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+/// Time tag meaning "as soon as received".
+const IMMEDIATELY: u64 = 1;
+
+#[derive(Debug, Error)]
+pub enum OscError {
+    #[error("OSC packet ends early")]
+    Truncated,
+    #[error("OSC string is not valid UTF-8")]
+    BadString,
+    #[error("unsupported OSC argument type '{0}'")]
+    UnsupportedType(char),
+    #[error("OSC packet is neither a message nor a bundle")]
+    NotAPacket,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// An argument of an OSC message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    Str(String),
+}
+
+impl OscArg {
+    fn tag(&self) -> u8 {
+        match self {
+            OscArg::Int(_) => b'i',
+            OscArg::Float(_) => b'f',
+            OscArg::Str(_) => b's',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.into(),
+            args,
+        }
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        write_string(out, &self.address);
+        let mut tags = String::from(",");
+        tags.extend(self.args.iter().map(|arg| arg.tag() as char));
+        write_string(out, &tags);
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(value) => out.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Float(value) => out.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Str(value) => write_string(out, value),
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, OscError> {
+        let mut reader = Reader { bytes, at: 0 };
+        let address = reader.string()?;
+        if !address.starts_with('/') {
+            return Err(OscError::NotAPacket);
+        }
+        // Messages without a type tag string are allowed and have no arguments.
+        if reader.at == bytes.len() {
+            return Ok(Self::new(address, Vec::new()));
+        }
+        let tags = reader.string()?;
+        let tags = tags.strip_prefix(',').ok_or(OscError::NotAPacket)?;
+        let args = tags
+            .chars()
+            .map(|tag| match tag {
+                'i' => Ok(OscArg::Int(i32::from_be_bytes(reader.word()?))),
+                'f' => Ok(OscArg::Float(f32::from_be_bytes(reader.word()?))),
+                's' => Ok(OscArg::Str(reader.string()?)),
+                other => Err(OscError::UnsupportedType(other)),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(address, args))
+    }
+}
+
+/// Encode `messages` as one bundle to be applied immediately.
+pub fn encode_bundle(messages: &[OscMessage]) -> Vec<u8> {
+    let mut out = BUNDLE_TAG.to_vec();
+    out.extend_from_slice(&IMMEDIATELY.to_be_bytes());
+    let mut message = Vec::new();
+    for element in messages {
+        message.clear();
+        element.encode(&mut message);
+        out.extend_from_slice(&(message.len() as i32).to_be_bytes());
+        out.extend_from_slice(&message);
+    }
+    out
+}
+
+/// Decode a message or bundle, flattening nested bundles into their messages.
+pub fn decode_packet(bytes: &[u8]) -> Result<Vec<OscMessage>, OscError> {
+    let Some(mut rest) = bytes.strip_prefix(BUNDLE_TAG) else {
+        return Ok(vec![OscMessage::decode(bytes)?]);
+    };
+    rest = rest.get(8..).ok_or(OscError::Truncated)?;
+    let mut messages = Vec::new();
+    while !rest.is_empty() {
+        let size: [u8; 4] = rest
+            .get(..4)
+            .and_then(|size| size.try_into().ok())
+            .ok_or(OscError::Truncated)?;
+        let size = usize::try_from(i32::from_be_bytes(size)).map_err(|_| OscError::NotAPacket)?;
+        let element = rest.get(4..4 + size).ok_or(OscError::Truncated)?;
+        messages.extend(decode_packet(element)?);
+        rest = &rest[4 + size..];
+    }
+    Ok(messages)
+}
+
+/// Write `value` null-terminated and padded to a multiple of four bytes.
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(value.as_bytes());
+    let padding = 4 - value.len() % 4;
+    out.extend(std::iter::repeat_n(0, padding));
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn word(&mut self) -> Result<[u8; 4], OscError> {
+        let word = self
+            .bytes
+            .get(self.at..self.at + 4)
+            .ok_or(OscError::Truncated)?;
+        self.at += 4;
+        Ok(word.try_into().unwrap())
+    }
+
+    fn string(&mut self) -> Result<String, OscError> {
+        let rest = self.bytes.get(self.at..).ok_or(OscError::Truncated)?;
+        let len = rest
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(OscError::Truncated)?;
+        let value = std::str::from_utf8(&rest[..len]).map_err(|_| OscError::BadString)?;
+        self.at += (len / 4 + 1) * 4;
+        if self.at > self.bytes.len() {
+            return Err(OscError::Truncated);
+        }
+        Ok(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_padded_strings_and_big_endian_arguments() {
+        let mut out = Vec::new();
+        OscMessage::new(
+            "/deejay/meter/master",
+            vec![
+                OscArg::Float(0.5),
+                OscArg::Int(-2),
+                OscArg::Str("play".into()),
+            ],
+        )
+        .encode(&mut out);
+        let mut expected = b"/deejay/meter/master\0\0\0\0,fis\0\0\0\0".to_vec();
+        expected.extend_from_slice(&0.5f32.to_be_bytes());
+        expected.extend_from_slice(&(-2i32).to_be_bytes());
+        expected.extend_from_slice(b"play\0\0\0\0");
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn round_trips_bundles_and_rejects_garbage() {
+        let messages = vec![
+            OscMessage::new("/deejay/deck/a/position", vec![OscArg::Float(0.25)]),
+            OscMessage::new("/deejay/deck/b/state", vec![OscArg::Str("paused".into())]),
+            OscMessage::new("/deejay/subscribe", Vec::new()),
+        ];
+        assert_eq!(decode_packet(&encode_bundle(&messages)).unwrap(), messages);
+
+        // A bare address with no type tags is a message without arguments.
+        assert_eq!(
+            decode_packet(b"/ping\0\0\0").unwrap(),
+            vec![OscMessage::new("/ping", Vec::new())]
+        );
+        assert!(matches!(
+            decode_packet(b"/x\0\0,f\0\0\0\0"),
+            Err(OscError::Truncated)
+        ));
+        assert!(matches!(
+            decode_packet(b"/x\0\0,d\0\0"),
+            Err(OscError::UnsupportedType('d'))
+        ));
+        assert!(matches!(
+            decode_packet(b"hello\0\0\0"),
+            Err(OscError::NotAPacket)
+        ));
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{decode_packet, encode_bundle, OscArg, OscError, OscMessage};
+use crate::crash::record_breadcrumb;
+use crate::deck::{DeckPosition, TransportState};
+use crate::fx::{DelayTime, FlangerRate};
+use crate::settings::Settings;
+use crate::{DeckId, ParameterUpdate};
+
+/// Clients not heard from for this long stop receiving feedback.
+pub const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Sent by a client to start feedback explicitly; any other message does too.
+pub const SUBSCRIBE: &str = "/deejay/subscribe";
+pub const UNSUBSCRIBE: &str = "/deejay/unsubscribe";
+/// Largest datagram read from a client.
+const MAX_PACKET_BYTES: usize = 8_192;
+
+fn deck_path(deck: DeckId) -> String {
+    deck.to_string().to_lowercase()
+}
+
+/// What OSC clients are shown, sampled by the feedback thread from the engine's snapshot handles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscState {
+    /// Absolute peak of the master mix per channel.
+    pub master_peak: [f32; 2],
+    pub decks: [DeckPosition; 2],
+    pub transport: [TransportState; 2],
+    /// Latest value of each parameter echoed back, by address.
+    parameters: BTreeMap<String, f32>,
+}
+
+impl Default for OscState {
+    fn default() -> Self {
+        Self {
+            master_peak: [0.0; 2],
+            decks: [DeckPosition::default(); 2],
+            transport: [TransportState::Empty, TransportState::Empty],
+            parameters: BTreeMap::new(),
+        }
+    }
+}
+
+impl OscState {
+    /// Remember a parameter update sent to the engine so clients see it echoed.
+    pub fn echo(&mut self, update: &ParameterUpdate) {
+        let deck = |deck: &DeckId, name: &str| format!("/deejay/deck/{}/{name}", deck_path(*deck));
+        let (address, value) = match update {
+            ParameterUpdate::DeckGain { deck: id, gain } => (deck(id, "gain"), *gain),
+            ParameterUpdate::DeckMute { deck: id, on } => (deck(id, "mute"), *on as u8 as f32),
+            ParameterUpdate::DeckSolo { deck: id, on } => (deck(id, "solo"), *on as u8 as f32),
+            ParameterUpdate::DeckCue { deck: id, on } => (deck(id, "cue"), *on as u8 as f32),
+            ParameterUpdate::CueMix(mix) => ("/deejay/cue/mix".into(), *mix),
+            ParameterUpdate::CueGain(gain) => ("/deejay/cue/gain".into(), *gain),
+            ParameterUpdate::DeckTrim { deck: id, gain } => (deck(id, "trim"), *gain),
+            ParameterUpdate::DeckFader { deck: id, position } => (deck(id, "fader"), *position),
+            ParameterUpdate::DeckFaderTaper { deck: id, taper } => {
+                (deck(id, "fader/taper"), *taper as u8 as f32)
+            }
+            ParameterUpdate::Crossfader(position) => ("/deejay/crossfader".into(), *position),
+            ParameterUpdate::CrossfaderRamp { target, .. }
+            | ParameterUpdate::CrossfadeTo { target, .. } => ("/deejay/crossfader".into(), *target),
+            ParameterUpdate::CrossfaderCurve(curve) => {
+                ("/deejay/crossfader/curve".into(), *curve as u8 as f32)
+            }
+            ParameterUpdate::CrossfaderSharpness(sharpness) => {
+                ("/deejay/crossfader/sharpness".into(), *sharpness)
+            }
+            ParameterUpdate::CrossfaderReverse(reverse) => {
+                ("/deejay/crossfader/reverse".into(), *reverse as u8 as f32)
+            }
+            ParameterUpdate::CrossfaderAssign { deck: id, assign } => {
+                (deck(id, "xfader/assign"), *assign as u8 as f32)
+            }
+            ParameterUpdate::MasterGain(gain) => ("/deejay/master/gain".into(), *gain),
+            ParameterUpdate::BoothGain(gain) => ("/deejay/booth/gain".into(), *gain),
+            ParameterUpdate::StereoWidth(width) => ("/deejay/master/width".into(), *width),
+            ParameterUpdate::MonoOutput(on) => ("/deejay/master/mono".into(), *on as u8 as f32),
+            ParameterUpdate::DeckEffect {
+                deck: id,
+                param,
+                value,
+            } => (deck(id, &format!("fx/{param}")), *value),
+            ParameterUpdate::FxDelayTime { deck: id, time } => match time {
+                DelayTime::Ms(ms) => (deck(id, "delay/time"), *ms),
+                DelayTime::Division(division) => (deck(id, "delay/division"), *division),
+            },
+            ParameterUpdate::FxDelayFeedback { deck: id, feedback } => {
+                (deck(id, "delay/feedback"), *feedback)
+            }
+            ParameterUpdate::FxDelayMix { deck: id, mix } => (deck(id, "delay/mix"), *mix),
+            ParameterUpdate::FxReverbRoomSize { deck: id, size } => {
+                (deck(id, "reverb/size"), *size)
+            }
+            ParameterUpdate::FxReverbDamping { deck: id, damping } => {
+                (deck(id, "reverb/damping"), *damping)
+            }
+            ParameterUpdate::FxReverbMix { deck: id, mix } => (deck(id, "reverb/mix"), *mix),
+            ParameterUpdate::FxFlangerRate { deck: id, rate } => match rate {
+                FlangerRate::Hz(hz) => (deck(id, "flanger/rate"), *hz),
+                FlangerRate::Division(division) => (deck(id, "flanger/division"), *division),
+            },
+            ParameterUpdate::FxFlangerDepth { deck: id, depth } => {
+                (deck(id, "flanger/depth"), *depth)
+            }
+            ParameterUpdate::FxFlangerFeedback { deck: id, feedback } => {
+                (deck(id, "flanger/feedback"), *feedback)
+            }
+            ParameterUpdate::FxFlangerStereoPhase { deck: id, phase } => {
+                (deck(id, "flanger/phase"), *phase)
+            }
+            ParameterUpdate::DeckEq {
+                deck: id,
+                band,
+                gain_db,
+            } => (deck(id, &format!("eq/{}", band.name())), *gain_db),
+            ParameterUpdate::DeckEqKill { deck: id, band, on } => (
+                deck(id, &format!("eq/{}/kill", band.name())),
+                *on as u8 as f32,
+            ),
+            ParameterUpdate::DeckFilter { deck: id, position } => (deck(id, "filter"), *position),
+            ParameterUpdate::DeckFilterResonance {
+                deck: id,
+                resonance,
+            } => (deck(id, "filter/resonance"), *resonance),
+            ParameterUpdate::DcBlock { deck: id, on } => (deck(id, "dcblock"), *on as u8 as f32),
+            // Left inverted adds 1, right 2.
+            ParameterUpdate::DeckPhaseInvert {
+                deck: id,
+                left,
+                right,
+            } => (deck(id, "phase"), (*left as u8 + 2 * *right as u8) as f32),
+            ParameterUpdate::Tempo(bpm) => ("/deejay/tempo".into(), bpm.unwrap_or(0.0)),
+            ParameterUpdate::LimiterCeilingDb(db) => ("/deejay/master/limiter/ceiling".into(), *db),
+            ParameterUpdate::LimiterReleaseMs(ms) => ("/deejay/master/limiter/release".into(), *ms),
+            ParameterUpdate::MasterSoftClip(on) => {
+                ("/deejay/master/softclip".into(), *on as u8 as f32)
+            }
+            ParameterUpdate::MasterSoftClipDrive(drive) => {
+                ("/deejay/master/softclip/drive".into(), *drive)
+            }
+            ParameterUpdate::MicGain(gain) => ("/deejay/mic/gain".into(), *gain),
+            ParameterUpdate::Talkover(on) => ("/deejay/mic/talkover".into(), *on as u8 as f32),
+            ParameterUpdate::MicLowCutHz(hz) => ("/deejay/mic/lowcut".into(), *hz),
+            ParameterUpdate::TalkoverThresholdDb(db) => {
+                ("/deejay/mic/talkover/threshold".into(), *db)
+            }
+            ParameterUpdate::TalkoverDepthDb(db) => ("/deejay/mic/talkover/depth".into(), *db),
+            ParameterUpdate::Record(on) => ("/deejay/record".into(), *on as u8 as f32),
+            // Actions rather than values, so there is nothing to echo.
+            ParameterUpdate::ResetClipIndicators
+            | ParameterUpdate::ResetLoudness
+            | ParameterUpdate::RequestSnapshot
+            | ParameterUpdate::BeginBatch
+            | ParameterUpdate::CommitBatch => return,
+        };
+        self.parameters.insert(address, value);
+    }
+
+    fn messages(&self) -> Vec<OscMessage> {
+        let mut messages = vec![OscMessage::new(
+            "/deejay/meter/master",
+            vec![
+                OscArg::Float(self.master_peak[0]),
+                OscArg::Float(self.master_peak[1]),
+            ],
+        )];
+        for deck in [DeckId::A, DeckId::B] {
+            let path = deck_path(deck);
+            messages.push(OscMessage::new(
+                format!("/deejay/deck/{path}/position"),
+                vec![OscArg::Float(self.decks[deck.index()].fraction as f32)],
+            ));
+            messages.push(OscMessage::new(
+                format!("/deejay/deck/{path}/state"),
+                vec![OscArg::Str(self.transport[deck.index()].name().into())],
+            ));
+        }
+        messages.extend(
+            self.parameters
+                .iter()
+                .map(|(address, value)| OscMessage::new(address, vec![OscArg::Float(*value)])),
+        );
+        messages
+    }
+}
+
+struct Client {
+    last_seen: Instant,
+    /// Arguments last sent per address; a new client gets everything.
+    sent: HashMap<String, Vec<OscArg>>,
+}
+
+/// Tracks subscribed clients and turns state snapshots into rate-limited bundles of what changed.
+pub struct OscPublisher {
+    min_interval: Duration,
+    timeout: Duration,
+    clients: HashMap<SocketAddr, Client>,
+    last_update: Option<Instant>,
+}
+
+impl OscPublisher {
+    pub fn new(max_rate_hz: f64) -> Self {
+        Self {
+            min_interval: Duration::from_secs_f64(1.0 / max_rate_hz.max(f64::MIN_POSITIVE)),
+            timeout: DEFAULT_CLIENT_TIMEOUT,
+            clients: HashMap::new(),
+            last_update: None,
+        }
+    }
+
+    /// How long a client may stay silent before it is dropped; tablets should ping within this.
+    pub fn set_client_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.clients.keys().copied()
+    }
+
+    /// Handle a packet from `from`: any message keeps its sender subscribed, unless it unsubscribes.
+    pub fn receive(&mut self, from: SocketAddr, messages: &[OscMessage], now: Instant) {
+        if messages
+            .iter()
+            .any(|message| message.address == UNSUBSCRIBE)
+        {
+            self.clients.remove(&from);
+            return;
+        }
+        self.clients
+            .entry(from)
+            .or_insert_with(|| Client {
+                last_seen: now,
+                sent: HashMap::new(),
+            })
+            .last_seen = now;
+    }
+
+    /// Stop sending to a client, e.g. because its address became unreachable.
+    pub fn drop_client(&mut self, client: SocketAddr) {
+        self.clients.remove(&client);
+    }
+
+    /// Whether the rate limit lets an update through at `now`.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.last_update
+            .is_none_or(|last| now.duration_since(last) >= self.min_interval)
+    }
+
+    /// Expire silent clients, then build a bundle per client of the values it has not seen yet.
+    ///
+    /// Nothing is sent more often than the maximum rate; changes in between go out with the
+    /// next update that is allowed through.
+    pub fn update(&mut self, state: &OscState, now: Instant) -> Vec<(SocketAddr, Vec<u8>)> {
+        let timeout = self.timeout;
+        self.clients
+            .retain(|_, client| now.saturating_duration_since(client.last_seen) < timeout);
+        if !self.is_due(now) {
+            return Vec::new();
+        }
+        self.last_update = Some(now);
+        let messages = state.messages();
+        self.clients
+            .iter_mut()
+            .filter_map(|(address, client)| {
+                let changed: Vec<_> = messages
+                    .iter()
+                    .filter(|message| client.sent.get(&message.address) != Some(&message.args))
+                    .cloned()
+                    .collect();
+                if changed.is_empty() {
+                    return None;
+                }
+                for message in &changed {
+                    client
+                        .sent
+                        .insert(message.address.clone(), message.args.clone());
+                }
+                Some((*address, encode_bundle(&changed)))
+            })
+            .collect()
+    }
+}
+
+/// Serves OSC feedback from a background thread.
+pub struct OscFeedback {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    worker: JoinHandle<()>,
+}
+
+impl OscFeedback {
+    /// Listen on `settings.osc_address` and send clients what `state` shows; `None` when OSC is
+    /// not configured.
+    pub fn start<F>(
+        settings: &Settings,
+        mut publisher: OscPublisher,
+        mut state: F,
+    ) -> Result<Option<Self>, OscError>
+    where
+        F: FnMut() -> OscState + Send + 'static,
+    {
+        let Some(address) = settings.osc_address.as_deref() else {
+            return Ok(None);
+        };
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(
+            (publisher.min_interval / 2).max(Duration::from_millis(1)),
+        ))?;
+        let address = socket.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = stop.clone();
+        let worker = thread::Builder::new()
+            .name("osc-feedback".into())
+            .spawn(move || {
+                let mut buffer = [0; MAX_PACKET_BYTES];
+                while !stopping.load(Ordering::Acquire) {
+                    match socket.recv_from(&mut buffer) {
+                        Ok((len, from)) => match decode_packet(&buffer[..len]) {
+                            Ok(messages) => publisher.receive(from, &messages, Instant::now()),
+                            Err(err) => record_breadcrumb(format!("OSC from {from}: {err}")),
+                        },
+                        Err(err)
+                            if matches!(
+                                err.kind(),
+                                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                            ) => {}
+                        Err(err) => record_breadcrumb(format!("OSC receive failed: {err}")),
+                    }
+                    let now = Instant::now();
+                    if !publisher.is_due(now) {
+                        continue;
+                    }
+                    for (client, packet) in publisher.update(&state(), now) {
+                        if let Err(err) = socket.send_to(&packet, client) {
+                            record_breadcrumb(format!("OSC feedback to {client} failed: {err}"));
+                            publisher.drop_client(client);
+                        }
+                    }
+                }
+            })?;
+        Ok(Some(Self {
+            address,
+            stop,
+            worker,
+        }))
+    }
+
+    /// Address actually bound.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Release);
+        let _ = self.worker.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn playing_state() -> OscState {
+        let mut state = OscState {
+            master_peak: [0.5, 0.25],
+            ..OscState::default()
+        };
+        state.decks[0].fraction = 0.5;
+        state.transport[0] = TransportState::Playing;
+        state.transport[1] = TransportState::Loaded;
+        state
+    }
+
+    fn addresses(packet: &[u8]) -> Vec<String> {
+        decode_packet(packet)
+            .unwrap()
+            .into_iter()
+            .map(|message| message.address)
+            .collect()
+    }
+
+    fn at(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn new_clients_get_everything_then_only_changes() {
+        let mut publisher = OscPublisher::new(20.0);
+        let client: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let start = Instant::now();
+        let mut state = playing_state();
+        state.echo(&ParameterUpdate::Crossfader(0.5));
+
+        // Nobody has subscribed yet.
+        assert!(publisher.update(&state, start).is_empty());
+        publisher.receive(
+            client,
+            &[OscMessage::new(SUBSCRIBE, Vec::new())],
+            at(start, 10),
+        );
+        let packets = publisher.update(&state, at(start, 60));
+        assert_eq!(packets.len(), 1);
+        let messages = decode_packet(&packets[0].1).unwrap();
+        assert_eq!(
+            messages[0],
+            OscMessage::new(
+                "/deejay/meter/master",
+                vec![OscArg::Float(0.5), OscArg::Float(0.25)]
+            )
+        );
+        assert!(messages.contains(&OscMessage::new(
+            "/deejay/deck/a/position",
+            vec![OscArg::Float(0.5)]
+        )));
+        assert!(messages.contains(&OscMessage::new(
+            "/deejay/deck/a/state",
+            vec![OscArg::Str("playing".into())]
+        )));
+        assert!(messages.contains(&OscMessage::new(
+            "/deejay/deck/b/state",
+            vec![OscArg::Str("loaded".into())]
+        )));
+        assert!(messages.contains(&OscMessage::new(
+            "/deejay/crossfader",
+            vec![OscArg::Float(0.5)]
+        )));
+
+        // Unchanged state sends nothing; a parameter change is echoed on its own.
+        assert!(publisher.update(&state, at(start, 120)).is_empty());
+        state.echo(&ParameterUpdate::DeckFilter {
+            deck: DeckId::B,
+            position: -0.5,
+        });
+        state.echo(&ParameterUpdate::Crossfader(0.5));
+        let packets = publisher.update(&state, at(start, 180));
+        assert_eq!(addresses(&packets[0].1), ["/deejay/deck/b/filter"]);
+    }
+
+    #[test]
+    fn updates_are_rate_limited_and_silent_clients_expire() {
+        let mut publisher = OscPublisher::new(10.0);
+        publisher.set_client_timeout(Duration::from_secs(1));
+        let tablet: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let phone: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let start = Instant::now();
+        let mut state = playing_state();
+
+        // Any message registers its sender.
+        publisher.receive(
+            tablet,
+            &[OscMessage::new("/deejay/ping", Vec::new())],
+            start,
+        );
+        publisher.receive(phone, &[OscMessage::new(SUBSCRIBE, Vec::new())], start);
+        assert_eq!(publisher.update(&state, start).len(), 2);
+
+        // 50 ms later is inside the 10 Hz interval even though the meter moved.
+        state.master_peak = [0.9, 0.9];
+        assert!(publisher.update(&state, at(start, 50)).is_empty());
+        let packets = publisher.update(&state, at(start, 100));
+        assert_eq!(packets.len(), 2);
+        assert_eq!(addresses(&packets[0].1), ["/deejay/meter/master"]);
+
+        // The tablet keeps pinging, the phone goes quiet and is dropped after the timeout.
+        publisher.receive(
+            tablet,
+            &[OscMessage::new("/deejay/ping", Vec::new())],
+            at(start, 900),
+        );
+        state.master_peak = [0.1, 0.1];
+        let packets = publisher.update(&state, at(start, 1_000));
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].0, tablet);
+        assert_eq!(publisher.clients().collect::<Vec<_>>(), [tablet]);
+
+        publisher.receive(
+            tablet,
+            &[OscMessage::new(UNSUBSCRIBE, Vec::new())],
+            at(start, 1_050),
+        );
+        assert_eq!(publisher.clients().count(), 0);
+    }
+
+    #[test]
+    fn feeds_back_over_a_loopback_socket() {
+        let settings = Settings {
+            osc_address: Some("127.0.0.1:0".into()),
+            ..Settings::default()
+        };
+        let mut publisher = OscPublisher::new(20.0);
+        publisher.set_client_timeout(Duration::from_millis(400));
+        let shared = Arc::new(Mutex::new(playing_state()));
+        let source = shared.clone();
+        let feedback = OscFeedback::start(&settings, publisher, move || {
+            let mut state = source.lock().unwrap();
+            // A moving meter changes on every sample.
+            state.master_peak[0] = (state.master_peak[0] + 0.01) % 1.0;
+            state.clone()
+        })
+        .unwrap()
+        .unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let mut subscribe = Vec::new();
+        OscMessage::new(SUBSCRIBE, Vec::new()).encode(&mut subscribe);
+        client.send_to(&subscribe, feedback.local_addr()).unwrap();
+
+        let mut buffer = [0; MAX_PACKET_BYTES];
+        let (len, _) = client.recv_from(&mut buffer).unwrap();
+        let first = addresses(&buffer[..len]);
+        assert!(first.contains(&"/deejay/deck/a/state".to_string()));
+        assert!(first.contains(&"/deejay/deck/b/position".to_string()));
+
+        // Only the meter keeps changing, and no faster than 20 Hz.
+        shared
+            .lock()
+            .unwrap()
+            .echo(&ParameterUpdate::MasterGain(0.8));
+        let window = Instant::now();
+        let mut packets = 0;
+        let mut echoed = false;
+        while window.elapsed() < Duration::from_millis(300) {
+            let (len, _) = client.recv_from(&mut buffer).unwrap();
+            let messages = addresses(&buffer[..len]);
+            echoed |= messages == ["/deejay/meter/master", "/deejay/master/gain"];
+            assert!(messages.iter().all(
+                |address| address == "/deejay/meter/master" || address == "/deejay/master/gain"
+            ));
+            packets += 1;
+        }
+        assert!(echoed);
+        assert!((2..=8).contains(&packets), "{packets} packets");
+
+        // Without pings the client expires and feedback stops.
+        thread::sleep(Duration::from_millis(400));
+        while client.recv_from(&mut buffer).is_ok() {}
+        assert!(client.recv_from(&mut buffer).is_err());
+        feedback.stop();
+    }
+}
@@ -0,0 +1,164 @@
+use crate::ParameterUpdate;
+
+/// Custom DSP inserted into a deck's path with
+/// [`SummingBus::set_deck_insert`](crate::SummingBus::set_deck_insert), ahead
+/// of the deck's trim, EQ and fader.
+///
+/// `process` runs on the audio thread, so it must not block or allocate.
+pub trait Processor {
+    /// Get ready to run at `sample_rate` on blocks of up to `max_block`
+    /// frames. Called off the audio thread, before the first block and again
+    /// whenever the bus is prepared for a new rate.
+    fn prepare(&mut self, sample_rate: u32, max_block: usize);
+
+    /// Process a block of interleaved stereo frames in place.
+    fn process(&mut self, buffer: &mut [f32]);
+
+    /// Follow an update the bus applied for this processor's deck, such as
+    /// an [`FxDelayTime`](ParameterUpdate::FxDelayTime), or the
+    /// [`Tempo`](ParameterUpdate::Tempo). Runs on the audio thread between
+    /// blocks; the default ignores everything.
+    fn apply(&mut self, _update: &ParameterUpdate) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::schedule::schedule_channel;
+    use crate::{parameter_channel, DeckId, SummingBus};
+
+    /// How a [`Gain`] was prepared, and the largest block it was handed.
+    #[derive(Default)]
+    struct Probe {
+        sample_rate: AtomicU32,
+        max_block: AtomicUsize,
+        largest_block: AtomicUsize,
+    }
+
+    /// Scales everything by a fixed gain.
+    struct Gain {
+        gain: f32,
+        probe: Arc<Probe>,
+    }
+
+    impl Processor for Gain {
+        fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+            self.probe.sample_rate.store(sample_rate, Ordering::Relaxed);
+            self.probe.max_block.store(max_block, Ordering::Relaxed);
+        }
+
+        fn process(&mut self, buffer: &mut [f32]) {
+            self.probe
+                .largest_block
+                .fetch_max(buffer.len() / 2, Ordering::Relaxed);
+            for sample in buffer {
+                *sample *= self.gain;
+            }
+        }
+    }
+
+    /// Scales everything by the mix of the last
+    /// [`FxDelayMix`](ParameterUpdate::FxDelayMix) it was sent.
+    struct Level(f32);
+
+    impl Processor for Level {
+        fn prepare(&mut self, _sample_rate: u32, _max_block: usize) {}
+
+        fn process(&mut self, buffer: &mut [f32]) {
+            for sample in buffer {
+                *sample *= self.0;
+            }
+        }
+
+        fn apply(&mut self, update: &ParameterUpdate) {
+            if let ParameterUpdate::FxDelayMix { mix, .. } = update {
+                self.0 = *mix;
+            }
+        }
+    }
+
+    fn gain(gain: f32) -> (Box<Gain>, Arc<Probe>) {
+        let probe = Arc::new(Probe::default());
+        let gain = Box::new(Gain {
+            gain,
+            probe: probe.clone(),
+        });
+        (gain, probe)
+    }
+
+    #[test]
+    fn insert_affects_only_its_deck_until_removed() {
+        let (_, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        // Deck A alone on the left, deck B alone on the right.
+        let deck_a: Vec<f32> = [0.5, 0.0].repeat(256);
+        let deck_b: Vec<f32> = [0.0, 0.5].repeat(256);
+        let mix = |bus: &mut SummingBus| {
+            let mut out = [0.0; 512];
+            bus.mix_stereo(&deck_a, &deck_b, &mut out);
+            (out[0], out[1])
+        };
+        let (left, right) = mix(&mut bus);
+
+        assert!(bus.set_deck_insert(DeckId::A, gain(0.5).0).is_none());
+        let (inserted_left, inserted_right) = mix(&mut bus);
+        assert!((inserted_left - left * 0.5).abs() < 1e-6);
+        assert_eq!(inserted_right, right);
+
+        // Removing it brings back the deck as it was.
+        assert!(bus.take_deck_insert(DeckId::A).is_some());
+        assert!(bus.take_deck_insert(DeckId::A).is_none());
+        assert_eq!(mix(&mut bus), (left, right));
+
+        // No deck C on a two-deck bus, so the insert comes straight back.
+        assert!(bus.set_deck_insert(DeckId(2), gain(0.5).0).is_some());
+    }
+
+    #[test]
+    fn insert_is_prepared_and_never_handed_more_than_its_block() {
+        let (_, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx).with_sample_rate(44_100);
+        let (insert, probe) = gain(1.0);
+        bus.set_deck_insert(DeckId::B, insert);
+        assert_eq!(probe.sample_rate.load(Ordering::Relaxed), 44_100);
+
+        let mut out = vec![0.0; 20_000];
+        bus.mix_stereo(&vec![0.25; 20_000], &vec![0.25; 20_000], &mut out);
+        let max_block = probe.max_block.load(Ordering::Relaxed);
+        assert!(max_block < 10_000);
+        assert_eq!(probe.largest_block.load(Ordering::Relaxed), max_block);
+
+        bus.prepare(96_000);
+        assert_eq!(probe.sample_rate.load(Ordering::Relaxed), 96_000);
+    }
+
+    #[test]
+    fn scheduled_updates_reach_the_insert_on_their_frame() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (schedule, receiver) = schedule_channel(4);
+        bus.set_schedule(Some(receiver));
+        bus.set_deck_insert(DeckId::A, Box::new(Level(1.0)));
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        let mix = |mix| ParameterUpdate::FxDelayMix {
+            deck: DeckId::A,
+            mix,
+        };
+        schedule.send(300, mix(0.5)).unwrap();
+        schedule.send(1_500, mix(0.25)).unwrap();
+
+        let mut out = vec![0.0; 2_048];
+        bus.mix_stereo(&[1.0; 2_048], &[0.0; 2_048], &mut out);
+        let left: Vec<f32> = out.iter().step_by(2).copied().collect();
+        assert!(left[..300].iter().all(|&s| s == 1.0));
+        assert!(left[300..].iter().all(|&s| s == 0.5));
+        // Held for the next block, which splits where it lands.
+        bus.mix_stereo(&[1.0; 2_048], &[0.0; 2_048], &mut out);
+        let left: Vec<f32> = out.iter().step_by(2).copied().collect();
+        assert!(left[..476].iter().all(|&s| s == 0.5));
+        assert!(left[476..].iter().all(|&s| s == 0.25));
+    }
+}
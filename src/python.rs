@@ -0,0 +1,287 @@
+//! Python bindings for offline analysis and rendering.
+//!
+//! Only compiled with the `python` feature; build the extension with
+//! `maturin develop --features python,pyo3/extension-module` and `import deejay_native`.
+
+use std::path::{Path, PathBuf};
+
+use numpy::{IntoPyArray, PyArray1};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::analysis::bpm::{estimate_bpm, BpmOptions};
+use crate::analysis::gain::analyze_gain;
+use crate::analysis::key::detect_key;
+use crate::analysis::waveform::{generate_waveform, BucketSize};
+use crate::analysis::CancelToken;
+use crate::deck::Track;
+use crate::decode::{decode_track, DecodeError};
+use crate::record::{write_wav, RecordError, RecorderOptions, WavFormat};
+use crate::render::{parse_automation, render_mix as render, RenderError};
+
+create_exception!(deejay_native, DeejayError, PyException);
+
+fn decode_error(path: &Path, err: DecodeError) -> PyErr {
+    let message = format!("{}: {err}", path.display());
+    match err {
+        DecodeError::Io(_) => PyOSError::new_err(message),
+        DecodeError::Format(_) | DecodeError::NoAudio => DeejayError::new_err(message),
+    }
+}
+
+fn render_error(err: RenderError) -> PyErr {
+    match err {
+        RenderError::Json(_) | RenderError::Automation(..) => {
+            PyValueError::new_err(err.to_string())
+        }
+        RenderError::SampleRateMismatch(..)
+        | RenderError::MissingTracks(_)
+        | RenderError::Decode(_)
+        | RenderError::Session(_) => DeejayError::new_err(err.to_string()),
+        RenderError::Io(_) => PyOSError::new_err(err.to_string()),
+    }
+}
+
+fn record_error(path: &Path, err: RecordError) -> PyErr {
+    PyOSError::new_err(format!("{}: {err}", path.display()))
+}
+
+fn load(path: &Path) -> PyResult<Track> {
+    decode_track(path).map_err(|err| decode_error(path, err))
+}
+
+fn mono(track: &Track) -> impl Iterator<Item = f32> + '_ {
+    track
+        .samples()
+        .chunks_exact(2)
+        .map(|frame| (frame[0] + frame[1]) * 0.5)
+}
+
+/// Everything `analyze` reports, gathered without the GIL.
+struct Analysis {
+    bpm: Option<f64>,
+    key: Option<String>,
+    gain: Option<f32>,
+    duration: f64,
+}
+
+impl Analysis {
+    fn of(track: &Track) -> Self {
+        let rate = track.sample_rate();
+        Self {
+            bpm: estimate_bpm(mono(track), rate, &BpmOptions::default()).map(|bpm| bpm.bpm),
+            key: detect_key(mono(track), rate, &CancelToken::new())
+                .map(|estimate| estimate.key.to_string()),
+            gain: analyze_gain(track.samples().iter().copied(), 2, rate).map(|gain| gain.trim_db),
+            duration: track.frames() as f64 / rate as f64,
+        }
+    }
+}
+
+/// Decode `path` and report its tempo, key, loudness trim in dB and duration in seconds.
+///
+/// Values that cannot be measured, e.g. the key of silence, are `None`.
+#[pyfunction]
+fn analyze(py: Python<'_>, path: PathBuf) -> PyResult<Bound<'_, PyDict>> {
+    let analysis = py.allow_threads(|| load(&path).map(|track| Analysis::of(&track)))?;
+    let result = PyDict::new_bound(py);
+    result.set_item("bpm", analysis.bpm)?;
+    result.set_item("key", analysis.key)?;
+    result.set_item("gain", analysis.gain)?;
+    result.set_item("duration", analysis.duration)?;
+    Ok(result)
+}
+
+/// Peak level of roughly `width` equal slices of `path`, as a float32 numpy array.
+#[pyfunction]
+fn waveform(py: Python<'_>, path: PathBuf, width: u32) -> PyResult<Bound<'_, PyArray1<f32>>> {
+    let peaks = py.allow_threads(|| {
+        let track = load(&path)?;
+        let size = BucketSize::TargetWidth {
+            width,
+            total_frames: track.frames(),
+        };
+        let overview = generate_waveform(
+            track.samples().iter().copied(),
+            2,
+            track.sample_rate(),
+            size,
+            false,
+        );
+        let peaks: Vec<f32> = overview
+            .summaries
+            .chunks_exact(2)
+            .map(|bucket| {
+                bucket
+                    .iter()
+                    .map(|summary| summary.max.max(-summary.min))
+                    .fold(0.0, f32::max)
+            })
+            .collect();
+        PyResult::Ok(peaks)
+    })?;
+    // Hands the vector's buffer to numpy without copying.
+    Ok(peaks.into_pyarray_bound(py))
+}
+
+/// Mix `a_path` and `b_path` under a JSON automation list and write a float WAV to `out_path`.
+///
+/// Returns the number of frames written.
+#[pyfunction]
+fn render_mix(
+    py: Python<'_>,
+    a_path: PathBuf,
+    b_path: PathBuf,
+    automation_json: &str,
+    out_path: PathBuf,
+) -> PyResult<u64> {
+    let automation = parse_automation(automation_json).map_err(render_error)?;
+    py.allow_threads(|| {
+        let tracks = [load(&a_path)?, load(&b_path)?];
+        let options = RecorderOptions {
+            // Unclipped, so the mix can be trimmed afterwards.
+            format: WavFormat::Float32,
+            sample_rate: tracks[0].sample_rate(),
+            ..RecorderOptions::default()
+        };
+        let mix = render(tracks, &automation).map_err(render_error)?;
+        write_wav(&out_path, &mix, &options).map_err(|err| record_error(&out_path, err))
+    })
+}
+
+#[pymodule]
+fn deejay_native(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("DeejayError", module.py().get_type_bound::<DeejayError>())?;
+    module.add_function(wrap_pyfunction!(analyze, module)?)?;
+    module.add_function(wrap_pyfunction!(waveform, module)?)?;
+    module.add_function(wrap_pyfunction!(render_mix, module)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::DeckAction;
+    use crate::render::{AutomationAction, AutomationPoint};
+    use crate::DeckId;
+    use tempfile::tempdir;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    /// Run `test` against a freshly built module, as Python would import it.
+    fn with_module(test: impl FnOnce(&Bound<'_, PyModule>) -> PyResult<()>) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new_bound(py, "deejay_native")?;
+            deejay_native(&module)?;
+            test(&module)
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn analyze_matches_the_native_analysis() {
+        with_module(|module| {
+            for file in ["tagged.flac", "tagged.mp3"] {
+                let native = Analysis::of(&decode_track(&fixture(file)).unwrap());
+                let result = module.getattr("analyze")?.call1((fixture(file),))?;
+                let get = |key: &str| result.get_item(key);
+                assert_eq!(get("bpm")?.extract::<Option<f64>>()?, native.bpm, "{file}");
+                assert_eq!(
+                    get("key")?.extract::<Option<String>>()?,
+                    native.key,
+                    "{file}"
+                );
+                assert_eq!(
+                    get("gain")?.extract::<Option<f32>>()?,
+                    native.gain,
+                    "{file}"
+                );
+                assert_eq!(
+                    get("duration")?.extract::<f64>()?,
+                    native.duration,
+                    "{file}"
+                );
+            }
+            assert!((Analysis::of(&load(&fixture("tagged.flac"))?).duration - 2.0).abs() < 0.1);
+
+            let missing = module.getattr("analyze")?.call1((fixture("missing.mp3"),));
+            let err = missing.unwrap_err();
+            assert!(err.is_instance_of::<PyOSError>(module.py()));
+            assert!(err.to_string().contains("missing.mp3"), "{err}");
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn waveform_returns_one_peak_per_bucket() {
+        with_module(|module| {
+            let track = decode_track(&fixture("tagged.flac")).unwrap();
+            let overview = generate_waveform(
+                track.samples().iter().copied(),
+                2,
+                track.sample_rate(),
+                BucketSize::TargetWidth {
+                    width: 50,
+                    total_frames: track.frames(),
+                },
+                false,
+            );
+            let peaks: Vec<f32> = module
+                .getattr("waveform")?
+                .call1((fixture("tagged.flac"), 50))?
+                .extract()?;
+            assert_eq!(peaks.len(), overview.len());
+            for (index, peak) in peaks.iter().enumerate() {
+                let left = overview.summary(index, 0).unwrap();
+                assert!(*peak >= left.max && *peak >= -left.min);
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn render_mix_writes_the_native_render() {
+        let dir = tempdir().unwrap();
+        let options = RecorderOptions {
+            format: WavFormat::Float32,
+            sample_rate: 1_000,
+            ..RecorderOptions::default()
+        };
+        let [a, b, out] = ["a.wav", "b.wav", "mix.wav"].map(|name| dir.path().join(name));
+        write_wav(&a, &[0.5; 2_000], &options).unwrap();
+        write_wav(&b, &[0.25; 1_000], &options).unwrap();
+        let automation = [DeckId::A, DeckId::B].map(|deck| AutomationPoint {
+            seconds: 0.0,
+            action: AutomationAction::Deck {
+                deck,
+                action: DeckAction::Play,
+            },
+        });
+        let json = serde_json::to_string(&automation).unwrap();
+
+        with_module(|module| {
+            let frames: u64 = module
+                .getattr("render_mix")?
+                .call1((&a, &b, json.as_str(), &out))?
+                .extract()?;
+            assert_eq!(frames, 1_000);
+
+            let bad = module
+                .getattr("render_mix")?
+                .call1((&a, &b, "[{\"cmd\":\"play\"}]", &out));
+            assert!(bad.unwrap_err().is_instance_of::<PyValueError>(module.py()));
+            Ok(())
+        });
+
+        let tracks = [&a, &b].map(|path| decode_track(path).unwrap());
+        let native = render(tracks, &automation).unwrap();
+        assert_eq!(decode_track(&out).unwrap().samples(), &native[..]);
+    }
+}
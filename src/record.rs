@@ -0,0 +1,487 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::metrics::{Collect, MetricSet};
+use crate::ring::RingConsumer;
+pub use crate::ring::{tee, RecordTap};
+
+/// Size of the canonical RIFF/WAVE header written before the sample data.
+const HEADER_BYTES: u64 = 44;
+/// How long the writer sleeps when the ring is empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// Samples drained from the ring per write.
+const WRITE_CHUNK_SAMPLES: usize = 8_192;
+
+#[derive(Debug, Error)]
+pub enum RecordError {
+    #[error("failed to write recording: {0}")]
+    Io(#[from] io::Error),
+    #[error("recording writer thread panicked")]
+    WriterPanicked,
+}
+
+/// Sample encoding of the recorded file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl WavFormat {
+    fn bytes_per_sample(self) -> u16 {
+        match self {
+            WavFormat::Int16 => 2,
+            WavFormat::Int24 => 3,
+            WavFormat::Float32 => 4,
+        }
+    }
+
+    /// WAVE format tag: 1 for integer PCM, 3 for IEEE float.
+    fn tag(self) -> u16 {
+        match self {
+            WavFormat::Float32 => 3,
+            _ => 1,
+        }
+    }
+
+    fn encode(self, sample: f32, out: &mut Vec<u8>) {
+        match self {
+            WavFormat::Int16 => {
+                let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            WavFormat::Int24 => {
+                let value = (sample.clamp(-1.0, 1.0) * 8_388_607.0).round() as i32;
+                out.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+            WavFormat::Float32 => out.extend_from_slice(&sample.to_le_bytes()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecorderOptions {
+    pub format: WavFormat,
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// Capacity of the tee ring between the audio callback and the writer.
+    pub ring_frames: usize,
+    /// How often the RIFF sizes are patched so a crash leaves a playable file.
+    pub flush_interval: Duration,
+}
+
+impl Default for RecorderOptions {
+    fn default() -> Self {
+        Self {
+            format: WavFormat::Int24,
+            channels: 2,
+            sample_rate: 48_000,
+            ring_frames: 48_000,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// State shared between the writer thread and the [`Recorder`].
+#[derive(Debug, Default)]
+struct RecorderState {
+    stop: AtomicBool,
+    /// Whether the writer should flush and finalize the header on stop.
+    finalize: AtomicBool,
+    frames_written: AtomicU64,
+}
+
+/// What a finished recording contains.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingSummary {
+    pub path: PathBuf,
+    pub frames: u64,
+    pub dropped_blocks: u64,
+}
+
+/// Recording counters as scraped by the metrics endpoint.
+#[derive(Clone)]
+pub struct RecordingMetrics {
+    state: Arc<RecorderState>,
+    dropped_blocks: Arc<AtomicU64>,
+    sample_rate: u32,
+}
+
+impl Collect for RecordingMetrics {
+    fn collect(&self, metrics: &mut MetricSet) {
+        let frames = self.state.frames_written.load(Ordering::Relaxed);
+        metrics.gauge(
+            "deejay_recording_seconds",
+            "Audio written by the current recording.",
+            frames as f64 / self.sample_rate as f64,
+        );
+        metrics.counter(
+            "deejay_recording_dropped_blocks_total",
+            "Master blocks the recording writer fell too far behind to take.",
+            self.dropped_blocks.load(Ordering::Relaxed),
+        );
+    }
+}
+
+/// Records the master bus to a WAV file from a background writer thread.
+pub struct Recorder {
+    path: PathBuf,
+    sample_rate: u32,
+    state: Arc<RecorderState>,
+    dropped_blocks: Arc<AtomicU64>,
+    writer: JoinHandle<Result<(), RecordError>>,
+}
+
+impl Recorder {
+    /// Create `path` and start the writer. Hand the returned tap to the audio callback.
+    pub fn start(
+        path: impl AsRef<Path>,
+        options: RecorderOptions,
+    ) -> Result<(Self, RecordTap), RecordError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = BufWriter::new(File::create(&path)?);
+        write_header(&mut file, &options, 0)?;
+
+        let (tap, consumer, dropped_blocks) = tee(options.ring_frames, options.channels);
+        let state = Arc::new(RecorderState::default());
+        let writer_state = state.clone();
+        let writer = thread::Builder::new()
+            .name("recorder".into())
+            .spawn(move || run_writer(file, consumer, options, &writer_state))?;
+
+        Ok((
+            Self {
+                path,
+                sample_rate: options.sample_rate,
+                state,
+                dropped_blocks,
+                writer,
+            },
+            tap,
+        ))
+    }
+
+    /// Blocks dropped because the writer fell behind.
+    pub fn dropped_blocks(&self) -> u64 {
+        self.dropped_blocks.load(Ordering::Relaxed)
+    }
+
+    /// Duration of audio written to the file so far.
+    pub fn elapsed(&self) -> Duration {
+        let frames = self.state.frames_written.load(Ordering::Relaxed);
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+
+    /// Collector for this recording's counters, for a metrics [`Registry`](crate::metrics::Registry).
+    pub fn metrics(&self) -> RecordingMetrics {
+        RecordingMetrics {
+            state: self.state.clone(),
+            dropped_blocks: self.dropped_blocks.clone(),
+            sample_rate: self.sample_rate,
+        }
+    }
+
+    /// Drain everything queued so far, finalize the header and close the file.
+    pub fn stop(self) -> Result<RecordingSummary, RecordError> {
+        self.state.finalize.store(true, Ordering::Release);
+        self.state.stop.store(true, Ordering::Release);
+        self.writer
+            .join()
+            .map_err(|_| RecordError::WriterPanicked)??;
+        Ok(RecordingSummary {
+            path: self.path,
+            frames: self.state.frames_written.load(Ordering::Relaxed),
+            dropped_blocks: self.dropped_blocks.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Write interleaved `samples` to `path` in one go, e.g. for an offline render.
+///
+/// Returns the number of frames written; a trailing partial frame is ignored.
+pub fn write_wav(
+    path: impl AsRef<Path>,
+    samples: &[f32],
+    options: &RecorderOptions,
+) -> Result<u64, RecordError> {
+    let channels = options.channels.max(1) as usize;
+    let frames = samples.len() / channels;
+    let mut file = BufWriter::new(File::create(path)?);
+    write_header(&mut file, options, frames as u64)?;
+    let mut bytes =
+        Vec::with_capacity(WRITE_CHUNK_SAMPLES * options.format.bytes_per_sample() as usize);
+    for chunk in samples[..frames * channels].chunks(WRITE_CHUNK_SAMPLES) {
+        bytes.clear();
+        for sample in chunk {
+            options.format.encode(*sample, &mut bytes);
+        }
+        file.write_all(&bytes)?;
+    }
+    file.flush()?;
+    Ok(frames as u64)
+}
+
+fn run_writer(
+    mut file: BufWriter<File>,
+    mut consumer: RingConsumer,
+    options: RecorderOptions,
+    state: &RecorderState,
+) -> Result<(), RecordError> {
+    let channels = options.channels as usize;
+    let mut samples = vec![0.0f32; WRITE_CHUNK_SAMPLES / channels * channels];
+    let mut bytes = Vec::with_capacity(samples.len() * options.format.bytes_per_sample() as usize);
+    let mut frames = 0u64;
+    let mut last_patch = Instant::now();
+
+    loop {
+        let stopping = state.stop.load(Ordering::Acquire);
+        let available = (consumer.occupancy() * channels).min(samples.len());
+        if available > 0 {
+            let read = consumer.read(&mut samples[..available]);
+            bytes.clear();
+            for sample in &samples[..read] {
+                options.format.encode(*sample, &mut bytes);
+            }
+            file.write_all(&bytes)?;
+            frames += (read / channels) as u64;
+            state.frames_written.store(frames, Ordering::Relaxed);
+        } else if stopping {
+            break;
+        } else {
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        if last_patch.elapsed() >= options.flush_interval {
+            patch_sizes(&mut file, &options, frames)?;
+            last_patch = Instant::now();
+        }
+    }
+
+    if state.finalize.load(Ordering::Acquire) {
+        patch_sizes(&mut file, &options, frames)?;
+    }
+    Ok(())
+}
+
+fn data_bytes(options: &RecorderOptions, frames: u64) -> u32 {
+    let block_align = options.channels as u64 * options.format.bytes_per_sample() as u64;
+    (frames * block_align).min(u32::MAX as u64 - HEADER_BYTES) as u32
+}
+
+fn write_header<W: Write>(out: &mut W, options: &RecorderOptions, frames: u64) -> io::Result<()> {
+    let bytes_per_sample = options.format.bytes_per_sample();
+    let block_align = options.channels * bytes_per_sample;
+    let data = data_bytes(options, frames);
+    out.write_all(b"RIFF")?;
+    out.write_all(&(data + HEADER_BYTES as u32 - 8).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&options.format.tag().to_le_bytes())?;
+    out.write_all(&options.channels.to_le_bytes())?;
+    out.write_all(&options.sample_rate.to_le_bytes())?;
+    out.write_all(&(options.sample_rate * block_align as u32).to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&(bytes_per_sample * 8).to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data.to_le_bytes())
+}
+
+/// Flush written samples and rewrite the RIFF and data chunk sizes to cover them.
+fn patch_sizes(
+    file: &mut BufWriter<File>,
+    options: &RecorderOptions,
+    frames: u64,
+) -> io::Result<()> {
+    file.flush()?;
+    let data = data_bytes(options, frames);
+    let inner = file.get_mut();
+    inner.seek(SeekFrom::Start(4))?;
+    inner.write_all(&(data + HEADER_BYTES as u32 - 8).to_le_bytes())?;
+    inner.seek(SeekFrom::Start(HEADER_BYTES - 4))?;
+    inner.write_all(&data.to_le_bytes())?;
+    inner.seek(SeekFrom::End(0))?;
+    inner.sync_data()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Parsed header fields and the raw sample data of a WAV file.
+    struct Wav {
+        riff_size: u32,
+        tag: u16,
+        channels: u16,
+        sample_rate: u32,
+        bits: u16,
+        data_size: u32,
+        data: Vec<u8>,
+    }
+
+    fn read_wav(path: &Path) -> Wav {
+        let bytes = std::fs::read(path).unwrap();
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[36..40], b"data");
+        Wav {
+            riff_size: u32_at(4),
+            tag: u16_at(20),
+            channels: u16_at(22),
+            sample_rate: u32_at(24),
+            bits: u16_at(34),
+            data_size: u32_at(40),
+            data: bytes[44..].to_vec(),
+        }
+    }
+
+    /// Stereo block whose samples encode their index: left rises, right mirrors it.
+    fn block(index: usize, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|f| {
+                let value = ((index * frames + f) % 1_000) as f32 / 1_000.0;
+                [value, -value]
+            })
+            .collect()
+    }
+
+    fn push_blocks(tap: &mut RecordTap, count: usize, frames: usize) {
+        for index in 0..count {
+            while !tap.push(&block(index, frames)) {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    #[test]
+    fn clean_stop_writes_exact_counts_and_headers() {
+        let dir = tempdir().unwrap();
+        for (format, tag, bits) in [
+            (WavFormat::Int16, 1, 16),
+            (WavFormat::Int24, 1, 24),
+            (WavFormat::Float32, 3, 32),
+        ] {
+            let path = dir.path().join(format!("{bits}.wav"));
+            let options = RecorderOptions {
+                format,
+                sample_rate: 44_100,
+                ring_frames: 4_096,
+                ..RecorderOptions::default()
+            };
+            let (recorder, mut tap) = Recorder::start(&path, options).unwrap();
+            push_blocks(&mut tap, 100, 441);
+            let summary = recorder.stop().unwrap();
+            assert_eq!(summary.frames, 44_100);
+
+            let wav = read_wav(&path);
+            let bytes_per_frame = 2 * bits as u32 / 8;
+            assert_eq!((wav.tag, wav.channels, wav.bits), (tag, 2, bits));
+            assert_eq!(wav.sample_rate, 44_100);
+            assert_eq!(wav.data_size, 44_100 * bytes_per_frame);
+            assert_eq!(wav.riff_size, wav.data_size + 36);
+            assert_eq!(wav.data.len() as u32, wav.data_size);
+
+            if format == WavFormat::Float32 {
+                let expected = block(3, 441);
+                let offset = 3 * 441 * 8;
+                for (i, sample) in expected.iter().enumerate() {
+                    let at = offset + i * 4;
+                    let got = f32::from_le_bytes(wav.data[at..at + 4].try_into().unwrap());
+                    assert_eq!(got, *sample);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn killed_writer_leaves_file_playable_to_last_patch() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("crash.wav");
+        let options = RecorderOptions {
+            format: WavFormat::Int16,
+            ring_frames: 8_192,
+            flush_interval: Duration::from_millis(10),
+            ..RecorderOptions::default()
+        };
+        let (recorder, mut tap) = Recorder::start(&path, options).unwrap();
+        push_blocks(&mut tap, 50, 480);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while read_wav(&path).data_size == 0 {
+            assert!(Instant::now() < deadline, "writer never patched the header");
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // Stop the writer without finalizing, as if the process had died.
+        recorder.state.stop.store(true, Ordering::Release);
+        recorder.writer.join().unwrap().unwrap();
+
+        let wav = read_wav(&path);
+        assert!(wav.data_size > 0);
+        assert_eq!(wav.data_size % 4, 0);
+        assert_eq!(wav.riff_size, wav.data_size + 36);
+        assert!(wav.data.len() >= wav.data_size as usize);
+        let expected: Vec<f32> = (0..50).flat_map(|i| block(i, 480)).collect();
+        for (i, bytes) in wav.data[..wav.data_size as usize]
+            .chunks_exact(2)
+            .enumerate()
+        {
+            let got = i16::from_le_bytes([bytes[0], bytes[1]]);
+            assert_eq!(
+                got,
+                (expected[i] * i16::MAX as f32).round() as i16,
+                "sample {i}"
+            );
+        }
+    }
+
+    #[test]
+    fn write_wav_matches_the_streaming_header() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("offline.wav");
+        let options = RecorderOptions {
+            format: WavFormat::Float32,
+            ..RecorderOptions::default()
+        };
+        let mut samples = block(0, 480);
+        samples.push(0.5);
+        assert_eq!(write_wav(&path, &samples, &options).unwrap(), 480);
+
+        let wav = read_wav(&path);
+        assert_eq!((wav.tag, wav.channels, wav.bits), (3, 2, 32));
+        assert_eq!(wav.data_size, 480 * 8);
+        assert_eq!(wav.riff_size, wav.data_size + 36);
+        let last = f32::from_le_bytes(wav.data[wav.data.len() - 4..].try_into().unwrap());
+        assert_eq!(last, samples[959]);
+    }
+
+    #[test]
+    fn oversized_blocks_are_dropped_and_counted() {
+        let dir = tempdir().unwrap();
+        let options = RecorderOptions {
+            ring_frames: 256,
+            ..RecorderOptions::default()
+        };
+        let (recorder, mut tap) = Recorder::start(dir.path().join("drop.wav"), options).unwrap();
+        assert!(!tap.push(&block(0, 512)));
+        assert!(tap.push(&block(0, 240)));
+        assert_eq!(recorder.dropped_blocks(), 1);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while recorder.elapsed() < Duration::from_millis(5) {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(1));
+        }
+        let summary = recorder.stop().unwrap();
+        assert_eq!((summary.frames, summary.dropped_blocks), (240, 1));
+    }
+}
@@ -0,0 +1,289 @@
+#[cfg(feature = "remote")]
+mod server;
+
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::deck::{DeckCommand, DeckEvent, DeckPosition, TransportState};
+use crate::{DeckId, ParameterUpdate};
+
+#[cfg(feature = "remote")]
+pub use server::{LoadRequest, RemoteServer, RemoteSinks, RemoteSnapshot};
+
+/// Version of the JSON schema below; bump it on any incompatible change.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum RemoteError {
+    #[error("malformed remote message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("remote protocol version {0} is not supported (expected {PROTOCOL_VERSION})")]
+    Version(u32),
+    #[error("remote access needs a token in the settings")]
+    NoToken,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Mixer parameter a remote can set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamTarget {
+    Crossfader,
+    MasterGain,
+    /// Needs a deck.
+    Gain,
+    /// Needs a deck.
+    Filter,
+    Tempo,
+    MicGain,
+}
+
+/// Transport command for a deck.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeckAction {
+    Play,
+    Pause,
+    Stop,
+    Eject,
+    BeatJump(i32),
+}
+
+impl ParamTarget {
+    /// The update setting this parameter to `value`, or why there is none.
+    pub fn update(self, deck: Option<DeckId>, value: f32) -> Result<ParameterUpdate, String> {
+        let deck = || deck.ok_or_else(|| format!("{self:?} needs a deck"));
+        Ok(match self {
+            ParamTarget::Crossfader => ParameterUpdate::Crossfader(value),
+            ParamTarget::MasterGain => ParameterUpdate::MasterGain(value),
+            ParamTarget::Gain => ParameterUpdate::DeckGain {
+                deck: deck()?,
+                gain: value,
+            },
+            ParamTarget::Filter => ParameterUpdate::DeckFilter {
+                deck: deck()?,
+                position: value,
+            },
+            ParamTarget::Tempo => ParameterUpdate::Tempo((value > 0.0).then_some(value)),
+            ParamTarget::MicGain => ParameterUpdate::MicGain(value),
+        })
+    }
+}
+
+impl From<DeckAction> for DeckCommand {
+    fn from(action: DeckAction) -> Self {
+        match action {
+            DeckAction::Play => DeckCommand::Play,
+            DeckAction::Pause => DeckCommand::Pause,
+            DeckAction::Stop => DeckCommand::Stop,
+            DeckAction::Eject => DeckCommand::Eject,
+            DeckAction::BeatJump(beats) => DeckCommand::BeatJump { beats },
+        }
+    }
+}
+
+/// Messages a remote sends, tagged by `cmd`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum ClientMessage {
+    SetParam {
+        target: ParamTarget,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deck: Option<DeckId>,
+        value: f32,
+    },
+    Deck {
+        deck: DeckId,
+        action: DeckAction,
+    },
+    /// Load a library track, identified by its indexed path.
+    Load {
+        deck: DeckId,
+        track: PathBuf,
+    },
+    /// Start receiving state, meter and event frames.
+    Subscribe,
+    Unsubscribe,
+}
+
+/// What a remote shows for one deck.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeckStatus {
+    pub state: String,
+    /// Playhead through the track, in [0, 1].
+    pub position: f64,
+    pub seconds: f64,
+    pub remaining_seconds: f64,
+    pub playing: bool,
+}
+
+impl DeckStatus {
+    pub fn new(transport: &TransportState, position: &DeckPosition) -> Self {
+        Self {
+            state: transport.name().into(),
+            position: position.fraction,
+            seconds: position.seconds,
+            remaining_seconds: position.remaining_seconds,
+            playing: position.playing,
+        }
+    }
+}
+
+/// Messages the server pushes, tagged by `type`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// First frame on every connection.
+    Hello {
+        version: u32,
+    },
+    State {
+        decks: [DeckStatus; 2],
+    },
+    Meter {
+        peak: [f32; 2],
+        /// Momentary loudness in LUFS; absent while silent.
+        loudness: Option<f32>,
+    },
+    Event {
+        deck: DeckId,
+        event: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    pub fn event(deck: DeckId, event: &DeckEvent) -> Self {
+        let (name, detail) = match event {
+            DeckEvent::TrackLoaded { .. } => ("track_loaded", None),
+            DeckEvent::Started => ("started", None),
+            DeckEvent::Ended => ("ended", None),
+            DeckEvent::LoadFailed(message) => ("load_failed", Some(message.clone())),
+            DeckEvent::Rejected { state, .. } => ("rejected", Some(state.name().into())),
+            DeckEvent::LoadRejected(state) => ("load_rejected", Some(state.name().into())),
+        };
+        ServerMessage::Event {
+            deck,
+            event: name.into(),
+            detail,
+        }
+    }
+}
+
+fn current_protocol() -> u32 {
+    PROTOCOL_VERSION
+}
+
+/// Wire form of every message in both directions: the body plus the protocol version.
+///
+/// Remotes may leave out `v`, which then means the current version.
+#[derive(Debug, Serialize, Deserialize)]
+struct Frame<T> {
+    #[serde(rename = "v", default = "current_protocol")]
+    version: u32,
+    #[serde(flatten)]
+    body: T,
+}
+
+/// Serialize `body` as a versioned frame.
+pub fn encode<T: Serialize>(body: T) -> String {
+    serde_json::to_string(&Frame {
+        version: PROTOCOL_VERSION,
+        body,
+    })
+    .expect("remote messages always serialize")
+}
+
+/// Parse a versioned frame, refusing other protocol versions.
+pub fn decode<T: DeserializeOwned>(text: &str) -> Result<T, RemoteError> {
+    let frame: Frame<serde_json::Value> = serde_json::from_str(text)?;
+    if frame.version != PROTOCOL_VERSION {
+        return Err(RemoteError::Version(frame.version));
+    }
+    Ok(serde_json::from_value(frame.body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_round_trip_and_accept_a_missing_version() {
+        let parsed: ClientMessage =
+            decode(r#"{"cmd":"set_param","target":"crossfader","value":0.3}"#).unwrap();
+        assert_eq!(
+            parsed,
+            ClientMessage::SetParam {
+                target: ParamTarget::Crossfader,
+                deck: None,
+                value: 0.3
+            }
+        );
+
+        for message in [
+            parsed,
+            ClientMessage::Deck {
+                deck: DeckId::B,
+                action: DeckAction::BeatJump(-4),
+            },
+            ClientMessage::Load {
+                deck: DeckId::A,
+                track: PathBuf::from("/music/track.flac"),
+            },
+            ClientMessage::Subscribe,
+        ] {
+            let text = encode(&message);
+            assert!(text.contains(r#""v":1"#), "{text}");
+            assert_eq!(decode::<ClientMessage>(&text).unwrap(), message);
+        }
+        assert_eq!(
+            encode(ClientMessage::Deck {
+                deck: DeckId::A,
+                action: DeckAction::Play
+            }),
+            r#"{"v":1,"cmd":"deck","deck":"A","action":"play"}"#
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions_and_unknown_commands() {
+        assert!(matches!(
+            decode::<ClientMessage>(r#"{"v":2,"cmd":"subscribe"}"#),
+            Err(RemoteError::Version(2))
+        ));
+        assert!(matches!(
+            decode::<ClientMessage>(r#"{"cmd":"self_destruct"}"#),
+            Err(RemoteError::Json(_))
+        ));
+        assert!(ParamTarget::Gain.update(None, 1.0).is_err());
+        assert!(matches!(
+            ParamTarget::Gain.update(Some(DeckId::B), 0.5),
+            Ok(ParameterUpdate::DeckGain {
+                deck: DeckId::B,
+                gain
+            }) if gain == 0.5
+        ));
+    }
+
+    #[test]
+    fn server_frames_are_tagged_by_type() {
+        let meter = ServerMessage::Meter {
+            peak: [0.5, 0.25],
+            loudness: None,
+        };
+        assert_eq!(
+            encode(&meter),
+            r#"{"v":1,"type":"meter","peak":[0.5,0.25],"loudness":null}"#
+        );
+        let event = ServerMessage::event(DeckId::B, &DeckEvent::LoadFailed("no such file".into()));
+        assert_eq!(decode::<ServerMessage>(&encode(&event)).unwrap(), event);
+    }
+}
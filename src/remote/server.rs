@@ -0,0 +1,508 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::{Message, WebSocket};
+
+use super::{
+    decode, encode, ClientMessage, DeckStatus, RemoteError, ServerMessage, PROTOCOL_VERSION,
+};
+use crate::crash::record_breadcrumb;
+use crate::deck::{DeckEvent, DeckPosition, TransportState};
+use crate::settings::Settings;
+use crate::{DeckCommandSender, DeckId, ParameterSender};
+
+/// How long the server sleeps when nothing arrived.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// A client that stalls its handshake longer than this is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Asks whoever owns the decks to load a library track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadRequest {
+    pub deck: DeckId,
+    pub track: PathBuf,
+}
+
+/// Where remote commands go.
+#[derive(Clone)]
+pub struct RemoteSinks {
+    pub parameters: ParameterSender,
+    pub decks: DeckCommandSender,
+    pub loads: Sender<LoadRequest>,
+}
+
+/// What subscribed remotes are shown, sampled at the push rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteSnapshot {
+    pub decks: [DeckStatus; 2],
+    /// Absolute peak of the master mix per channel.
+    pub peak: [f32; 2],
+    /// Momentary loudness of the master in LUFS.
+    pub loudness: f32,
+    /// Deck events since the previous snapshot.
+    pub events: Vec<(DeckId, DeckEvent)>,
+}
+
+impl Default for RemoteSnapshot {
+    fn default() -> Self {
+        let empty = DeckStatus::new(&TransportState::Empty, &DeckPosition::default());
+        Self {
+            decks: [empty.clone(), empty],
+            peak: [0.0; 2],
+            loudness: f32::NEG_INFINITY,
+            events: Vec::new(),
+        }
+    }
+}
+
+/// Token from `?token=` in the URL, as browsers cannot set headers, or an `Authorization: Bearer` header.
+fn request_token(request: &Request) -> Option<&str> {
+    let from_query = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+    });
+    from_query.or_else(|| {
+        request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+    })
+}
+
+fn handshake(stream: TcpStream, token: &str) -> Result<WebSocket<TcpStream>, String> {
+    // Accepted sockets inherit non-blocking mode on some platforms.
+    stream
+        .set_nonblocking(false)
+        .map_err(|err| err.to_string())?;
+    stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+    // The callback's signature is fixed by tungstenite.
+    #[allow(clippy::result_large_err)]
+    let authorize = |request: &Request, response: Response| {
+        if request_token(request) == Some(token) {
+            Ok(response)
+        } else {
+            let mut rejection = ErrorResponse::new(Some("invalid token".into()));
+            *rejection.status_mut() = StatusCode::UNAUTHORIZED;
+            Err(rejection)
+        }
+    };
+    let mut socket = tungstenite::accept_hdr(stream, authorize).map_err(|err| err.to_string())?;
+    socket
+        .send(Message::text(encode(ServerMessage::Hello {
+            version: PROTOCOL_VERSION,
+        })))
+        .map_err(|err| err.to_string())?;
+    socket
+        .get_ref()
+        .set_nonblocking(true)
+        .map_err(|err| err.to_string())?;
+    Ok(socket)
+}
+
+struct Connection {
+    socket: WebSocket<TcpStream>,
+    open: bool,
+    subscribed: bool,
+    /// Last state and meter frames sent, so unchanged ones are not repeated.
+    last_state: Option<String>,
+    last_meter: Option<String>,
+}
+
+impl Connection {
+    fn new(socket: WebSocket<TcpStream>) -> Self {
+        Self {
+            socket,
+            open: true,
+            subscribed: false,
+            last_state: None,
+            last_meter: None,
+        }
+    }
+
+    /// Handle everything the remote has sent; returns whether anything arrived.
+    fn receive(&mut self, sinks: &RemoteSinks) -> bool {
+        let mut received = false;
+        while self.open {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    received = true;
+                    let result = decode(text.as_str())
+                        .map_err(|err| err.to_string())
+                        .and_then(|message| self.apply(message, sinks));
+                    if let Err(message) = result {
+                        self.push(encode(ServerMessage::Error { message }));
+                    }
+                }
+                // Pings are answered and closes acknowledged by tungstenite itself.
+                Ok(_) => received = true,
+                Err(tungstenite::Error::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                    break
+                }
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    self.open = false
+                }
+                Err(err) => {
+                    record_breadcrumb(format!("remote connection failed: {err}"));
+                    self.open = false;
+                }
+            }
+        }
+        received
+    }
+
+    fn apply(&mut self, message: ClientMessage, sinks: &RemoteSinks) -> Result<(), String> {
+        match message {
+            ClientMessage::SetParam {
+                target,
+                deck,
+                value,
+            } => sinks
+                .parameters
+                .send(target.update(deck, value)?)
+                .map_err(|_| "parameter queue is full".to_string()),
+            ClientMessage::Deck { deck, action } => sinks
+                .decks
+                .send(deck, action.into())
+                .map_err(|_| "deck command queue is full".to_string()),
+            ClientMessage::Load { deck, track } => sinks
+                .loads
+                .send(LoadRequest { deck, track })
+                .map_err(|_| "tracks cannot be loaded right now".to_string()),
+            ClientMessage::Subscribe => {
+                self.subscribed = true;
+                self.last_state = None;
+                self.last_meter = None;
+                Ok(())
+            }
+            ClientMessage::Unsubscribe => {
+                self.subscribed = false;
+                Ok(())
+            }
+        }
+    }
+
+    fn push(&mut self, frame: String) {
+        match self.socket.send(Message::text(frame)) {
+            // Queued; the rest goes out with the next flush.
+            Ok(()) => {}
+            Err(tungstenite::Error::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(err) => {
+                record_breadcrumb(format!("remote send failed: {err}"));
+                self.open = false;
+            }
+        }
+    }
+
+    /// Send the snapshot's frames that this remote has not seen.
+    fn publish(&mut self, state: &str, meter: &str, events: &[String]) {
+        if self.last_state.as_deref() != Some(state) {
+            self.push(state.to_string());
+            self.last_state = Some(state.to_string());
+        }
+        if self.last_meter.as_deref() != Some(meter) {
+            self.push(meter.to_string());
+            self.last_meter = Some(meter.to_string());
+        }
+        for event in events {
+            self.push(event.clone());
+        }
+    }
+
+    fn flush(&mut self) {
+        match self.socket.flush() {
+            Ok(()) => {}
+            Err(tungstenite::Error::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(_) => self.open = false,
+        }
+    }
+}
+
+/// WebSocket remote control, served from a background thread.
+pub struct RemoteServer {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    worker: JoinHandle<()>,
+}
+
+impl RemoteServer {
+    /// Listen on `settings.remote_address` and push what `state` shows at `settings.remote_push_hz`;
+    /// `None` when the remote is not configured.
+    pub fn start<F>(
+        settings: &Settings,
+        sinks: RemoteSinks,
+        mut state: F,
+    ) -> Result<Option<Self>, RemoteError>
+    where
+        F: FnMut() -> RemoteSnapshot + Send + 'static,
+    {
+        let Some(address) = settings.remote_address.as_deref() else {
+            return Ok(None);
+        };
+        let token = settings
+            .remote_token
+            .clone()
+            .filter(|token| !token.is_empty())
+            .ok_or(RemoteError::NoToken)?;
+        let interval = Duration::from_secs_f64(1.0 / settings.remote_push_hz.max(1) as f64);
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = stop.clone();
+        let worker = thread::Builder::new()
+            .name("remote".into())
+            .spawn(move || {
+                let mut connections: Vec<Connection> = Vec::new();
+                let mut last_push: Option<Instant> = None;
+                while !stopping.load(Ordering::Acquire) {
+                    let mut busy = false;
+                    match listener.accept() {
+                        Ok((stream, peer)) => {
+                            busy = true;
+                            match handshake(stream, &token) {
+                                Ok(socket) => connections.push(Connection::new(socket)),
+                                Err(err) => {
+                                    record_breadcrumb(format!("remote from {peer} refused: {err}"))
+                                }
+                            }
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                        Err(err) => record_breadcrumb(format!("remote accept failed: {err}")),
+                    }
+                    for connection in &mut connections {
+                        busy |= connection.receive(&sinks);
+                    }
+
+                    let now = Instant::now();
+                    if last_push.is_none_or(|last| now - last >= interval) {
+                        last_push = Some(now);
+                        let snapshot = state();
+                        let frame = encode(ServerMessage::State {
+                            decks: snapshot.decks,
+                        });
+                        let meter = encode(ServerMessage::Meter {
+                            peak: snapshot.peak,
+                            loudness: Some(snapshot.loudness).filter(|lufs| lufs.is_finite()),
+                        });
+                        let events: Vec<_> = snapshot
+                            .events
+                            .iter()
+                            .map(|(deck, event)| encode(ServerMessage::event(*deck, event)))
+                            .collect();
+                        for connection in connections.iter_mut().filter(|c| c.subscribed) {
+                            connection.publish(&frame, &meter, &events);
+                        }
+                    }
+                    for connection in &mut connections {
+                        connection.flush();
+                    }
+                    connections.retain(|connection| connection.open);
+                    if !busy {
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                }
+            })?;
+        Ok(Some(Self {
+            address,
+            stop,
+            worker,
+        }))
+    }
+
+    /// Address actually bound.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Release);
+        let _ = self.worker.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deck_command_channel, parameter_channel, ParameterUpdate};
+    use std::sync::{mpsc, Mutex};
+    use tungstenite::handshake::HandshakeError;
+
+    fn settings() -> Settings {
+        Settings {
+            remote_address: Some("127.0.0.1:0".into()),
+            remote_token: Some("s3cret".into()),
+            remote_push_hz: 50,
+            ..Settings::default()
+        }
+    }
+
+    fn connect(address: SocketAddr, token: &str) -> WebSocket<TcpStream> {
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (socket, _) = tungstenite::client(format!("ws://{address}/?token={token}"), stream)
+            .unwrap_or_else(|err| panic!("handshake failed: {err}"));
+        socket
+    }
+
+    fn next(socket: &mut WebSocket<TcpStream>) -> ServerMessage {
+        match socket.read().unwrap() {
+            Message::Text(text) => decode(text.as_str()).unwrap(),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    fn send(socket: &mut WebSocket<TcpStream>, text: &str) {
+        socket.send(Message::text(text)).unwrap();
+    }
+
+    fn eventually<T>(mut poll: impl FnMut() -> Option<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(value) = poll() {
+                return value;
+            }
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    #[test]
+    fn commands_reach_the_channels_and_state_is_pushed() {
+        let (parameters, parameter_rx) = parameter_channel(8);
+        let (decks, deck_rx) = deck_command_channel(8);
+        let (loads, load_rx) = mpsc::channel();
+        let snapshot = Arc::new(Mutex::new(RemoteSnapshot::default()));
+        let source = snapshot.clone();
+        let server = RemoteServer::start(
+            &settings(),
+            RemoteSinks {
+                parameters,
+                decks,
+                loads,
+            },
+            move || {
+                let mut snapshot = source.lock().unwrap();
+                let sampled = snapshot.clone();
+                snapshot.events.clear();
+                sampled
+            },
+        )
+        .unwrap()
+        .unwrap();
+        let mut socket = connect(server.local_addr(), "s3cret");
+        assert_eq!(
+            next(&mut socket),
+            ServerMessage::Hello {
+                version: PROTOCOL_VERSION
+            }
+        );
+
+        send(
+            &mut socket,
+            r#"{"cmd":"set_param","target":"crossfader","value":0.3}"#,
+        );
+        match eventually(|| parameter_rx.pop()) {
+            ParameterUpdate::Crossfader(value) => assert_eq!(value, 0.3),
+            other => panic!("unexpected {other:?}"),
+        }
+        send(
+            &mut socket,
+            r#"{"v":1,"cmd":"deck","deck":"B","action":{"beat_jump":-4}}"#,
+        );
+        assert!(matches!(
+            eventually(|| deck_rx.pop()),
+            (DeckId::B, crate::deck::DeckCommand::BeatJump { beats: -4 })
+        ));
+        send(
+            &mut socket,
+            r#"{"cmd":"load","deck":"A","track":"/music/a.flac"}"#,
+        );
+        assert_eq!(
+            load_rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            LoadRequest {
+                deck: DeckId::A,
+                track: "/music/a.flac".into()
+            }
+        );
+        send(
+            &mut socket,
+            r#"{"cmd":"set_param","target":"gain","value":1}"#,
+        );
+        assert!(matches!(next(&mut socket), ServerMessage::Error { .. }));
+
+        // Subscribing pushes the state and meter once, then only what changes.
+        send(&mut socket, r#"{"cmd":"subscribe"}"#);
+        assert!(matches!(next(&mut socket), ServerMessage::State { .. }));
+        assert!(matches!(
+            next(&mut socket),
+            ServerMessage::Meter { loudness: None, .. }
+        ));
+        {
+            let mut snapshot = snapshot.lock().unwrap();
+            snapshot.decks[0].state = "playing".into();
+            snapshot.events.push((DeckId::A, DeckEvent::Started));
+        }
+        match next(&mut socket) {
+            ServerMessage::State { decks } => assert_eq!(decks[0].state, "playing"),
+            other => panic!("unexpected {other:?}"),
+        }
+        assert_eq!(
+            next(&mut socket),
+            ServerMessage::Event {
+                deck: DeckId::A,
+                event: "started".into(),
+                detail: None
+            }
+        );
+        server.stop();
+    }
+
+    #[test]
+    fn rejects_a_wrong_token_and_needs_one_configured() {
+        let (parameters, _) = parameter_channel(1);
+        let (decks, _) = deck_command_channel(1);
+        let (loads, _) = mpsc::channel();
+        let sinks = RemoteSinks {
+            parameters,
+            decks,
+            loads,
+        };
+        let server = RemoteServer::start(&settings(), sinks.clone(), RemoteSnapshot::default)
+            .unwrap()
+            .unwrap();
+
+        let stream = TcpStream::connect(server.local_addr()).unwrap();
+        let url = format!("ws://{}/?token=guess", server.local_addr());
+        match tungstenite::client(url, stream) {
+            Err(HandshakeError::Failure(tungstenite::Error::Http(response))) => {
+                assert_eq!(response.status(), StatusCode::UNAUTHORIZED)
+            }
+            Err(other) => panic!("unexpected {other}"),
+            Ok(_) => panic!("a wrong token was accepted"),
+        }
+        server.stop();
+
+        let open = Settings {
+            remote_token: None,
+            ..settings()
+        };
+        assert!(matches!(
+            RemoteServer::start(&open, sinks, RemoteSnapshot::default),
+            Err(RemoteError::NoToken)
+        ));
+    }
+}
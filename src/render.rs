@@ -0,0 +1,606 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::deck::{Deck, Track};
+use crate::decode::{decode_track, DecodeError};
+use crate::fx::FxChain;
+use crate::meter::{loudness_meter, LoudnessReading};
+use crate::remote::{DeckAction, ParamTarget};
+use crate::session::{Session, SessionError};
+use crate::{parameter_channel, DeckId, ParameterSender, SummingBus};
+
+/// Frames mixed per block between automation points.
+const BLOCK_FRAMES: usize = 512;
+/// Room in the parameter queue for a session's mixer state on top of the automation.
+const SESSION_UPDATES: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error("malformed automation: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("automation point at {0} s: {1}")]
+    Automation(f64, String),
+    #[error("decks run at {0} Hz and {1} Hz; resample one of them first")]
+    SampleRateMismatch(u32, u32),
+    #[error("failed to write automation: {0}")]
+    Io(#[from] io::Error),
+    #[error("session tracks are missing: {}", list_paths(.0))]
+    MissingTracks(Vec<(DeckId, PathBuf)>),
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+}
+
+fn list_paths(missing: &[(DeckId, PathBuf)]) -> String {
+    missing
+        .iter()
+        .map(|(deck, path)| format!("{} (deck {deck:?})", path.display()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Level of a finished render.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSummary {
+    pub frames: u64,
+    pub sample_rate: u32,
+    /// Largest absolute sample.
+    pub peak: f32,
+    pub loudness: LoudnessReading,
+}
+
+impl RenderSummary {
+    fn of(mix: &[f32], sample_rate: u32) -> Self {
+        let (mut meter, reader) = loudness_meter(sample_rate);
+        meter.process(mix);
+        Self {
+            frames: (mix.len() / 2) as u64,
+            sample_rate,
+            peak: mix.iter().fold(0.0, |peak, s| peak.max(s.abs())),
+            loudness: reader.reading(),
+        }
+    }
+}
+
+/// What happens at an automation point, in the same shape as the remote commands.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum AutomationAction {
+    SetParam {
+        target: ParamTarget,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deck: Option<DeckId>,
+        value: f32,
+    },
+    Deck {
+        deck: DeckId,
+        action: DeckAction,
+    },
+}
+
+/// An action scheduled `seconds` into the render, e.g.
+/// `{"seconds":8.0,"cmd":"set_param","target":"crossfader","value":1.0}`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AutomationPoint {
+    pub seconds: f64,
+    #[serde(flatten)]
+    pub action: AutomationAction,
+}
+
+/// Parse a JSON array of [`AutomationPoint`]s.
+pub fn parse_automation(json: &str) -> Result<Vec<AutomationPoint>, RenderError> {
+    Ok(serde_json::from_str(json)?)
+}
+
+/// Write `points` to `path` in the format [`parse_automation`] reads.
+pub fn save_automation(path: &Path, points: &[AutomationPoint]) -> Result<(), RenderError> {
+    let payload = serde_json::to_string_pretty(points)?;
+    fs::write(path, payload)?;
+    Ok(())
+}
+
+/// Mix `tracks` on decks A and B through a [`SummingBus`], driven by `automation`.
+///
+/// Both decks start parked at the top of their track, so the automation has to
+/// start them. Rendering runs until the last point has passed and every playing
+/// deck has reached the end of its track. Returns interleaved stereo at the
+/// tracks' shared sample rate.
+pub fn render_mix(
+    tracks: [Track; 2],
+    automation: &[AutomationPoint],
+) -> Result<Vec<f32>, RenderError> {
+    let [track_a, track_b] = tracks;
+    let sample_rate = track_a.sample_rate();
+    if track_b.sample_rate() != sample_rate {
+        return Err(RenderError::SampleRateMismatch(
+            sample_rate,
+            track_b.sample_rate(),
+        ));
+    }
+
+    let (params, receiver) = parameter_channel(automation.len().max(1));
+    let mut bus = SummingBus::new(receiver).with_sample_rate(sample_rate);
+    let mut decks = [Deck::new(), Deck::new()];
+    decks[0].load(track_a);
+    decks[1].load(track_b);
+    run(
+        &mut decks,
+        &mut bus,
+        &params,
+        automation,
+        sample_rate,
+        |_| {},
+    )
+}
+
+/// Session tracks that are not on disk.
+pub fn missing_tracks(session: &Session) -> Vec<(DeckId, PathBuf)> {
+    [DeckId::A, DeckId::B]
+        .into_iter()
+        .zip(&session.decks)
+        .filter_map(|(deck, saved)| Some((deck, saved.path.clone()?)))
+        .filter(|(_, path)| !path.exists())
+        .collect()
+}
+
+/// Play a set back offline: restore `session` onto the decks, mixer and effect
+/// chains, then render it under `automation` as [`render_mix`] does.
+///
+/// Every track is checked for and decoded before mixing starts, so a missing
+/// file fails the render up front. `progress` is called after each block with
+/// the seconds rendered so far.
+pub fn render_session(
+    session: &Session,
+    automation: &[AutomationPoint],
+    progress: impl FnMut(f64),
+) -> Result<(Vec<f32>, RenderSummary), RenderError> {
+    let missing = missing_tracks(session);
+    if !missing.is_empty() {
+        return Err(RenderError::MissingTracks(missing));
+    }
+    let mut tracks = Vec::new();
+    for path in session
+        .decks
+        .iter()
+        .filter_map(|saved| saved.path.as_deref())
+    {
+        tracks.push(decode_track(path)?);
+    }
+    let sample_rate = tracks.first().map_or(48_000, Track::sample_rate);
+    if let Some(other) = tracks
+        .iter()
+        .find(|track| track.sample_rate() != sample_rate)
+    {
+        return Err(RenderError::SampleRateMismatch(
+            sample_rate,
+            other.sample_rate(),
+        ));
+    }
+
+    let (params, receiver) = parameter_channel(automation.len() + SESSION_UPDATES);
+    let mut bus = SummingBus::new(receiver).with_sample_rate(sample_rate);
+    let (chain_a, handle_a) = FxChain::new(sample_rate, BLOCK_FRAMES);
+    let (chain_b, handle_b) = FxChain::new(sample_rate, BLOCK_FRAMES);
+    bus.set_deck_fx(DeckId::A, Some(Box::new(chain_a)));
+    bus.set_deck_fx(DeckId::B, Some(Box::new(chain_b)));
+    let mut decks = [Deck::new(), Deck::new()];
+    let mut decoded = tracks.into_iter();
+    session.restore(
+        &mut decks,
+        |_| decoded.next().ok_or_else(|| "already decoded".to_string()),
+        &params,
+        &[handle_a, handle_b],
+        sample_rate,
+    )?;
+
+    let mix = run(
+        &mut decks,
+        &mut bus,
+        &params,
+        automation,
+        sample_rate,
+        progress,
+    )?;
+    let summary = RenderSummary::of(&mix, sample_rate);
+    Ok((mix, summary))
+}
+
+/// Mix until the automation is used up and no deck is still playing.
+fn run(
+    decks: &mut [Deck; 2],
+    bus: &mut SummingBus,
+    params: &ParameterSender,
+    automation: &[AutomationPoint],
+    sample_rate: u32,
+    mut progress: impl FnMut(f64),
+) -> Result<Vec<f32>, RenderError> {
+    let mut points = automation.to_vec();
+    points.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+    // Rounded, so points written from frame numbers land back on them.
+    let at_frame =
+        |point: &AutomationPoint| (point.seconds.max(0.0) * sample_rate as f64).round() as u64;
+
+    let mut pending = points.iter().peekable();
+    let mut output = Vec::new();
+    let mut buffers = [vec![0.0; BLOCK_FRAMES * 2], vec![0.0; BLOCK_FRAMES * 2]];
+    let mut mixed = vec![0.0; BLOCK_FRAMES * 2];
+    let mut frame = 0u64;
+    loop {
+        while let Some(point) = pending.next_if(|point| at_frame(point) <= frame) {
+            match point.action {
+                AutomationAction::SetParam {
+                    target,
+                    deck,
+                    value,
+                } => {
+                    let update = target
+                        .update(deck, value)
+                        .map_err(|reason| RenderError::Automation(point.seconds, reason))?;
+                    // Sized for every point, and drained on each block.
+                    let _ = params.send(update);
+                }
+                AutomationAction::Deck { deck, action } => {
+                    decks[deck.index()].apply(action.into());
+                }
+            }
+        }
+        let playing = decks.iter().map(remaining_frames).max().unwrap_or(0);
+        let frames = match pending.peek() {
+            Some(point) => at_frame(point) - frame,
+            None if playing > 0 => playing,
+            None => break,
+        }
+        .min(BLOCK_FRAMES as u64) as usize;
+
+        let [deck_a, deck_b] = &mut buffers;
+        let (deck_a, deck_b) = (&mut deck_a[..frames * 2], &mut deck_b[..frames * 2]);
+        decks[0].render(deck_a);
+        decks[1].render(deck_b);
+        bus.process(deck_a, deck_b, &mut mixed[..frames * 2]);
+        output.extend_from_slice(&mixed[..frames * 2]);
+        frame += frames as u64;
+        progress(frame as f64 / sample_rate as f64);
+    }
+    Ok(output)
+}
+
+/// Output frames until a playing deck runs off the end of its track.
+fn remaining_frames(deck: &Deck) -> u64 {
+    match deck.track() {
+        Some(track) if deck.is_playing() => {
+            let left = (track.frames() as f64 - deck.position()).max(0.0);
+            (left / deck.tempo_ratio()).ceil() as u64
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automation::{automation_log, DEFAULT_EPSILON};
+    use crate::deck::DeckCommand;
+    use crate::record::{write_wav, RecorderOptions, WavFormat};
+    use crate::session::{DeckSession, MixerState};
+    use crate::ParameterUpdate;
+    use chrono::Utc;
+    use tempfile::{tempdir, TempDir};
+
+    fn constant(value: f32, frames: usize) -> Track {
+        Track::from_interleaved(vec![value; frames * 2], 1_000)
+    }
+
+    #[test]
+    fn parses_remote_shaped_points() {
+        let points = parse_automation(
+            r#"[{"seconds":0,"cmd":"deck","deck":"A","action":"play"},
+                {"seconds":1.5,"cmd":"set_param","target":"gain","deck":"B","value":0.5}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            points[1],
+            AutomationPoint {
+                seconds: 1.5,
+                action: AutomationAction::SetParam {
+                    target: ParamTarget::Gain,
+                    deck: Some(DeckId::B),
+                    value: 0.5,
+                },
+            }
+        );
+        assert!(matches!(
+            parse_automation(r#"[{"seconds":0,"cmd":"eject_everything"}]"#),
+            Err(RenderError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn automation_lands_on_its_frame() {
+        let automation = [
+            AutomationPoint {
+                seconds: 0.0,
+                action: AutomationAction::SetParam {
+                    target: ParamTarget::Crossfader,
+                    deck: None,
+                    value: 0.0,
+                },
+            },
+            AutomationPoint {
+                seconds: 0.0,
+                action: AutomationAction::Deck {
+                    deck: DeckId::A,
+                    action: DeckAction::Play,
+                },
+            },
+            AutomationPoint {
+                seconds: 0.5,
+                action: AutomationAction::Deck {
+                    deck: DeckId::B,
+                    action: DeckAction::Play,
+                },
+            },
+            AutomationPoint {
+                seconds: 0.75,
+                action: AutomationAction::SetParam {
+                    target: ParamTarget::Crossfader,
+                    deck: None,
+                    value: 1.0,
+                },
+            },
+        ];
+        let out = render_mix([constant(0.5, 1_000), constant(0.25, 1_000)], &automation).unwrap();
+
+        // Deck B plays a full track from 0.5 s, ending the render at 1.5 s.
+        assert_eq!(out.len(), 1_500 * 2);
+        assert!(out[..1_500].iter().all(|s| *s == 0.5));
+        assert!(out[1_500..].iter().all(|s| (s - 0.25).abs() < 1e-6));
+    }
+
+    #[test]
+    fn rejects_mismatched_rates_and_deckless_gains() {
+        let other = Track::from_interleaved(vec![0.0; 20], 2_000);
+        assert!(matches!(
+            render_mix([constant(0.0, 10), other], &[]),
+            Err(RenderError::SampleRateMismatch(1_000, 2_000))
+        ));
+        let gain = AutomationPoint {
+            seconds: 0.0,
+            action: AutomationAction::SetParam {
+                target: ParamTarget::Gain,
+                deck: None,
+                value: 1.0,
+            },
+        };
+        assert!(matches!(
+            render_mix([constant(0.0, 10), constant(0.0, 10)], &[gain]),
+            Err(RenderError::Automation(..))
+        ));
+    }
+
+    #[test]
+    fn replaying_a_logged_mix_reproduces_it() {
+        let tracks = || [constant(0.5, 2_000), constant(0.25, 2_000)];
+        let (params, receiver) = parameter_channel(4);
+        let mut bus = SummingBus::new(receiver).with_sample_rate(1_000);
+        let (tap, mut log) = automation_log(4_096, DEFAULT_EPSILON);
+        bus.set_automation_tap(Some(tap));
+        let mut decks = [Deck::new(), Deck::new()];
+        for (deck, track) in decks.iter_mut().zip(tracks()) {
+            deck.load(track);
+            deck.apply(DeckCommand::Play);
+        }
+
+        let mut original = Vec::new();
+        let mut buffers = [vec![0.0; 200], vec![0.0; 200]];
+        let mut mixed = vec![0.0; 200];
+        for block in 0..20 {
+            let update = match block {
+                2 => Some(ParameterUpdate::DeckGain {
+                    deck: DeckId::A,
+                    gain: 1.5,
+                }),
+                4 => Some(ParameterUpdate::CrossfaderRamp {
+                    target: 1.0,
+                    seconds: 0.5,
+                }),
+                12 => Some(ParameterUpdate::DeckFilter {
+                    deck: DeckId::B,
+                    position: 3.0,
+                }),
+                15 => Some(ParameterUpdate::MasterGain(0.8)),
+                _ => None,
+            };
+            if let Some(update) = update {
+                params.send(update).unwrap();
+            }
+            let [deck_a, deck_b] = &mut buffers;
+            decks[0].render(deck_a);
+            decks[1].render(deck_b);
+            bus.process(deck_a, deck_b, &mut mixed);
+            original.extend_from_slice(&mixed);
+            log.drain();
+        }
+
+        let mut automation = log.to_automation(1_000);
+        assert!(automation.contains(&AutomationPoint {
+            seconds: 1.2,
+            action: AutomationAction::SetParam {
+                target: ParamTarget::Filter,
+                deck: Some(DeckId::B),
+                value: 1.0,
+            },
+        }));
+        automation.extend([DeckId::A, DeckId::B].map(|deck| AutomationPoint {
+            seconds: 0.0,
+            action: AutomationAction::Deck {
+                deck,
+                action: DeckAction::Play,
+            },
+        }));
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("automation.json");
+        save_automation(&path, &automation).unwrap();
+        let loaded = parse_automation(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(loaded, automation);
+
+        let replay = render_mix(tracks(), &loaded).unwrap();
+        assert_eq!(replay.len(), original.len());
+        // Only the decimated crossfader ramp differs, by at most the epsilon's worth of gain.
+        for (frame, (a, b)) in replay.iter().zip(&original).enumerate() {
+            assert!((a - b).abs() < 2e-3, "{frame}: {a} vs {b}");
+        }
+    }
+
+    const SET_RATE: u32 = 8_000;
+
+    /// Two one-second tones on disk, deck A playing and deck B cued, crossfader on A.
+    fn tiny_set(dir: &TempDir) -> Session {
+        let options = RecorderOptions {
+            format: WavFormat::Float32,
+            sample_rate: SET_RATE,
+            ..RecorderOptions::default()
+        };
+        let mut decks = [DeckSession::default(), DeckSession::default()];
+        for (index, (saved, hz)) in decks.iter_mut().zip([220.0, 330.0]).enumerate() {
+            let tone: Vec<f32> = (0..SET_RATE)
+                .flat_map(|frame| {
+                    let s =
+                        0.5 * (std::f32::consts::TAU * hz * frame as f32 / SET_RATE as f32).sin();
+                    [s, s]
+                })
+                .collect();
+            let path = dir.path().join(format!("track{index}.wav"));
+            write_wav(&path, &tone, &options).unwrap();
+            *saved = DeckSession {
+                path: Some(path),
+                tempo_ratio: 1.0,
+                playing: index == 0,
+                ..DeckSession::default()
+            };
+        }
+        Session {
+            saved_at: Utc::now(),
+            decks,
+            mixer: MixerState {
+                crossfader: 0.0,
+                ..MixerState::default()
+            },
+            fx: Default::default(),
+        }
+    }
+
+    /// Start deck B at frame 512 and cross over to it in quarter steps.
+    fn crossfade() -> Vec<AutomationPoint> {
+        let mut points = vec![AutomationPoint {
+            seconds: 512.0 / SET_RATE as f64,
+            action: AutomationAction::Deck {
+                deck: DeckId::B,
+                action: DeckAction::Play,
+            },
+        }];
+        points.extend((1..=4).map(|step| AutomationPoint {
+            seconds: (768 + 256 * step) as f64 / SET_RATE as f64,
+            action: AutomationAction::SetParam {
+                target: ParamTarget::Crossfader,
+                deck: None,
+                value: step as f32 / 4.0,
+            },
+        }));
+        points
+    }
+
+    #[test]
+    fn session_render_matches_a_live_simulation() {
+        let dir = tempdir().unwrap();
+        let session = tiny_set(&dir);
+        let automation = crossfade();
+        let mut seconds = Vec::new();
+        let (offline, summary) =
+            render_session(&session, &automation, |done| seconds.push(done)).unwrap();
+        // Deck B plays its full second from frame 512.
+        assert_eq!(summary.frames, 8_512);
+        assert_eq!(offline.len(), 8_512 * 2);
+        assert_eq!(*seconds.last().unwrap(), 8_512.0 / SET_RATE as f64);
+        assert!(seconds.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Live: 256-frame callbacks, with each point sent just before the block it lands on.
+        let (params, receiver) = parameter_channel(64);
+        let mut bus = SummingBus::new(receiver).with_sample_rate(SET_RATE);
+        let mut decks = [Deck::new(), Deck::new()];
+        let (chain_a, handle_a) = FxChain::new(SET_RATE, 256);
+        let (chain_b, handle_b) = FxChain::new(SET_RATE, 256);
+        bus.set_deck_fx(DeckId::A, Some(Box::new(chain_a)));
+        bus.set_deck_fx(DeckId::B, Some(Box::new(chain_b)));
+        session
+            .restore(
+                &mut decks,
+                |path| decode_track(path).map_err(|err| err.to_string()),
+                &params,
+                &[handle_a, handle_b],
+                SET_RATE,
+            )
+            .unwrap();
+        let mut live = Vec::new();
+        let mut frame = 0;
+        for block in offline.chunks(512) {
+            for point in automation
+                .iter()
+                .filter(|point| (point.seconds * SET_RATE as f64).round() as usize == frame)
+            {
+                match point.action {
+                    AutomationAction::SetParam {
+                        target,
+                        deck,
+                        value,
+                    } => params.send(target.update(deck, value).unwrap()).unwrap(),
+                    AutomationAction::Deck { deck, action } => {
+                        decks[deck.index()].apply(action.into())
+                    }
+                }
+            }
+            let mut deck_a = vec![0.0; block.len()];
+            let mut deck_b = vec![0.0; block.len()];
+            let mut mixed = vec![0.0; block.len()];
+            decks[0].render(&mut deck_a);
+            decks[1].render(&mut deck_b);
+            bus.process(&mut deck_a, &mut deck_b, &mut mixed);
+            live.extend_from_slice(&mixed);
+            frame += block.len() / 2;
+        }
+        for (index, (offline, live)) in offline.iter().zip(&live).enumerate() {
+            assert!(
+                (offline - live).abs() < 1e-6,
+                "{index}: {offline} vs {live}"
+            );
+        }
+
+        assert_eq!(
+            summary.peak,
+            offline.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+        );
+        // Two half-scale tones meet at the equal-power midpoint.
+        assert!(summary.peak <= 0.5 * std::f32::consts::SQRT_2 + 1e-6);
+        assert!(summary.loudness.integrated.is_finite());
+    }
+
+    #[test]
+    fn missing_tracks_fail_before_rendering() {
+        let dir = tempdir().unwrap();
+        let mut session = tiny_set(&dir);
+        let gone = dir.path().join("gone.flac");
+        session.decks[1].path = Some(gone.clone());
+        let mut called = false;
+        let err = render_session(&session, &crossfade(), |_| called = true).unwrap_err();
+        let RenderError::MissingTracks(missing) = &err else {
+            panic!("{err}");
+        };
+        assert_eq!(missing, &[(DeckId::B, gone)]);
+        assert!(err.to_string().contains("gone.flac"), "{err}");
+        assert!(!called);
+    }
+}
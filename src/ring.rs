@@ -0,0 +1,354 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+/// State shared between the two halves of an [`AudioRing`].
+struct Shared {
+    buffer: Box<[UnsafeCell<f32>]>,
+    channels: usize,
+    /// Total samples ever written; only the producer stores to it.
+    head: AtomicUsize,
+    /// Total samples ever read; only the consumer stores to it.
+    tail: AtomicUsize,
+    low_watermark: AtomicUsize,
+    refill_requested: AtomicBool,
+    producer_thread: OnceLock<Thread>,
+    underruns: AtomicU64,
+    underrun_frames: AtomicU64,
+}
+
+// SAFETY: each buffer slot is only written by the producer while it lies in the
+// free region and only read by the consumer while it lies in the filled region.
+// The head/tail release-acquire pairs hand slots between the two sides.
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn occupancy_samples(&self) -> usize {
+        self.head
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.tail.load(Ordering::Acquire))
+    }
+}
+
+/// Lock-free single-producer/single-consumer ring of interleaved f32 frames.
+///
+/// A decoder thread owns the [`RingProducer`] and the audio callback owns the
+/// [`RingConsumer`]. Neither side allocates or blocks after construction.
+pub struct AudioRing {
+    shared: Arc<Shared>,
+}
+
+impl AudioRing {
+    pub fn with_capacity_frames(frames: usize, channels: usize) -> Self {
+        let channels = channels.max(1);
+        let buffer = (0..frames.max(1) * channels)
+            .map(|_| UnsafeCell::new(0.0))
+            .collect();
+        Self {
+            shared: Arc::new(Shared {
+                buffer,
+                channels,
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                low_watermark: AtomicUsize::new(0),
+                refill_requested: AtomicBool::new(false),
+                producer_thread: OnceLock::new(),
+                underruns: AtomicU64::new(0),
+                underrun_frames: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Ask for a refill notification whenever occupancy drops below `frames`.
+    pub fn with_low_watermark(self, frames: usize) -> Self {
+        self.shared
+            .low_watermark
+            .store(frames * self.shared.channels, Ordering::Relaxed);
+        self
+    }
+
+    /// Split into the producer and consumer halves.
+    pub fn split(self) -> (RingProducer, RingConsumer) {
+        (
+            RingProducer {
+                shared: self.shared.clone(),
+            },
+            RingConsumer {
+                shared: self.shared,
+            },
+        )
+    }
+}
+
+/// Writing half of an [`AudioRing`], owned by the decoder thread.
+pub struct RingProducer {
+    shared: Arc<Shared>,
+}
+
+impl RingProducer {
+    /// Copy as many whole frames of `samples` as fit. Returns the number of samples written.
+    pub fn write(&mut self, samples: &[f32]) -> usize {
+        let shared = &*self.shared;
+        let capacity = shared.buffer.len();
+        let head = shared.head.load(Ordering::Relaxed);
+        let tail = shared.tail.load(Ordering::Acquire);
+        let free = capacity - head.wrapping_sub(tail);
+        let count = samples.len().min(free) / shared.channels * shared.channels;
+
+        for (offset, sample) in samples[..count].iter().enumerate() {
+            let slot = &shared.buffer[head.wrapping_add(offset) % capacity];
+            // SAFETY: the slot is in the free region, which the consumer never touches.
+            unsafe { *slot.get() = *sample };
+        }
+        shared
+            .head
+            .store(head.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// Frames currently buffered.
+    pub fn occupancy(&self) -> usize {
+        self.shared.occupancy_samples() / self.shared.channels
+    }
+
+    /// Free space in frames.
+    pub fn available(&self) -> usize {
+        self.shared.buffer.len() / self.shared.channels - self.occupancy()
+    }
+
+    pub fn channels(&self) -> usize {
+        self.shared.channels
+    }
+
+    /// Consume a pending refill request raised by the consumer.
+    pub fn take_refill_request(&self) -> bool {
+        self.shared.refill_requested.swap(false, Ordering::AcqRel)
+    }
+
+    /// Park the calling thread until the consumer asks for a refill or `timeout` passes.
+    /// Returns `true` if a refill was requested.
+    pub fn wait_for_refill(&self, timeout: Duration) -> bool {
+        let current = thread::current();
+        let registered = self.shared.producer_thread.get_or_init(|| current.clone());
+        if registered.id() == current.id() && !self.take_refill_request() {
+            thread::park_timeout(timeout);
+        }
+        self.take_refill_request()
+    }
+}
+
+/// Reading half of an [`AudioRing`], owned by the audio callback.
+pub struct RingConsumer {
+    shared: Arc<Shared>,
+}
+
+impl RingConsumer {
+    /// Fill `output` with buffered frames, padding any shortfall with silence and
+    /// counting it as an underrun. Returns the number of samples taken from the ring.
+    pub fn read(&mut self, output: &mut [f32]) -> usize {
+        let shared = &*self.shared;
+        let capacity = shared.buffer.len();
+        let tail = shared.tail.load(Ordering::Relaxed);
+        let head = shared.head.load(Ordering::Acquire);
+        let wanted = output.len() / shared.channels * shared.channels;
+        let count = wanted.min(head.wrapping_sub(tail));
+
+        for (offset, out) in output[..count].iter_mut().enumerate() {
+            let slot = &shared.buffer[tail.wrapping_add(offset) % capacity];
+            // SAFETY: the slot is in the filled region, which the producer never touches.
+            *out = unsafe { *slot.get() };
+        }
+        shared
+            .tail
+            .store(tail.wrapping_add(count), Ordering::Release);
+
+        if count < wanted {
+            output[count..].fill(0.0);
+            shared.underruns.fetch_add(1, Ordering::Relaxed);
+            shared.underrun_frames.fetch_add(
+                ((wanted - count) / shared.channels) as u64,
+                Ordering::Relaxed,
+            );
+        }
+
+        if head.wrapping_sub(tail) - count < shared.low_watermark.load(Ordering::Relaxed)
+            && !shared.refill_requested.swap(true, Ordering::AcqRel)
+        {
+            if let Some(producer) = shared.producer_thread.get() {
+                producer.unpark();
+            }
+        }
+        count
+    }
+
+    /// Frames currently buffered.
+    pub fn occupancy(&self) -> usize {
+        self.shared.occupancy_samples() / self.shared.channels
+    }
+
+    pub fn channels(&self) -> usize {
+        self.shared.channels
+    }
+
+    /// Number of reads that could not be fully satisfied.
+    pub fn underruns(&self) -> u64 {
+        self.shared.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Total frames of silence inserted because the ring ran dry.
+    pub fn underrun_frames(&self) -> u64 {
+        self.shared.underrun_frames.load(Ordering::Relaxed)
+    }
+}
+
+/// Audio-callback side of a tee: copies master blocks into a consumer thread's ring.
+pub struct RecordTap {
+    producer: RingProducer,
+    dropped_blocks: Arc<AtomicU64>,
+}
+
+impl RecordTap {
+    /// Queue one interleaved block. Blocks that do not fit whole are dropped and counted.
+    pub fn push(&mut self, samples: &[f32]) -> bool {
+        let fits = self.reserve(samples.len() / self.producer.channels());
+        if fits {
+            self.producer.write(samples);
+        }
+        fits
+    }
+
+    /// Whether a block of `frames` fits whole, counting it dropped if not. A
+    /// block that fits is then queued a piece at a time with [`write`](Self::write).
+    pub(crate) fn reserve(&mut self, frames: usize) -> bool {
+        let fits = self.producer.available() >= frames;
+        if !fits {
+            self.dropped_blocks.fetch_add(1, Ordering::Relaxed);
+        }
+        fits
+    }
+
+    /// Queue the next piece of a block [`reserve`](Self::reserve) made room for.
+    pub(crate) fn write(&mut self, samples: &[f32]) {
+        self.producer.write(samples);
+    }
+}
+
+impl std::fmt::Debug for RecordTap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordTap")
+            .field("channels", &self.producer.channels())
+            .field("dropped_blocks", &self.dropped_blocks)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Split off a tee of the master mix: the tap goes to the audio callback and
+/// the ring consumer to the thread writing the audio somewhere. The counter
+/// tracks blocks the tap had to drop.
+pub fn tee(ring_frames: usize, channels: u16) -> (RecordTap, RingConsumer, Arc<AtomicU64>) {
+    let (producer, consumer) =
+        AudioRing::with_capacity_frames(ring_frames, channels as usize).split();
+    let dropped_blocks = Arc::new(AtomicU64::new(0));
+    let tap = RecordTap {
+        producer,
+        dropped_blocks: dropped_blocks.clone(),
+    };
+    (tap, consumer, dropped_blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const TOTAL_FRAMES: usize = 200_000;
+
+    /// Stereo frames stamped with a running sequence number starting at 1.
+    fn stamped(start: usize, frames: usize) -> Vec<f32> {
+        (start..start + frames)
+            .flat_map(|n| [n as f32, -(n as f32)])
+            .collect()
+    }
+
+    /// Run a producer and consumer with the given per-block pauses and check the
+    /// consumer sees an uninterrupted sequence plus exact underrun accounting.
+    fn stress(producer_pause: Duration, consumer_pause: Duration) {
+        let (mut producer, mut consumer) = AudioRing::with_capacity_frames(1_024, 2)
+            .with_low_watermark(256)
+            .split();
+
+        let writer = thread::spawn(move || {
+            let mut next = 1;
+            while next <= TOTAL_FRAMES {
+                let block = stamped(next, 300.min(TOTAL_FRAMES + 1 - next));
+                let mut written = 0;
+                while written < block.len() {
+                    written += producer.write(&block[written..]);
+                    if written < block.len() {
+                        producer.wait_for_refill(Duration::from_millis(1));
+                    }
+                }
+                next += block.len() / 2;
+                if !producer_pause.is_zero() {
+                    thread::sleep(producer_pause);
+                }
+            }
+        });
+
+        let mut expected = 1;
+        let mut short_reads = 0;
+        let mut missing = 0;
+        let mut block = [0.0f32; 128];
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while expected <= TOTAL_FRAMES {
+            assert!(Instant::now() < deadline, "consumer stalled at {expected}");
+            let read = consumer.read(&mut block);
+            for frame in block[..read].chunks_exact(2) {
+                assert_eq!(frame[0], expected as f32);
+                assert_eq!(frame[1], -(expected as f32));
+                expected += 1;
+            }
+            assert!(block[read..].iter().all(|s| *s == 0.0));
+            if read < block.len() {
+                short_reads += 1;
+                missing += (block.len() - read) / 2;
+            }
+            if !consumer_pause.is_zero() {
+                thread::sleep(consumer_pause);
+            }
+        }
+        writer.join().unwrap();
+
+        assert_eq!(consumer.underruns(), short_reads);
+        assert_eq!(consumer.underrun_frames(), missing as u64);
+    }
+
+    #[test]
+    fn fast_producer_slow_consumer() {
+        stress(Duration::ZERO, Duration::from_micros(20));
+    }
+
+    #[test]
+    fn slow_producer_fast_consumer() {
+        stress(Duration::from_micros(50), Duration::ZERO);
+    }
+
+    #[test]
+    fn writes_only_whole_frames_and_signals_refill() {
+        let (mut producer, mut consumer) = AudioRing::with_capacity_frames(4, 2)
+            .with_low_watermark(2)
+            .split();
+        assert_eq!(producer.write(&[1.0, 2.0, 3.0]), 2);
+        assert_eq!(producer.write(&stamped(1, 10)), 6);
+        assert_eq!(producer.occupancy(), 4);
+        assert!(!producer.take_refill_request());
+
+        let mut out = [0.0; 6];
+        assert_eq!(consumer.read(&mut out), 6);
+        assert_eq!(consumer.occupancy(), 1);
+        assert!(producer.take_refill_request());
+        assert_eq!(consumer.underruns(), 0);
+    }
+}
@@ -0,0 +1,325 @@
+use std::sync::Arc;
+
+use crossbeam_queue::ArrayQueue;
+
+use crate::deck::Track;
+
+/// One-shots a sampler holds.
+pub const SAMPLER_SLOTS: usize = 8;
+/// Fade applied when a shot starts, restarts or is choked.
+const FADE_SECONDS: f32 = 0.0015;
+/// Pending sampler commands.
+const COMMAND_QUEUE_CAPACITY: usize = 64;
+
+/// Control-thread request applied by the sampler at the start of the next block.
+#[derive(Debug)]
+pub enum SamplerCommand {
+    /// Put `track` in `slot`; a trigger in a choke group stops the group's other slots.
+    Load {
+        slot: usize,
+        track: Box<Track>,
+        choke_group: Option<u32>,
+    },
+    /// Start `slot` from the top `offset` frames into the next block.
+    Trigger {
+        slot: usize,
+        offset: usize,
+    },
+    Choke {
+        slot: usize,
+    },
+    SetGain {
+        slot: usize,
+        gain: f32,
+    },
+}
+
+/// Control-side handle to a [`Sampler`] running on the audio thread.
+#[derive(Debug, Clone)]
+pub struct SamplerHandle {
+    commands: Arc<ArrayQueue<SamplerCommand>>,
+    retired: Arc<ArrayQueue<Box<Track>>>,
+}
+
+impl SamplerHandle {
+    /// Queue `command`; returns it back if the queue is full.
+    pub fn send(&self, command: SamplerCommand) -> Result<(), SamplerCommand> {
+        self.commands.push(command)
+    }
+
+    pub fn trigger(&self, slot: usize) -> bool {
+        self.send(SamplerCommand::Trigger { slot, offset: 0 })
+            .is_ok()
+    }
+
+    pub fn choke(&self, slot: usize) -> bool {
+        self.send(SamplerCommand::Choke { slot }).is_ok()
+    }
+
+    /// Drop samples the sampler has replaced, so they are never freed on the audio thread.
+    pub fn collect_retired(&self) -> usize {
+        std::iter::from_fn(|| self.retired.pop()).count()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    position: usize,
+    /// Frames to wait before the first sample.
+    delay: usize,
+    gain: f32,
+    /// Per-frame gain change: positive while fading in, negative once released.
+    step: f32,
+}
+
+impl Voice {
+    fn release(&mut self, fade_step: f32) -> bool {
+        self.step = -fade_step;
+        // A shot that has not started yet simply never plays.
+        self.delay == 0
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    track: Box<Track>,
+    gain: f32,
+    choke_group: Option<u32>,
+    /// The playing shot, and one fading out after a restart.
+    voices: [Option<Voice>; 2],
+}
+
+impl Slot {
+    fn release(&mut self, fade_step: f32) {
+        for voice in &mut self.voices {
+            if let Some(v) = voice {
+                if !v.release(fade_step) {
+                    *voice = None;
+                }
+            }
+        }
+    }
+
+    fn next_frame(&mut self) -> [f32; 2] {
+        let samples = self.track.samples();
+        let mut out = [0.0; 2];
+        for voice in &mut self.voices {
+            let Some(v) = voice else { continue };
+            if v.delay > 0 {
+                v.delay -= 1;
+                continue;
+            }
+            let index = v.position * 2;
+            if index + 1 >= samples.len() {
+                *voice = None;
+                continue;
+            }
+            let gain = v.gain * self.gain;
+            out[0] += samples[index] * gain;
+            out[1] += samples[index + 1] * gain;
+            v.position += 1;
+            v.gain = (v.gain + v.step).clamp(0.0, 1.0);
+            if v.step < 0.0 && v.gain == 0.0 {
+                *voice = None;
+            }
+        }
+        out
+    }
+}
+
+/// Bank of preloaded one-shots summed into the master after the crossfader.
+#[derive(Debug)]
+pub struct Sampler {
+    slots: [Option<Slot>; SAMPLER_SLOTS],
+    fade_step: f32,
+    commands: Arc<ArrayQueue<SamplerCommand>>,
+    retired: Arc<ArrayQueue<Box<Track>>>,
+}
+
+impl Sampler {
+    /// Create an empty sampler for tracks at `sample_rate`, and its handle.
+    pub fn new(sample_rate: u32) -> (Self, SamplerHandle) {
+        let commands = Arc::new(ArrayQueue::new(COMMAND_QUEUE_CAPACITY));
+        let retired = Arc::new(ArrayQueue::new(SAMPLER_SLOTS + COMMAND_QUEUE_CAPACITY));
+        let sampler = Self {
+            slots: Default::default(),
+            fade_step: 1.0 / (FADE_SECONDS * sample_rate as f32).max(1.0),
+            commands: commands.clone(),
+            retired: retired.clone(),
+        };
+        (sampler, SamplerHandle { commands, retired })
+    }
+
+    /// Apply queued commands; call once per block before [`next_frame`](Self::next_frame).
+    pub fn begin_block(&mut self) {
+        while let Some(command) = self.commands.pop() {
+            match command {
+                SamplerCommand::Load {
+                    slot,
+                    track,
+                    choke_group,
+                } => {
+                    let Some(target) = self.slots.get_mut(slot) else {
+                        let _ = self.retired.push(track);
+                        continue;
+                    };
+                    let old = target.replace(Slot {
+                        track,
+                        gain: 1.0,
+                        choke_group,
+                        voices: [None; 2],
+                    });
+                    if let Some(old) = old {
+                        // Only drops here if the control side stopped collecting.
+                        let _ = self.retired.push(old.track);
+                    }
+                }
+                SamplerCommand::Trigger { slot, offset } => self.trigger(slot, offset),
+                SamplerCommand::Choke { slot } => {
+                    if let Some(Some(slot)) = self.slots.get_mut(slot) {
+                        slot.release(self.fade_step);
+                    }
+                }
+                SamplerCommand::SetGain { slot, gain } => {
+                    if let Some(Some(slot)) = self.slots.get_mut(slot) {
+                        slot.gain = gain.max(0.0);
+                    }
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self, index: usize, offset: usize) {
+        let Some(Some(slot)) = self.slots.get(index) else {
+            return;
+        };
+        if let Some(group) = slot.choke_group {
+            for (other, slot) in self.slots.iter_mut().enumerate() {
+                if let Some(slot) = slot {
+                    if other != index && slot.choke_group == Some(group) {
+                        slot.release(self.fade_step);
+                    }
+                }
+            }
+        }
+        let Some(Some(slot)) = self.slots.get_mut(index) else {
+            return;
+        };
+        // Restart: the running shot fades out underneath the new one.
+        let mut previous = slot.voices[0].take();
+        if previous.as_mut().is_some_and(|v| v.release(self.fade_step)) {
+            slot.voices[1] = previous;
+        }
+        slot.voices[0] = Some(Voice {
+            position: 0,
+            delay: offset,
+            gain: 0.0,
+            step: self.fade_step,
+        });
+    }
+
+    /// Sum of every playing shot for the next frame.
+    pub fn next_frame(&mut self) -> [f32; 2] {
+        let mut out = [0.0; 2];
+        for slot in self.slots.iter_mut().flatten() {
+            let [l, r] = slot.next_frame();
+            out[0] += l;
+            out[1] += r;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn constant(value: f32, frames: usize) -> Track {
+        Track::from_interleaved(vec![value; frames * 2], SAMPLE_RATE)
+    }
+
+    fn render(sampler: &mut Sampler, frames: usize) -> Vec<f32> {
+        sampler.begin_block();
+        (0..frames).map(|_| sampler.next_frame()[0]).collect()
+    }
+
+    fn load(handle: &SamplerHandle, slot: usize, track: Track, choke_group: Option<u32>) {
+        handle
+            .send(SamplerCommand::Load {
+                slot,
+                track: Box::new(track),
+                choke_group,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn overlapping_shots_sum_with_a_short_fade_in() {
+        let (mut sampler, handle) = Sampler::new(SAMPLE_RATE);
+        load(&handle, 0, constant(0.5, 1_000), None);
+        load(&handle, 1, constant(0.25, 1_000), None);
+        handle
+            .send(SamplerCommand::SetGain { slot: 1, gain: 2.0 })
+            .unwrap();
+        assert!(handle.trigger(0));
+        handle
+            .send(SamplerCommand::Trigger {
+                slot: 1,
+                offset: 200,
+            })
+            .unwrap();
+
+        let out = render(&mut sampler, 1_400);
+        assert_eq!(out[0], 0.0);
+        assert!(out[1..72].windows(2).all(|w| w[1] > w[0]));
+        assert_eq!(out[100], 0.5);
+        assert_eq!(out[199], 0.5);
+        // The second shot starts exactly at its offset, fading in.
+        assert_eq!(out[200], 0.5);
+        assert_eq!(out[400], 1.0);
+        assert_eq!(out[1_100], 0.5);
+        assert_eq!(out[1_300], 0.0);
+    }
+
+    #[test]
+    fn choke_groups_stop_the_previous_shot() {
+        let (mut sampler, handle) = Sampler::new(SAMPLE_RATE);
+        load(&handle, 2, constant(0.5, 10_000), Some(1));
+        load(&handle, 3, constant(0.25, 10_000), Some(1));
+        load(&handle, 4, constant(0.125, 10_000), None);
+        handle.trigger(2);
+        handle.trigger(4);
+        assert_eq!(render(&mut sampler, 500)[499], 0.625);
+
+        handle.trigger(3);
+        let out = render(&mut sampler, 500);
+        // Slot 2 fades out as slot 3 fades in; slot 4 is in no group and plays on.
+        assert!(out[..72].iter().all(|s| (0.375..=0.625).contains(s)));
+        assert_eq!(out[499], 0.375);
+
+        handle.choke(3);
+        let out = render(&mut sampler, 500);
+        assert_eq!(out[499], 0.125);
+    }
+
+    #[test]
+    fn retrigger_restarts_from_the_top() {
+        let (mut sampler, handle) = Sampler::new(SAMPLE_RATE);
+        // A ramp, so the playback position can be read off the output.
+        let ramp: Vec<f32> = (0..2_000).flat_map(|i| [i as f32, i as f32]).collect();
+        load(&handle, 0, Track::from_interleaved(ramp, SAMPLE_RATE), None);
+        handle.trigger(0);
+        assert_eq!(render(&mut sampler, 1_000)[999], 999.0);
+
+        handle.trigger(0);
+        let out = render(&mut sampler, 1_000);
+        // The old shot is gone after the fade and the new one is 900 frames in.
+        assert_eq!(out[900], 900.0);
+        assert_eq!(handle.collect_retired(), 0);
+        load(&handle, 0, constant(0.0, 10), None);
+        render(&mut sampler, 1);
+        assert_eq!(handle.collect_retired(), 1);
+    }
+}
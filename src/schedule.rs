@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use crossbeam_queue::ArrayQueue;
+
+use crate::ParameterUpdate;
+
+/// A parameter update to land on a given output frame of the bus.
+#[derive(Debug, Clone)]
+pub struct ScheduledUpdate {
+    /// Output frame, counted from the first the bus mixed, the update applies
+    /// from; frames already mixed apply it at the start of the next block.
+    pub frame: u64,
+    pub update: ParameterUpdate,
+}
+
+/// Control-side handle to a schedule queue.
+#[derive(Debug, Clone)]
+pub struct ScheduleSender {
+    queue: Arc<ArrayQueue<ScheduledUpdate>>,
+}
+
+impl ScheduleSender {
+    /// Queue `update` to land on output `frame`. Updates land in the order
+    /// they are sent, so send them in frame order: one sent behind a later
+    /// frame waits for it. Returns `Err` if the queue is full.
+    pub fn send(&self, frame: u64, update: ParameterUpdate) -> Result<(), ScheduledUpdate> {
+        self.queue.push(ScheduledUpdate { frame, update })
+    }
+}
+
+/// Audio-thread side of a schedule queue, handed to
+/// [`SummingBus::set_schedule`](crate::SummingBus::set_schedule).
+#[derive(Debug)]
+pub struct ScheduleReceiver {
+    queue: Arc<ArrayQueue<ScheduledUpdate>>,
+}
+
+impl ScheduleReceiver {
+    pub(crate) fn pop(&self) -> Option<ScheduledUpdate> {
+        self.queue.pop()
+    }
+}
+
+/// Create a bounded, lock-free queue of updates timed to the frame, for
+/// automation that must not wait for a block boundary.
+pub fn schedule_channel(capacity: usize) -> (ScheduleSender, ScheduleReceiver) {
+    let queue = Arc::new(ArrayQueue::new(capacity));
+    (
+        ScheduleSender {
+            queue: queue.clone(),
+        },
+        ScheduleReceiver { queue },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, DeckId, SummingBus};
+
+    fn gain(deck: DeckId, gain: f32) -> ParameterUpdate {
+        ParameterUpdate::DeckGain { deck, gain }
+    }
+
+    #[test]
+    fn gain_change_lands_on_the_exact_frame() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (schedule, receiver) = schedule_channel(8);
+        bus.set_schedule(Some(receiver));
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        schedule.send(100, gain(DeckId::A, 0.5)).unwrap();
+        // Past this block, held for the next.
+        schedule.send(300, gain(DeckId::A, 0.25)).unwrap();
+
+        let mut out = vec![0.0; 512];
+        bus.mix_stereo(&[1.0; 512], &[0.0; 512], &mut out);
+        assert!(out[..200].iter().all(|&s| s == 1.0));
+        assert!(out[200..].iter().all(|&s| s == 0.5));
+        bus.mix_stereo(&[1.0; 512], &[0.0; 512], &mut out);
+        assert!(out[..88].iter().all(|&s| s == 0.5));
+        assert!(out[88..].iter().all(|&s| s == 0.25));
+        assert_eq!(bus.frame(), 512);
+
+        // Already past, it lands at the start of the next block.
+        schedule.send(10, gain(DeckId::A, 1.0)).unwrap();
+        bus.mix_stereo(&[1.0; 8], &[0.0; 8], &mut out[..8]);
+        assert!(out[..8].iter().all(|&s| s == 1.0));
+    }
+
+    #[test]
+    fn several_updates_split_one_block() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (schedule, receiver) = schedule_channel(8);
+        bus.set_schedule(Some(receiver));
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        for (frame, master) in [(1, 0.5), (2, 0.25), (2, 0.125), (5, 2.0)] {
+            schedule
+                .send(frame, ParameterUpdate::MasterGain(master))
+                .unwrap();
+        }
+        let mut out = [0.0; 12];
+        bus.mix_stereo(&[1.0; 12], &[0.0; 12], &mut out);
+        let left: Vec<f32> = out.iter().step_by(2).copied().collect();
+        assert_eq!(left, [1.0, 0.5, 0.125, 0.125, 0.125, 2.0]);
+    }
+}
@@ -0,0 +1,903 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::deck::{Deck, LoopRegion, Track, TrackMarkers};
+use crate::eq::EqBand;
+use crate::fx::{
+    ChainCommand, DelayFx, FilterFx, FlangerFx, Fx, FxChainHandle, PhaserFx, ReverbFx,
+};
+use crate::limiter::LimiterOptions;
+use crate::softclip::SoftClip;
+use crate::{CrossfaderCurve, DeckId, FaderTaper, ParameterSender, ParameterUpdate, XfAssign};
+
+/// Default time between periodic session snapshots.
+pub const DEFAULT_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("failed to access session file: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse session file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("parameter queue is full")]
+    QueueFull,
+    #[error("effect chain queue for deck {0:?} is full")]
+    ChainFull(DeckId),
+}
+
+/// Control-side copy of every summing bus parameter, kept by applying the
+/// updates sent to the bus.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MixerState {
+    /// Per deck, as many as updates have been sent for and at least A and B.
+    #[serde(default = "unity_trims")]
+    pub deck_trims: Vec<f32>,
+    pub deck_gains: Vec<f32>,
+    #[serde(default = "default_decks")]
+    pub fader_tapers: Vec<FaderTaper>,
+    #[serde(default = "default_decks")]
+    pub deck_mutes: Vec<bool>,
+    #[serde(default = "default_decks")]
+    pub deck_solos: Vec<bool>,
+    #[serde(default = "default_decks")]
+    pub deck_cues: Vec<bool>,
+    #[serde(default)]
+    pub cue_mix: f32,
+    #[serde(default = "unity")]
+    pub cue_gain: f32,
+    pub crossfader: f32,
+    #[serde(default)]
+    pub crossfader_curve: CrossfaderCurve,
+    #[serde(default)]
+    pub crossfader_sharpness: f32,
+    #[serde(default)]
+    pub crossfader_reverse: bool,
+    #[serde(default = "default_crossfader_assign")]
+    pub crossfader_assign: Vec<XfAssign>,
+    pub master_gain: f32,
+    #[serde(default = "unity")]
+    pub booth_gain: f32,
+    #[serde(default = "unity")]
+    pub stereo_width: f32,
+    #[serde(default)]
+    pub mono_output: bool,
+    /// Per deck, the low, mid and high band in dB.
+    #[serde(default = "default_decks")]
+    pub eq_gains_db: Vec<[f32; 3]>,
+    #[serde(default = "default_decks")]
+    pub eq_kills: Vec<[bool; 3]>,
+    pub filter_positions: Vec<f32>,
+    pub filter_resonances: Vec<f32>,
+    #[serde(default = "default_decks")]
+    pub dc_blocks: Vec<bool>,
+    /// Per deck, left and right inverted.
+    #[serde(default = "default_decks")]
+    pub phase_inverts: Vec<[bool; 2]>,
+    /// Applied only while the bus has a limiter.
+    #[serde(default = "default_limiter_ceiling_db")]
+    pub limiter_ceiling_db: f32,
+    #[serde(default = "default_limiter_release_ms")]
+    pub limiter_release_ms: f32,
+    #[serde(default)]
+    pub soft_clip: bool,
+    #[serde(default = "default_soft_clip_drive")]
+    pub soft_clip_drive: f32,
+    pub tempo: Option<f32>,
+    pub mic_gain: f32,
+    #[serde(default)]
+    pub talkover: bool,
+    pub mic_low_cut_hz: f32,
+    pub talkover_threshold_db: f32,
+    pub talkover_depth_db: f32,
+}
+
+fn unity_trims() -> Vec<f32> {
+    vec![1.0; 2]
+}
+
+fn default_decks<T: Default + Clone>() -> Vec<T> {
+    vec![T::default(); 2]
+}
+
+fn unity() -> f32 {
+    1.0
+}
+
+fn default_crossfader_assign() -> Vec<XfAssign> {
+    vec![DeckId::A, DeckId::B]
+        .into_iter()
+        .map(XfAssign::default_for)
+        .collect()
+}
+
+fn default_limiter_ceiling_db() -> f32 {
+    LimiterOptions::default().ceiling_db
+}
+
+fn default_limiter_release_ms() -> f32 {
+    LimiterOptions::default().release_seconds * 1_000.0
+}
+
+fn default_soft_clip_drive() -> f32 {
+    SoftClip::default().drive()
+}
+
+impl Default for MixerState {
+    /// The bus as it starts up.
+    fn default() -> Self {
+        Self {
+            deck_trims: unity_trims(),
+            deck_gains: vec![1.0; 2],
+            fader_tapers: default_decks(),
+            deck_mutes: default_decks(),
+            deck_solos: default_decks(),
+            deck_cues: default_decks(),
+            cue_mix: 0.0,
+            cue_gain: 1.0,
+            crossfader: 0.5,
+            crossfader_curve: CrossfaderCurve::EqualPower,
+            crossfader_sharpness: 0.0,
+            crossfader_reverse: false,
+            crossfader_assign: default_crossfader_assign(),
+            master_gain: 1.0,
+            booth_gain: 1.0,
+            stereo_width: 1.0,
+            mono_output: false,
+            eq_gains_db: default_decks(),
+            eq_kills: default_decks(),
+            filter_positions: default_decks(),
+            filter_resonances: default_decks(),
+            dc_blocks: default_decks(),
+            phase_inverts: default_decks(),
+            limiter_ceiling_db: default_limiter_ceiling_db(),
+            limiter_release_ms: default_limiter_release_ms(),
+            soft_clip: false,
+            soft_clip_drive: default_soft_clip_drive(),
+            tempo: None,
+            mic_gain: 1.0,
+            talkover: false,
+            mic_low_cut_hz: 100.0,
+            talkover_threshold_db: -30.0,
+            talkover_depth_db: -12.0,
+        }
+    }
+}
+
+impl MixerState {
+    /// Follow an update sent to the bus. Ramps are recorded at their target, and
+    /// effect parameters are tracked by [`ChainState`] instead; those of deck
+    /// inserts, which a session doesn't hold, are skipped. An update for a deck
+    /// past those held so far adds it, and any between, as the bus starts them.
+    pub fn apply(&mut self, update: &ParameterUpdate) {
+        if let Some(deck) = update.deck() {
+            self.add_decks(deck.index() + 1);
+        }
+        match *update {
+            ParameterUpdate::DeckGain { deck, gain } => self.deck_gains[deck.index()] = gain,
+            // Kept as the gain the taper gives, which the bus arrives at too.
+            ParameterUpdate::DeckFader { deck, position } => {
+                if !position.is_nan() {
+                    self.deck_gains[deck.index()] = self.fader_tapers[deck.index()].gain(position)
+                }
+            }
+            ParameterUpdate::DeckFaderTaper { deck, taper } => {
+                self.fader_tapers[deck.index()] = taper
+            }
+            ParameterUpdate::DeckMute { deck, on } => self.deck_mutes[deck.index()] = on,
+            ParameterUpdate::DeckSolo { deck, on } => self.deck_solos[deck.index()] = on,
+            ParameterUpdate::DeckCue { deck, on } => self.deck_cues[deck.index()] = on,
+            ParameterUpdate::CueMix(mix) => self.cue_mix = mix,
+            ParameterUpdate::CueGain(gain) => self.cue_gain = gain,
+            ParameterUpdate::DeckTrim { deck, gain } => self.deck_trims[deck.index()] = gain,
+            ParameterUpdate::Crossfader(value) => self.crossfader = value,
+            ParameterUpdate::CrossfaderRamp { target, .. }
+            | ParameterUpdate::CrossfadeTo { target, .. } => self.crossfader = target,
+            ParameterUpdate::CrossfaderCurve(curve) => self.crossfader_curve = curve,
+            ParameterUpdate::CrossfaderSharpness(sharpness) => {
+                self.crossfader_sharpness = sharpness
+            }
+            ParameterUpdate::CrossfaderReverse(reverse) => self.crossfader_reverse = reverse,
+            ParameterUpdate::CrossfaderAssign { deck, assign } => {
+                self.crossfader_assign[deck.index()] = assign
+            }
+            ParameterUpdate::MasterGain(gain) => self.master_gain = gain,
+            ParameterUpdate::BoothGain(gain) => self.booth_gain = gain,
+            ParameterUpdate::StereoWidth(width) => self.stereo_width = width,
+            ParameterUpdate::MonoOutput(on) => self.mono_output = on,
+            ParameterUpdate::DeckEffect { .. }
+            | ParameterUpdate::FxDelayTime { .. }
+            | ParameterUpdate::FxDelayFeedback { .. }
+            | ParameterUpdate::FxDelayMix { .. }
+            | ParameterUpdate::FxReverbRoomSize { .. }
+            | ParameterUpdate::FxReverbDamping { .. }
+            | ParameterUpdate::FxReverbMix { .. }
+            | ParameterUpdate::FxFlangerRate { .. }
+            | ParameterUpdate::FxFlangerDepth { .. }
+            | ParameterUpdate::FxFlangerFeedback { .. }
+            | ParameterUpdate::FxFlangerStereoPhase { .. } => {}
+            ParameterUpdate::DeckEq {
+                deck,
+                band,
+                gain_db,
+            } => self.eq_gains_db[deck.index()][band as usize] = gain_db,
+            ParameterUpdate::DeckEqKill { deck, band, on } => {
+                self.eq_kills[deck.index()][band as usize] = on
+            }
+            ParameterUpdate::DeckFilter { deck, position } => {
+                self.filter_positions[deck.index()] = position
+            }
+            ParameterUpdate::DeckFilterResonance { deck, resonance } => {
+                self.filter_resonances[deck.index()] = resonance
+            }
+            ParameterUpdate::DcBlock { deck, on } => self.dc_blocks[deck.index()] = on,
+            ParameterUpdate::DeckPhaseInvert { deck, left, right } => {
+                self.phase_inverts[deck.index()] = [left, right]
+            }
+            ParameterUpdate::Tempo(bpm) => self.tempo = bpm,
+            ParameterUpdate::LimiterCeilingDb(db) => self.limiter_ceiling_db = db,
+            ParameterUpdate::LimiterReleaseMs(ms) => self.limiter_release_ms = ms,
+            ParameterUpdate::MasterSoftClip(on) => self.soft_clip = on,
+            ParameterUpdate::MasterSoftClipDrive(drive) => self.soft_clip_drive = drive,
+            ParameterUpdate::MicGain(gain) => self.mic_gain = gain,
+            ParameterUpdate::Talkover(on) => self.talkover = on,
+            ParameterUpdate::MicLowCutHz(hz) => self.mic_low_cut_hz = hz,
+            ParameterUpdate::TalkoverThresholdDb(db) => self.talkover_threshold_db = db,
+            ParameterUpdate::TalkoverDepthDb(db) => self.talkover_depth_db = db,
+            // A restored session never starts recording by itself.
+            ParameterUpdate::Record(_)
+            | ParameterUpdate::ResetClipIndicators
+            | ParameterUpdate::ResetLoudness
+            | ParameterUpdate::RequestSnapshot
+            | ParameterUpdate::BeginBatch
+            | ParameterUpdate::CommitBatch => {}
+        }
+    }
+
+    /// Decks held, counting any only some of the per-deck values cover.
+    pub fn decks(&self) -> usize {
+        [
+            self.deck_trims.len(),
+            self.deck_gains.len(),
+            self.fader_tapers.len(),
+            self.deck_mutes.len(),
+            self.deck_solos.len(),
+            self.deck_cues.len(),
+            self.crossfader_assign.len(),
+            self.eq_gains_db.len(),
+            self.eq_kills.len(),
+            self.filter_positions.len(),
+            self.filter_resonances.len(),
+            self.dc_blocks.len(),
+            self.phase_inverts.len(),
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0)
+    }
+
+    /// Grow the per-deck values to at least `decks`, each new one as the bus
+    /// starts it.
+    fn add_decks(&mut self, decks: usize) {
+        fn grow<T: Clone>(values: &mut Vec<T>, decks: usize, value: T) {
+            if values.len() < decks {
+                values.resize(decks, value);
+            }
+        }
+        grow(&mut self.deck_trims, decks, 1.0);
+        grow(&mut self.deck_gains, decks, 1.0);
+        grow(&mut self.fader_tapers, decks, FaderTaper::default());
+        grow(&mut self.deck_mutes, decks, false);
+        grow(&mut self.deck_solos, decks, false);
+        grow(&mut self.deck_cues, decks, false);
+        while self.crossfader_assign.len() < decks {
+            let deck = DeckId(self.crossfader_assign.len());
+            self.crossfader_assign.push(XfAssign::default_for(deck));
+        }
+        grow(&mut self.eq_gains_db, decks, [0.0; 3]);
+        grow(&mut self.eq_kills, decks, [false; 3]);
+        grow(&mut self.filter_positions, decks, 0.0);
+        grow(&mut self.filter_resonances, decks, 0.0);
+        grow(&mut self.dc_blocks, decks, false);
+        grow(&mut self.phase_inverts, decks, [false; 2]);
+    }
+
+    /// Which decks the bus lets through, for lighting mute and solo buttons:
+    /// the soloed ones while any is soloed, otherwise the unmuted ones.
+    pub fn audible_decks(&self) -> Vec<bool> {
+        if self.deck_solos.contains(&true) {
+            self.deck_solos.clone()
+        } else {
+            self.deck_mutes.iter().map(|muted| !muted).collect()
+        }
+    }
+
+    /// Updates that bring a freshly started bus, with as many decks, to this
+    /// state.
+    pub fn updates(&self) -> Vec<ParameterUpdate> {
+        // Lists short of the longest take on the values the bus starts with.
+        let mut state = self.clone();
+        state.add_decks(self.decks());
+        let mut updates = vec![
+            ParameterUpdate::Crossfader(state.crossfader),
+            ParameterUpdate::CrossfaderCurve(state.crossfader_curve),
+            ParameterUpdate::CrossfaderSharpness(state.crossfader_sharpness),
+            ParameterUpdate::CrossfaderReverse(state.crossfader_reverse),
+            ParameterUpdate::MasterGain(state.master_gain),
+            ParameterUpdate::BoothGain(state.booth_gain),
+            ParameterUpdate::StereoWidth(state.stereo_width),
+            ParameterUpdate::MonoOutput(state.mono_output),
+            ParameterUpdate::CueMix(state.cue_mix),
+            ParameterUpdate::CueGain(state.cue_gain),
+            ParameterUpdate::Tempo(state.tempo),
+            ParameterUpdate::LimiterCeilingDb(state.limiter_ceiling_db),
+            ParameterUpdate::LimiterReleaseMs(state.limiter_release_ms),
+            ParameterUpdate::MasterSoftClip(state.soft_clip),
+            ParameterUpdate::MasterSoftClipDrive(state.soft_clip_drive),
+            ParameterUpdate::MicGain(state.mic_gain),
+            ParameterUpdate::Talkover(state.talkover),
+            ParameterUpdate::MicLowCutHz(state.mic_low_cut_hz),
+            ParameterUpdate::TalkoverThresholdDb(state.talkover_threshold_db),
+            ParameterUpdate::TalkoverDepthDb(state.talkover_depth_db),
+        ];
+        for index in 0..state.decks() {
+            let deck = DeckId(index);
+            updates.extend([
+                ParameterUpdate::DeckTrim {
+                    deck,
+                    gain: state.deck_trims[index],
+                },
+                ParameterUpdate::DeckFaderTaper {
+                    deck,
+                    taper: state.fader_tapers[index],
+                },
+                ParameterUpdate::DeckGain {
+                    deck,
+                    gain: state.deck_gains[index],
+                },
+                ParameterUpdate::DeckMute {
+                    deck,
+                    on: state.deck_mutes[index],
+                },
+                ParameterUpdate::DeckSolo {
+                    deck,
+                    on: state.deck_solos[index],
+                },
+                ParameterUpdate::DeckCue {
+                    deck,
+                    on: state.deck_cues[index],
+                },
+                ParameterUpdate::CrossfaderAssign {
+                    deck,
+                    assign: state.crossfader_assign[index],
+                },
+                ParameterUpdate::DeckFilter {
+                    deck,
+                    position: state.filter_positions[index],
+                },
+                ParameterUpdate::DeckFilterResonance {
+                    deck,
+                    resonance: state.filter_resonances[index],
+                },
+                ParameterUpdate::DcBlock {
+                    deck,
+                    on: state.dc_blocks[index],
+                },
+                ParameterUpdate::DeckPhaseInvert {
+                    deck,
+                    left: state.phase_inverts[index][0],
+                    right: state.phase_inverts[index][1],
+                },
+            ]);
+            updates.extend(EqBand::ALL.map(|band| ParameterUpdate::DeckEq {
+                deck,
+                band,
+                gain_db: state.eq_gains_db[index][band as usize],
+            }));
+            updates.extend(EqBand::ALL.map(|band| ParameterUpdate::DeckEqKill {
+                deck,
+                band,
+                on: state.eq_kills[index][band as usize],
+            }));
+        }
+        updates
+    }
+}
+
+/// Effects that can be rebuilt from a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FxKind {
+    Delay,
+    Filter,
+    Flanger,
+    Phaser,
+    Reverb,
+}
+
+impl FxKind {
+    pub fn build(self, sample_rate: u32) -> Box<dyn Fx> {
+        match self {
+            FxKind::Delay => Box::new(DelayFx::new(sample_rate)),
+            FxKind::Filter => Box::new(FilterFx::new(sample_rate)),
+            FxKind::Flanger => Box::new(FlangerFx::new(sample_rate)),
+            FxKind::Phaser => Box::new(PhaserFx::new(sample_rate)),
+            FxKind::Reverb => Box::new(ReverbFx::new(sample_rate)),
+        }
+    }
+}
+
+/// One occupied effect slot and the parameters set on it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FxSlotState {
+    pub kind: FxKind,
+    pub enabled: bool,
+    #[serde(default)]
+    pub params: BTreeMap<u32, f32>,
+}
+
+/// Control-side description of a deck's effect chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainState {
+    pub wet: f32,
+    /// Slot contents by index; `None` for an empty slot.
+    pub slots: Vec<Option<FxSlotState>>,
+}
+
+impl Default for ChainState {
+    fn default() -> Self {
+        Self {
+            wet: 1.0,
+            slots: Vec::new(),
+        }
+    }
+}
+
+impl ChainState {
+    /// Commands that rebuild this chain on an empty [`crate::fx::FxChain`].
+    pub fn commands(&self, sample_rate: u32) -> Vec<ChainCommand> {
+        let mut commands = vec![ChainCommand::Wet(self.wet)];
+        for (slot, state) in self.slots.iter().enumerate() {
+            let Some(state) = state else {
+                continue;
+            };
+            commands.push(ChainCommand::Insert {
+                slot,
+                fx: state.kind.build(sample_rate),
+            });
+            commands.extend(
+                state
+                    .params
+                    .iter()
+                    .map(|(&id, &value)| ChainCommand::SetParam { slot, id, value }),
+            );
+            commands.push(ChainCommand::SetEnabled {
+                slot,
+                enabled: state.enabled,
+            });
+        }
+        commands
+    }
+}
+
+/// What was on a deck and where it was.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeckSession {
+    /// File the track was loaded from; `None` for an empty deck.
+    pub path: Option<PathBuf>,
+    pub position_frames: f64,
+    pub playing: bool,
+    pub tempo_ratio: f64,
+    pub trim_db: f32,
+    #[serde(default)]
+    pub markers: TrackMarkers,
+    #[serde(default)]
+    pub active_loop: Option<LoopRegion>,
+}
+
+impl DeckSession {
+    /// Capture `deck`, whose track came from `path`.
+    pub fn capture(deck: &Deck, path: Option<PathBuf>) -> Self {
+        Self {
+            path: deck.track().and(path),
+            position_frames: deck.position(),
+            playing: deck.is_playing(),
+            tempo_ratio: deck.tempo_ratio(),
+            trim_db: deck.trim_db(),
+            markers: deck.markers().cloned().unwrap_or_default(),
+            active_loop: deck.active_loop(),
+        }
+    }
+}
+
+/// Everything needed to pick a set back up after a crash or power cut.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub saved_at: DateTime<Utc>,
+    pub decks: [DeckSession; 2],
+    pub mixer: MixerState,
+    #[serde(default)]
+    pub fx: [ChainState; 2],
+}
+
+/// Restored decks whose track could not be loaded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestoreReport {
+    pub missing: Vec<(DeckId, PathBuf)>,
+}
+
+impl Session {
+    pub fn default_path() -> PathBuf {
+        Path::new("session.json").to_path_buf()
+    }
+
+    /// Write through a temporary file and rename it over `path`, so a crash
+    /// mid-write leaves the previous snapshot intact.
+    pub fn save(&self, path: &Path) -> Result<(), SessionError> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        fs::write(&temp, serde_json::to_string_pretty(self)?)?;
+        fs::File::open(&temp)?.sync_all()?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SessionError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Put the session back: reload each deck's track through `load`, seek, loop and
+    /// play as saved, queue the mixer parameters as one batch and rebuild the effect chains.
+    ///
+    /// A deck whose track fails to load is left empty and listed in the report.
+    pub fn restore<F>(
+        &self,
+        decks: &mut [Deck; 2],
+        mut load: F,
+        params: &ParameterSender,
+        chains: &[FxChainHandle; 2],
+        sample_rate: u32,
+    ) -> Result<RestoreReport, SessionError>
+    where
+        F: FnMut(&Path) -> Result<Track, String>,
+    {
+        let mut report = RestoreReport::default();
+        for (id, (deck, saved)) in [DeckId::A, DeckId::B]
+            .into_iter()
+            .zip(decks.iter_mut().zip(&self.decks))
+        {
+            let Some(path) = &saved.path else {
+                continue;
+            };
+            match load(path) {
+                Ok(track) => restore_deck(deck, track, saved),
+                Err(_) => report.missing.push((id, path.clone())),
+            }
+        }
+
+        params
+            .send_batch(&self.mixer.updates())
+            .map_err(|_| SessionError::QueueFull)?;
+        for (id, (chain, state)) in [DeckId::A, DeckId::B]
+            .into_iter()
+            .zip(chains.iter().zip(&self.fx))
+        {
+            for command in state.commands(sample_rate) {
+                chain
+                    .send(command)
+                    .map_err(|_| SessionError::ChainFull(id))?;
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn restore_deck(deck: &mut Deck, track: Track, saved: &DeckSession) {
+    deck.load(track.with_markers(saved.markers.clone()));
+    deck.seek(saved.position_frames.max(0.0) as u64);
+    deck.set_tempo_ratio(saved.tempo_ratio);
+    deck.set_trim_db(saved.trim_db);
+    if let Some(region) = saved.active_loop {
+        // A loop that no longer fits the file is dropped rather than failing the restore.
+        let _ = deck.enable_loop(region.start, region.end);
+    }
+    if saved.playing {
+        deck.play();
+    }
+}
+
+/// Decides when to snapshot: every interval, and straight after significant events.
+#[derive(Debug, Clone)]
+pub struct Autosave {
+    path: PathBuf,
+    interval: Duration,
+    last_saved: Option<Instant>,
+    event_pending: bool,
+}
+
+impl Autosave {
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+            last_saved: None,
+            event_pending: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Ask for a snapshot at the next check, e.g. after a track load.
+    pub fn note_event(&mut self) {
+        self.event_pending = true;
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.event_pending
+            || self
+                .last_saved
+                .is_none_or(|last| now.saturating_duration_since(last) >= self.interval)
+    }
+
+    /// Save `session` if a snapshot is due; returns whether one was written.
+    pub fn save_if_due(&mut self, session: &Session, now: Instant) -> Result<bool, SessionError> {
+        if !self.is_due(now) {
+            return Ok(false);
+        }
+        session.save(&self.path)?;
+        self.last_saved = Some(now);
+        self.event_pending = false;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::{BeatGrid, HotCues};
+    use crate::fx::FxChain;
+    use crate::parameter_channel;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn session() -> Session {
+        let mut hot_cues = HotCues::default();
+        assert!(hot_cues.set(0, 12_000));
+        assert!(hot_cues.set(3, 40_000));
+        let markers = TrackMarkers {
+            hot_cues,
+            beat_grid: Some(BeatGrid::new(126.0, 0.0, 48_000)),
+            gain: None,
+        };
+        let mut mixer = MixerState::default();
+        for update in [
+            ParameterUpdate::Crossfader(0.8),
+            ParameterUpdate::CrossfaderCurve(CrossfaderCurve::SharpCut),
+            ParameterUpdate::CrossfaderSharpness(0.3),
+            ParameterUpdate::CrossfaderReverse(true),
+            ParameterUpdate::DeckGain {
+                deck: DeckId::B,
+                gain: 0.7,
+            },
+            ParameterUpdate::DeckFaderTaper {
+                deck: DeckId::B,
+                taper: FaderTaper::Linear,
+            },
+            ParameterUpdate::DeckTrim {
+                deck: DeckId::A,
+                gain: 1.4,
+            },
+            ParameterUpdate::DeckMute {
+                deck: DeckId::B,
+                on: true,
+            },
+            ParameterUpdate::MasterGain(0.9),
+            ParameterUpdate::BoothGain(0.6),
+            ParameterUpdate::StereoWidth(1.4),
+            ParameterUpdate::MonoOutput(true),
+            ParameterUpdate::MasterSoftClip(true),
+            ParameterUpdate::MasterSoftClipDrive(0.8),
+            ParameterUpdate::DeckCue {
+                deck: DeckId::A,
+                on: true,
+            },
+            ParameterUpdate::CueMix(0.25),
+            ParameterUpdate::CueGain(0.8),
+            ParameterUpdate::CrossfaderAssign {
+                deck: DeckId::A,
+                assign: XfAssign::Thru,
+            },
+            ParameterUpdate::DeckFilter {
+                deck: DeckId::A,
+                position: -0.3,
+            },
+            ParameterUpdate::DeckFilterResonance {
+                deck: DeckId::A,
+                resonance: 0.4,
+            },
+            ParameterUpdate::DcBlock {
+                deck: DeckId::B,
+                on: true,
+            },
+            ParameterUpdate::DeckPhaseInvert {
+                deck: DeckId::A,
+                left: false,
+                right: true,
+            },
+            ParameterUpdate::DeckEq {
+                deck: DeckId::B,
+                band: EqBand::Low,
+                gain_db: -26.0,
+            },
+            ParameterUpdate::DeckEqKill {
+                deck: DeckId::A,
+                band: EqBand::High,
+                on: true,
+            },
+            ParameterUpdate::Tempo(Some(126.0)),
+            ParameterUpdate::MicGain(0.5),
+            ParameterUpdate::Talkover(true),
+            ParameterUpdate::MicLowCutHz(150.0),
+            ParameterUpdate::TalkoverThresholdDb(-24.0),
+            ParameterUpdate::TalkoverDepthDb(-9.0),
+        ] {
+            mixer.apply(&update);
+        }
+        let delay = FxSlotState {
+            kind: FxKind::Delay,
+            enabled: true,
+            params: BTreeMap::from([(DelayFx::FEEDBACK, 0.6), (DelayFx::WET, 0.3)]),
+        };
+        Session {
+            saved_at: Utc.with_ymd_and_hms(2026, 5, 1, 23, 15, 0).unwrap(),
+            decks: [
+                DeckSession {
+                    path: Some("/music/playing.flac".into()),
+                    position_frames: 24_000.0,
+                    playing: true,
+                    tempo_ratio: 1.04,
+                    trim_db: -3.0,
+                    markers,
+                    active_loop: Some(LoopRegion {
+                        start: 20_000,
+                        end: 30_000,
+                    }),
+                },
+                DeckSession {
+                    path: Some("/music/deleted.mp3".into()),
+                    position_frames: 1_000.0,
+                    tempo_ratio: 1.0,
+                    ..DeckSession::default()
+                },
+            ],
+            mixer,
+            fx: [
+                ChainState {
+                    wet: 0.75,
+                    slots: vec![None, Some(delay)],
+                },
+                ChainState::default(),
+            ],
+        }
+    }
+
+    #[test]
+    fn restores_every_field_and_reports_missing_tracks() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let saved = session();
+        saved.save(&path).unwrap();
+        assert!(!dir.path().join("session.json.tmp").exists());
+        let loaded = Session::load(&path).unwrap();
+        assert_eq!(loaded, saved);
+
+        let (params, bus) = parameter_channel(64);
+        let (_chain_a, chain_a) = FxChain::new(48_000, 256);
+        let (_chain_b, chain_b) = FxChain::new(48_000, 256);
+        let mut decks = [Deck::new(), Deck::new()];
+        let report = loaded
+            .restore(
+                &mut decks,
+                |path| match path.to_str() {
+                    Some("/music/playing.flac") => {
+                        Ok(Track::from_interleaved(vec![0.0; 96_000], 48_000))
+                    }
+                    _ => Err("no such file".into()),
+                },
+                &params,
+                &[chain_a, chain_b],
+                48_000,
+            )
+            .unwrap();
+        assert_eq!(
+            report.missing,
+            [(DeckId::B, PathBuf::from("/music/deleted.mp3"))]
+        );
+
+        let restored = DeckSession::capture(&decks[0], Some("/music/playing.flac".into()));
+        assert_eq!(restored, saved.decks[0]);
+        assert!(decks[1].track().is_none());
+
+        // Replaying what reached the bus gives back the saved mixer.
+        let mut mixer = MixerState::default();
+        while let Some(update) = bus.pop() {
+            mixer.apply(&update);
+        }
+        assert_eq!(mixer, saved.mixer);
+    }
+
+    #[test]
+    fn follows_and_restores_decks_past_b() {
+        let mut mixer = MixerState::default();
+        mixer.apply(&ParameterUpdate::DeckGain {
+            deck: DeckId(3),
+            gain: 0.4,
+        });
+        mixer.apply(&ParameterUpdate::DeckFilter {
+            deck: DeckId(2),
+            position: 0.6,
+        });
+        assert_eq!(mixer.decks(), 4);
+        assert_eq!(mixer.deck_gains, [1.0, 1.0, 1.0, 0.4]);
+        assert_eq!(mixer.filter_positions, [0.0, 0.0, 0.6, 0.0]);
+        assert_eq!(mixer.crossfader_assign[2..], [XfAssign::Thru; 2]);
+
+        let json = serde_json::to_string(&mixer).unwrap();
+        let loaded: MixerState = serde_json::from_str(&json).unwrap();
+        let mut restored = MixerState::default();
+        for update in loaded.updates() {
+            restored.apply(&update);
+        }
+        assert_eq!(restored, mixer);
+    }
+
+    #[test]
+    fn chain_state_rebuilds_slots_in_order() {
+        let commands = session().fx[0].commands(48_000);
+        let summary: Vec<String> = commands
+            .iter()
+            .map(|command| match command {
+                ChainCommand::Wet(wet) => format!("wet {wet}"),
+                ChainCommand::Insert { slot, .. } => format!("insert {slot}"),
+                ChainCommand::SetParam { slot, id, value } => format!("{slot}.{id}={value}"),
+                ChainCommand::SetEnabled { slot, enabled } => format!("{slot} on={enabled}"),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            ["wet 0.75", "insert 1", "1.1=0.6", "1.2=0.3", "1 on=true"]
+        );
+    }
+
+    #[test]
+    fn autosaves_on_a_timer_and_after_events() {
+        let dir = tempdir().unwrap();
+        let mut autosave = Autosave::new(dir.path().join("session.json"), Duration::from_secs(10));
+        let start = Instant::now();
+        let snapshot = session();
+        assert!(autosave.save_if_due(&snapshot, start).unwrap());
+        assert!(!autosave
+            .save_if_due(&snapshot, start + Duration::from_secs(5))
+            .unwrap());
+        autosave.note_event();
+        assert!(autosave
+            .save_if_due(&snapshot, start + Duration::from_secs(6))
+            .unwrap());
+        assert!(!autosave
+            .save_if_due(&snapshot, start + Duration::from_secs(15))
+            .unwrap());
+        assert!(autosave
+            .save_if_due(&snapshot, start + Duration::from_secs(16))
+            .unwrap());
+        assert_eq!(Session::load(autosave.path()).unwrap(), snapshot);
+    }
+}
@@ -17,6 +17,91 @@ pub struct Settings {
     pub device: String,
     pub buffer_frames: u32,
     pub sample_rate: u32,
+    /// Audio host/driver to open devices on (e.g. "ALSA", "WASAPI"); the platform default when unset.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Ask for an exclusive-mode (WASAPI) stream, falling back to shared mode if refused.
+    #[serde(default)]
+    pub exclusive_mode: bool,
+    /// Reopen the system default output when the configured device stays gone
+    /// after a few attempts, e.g. a USB interface pulled mid-set.
+    #[serde(default)]
+    pub fallback_to_default: bool,
+    /// Capture device for the live input deck; the host's default input when unset.
+    #[serde(default)]
+    pub input_device: Option<String>,
+    /// Output device for prelistening, e.g. headphones on a second sound card; no preview when unset.
+    #[serde(default)]
+    pub preview_device: Option<String>,
+    /// Output device for the cue, e.g. the built-in headphone jack next to a USB DAC
+    /// playing the master; the cue is not played when unset.
+    #[serde(default)]
+    pub cue_device: Option<String>,
+    /// Brickwall-limit the master, adding a few ms of latency.
+    #[serde(default)]
+    pub master_limiter: bool,
+    /// Time constant deck gains, crossfader and master gain glide to new values with, in ms.
+    #[serde(default = "default_smoothing_ms")]
+    pub smoothing_ms: u32,
+    /// Stop the preview whenever a track is loaded to a deck.
+    #[serde(default = "default_preview_stop_on_deck_load")]
+    pub preview_stop_on_deck_load: bool,
+    /// Fader-start per deck (A, B): opening the channel plays the deck, closing it cues it.
+    #[serde(default)]
+    pub fader_start: [bool; 2],
+    /// Folders scanned into the library index.
+    #[serde(default)]
+    pub library_paths: Vec<PathBuf>,
+    /// MIDI input ports to open, each matched as a substring of the port name.
+    #[serde(default)]
+    pub midi_inputs: Vec<String>,
+    /// MIDI output port for controller LED feedback, matched like the inputs.
+    #[serde(default)]
+    pub midi_output: Option<String>,
+    /// MIDI output port that receives clock following the master tempo.
+    #[serde(default)]
+    pub midi_clock_output: Option<String>,
+    /// Send Start/Stop/Continue along with the clock ticks.
+    #[serde(default = "default_midi_clock_transport")]
+    pub midi_clock_transport: bool,
+    /// Address the metrics endpoint listens on, e.g. "0.0.0.0:9898"; disabled when unset.
+    #[serde(default)]
+    pub metrics_address: Option<String>,
+    /// UDP address OSC clients send to and receive feedback from, e.g. "0.0.0.0:9000".
+    #[serde(default)]
+    pub osc_address: Option<String>,
+    /// Most feedback bundles sent to each OSC client per second.
+    #[serde(default = "default_osc_feedback_hz")]
+    pub osc_feedback_hz: u32,
+    /// Address the WebSocket remote listens on, e.g. "0.0.0.0:9001"; disabled when unset.
+    #[serde(default)]
+    pub remote_address: Option<String>,
+    /// Token remotes must present; the remote refuses to start without one.
+    #[serde(default)]
+    pub remote_token: Option<String>,
+    /// How often state and meters are pushed to subscribed remotes, per second.
+    #[serde(default = "default_remote_push_hz")]
+    pub remote_push_hz: u32,
+}
+
+fn default_midi_clock_transport() -> bool {
+    true
+}
+
+fn default_smoothing_ms() -> u32 {
+    10
+}
+
+fn default_preview_stop_on_deck_load() -> bool {
+    true
+}
+
+fn default_osc_feedback_hz() -> u32 {
+    20
+}
+
+fn default_remote_push_hz() -> u32 {
+    15
 }
 
 impl Default for Settings {
@@ -25,6 +110,27 @@ impl Default for Settings {
             device: "default".to_string(),
             buffer_frames: 512,
             sample_rate: 48_000,
+            host: None,
+            exclusive_mode: false,
+            fallback_to_default: false,
+            input_device: None,
+            preview_device: None,
+            cue_device: None,
+            master_limiter: false,
+            smoothing_ms: default_smoothing_ms(),
+            preview_stop_on_deck_load: true,
+            fader_start: [false; 2],
+            library_paths: Vec::new(),
+            midi_inputs: Vec::new(),
+            midi_output: None,
+            midi_clock_output: None,
+            midi_clock_transport: true,
+            metrics_address: None,
+            osc_address: None,
+            osc_feedback_hz: default_osc_feedback_hz(),
+            remote_address: None,
+            remote_token: None,
+            remote_push_hz: default_remote_push_hz(),
         }
     }
 }
@@ -61,9 +167,11 @@ mod tests {
     fn round_trips_settings() {
         let dir = tempdir().unwrap();
         let file = dir.path().join("settings.json");
-        let mut settings = Settings::default();
-        settings.device = "loopback".into();
-        settings.buffer_frames = 1024;
+        let settings = Settings {
+            device: "loopback".into(),
+            buffer_frames: 1024,
+            ..Settings::default()
+        };
 
         let previous = std::env::current_dir().unwrap();
         std::env::set_current_dir(dir.path()).unwrap();
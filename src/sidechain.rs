@@ -0,0 +1,360 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::db::db_to_linear;
+
+/// Averaging time of the RMS detector.
+const RMS_WINDOW_SECONDS: f32 = 0.01;
+/// Key levels below this are treated as silence.
+const SILENCE_DB: f32 = -120.0;
+
+fn coeff(seconds: f32, sample_rate: u32) -> f32 {
+    (-1.0 / (seconds.max(1e-6) * sample_rate as f32)).exp()
+}
+
+/// How the key signal's level is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Detector {
+    /// Louder channel of each frame; reacts to every transient.
+    #[default]
+    Peak,
+    /// Mean power over a short window; follows speech more smoothly.
+    Rms,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SidechainOptions {
+    /// Key level above which the music is compressed, in dBFS.
+    pub threshold_db: f32,
+    /// Each dB the key rises over the threshold pulls the music down `1 - 1 / ratio` dB;
+    /// 1 leaves it alone.
+    pub ratio: f32,
+    /// Time for the gain reduction to cover 63% of a step towards more reduction.
+    pub attack_seconds: f32,
+    /// Time for the gain reduction to cover 63% of a step back towards none.
+    pub release_seconds: f32,
+    /// Width of the soft knee centred on the threshold, in dB; 0 is a hard knee.
+    pub knee_db: f32,
+    /// Gain applied to the music after compression, in dB.
+    pub makeup_db: f32,
+    pub detector: Detector,
+}
+
+impl Default for SidechainOptions {
+    fn default() -> Self {
+        Self {
+            threshold_db: -30.0,
+            ratio: 4.0,
+            attack_seconds: 0.01,
+            release_seconds: 0.4,
+            knee_db: 6.0,
+            makeup_db: 0.0,
+            detector: Detector::Peak,
+        }
+    }
+}
+
+/// Control-side view of a [`SidechainCompressor`]'s or
+/// [`Limiter`](crate::limiter::Limiter)'s gain reduction.
+#[derive(Debug, Clone, Default)]
+pub struct GainReductionReader {
+    reduction_db: Arc<AtomicU32>,
+}
+
+impl GainReductionReader {
+    /// Deepest gain reduction over the last mixed block, in dB; 0 when the music is untouched.
+    pub fn reduction_db(&self) -> f32 {
+        f32::from_bits(self.reduction_db.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn publish(&self, reduction_db: f32) {
+        self.reduction_db
+            .store(reduction_db.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Compressor on the summed decks keyed from the mic, so speech pushes the
+/// music down in proportion to how loud it is rather than by a fixed depth.
+#[derive(Debug, Clone)]
+pub struct SidechainCompressor {
+    options: SidechainOptions,
+    attack: f32,
+    release: f32,
+    rms: f32,
+    /// Mean square of the key, for the RMS detector.
+    power: f32,
+    /// Smoothed gain reduction, in dB (zero or negative).
+    reduction: f32,
+    makeup: f32,
+    block_reduction: f32,
+    reader: GainReductionReader,
+}
+
+impl SidechainCompressor {
+    pub fn new(sample_rate: u32, options: SidechainOptions) -> Self {
+        let mut compressor = Self {
+            options,
+            attack: 0.0,
+            release: 0.0,
+            rms: 0.0,
+            power: 0.0,
+            reduction: 0.0,
+            makeup: 1.0,
+            block_reduction: 0.0,
+            reader: GainReductionReader::default(),
+        };
+        compressor.prepare(sample_rate);
+        compressor
+    }
+
+    /// Recompute the time constants for the session `sample_rate` and start over.
+    pub fn prepare(&mut self, sample_rate: u32) {
+        let options = self.options;
+        self.attack = coeff(options.attack_seconds, sample_rate);
+        self.release = coeff(options.release_seconds, sample_rate);
+        self.rms = coeff(RMS_WINDOW_SECONDS, sample_rate);
+        self.makeup = db_to_linear(options.makeup_db);
+        self.power = 0.0;
+        self.reduction = 0.0;
+        self.block_reduction = 0.0;
+    }
+
+    pub fn options(&self) -> SidechainOptions {
+        self.options
+    }
+
+    /// Lock-free view of the gain reduction, refreshed by [`publish`](Self::publish).
+    pub fn reader(&self) -> GainReductionReader {
+        self.reader.clone()
+    }
+
+    /// Gain reduction the static curve asks for at `level_db`, in dB.
+    fn curve(&self, level_db: f32) -> f32 {
+        let SidechainOptions {
+            threshold_db,
+            ratio,
+            knee_db,
+            ..
+        } = self.options;
+        let slope = 1.0 / ratio.max(1.0) - 1.0;
+        let over = level_db - threshold_db;
+        if 2.0 * over <= -knee_db {
+            0.0
+        } else if 2.0 * over.abs() < knee_db {
+            slope * (over + knee_db / 2.0).powi(2) / (2.0 * knee_db)
+        } else {
+            slope * over
+        }
+    }
+
+    /// Measure one key frame and advance the compressor. Returns the gain to
+    /// apply to the music for this frame, make-up included.
+    pub fn tick(&mut self, key: [f32; 2]) -> f32 {
+        let level = match self.options.detector {
+            Detector::Peak => key[0].abs().max(key[1].abs()),
+            Detector::Rms => {
+                let power = (key[0] * key[0] + key[1] * key[1]) * 0.5;
+                self.power = power + (self.power - power) * self.rms;
+                self.power.sqrt()
+            }
+        };
+        let level_db = if level > 0.0 {
+            (20.0 * level.log10()).max(SILENCE_DB)
+        } else {
+            SILENCE_DB
+        };
+        let target = self.curve(level_db);
+        let coeff = if target < self.reduction {
+            self.attack
+        } else {
+            self.release
+        };
+        let next = target + (self.reduction - target) * coeff;
+        // Snap once close so a silent key ends at exactly no reduction.
+        self.reduction = if (next - target).abs() < 1e-4 {
+            target
+        } else {
+            next
+        };
+        self.block_reduction = self.block_reduction.min(self.reduction);
+        db_to_linear(self.reduction) * self.makeup
+    }
+
+    /// Hand the block's deepest reduction to the reader; call once per mixed block.
+    pub fn publish(&mut self) {
+        self.reader.publish(-self.block_reduction);
+        self.block_reduction = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, ParameterUpdate, SummingBus};
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    /// Square wave at `db` dBFS, so peak and RMS detectors read the same level.
+    fn key(db: f32, frame: usize) -> [f32; 2] {
+        let level = db_to_linear(db);
+        let s = if (frame / 48).is_multiple_of(2) {
+            level
+        } else {
+            -level
+        };
+        [s, s]
+    }
+
+    fn reduction_db(gain: f32) -> f32 {
+        -20.0 * gain.log10()
+    }
+
+    #[test]
+    fn reduction_depth_follows_the_ratio() {
+        for detector in [Detector::Peak, Detector::Rms] {
+            for ratio in [2.0, 4.0, 8.0] {
+                let mut compressor = SidechainCompressor::new(
+                    SAMPLE_RATE,
+                    SidechainOptions {
+                        ratio,
+                        knee_db: 0.0,
+                        detector,
+                        ..SidechainOptions::default()
+                    },
+                );
+                let reader = compressor.reader();
+                let mut gain = 1.0;
+                for frame in 0..SAMPLE_RATE as usize / 2 {
+                    gain = compressor.tick(key(-10.0, frame));
+                }
+                compressor.publish();
+                // 20 dB over the threshold comes out 20 / ratio dB over.
+                let expected = 20.0 * (1.0 - 1.0 / ratio);
+                assert!((reduction_db(gain) - expected).abs() < 0.1, "{ratio}");
+                assert!((reader.reduction_db() - expected).abs() < 0.1);
+            }
+        }
+
+        // At the threshold a soft knee is already compressing, a hard one not yet.
+        let soft = SidechainCompressor::new(SAMPLE_RATE, SidechainOptions::default());
+        assert!((soft.curve(-30.0) - -0.5625).abs() < 1e-4);
+        assert_eq!(soft.curve(-34.0), 0.0);
+        let hard = SidechainCompressor::new(
+            SAMPLE_RATE,
+            SidechainOptions {
+                knee_db: 0.0,
+                ..SidechainOptions::default()
+            },
+        );
+        assert_eq!(hard.curve(-30.0), 0.0);
+    }
+
+    #[test]
+    fn attack_and_release_follow_their_time_constants() {
+        let options = SidechainOptions {
+            ratio: 4.0,
+            knee_db: 0.0,
+            attack_seconds: 0.02,
+            release_seconds: 0.5,
+            ..SidechainOptions::default()
+        };
+        let mut compressor = SidechainCompressor::new(SAMPLE_RATE, options);
+        let attack = (options.attack_seconds * SAMPLE_RATE as f32) as usize;
+        let release = (options.release_seconds * SAMPLE_RATE as f32) as usize;
+        let full = 15.0;
+
+        let mut gain = 1.0;
+        for frame in 0..attack {
+            gain = compressor.tick(key(-10.0, frame));
+        }
+        let step = full * (1.0 - (-1f32).exp());
+        assert!(
+            (reduction_db(gain) - step).abs() < 0.3,
+            "{}",
+            reduction_db(gain)
+        );
+        for frame in attack..SAMPLE_RATE as usize {
+            gain = compressor.tick(key(-10.0, frame));
+        }
+        assert!((reduction_db(gain) - full).abs() < 0.01);
+
+        for _ in 0..release {
+            gain = compressor.tick([0.0; 2]);
+        }
+        let left = full * (-1f32).exp();
+        assert!(
+            (reduction_db(gain) - left).abs() < 0.3,
+            "{}",
+            reduction_db(gain)
+        );
+    }
+
+    #[test]
+    fn silent_key_leaves_the_music_alone() {
+        let mut compressor = SidechainCompressor::new(
+            SAMPLE_RATE,
+            SidechainOptions {
+                makeup_db: 6.0,
+                ..SidechainOptions::default()
+            },
+        );
+        for _ in 0..SAMPLE_RATE {
+            assert_eq!(compressor.tick([0.0; 2]), db_to_linear(6.0));
+        }
+        compressor.publish();
+        assert_eq!(compressor.reader().reduction_db(), 0.0);
+    }
+
+    #[test]
+    fn keyed_burst_compresses_a_steady_tone_on_the_bus() {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx).with_sample_rate(SAMPLE_RATE);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::MicLowCutHz(0.0)).unwrap();
+        let compressor = SidechainCompressor::new(
+            SAMPLE_RATE,
+            SidechainOptions {
+                knee_db: 0.0,
+                ..SidechainOptions::default()
+            },
+        );
+        let reader = compressor.reader();
+        assert!(bus.set_sidechain(Some(compressor)).is_none());
+
+        let frames = SAMPLE_RATE as usize;
+        let burst = frames / 4..frames / 2;
+        let mut mic = vec![0.0; frames * 2];
+        for frame in burst.clone() {
+            mic[frame * 2..frame * 2 + 2].copy_from_slice(&key(-10.0, frame));
+        }
+        let mut tone: Vec<f32> = (0..frames)
+            .flat_map(|frame| {
+                let s =
+                    0.5 * (frame as f32 * 440.0 / SAMPLE_RATE as f32 * std::f32::consts::TAU).sin();
+                [s, s]
+            })
+            .collect();
+        let mut out = vec![0.0; frames * 2];
+        let mut reductions = Vec::new();
+        for ((a, m), o) in tone
+            .chunks_mut(512)
+            .zip(mic.chunks(512))
+            .zip(out.chunks_mut(512))
+        {
+            bus.process_with_mic(a, &mut [0.0; 512][..a.len()], Some(m), o);
+            reductions.push(reader.reduction_db());
+        }
+
+        // Untouched before the burst, and pulled down 15 dB by its end.
+        assert!(reductions[..burst.start / 256].iter().all(|db| *db == 0.0));
+        let last = burst.end / 256 - 1;
+        assert!(
+            (reductions[last] - 15.0).abs() < 0.1,
+            "{}",
+            reductions[last]
+        );
+        let music = |frame: usize| out[frame * 2] - mic[frame * 2];
+        let expected = tone[(burst.end - 1) * 2] * db_to_linear(-15.0);
+        assert!((music(burst.end - 1) - expected).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,196 @@
+/// Samples each vector step handles.
+const LANES: usize = 4;
+
+/// Add `input · trim · gain` into `out`, sample by sample, four at a time where
+/// the target has vectors. Returns how many faded samples were past full scale.
+///
+/// Rounds exactly as the per-frame mix does, multiplying by the trim and then
+/// the gain, so a block mixed here matches one mixed frame by frame.
+pub(crate) fn fade_add(out: &mut [f32], input: &[f32], trim: f32, gain: f32) -> u64 {
+    let done = out.len().min(input.len()) / LANES * LANES;
+    let clips = vector::fade_add(&mut out[..done], &input[..done], trim, gain);
+    clips + scalar::fade_add(&mut out[done..], &input[done..], trim, gain)
+}
+
+/// Multiply `out` by `gain` in place. Returns how many samples ended up past
+/// full scale.
+pub(crate) fn scale(out: &mut [f32], gain: f32) -> u64 {
+    let done = out.len() / LANES * LANES;
+    let clips = vector::scale(&mut out[..done], gain);
+    clips + scalar::scale(&mut out[done..], gain)
+}
+
+/// Reference kernels, and the tail of every block.
+mod scalar {
+    pub(super) fn fade_add(out: &mut [f32], input: &[f32], trim: f32, gain: f32) -> u64 {
+        let mut clips = 0;
+        for (out, input) in out.iter_mut().zip(input) {
+            let faded = input * trim * gain;
+            clips += (faded.abs() > 1.0) as u64;
+            *out += faded;
+        }
+        clips
+    }
+
+    pub(super) fn scale(out: &mut [f32], gain: f32) -> u64 {
+        let mut clips = 0;
+        for sample in out {
+            *sample *= gain;
+            clips += (sample.abs() > 1.0) as u64;
+        }
+        clips
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod vector {
+    use std::arch::x86_64::*;
+
+    use super::LANES;
+
+    pub(super) fn fade_add(out: &mut [f32], input: &[f32], trim: f32, gain: f32) -> u64 {
+        let mut clips = 0;
+        // SAFETY: SSE is part of the x86-64 baseline, and every load and store
+        // stays within a chunk of four samples.
+        unsafe {
+            let (trim, gain) = (_mm_set1_ps(trim), _mm_set1_ps(gain));
+            let (one, sign) = (_mm_set1_ps(1.0), _mm_set1_ps(-0.0));
+            for (out, input) in out.chunks_exact_mut(LANES).zip(input.chunks_exact(LANES)) {
+                let faded = _mm_mul_ps(_mm_mul_ps(_mm_loadu_ps(input.as_ptr()), trim), gain);
+                let over = _mm_cmpgt_ps(_mm_andnot_ps(sign, faded), one);
+                clips += _mm_movemask_ps(over).count_ones() as u64;
+                let mixed = _mm_add_ps(_mm_loadu_ps(out.as_ptr()), faded);
+                _mm_storeu_ps(out.as_mut_ptr(), mixed);
+            }
+        }
+        clips
+    }
+
+    pub(super) fn scale(out: &mut [f32], gain: f32) -> u64 {
+        let mut clips = 0;
+        // SAFETY: as in `fade_add`.
+        unsafe {
+            let gain = _mm_set1_ps(gain);
+            let (one, sign) = (_mm_set1_ps(1.0), _mm_set1_ps(-0.0));
+            for out in out.chunks_exact_mut(LANES) {
+                let scaled = _mm_mul_ps(_mm_loadu_ps(out.as_ptr()), gain);
+                let over = _mm_cmpgt_ps(_mm_andnot_ps(sign, scaled), one);
+                clips += _mm_movemask_ps(over).count_ones() as u64;
+                _mm_storeu_ps(out.as_mut_ptr(), scaled);
+            }
+        }
+        clips
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod vector {
+    use std::arch::aarch64::*;
+
+    use super::LANES;
+
+    pub(super) fn fade_add(out: &mut [f32], input: &[f32], trim: f32, gain: f32) -> u64 {
+        let mut clips = 0;
+        // SAFETY: NEON is part of the aarch64 baseline, and every load and store
+        // stays within a chunk of four samples.
+        unsafe {
+            let (trim, gain, one) = (vdupq_n_f32(trim), vdupq_n_f32(gain), vdupq_n_f32(1.0));
+            for (out, input) in out.chunks_exact_mut(LANES).zip(input.chunks_exact(LANES)) {
+                let faded = vmulq_f32(vmulq_f32(vld1q_f32(input.as_ptr()), trim), gain);
+                clips += vaddvq_u32(vshrq_n_u32::<31>(vcagtq_f32(faded, one))) as u64;
+                vst1q_f32(out.as_mut_ptr(), vaddq_f32(vld1q_f32(out.as_ptr()), faded));
+            }
+        }
+        clips
+    }
+
+    pub(super) fn scale(out: &mut [f32], gain: f32) -> u64 {
+        let mut clips = 0;
+        // SAFETY: as in `fade_add`.
+        unsafe {
+            let (gain, one) = (vdupq_n_f32(gain), vdupq_n_f32(1.0));
+            for out in out.chunks_exact_mut(LANES) {
+                let scaled = vmulq_f32(vld1q_f32(out.as_ptr()), gain);
+                clips += vaddvq_u32(vshrq_n_u32::<31>(vcagtq_f32(scaled, one))) as u64;
+                vst1q_f32(out.as_mut_ptr(), scaled);
+            }
+        }
+        clips
+    }
+}
+
+/// Without vectors the whole block goes through the scalar kernels.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod vector {
+    pub(super) use super::scalar::{fade_add, scale};
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    use super::*;
+
+    fn random(len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                ((state >> 8) as f32 / (1u32 << 23) as f32 - 1.0) * 1.5
+            })
+            .collect()
+    }
+
+    fn ulps(a: f32, b: f32) -> u32 {
+        if a == b {
+            0
+        } else {
+            (a.to_bits() as i32).abs_diff(b.to_bits() as i32)
+        }
+    }
+
+    #[test]
+    fn vector_kernels_match_the_scalar_ones() {
+        // Odd lengths leave a scalar tail behind the vectors.
+        for (len, seed) in [(0, 1), (3, 2), (64, 3), (130, 4), (1_027, 5)] {
+            let input = random(len, seed);
+            let start = random(len, seed + 100);
+            for (trim, gain) in [(1.0, 1.0), (0.7, 0.35), (1.9, 0.9), (0.0, 1.0)] {
+                let (mut fast, mut reference) = (start.clone(), start.clone());
+                let clips = fade_add(&mut fast, &input, trim, gain);
+                assert_eq!(clips, scalar::fade_add(&mut reference, &input, trim, gain));
+                assert!(fast.iter().zip(&reference).all(|(a, b)| ulps(*a, *b) <= 1));
+
+                let clips = scale(&mut fast, gain * 1.3);
+                assert_eq!(clips, scalar::scale(&mut reference, gain * 1.3));
+                assert!(fast.iter().zip(&reference).all(|(a, b)| ulps(*a, *b) <= 1));
+            }
+        }
+
+        // Non-finite samples count as the scalar kernel counts them.
+        let input = [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 0.5, 2.0];
+        let (mut fast, mut reference) = ([0.0; 5], [0.0; 5]);
+        assert_eq!(
+            fade_add(&mut fast, &input, 1.0, 1.0),
+            scalar::fade_add(&mut reference, &input, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    #[ignore = "timing; run with --release"]
+    fn vector_kernels_run_faster_than_the_scalar_ones() {
+        // One deck of 64 stereo frames, as an audio callback at 96 kHz might ask for.
+        let input = random(128, 1);
+        let time = |kernel: fn(&mut [f32], &[f32], f32, f32) -> u64| {
+            let mut out = vec![0.0; 128];
+            let start = Instant::now();
+            for _ in 0..2_000_000 {
+                black_box(kernel(black_box(&mut out), black_box(&input), 0.7, 0.35));
+            }
+            start.elapsed()
+        };
+        let (vector, scalar) = (time(fade_add), time(scalar::fade_add));
+        assert!(vector < scalar, "vector {vector:?}, scalar {scalar:?}");
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use crossbeam_queue::ArrayQueue;
+
+use crate::meter::DeckValues;
+use crate::{CrossfaderCurve, FaderTaper, XfAssign};
+
+/// Parameter values the bus is mixing with, after it clamped them.
+///
+/// Per-deck values cover every deck of the bus, as a
+/// [`MeterFrame`](crate::MeterFrame)'s do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixerSnapshot {
+    pub deck_trims: DeckValues<f32>,
+    pub deck_gains: DeckValues<f32>,
+    pub fader_tapers: DeckValues<FaderTaper>,
+    pub deck_mutes: DeckValues<bool>,
+    pub deck_solos: DeckValues<bool>,
+    pub deck_cues: DeckValues<bool>,
+    pub cue_mix: f32,
+    pub cue_gain: f32,
+    /// Where the crossfader is, partway along any ramp.
+    pub crossfader: f32,
+    /// Automated crossfade under way, if any.
+    pub crossfade: Option<CrossfadeProgress>,
+    pub crossfader_curve: CrossfaderCurve,
+    pub crossfader_sharpness: f32,
+    pub crossfader_reverse: bool,
+    pub crossfader_assign: DeckValues<XfAssign>,
+    pub master_gain: f32,
+    pub booth_gain: f32,
+    pub stereo_width: f32,
+    pub mono_output: bool,
+    pub filter_positions: DeckValues<f32>,
+    pub soft_clip: bool,
+    /// Whether the master is being copied to the bus's record tap.
+    pub recording: bool,
+    /// Output frame the values hold from.
+    pub frame: u64,
+}
+
+/// How far a [`CrossfadeTo`](crate::ParameterUpdate::CrossfadeTo) has got.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrossfadeProgress {
+    pub target: f32,
+    pub elapsed_frames: u64,
+    pub duration_frames: u64,
+}
+
+impl CrossfadeProgress {
+    /// Share of the fade mixed so far, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        (self.elapsed_frames as f64 / self.duration_frames as f64) as f32
+    }
+}
+
+/// Audio-thread side of a snapshot queue, handed to
+/// [`SummingBus::set_snapshots`](crate::SummingBus::set_snapshots).
+#[derive(Debug)]
+pub struct SnapshotSender {
+    queue: Arc<ArrayQueue<MixerSnapshot>>,
+}
+
+impl SnapshotSender {
+    /// Enqueue `snapshot`, dropping it if the control side has fallen behind.
+    pub fn send(&self, snapshot: MixerSnapshot) {
+        let _ = self.queue.push(snapshot);
+    }
+
+    /// Whether the next [`send`](Self::send) would be dropped.
+    pub(crate) fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+}
+
+/// Control-side end of a snapshot queue.
+#[derive(Debug)]
+pub struct SnapshotReceiver {
+    queue: Arc<ArrayQueue<MixerSnapshot>>,
+}
+
+impl SnapshotReceiver {
+    pub fn pop(&self) -> Option<MixerSnapshot> {
+        self.queue.pop()
+    }
+
+    /// Drain the queue, keeping only the newest snapshot.
+    pub fn latest(&self) -> Option<MixerSnapshot> {
+        std::iter::from_fn(|| self.queue.pop()).last()
+    }
+}
+
+/// Create a bounded, lock-free queue of mixer snapshots, one per
+/// [`RequestSnapshot`](crate::ParameterUpdate::RequestSnapshot) the bus applies.
+pub fn snapshot_channel(capacity: usize) -> (SnapshotSender, SnapshotReceiver) {
+    let queue = Arc::new(ArrayQueue::new(capacity));
+    (
+        SnapshotSender {
+            queue: queue.clone(),
+        },
+        SnapshotReceiver { queue },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, DeckId, ParameterUpdate, SummingBus};
+
+    #[test]
+    fn snapshot_reports_the_clamped_values() {
+        let (tx, rx) = parameter_channel(16);
+        let mut bus = SummingBus::new(rx);
+        let (snapshots, receiver) = snapshot_channel(4);
+        bus.set_snapshots(Some(snapshots));
+        for update in [
+            ParameterUpdate::Crossfader(1.7),
+            ParameterUpdate::MasterGain(-3.0),
+            ParameterUpdate::DeckGain {
+                deck: DeckId::A,
+                gain: 4.0,
+            },
+            ParameterUpdate::DeckTrim {
+                deck: DeckId::B,
+                gain: 100.0,
+            },
+            ParameterUpdate::DeckFilter {
+                deck: DeckId::B,
+                position: -9.0,
+            },
+            ParameterUpdate::StereoWidth(5.0),
+            ParameterUpdate::CueMix(-1.0),
+            ParameterUpdate::RequestSnapshot,
+            // Applied after the request, so not in its snapshot.
+            ParameterUpdate::MonoOutput(true),
+        ] {
+            tx.send(update).unwrap();
+        }
+        assert!(receiver.pop().is_none());
+        bus.mix_stereo(&[0.0; 8], &[0.0; 8], &mut [0.0; 8]);
+
+        let snapshot = receiver.pop().unwrap();
+        assert!(receiver.pop().is_none());
+        assert_eq!(snapshot.crossfader, 1.0);
+        assert_eq!(snapshot.master_gain, 0.0);
+        assert_eq!(snapshot.deck_gains[..], [1.0, 1.0]);
+        assert_eq!(snapshot.deck_trims[..], [1.0, crate::MAX_TRIM]);
+        assert_eq!(snapshot.filter_positions[..], [0.0, -1.0]);
+        assert_eq!(snapshot.stereo_width, 2.0);
+        assert_eq!(snapshot.cue_mix, 0.0);
+        assert!(!snapshot.mono_output);
+        assert_eq!(snapshot.frame, 0);
+
+        // Offline, the same values straight from the bus.
+        let now = bus.snapshot();
+        assert!(now.mono_output);
+        assert_eq!(now.frame, 4);
+        assert_eq!(
+            MixerSnapshot {
+                mono_output: false,
+                frame: 0,
+                ..now
+            },
+            snapshot
+        );
+    }
+
+    #[test]
+    fn snapshot_covers_every_deck_of_a_wider_bus() {
+        let (tx, rx) = parameter_channel(16);
+        let mut bus = SummingBus::with_decks(4, rx);
+        let (snapshots, receiver) = snapshot_channel(4);
+        bus.set_snapshots(Some(snapshots));
+        for update in [
+            ParameterUpdate::DeckGain {
+                deck: DeckId(3),
+                gain: 0.5,
+            },
+            ParameterUpdate::DeckMute {
+                deck: DeckId(2),
+                on: true,
+            },
+            ParameterUpdate::DeckCue {
+                deck: DeckId(3),
+                on: true,
+            },
+            ParameterUpdate::CrossfaderAssign {
+                deck: DeckId(3),
+                assign: XfAssign::B,
+            },
+            ParameterUpdate::DeckFilter {
+                deck: DeckId(2),
+                position: 0.25,
+            },
+            ParameterUpdate::RequestSnapshot,
+        ] {
+            tx.send(update).unwrap();
+        }
+        bus.mix(&[&[0.0; 8][..]; 4], &mut [0.0; 8]);
+
+        let snapshot = receiver.pop().unwrap();
+        assert_eq!(snapshot.deck_gains[..], [1.0, 1.0, 1.0, 0.5]);
+        assert_eq!(snapshot.deck_trims[..], [1.0; 4]);
+        assert_eq!(snapshot.fader_tapers.len(), 4);
+        assert_eq!(snapshot.deck_mutes[..], [false, false, true, false]);
+        assert_eq!(snapshot.deck_solos[..], [false; 4]);
+        assert_eq!(snapshot.deck_cues[..], [false, false, false, true]);
+        assert_eq!(
+            snapshot.crossfader_assign[..],
+            [XfAssign::A, XfAssign::B, XfAssign::Thru, XfAssign::B]
+        );
+        assert_eq!(snapshot.filter_positions[..], [0.0, 0.0, 0.25, 0.0]);
+        assert_eq!(
+            MixerSnapshot {
+                frame: 0,
+                ..bus.snapshot()
+            },
+            snapshot
+        );
+    }
+}
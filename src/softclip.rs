@@ -0,0 +1,133 @@
+/// Share of full scale the knee moves down by at full drive.
+const KNEE_SPAN: f32 = 0.5;
+
+/// Waveshaper that rounds the master into full scale instead of limiting it.
+///
+/// Samples up to the knee pass through untouched; above it a tanh curve, with
+/// the same slope where it takes over, bends them towards full scale so nothing
+/// leaves [-1, 1] however hot the input. More drive lowers the knee, from full
+/// scale (a plain clamp) at 0 to half of it at 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftClip {
+    drive: f32,
+    knee: f32,
+}
+
+impl Default for SoftClip {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+impl SoftClip {
+    pub fn new(drive: f32) -> Self {
+        let mut clip = Self {
+            drive: 0.0,
+            knee: 1.0,
+        };
+        clip.set_drive(drive);
+        clip
+    }
+
+    pub fn drive(&self) -> f32 {
+        self.drive
+    }
+
+    /// Drive in [0, 1]; NaN is ignored.
+    pub fn set_drive(&mut self, drive: f32) {
+        if drive.is_nan() {
+            return;
+        }
+        self.drive = drive.clamp(0.0, 1.0);
+        self.knee = 1.0 - KNEE_SPAN * self.drive;
+    }
+
+    /// Highest level that passes untouched.
+    pub fn knee(&self) -> f32 {
+        self.knee
+    }
+
+    pub fn shape(&self, sample: f32) -> f32 {
+        let level = sample.abs();
+        if level <= self.knee {
+            return sample;
+        }
+        let headroom = 1.0 - self.knee;
+        let shaped = if headroom > 0.0 {
+            self.knee + headroom * ((level - self.knee) / headroom).tanh()
+        } else {
+            1.0
+        };
+        shaped.min(1.0).copysign(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, ParameterUpdate, SummingBus};
+
+    fn sine(amplitude: f32) -> Vec<f32> {
+        (0..4_800)
+            .flat_map(|i| {
+                let s = (i as f32 / 48_000.0 * 440.0 * std::f32::consts::TAU).sin();
+                [s * amplitude, s * amplitude]
+            })
+            .collect()
+    }
+
+    fn clipped(input: &[f32], drive: f32) -> Vec<f32> {
+        let (tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        tx.send(ParameterUpdate::Crossfader(0.0)).unwrap();
+        tx.send(ParameterUpdate::MasterSoftClip(true)).unwrap();
+        tx.send(ParameterUpdate::MasterSoftClipDrive(drive))
+            .unwrap();
+        let mut out = vec![0.0; input.len()];
+        bus.mix_stereo(input, &vec![0.0; input.len()], &mut out);
+        out
+    }
+
+    #[test]
+    fn half_scale_sine_matches_the_dry_signal() {
+        let dry = sine(0.5);
+        for drive in [0.0, 0.5, 1.0] {
+            let wet = clipped(&dry, drive);
+            let error = dry
+                .iter()
+                .zip(&wet)
+                .map(|(dry, wet)| (dry - wet).powi(2))
+                .sum::<f32>();
+            let signal = dry.iter().map(|s| s * s).sum::<f32>();
+            let deviation_db = 10.0 * (error / signal).max(1e-30).log10();
+            assert!(deviation_db < -80.0, "{drive}: {deviation_db}");
+        }
+    }
+
+    #[test]
+    fn hot_sine_stays_in_range_without_jumps() {
+        let dry = sine(2.0);
+        for drive in [0.25, 0.5, 1.0] {
+            let wet = clipped(&dry, drive);
+            assert!(wet.iter().all(|s| (-1.0..=1.0).contains(s)));
+            assert!(wet.iter().any(|s| *s > 0.99));
+            // Steps between frames never exceed the dry signal's.
+            let largest_step = |signal: &[f32]| {
+                signal
+                    .iter()
+                    .step_by(2)
+                    .collect::<Vec<_>>()
+                    .windows(2)
+                    .fold(0.0f32, |m, pair| m.max((pair[1] - pair[0]).abs()))
+            };
+            assert!(largest_step(&wet) <= largest_step(&dry));
+        }
+
+        // Slope matches at the knee, so the curve takes over without a kink.
+        let clip = SoftClip::new(1.0);
+        let slope = |x: f32| (clip.shape(x + 1e-3) - clip.shape(x)) / 1e-3;
+        assert!((slope(clip.knee()) - slope(clip.knee() - 2e-3)).abs() < 0.01);
+        assert_eq!(clip.shape(f32::INFINITY), 1.0);
+        assert_eq!(clip.shape(-10.0), -clip.shape(10.0));
+    }
+}
@@ -0,0 +1,354 @@
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use crate::ring::{AudioRing, RingConsumer, RingProducer};
+
+/// Samples the tap gathers before handing them to the ring in one write.
+const TAP_CHUNK: usize = 64;
+/// Decimated audio the ring holds for an analyzer that falls behind.
+const RING_SECONDS: f32 = 1.0;
+/// Smallest transform [`SpectrumAnalyzer::with_fft_size`] accepts.
+const MIN_FFT_SIZE: usize = 16;
+/// Most a new transform can overlap the last, leaving some hop to take.
+const MAX_OVERLAP: f32 = 0.95;
+
+/// Audio-callback side of a spectrum display: folds the master to mono,
+/// decimates it and copies it into a ring for a [`SpectrumAnalyzer`].
+///
+/// Only ever writes to the ring; all the windowing and FFT work happens in
+/// [`SpectrumAnalyzer::poll`]. Audio that finds the ring full is dropped.
+pub struct SpectrumTap {
+    producer: RingProducer,
+    decimation: usize,
+    /// Frames summed so far towards the next decimated sample.
+    summed: usize,
+    sum: f32,
+    chunk: [f32; TAP_CHUNK],
+    len: usize,
+}
+
+impl SpectrumTap {
+    /// Take one stereo frame of the master.
+    pub fn push_frame(&mut self, frame: [f32; 2]) {
+        self.sum += frame[0] + frame[1];
+        self.summed += 1;
+        if self.summed < self.decimation {
+            return;
+        }
+        self.chunk[self.len] = self.sum / (2 * self.decimation) as f32;
+        self.sum = 0.0;
+        self.summed = 0;
+        self.len += 1;
+        if self.len == TAP_CHUNK {
+            self.producer.write(&self.chunk);
+            self.len = 0;
+        }
+    }
+
+    /// Take a block of interleaved stereo frames.
+    pub fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(2) {
+            self.push_frame([frame[0], frame[1]]);
+        }
+    }
+}
+
+impl std::fmt::Debug for SpectrumTap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpectrumTap")
+            .field("decimation", &self.decimation)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Control-side end of a spectrum display: turns what the [`SpectrumTap`]
+/// copied into log-spaced band levels in dB.
+///
+/// Each band reads the peak amplitude of the bins in it, so a full-scale sine
+/// reads 0 dB in its band; bands with nothing in them read the floor.
+pub struct SpectrumAnalyzer {
+    consumer: RingConsumer,
+    /// Rate of the decimated audio in the ring.
+    sample_rate: f32,
+    fft_size: usize,
+    overlap: f32,
+    averaging: f32,
+    floor_db: f32,
+    band_count: usize,
+    min_hz: f32,
+    max_hz: f32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    /// The last `fft_size` samples, oldest first.
+    history: Vec<f32>,
+    /// Samples taken into `history` since the last transform.
+    fresh: usize,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    /// First and last bin each band reads.
+    band_bins: Vec<(usize, usize)>,
+    /// Averaged peak amplitude of each band.
+    amplitudes: Vec<f32>,
+    levels_db: Vec<f32>,
+}
+
+impl SpectrumAnalyzer {
+    /// Transform `size` samples at a time, at least 16.
+    pub fn with_fft_size(mut self, size: usize) -> Self {
+        self.fft_size = size.max(MIN_FFT_SIZE);
+        self.rebuild();
+        self
+    }
+
+    /// Share of each transform, in [0, 0.95], that overlaps the last; more
+    /// overlap means more transforms per second for the same size.
+    pub fn with_overlap(mut self, overlap: f32) -> Self {
+        self.overlap = overlap.clamp(0.0, MAX_OVERLAP);
+        self
+    }
+
+    /// How much of the previous reading each band keeps per transform, in
+    /// [0, 1); 0 shows each transform as it is.
+    pub fn with_averaging(mut self, averaging: f32) -> Self {
+        self.averaging = averaging.clamp(0.0, 0.99);
+        self
+    }
+
+    /// Split `min_hz` to `max_hz` into `count` log-spaced bands.
+    pub fn with_bands(mut self, count: usize, min_hz: f32, max_hz: f32) -> Self {
+        self.band_count = count.max(1);
+        self.min_hz = min_hz.max(1.0);
+        self.max_hz = max_hz.max(self.min_hz * 1.01);
+        self.rebuild();
+        self
+    }
+
+    /// Level, in dB, reported for bands quieter than it or empty.
+    pub fn with_floor_db(mut self, floor_db: f32) -> Self {
+        self.floor_db = floor_db;
+        self.levels_db.fill(floor_db);
+        self
+    }
+
+    /// Lower and upper edge of band `index`, in Hz.
+    pub fn band_edges(&self, index: usize) -> (f32, f32) {
+        let ratio = self.max_hz / self.min_hz;
+        let edge = |i: usize| self.min_hz * ratio.powf(i as f32 / self.band_count as f32);
+        (edge(index), edge(index + 1))
+    }
+
+    /// Analyze everything the tap has copied since the last poll and return
+    /// the band levels, lowest band first. Call it at the display's frame
+    /// rate; with nothing new it returns the last levels again.
+    pub fn poll(&mut self) -> &[f32] {
+        let hop = ((self.fft_size as f32 * (1.0 - self.overlap)).round() as usize).max(1);
+        loop {
+            let take = (hop - self.fresh.min(hop)).min(self.consumer.occupancy());
+            if take == 0 {
+                break;
+            }
+            self.history.copy_within(take.., 0);
+            let len = self.history.len();
+            self.consumer.read(&mut self.history[len - take..]);
+            self.fresh += take;
+            if self.fresh >= hop {
+                self.transform();
+                self.fresh = 0;
+            }
+        }
+        &self.levels_db
+    }
+
+    /// Size the window, buffers and band layout for the current settings.
+    fn rebuild(&mut self) {
+        let size = self.fft_size;
+        self.fft = FftPlanner::new().plan_fft_forward(size);
+        // Periodic Hann, which overlaps to a constant.
+        self.window = (0..size)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / size as f32).cos())
+            .collect();
+        self.history = vec![0.0; size];
+        self.fresh = 0;
+        self.spectrum = vec![Complex::default(); size];
+        self.scratch = vec![Complex::default(); self.fft.get_inplace_scratch_len()];
+
+        let bin_hz = self.sample_rate / size as f32;
+        let last_bin = size / 2;
+        self.band_bins = (0..self.band_count)
+            .map(|band| {
+                let (low, high) = self.band_edges(band);
+                let first = (low / bin_hz).ceil() as usize;
+                let last = ((high / bin_hz).ceil() as usize).saturating_sub(1);
+                if first <= last {
+                    (first.min(last_bin), last.min(last_bin))
+                } else {
+                    // Narrower than a bin: read the one nearest its middle.
+                    let nearest = (((low * high).sqrt() / bin_hz).round() as usize).min(last_bin);
+                    (nearest, nearest)
+                }
+            })
+            .collect();
+        self.amplitudes = vec![0.0; self.band_count];
+        self.levels_db = vec![self.floor_db; self.band_count];
+    }
+
+    fn transform(&mut self) {
+        for ((bin, sample), window) in self
+            .spectrum
+            .iter_mut()
+            .zip(&self.history)
+            .zip(&self.window)
+        {
+            *bin = Complex::new(sample * window, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        // Twice the bin over the window's sum is the amplitude of a sine on it.
+        let scale = 2.0 / self.window.iter().sum::<f32>();
+        for (band, &(first, last)) in self.band_bins.iter().enumerate() {
+            let peak = self.spectrum[first..=last]
+                .iter()
+                .map(|bin| bin.norm() * scale)
+                .fold(0.0, f32::max);
+            let amplitude = &mut self.amplitudes[band];
+            *amplitude = *amplitude * self.averaging + peak * (1.0 - self.averaging);
+            self.levels_db[band] = if *amplitude > 0.0 {
+                (20.0 * amplitude.log10()).max(self.floor_db)
+            } else {
+                self.floor_db
+            };
+        }
+    }
+}
+
+impl std::fmt::Debug for SpectrumAnalyzer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpectrumAnalyzer")
+            .field("sample_rate", &self.sample_rate)
+            .field("fft_size", &self.fft_size)
+            .field("bands", &self.band_count)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Create a spectrum display for a master running at `sample_rate`, keeping
+/// one sample in every `decimation` frames. The tap goes to the bus through
+/// [`SummingBus::set_spectrum_tap`](crate::SummingBus::set_spectrum_tap) and
+/// the analyzer to the display thread.
+///
+/// The analyzer starts out with a 4096-point FFT overlapping by half, light
+/// averaging, and 32 bands from 20 Hz to 20 kHz (or the decimated Nyquist)
+/// above a -120 dB floor; its `with_*` builders change those. Decimating
+/// averages the frames it folds together, which is plenty of anti-aliasing
+/// for a display but not for audio.
+pub fn spectrum_channel(sample_rate: u32, decimation: usize) -> (SpectrumTap, SpectrumAnalyzer) {
+    let decimation = decimation.max(1);
+    let rate = sample_rate as f32 / decimation as f32;
+    let ring_frames = (rate * RING_SECONDS).ceil() as usize;
+    let (producer, consumer) = AudioRing::with_capacity_frames(ring_frames, 1).split();
+    let tap = SpectrumTap {
+        producer,
+        decimation,
+        summed: 0,
+        sum: 0.0,
+        chunk: [0.0; TAP_CHUNK],
+        len: 0,
+    };
+    let fft_size = 4_096;
+    let analyzer = SpectrumAnalyzer {
+        consumer,
+        sample_rate: rate,
+        fft_size,
+        overlap: 0.5,
+        averaging: 0.5,
+        floor_db: -120.0,
+        band_count: 32,
+        min_hz: 20.0,
+        max_hz: 20_000.0,
+        fft: FftPlanner::new().plan_fft_forward(fft_size),
+        window: Vec::new(),
+        history: Vec::new(),
+        fresh: 0,
+        spectrum: Vec::new(),
+        scratch: Vec::new(),
+        band_bins: Vec::new(),
+        amplitudes: Vec::new(),
+        levels_db: Vec::new(),
+    }
+    .with_bands(32, 20.0, 20_000f32.min(rate / 2.0));
+    (tap, analyzer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parameter_channel, SummingBus};
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn sine(freq: f32, amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let s = amplitude
+                    * (std::f32::consts::TAU * freq * i as f32 / SAMPLE_RATE as f32).sin();
+                [s, s]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sine_peaks_in_its_band_at_its_level() {
+        for decimation in [1, 2] {
+            let (mut tap, analyzer) = spectrum_channel(SAMPLE_RATE, decimation);
+            let mut analyzer = analyzer.with_fft_size(2_048).with_overlap(0.75);
+            // -6 dBFS at 1 kHz.
+            for block in sine(1_000.0, 0.5, SAMPLE_RATE as usize / 4).chunks(512) {
+                tap.push(block);
+            }
+            let levels = analyzer.poll().to_vec();
+
+            let (peak, level) = levels
+                .iter()
+                .enumerate()
+                .fold(
+                    (0, f32::MIN),
+                    |best, (i, l)| if *l > best.1 { (i, *l) } else { best },
+                );
+            let (low, high) = analyzer.band_edges(peak);
+            assert!(low <= 1_000.0 && 1_000.0 < high, "peak in {low}..{high} Hz");
+            // Hann scalloping costs at most 1.5 dB between bins.
+            assert!(level <= -6.0 && level > -7.6, "{decimation}: {level} dB");
+            // Well away from the tone there is next to nothing.
+            assert!(levels[..peak - 4].iter().all(|l| *l < level - 40.0));
+        }
+    }
+
+    #[test]
+    fn silence_through_the_bus_reports_the_floor() {
+        let (_tx, rx) = parameter_channel(4);
+        let mut bus = SummingBus::new(rx);
+        let (tap, analyzer) = spectrum_channel(SAMPLE_RATE, 1);
+        let mut analyzer = analyzer
+            .with_floor_db(-100.0)
+            .with_bands(16, 40.0, 16_000.0);
+        assert!(bus.set_spectrum_tap(Some(tap)).is_none());
+
+        // Nothing mixed yet, nothing to show.
+        assert!(analyzer.poll().iter().all(|l| *l == -100.0));
+        let mut out = vec![0.0; 1_024];
+        for _ in 0..20 {
+            bus.mix_stereo(&[0.0; 1_024], &[0.0; 1_024], &mut out);
+        }
+        assert!(analyzer.poll().iter().all(|l| *l == -100.0));
+
+        // The same bus carrying a tone lifts the bands around it.
+        let tone = sine(1_000.0, 0.5, 512);
+        for _ in 0..20 {
+            bus.mix_stereo(&tone, &tone, &mut out);
+        }
+        assert!(analyzer.poll().iter().any(|l| *l > -40.0));
+    }
+}
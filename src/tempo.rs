@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use crate::deck::Deck;
+use crate::{ParameterSender, ParameterUpdate};
+
+/// Taps averaged into the tapped tempo.
+const TAP_HISTORY: usize = 8;
+/// A pause longer than this starts a new tap sequence.
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Master tempo for beat-synced effects, following the synced deck or tapped by hand.
+#[derive(Debug, Clone, Default)]
+pub struct TempoSource {
+    bpm: Option<f64>,
+    taps: Vec<Instant>,
+}
+
+impl TempoSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bpm(&self) -> Option<f64> {
+        self.bpm
+    }
+
+    pub fn set_bpm(&mut self, bpm: Option<f64>) {
+        self.bpm = bpm.filter(|bpm| *bpm > 0.0);
+        self.taps.clear();
+    }
+
+    /// Take the effective tempo of `deck`, if it has a beat grid.
+    pub fn follow(&mut self, deck: &Deck) {
+        if let Some(bpm) = deck.effective_bpm() {
+            self.set_bpm(Some(bpm));
+        }
+    }
+
+    /// Register a tap now. See [`tap_at`](Self::tap_at).
+    pub fn tap(&mut self) -> Option<f64> {
+        self.tap_at(Instant::now())
+    }
+
+    /// Register a tap at `now` and return the tempo averaged over recent taps.
+    pub fn tap_at(&mut self, now: Instant) -> Option<f64> {
+        if self
+            .taps
+            .last()
+            .is_some_and(|last| now.saturating_duration_since(*last) > TAP_TIMEOUT)
+        {
+            self.taps.clear();
+        }
+        if self.taps.len() == TAP_HISTORY {
+            self.taps.remove(0);
+        }
+        self.taps.push(now);
+        if let [first, .., last] = self.taps[..] {
+            let interval = (last - first).as_secs_f64() / (self.taps.len() - 1) as f64;
+            if interval > 0.0 {
+                self.bpm = Some(60.0 / interval);
+            }
+        }
+        self.bpm
+    }
+
+    /// Send the current tempo to the audio thread.
+    pub fn publish(&self, params: &ParameterSender) -> Result<(), ParameterUpdate> {
+        params.send(ParameterUpdate::Tempo(self.bpm.map(|bpm| bpm as f32)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::{BeatGrid, Track};
+
+    #[test]
+    fn taps_average_and_restart_after_a_pause() {
+        let mut tempo = TempoSource::new();
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        assert_eq!(tempo.tap_at(at(0)), None);
+        // Slightly uneven taps around 500 ms average out to 120 BPM.
+        for ms in [490, 1_000, 1_510, 2_000] {
+            tempo.tap_at(at(ms));
+        }
+        assert!((tempo.bpm().unwrap() - 120.0).abs() < 1e-9);
+
+        // After a long pause the old taps no longer count.
+        tempo.tap_at(at(10_000));
+        assert_eq!(tempo.tap_at(at(10_600)), Some(100.0));
+    }
+
+    #[test]
+    fn follows_the_deck_and_publishes() {
+        let mut deck = Deck::new();
+        deck.load(Track::from_interleaved(vec![0.0; 960], 48_000));
+        deck.set_beat_grid(BeatGrid::new(128.0, 0.0, 48_000))
+            .unwrap();
+        deck.set_tempo_ratio(1.02);
+
+        let mut tempo = TempoSource::new();
+        tempo.follow(&deck);
+        assert!((tempo.bpm().unwrap() - 130.56).abs() < 1e-9);
+
+        let (tx, rx) = crate::parameter_channel(1);
+        tempo.publish(&tx).unwrap();
+        match rx.pop() {
+            Some(ParameterUpdate::Tempo(Some(bpm))) => assert!((bpm - 130.56).abs() < 1e-3),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+}
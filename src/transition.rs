@@ -0,0 +1,422 @@
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::deck::{Deck, DeckError};
+use crate::{DeckId, ParameterSender, ParameterUpdate};
+
+/// Filter position that takes the bass out of a deck for a bass swap.
+///
+/// There is no EQ on the bus yet, so the swap uses the deck filter's high-pass side.
+pub const BASS_CUT_POSITION: f32 = 0.4;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum TransitionError {
+    #[error("a transition is already running")]
+    AlreadyRunning,
+    #[error("cannot transition a deck into itself")]
+    SameDeck,
+    #[error("deck {0} is not on the crossfader")]
+    NotOnCrossfader(DeckId),
+    #[error("tempo sync failed: {0}")]
+    Sync(#[from] DeckError),
+    #[error("parameter queue is full")]
+    QueueFull,
+}
+
+/// Curve the crossfader follows over the transition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FadeShape {
+    Linear,
+    /// Slow at both ends, quick through the middle.
+    #[default]
+    SCurve,
+}
+
+impl FadeShape {
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            FadeShape::Linear => t,
+            FadeShape::SCurve => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransitionProfile {
+    pub duration: Duration,
+    pub shape: FadeShape,
+    /// Cut the incoming deck's bass, then swap the cut to the outgoing deck at the midpoint.
+    pub bass_swap: bool,
+    /// Sync the incoming deck to the outgoing one before fading.
+    pub sync_tempo: bool,
+    /// Send crossfader ramps between ticks; when false, send a stepped position each tick.
+    pub ramps: bool,
+}
+
+impl Default for TransitionProfile {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(16),
+            shape: FadeShape::default(),
+            bass_swap: true,
+            sync_tempo: true,
+            ramps: true,
+        }
+    }
+}
+
+/// Where a transition stands after a [`TransitionEngine::tick`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransitionStatus {
+    Idle,
+    Running { progress: f32 },
+    Finished,
+}
+
+#[derive(Debug)]
+struct Transition {
+    from: DeckId,
+    to: DeckId,
+    profile: TransitionProfile,
+    started: Instant,
+    last_tick: Instant,
+    swapped: bool,
+}
+
+impl Transition {
+    fn progress(&self, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(self.started).as_secs_f32();
+        (elapsed / self.profile.duration.as_secs_f32().max(f32::EPSILON)).min(1.0)
+    }
+
+    /// Crossfader position at `progress`.
+    fn crossfader(&self, progress: f32) -> f32 {
+        let t = self.profile.shape.apply(progress);
+        if self.to == DeckId::A {
+            1.0 - t
+        } else {
+            t
+        }
+    }
+}
+
+/// Control-side automation that crossfades from one deck to the other.
+///
+/// Call [`tick`](Self::tick) regularly, e.g. from the UI timer; each tick sends
+/// the updates due since the last one.
+pub struct TransitionEngine {
+    params: ParameterSender,
+    active: Option<Transition>,
+}
+
+impl TransitionEngine {
+    pub fn new(params: ParameterSender) -> Self {
+        Self {
+            params,
+            active: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.active.is_some()
+    }
+
+    fn send(&self, update: ParameterUpdate) -> Result<(), TransitionError> {
+        self.params
+            .send(update)
+            .map_err(|_| TransitionError::QueueFull)
+    }
+
+    /// Begin moving the mix from `from` to the other deck, which should already be playing.
+    pub fn start(
+        &mut self,
+        from: DeckId,
+        to: DeckId,
+        decks: &mut [Deck; 2],
+        profile: TransitionProfile,
+        now: Instant,
+    ) -> Result<(), TransitionError> {
+        if self.active.is_some() {
+            return Err(TransitionError::AlreadyRunning);
+        }
+        if from == to {
+            return Err(TransitionError::SameDeck);
+        }
+        if let Some(deck) = [from, to].into_iter().find(|deck| deck.index() > 1) {
+            return Err(TransitionError::NotOnCrossfader(deck));
+        }
+        if profile.sync_tempo {
+            let [a, b] = decks;
+            if to == DeckId::A {
+                a.sync_to(b)?;
+            } else {
+                b.sync_to(a)?;
+            }
+        }
+        let transition = Transition {
+            from,
+            to,
+            profile,
+            started: now,
+            last_tick: now,
+            swapped: false,
+        };
+        self.send(ParameterUpdate::Crossfader(transition.crossfader(0.0)))?;
+        if profile.bass_swap {
+            self.send(ParameterUpdate::DeckFilter {
+                deck: to,
+                position: BASS_CUT_POSITION,
+            })?;
+        }
+        self.active = Some(transition);
+        Ok(())
+    }
+
+    /// Send whatever is due at `now`. At the end the outgoing deck is paused.
+    pub fn tick(
+        &mut self,
+        now: Instant,
+        decks: &mut [Deck; 2],
+    ) -> Result<TransitionStatus, TransitionError> {
+        let Some(mut transition) = self.active.take() else {
+            return Ok(TransitionStatus::Idle);
+        };
+        let status = self.advance(&mut transition, now, decks);
+        if status != Ok(TransitionStatus::Finished) {
+            self.active = Some(transition);
+        }
+        status
+    }
+
+    fn advance(
+        &self,
+        transition: &mut Transition,
+        now: Instant,
+        decks: &mut [Deck; 2],
+    ) -> Result<TransitionStatus, TransitionError> {
+        let progress = transition.progress(now);
+        let (from, to) = (transition.from, transition.to);
+
+        if transition.profile.bass_swap && !transition.swapped && progress >= 0.5 {
+            self.send(ParameterUpdate::DeckFilter {
+                deck: to,
+                position: 0.0,
+            })?;
+            self.send(ParameterUpdate::DeckFilter {
+                deck: from,
+                position: BASS_CUT_POSITION,
+            })?;
+            transition.swapped = true;
+        }
+
+        if progress >= 1.0 {
+            self.send(ParameterUpdate::Crossfader(transition.crossfader(1.0)))?;
+            decks[from.index()].pause();
+            if transition.profile.bass_swap {
+                self.send(ParameterUpdate::DeckFilter {
+                    deck: from,
+                    position: 0.0,
+                })?;
+            }
+            return Ok(TransitionStatus::Finished);
+        }
+
+        let update = if transition.profile.ramps {
+            // Aim one tick ahead so the ramp lands as the next tick arrives.
+            let interval = now.saturating_duration_since(transition.last_tick);
+            let ahead = transition.progress(now + interval);
+            ParameterUpdate::CrossfaderRamp {
+                target: transition.crossfader(ahead),
+                seconds: interval.as_secs_f32(),
+            }
+        } else {
+            ParameterUpdate::Crossfader(transition.crossfader(progress))
+        };
+        self.send(update)?;
+        transition.last_tick = now;
+        Ok(TransitionStatus::Running { progress })
+    }
+
+    /// Stop where the mix is now: the crossfader holds its position, both decks
+    /// keep playing and any bass cut is lifted.
+    pub fn cancel(&mut self, now: Instant) -> Result<(), TransitionError> {
+        let Some(transition) = self.active.take() else {
+            return Ok(());
+        };
+        let position = transition.crossfader(transition.progress(now));
+        self.send(ParameterUpdate::Crossfader(position))?;
+        if transition.profile.bass_swap {
+            for deck in [transition.from, transition.to] {
+                self.send(ParameterUpdate::DeckFilter {
+                    deck,
+                    position: 0.0,
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::{BeatGrid, Track};
+    use crate::{parameter_channel, ParameterReceiver};
+
+    fn decks() -> [Deck; 2] {
+        [124.0, 128.0].map(|bpm| {
+            let mut deck = Deck::new();
+            deck.load(Track::from_interleaved(vec![0.0; 96_000], 48_000));
+            deck.set_beat_grid(BeatGrid::new(bpm, 0.0, 48_000)).unwrap();
+            deck.play();
+            deck
+        })
+    }
+
+    fn drain(rx: &ParameterReceiver) -> Vec<ParameterUpdate> {
+        std::iter::from_fn(|| rx.pop()).collect()
+    }
+
+    fn crossfader(updates: &[ParameterUpdate]) -> Vec<f32> {
+        updates
+            .iter()
+            .filter_map(|update| match update {
+                ParameterUpdate::Crossfader(value) => Some(*value),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn filters(updates: &[ParameterUpdate]) -> Vec<(DeckId, f32)> {
+        updates
+            .iter()
+            .filter_map(|update| match update {
+                ParameterUpdate::DeckFilter { deck, position } => Some((*deck, *position)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stepped_transition_swaps_bass_at_midpoint_and_stops_outgoing_deck() {
+        let (tx, rx) = parameter_channel(64);
+        let mut engine = TransitionEngine::new(tx);
+        let mut decks = decks();
+        let start = Instant::now();
+        let at = |secs: f32| start + Duration::from_secs_f32(secs);
+        let profile = TransitionProfile {
+            shape: FadeShape::Linear,
+            ramps: false,
+            ..TransitionProfile::default()
+        };
+
+        engine
+            .start(DeckId::A, DeckId::B, &mut decks, profile, start)
+            .unwrap();
+        // The incoming deck now runs at the outgoing deck's tempo.
+        assert!((decks[1].effective_bpm().unwrap() - 124.0).abs() < 1e-9);
+        let updates = drain(&rx);
+        assert_eq!(crossfader(&updates), [0.0]);
+        assert_eq!(filters(&updates), [(DeckId::B, BASS_CUT_POSITION)]);
+
+        assert_eq!(
+            engine.tick(at(4.0), &mut decks),
+            Ok(TransitionStatus::Running { progress: 0.25 })
+        );
+        let updates = drain(&rx);
+        assert_eq!(crossfader(&updates), [0.25]);
+        assert!(filters(&updates).is_empty());
+
+        engine.tick(at(8.0), &mut decks).unwrap();
+        let updates = drain(&rx);
+        assert_eq!(
+            filters(&updates),
+            [(DeckId::B, 0.0), (DeckId::A, BASS_CUT_POSITION)]
+        );
+        assert_eq!(crossfader(&updates), [0.5]);
+
+        engine.tick(at(12.0), &mut decks).unwrap();
+        assert!(filters(&drain(&rx)).is_empty());
+        assert!(decks[0].is_playing());
+
+        assert_eq!(
+            engine.tick(at(16.5), &mut decks),
+            Ok(TransitionStatus::Finished)
+        );
+        let updates = drain(&rx);
+        assert_eq!(crossfader(&updates), [1.0]);
+        assert_eq!(filters(&updates), [(DeckId::A, 0.0)]);
+        assert!(!decks[0].is_playing());
+        assert!(decks[1].is_playing());
+        assert_eq!(
+            engine.tick(at(17.0), &mut decks),
+            Ok(TransitionStatus::Idle)
+        );
+    }
+
+    #[test]
+    fn ramps_aim_one_tick_ahead_along_the_shape() {
+        let (tx, rx) = parameter_channel(64);
+        let mut engine = TransitionEngine::new(tx);
+        let mut decks = decks();
+        let start = Instant::now();
+        let profile = TransitionProfile {
+            duration: Duration::from_secs(10),
+            bass_swap: false,
+            sync_tempo: false,
+            ..TransitionProfile::default()
+        };
+        engine
+            .start(DeckId::B, DeckId::A, &mut decks, profile, start)
+            .unwrap();
+        assert_eq!(crossfader(&drain(&rx)), [1.0]);
+
+        engine
+            .tick(start + Duration::from_secs(2), &mut decks)
+            .unwrap();
+        match drain(&rx).as_slice() {
+            [ParameterUpdate::CrossfaderRamp { target, seconds }] => {
+                // Heading for 40% along an S-curve, towards deck A.
+                assert!((target - (1.0 - FadeShape::SCurve.apply(0.4))).abs() < 1e-6);
+                assert!((seconds - 2.0).abs() < 1e-6);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn cancel_holds_the_crossfader_and_lifts_bass_cuts() {
+        let (tx, rx) = parameter_channel(64);
+        let mut engine = TransitionEngine::new(tx);
+        let mut decks = decks();
+        let start = Instant::now();
+        let profile = TransitionProfile {
+            shape: FadeShape::Linear,
+            ..TransitionProfile::default()
+        };
+        engine
+            .start(DeckId::A, DeckId::B, &mut decks, profile, start)
+            .unwrap();
+        assert_eq!(
+            engine.start(DeckId::A, DeckId::B, &mut decks, profile, start),
+            Err(TransitionError::AlreadyRunning)
+        );
+        engine
+            .tick(start + Duration::from_secs(10), &mut decks)
+            .unwrap();
+        drain(&rx);
+
+        engine.cancel(start + Duration::from_secs(12)).unwrap();
+        let updates = drain(&rx);
+        assert_eq!(crossfader(&updates), [0.75]);
+        assert_eq!(filters(&updates), [(DeckId::A, 0.0), (DeckId::B, 0.0)]);
+        assert!(decks.iter().all(Deck::is_playing));
+        assert!(!engine.is_running());
+        assert_eq!(
+            engine.tick(start + Duration::from_secs(20), &mut decks),
+            Ok(TransitionStatus::Idle)
+        );
+        assert!(drain(&rx).is_empty());
+    }
+}
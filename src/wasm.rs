@@ -0,0 +1,112 @@
+//! Browser bindings for the mixing and analysis core.
+//!
+//! Only compiled with the `wasm` feature; build with
+//! `cargo build --target wasm32-unknown-unknown --no-default-features --features wasm`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::analysis::bpm::{estimate_bpm, BpmOptions};
+use crate::{parameter_channel, DeckId, ParameterSender, ParameterUpdate, SummingBus};
+
+/// A summing bus driven from JavaScript.
+///
+/// The parameter queue is the same lock-free `ArrayQueue` the app uses; on
+/// single-threaded wasm its atomics lower to plain loads and stores.
+#[wasm_bindgen]
+pub struct Mixer {
+    bus: SummingBus,
+    params: ParameterSender,
+}
+
+#[wasm_bindgen]
+impl Mixer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(sample_rate: u32) -> Mixer {
+        let (params, receiver) = parameter_channel(64);
+        Mixer {
+            bus: SummingBus::new(receiver).with_sample_rate(sample_rate),
+            params,
+        }
+    }
+
+    /// Crossfader position in [0, 1], 0 being all deck A.
+    pub fn set_crossfader(&self, position: f32) {
+        self.apply(ParameterUpdate::Crossfader(position));
+    }
+
+    /// Glide the crossfader to `target` over `seconds` of mixed audio.
+    pub fn ramp_crossfader(&self, target: f32, seconds: f32) {
+        self.apply(ParameterUpdate::CrossfaderRamp { target, seconds });
+    }
+
+    /// Channel gain of deck A (`deck` 0) or B (any other value).
+    pub fn set_deck_gain(&self, deck: u8, gain: f32) {
+        let deck = if deck == 0 { DeckId::A } else { DeckId::B };
+        self.apply(ParameterUpdate::DeckGain { deck, gain });
+    }
+
+    pub fn set_master_gain(&self, gain: f32) {
+        self.apply(ParameterUpdate::MasterGain(gain));
+    }
+
+    /// Mix two interleaved stereo blocks of equal length into a new one.
+    pub fn mix_block(&mut self, deck_a: &[f32], deck_b: &[f32]) -> Result<Vec<f32>, JsError> {
+        if deck_a.len() != deck_b.len() || deck_a.len() % 2 != 0 {
+            return Err(JsError::new(
+                "decks must be interleaved stereo blocks of equal length",
+            ));
+        }
+        let mut output = vec![0.0; deck_a.len()];
+        self.bus.mix_stereo(deck_a, deck_b, &mut output);
+        Ok(output)
+    }
+
+    fn apply(&self, update: ParameterUpdate) {
+        // Updates are drained on every block, so the queue only fills if
+        // thousands are set between two blocks; the newest ones are dropped then.
+        let _ = self.params.send(update);
+    }
+}
+
+/// Tempo of mono `samples` at `sample_rate`, or `undefined` for silent or too-short material.
+#[wasm_bindgen]
+pub fn analyze_bpm(samples: &[f32], sample_rate: u32) -> Option<f64> {
+    estimate_bpm(samples.iter().copied(), sample_rate, &BpmOptions::default())
+        .map(|estimate| estimate.bpm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run natively with `cargo test --features wasm` and in a browser with `wasm-pack test`.
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    fn mix_block_follows_the_crossfader() {
+        let mut mixer = Mixer::new(48_000);
+        let ones = vec![1.0; 8];
+        let silence = vec![0.0; 8];
+
+        let centred = mixer.mix_block(&ones, &silence).unwrap();
+        assert!((centred[0] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+
+        mixer.set_crossfader(1.0);
+        let out = mixer.mix_block(&ones, &silence).unwrap();
+        assert!(out.iter().all(|sample| sample.abs() < 1e-6));
+        let out = mixer.mix_block(&silence, &ones).unwrap();
+        assert!(out.iter().all(|sample| (sample - 1.0).abs() < 1e-6));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    fn analyze_bpm_finds_a_click_track() {
+        let sample_rate = 22_050;
+        let beat = sample_rate as usize / 2;
+        let clicks: Vec<f32> = (0..beat * 40)
+            .map(|i| if i % beat < 64 { 1.0 } else { 0.0 })
+            .collect();
+        let bpm = analyze_bpm(&clicks, sample_rate).unwrap();
+        assert!((bpm - 120.0).abs() < 1.0, "{bpm}");
+        assert_eq!(analyze_bpm(&[0.0; 16], sample_rate), None);
+    }
+}